@@ -14,6 +14,25 @@ use collections::Vec;
 ///////////////////////////////////////////////////////////////////////////////
 
 /// `Bytes` wraps a `&[u8]` in order to serialize into a byte array.
+///
+/// Slices and fixed size arrays of `u8` serialize as a sequence of numbers, one per byte. Wrapping
+/// them in `Bytes` serializes them with a single `serialize_bytes` call instead, which is what
+/// binary identifiers such as a 16 byte UUID usually want:
+///
+/// ```rust
+/// use serde::{Serialize, Serializer};
+/// use serde::bytes::Bytes;
+///
+/// struct Id([u8; 16]);
+///
+/// impl Serialize for Id {
+///     fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+///         where S: Serializer,
+///     {
+///         Bytes::from(&self.0).serialize(serializer)
+///     }
+/// }
+/// ```
 #[derive(Clone, Copy, Eq, Hash, PartialEq, PartialOrd, Ord)]
 pub struct Bytes<'a> {
     bytes: &'a [u8],
@@ -37,6 +56,24 @@ impl<'a> From<&'a [u8]> for Bytes<'a> {
     }
 }
 
+macro_rules! array_from_impls {
+    ($($len:expr)+) => {
+        $(
+            impl<'a> From<&'a [u8; $len]> for Bytes<'a> {
+                fn from(bytes: &'a [u8; $len]) -> Self {
+                    Bytes {
+                        bytes: bytes,
+                    }
+                }
+            }
+        )+
+    }
+}
+
+array_from_impls! {
+    0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31 32
+}
+
 #[cfg(any(feature = "std", feature = "collections"))]
 impl<'a> From<&'a Vec<u8>> for Bytes<'a> {
     fn from(bytes: &'a Vec<u8>) -> Self {
@@ -69,6 +106,57 @@ impl<'a> ser::Serialize for Bytes<'a> {
 
 ///////////////////////////////////////////////////////////////////////////////
 
+/// `Chunked` wraps a `&[u8]` in order to serialize it as a sequence of byte arrays of a fixed
+/// size, for transports that frame binary data in chunks. The last chunk is shorter when the
+/// length of the slice is not a multiple of the chunk size.
+///
+/// ```rust
+/// use serde::bytes::Chunked;
+/// use serde::token::{self, Token};
+///
+/// let tokens = token::to_tokens(&Chunked::new(b"abc", 2)).unwrap();
+/// assert_eq!(tokens, vec![
+///     Token::SeqStart(Some(2)),
+///     Token::SeqSep, Token::Bytes(b"ab"),
+///     Token::SeqSep, Token::Bytes(b"c"),
+///     Token::SeqEnd,
+/// ]);
+/// ```
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Chunked<'a> {
+    bytes: &'a [u8],
+    chunk_size: usize,
+}
+
+impl<'a> Chunked<'a> {
+    /// Wraps `bytes` to be serialized in chunks of `chunk_size` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is 0.
+    pub fn new(bytes: &'a [u8], chunk_size: usize) -> Self {
+        assert!(chunk_size != 0, "chunk size must be greater than zero");
+        Chunked {
+            bytes: bytes,
+            chunk_size: chunk_size,
+        }
+    }
+}
+
+impl<'a> ser::Serialize for Chunked<'a> {
+    #[inline]
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: ser::Serializer
+    {
+        let chunks = self.bytes.chunks(self.chunk_size);
+        let len = chunks.len();
+        serializer.serialize_seq(ser::impls::SeqIteratorVisitor::new(chunks.map(Bytes::from),
+                                                                     Some(len)))
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
 #[cfg(any(feature = "std", feature = "collections"))]
 mod bytebuf {
     use core::ops;
@@ -70,7 +70,7 @@ use de::{
     VariantVisitor,
     Visitor,
 };
-use de::from_primitive::FromPrimitive;
+use de::num::{Coercion, FromNumber, Number};
 
 ///////////////////////////////////////////////////////////////////////////////
 
@@ -137,14 +137,14 @@ impl Deserialize for bool {
 ///////////////////////////////////////////////////////////////////////////////
 
 macro_rules! impl_deserialize_num_method {
-    ($src_ty:ty, $method:ident, $from_method:ident, $ty:expr) => {
+    ($src_ty:ty, $method:ident, $variant:ident) => {
         #[inline]
         fn $method<E>(&mut self, v: $src_ty) -> Result<T, E>
             where E: Error,
         {
-            match FromPrimitive::$from_method(v) {
-                Some(v) => Ok(v),
-                None => Err(Error::invalid_type($ty)),
+            match T::from_number(Number::$variant(v), self.coercion) {
+                Ok(v) => Ok(v),
+                Err(err) => Err(Error::invalid_value(&format!("{}", err))),
             }
         }
     }
@@ -152,36 +152,30 @@ macro_rules! impl_deserialize_num_method {
 
 /// A visitor that produces a primitive type.
 struct PrimitiveVisitor<T> {
+    coercion: Coercion,
     marker: PhantomData<T>,
 }
 
 impl<T> PrimitiveVisitor<T> {
-    /// Construct a new `PrimitiveVisitor`.
+    /// Construct a new `PrimitiveVisitor` that converts numbers as `coercion` allows.
     #[inline]
-    fn new() -> Self {
+    fn new(coercion: Coercion) -> Self {
         PrimitiveVisitor {
+            coercion: coercion,
             marker: PhantomData,
         }
     }
 }
 
 impl<T> Visitor for PrimitiveVisitor<T>
-    where T: Deserialize + FromPrimitive + str::FromStr
+    where T: Deserialize + FromNumber + str::FromStr
 {
     type Value = T;
 
-    impl_deserialize_num_method!(isize, visit_isize, from_isize, Type::Isize);
-    impl_deserialize_num_method!(i8, visit_i8, from_i8, Type::I8);
-    impl_deserialize_num_method!(i16, visit_i16, from_i16, Type::I16);
-    impl_deserialize_num_method!(i32, visit_i32, from_i32, Type::I32);
-    impl_deserialize_num_method!(i64, visit_i64, from_i64, Type::I64);
-    impl_deserialize_num_method!(usize, visit_usize, from_usize, Type::Usize);
-    impl_deserialize_num_method!(u8, visit_u8, from_u8, Type::U8);
-    impl_deserialize_num_method!(u16, visit_u16, from_u16, Type::U16);
-    impl_deserialize_num_method!(u32, visit_u32, from_u32, Type::U32);
-    impl_deserialize_num_method!(u64, visit_u64, from_u64, Type::U64);
-    impl_deserialize_num_method!(f32, visit_f32, from_f32, Type::F32);
-    impl_deserialize_num_method!(f64, visit_f64, from_f64, Type::F64);
+    // The narrower types reach these through the default methods of `Visitor`.
+    impl_deserialize_num_method!(i64, visit_i64, Signed);
+    impl_deserialize_num_method!(u64, visit_u64, Unsigned);
+    impl_deserialize_num_method!(f64, visit_f64, Float);
 
     #[inline]
     fn visit_str<E>(&mut self, s: &str) -> Result<T, E>
@@ -200,7 +194,8 @@ macro_rules! impl_deserialize_num {
             fn deserialize<D>(deserializer: &mut D) -> Result<$ty, D::Error>
                 where D: Deserializer,
             {
-                deserializer.$method(PrimitiveVisitor::new())
+                let coercion = deserializer.coercion();
+                deserializer.$method(PrimitiveVisitor::new(coercion))
             }
         }
     }
@@ -514,6 +509,23 @@ seq_impl!(
 
 ///////////////////////////////////////////////////////////////////////////////
 
+/// Ends the sequence of a tuple or fixed size array of length `expected` whose elements have all
+/// been visited, counting any extra elements so the error can report the actual length.
+fn end_fixed_size<V>(mut visitor: V, expected: usize) -> Result<(), V::Error>
+    where V: SeqVisitor,
+{
+    let mut len = expected;
+    while let Some(IgnoredAny) = try!(visitor.visit()) {
+        len += 1;
+    }
+
+    if len != expected {
+        return Err(Error::invalid_arity(expected, len));
+    }
+
+    visitor.end()
+}
+
 struct ArrayVisitor0<T> {
     marker: PhantomData<T>,
 }
@@ -538,10 +550,10 @@ impl<T> Visitor for ArrayVisitor0<T> where T: Deserialize + Default {
     }
 
     #[inline]
-    fn visit_seq<V>(&mut self, mut visitor: V) -> Result<[T; 0], V::Error>
+    fn visit_seq<V>(&mut self, visitor: V) -> Result<[T; 0], V::Error>
         where V: SeqVisitor,
     {
-        try!(visitor.end());
+        try!(end_fixed_size(visitor, 0));
         Ok([T::default(); 0])
     }
 }
@@ -576,17 +588,20 @@ macro_rules! array_impls {
                 type Value = [T; $len];
 
                 #[inline]
+                #[allow(unused_assignments)]
                 fn visit_seq<V>(&mut self, mut visitor: V) -> Result<[T; $len], V::Error>
                     where V: SeqVisitor,
                 {
+                    let mut len = 0;
+
                     $(
                         let $name = match try!(visitor.visit()) {
-                            Some(val) => val,
-                            None => { return Err(Error::end_of_stream()); }
+                            Some(val) => { len += 1; val }
+                            None => { return Err(Error::invalid_arity($len, len)); }
                         };
                     )+;
 
-                    try!(visitor.end());
+                    try!(end_fixed_size(visitor, $len));
 
                     Ok([$($name,)+])
                 }
@@ -675,18 +690,20 @@ macro_rules! tuple_impls {
                 type Value = ($($name,)+);
 
                 #[inline]
-                #[allow(non_snake_case)]
+                #[allow(non_snake_case, unused_assignments)]
                 fn visit_seq<V>(&mut self, mut visitor: V) -> Result<($($name,)+), V::Error>
                     where V: SeqVisitor,
                 {
+                    let mut len = 0;
+
                     $(
                         let $name = match try!(visitor.visit()) {
-                            Some(value) => value,
-                            None => { return Err(Error::end_of_stream()); }
+                            Some(value) => { len += 1; value }
+                            None => { return Err(Error::invalid_arity($len, len)); }
                         };
                      )+;
 
-                    try!(visitor.end());
+                    try!(end_fixed_size(visitor, $len));
 
                     Ok(($($name,)+))
                 }
@@ -9,8 +9,8 @@ use error;
 use collections::{String, Vec};
 
 pub mod impls;
+pub mod num;
 pub mod value;
-mod from_primitive;
 
 ///////////////////////////////////////////////////////////////////////////////
 
@@ -46,6 +46,14 @@ pub trait Error: Sized + error::Error {
         Error::custom(format!("Invalid length: {}", len))
     }
 
+    /// Raised when a tuple or fixed size array was passed the wrong amount of elements.
+    ///
+    /// The parameter `expected` is the length of the type, and `len` is the number of elements
+    /// found in the serialization.
+    fn invalid_arity(expected: usize, len: usize) -> Self {
+        Error::custom(format!("Invalid length: expected {} elements, found {}", expected, len))
+    }
+
     /// Raised when a `Deserialize` enum type received an unexpected variant.
     fn unknown_variant(field: &str) -> Self {
         Error::custom(format!("Unknown variant `{}`", field))
@@ -473,6 +481,13 @@ pub trait Deserializer {
     {
         self.deserialize(visitor)
     }
+
+    /// Returns the conversions between numeric types that the primitive `Deserialize`
+    /// implementations allow for the numbers of this deserializer. See the `num` module.
+    #[inline]
+    fn coercion(&self) -> num::Coercion {
+        num::Coercion::new()
+    }
 }
 
 ///////////////////////////////////////////////////////////////////////////////
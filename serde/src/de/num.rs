@@ -0,0 +1,250 @@
+//! The conversions between numeric types that every primitive `Deserialize` implementation
+//! applies, so that all formats accept and reject the same numbers.
+//!
+//! A format hands over a number as whatever type it was stored with, which is rarely the type
+//! being deserialized. `FromNumber` converts it following this table:
+//!
+//! | From    | Into    | Allowed                                                            |
+//! |---------|---------|--------------------------------------------------------------------|
+//! | integer | integer | if the value fits the target type                                  |
+//! | integer | float   | always, and only if the float holds the value exactly when strict |
+//! | float   | float   | if a finite value fits the target type, rounding to its precision  |
+//! | float   | integer | only when lenient, for a whole number that fits the target type    |
+//! | `bool`  | number  | never                                                              |
+//!
+//! The strict and lenient flags are set on a `Coercion`, which a `Deserializer` returns from
+//! its `coercion` method. Both are off by default.
+//!
+//! ```rust
+//! use serde::de::num::{Coercion, FromNumber, Number};
+//!
+//! assert_eq!(u8::from_number(Number::Signed(200), Coercion::new()), Ok(200));
+//! assert!(u8::from_number(Number::Signed(-1), Coercion::new()).is_err());
+//!
+//! let lenient = Coercion::new().lenient(true);
+//! assert!(i32::from_number(Number::Float(3.0), Coercion::new()).is_err());
+//! assert_eq!(i32::from_number(Number::Float(3.0), lenient), Ok(3));
+//! assert!(i32::from_number(Number::Float(3.5), lenient).is_err());
+//! ```
+
+use core::fmt;
+
+#[cfg(feature = "std")]
+use std::error;
+#[cfg(not(feature = "std"))]
+use error;
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A number as a format stored it, widened to the largest type of its kind.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Number {
+    /// A value of one of the signed integer types.
+    Signed(i64),
+    /// A value of one of the unsigned integer types.
+    Unsigned(u64),
+    /// A value of one of the float types.
+    Float(f64),
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Number::Signed(v) => write!(formatter, "{}", v),
+            Number::Unsigned(v) => write!(formatter, "{}", v),
+            // Keeps the fraction of whole numbers, so `3.0` doesn't read as an integer.
+            Number::Float(v) => write!(formatter, "{:?}", v),
+        }
+    }
+}
+
+/// The conversions between numeric types that are allowed beyond the ones that never lose
+/// information.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Coercion {
+    strict: bool,
+    lenient: bool,
+}
+
+impl Coercion {
+    /// Construct a new `Coercion` with both flags off.
+    pub fn new() -> Self {
+        Coercion::default()
+    }
+
+    /// Sets whether an integer only becomes a float if the float holds the same value, instead
+    /// of the nearest value the float can hold.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Sets whether a float becomes an integer if it is a whole number.
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+}
+
+/// Why a `Number` couldn't be converted.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Reason {
+    OutOfRange,
+    Inexact,
+    NotLenient,
+    NotWhole,
+}
+
+/// The error returned by `FromNumber` for a number the target type doesn't accept.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct NumError {
+    value: Number,
+    target: &'static str,
+    reason: Reason,
+}
+
+impl NumError {
+    /// Returns the number that was rejected.
+    pub fn value(&self) -> Number {
+        self.value
+    }
+
+    /// Returns the name of the type the number was converted into.
+    pub fn target(&self) -> &'static str {
+        self.target
+    }
+}
+
+impl fmt::Display for NumError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self.reason {
+            Reason::OutOfRange => {
+                write!(formatter, "{} is out of range for {}", self.value, self.target)
+            }
+            Reason::Inexact => {
+                write!(formatter, "{} can't be represented exactly as {}", self.value, self.target)
+            }
+            Reason::NotLenient => {
+                write!(formatter, "{} is a float, which only becomes {} with lenient coercion",
+                       self.value, self.target)
+            }
+            Reason::NotWhole => {
+                write!(formatter, "{} is not a whole number, as {} requires",
+                       self.value, self.target)
+            }
+        }
+    }
+}
+
+impl error::Error for NumError {
+    fn description(&self) -> &str {
+        "number can't be converted"
+    }
+}
+
+/// A numeric type that a `Number` can be converted into.
+pub trait FromNumber: Sized {
+    /// Converts `value`, allowing the conversions in `coercion`.
+    fn from_number(value: Number, coercion: Coercion) -> Result<Self, NumError>;
+}
+
+fn error<T>(value: Number, target: &'static str, reason: Reason) -> Result<T, NumError> {
+    Err(NumError {
+        value: value,
+        target: target,
+        reason: reason,
+    })
+}
+
+/// 2^63 and 2^64 as floats, the first values past the ranges of `i64` and `u64`.
+const I64_END: f64 = 9223372036854775808.0;
+const U64_END: f64 = 18446744073709551616.0;
+
+macro_rules! int_impl {
+    ($($ty:ident)*) => {
+        $(
+            impl FromNumber for $ty {
+                #[allow(unused_comparisons)]
+                fn from_number(value: Number, coercion: Coercion) -> Result<$ty, NumError> {
+                    match value {
+                        Number::Signed(v) if v < 0 => {
+                            if $ty::min_value() as i64 <= v {
+                                return Ok(v as $ty);
+                            }
+                        }
+                        Number::Signed(v) => {
+                            if v as u64 <= $ty::max_value() as u64 {
+                                return Ok(v as $ty);
+                            }
+                        }
+                        Number::Unsigned(v) => {
+                            if v <= $ty::max_value() as u64 {
+                                return Ok(v as $ty);
+                            }
+                        }
+                        Number::Float(v) => {
+                            if !coercion.lenient {
+                                return error(value, stringify!($ty), Reason::NotLenient);
+                            }
+                            if !v.is_finite() {
+                                return error(value, stringify!($ty), Reason::NotWhole);
+                            }
+                            // The bounds are exact as floats, except that the maximum of a 64
+                            // bit type rounds up to the end of the range, which is still right.
+                            let (min, end) = if $ty::min_value() < 0 {
+                                ($ty::min_value() as f64, -($ty::min_value() as f64))
+                            } else {
+                                (0.0, $ty::max_value() as f64 + 1.0)
+                            };
+                            if min <= v && v < end {
+                                if v as $ty as f64 == v {
+                                    return Ok(v as $ty);
+                                }
+                                return error(value, stringify!($ty), Reason::NotWhole);
+                            }
+                        }
+                    }
+
+                    error(value, stringify!($ty), Reason::OutOfRange)
+                }
+            }
+        )*
+    }
+}
+
+int_impl!(isize i8 i16 i32 i64 usize u8 u16 u32 u64);
+
+macro_rules! float_impl {
+    ($($ty:ident)*) => {
+        $(
+            impl FromNumber for $ty {
+                fn from_number(value: Number, coercion: Coercion) -> Result<$ty, NumError> {
+                    let (v, exact) = match value {
+                        Number::Signed(v) => {
+                            let back = v as $ty as f64;
+                            (v as $ty, -I64_END <= back && back < I64_END && back as i64 == v)
+                        }
+                        Number::Unsigned(v) => {
+                            let back = v as $ty as f64;
+                            (v as $ty, back < U64_END && back as u64 == v)
+                        }
+                        Number::Float(v) => {
+                            if v.is_finite() && !(v as $ty).is_finite() {
+                                return error(value, stringify!($ty), Reason::OutOfRange);
+                            }
+                            return Ok(v as $ty);
+                        }
+                    };
+
+                    if coercion.strict && !exact {
+                        error(value, stringify!($ty), Reason::Inexact)
+                    } else {
+                        Ok(v)
+                    }
+                }
+            }
+        )*
+    }
+}
+
+float_impl!(f32 f64);
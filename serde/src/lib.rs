@@ -29,8 +29,9 @@ extern crate alloc;
 
 #[cfg(feature = "std")]
 mod core {
-    pub use std::{ops, hash, fmt, cmp, marker, mem, i8, i16, i32, i64, u8, u16, u32, u64, isize,
-            usize, f32, f64, char, str, num, slice, iter};
+    pub use std::{ops, hash, fmt, cmp, marker, char, str, slice, iter};
+    #[cfg(feature = "nightly")]
+    pub use std::num;
     #[cfg(feature = "nightly")]
     extern crate core;
     #[cfg(feature = "nightly")]
@@ -45,11 +46,18 @@ macro_rules! format {
     ($s:expr, $($rest:tt)*) => ($s)
 }
 
+#[macro_use]
+mod macros;
+
 pub mod bytes;
 pub mod de;
 #[cfg(feature = "std")]
 pub mod iter;
 pub mod ser;
+#[cfg(any(feature = "std", feature = "collections"))]
+pub mod token;
 #[cfg(not(feature = "std"))]
 pub mod error;
+#[cfg(feature = "uuid")]
+pub mod uuid;
 mod utils;
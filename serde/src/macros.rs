@@ -0,0 +1,527 @@
+//! Macros for implementing `Serialize`, and `DeserializeInto` for token streams, without a code
+//! generator, and for writing down a `token::Value`.
+
+/// Implements `Serialize` for a struct with named fields, serializing it as a struct with the
+/// listed fields in the given order.
+///
+/// Every listed field has to implement `Serialize`. Fields that are left out of the list are not
+/// serialized. Generic structs are not supported.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate serde;
+///
+/// struct Point {
+///     x: i32,
+///     y: i32,
+/// }
+///
+/// serializable_struct!(Point { x, y });
+///
+/// # fn main() {
+/// use serde::token::{self, Token};
+///
+/// let tokens = token::to_tokens(&Point { x: 1, y: 2 }).unwrap();
+/// assert_eq!(tokens, vec![
+///     Token::StructStart("Point", Some(2)),
+///     Token::StructSep, Token::Str("x"), Token::I32(1),
+///     Token::StructSep, Token::Str("y"), Token::I32(2),
+///     Token::StructEnd,
+/// ]);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! serializable_struct {
+    ($name:ident { $($field:ident),* }) => {
+        impl $crate::ser::Serialize for $name {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: $crate::ser::Serializer,
+            {
+                struct Visitor<'a> {
+                    value: &'a $name,
+                    state: usize,
+                }
+
+                impl<'a> $crate::ser::MapVisitor for Visitor<'a> {
+                    #[allow(unused_assignments, unused_mut, unused_variables)]
+                    fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+                        where S: $crate::ser::Serializer,
+                    {
+                        let mut index = 0;
+                        $(
+                            if self.state == index {
+                                self.state += 1;
+                                let value = &self.value.$field;
+                                try!(serializer.serialize_struct_elt(stringify!($field), value));
+                                return Ok(Some(()));
+                            }
+                            index += 1;
+                        )*
+                        Ok(None)
+                    }
+
+                    fn len(&self) -> Option<usize> {
+                        Some(0 $(+ { stringify!($field); 1 })*)
+                    }
+                }
+
+                serializer.serialize_struct(stringify!($name), Visitor { value: self, state: 0 })
+            }
+        }
+    };
+    ($name:ident { $($field:ident),+ , }) => {
+        serializable_struct!($name { $($field),+ });
+    };
+}
+
+/// Implements `token::borrowed::DeserializeInto` for a struct with named fields, overwriting
+/// each listed field in place.
+///
+/// Every listed field has to implement `DeserializeInto` and has to appear in the stream, and
+/// the stream can't have fields that aren't listed. A field that appears more than once keeps
+/// its last value. Up to 64 fields are supported, and generic structs are not.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate serde;
+///
+/// struct Record {
+///     name: String,
+///     tags: Vec<String>,
+/// }
+///
+/// deserialize_into_struct!(Record { name, tags });
+///
+/// # fn main() {
+/// use serde::token::{self, json};
+///
+/// let mut record = Record { name: String::new(), tags: Vec::new() };
+/// let mut tokens = json::from_str(r#"{"name": "frog", "tags": ["green"]}"#).unwrap().into_iter();
+/// token::deserialize_into(&mut record, &mut tokens).unwrap();
+/// assert_eq!((&*record.name, &*record.tags), ("frog", &["green".to_owned()][..]));
+/// # }
+/// ```
+#[macro_export]
+macro_rules! deserialize_into_struct {
+    ($name:ident { $($field:ident),* }) => {
+        impl<'a> $crate::token::borrowed::DeserializeInto<'a> for $name {
+            #[allow(unused_assignments, unused_mut, unused_variables)]
+            fn deserialize_into<I>(&mut self,
+                                   deserializer: &mut $crate::token::borrowed::Deserializer<I>)
+                                   -> Result<(), $crate::token::Error>
+                where I: Iterator<Item=$crate::token::Token<'a>>,
+            {
+                try!(deserializer.struct_start());
+                // A bit for each field that was read, in the order of the list.
+                let mut seen = 0u64;
+                while let Some(field) = try!(deserializer.struct_field()) {
+                    let mut index = 0;
+                    $(
+                        if field == stringify!($field) {
+                            try!($crate::token::borrowed::DeserializeInto::deserialize_into(
+                                &mut self.$field, deserializer));
+                            seen |= 1 << index;
+                            continue;
+                        }
+                        index += 1;
+                    )*
+                    return Err($crate::token::Error::InvalidValue(
+                        format!("unknown field {:?} of {}", field, stringify!($name))));
+                }
+
+                let mut index = 0;
+                $(
+                    if seen & 1 << index == 0 {
+                        return Err($crate::token::Error::InvalidValue(
+                            format!("missing field {:?} of {}", stringify!($field),
+                                    stringify!($name))));
+                    }
+                    index += 1;
+                )*
+                Ok(())
+            }
+        }
+    };
+    ($name:ident { $($field:ident),+ , }) => {
+        deserialize_into_struct!($name { $($field),+ });
+    };
+}
+
+/// Implements `Serialize` for an enum with unit and tuple variants, serializing each variant
+/// with the variant index it has in the list.
+///
+/// Tuple variants list a binding name for each of their fields. Variants with a single field
+/// are serialized as newtype variants, and every field has to implement `Serialize`. Generic
+/// enums and struct variants are not supported.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate serde;
+///
+/// enum Shape {
+///     Empty,
+///     Circle(f64),
+///     Rect(f64, f64),
+/// }
+///
+/// serializable_enum!(Shape { Empty, Circle(radius), Rect(width, height) });
+///
+/// # fn main() {
+/// use serde::token::{self, Token};
+///
+/// let tokens = token::to_tokens(&Shape::Rect(2.0, 3.0)).unwrap();
+/// assert_eq!(tokens, vec![
+///     Token::EnumSeqStart("Shape", "Rect", Some(2)),
+///     Token::EnumSeqSep, Token::F64(2.0),
+///     Token::EnumSeqSep, Token::F64(3.0),
+///     Token::EnumSeqEnd,
+/// ]);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! serializable_enum {
+    ($name:ident { $($variants:tt)* }) => {
+        serializable_enum!(@munch $name (0) [] $($variants)*);
+    };
+
+    // Numbers the variants one at a time.
+    (@munch $name:ident ($index:expr) [$($done:tt)*]
+     $variant:ident ($($field:ident),*) , $($rest:tt)*) => {
+        serializable_enum!(@munch $name ($index + 1) [$($done)* ($index) $variant ($($field),*)]
+                           $($rest)*);
+    };
+    (@munch $name:ident ($index:expr) [$($done:tt)*] $variant:ident ($($field:ident),*)) => {
+        serializable_enum!(@munch $name ($index + 1) [$($done)* ($index) $variant ($($field),*)]);
+    };
+    (@munch $name:ident ($index:expr) [$($done:tt)*] $variant:ident , $($rest:tt)*) => {
+        serializable_enum!(@munch $name ($index + 1) [$($done)* ($index) $variant ()] $($rest)*);
+    };
+    (@munch $name:ident ($index:expr) [$($done:tt)*] $variant:ident) => {
+        serializable_enum!(@munch $name ($index + 1) [$($done)* ($index) $variant ()]);
+    };
+    (@munch $name:ident ($count:expr)
+     [$(($index:expr) $variant:ident ($($field:ident),*))*]) => {
+        impl $crate::ser::Serialize for $name {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: $crate::ser::Serializer,
+            {
+                match *self {
+                    $(
+                        serializable_enum!(@pattern $name $variant ($($field),*)) => {
+                            serializable_enum!(@body serializer $name $variant ($index)
+                                               ($($field),*))
+                        }
+                    )*
+                }
+            }
+        }
+    };
+
+    (@pattern $name:ident $variant:ident ()) => {
+        $name::$variant
+    };
+    (@pattern $name:ident $variant:ident ($($field:ident),+)) => {
+        $name::$variant($(ref $field),+)
+    };
+
+    (@body $serializer:ident $name:ident $variant:ident ($index:expr) ()) => {
+        $serializer.serialize_unit_variant(stringify!($name), $index, stringify!($variant))
+    };
+    (@body $serializer:ident $name:ident $variant:ident ($index:expr) ($field:ident)) => {
+        $serializer.serialize_newtype_variant(stringify!($name),
+                                              $index,
+                                              stringify!($variant),
+                                              $field)
+    };
+    (@body $serializer:ident $name:ident $variant:ident ($index:expr) ($($field:ident),+)) => {{
+        // The field names double as the names of their type parameters.
+        #[allow(non_camel_case_types)]
+        struct Visitor<'a, $($field: 'a),+> {
+            $($field: &'a $field,)+
+            state: usize,
+        }
+
+        #[allow(non_camel_case_types)]
+        impl<'a, $($field),+> $crate::ser::SeqVisitor for Visitor<'a, $($field),+>
+            where $($field: $crate::ser::Serialize),+
+        {
+            #[allow(unused_assignments)]
+            fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+                where S: $crate::ser::Serializer,
+            {
+                let mut index = 0;
+                $(
+                    if self.state == index {
+                        self.state += 1;
+                        try!(serializer.serialize_tuple_variant_elt(self.$field));
+                        return Ok(Some(()));
+                    }
+                    index += 1;
+                )+
+                Ok(None)
+            }
+
+            fn len(&self) -> Option<usize> {
+                Some(0 $(+ { stringify!($field); 1 })+)
+            }
+        }
+
+        let visitor = Visitor { $($field: $field,)+ state: 0 };
+        $serializer.serialize_tuple_variant(stringify!($name),
+                                            $index,
+                                            stringify!($variant),
+                                            visitor)
+    }};
+}
+
+/// Implements `Serialize` and `Deserialize` for a fieldless enum, serializing each variant
+/// either as its name or as its discriminant.
+///
+/// `as name` serializes a variant as a string holding its name, `as number` serializes it as
+/// an `i64` holding its discriminant. Either way, deserialization accepts both the name and the
+/// discriminant, so data written in one mode can be read back after switching to the other.
+/// Unknown names and numbers are errors.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate serde;
+///
+/// #[derive(Debug, PartialEq)]
+/// enum Color {
+///     Red = 1,
+///     Green = 2,
+/// }
+///
+/// c_like_enum!(Color as number { Red, Green });
+///
+/// # fn main() {
+/// use serde::de::Deserialize;
+/// use serde::de::value::{Error, ValueDeserializer};
+/// use serde::token::{self, Token};
+///
+/// assert_eq!(token::to_tokens(&Color::Green).unwrap(), vec![Token::I64(2)]);
+///
+/// let mut deserializer = ValueDeserializer::<Error>::into_deserializer("Red");
+/// assert_eq!(Color::deserialize(&mut deserializer), Ok(Color::Red));
+/// # }
+/// ```
+#[macro_export]
+macro_rules! c_like_enum {
+    ($name:ident as $mode:ident { $($variant:ident),* }) => {
+        impl $crate::ser::Serialize for $name {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: $crate::ser::Serializer,
+            {
+                match *self {
+                    $(
+                        $name::$variant => {
+                            c_like_enum!(@serialize $mode serializer $name $variant)
+                        }
+                    )*
+                }
+            }
+        }
+
+        impl $crate::de::Deserialize for $name {
+            fn deserialize<D>(deserializer: &mut D) -> Result<$name, D::Error>
+                where D: $crate::de::Deserializer,
+            {
+                struct Visitor;
+
+                impl $crate::de::Visitor for Visitor {
+                    type Value = $name;
+
+                    fn visit_str<E>(&mut self, v: &str) -> Result<$name, E>
+                        where E: $crate::de::Error,
+                    {
+                        $(
+                            if v == stringify!($variant) {
+                                return Ok($name::$variant);
+                            }
+                        )*
+                        Err($crate::de::Error::unknown_variant(v))
+                    }
+
+                    fn visit_i64<E>(&mut self, v: i64) -> Result<$name, E>
+                        where E: $crate::de::Error,
+                    {
+                        $(
+                            if v == $name::$variant as i64 {
+                                return Ok($name::$variant);
+                            }
+                        )*
+                        Err($crate::de::Error::invalid_value(
+                            &format!("unknown discriminant {} for {}", v, stringify!($name))))
+                    }
+
+                    fn visit_u64<E>(&mut self, v: u64) -> Result<$name, E>
+                        where E: $crate::de::Error,
+                    {
+                        if v as i64 >= 0 {
+                            self.visit_i64(v as i64)
+                        } else {
+                            Err($crate::de::Error::invalid_value(
+                                &format!("unknown discriminant {} for {}", v, stringify!($name))))
+                        }
+                    }
+                }
+
+                deserializer.deserialize(Visitor)
+            }
+        }
+    };
+    ($name:ident as $mode:ident { $($variant:ident),+ , }) => {
+        c_like_enum!($name as $mode { $($variant),+ });
+    };
+
+    (@serialize name $serializer:ident $name:ident $variant:ident) => {
+        $serializer.serialize_str(stringify!($variant))
+    };
+    (@serialize number $serializer:ident $name:ident $variant:ident) => {
+        $serializer.serialize_i64($name::$variant as i64)
+    };
+}
+
+/// Implements `Serialize` for a map-like type, serializing it as a map of the `(key, value)`
+/// pairs that the method `iter` returns, in the order it returns them.
+///
+/// Generic types list their type parameters, each of which has to implement `Serialize`, and
+/// `iter` can't require any other bounds on them. The length of the map is announced when the
+/// iterator knows its exact size. Deserializing needs
+/// more than a method name, since many such types can't be built from their entries alone;
+/// `token::borrowed::Deserializer::map_entry` reads the entries into a value the caller built.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate serde;
+///
+/// /// The most recently used entry comes first.
+/// struct Recent<K, V> {
+///     entries: Vec<(K, V)>,
+/// }
+///
+/// impl<K, V> Recent<K, V> {
+///     fn iter<'a>(&'a self) -> Box<Iterator<Item=(&'a K, &'a V)> + 'a> {
+///         Box::new(self.entries.iter().map(|&(ref key, ref value)| (key, value)))
+///     }
+/// }
+///
+/// impl_map_serializable!(Recent<K, V>, iter);
+///
+/// # fn main() {
+/// use serde::token;
+///
+/// let recent = Recent { entries: vec![("b", 2), ("a", 1)] };
+/// assert_eq!(token::to_json(token::to_tokens(&recent).unwrap()).unwrap(), r#"{"b":2,"a":1}"#);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! impl_map_serializable {
+    ($name:ident < $($param:ident),+ >, $iter:ident) => {
+        impl<$($param),+> $crate::ser::Serialize for $name<$($param),+>
+            where $($param: $crate::ser::Serialize),+
+        {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: $crate::ser::Serializer,
+            {
+                impl_map_serializable!(@serialize self serializer $iter)
+            }
+        }
+    };
+    ($name:ident, $iter:ident) => {
+        impl $crate::ser::Serialize for $name {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: $crate::ser::Serializer,
+            {
+                impl_map_serializable!(@serialize self serializer $iter)
+            }
+        }
+    };
+
+    (@serialize $value:ident $serializer:ident $iter:ident) => {{
+        let iter = $value.$iter();
+        let len = match iter.size_hint() {
+            (lower, Some(upper)) if lower == upper => Some(lower),
+            _ => None,
+        };
+        $serializer.serialize_map($crate::ser::impls::MapIteratorVisitor::new(iter, len))
+    }};
+}
+
+/// Builds a `token::Value` from a JSON literal.
+///
+/// Objects, arrays, `null`, `true` and `false` are written as in JSON. Any other value, a number,
+/// a string or any Rust expression that implements `Serialize`, is converted with
+/// `Value::from_serialize`, so `1` becomes `Value::I64(1)` and `"a"` becomes `Value::String`,
+/// the same `Value` the JSON reader produces for the same text. Object keys are string literals
+/// or parenthesized expressions that implement `ToString`. Trailing commas are allowed.
+///
+/// A value ends at the next comma, so an expression with a comma of its own, like a call with
+/// several arguments, has to be put in parentheses.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate serde;
+///
+/// # fn main() {
+/// use serde::token::{Value, json};
+///
+/// let limit = 10;
+/// let value = value!({"a": [1, -2], "b": null, "c": {"nested": true, "limit": limit * 2}});
+/// let text = r#"{"a": [1, -2], "b": null, "c": {"nested": true, "limit": 20}}"#;
+/// assert_eq!(value, Value::from_tokens(json::from_str(text).unwrap()).unwrap());
+/// # }
+/// ```
+#[macro_export]
+macro_rules! value {
+    // Collects the token trees of the next element up to a comma.
+    (@seq [$($elements:expr,)*] ()) => {
+        vec![$($elements,)*]
+    };
+    (@seq [$($elements:expr,)*] ($($element:tt)+)) => {
+        vec![$($elements,)* value!($($element)+),]
+    };
+    (@seq [$($elements:expr,)*] ($($element:tt)+) , $($rest:tt)*) => {
+        value!(@seq [$($elements,)* value!($($element)+),] () $($rest)*)
+    };
+    (@seq [$($elements:expr,)*] ($($element:tt)*) $next:tt $($rest:tt)*) => {
+        value!(@seq [$($elements,)*] ($($element)* $next) $($rest)*)
+    };
+
+    // Inserts the entries one at a time, collecting the token trees of each value up to a comma.
+    (@map $map:ident) => {};
+    (@map $map:ident $key:tt : $($rest:tt)+) => {
+        value!(@entry $map ($key) () $($rest)+)
+    };
+    (@entry $map:ident ($key:tt) ($($value:tt)+)) => {
+        $map.insert(::std::string::ToString::to_string(&$key), value!($($value)+));
+    };
+    (@entry $map:ident ($key:tt) ($($value:tt)+) , $($rest:tt)*) => {
+        $map.insert(::std::string::ToString::to_string(&$key), value!($($value)+));
+        value!(@map $map $($rest)*);
+    };
+    (@entry $map:ident ($key:tt) ($($value:tt)*) $next:tt $($rest:tt)*) => {
+        value!(@entry $map ($key) ($($value)* $next) $($rest)*)
+    };
+
+    (null) => {
+        $crate::token::Value::Null
+    };
+    (true) => {
+        $crate::token::Value::Bool(true)
+    };
+    (false) => {
+        $crate::token::Value::Bool(false)
+    };
+    ([ $($element:tt)* ]) => {
+        $crate::token::Value::Seq(value!(@seq [] () $($element)*))
+    };
+    ({ $($entry:tt)* }) => {{
+        let mut map = ::std::collections::BTreeMap::new();
+        value!(@map map $($entry)*);
+        $crate::token::Value::Map(map)
+    }};
+    ($other:expr) => {
+        $crate::token::Value::from_serialize(&$other).expect("the value can't be converted")
+    };
+}
@@ -32,6 +32,8 @@ use collections::enum_set::{CLike, EnumSet};
 use collections::borrow::ToOwned;
 
 use core::hash::Hash;
+#[cfg(feature = "std")]
+use core::fmt;
 #[cfg(feature = "nightly")]
 use core::iter;
 #[cfg(feature = "std")]
@@ -230,6 +232,95 @@ impl<T, Iter> SeqVisitor for SeqIteratorVisitor<Iter>
 
 ///////////////////////////////////////////////////////////////////////////////
 
+/// Serializes the items of an iterator as a sequence, without collecting them first.
+///
+/// The iterator is produced by a closure every time the `LazySeq` is serialized, so items can
+/// come from a cursor or a computation and are passed to the serializer as they are pulled. The
+/// length is unknown unless it is given with `with_len`. A one-shot iterator that is `Clone` can
+/// be wrapped as `move || iter.clone()`.
+///
+/// # Examples
+///
+/// ```
+/// use serde::ser::impls::LazySeq;
+/// use serde::token::{self, Token};
+///
+/// let squares = LazySeq::new(|| (1..4).map(|i| i * i));
+/// assert_eq!(token::to_tokens(&squares).unwrap()[0], Token::SeqStart(None));
+///
+/// let squares = squares.with_len(3);
+/// assert_eq!(token::to_tokens(&squares).unwrap()[0], Token::SeqStart(Some(3)));
+/// ```
+pub struct LazySeq<F, I> {
+    f: F,
+    len: Option<usize>,
+    marker: PhantomData<fn() -> I>,
+}
+
+impl<F, I> LazySeq<F, I>
+    where F: Fn() -> I,
+          I: IntoIterator,
+          I::Item: Serialize,
+{
+    /// Construct a new `LazySeq` of unknown length from a closure producing the items.
+    pub fn new(f: F) -> Self {
+        LazySeq {
+            f: f,
+            len: None,
+            marker: PhantomData,
+        }
+    }
+
+    /// Announces that the closure produces `len` items.
+    pub fn with_len(mut self, len: usize) -> Self {
+        self.len = Some(len);
+        self
+    }
+}
+
+impl<F, I> Serialize for LazySeq<F, I>
+    where F: Fn() -> I,
+          I: IntoIterator,
+          I::Item: Serialize,
+{
+    #[inline]
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer,
+    {
+        serializer.serialize_seq(SeqIteratorVisitor::new((self.f)().into_iter(), self.len))
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Serializes a value after announcing the name of its type with `serialize_type_tag`.
+///
+/// Serializers that don't record type names drop the tag, so the output only differs for those
+/// that do, like the token `Serializer`:
+///
+/// ```
+/// use serde::ser::impls::Tagged;
+/// use serde::token::{self, Token};
+///
+/// let tokens = token::to_tokens(&Tagged("MyInt", &5i32)).unwrap();
+/// assert_eq!(tokens, vec![Token::TypeTag("MyInt"), Token::I32(5)]);
+/// ```
+pub struct Tagged<'a, T: 'a + ?Sized>(pub &'static str, pub &'a T);
+
+impl<'a, T: ?Sized> Serialize for Tagged<'a, T>
+    where T: Serialize,
+{
+    #[inline]
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer,
+    {
+        try!(serializer.serialize_type_tag(self.0));
+        self.1.serialize(serializer)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
 impl<T> Serialize for [T]
     where T: Serialize,
 {
@@ -665,6 +756,96 @@ impl<K, V> Serialize for HashMap<K, V>
 
 ///////////////////////////////////////////////////////////////////////////////
 
+/// A `serde::Visitor` for (key, value) map iterators whose keys are strings.
+///
+/// Each key is passed to the serializer as a `&str`, so it is written with `serialize_str`
+/// without going through the `Serialize` implementation of the key type.
+pub struct StrMapVisitor<Iter> {
+    iter: Iter,
+    len: Option<usize>,
+}
+
+impl<K, V, Iter> StrMapVisitor<Iter>
+    where K: AsRef<str>,
+          Iter: Iterator<Item=(K, V)>,
+{
+    /// Construct a new `StrMapVisitor<Iter>`.
+    #[inline]
+    pub fn new(iter: Iter, len: Option<usize>) -> StrMapVisitor<Iter> {
+        StrMapVisitor {
+            iter: iter,
+            len: len,
+        }
+    }
+}
+
+impl<K, V, I> MapVisitor for StrMapVisitor<I>
+    where K: AsRef<str>,
+          V: Serialize,
+          I: Iterator<Item=(K, V)>,
+{
+    #[inline]
+    fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+        where S: Serializer,
+    {
+        match self.iter.next() {
+            Some((key, value)) => {
+                try!(serializer.serialize_map_elt(key.as_ref(), value));
+                Ok(Some(()))
+            }
+            None => Ok(None)
+        }
+    }
+
+    #[inline]
+    fn len(&self) -> Option<usize> {
+        self.len
+    }
+}
+
+/// `StrMap` serializes a map with `String` keys through a `StrMapVisitor`. The output is the
+/// same as that of the map itself.
+///
+/// # Examples
+///
+/// ```
+/// use std::collections::BTreeMap;
+/// use serde::ser::impls::StrMap;
+/// use serde::token;
+///
+/// let mut map = BTreeMap::new();
+/// map.insert("a".to_owned(), 1);
+/// assert_eq!(token::to_tokens(&StrMap(&map)).unwrap(), token::to_tokens(&map).unwrap());
+/// ```
+#[cfg(any(feature = "std", feature = "collections"))]
+pub struct StrMap<'a, M: 'a>(pub &'a M);
+
+#[cfg(any(feature = "std", feature = "collections"))]
+impl<'a, V> Serialize for StrMap<'a, BTreeMap<String, V>>
+    where V: Serialize,
+{
+    #[inline]
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer,
+    {
+        serializer.serialize_map(StrMapVisitor::new(self.0.iter(), Some(self.0.len())))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, V> Serialize for StrMap<'a, HashMap<String, V>>
+    where V: Serialize,
+{
+    #[inline]
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer,
+    {
+        serializer.serialize_map(StrMapVisitor::new(self.0.iter(), Some(self.0.len())))
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
 impl<'a, T: ?Sized> Serialize for &'a T where T: Serialize {
     #[inline]
     fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
@@ -694,7 +875,7 @@ impl<T: ?Sized> Serialize for Box<T> where T: Serialize {
 }
 
 #[cfg(any(feature = "std", feature = "alloc"))]
-impl<T> Serialize for Rc<T> where T: Serialize, {
+impl<T: ?Sized> Serialize for Rc<T> where T: Serialize, {
     #[inline]
     fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
         where S: Serializer,
@@ -704,7 +885,7 @@ impl<T> Serialize for Rc<T> where T: Serialize, {
 }
 
 #[cfg(any(feature = "std", feature = "alloc"))]
-impl<T> Serialize for Arc<T> where T: Serialize, {
+impl<T: ?Sized> Serialize for Arc<T> where T: Serialize, {
     #[inline]
     fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
         where S: Serializer,
@@ -831,3 +1012,24 @@ impl<T> Serialize for NonZero<T> where T: Serialize + Zeroable {
         (**self).serialize(serializer)
     }
 }
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// `ViaDisplay` serializes the wrapped value as the string produced by its `Display`
+/// implementation.
+///
+/// This is an escape hatch for types that don't implement `Serialize`. It is lossy: only the
+/// formatted text is written, so the structure of the value is gone and it generally can't be
+/// deserialized back into the original type.
+#[cfg(feature = "std")]
+pub struct ViaDisplay<'a, T: ?Sized + 'a>(pub &'a T);
+
+#[cfg(feature = "std")]
+impl<'a, T: ?Sized> Serialize for ViaDisplay<'a, T> where T: fmt::Display {
+    #[inline]
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer,
+    {
+        serializer.serialize_str(&self.0.to_string())
+    }
+}
@@ -139,6 +139,29 @@ pub trait Serializer {
         self.serialize_seq(impls::SeqIteratorVisitor::new(value.iter(), Some(value.len())))
     }
 
+    /// Returns whether the format is meant to be read by people. Types with both a readable and
+    /// a compact form, like `SystemTime` and `Duration`, consult it to pick one. By default it
+    /// returns `true`.
+    #[inline]
+    fn is_human_readable(&self) -> bool {
+        true
+    }
+
+    /// Enables those serialization formats that have a native timestamp type to serialize a
+    /// point in time, given as seconds and nanoseconds since the Unix epoch. By default it
+    /// serializes as a `(secs, nanos)` tuple.
+    #[inline]
+    fn serialize_timestamp(&mut self, secs: i64, nanos: u32) -> Result<(), Self::Error> {
+        self.serialize_tuple(impls::TupleVisitor2::new(&(secs, nanos)))
+    }
+
+    /// Enables those serialization formats that can record which type produced a value to
+    /// receive the name of that type, ahead of the value itself. By default the name is dropped.
+    #[inline]
+    fn serialize_type_tag(&mut self, _name: &'static str) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
     /// Serializes a `()` value.
     fn serialize_unit(&mut self) -> Result<(), Self::Error>;
 
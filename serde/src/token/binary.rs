@@ -0,0 +1,913 @@
+//! Encode and decode token streams in a compact binary format.
+//!
+//! Every token is written as a one byte tag followed by its payload, so a stream reads back
+//! token for token without knowing the types it came from. Integers are written in their own
+//! width, little-endian by default, except that `Isize` and `Usize` become the fixed-width
+//! integers of the target's pointer width, see `normalize_width`. Floats are written as their
+//! IEEE 754 bits. Strings, byte arrays and
+//! names are prefixed with their length, a `u64` by default, and the optional lengths of
+//! containers with a flag byte. Borrowed and owned strings share a tag, as do borrowed and owned
+//! byte arrays, and decoding borrows both from the input.
+//!
+//! `IntEncoding`, or `BinaryConfig::varints` for short, switches integers and length prefixes
+//! to LEB128 varints, which saves space when most values are small. `ByteOrder` and `LenWidth`
+//! adapt fixed-width integers and length prefixes to readers that expect another layout.
+//!
+//! With `string_table`, or `BinaryConfig::string_table`, the string keys of maps and the field
+//! names of structs are collected in an `intern::Interner` as they are written. The first
+//! occurrence of a key is written with its text and adds it to the table, every later one only
+//! as its index, so a sequence of records doesn't repeat the same names over and over.
+
+use std::error;
+use std::fmt;
+use std::char;
+use std::io;
+use std::str;
+
+use super::{Token, f32_from_bits, f32_to_bits, f64_from_bits, f64_to_bits, normalize_width};
+use super::intern::Interner;
+
+const TAG_BOOL: u8 = 0;
+const TAG_ISIZE: u8 = 1;
+const TAG_I8: u8 = 2;
+const TAG_I16: u8 = 3;
+const TAG_I32: u8 = 4;
+const TAG_I64: u8 = 5;
+const TAG_USIZE: u8 = 6;
+const TAG_U8: u8 = 7;
+const TAG_U16: u8 = 8;
+const TAG_U32: u8 = 9;
+const TAG_U64: u8 = 10;
+const TAG_F32: u8 = 11;
+const TAG_F64: u8 = 12;
+const TAG_CHAR: u8 = 13;
+const TAG_STR: u8 = 14;
+const TAG_BYTES: u8 = 15;
+const TAG_TIMESTAMP: u8 = 16;
+const TAG_OPTION: u8 = 17;
+const TAG_UNIT: u8 = 18;
+const TAG_UNIT_STRUCT: u8 = 19;
+const TAG_STRUCT_NEWTYPE: u8 = 20;
+const TAG_ENUM_START: u8 = 21;
+const TAG_ENUM_UNIT: u8 = 22;
+const TAG_ENUM_NEWTYPE: u8 = 23;
+const TAG_SEQ_START: u8 = 24;
+const TAG_SEQ_ARRAY_START: u8 = 25;
+const TAG_SEQ_SEP: u8 = 26;
+const TAG_SEQ_END: u8 = 27;
+const TAG_TUPLE_START: u8 = 28;
+const TAG_TUPLE_SEP: u8 = 29;
+const TAG_TUPLE_END: u8 = 30;
+const TAG_TUPLE_STRUCT_START: u8 = 31;
+const TAG_TUPLE_STRUCT_SEP: u8 = 32;
+const TAG_TUPLE_STRUCT_END: u8 = 33;
+const TAG_MAP_START: u8 = 34;
+const TAG_MAP_SEP: u8 = 35;
+const TAG_MAP_END: u8 = 36;
+const TAG_STRUCT_START: u8 = 37;
+const TAG_STRUCT_SEP: u8 = 38;
+const TAG_STRUCT_END: u8 = 39;
+const TAG_ENUM_SEQ_START: u8 = 40;
+const TAG_ENUM_SEQ_SEP: u8 = 41;
+const TAG_ENUM_SEQ_END: u8 = 42;
+const TAG_ENUM_MAP_START: u8 = 43;
+const TAG_ENUM_MAP_SEP: u8 = 44;
+const TAG_ENUM_MAP_END: u8 = 45;
+const TAG_BIGNUM: u8 = 46;
+const TAG_TYPE_TAG: u8 = 47;
+const TAG_KEY: u8 = 48;
+const TAG_KEY_REF: u8 = 49;
+
+/// How integers and length prefixes are encoded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IntEncoding {
+    /// Every integer takes the width of its type, and length prefixes the width set with
+    /// `LenWidth`. This is the default.
+    Fixed,
+    /// Unsigned integers and length prefixes are LEB128 varints, so values below 128 take one
+    /// byte. Signed integers keep their fixed width.
+    Varint,
+    /// Like `Varint`, and signed integers are zigzag encoded into varints as well, so small
+    /// negative values are short too.
+    VarintZigzag,
+}
+
+impl Default for IntEncoding {
+    fn default() -> Self {
+        IntEncoding::Fixed
+    }
+}
+
+/// The byte order of fixed-width integers, floats and length prefixes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ByteOrder {
+    /// Least significant byte first. This is the default.
+    Little,
+    /// Most significant byte first.
+    Big,
+    /// The byte order of the target platform.
+    Native,
+}
+
+impl Default for ByteOrder {
+    fn default() -> Self {
+        ByteOrder::Little
+    }
+}
+
+impl ByteOrder {
+    fn is_big(self) -> bool {
+        match self {
+            ByteOrder::Little => false,
+            ByteOrder::Big => true,
+            ByteOrder::Native => cfg!(target_endian = "big"),
+        }
+    }
+}
+
+/// The width of length prefixes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LenWidth {
+    /// Lengths up to `u16::MAX`.
+    U16,
+    /// Lengths up to `u32::MAX`.
+    U32,
+    /// Lengths up to `u64::MAX`. This is the default.
+    U64,
+}
+
+impl Default for LenWidth {
+    fn default() -> Self {
+        LenWidth::U64
+    }
+}
+
+impl LenWidth {
+    fn bits(self) -> u32 {
+        match self {
+            LenWidth::U16 => 16,
+            LenWidth::U32 => 32,
+            LenWidth::U64 => 64,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            LenWidth::U16 => "u16",
+            LenWidth::U32 => "u32",
+            LenWidth::U64 => "u64",
+        }
+    }
+}
+
+/// The options of `to_binary_with` and `from_binary_with`.
+///
+/// This is a shorthand for the common choices, the `Encoder` and `Decoder` builders expose
+/// every option.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BinaryConfig {
+    /// Write unsigned integers and length prefixes as LEB128 varints and signed integers as
+    /// zigzag varints, as with `IntEncoding::VarintZigzag`, instead of fixed width.
+    pub varints: bool,
+    /// Write repeated map keys and struct field names as indices into a table of the keys seen
+    /// so far, as with `Encoder::string_table`.
+    pub string_table: bool,
+}
+
+impl BinaryConfig {
+    fn int_encoding(self) -> IntEncoding {
+        if self.varints {
+            IntEncoding::VarintZigzag
+        } else {
+            IntEncoding::Fixed
+        }
+    }
+}
+
+/// Returns the number of bytes a varint of an integer with `bits` bits may take.
+fn max_varint_len(bits: u32) -> usize {
+    (bits as usize + 6) / 7
+}
+
+fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+fn unzigzag(v: u64) -> i64 {
+    (v >> 1) as i64 ^ -((v & 1) as i64)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Encodes the values in `iter` with the default options.
+///
+/// ```rust
+/// use serde::token::{self, binary};
+///
+/// let tokens = token::to_tokens(&vec![1u16, 300]).unwrap();
+/// let bytes = binary::to_binary(tokens.clone());
+/// assert_eq!(binary::from_binary(&bytes).unwrap(), tokens);
+/// ```
+pub fn to_binary<'a, I>(iter: I) -> Vec<u8>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let mut encoder = Encoder::new(Vec::new());
+    encoder.encode(iter).expect("writing to a Vec can't fail");
+    encoder.into_inner()
+}
+
+/// Encodes the values in `iter` with the options in `config`.
+///
+/// ```rust
+/// use serde::token::{self, binary};
+/// use serde::token::binary::BinaryConfig;
+///
+/// let config = BinaryConfig { varints: true, ..BinaryConfig::default() };
+/// let tokens = token::to_tokens(&vec![1u64, 2, 3]).unwrap();
+/// let bytes = binary::to_binary_with(tokens.clone(), config);
+/// assert!(bytes.len() < binary::to_binary(tokens.clone()).len());
+/// assert_eq!(binary::from_binary_with(&bytes, config).unwrap(), tokens);
+/// ```
+pub fn to_binary_with<'a, I>(iter: I, config: BinaryConfig) -> Vec<u8>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let mut encoder = Encoder::new(Vec::new())
+        .int_encoding(config.int_encoding())
+        .string_table(config.string_table);
+    encoder.encode(iter).expect("writing to a Vec can't fail");
+    encoder.into_inner()
+}
+
+/// Encodes the values in `iter` with the default options into `writer`.
+pub fn write_binary<'a, I, W>(iter: I, writer: W) -> io::Result<()>
+    where I: IntoIterator<Item=Token<'a>>,
+          W: io::Write,
+{
+    Encoder::new(writer).encode(iter)
+}
+
+/// A binary encoder that is fed one token at a time.
+pub struct Encoder<W> {
+    writer: W,
+    int_encoding: IntEncoding,
+    byte_order: ByteOrder,
+    len_width: LenWidth,
+    /// The keys written so far, if repeated keys are written as indices.
+    strings: Option<Interner>,
+    /// Whether the next token is a map key or struct field name.
+    key_position: bool,
+}
+
+impl<W> Encoder<W>
+    where W: io::Write,
+{
+    /// Construct a new `Encoder` writing into `writer`.
+    pub fn new(writer: W) -> Self {
+        Encoder {
+            writer: writer,
+            int_encoding: IntEncoding::Fixed,
+            byte_order: ByteOrder::Little,
+            len_width: LenWidth::U64,
+            strings: None,
+            key_position: false,
+        }
+    }
+
+    /// Sets how integers and length prefixes are encoded. A `Decoder` has to use the same
+    /// encoding to read the output back.
+    pub fn int_encoding(mut self, int_encoding: IntEncoding) -> Self {
+        self.int_encoding = int_encoding;
+        self
+    }
+
+    /// Sets the byte order of fixed-width integers, floats and length prefixes.
+    pub fn byte_order(mut self, byte_order: ByteOrder) -> Self {
+        self.byte_order = byte_order;
+        self
+    }
+
+    /// Sets the width of length prefixes. Writing a string, byte array or container that is
+    /// longer than the width allows fails with an `InvalidInput` error.
+    pub fn len_width(mut self, len_width: LenWidth) -> Self {
+        self.len_width = len_width;
+        self
+    }
+
+    /// Sets whether string map keys and struct field names are written to a string table, so
+    /// that repeated ones take an index instead of their text. A `Decoder` has to have the
+    /// string table enabled as well to read the output back.
+    ///
+    /// ```rust
+    /// use std::collections::BTreeMap;
+    /// use serde::token::{self, binary};
+    /// use serde::token::binary::{Decoder, Encoder};
+    ///
+    /// let mut rows = Vec::new();
+    /// for id in 0..100 {
+    ///     let mut row = BTreeMap::new();
+    ///     row.insert("identifier".to_owned(), id);
+    ///     rows.push(row);
+    /// }
+    /// let tokens = token::to_tokens(&rows).unwrap();
+    ///
+    /// let mut encoder = Encoder::new(Vec::new()).string_table(true);
+    /// encoder.encode(tokens.clone()).unwrap();
+    /// assert_eq!(encoder.strings().unwrap().strings(), &["identifier".to_owned()]);
+    ///
+    /// let bytes = encoder.into_inner();
+    /// assert!(bytes.len() < binary::to_binary(tokens.clone()).len());
+    /// assert_eq!(Decoder::new(&bytes).string_table(true).decode().unwrap(), tokens);
+    /// ```
+    pub fn string_table(mut self, string_table: bool) -> Self {
+        self.strings = if string_table { Some(Interner::new()) } else { None };
+        self
+    }
+
+    /// Returns the string table, if it is enabled.
+    pub fn strings(&self) -> Option<&Interner> {
+        self.strings.as_ref()
+    }
+
+    /// Unwraps this `Encoder`, returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Encodes all tokens of `iter`.
+    pub fn encode<'a, I>(&mut self, iter: I) -> io::Result<()>
+        where I: IntoIterator<Item=Token<'a>>,
+    {
+        for token in iter {
+            try!(self.write_token(token));
+        }
+        Ok(())
+    }
+
+    /// Encodes the next token.
+    pub fn write_token(&mut self, token: Token) -> io::Result<()> {
+        if self.strings.is_some() {
+            let key_position = self.key_position;
+            self.key_position = match token {
+                Token::MapSep | Token::StructSep | Token::EnumMapSep => true,
+                _ => false,
+            };
+            if key_position {
+                match token {
+                    Token::Str(v) => return self.key(v),
+                    Token::String(ref v) => return self.key(v),
+                    _ => { }
+                }
+            }
+        }
+
+        match normalize_width(token) {
+            Token::Bool(v) => self.tagged(TAG_BOOL, &[v as u8]),
+            Token::Isize(_) | Token::Usize(_) => {
+                unreachable!("pointer sized integers are normalized")
+            }
+            Token::I8(v) => self.signed(TAG_I8, v as i64, 8),
+            Token::I16(v) => self.signed(TAG_I16, v as i64, 16),
+            Token::I32(v) => self.signed(TAG_I32, v as i64, 32),
+            Token::I64(v) => self.signed(TAG_I64, v, 64),
+            Token::U8(v) => self.unsigned(TAG_U8, v as u64, 8),
+            Token::U16(v) => self.unsigned(TAG_U16, v as u64, 16),
+            Token::U32(v) => self.unsigned(TAG_U32, v as u64, 32),
+            Token::U64(v) => self.unsigned(TAG_U64, v, 64),
+            Token::F32(v) => {
+                try!(self.tag(TAG_F32));
+                self.fixed(f32_to_bits(v) as u64, 32)
+            }
+            Token::F64(v) => {
+                try!(self.tag(TAG_F64));
+                self.fixed(f64_to_bits(v), 64)
+            }
+            Token::Char(v) => {
+                try!(self.tag(TAG_CHAR));
+                self.fixed(v as u64, 32)
+            }
+            Token::Str(v) => self.tagged_str(TAG_STR, v),
+            Token::String(ref v) => self.tagged_str(TAG_STR, v),
+            Token::BigNum(ref v) => self.tagged_str(TAG_BIGNUM, v),
+            Token::Bytes(v) => self.tagged_bytes(TAG_BYTES, v),
+            Token::ByteBuf(ref v) => self.tagged_bytes(TAG_BYTES, v),
+            Token::Timestamp(secs, nanos) => {
+                try!(self.tag(TAG_TIMESTAMP));
+                try!(self.fixed(secs as u64, 64));
+                self.fixed(nanos as u64, 32)
+            }
+
+            Token::Option(v) => self.tagged(TAG_OPTION, &[v as u8]),
+            Token::Unit => self.tag(TAG_UNIT),
+            Token::UnitStruct(name) => self.tagged_str(TAG_UNIT_STRUCT, name),
+            Token::StructNewType(name) => self.tagged_str(TAG_STRUCT_NEWTYPE, name),
+            Token::TypeTag(name) => self.tagged_str(TAG_TYPE_TAG, name),
+            Token::EnumStart(name) => self.tagged_str(TAG_ENUM_START, name),
+            Token::EnumUnit(name, variant) => {
+                try!(self.tagged_str(TAG_ENUM_UNIT, name));
+                self.str(variant)
+            }
+            Token::EnumNewType(name, variant) => {
+                try!(self.tagged_str(TAG_ENUM_NEWTYPE, name));
+                self.str(variant)
+            }
+
+            Token::SeqStart(len) => {
+                try!(self.tag(TAG_SEQ_START));
+                self.optional_len(len)
+            }
+            Token::SeqArrayStart(len) => {
+                try!(self.tag(TAG_SEQ_ARRAY_START));
+                self.len(len)
+            }
+            Token::SeqSep => self.tag(TAG_SEQ_SEP),
+            Token::SeqEnd => self.tag(TAG_SEQ_END),
+
+            Token::TupleStart(len) => {
+                try!(self.tag(TAG_TUPLE_START));
+                self.len(len)
+            }
+            Token::TupleSep => self.tag(TAG_TUPLE_SEP),
+            Token::TupleEnd => self.tag(TAG_TUPLE_END),
+
+            Token::TupleStructStart(name, len) => {
+                try!(self.tagged_str(TAG_TUPLE_STRUCT_START, name));
+                self.optional_len(len)
+            }
+            Token::TupleStructSep => self.tag(TAG_TUPLE_STRUCT_SEP),
+            Token::TupleStructEnd => self.tag(TAG_TUPLE_STRUCT_END),
+
+            Token::MapStart(len) => {
+                try!(self.tag(TAG_MAP_START));
+                self.optional_len(len)
+            }
+            Token::MapSep => self.tag(TAG_MAP_SEP),
+            Token::MapEnd => self.tag(TAG_MAP_END),
+
+            Token::StructStart(name, len) => {
+                try!(self.tagged_str(TAG_STRUCT_START, name));
+                self.optional_len(len)
+            }
+            Token::StructSep => self.tag(TAG_STRUCT_SEP),
+            Token::StructEnd => self.tag(TAG_STRUCT_END),
+
+            Token::EnumSeqStart(name, variant, len) => {
+                try!(self.tagged_str(TAG_ENUM_SEQ_START, name));
+                try!(self.str(variant));
+                self.optional_len(len)
+            }
+            Token::EnumSeqSep => self.tag(TAG_ENUM_SEQ_SEP),
+            Token::EnumSeqEnd => self.tag(TAG_ENUM_SEQ_END),
+
+            Token::EnumMapStart(name, variant, len) => {
+                try!(self.tagged_str(TAG_ENUM_MAP_START, name));
+                try!(self.str(variant));
+                self.optional_len(len)
+            }
+            Token::EnumMapSep => self.tag(TAG_ENUM_MAP_SEP),
+            Token::EnumMapEnd => self.tag(TAG_ENUM_MAP_END),
+        }
+    }
+
+    fn tag(&mut self, tag: u8) -> io::Result<()> {
+        self.writer.write_all(&[tag])
+    }
+
+    /// Writes a key through the string table, with its text the first time and as its index
+    /// after that.
+    fn key(&mut self, v: &str) -> io::Result<()> {
+        let (len, id) = {
+            let strings = self.strings.as_mut().expect("keys are only written to a string table");
+            (strings.len(), strings.intern(v))
+        };
+        if id < len {
+            try!(self.tag(TAG_KEY_REF));
+            self.len(id)
+        } else {
+            self.tagged_str(TAG_KEY, v)
+        }
+    }
+
+    fn tagged(&mut self, tag: u8, payload: &[u8]) -> io::Result<()> {
+        try!(self.tag(tag));
+        self.writer.write_all(payload)
+    }
+
+    fn tagged_str(&mut self, tag: u8, v: &str) -> io::Result<()> {
+        try!(self.tag(tag));
+        self.str(v)
+    }
+
+    fn tagged_bytes(&mut self, tag: u8, v: &[u8]) -> io::Result<()> {
+        try!(self.tag(tag));
+        try!(self.len(v.len()));
+        self.writer.write_all(v)
+    }
+
+    fn str(&mut self, v: &str) -> io::Result<()> {
+        try!(self.len(v.len()));
+        self.writer.write_all(v.as_bytes())
+    }
+
+    fn signed(&mut self, tag: u8, v: i64, bits: u32) -> io::Result<()> {
+        try!(self.tag(tag));
+        match self.int_encoding {
+            IntEncoding::Fixed | IntEncoding::Varint => self.fixed(v as u64, bits),
+            IntEncoding::VarintZigzag => self.varint(zigzag(v)),
+        }
+    }
+
+    fn unsigned(&mut self, tag: u8, v: u64, bits: u32) -> io::Result<()> {
+        try!(self.tag(tag));
+        match self.int_encoding {
+            IntEncoding::Fixed => self.fixed(v, bits),
+            IntEncoding::Varint | IntEncoding::VarintZigzag => self.varint(v),
+        }
+    }
+
+    fn len(&mut self, len: usize) -> io::Result<()> {
+        let bits = self.len_width.bits();
+        if bits < 64 && (len as u64) >> bits != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("length {} does not fit into a {} prefix",
+                                              len,
+                                              self.len_width.name())));
+        }
+
+        match self.int_encoding {
+            IntEncoding::Fixed => self.fixed(len as u64, bits),
+            IntEncoding::Varint | IntEncoding::VarintZigzag => self.varint(len as u64),
+        }
+    }
+
+    fn optional_len(&mut self, len: Option<usize>) -> io::Result<()> {
+        match len {
+            Some(len) => {
+                try!(self.writer.write_all(&[1]));
+                self.len(len)
+            }
+            None => self.writer.write_all(&[0]),
+        }
+    }
+
+    /// Writes the low `bits` bits of `v` in the configured byte order.
+    fn fixed(&mut self, v: u64, bits: u32) -> io::Result<()> {
+        let mut bytes = [0; 8];
+        let len = bits as usize / 8;
+        for (i, byte) in bytes[..len].iter_mut().enumerate() {
+            *byte = (v >> (8 * i)) as u8;
+        }
+        if self.byte_order.is_big() {
+            bytes[..len].reverse();
+        }
+        self.writer.write_all(&bytes[..len])
+    }
+
+    fn varint(&mut self, mut v: u64) -> io::Result<()> {
+        let mut bytes = [0; 10];
+        let mut len = 0;
+        loop {
+            let byte = (v & 0x7f) as u8;
+            v >>= 7;
+            if v == 0 {
+                bytes[len] = byte;
+                len += 1;
+                break;
+            }
+            bytes[len] = byte | 0x80;
+            len += 1;
+        }
+        self.writer.write_all(&bytes[..len])
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// The error returned when decoding fails.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    offset: usize,
+    message: String,
+}
+
+impl Error {
+    /// Returns the offset of the byte at which the problem was found.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns a description of the problem.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{} at offset {}", self.message, self.offset)
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        "invalid binary token stream"
+    }
+}
+
+/// Decodes all tokens in `input` with the default options.
+pub fn from_binary(input: &[u8]) -> Result<Vec<Token>, Error> {
+    Decoder::new(input).decode()
+}
+
+/// Decodes all tokens in `input` with the options in `config`, which have to match the ones it
+/// was encoded with.
+pub fn from_binary_with(input: &[u8], config: BinaryConfig) -> Result<Vec<Token>, Error> {
+    Decoder::new(input)
+        .int_encoding(config.int_encoding())
+        .string_table(config.string_table)
+        .decode()
+}
+
+/// A binary decoder.
+pub struct Decoder<'a> {
+    input: &'a [u8],
+    pos: usize,
+    int_encoding: IntEncoding,
+    byte_order: ByteOrder,
+    len_width: LenWidth,
+    /// The keys read so far, if the string table is enabled.
+    strings: Option<Vec<&'a str>>,
+}
+
+impl<'a> Decoder<'a> {
+    /// Construct a new `Decoder` reading from `input`.
+    pub fn new(input: &'a [u8]) -> Self {
+        Decoder {
+            input: input,
+            pos: 0,
+            int_encoding: IntEncoding::Fixed,
+            byte_order: ByteOrder::Little,
+            len_width: LenWidth::U64,
+            strings: None,
+        }
+    }
+
+    /// Sets how integers and length prefixes are encoded. Varints that are longer than the
+    /// integer type they encode can be are rejected.
+    pub fn int_encoding(mut self, int_encoding: IntEncoding) -> Self {
+        self.int_encoding = int_encoding;
+        self
+    }
+
+    /// Sets the byte order of fixed-width integers, floats and length prefixes.
+    pub fn byte_order(mut self, byte_order: ByteOrder) -> Self {
+        self.byte_order = byte_order;
+        self
+    }
+
+    /// Sets the width of length prefixes.
+    pub fn len_width(mut self, len_width: LenWidth) -> Self {
+        self.len_width = len_width;
+        self
+    }
+
+    /// Sets whether map keys and struct field names may refer to a string table, as written by
+    /// `Encoder::string_table`. Keys are decoded as `Str` tokens borrowing from the input.
+    pub fn string_table(mut self, string_table: bool) -> Self {
+        self.strings = if string_table { Some(Vec::new()) } else { None };
+        self
+    }
+
+    /// Decodes all tokens in the input.
+    pub fn decode(mut self) -> Result<Vec<Token<'a>>, Error> {
+        let mut tokens = Vec::new();
+        while self.pos < self.input.len() {
+            tokens.push(try!(self.token()));
+        }
+        Ok(tokens)
+    }
+
+    fn token(&mut self) -> Result<Token<'a>, Error> {
+        let start = self.pos;
+        let token = match try!(self.byte()) {
+            TAG_BOOL => Token::Bool(try!(self.flag())),
+            TAG_ISIZE => Token::Isize(try!(self.signed(64, "isize")) as isize),
+            TAG_I8 => Token::I8(try!(self.signed(8, "i8")) as i8),
+            TAG_I16 => Token::I16(try!(self.signed(16, "i16")) as i16),
+            TAG_I32 => Token::I32(try!(self.signed(32, "i32")) as i32),
+            TAG_I64 => Token::I64(try!(self.signed(64, "i64"))),
+            TAG_USIZE => Token::Usize(try!(self.unsigned(64, "usize")) as usize),
+            TAG_U8 => Token::U8(try!(self.unsigned(8, "u8")) as u8),
+            TAG_U16 => Token::U16(try!(self.unsigned(16, "u16")) as u16),
+            TAG_U32 => Token::U32(try!(self.unsigned(32, "u32")) as u32),
+            TAG_U64 => Token::U64(try!(self.unsigned(64, "u64"))),
+            TAG_F32 => Token::F32(f32_from_bits(try!(self.fixed(32)) as u32)),
+            TAG_F64 => Token::F64(f64_from_bits(try!(self.fixed(64)))),
+            TAG_CHAR => {
+                let v = try!(self.fixed(32)) as u32;
+                match char::from_u32(v) {
+                    Some(v) => Token::Char(v),
+                    None => return Err(self.error_at(start + 1, "invalid char")),
+                }
+            }
+            TAG_STR => Token::Str(try!(self.str())),
+            TAG_BIGNUM => Token::BigNum(try!(self.str()).to_owned()),
+            TAG_BYTES => {
+                let len = try!(self.len());
+                Token::Bytes(try!(self.slice(len)))
+            }
+            TAG_TIMESTAMP => {
+                let secs = try!(self.fixed(64)) as i64;
+                let nanos = try!(self.fixed(32)) as u32;
+                if nanos >= 1_000_000_000 {
+                    return Err(self.error_at(start + 9, "invalid nanoseconds"));
+                }
+                Token::Timestamp(secs, nanos)
+            }
+
+            TAG_OPTION => Token::Option(try!(self.flag())),
+            TAG_UNIT => Token::Unit,
+            TAG_UNIT_STRUCT => Token::UnitStruct(try!(self.str())),
+            TAG_STRUCT_NEWTYPE => Token::StructNewType(try!(self.str())),
+            TAG_TYPE_TAG => Token::TypeTag(try!(self.str())),
+            TAG_KEY if self.strings.is_some() => {
+                let key = try!(self.str());
+                if let Some(ref mut strings) = self.strings {
+                    strings.push(key);
+                }
+                Token::Str(key)
+            }
+            TAG_KEY_REF if self.strings.is_some() => Token::Str(try!(self.key_ref())),
+            TAG_ENUM_START => Token::EnumStart(try!(self.str())),
+            TAG_ENUM_UNIT => Token::EnumUnit(try!(self.str()), try!(self.str())),
+            TAG_ENUM_NEWTYPE => Token::EnumNewType(try!(self.str()), try!(self.str())),
+
+            TAG_SEQ_START => Token::SeqStart(try!(self.optional_len())),
+            TAG_SEQ_ARRAY_START => Token::SeqArrayStart(try!(self.len())),
+            TAG_SEQ_SEP => Token::SeqSep,
+            TAG_SEQ_END => Token::SeqEnd,
+
+            TAG_TUPLE_START => Token::TupleStart(try!(self.len())),
+            TAG_TUPLE_SEP => Token::TupleSep,
+            TAG_TUPLE_END => Token::TupleEnd,
+
+            TAG_TUPLE_STRUCT_START => {
+                Token::TupleStructStart(try!(self.str()), try!(self.optional_len()))
+            }
+            TAG_TUPLE_STRUCT_SEP => Token::TupleStructSep,
+            TAG_TUPLE_STRUCT_END => Token::TupleStructEnd,
+
+            TAG_MAP_START => Token::MapStart(try!(self.optional_len())),
+            TAG_MAP_SEP => Token::MapSep,
+            TAG_MAP_END => Token::MapEnd,
+
+            TAG_STRUCT_START => Token::StructStart(try!(self.str()), try!(self.optional_len())),
+            TAG_STRUCT_SEP => Token::StructSep,
+            TAG_STRUCT_END => Token::StructEnd,
+
+            TAG_ENUM_SEQ_START => {
+                Token::EnumSeqStart(try!(self.str()), try!(self.str()), try!(self.optional_len()))
+            }
+            TAG_ENUM_SEQ_SEP => Token::EnumSeqSep,
+            TAG_ENUM_SEQ_END => Token::EnumSeqEnd,
+
+            TAG_ENUM_MAP_START => {
+                Token::EnumMapStart(try!(self.str()), try!(self.str()), try!(self.optional_len()))
+            }
+            TAG_ENUM_MAP_SEP => Token::EnumMapSep,
+            TAG_ENUM_MAP_END => Token::EnumMapEnd,
+
+            tag => return Err(self.error_at(start, &format!("unknown tag {}", tag))),
+        };
+        Ok(token)
+    }
+
+    fn flag(&mut self) -> Result<bool, Error> {
+        match try!(self.byte()) {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(self.error_at(self.pos - 1, "invalid bool")),
+        }
+    }
+
+    fn str(&mut self) -> Result<&'a str, Error> {
+        let len = try!(self.len());
+        let start = self.pos;
+        let bytes = try!(self.slice(len));
+        str::from_utf8(bytes).map_err(|_| self.error_at(start, "invalid UTF-8 in string"))
+    }
+
+    fn signed(&mut self, bits: u32, ty: &str) -> Result<i64, Error> {
+        match self.int_encoding {
+            IntEncoding::Fixed | IntEncoding::Varint => {
+                // Sign extend from `bits` bits.
+                let shift = 64 - bits;
+                Ok((try!(self.fixed(bits)) << shift) as i64 >> shift)
+            }
+            IntEncoding::VarintZigzag => self.varint(bits, ty).map(unzigzag),
+        }
+    }
+
+    fn unsigned(&mut self, bits: u32, ty: &str) -> Result<u64, Error> {
+        match self.int_encoding {
+            IntEncoding::Fixed => self.fixed(bits),
+            IntEncoding::Varint | IntEncoding::VarintZigzag => self.varint(bits, ty),
+        }
+    }
+
+    /// Reads a length prefix. Lengths can't exceed the rest of the input, since every element
+    /// takes at least one byte, so they are checked against it before anything is allocated.
+    fn len(&mut self) -> Result<usize, Error> {
+        let start = self.pos;
+        let bits = self.len_width.bits();
+        let len = match self.int_encoding {
+            IntEncoding::Fixed => try!(self.fixed(bits)),
+            IntEncoding::Varint | IntEncoding::VarintZigzag => try!(self.varint(bits, "length")),
+        };
+        if len > (self.input.len() - self.pos) as u64 {
+            return Err(self.error_at(start, "length exceeds the rest of the input"));
+        }
+        Ok(len as usize)
+    }
+
+    /// Reads the index of a key in the string table.
+    fn key_ref(&mut self) -> Result<&'a str, Error> {
+        let start = self.pos;
+        let bits = self.len_width.bits();
+        let id = match self.int_encoding {
+            IntEncoding::Fixed => try!(self.fixed(bits)),
+            IntEncoding::Varint | IntEncoding::VarintZigzag => try!(self.varint(bits, "index")),
+        };
+        let strings = self.strings.as_ref().map_or(&[][..], |strings| &strings[..]);
+        if id >= strings.len() as u64 {
+            return Err(self.error_at(start, "unknown string table index"));
+        }
+        Ok(strings[id as usize])
+    }
+
+    fn optional_len(&mut self) -> Result<Option<usize>, Error> {
+        if try!(self.flag()) {
+            self.len().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Reads an integer of `bits` bits in the configured byte order.
+    fn fixed(&mut self, bits: u32) -> Result<u64, Error> {
+        let bytes = try!(self.slice(bits as usize / 8));
+        if self.byte_order.is_big() {
+            Ok(bytes.iter().fold(0, |n, &byte| n << 8 | byte as u64))
+        } else {
+            Ok(bytes.iter().rev().fold(0, |n, &byte| n << 8 | byte as u64))
+        }
+    }
+
+    /// Reads a varint that has to fit into `bits` bits.
+    fn varint(&mut self, bits: u32, ty: &str) -> Result<u64, Error> {
+        let start = self.pos;
+        let max_len = max_varint_len(bits);
+        let mut v = 0u64;
+
+        for i in 0..max_len {
+            let byte = try!(self.byte());
+            let payload = (byte & 0x7f) as u64;
+            let shift = 7 * i as u32;
+            if bits - shift < 7 && payload >> (bits - shift) != 0 {
+                return Err(self.error_at(start, &format!("varint is out of range for {}", ty)));
+            }
+            v |= payload << shift;
+            if byte & 0x80 == 0 {
+                return Ok(v);
+            }
+        }
+
+        Err(self.error_at(start, &format!("varint is longer than {} bytes for {}", max_len, ty)))
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        let bytes = try!(self.slice(1));
+        Ok(bytes[0])
+    }
+
+    fn slice(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let input = self.input;
+        if len > input.len() - self.pos {
+            self.pos = input.len();
+            return Err(self.error("unexpected end of input"));
+        }
+        let start = self.pos;
+        self.pos += len;
+        Ok(&input[start..self.pos])
+    }
+
+    fn error(&self, message: &str) -> Error {
+        self.error_at(self.pos, message)
+    }
+
+    fn error_at(&self, offset: usize, message: &str) -> Error {
+        Error {
+            offset: offset,
+            message: message.to_owned(),
+        }
+    }
+}
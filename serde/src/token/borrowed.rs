@@ -0,0 +1,818 @@
+//! Deserialize values that borrow from a token stream.
+//!
+//! A `Str` or `Bytes` token borrows its payload from whatever the stream was read from, e.g. the
+//! input of `json::from_str`. `DeserializeBorrowed` lets a value keep that borrow instead of
+//! copying it into a `String`:
+//!
+//! ```rust
+//! use serde::token::{self, json};
+//!
+//! let tokens = json::from_str(r#"["plain", "esc\"aped"]"#).unwrap();
+//! // The second string has an escape, so the stream owns a copy of it.
+//! assert!(token::from_tokens_borrowed::<Vec<&str>, _>(tokens.clone()).is_err());
+//!
+//! let names: Vec<std::borrow::Cow<str>> = token::from_tokens_borrowed(tokens).unwrap();
+//! assert_eq!(names, vec!["plain", "esc\"aped"]);
+//! ```
+//!
+//! A `String` or `ByteBuf` token owns its payload, so there is nothing to borrow. `&str` and
+//! `&[u8]` reject them with an error instead of silently copying. Deserializing a `Cow<str>`
+//! borrows where possible and falls back to a copy otherwise.
+//!
+//! Textual formats like JSON can only write map keys as strings. A `Deserializer` created with
+//! `coerce_keys(true)` parses string map keys into numbers, booleans and characters when the key
+//! type asks for one. Streams that keep the original key tokens don't need this, so it is off by
+//! default.
+//!
+//! Numbers convert between numeric types as the `de::num` module describes, with the flags of the
+//! `Coercion` set by the `coercion` option.
+//!
+//! Textual formats write byte strings as encoded strings, see `BytesEncoding`. `ByteBuf` and
+//! `Cow<[u8]>` decode strings with the encoding set by the `bytes_encoding` option, base64 by
+//! default. `&[u8]` can't point into bytes decoded on the fly, so it keeps taking the raw bytes of
+//! a borrowed string.
+//!
+//! A key that appears more than once in the same map keeps its last value. The
+//! `duplicate_keys` option picks another `DuplicateKeyPolicy`, which `set_field` applies to the
+//! fields of structs as well.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::iter::Peekable;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use bytes::ByteBuf;
+use de::num::{Coercion, FromNumber, Number};
+
+use super::{DuplicateKeyPolicy, Error, Token, time};
+use super::encoding::{self, BytesEncoding};
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A value that can be deserialized from a token stream, possibly borrowing from the stream's
+/// `Str` and `Bytes` tokens for the lifetime `'a`.
+pub trait DeserializeBorrowed<'a>: Sized {
+    /// Deserialize the next value of `deserializer`.
+    fn deserialize_borrowed<I>(deserializer: &mut Deserializer<I>) -> Result<Self, Error>
+        where I: Iterator<Item=Token<'a>>;
+}
+
+/// Deserializes the single value in `iter`.
+pub fn from_tokens_borrowed<'a, T, I>(iter: I) -> Result<T, Error>
+    where T: DeserializeBorrowed<'a>,
+          I: IntoIterator<Item=Token<'a>>,
+{
+    let mut deserializer = Deserializer::new(iter.into_iter());
+    let value = try!(T::deserialize_borrowed(&mut deserializer));
+    try!(deserializer.end());
+    Ok(value)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A cursor over a token stream, handed to `DeserializeBorrowed` implementations.
+pub struct Deserializer<I>
+    where I: Iterator,
+{
+    tokens: Peekable<I>,
+    coerce_keys: bool,
+    coercion: Coercion,
+    bytes_encoding: BytesEncoding,
+    duplicate_keys: DuplicateKeyPolicy,
+    /// True while a map key is being deserialized.
+    in_key: bool,
+    /// The index of the next token.
+    pos: usize,
+    /// The index of the last field name read by `struct_field`.
+    field_pos: usize,
+}
+
+impl<'a, I> Deserializer<I>
+    where I: Iterator<Item=Token<'a>>,
+{
+    /// Construct a new `Deserializer` reading from `tokens`.
+    pub fn new(tokens: I) -> Self {
+        Deserializer {
+            tokens: tokens.peekable(),
+            coerce_keys: false,
+            coercion: Coercion::new(),
+            bytes_encoding: BytesEncoding::Base64,
+            duplicate_keys: DuplicateKeyPolicy::LastWins,
+            in_key: false,
+            pos: 0,
+            field_pos: 0,
+        }
+    }
+
+    /// Sets what happens to keys that appear more than once in the same map, and to fields
+    /// stored with `set_field`.
+    pub fn duplicate_keys(mut self, policy: DuplicateKeyPolicy) -> Self {
+        self.duplicate_keys = policy;
+        self
+    }
+
+    /// Sets whether string map keys are parsed into numbers, booleans and characters when the
+    /// key type asks for one.
+    pub fn coerce_keys(mut self, coerce: bool) -> Self {
+        self.coerce_keys = coerce;
+        self
+    }
+
+    /// Sets the conversions between numeric types that are allowed when a number is
+    /// deserialized as another numeric type. See `de::num`.
+    pub fn coercion(mut self, coercion: Coercion) -> Self {
+        self.coercion = coercion;
+        self
+    }
+
+    /// Sets the encoding of strings that are deserialized as bytes.
+    pub fn bytes_encoding(mut self, encoding: BytesEncoding) -> Self {
+        self.bytes_encoding = encoding;
+        self
+    }
+
+    /// Parses `token` into a `T` if it is a string map key that should be coerced, returning
+    /// `None` otherwise. `expected` names `T` in the error for a key that doesn't parse.
+    pub fn parse_key<T>(&self, token: &Token, expected: &str) -> Option<Result<T, Error>>
+        where T: FromStr,
+    {
+        if !(self.coerce_keys && self.in_key) {
+            return None;
+        }
+
+        token.as_str().map(|key| {
+            key.parse().map_err(|_| {
+                Error::InvalidValue(format!("invalid map key {:?}: expected {}", key, expected))
+            })
+        })
+    }
+
+    /// Returns the next token, or an error when the stream is done. `TypeTag` tokens carry no
+    /// data for the value that follows them and are skipped.
+    pub fn next_token(&mut self) -> Result<Token<'a>, Error> {
+        self.skip_type_tags();
+        self.pos += 1;
+        self.tokens.next().ok_or_else(eof)
+    }
+
+    /// Checks that the stream is done.
+    pub fn end(&mut self) -> Result<(), Error> {
+        self.skip_type_tags();
+        match self.tokens.next() {
+            None => Ok(()),
+            Some(token) => Err(unexpected(&token)),
+        }
+    }
+
+    /// Returns the next token without consuming it, skipping `TypeTag` tokens like
+    /// `next_token`.
+    pub fn peek_token(&mut self) -> Option<&Token<'a>> {
+        self.skip_type_tags();
+        self.tokens.peek()
+    }
+
+    fn skip_type_tags(&mut self) {
+        while let Some(&Token::TypeTag(_)) = self.tokens.peek() {
+            self.pos += 1;
+            self.tokens.next();
+        }
+    }
+
+    /// Consumes the start of a struct or map.
+    pub fn struct_start(&mut self) -> Result<(), Error> {
+        match try!(self.next_token()) {
+            Token::StructStart(_, _) | Token::MapStart(_) => Ok(()),
+            token => Err(unexpected(&token)),
+        }
+    }
+
+    /// Consumes the next field name of a struct or map, or returns `None` after consuming the
+    /// end of it. The name is borrowed unless the stream owns it.
+    pub fn struct_field(&mut self) -> Result<Option<Cow<'a, str>>, Error> {
+        match try!(self.next_token()) {
+            Token::StructSep | Token::MapSep => {
+                self.field_pos = self.pos;
+                let name = try!(Cow::deserialize_borrowed(self));
+                Ok(Some(name))
+            }
+            Token::StructEnd | Token::MapEnd => Ok(None),
+            token => Err(unexpected(&token)),
+        }
+    }
+
+    /// Consumes the start of a map.
+    pub fn map_start(&mut self) -> Result<(), Error> {
+        match try!(self.next_token()) {
+            Token::MapStart(_) => Ok(()),
+            token => Err(unexpected(&token)),
+        }
+    }
+
+    /// Consumes the next entry of a map, or returns `None` after consuming the end of it.
+    ///
+    /// Together with `map_start` this reads a map into a container the caller already built,
+    /// for example one with a capacity that isn't part of the stream.
+    pub fn map_entry<K, V>(&mut self) -> Result<Option<(K, V)>, Error>
+        where K: DeserializeBorrowed<'a>,
+              V: DeserializeBorrowed<'a>,
+    {
+        match try!(self.next_token()) {
+            Token::MapSep => {
+                self.in_key = true;
+                let key = K::deserialize_borrowed(self);
+                self.in_key = false;
+
+                let key = try!(key);
+                let value = try!(V::deserialize_borrowed(self));
+                Ok(Some((key, value)))
+            }
+            Token::MapEnd => Ok(None),
+            token => Err(unexpected(&token)),
+        }
+    }
+
+    /// Consumes a unit variant of the enum `name` and returns its index in `variants`.
+    ///
+    /// The variant can be an `EnumUnit` token, its bare name as a string, or a single entry map
+    /// from its name to an empty sequence or `()`, which is how JSON writes unit variants unless
+    /// asked to write strings. Strings are only read as variants when the target type asks for
+    /// one this way, so a string field next to an enum field stays a string.
+    ///
+    /// ```rust
+    /// use serde::token::{Error, Token};
+    /// use serde::token::borrowed::Deserializer;
+    ///
+    /// const VARIANTS: &'static [&'static str] = &["Red", "Green"];
+    ///
+    /// let mut deserializer = Deserializer::new(vec![Token::Str("Green")].into_iter());
+    /// assert_eq!(deserializer.unit_variant("Color", VARIANTS), Ok(1));
+    ///
+    /// let mut deserializer = Deserializer::new(vec![Token::Str("Blue")].into_iter());
+    /// assert_eq!(deserializer.unit_variant("Color", VARIANTS),
+    ///            Err(Error::InvalidValue(
+    ///                "unknown variant `Blue` of Color, expected one of `Red`, `Green`"
+    ///                    .to_owned())));
+    /// ```
+    pub fn unit_variant(&mut self, name: &str, variants: &[&str]) -> Result<usize, Error> {
+        let variant = match try!(self.next_token()) {
+            Token::EnumUnit(_, variant) => Cow::Borrowed(variant),
+            Token::Str(variant) => Cow::Borrowed(variant),
+            Token::String(variant) => Cow::Owned(variant),
+            Token::MapStart(_) => {
+                let variant = match try!(self.struct_field()) {
+                    Some(variant) => variant,
+                    None => return Err(Error::InvalidValue(format!("empty map for {}", name))),
+                };
+                match try!(self.next_token()) {
+                    Token::Unit => { }
+                    Token::SeqStart(_) | Token::SeqArrayStart(_) | Token::TupleStart(_) => {
+                        match try!(self.next_token()) {
+                            ref token if token.is_end() => { }
+                            token => return Err(unexpected(&token)),
+                        }
+                    }
+                    token => return Err(unexpected(&token)),
+                }
+                match try!(self.next_token()) {
+                    Token::MapEnd => variant,
+                    token => return Err(unexpected(&token)),
+                }
+            }
+            token => return Err(unexpected(&token)),
+        };
+
+        match variants.iter().position(|v| *v == variant) {
+            Some(index) => Ok(index),
+            None => {
+                let expected: Vec<String> = variants.iter().map(|v| format!("`{}`", v)).collect();
+                Err(Error::InvalidValue(format!("unknown variant `{}` of {}, expected one of {}",
+                                                variant,
+                                                name,
+                                                expected.join(", "))))
+            }
+        }
+    }
+
+    /// Stores the value of the field `name`, which was just read by `struct_field`, into `slot`,
+    /// applying the duplicate key policy if the field was seen before.
+    ///
+    /// ```rust
+    /// use serde::token::{DuplicateKeyPolicy, Error, Token};
+    /// use serde::token::borrowed::{DeserializeBorrowed, Deserializer};
+    ///
+    /// let tokens = vec![
+    ///     Token::StructStart("S", Some(2)),
+    ///     Token::StructSep, Token::Str("a"), Token::I32(1),
+    ///     Token::StructSep, Token::Str("a"), Token::I32(2),
+    ///     Token::StructEnd,
+    /// ];
+    /// let mut deserializer = Deserializer::new(tokens.into_iter())
+    ///     .duplicate_keys(DuplicateKeyPolicy::Error);
+    /// deserializer.struct_start().unwrap();
+    ///
+    /// let mut a: Option<i32> = None;
+    /// let mut result = Ok(());
+    /// while let Some(field) = deserializer.struct_field().unwrap() {
+    ///     let value = DeserializeBorrowed::deserialize_borrowed(&mut deserializer).unwrap();
+    ///     result = deserializer.set_field(&mut a, value, &field);
+    ///     if result.is_err() {
+    ///         break;
+    ///     }
+    /// }
+    /// assert_eq!(result, Err(Error::InvalidValue("duplicate key \"a\" at token 5".to_owned())));
+    /// ```
+    pub fn set_field<T>(&self, slot: &mut Option<T>, value: T, name: &str) -> Result<(), Error> {
+        self.duplicate_keys.set(slot, value, name, self.field_pos)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+impl<'a> DeserializeBorrowed<'a> for &'a str {
+    fn deserialize_borrowed<I>(deserializer: &mut Deserializer<I>) -> Result<Self, Error>
+        where I: Iterator<Item=Token<'a>>,
+    {
+        match try!(deserializer.next_token()) {
+            Token::Str(v) => Ok(v),
+            Token::String(v) => {
+                Err(Error::InvalidValue(format!("cannot borrow the owned string {:?}", v)))
+            }
+            token => Err(unexpected(&token)),
+        }
+    }
+}
+
+impl<'a> DeserializeBorrowed<'a> for &'a [u8] {
+    fn deserialize_borrowed<I>(deserializer: &mut Deserializer<I>) -> Result<Self, Error>
+        where I: Iterator<Item=Token<'a>>,
+    {
+        match try!(deserializer.next_token()) {
+            Token::Bytes(v) => Ok(v),
+            Token::Str(v) => Ok(v.as_bytes()),
+            Token::ByteBuf(v) => {
+                Err(Error::InvalidValue(format!("cannot borrow the owned bytes {:?}", v)))
+            }
+            Token::String(v) => {
+                Err(Error::InvalidValue(format!("cannot borrow the owned string {:?}", v)))
+            }
+            token => Err(unexpected(&token)),
+        }
+    }
+}
+
+impl<'a> DeserializeBorrowed<'a> for Cow<'a, [u8]> {
+    fn deserialize_borrowed<I>(deserializer: &mut Deserializer<I>) -> Result<Self, Error>
+        where I: Iterator<Item=Token<'a>>,
+    {
+        if let Some(&Token::SeqStart(_)) = deserializer.peek_token() {
+            let v: Vec<u8> = try!(DeserializeBorrowed::deserialize_borrowed(deserializer));
+            return Ok(Cow::Owned(v));
+        }
+
+        let encoding = deserializer.bytes_encoding;
+        let text = match try!(deserializer.next_token()) {
+            Token::Bytes(v) => return Ok(Cow::Borrowed(v)),
+            Token::ByteBuf(v) => return Ok(Cow::Owned(v)),
+            Token::Str(v) => Cow::Borrowed(v),
+            Token::String(v) => Cow::Owned(v),
+            token => return Err(unexpected(&token)),
+        };
+        match encoding::decode(&text, encoding) {
+            Ok(v) => Ok(Cow::Owned(v)),
+            Err(err) => Err(Error::InvalidValue(format!("{:?}: {}", text, err))),
+        }
+    }
+}
+
+impl<'a> DeserializeBorrowed<'a> for ByteBuf {
+    fn deserialize_borrowed<I>(deserializer: &mut Deserializer<I>) -> Result<Self, Error>
+        where I: Iterator<Item=Token<'a>>,
+    {
+        let v: Cow<[u8]> = try!(DeserializeBorrowed::deserialize_borrowed(deserializer));
+        Ok(ByteBuf::from(v.into_owned()))
+    }
+}
+
+impl<'a> DeserializeBorrowed<'a> for Cow<'a, str> {
+    fn deserialize_borrowed<I>(deserializer: &mut Deserializer<I>) -> Result<Self, Error>
+        where I: Iterator<Item=Token<'a>>,
+    {
+        match try!(deserializer.next_token()) {
+            Token::Str(v) => Ok(Cow::Borrowed(v)),
+            Token::String(v) => Ok(Cow::Owned(v)),
+            Token::Char(v) => Ok(Cow::Owned(v.to_string())),
+            // The digits of a number too large for the integer types, for types that can parse
+            // them.
+            Token::BigNum(v) => Ok(Cow::Owned(v)),
+            token => Err(unexpected(&token)),
+        }
+    }
+}
+
+impl<'a> DeserializeBorrowed<'a> for String {
+    fn deserialize_borrowed<I>(deserializer: &mut Deserializer<I>) -> Result<Self, Error>
+        where I: Iterator<Item=Token<'a>>,
+    {
+        let v: Cow<str> = try!(DeserializeBorrowed::deserialize_borrowed(deserializer));
+        Ok(v.into_owned())
+    }
+}
+
+impl<'a> DeserializeBorrowed<'a> for bool {
+    fn deserialize_borrowed<I>(deserializer: &mut Deserializer<I>) -> Result<Self, Error>
+        where I: Iterator<Item=Token<'a>>,
+    {
+        let token = try!(deserializer.next_token());
+        if let Some(key) = deserializer.parse_key(&token, "bool") {
+            return key;
+        }
+
+        match token {
+            Token::Bool(v) => Ok(v),
+            token => Err(unexpected(&token)),
+        }
+    }
+}
+
+impl<'a> DeserializeBorrowed<'a> for char {
+    fn deserialize_borrowed<I>(deserializer: &mut Deserializer<I>) -> Result<Self, Error>
+        where I: Iterator<Item=Token<'a>>,
+    {
+        let token = try!(deserializer.next_token());
+        if let Some(key) = deserializer.parse_key(&token, "char") {
+            return key;
+        }
+
+        match token {
+            Token::Char(v) => Ok(v),
+            token => Err(unexpected(&token)),
+        }
+    }
+}
+
+/// The value of a number token.
+fn number(token: &Token) -> Option<Number> {
+    match *token {
+        Token::Isize(v) => Some(Number::Signed(v as i64)),
+        Token::I8(v) => Some(Number::Signed(v as i64)),
+        Token::I16(v) => Some(Number::Signed(v as i64)),
+        Token::I32(v) => Some(Number::Signed(v as i64)),
+        Token::I64(v) => Some(Number::Signed(v)),
+        Token::Usize(v) => Some(Number::Unsigned(v as u64)),
+        Token::U8(v) => Some(Number::Unsigned(v as u64)),
+        Token::U16(v) => Some(Number::Unsigned(v as u64)),
+        Token::U32(v) => Some(Number::Unsigned(v as u64)),
+        Token::U64(v) => Some(Number::Unsigned(v)),
+        Token::F32(v) => Some(Number::Float(v as f64)),
+        Token::F64(v) => Some(Number::Float(v)),
+        _ => None,
+    }
+}
+
+macro_rules! number_impl {
+    ($($ty:ident)*) => {
+        $(
+            impl<'a> DeserializeBorrowed<'a> for $ty {
+                fn deserialize_borrowed<I>(deserializer: &mut Deserializer<I>)
+                                           -> Result<Self, Error>
+                    where I: Iterator<Item=Token<'a>>,
+                {
+                    let token = try!(deserializer.next_token());
+                    if let Some(key) = deserializer.parse_key(&token, stringify!($ty)) {
+                        return key;
+                    }
+
+                    match number(&token) {
+                        Some(v) => {
+                            $ty::from_number(v, deserializer.coercion).map_err(|err| {
+                                Error::InvalidValue(err.to_string())
+                            })
+                        }
+                        None => Err(unexpected(&token)),
+                    }
+                }
+            }
+        )*
+    }
+}
+
+number_impl!(isize i8 i16 i32 i64 usize u8 u16 u32 u64 f32 f64);
+
+/// Accepts `Timestamp` tokens, RFC 3339 strings and integers counting the seconds since the Unix
+/// epoch, which covers the forms textual writers render timestamps in by default.
+impl<'a> DeserializeBorrowed<'a> for SystemTime {
+    fn deserialize_borrowed<I>(deserializer: &mut Deserializer<I>) -> Result<Self, Error>
+        where I: Iterator<Item=Token<'a>>,
+    {
+        let token = try!(deserializer.next_token());
+        let timestamp = match token {
+            Token::Timestamp(secs, nanos) => Some((secs, nanos)),
+            Token::Str(v) => time::parse_rfc3339(v),
+            Token::String(ref v) => time::parse_rfc3339(v),
+            _ => {
+                match number(&token) {
+                    Some(Number::Signed(v)) => Some((v, 0)),
+                    Some(Number::Unsigned(v)) if v <= i64::max_value() as u64 => {
+                        Some((v as i64, 0))
+                    }
+                    Some(Number::Unsigned(_)) => None,
+                    Some(Number::Float(_)) | None => return Err(unexpected(&token)),
+                }
+            }
+        };
+
+        timestamp.and_then(|(secs, nanos)| time::to_system_time(secs, nanos)).ok_or_else(|| {
+            Error::InvalidValue(format!("{:?} is not a valid timestamp", token))
+        })
+    }
+}
+
+/// Accepts strings like `1.500s` and `(secs, nanos)` sequences, the forms `Duration` serializes
+/// into for human readable and compact formats.
+impl<'a> DeserializeBorrowed<'a> for Duration {
+    fn deserialize_borrowed<I>(deserializer: &mut Deserializer<I>) -> Result<Self, Error>
+        where I: Iterator<Item=Token<'a>>,
+    {
+        let token = try!(deserializer.next_token());
+        let duration = match token {
+            Token::Str(v) => time::parse_duration(v),
+            Token::String(ref v) => time::parse_duration(v),
+            Token::SeqStart(_) | Token::SeqArrayStart(_) | Token::TupleStart(_) => {
+                let secs = try!(element::<u64, _>(deserializer));
+                let nanos = try!(element::<u32, _>(deserializer));
+                match try!(deserializer.next_token()) {
+                    ref end if end.is_end() => { }
+                    token => return Err(unexpected(&token)),
+                }
+                if nanos < 1_000_000_000 {
+                    Some(Duration::new(secs, nanos))
+                } else {
+                    None
+                }
+            }
+            token => return Err(unexpected(&token)),
+        };
+
+        duration.ok_or_else(|| Error::InvalidValue(format!("{:?} is not a valid duration", token)))
+    }
+}
+
+/// Reads the separator and the value of the next element of a sequence.
+fn element<'a, T, I>(deserializer: &mut Deserializer<I>) -> Result<T, Error>
+    where T: DeserializeBorrowed<'a>,
+          I: Iterator<Item=Token<'a>>,
+{
+    match try!(deserializer.next_token()) {
+        ref sep if sep.is_sep() => T::deserialize_borrowed(deserializer),
+        token => Err(unexpected(&token)),
+    }
+}
+
+impl<'a, T> DeserializeBorrowed<'a> for Option<T>
+    where T: DeserializeBorrowed<'a>,
+{
+    fn deserialize_borrowed<I>(deserializer: &mut Deserializer<I>) -> Result<Self, Error>
+        where I: Iterator<Item=Token<'a>>,
+    {
+        match deserializer.peek_token() {
+            Some(&Token::Option(false)) | Some(&Token::Unit) => {
+                try!(deserializer.next_token());
+                return Ok(None);
+            }
+            Some(&Token::Option(true)) => {
+                try!(deserializer.next_token());
+            }
+            _ => { }
+        }
+
+        T::deserialize_borrowed(deserializer).map(Some)
+    }
+}
+
+impl<'a, T> DeserializeBorrowed<'a> for Vec<T>
+    where T: DeserializeBorrowed<'a>,
+{
+    fn deserialize_borrowed<I>(deserializer: &mut Deserializer<I>) -> Result<Self, Error>
+        where I: Iterator<Item=Token<'a>>,
+    {
+        match try!(deserializer.next_token()) {
+            Token::SeqStart(_) | Token::SeqArrayStart(_) | Token::TupleStart(_) => { }
+            token => return Err(unexpected(&token)),
+        }
+
+        let mut values = Vec::new();
+        loop {
+            match try!(deserializer.next_token()) {
+                ref token if token.is_sep() => {
+                    values.push(try!(T::deserialize_borrowed(deserializer)));
+                }
+                ref token if token.is_end() => return Ok(values),
+                token => return Err(unexpected(&token)),
+            }
+        }
+    }
+}
+
+impl<'a, K, V> DeserializeBorrowed<'a> for BTreeMap<K, V>
+    where K: DeserializeBorrowed<'a> + Ord,
+          V: DeserializeBorrowed<'a>,
+{
+    fn deserialize_borrowed<I>(deserializer: &mut Deserializer<I>) -> Result<Self, Error>
+        where I: Iterator<Item=Token<'a>>,
+    {
+        match try!(deserializer.next_token()) {
+            Token::MapStart(_) => { }
+            token => return Err(unexpected(&token)),
+        }
+
+        let mut map = BTreeMap::new();
+        loop {
+            match try!(deserializer.next_token()) {
+                Token::MapSep => {
+                    let position = deserializer.pos;
+                    let policy = deserializer.duplicate_keys;
+                    // Only an error needs to describe the key.
+                    let name = match (policy, deserializer.peek_token()) {
+                        (DuplicateKeyPolicy::Error, Some(token)) => key_name(token),
+                        _ => String::new(),
+                    };
+
+                    deserializer.in_key = true;
+                    let key = K::deserialize_borrowed(deserializer);
+                    deserializer.in_key = false;
+
+                    let key = try!(key);
+                    let value = try!(V::deserialize_borrowed(deserializer));
+                    try!(policy.insert(&mut map, key, value, &name, position));
+                }
+                Token::MapEnd => return Ok(map),
+                token => return Err(unexpected(&token)),
+            }
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A value that can be overwritten in place with a value deserialized from a token stream,
+/// reusing the allocations it already owns.
+///
+/// If deserializing fails, the target is left partially overwritten.
+pub trait DeserializeInto<'a> {
+    /// Overwrites `self` with the next value of `deserializer`.
+    fn deserialize_into<I>(&mut self, deserializer: &mut Deserializer<I>) -> Result<(), Error>
+        where I: Iterator<Item=Token<'a>>;
+}
+
+/// Overwrites `target` with the next value in `tokens`, leaving the tokens after it unread.
+///
+/// Decoding many records into the same target this way allocates only when a record needs more
+/// room than the ones before it. Strings and sequences keep their capacity when a record is
+/// shorter than the last one, and are truncated to the new length.
+///
+/// ```rust
+/// use serde::token::{self, Token};
+///
+/// let mut tokens = vec![Token::Str("first"), Token::Str("second")].into_iter();
+/// let mut name = String::new();
+/// token::deserialize_into(&mut name, &mut tokens).unwrap();
+/// assert_eq!(name, "first");
+/// token::deserialize_into(&mut name, &mut tokens).unwrap();
+/// assert_eq!(name, "second");
+/// ```
+pub fn deserialize_into<'a, T: ?Sized, I>(target: &mut T, tokens: &mut I) -> Result<(), Error>
+    where T: DeserializeInto<'a>,
+          I: Iterator<Item=Token<'a>>,
+{
+    // A value never peeks beyond its own tokens, so the rest of `tokens` stays unread.
+    let mut deserializer = Deserializer::new(tokens.by_ref());
+    target.deserialize_into(&mut deserializer)
+}
+
+macro_rules! overwrite_impl {
+    ($($ty:ty)*) => {
+        $(
+            impl<'a> DeserializeInto<'a> for $ty {
+                fn deserialize_into<I>(&mut self, deserializer: &mut Deserializer<I>)
+                                       -> Result<(), Error>
+                    where I: Iterator<Item=Token<'a>>,
+                {
+                    *self = try!(DeserializeBorrowed::deserialize_borrowed(deserializer));
+                    Ok(())
+                }
+            }
+        )*
+    }
+}
+
+overwrite_impl!(bool char isize i8 i16 i32 i64 usize u8 u16 u32 u64 f32 f64 SystemTime Duration);
+
+impl<'a> DeserializeInto<'a> for String {
+    fn deserialize_into<I>(&mut self, deserializer: &mut Deserializer<I>) -> Result<(), Error>
+        where I: Iterator<Item=Token<'a>>,
+    {
+        let v: Cow<str> = try!(DeserializeBorrowed::deserialize_borrowed(deserializer));
+        self.clear();
+        self.push_str(&v);
+        Ok(())
+    }
+}
+
+/// A `Some` value is overwritten in place when the stream holds `Some` too.
+impl<'a, T> DeserializeInto<'a> for Option<T>
+    where T: DeserializeInto<'a> + DeserializeBorrowed<'a>,
+{
+    fn deserialize_into<I>(&mut self, deserializer: &mut Deserializer<I>) -> Result<(), Error>
+        where I: Iterator<Item=Token<'a>>,
+    {
+        match deserializer.peek_token() {
+            Some(&Token::Option(false)) | Some(&Token::Unit) => {
+                try!(deserializer.next_token());
+                *self = None;
+                return Ok(());
+            }
+            Some(&Token::Option(true)) => {
+                try!(deserializer.next_token());
+            }
+            _ => { }
+        }
+
+        match *self {
+            Some(ref mut value) => value.deserialize_into(deserializer),
+            None => {
+                *self = Some(try!(T::deserialize_borrowed(deserializer)));
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Existing elements are overwritten in place, elements beyond them are appended and elements
+/// the stream has no value for are dropped.
+impl<'a, T> DeserializeInto<'a> for Vec<T>
+    where T: DeserializeInto<'a> + DeserializeBorrowed<'a>,
+{
+    fn deserialize_into<I>(&mut self, deserializer: &mut Deserializer<I>) -> Result<(), Error>
+        where I: Iterator<Item=Token<'a>>,
+    {
+        match try!(deserializer.next_token()) {
+            Token::SeqStart(_) | Token::SeqArrayStart(_) | Token::TupleStart(_) => { }
+            token => return Err(unexpected(&token)),
+        }
+
+        let mut len = 0;
+        loop {
+            match try!(deserializer.next_token()) {
+                ref token if token.is_sep() => {
+                    if len < self.len() {
+                        try!(self[len].deserialize_into(deserializer));
+                    } else {
+                        self.push(try!(T::deserialize_borrowed(deserializer)));
+                    }
+                    len += 1;
+                }
+                ref token if token.is_end() => {
+                    self.truncate(len);
+                    return Ok(());
+                }
+                token => return Err(unexpected(&token)),
+            }
+        }
+    }
+}
+
+/// The map is cleared, keeping its capacity, and a key that appears more than once keeps its
+/// last value.
+impl<'a, K, V> DeserializeInto<'a> for HashMap<K, V>
+    where K: DeserializeBorrowed<'a> + Eq + Hash,
+          V: DeserializeBorrowed<'a>,
+{
+    fn deserialize_into<I>(&mut self, deserializer: &mut Deserializer<I>) -> Result<(), Error>
+        where I: Iterator<Item=Token<'a>>,
+    {
+        try!(deserializer.map_start());
+        self.clear();
+        while let Some((key, value)) = try!(deserializer.map_entry()) {
+            self.insert(key, value);
+        }
+        Ok(())
+    }
+}
+
+/// Returns the text of a string key, or the debug representation of any other key token.
+fn key_name(token: &Token) -> String {
+    match token.as_str() {
+        Some(name) => name.to_owned(),
+        None => format!("{:?}", token),
+    }
+}
+
+fn eof() -> Error {
+    Error::InvalidValue("unexpected end of stream".to_owned())
+}
+
+fn unexpected(token: &Token) -> Error {
+    Error::InvalidValue(format!("unexpected token {:?}", token))
+}
@@ -0,0 +1,547 @@
+//! Check and produce the canonical form of a token stream.
+//!
+//! Two streams of the same logical value can differ in the integer types they use, in the order
+//! of map entries and in whether compound values announce their length. A canonical stream picks
+//! one spelling for each of these:
+//!
+//! * every compound value has a known length, so there is no `SeqStart(None)` or
+//!   `MapStart(None)`,
+//! * the keys of every map are strictly increasing,
+//! * every integer uses the narrowest type that holds it, unsigned for values that are not
+//!   negative and signed otherwise, so `I64(5)` is spelled `U8(5)` and `I32(-300)` is spelled
+//!   `I16(-300)`.
+//!
+//! Map keys are ordered by their tokens: units first, then booleans, integers by value, floats,
+//! strings and characters by their bytes, byte strings, and everything else by its debug
+//! representation. The fields of a struct are not reordered, their order belongs to the type.
+
+use std::cmp::Ordering;
+use std::error;
+use std::fmt;
+use std::{i16, i32, i8, u16, u32, u8};
+
+use super::Token;
+
+/// The error returned by `is_canonical` and `canonicalize`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CanonError {
+    index: usize,
+    message: String,
+}
+
+impl CanonError {
+    /// Returns the index of the offending token in the stream.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns a description of the problem.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for CanonError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "token #{}: {}", self.index, self.message)
+    }
+}
+
+impl error::Error for CanonError {
+    fn description(&self) -> &str {
+        "token stream is not canonical"
+    }
+}
+
+/// Checks that `iter` is a single value in canonical form, reporting the first violation.
+///
+/// ```rust
+/// use serde::token::{self, Token};
+///
+/// let tokens = vec![
+///     Token::MapStart(Some(2)),
+///     Token::MapSep, Token::Str("b"), Token::U8(1),
+///     Token::MapSep, Token::Str("a"), Token::U8(2),
+///     Token::MapEnd,
+/// ];
+/// let err = token::is_canonical(tokens).unwrap_err();
+/// assert_eq!(err.index(), 5);
+/// ```
+pub fn is_canonical<'a, I>(iter: I) -> Result<(), CanonError>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    Canon::new(iter.into_iter(), false).run().map(|_| ())
+}
+
+/// Rewrites `iter` into canonical form, buffering every map to sort its entries.
+///
+/// Malformed streams and maps with two equal keys are still errors, since there is no single
+/// canonical spelling for them.
+///
+/// ```rust
+/// use serde::token::{self, Token};
+///
+/// let tokens = vec![
+///     Token::MapStart(None),
+///     Token::MapSep, Token::Str("b"), Token::I64(1),
+///     Token::MapSep, Token::Str("a"), Token::I64(-2),
+///     Token::MapEnd,
+/// ];
+/// assert_eq!(token::canonicalize(tokens).unwrap(), vec![
+///     Token::MapStart(Some(2)),
+///     Token::MapSep, Token::Str("a"), Token::I8(-2),
+///     Token::MapSep, Token::Str("b"), Token::U8(1),
+///     Token::MapEnd,
+/// ]);
+/// ```
+pub fn canonicalize<'a, I>(iter: I) -> Result<Vec<Token<'a>>, CanonError>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    Canon::new(iter.into_iter(), true).run()
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// What follows each separator of a compound value.
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    /// A single value.
+    Value,
+    /// A key value followed by a value.
+    Entry,
+    /// A field name followed by a value.
+    Field,
+}
+
+/// One element of a compound value, in canonical form.
+struct Element<'a> {
+    /// The index of the first token after the separator.
+    index: usize,
+    /// The tokens of the key or field name, empty for sequence elements.
+    key: Vec<Token<'a>>,
+    value: Vec<Token<'a>>,
+}
+
+struct Canon<I> {
+    tokens: I,
+    /// The number of tokens consumed so far.
+    index: usize,
+    /// Whether violations are fixed instead of reported.
+    rewrite: bool,
+}
+
+impl<'a, I> Canon<I>
+    where I: Iterator<Item=Token<'a>>,
+{
+    fn new(tokens: I, rewrite: bool) -> Self {
+        Canon {
+            tokens: tokens,
+            index: 0,
+            rewrite: rewrite,
+        }
+    }
+
+    fn run(mut self) -> Result<Vec<Token<'a>>, CanonError> {
+        let mut out = Vec::new();
+        try!(self.value(&mut out));
+        match self.next() {
+            None => Ok(out),
+            Some(token) => {
+                Err(self.error(format!("expected the end of the stream, found {:?}", token)))
+            }
+        }
+    }
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let token = self.tokens.next();
+        if token.is_some() {
+            self.index += 1;
+        }
+        token
+    }
+
+    /// Reads a value and appends its canonical tokens to `out`.
+    fn value(&mut self, out: &mut Vec<Token<'a>>) -> Result<(), CanonError> {
+        let token = match self.next() {
+            Some(token) => token,
+            None => return Err(self.error("expected a value, found the end of the stream")),
+        };
+
+        match token {
+            Token::Isize(_) |
+            Token::I8(_) |
+            Token::I16(_) |
+            Token::I32(_) |
+            Token::I64(_) |
+            Token::Usize(_) |
+            Token::U8(_) |
+            Token::U16(_) |
+            Token::U32(_) |
+            Token::U64(_) => {
+                let narrow = narrow(&token);
+                if !self.rewrite && narrow != token {
+                    return Err(self.error(format!("{:?} is not in its narrowest representation \
+                                                   {:?}",
+                                                  token, narrow)));
+                }
+                out.push(narrow);
+                Ok(())
+            }
+
+            Token::Bool(_) |
+            Token::F32(_) |
+            Token::F64(_) |
+            Token::Char(_) |
+            Token::Str(_) |
+            Token::String(_) |
+            Token::Bytes(_) |
+            Token::ByteBuf(_) |
+            Token::Timestamp(_, _) |
+            Token::BigNum(_) |
+            Token::Option(false) |
+            Token::Unit |
+            Token::UnitStruct(_) |
+            Token::EnumUnit(_, _) => {
+                out.push(token);
+                Ok(())
+            }
+
+            Token::Option(true) |
+            Token::StructNewType(_) |
+            Token::TypeTag(_) |
+            Token::EnumNewType(_, _) => {
+                out.push(token);
+                self.value(out)
+            }
+
+            Token::EnumStart(_) => {
+                // The variant identifier followed by its payload.
+                out.push(token);
+                try!(self.value(out));
+                self.value(out)
+            }
+
+            Token::SeqStart(len) => {
+                let elements = try!(self.elements(len, Kind::Value, Token::SeqSep, Token::SeqEnd));
+                emit(out, Token::SeqStart(Some(elements.len())), Token::SeqSep, elements,
+                     Token::SeqEnd);
+                Ok(())
+            }
+            Token::SeqArrayStart(len) => {
+                let elements = try!(self.elements(Some(len), Kind::Value, Token::SeqSep,
+                                                  Token::SeqEnd));
+                emit(out, Token::SeqArrayStart(len), Token::SeqSep, elements, Token::SeqEnd);
+                Ok(())
+            }
+            Token::TupleStart(len) => {
+                let elements = try!(self.elements(Some(len), Kind::Value, Token::TupleSep,
+                                                  Token::TupleEnd));
+                emit(out, Token::TupleStart(len), Token::TupleSep, elements, Token::TupleEnd);
+                Ok(())
+            }
+            Token::TupleStructStart(name, len) => {
+                let elements = try!(self.elements(len, Kind::Value, Token::TupleStructSep,
+                                                  Token::TupleStructEnd));
+                emit(out, Token::TupleStructStart(name, Some(elements.len())),
+                     Token::TupleStructSep, elements, Token::TupleStructEnd);
+                Ok(())
+            }
+            Token::EnumSeqStart(name, variant, len) => {
+                let elements = try!(self.elements(len, Kind::Value, Token::EnumSeqSep,
+                                                  Token::EnumSeqEnd));
+                emit(out, Token::EnumSeqStart(name, variant, Some(elements.len())),
+                     Token::EnumSeqSep, elements, Token::EnumSeqEnd);
+                Ok(())
+            }
+            Token::MapStart(len) => {
+                let elements = try!(self.elements(len, Kind::Entry, Token::MapSep, Token::MapEnd));
+                emit(out, Token::MapStart(Some(elements.len())), Token::MapSep, elements,
+                     Token::MapEnd);
+                Ok(())
+            }
+            Token::StructStart(name, len) => {
+                let elements = try!(self.elements(len, Kind::Field, Token::StructSep,
+                                                  Token::StructEnd));
+                emit(out, Token::StructStart(name, Some(elements.len())), Token::StructSep,
+                     elements, Token::StructEnd);
+                Ok(())
+            }
+            Token::EnumMapStart(name, variant, len) => {
+                let elements = try!(self.elements(len, Kind::Field, Token::EnumMapSep,
+                                                  Token::EnumMapEnd));
+                emit(out, Token::EnumMapStart(name, variant, Some(elements.len())),
+                     Token::EnumMapSep, elements, Token::EnumMapEnd);
+                Ok(())
+            }
+
+            token => Err(self.error(format!("expected a value, found {:?}", token))),
+        }
+    }
+
+    /// Reads the elements of the compound value whose start token was just read. The entries of
+    /// a map come back sorted by key.
+    fn elements(&mut self,
+                len: Option<usize>,
+                kind: Kind,
+                sep: Token<'static>,
+                end: Token<'static>) -> Result<Vec<Element<'a>>, CanonError> {
+        if len.is_none() && !self.rewrite {
+            return Err(self.error("compound value without a length"));
+        }
+
+        let mut elements: Vec<Element<'a>> = Vec::new();
+        loop {
+            match self.next() {
+                Some(ref token) if *token == sep => { }
+                Some(ref token) if *token == end => break,
+                Some(token) => {
+                    return Err(self.error(format!("expected {:?} or {:?}, found {:?}",
+                                                  sep, end, token)));
+                }
+                None => {
+                    return Err(self.error(format!("expected {:?} or {:?}, found the end of the \
+                                                   stream",
+                                                  sep, end)));
+                }
+            }
+
+            let index = self.index;
+            let mut key = Vec::new();
+            match kind {
+                Kind::Value => { }
+                Kind::Entry => {
+                    try!(self.value(&mut key));
+                    if !self.rewrite {
+                        if let Some(previous) = elements.last() {
+                            try!(check_order(&previous.key, &key, index));
+                        }
+                    }
+                }
+                Kind::Field => {
+                    match self.next() {
+                        Some(token) => {
+                            if token.as_str().is_none() {
+                                return Err(self.error(format!("expected a field name, found \
+                                                               {:?}",
+                                                              token)));
+                            }
+                            key.push(token);
+                        }
+                        None => {
+                            return Err(self.error(
+                                "expected a field name, found the end of the stream"));
+                        }
+                    }
+                }
+            }
+
+            let mut value = Vec::new();
+            try!(self.value(&mut value));
+            elements.push(Element {
+                index: index,
+                key: key,
+                value: value,
+            });
+        }
+
+        if let Some(len) = len {
+            if len != elements.len() {
+                return Err(self.error(format!("expected {} elements, found {}",
+                                              len, elements.len())));
+            }
+        }
+
+        if kind == Kind::Entry && self.rewrite {
+            elements.sort_by(|a, b| cmp_keys(&a.key, &b.key));
+            for pair in elements.windows(2) {
+                try!(check_order(&pair[0].key, &pair[1].key, pair[1].index));
+            }
+        }
+        Ok(elements)
+    }
+
+    /// Returns an error pointing at the token that was consumed last.
+    fn error<M: Into<String>>(&self, message: M) -> CanonError {
+        CanonError {
+            index: if self.index == 0 { 0 } else { self.index - 1 },
+            message: message.into(),
+        }
+    }
+}
+
+/// Appends a compound value made of `elements` to `out`.
+fn emit<'a>(out: &mut Vec<Token<'a>>,
+            start: Token<'a>,
+            sep: Token<'a>,
+            elements: Vec<Element<'a>>,
+            end: Token<'a>) {
+    out.push(start);
+    for element in elements {
+        out.push(sep.clone());
+        out.extend(element.key);
+        out.extend(element.value);
+    }
+    out.push(end);
+}
+
+/// Checks that `key`, whose first token is at `index`, sorts strictly after `previous`.
+fn check_order(previous: &[Token], key: &[Token], index: usize) -> Result<(), CanonError> {
+    let message = match cmp_keys(previous, key) {
+        Ordering::Less => return Ok(()),
+        Ordering::Equal => format!("duplicate map key {}", describe(key)),
+        Ordering::Greater => {
+            format!("map key {} is out of order after {}", describe(key), describe(previous))
+        }
+    };
+    Err(CanonError {
+        index: index,
+        message: message,
+    })
+}
+
+fn describe(key: &[Token]) -> String {
+    if key.len() == 1 {
+        format!("{:?}", key[0])
+    } else {
+        format!("{:?}", key)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Returns the narrowest spelling of an integer token.
+fn narrow<'a>(token: &Token) -> Token<'a> {
+    match *token {
+        Token::Isize(v) => signed(v as i64),
+        Token::I8(v) => signed(v as i64),
+        Token::I16(v) => signed(v as i64),
+        Token::I32(v) => signed(v as i64),
+        Token::I64(v) => signed(v),
+        Token::Usize(v) => unsigned(v as u64),
+        Token::U8(v) => unsigned(v as u64),
+        Token::U16(v) => unsigned(v as u64),
+        Token::U32(v) => unsigned(v as u64),
+        Token::U64(v) => unsigned(v),
+        _ => unreachable!("narrow is only called on integers"),
+    }
+}
+
+fn signed<'a>(v: i64) -> Token<'a> {
+    if v >= 0 {
+        unsigned(v as u64)
+    } else if v >= i8::MIN as i64 {
+        Token::I8(v as i8)
+    } else if v >= i16::MIN as i64 {
+        Token::I16(v as i16)
+    } else if v >= i32::MIN as i64 {
+        Token::I32(v as i32)
+    } else {
+        Token::I64(v)
+    }
+}
+
+fn unsigned<'a>(v: u64) -> Token<'a> {
+    if v <= u8::MAX as u64 {
+        Token::U8(v as u8)
+    } else if v <= u16::MAX as u64 {
+        Token::U16(v as u16)
+    } else if v <= u32::MAX as u64 {
+        Token::U32(v as u32)
+    } else {
+        Token::U64(v)
+    }
+}
+
+/// Orders two keys token by token.
+fn cmp_keys(a: &[Token], b: &[Token]) -> Ordering {
+    for (a, b) in a.iter().zip(b) {
+        match cmp_token(a, b) {
+            Ordering::Equal => { }
+            ordering => return ordering,
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+fn cmp_token(a: &Token, b: &Token) -> Ordering {
+    match rank(a).cmp(&rank(b)) {
+        Ordering::Equal => { }
+        ordering => return ordering,
+    }
+
+    match (a, b) {
+        (&Token::Bool(a), &Token::Bool(b)) => a.cmp(&b),
+        _ => {
+            if let (Some(a), Some(b)) = (integer(a), integer(b)) {
+                return a.cmp(&b);
+            }
+            if let (Some(a), Some(b)) = (float(a), float(b)) {
+                return a.partial_cmp(&b).unwrap_or(Ordering::Equal);
+            }
+            if let (Some(a), Some(b)) = (text(a), text(b)) {
+                return a.cmp(&b);
+            }
+            if let (Some(a), Some(b)) = (a.as_bytes(), b.as_bytes()) {
+                return a.cmp(b);
+            }
+            format!("{:?}", a).cmp(&format!("{:?}", b))
+        }
+    }
+}
+
+/// Groups tokens that are ordered among each other.
+fn rank(token: &Token) -> u8 {
+    match *token {
+        Token::Unit | Token::Option(false) => 0,
+        Token::Bool(_) => 1,
+        Token::Isize(_) | Token::I8(_) | Token::I16(_) | Token::I32(_) | Token::I64(_) |
+        Token::Usize(_) | Token::U8(_) | Token::U16(_) | Token::U32(_) | Token::U64(_) => 2,
+        Token::F32(_) | Token::F64(_) => 3,
+        Token::Char(_) | Token::Str(_) | Token::String(_) => 4,
+        Token::Bytes(_) | Token::ByteBuf(_) => 5,
+        _ => 6,
+    }
+}
+
+/// An integer value, with every negative value ordered before every other one.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum Integer {
+    Negative(i64),
+    NonNegative(u64),
+}
+
+fn integer(token: &Token) -> Option<Integer> {
+    let v = match *token {
+        Token::Isize(v) => v as i64,
+        Token::I8(v) => v as i64,
+        Token::I16(v) => v as i64,
+        Token::I32(v) => v as i64,
+        Token::I64(v) => v,
+        Token::Usize(v) => return Some(Integer::NonNegative(v as u64)),
+        Token::U8(v) => return Some(Integer::NonNegative(v as u64)),
+        Token::U16(v) => return Some(Integer::NonNegative(v as u64)),
+        Token::U32(v) => return Some(Integer::NonNegative(v as u64)),
+        Token::U64(v) => return Some(Integer::NonNegative(v)),
+        _ => return None,
+    };
+    if v < 0 {
+        Some(Integer::Negative(v))
+    } else {
+        Some(Integer::NonNegative(v as u64))
+    }
+}
+
+fn float(token: &Token) -> Option<f64> {
+    match *token {
+        Token::F32(v) => Some(v as f64),
+        Token::F64(v) => Some(v),
+        _ => None,
+    }
+}
+
+fn text(token: &Token) -> Option<String> {
+    match *token {
+        Token::Char(v) => Some(v.to_string()),
+        _ => token.as_str().map(|v| v.to_owned()),
+    }
+}
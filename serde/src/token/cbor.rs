@@ -0,0 +1,657 @@
+//! Encode and decode token streams as CBOR (RFC 7049).
+//!
+//! Containers with a known length use the definite-length encodings, those without one use the
+//! indefinite-length encodings terminated by a break. Structs become maps keyed by field name,
+//! tuples and tuple structs become arrays, and `None` and `()` become `null`. An enum variant
+//! with a payload becomes a single entry map from the variant name to the payload. Timestamps
+//! become epoch-based date-times (tag 1), written as a float when they have a fraction of a
+//! second. `BigNum` tokens become strings of decimal digits, like the bignums they are decoded
+//! into.
+//!
+//! Decoding turns arrays into sequences and maps into maps, with the length when it is definite.
+//! Definite-length strings borrow from the input, indefinite-length ones are concatenated into
+//! owned strings. Both date-time tags become `Timestamp` tokens, and bignums become strings of
+//! decimal digits.
+
+use std::error;
+use std::fmt;
+use std::{f32, f64, i64};
+use std::io;
+use std::str;
+
+use super::{Token, be_u16, be_u32, be_u64, f32_from_bits, f32_to_bits, f64_from_bits, f64_to_bits,
+            time};
+
+const MAJOR_UNSIGNED: u8 = 0;
+const MAJOR_NEGATIVE: u8 = 1;
+const MAJOR_BYTES: u8 = 2;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_ARRAY: u8 = 4;
+const MAJOR_MAP: u8 = 5;
+const MAJOR_TAG: u8 = 6;
+
+const FALSE: u8 = 0xf4;
+const TRUE: u8 = 0xf5;
+const NULL: u8 = 0xf6;
+const UNDEFINED: u8 = 0xf7;
+const FLOAT16: u8 = 0xf9;
+const FLOAT32: u8 = 0xfa;
+const FLOAT64: u8 = 0xfb;
+const INDEFINITE: u8 = 31;
+const BREAK: u8 = 0xff;
+
+const TAG_DATETIME: u64 = 0;
+const TAG_EPOCH: u64 = 1;
+const TAG_POSITIVE_BIGNUM: u64 = 2;
+const TAG_NEGATIVE_BIGNUM: u64 = 3;
+const TAG_SELF_DESCRIBE: u64 = 55799;
+
+/// Encodes the values in `iter` as CBOR.
+pub fn to_cbor<'a, I>(iter: I) -> Vec<u8>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let mut out = Vec::new();
+    write_cbor(iter, &mut out).expect("writing to a Vec can't fail");
+    out
+}
+
+/// Encodes the values in `iter` as CBOR into `writer`.
+pub fn write_cbor<'a, I, W>(iter: I, writer: &mut W) -> io::Result<()>
+    where I: IntoIterator<Item=Token<'a>>,
+          W: io::Write,
+{
+    let mut encoder = Encoder {
+        writer: writer,
+        indefinite: Vec::new(),
+    };
+
+    for token in iter {
+        try!(encoder.token(token));
+    }
+
+    Ok(())
+}
+
+struct Encoder<'w, W: 'w> {
+    writer: &'w mut W,
+    /// One entry per open container, true when it was opened with an indefinite length.
+    indefinite: Vec<bool>,
+}
+
+impl<'w, W> Encoder<'w, W>
+    where W: io::Write,
+{
+    fn token(&mut self, token: Token) -> io::Result<()> {
+        match token {
+            Token::Bool(v) => self.writer.write_all(&[if v { TRUE } else { FALSE }]),
+            Token::Isize(v) => self.signed(v as i64),
+            Token::I8(v) => self.signed(v as i64),
+            Token::I16(v) => self.signed(v as i64),
+            Token::I32(v) => self.signed(v as i64),
+            Token::I64(v) => self.signed(v),
+            Token::Usize(v) => self.head(MAJOR_UNSIGNED, v as u64),
+            Token::U8(v) => self.head(MAJOR_UNSIGNED, v as u64),
+            Token::U16(v) => self.head(MAJOR_UNSIGNED, v as u64),
+            Token::U32(v) => self.head(MAJOR_UNSIGNED, v as u64),
+            Token::U64(v) => self.head(MAJOR_UNSIGNED, v),
+            Token::F32(v) => {
+                try!(self.writer.write_all(&[FLOAT32]));
+                self.writer.write_all(&be_u32(f32_to_bits(v)))
+            }
+            Token::F64(v) => {
+                try!(self.writer.write_all(&[FLOAT64]));
+                self.writer.write_all(&be_u64(f64_to_bits(v)))
+            }
+            Token::Char(v) => self.text(::utils::encode_utf8(v).as_str()),
+            Token::Str(v) => self.text(v),
+            Token::String(ref v) => self.text(v),
+            Token::Bytes(v) => self.bytes(v),
+            Token::ByteBuf(ref v) => self.bytes(v),
+            Token::BigNum(ref v) => self.text(v),
+            Token::Timestamp(secs, nanos) => {
+                try!(self.head(MAJOR_TAG, TAG_EPOCH));
+                if nanos == 0 {
+                    self.signed(secs)
+                } else {
+                    let secs = secs as f64 + nanos as f64 / 1e9;
+                    try!(self.writer.write_all(&[FLOAT64]));
+                    self.writer.write_all(&be_u64(f64_to_bits(secs)))
+                }
+            }
+
+            Token::Option(false) | Token::Unit | Token::UnitStruct(_) => {
+                self.writer.write_all(&[NULL])
+            }
+            Token::Option(true) |
+            Token::StructNewType(_) |
+            Token::TypeTag(_) |
+            Token::EnumStart(_) => Ok(()),
+
+            Token::EnumUnit(_, variant) => self.text(variant),
+            Token::EnumNewType(_, variant) => {
+                try!(self.head(MAJOR_MAP, 1));
+                self.text(variant)
+            }
+
+            Token::SeqStart(len) |
+            Token::TupleStructStart(_, len) => self.open(MAJOR_ARRAY, len),
+            Token::SeqArrayStart(len) |
+            Token::TupleStart(len) => self.open(MAJOR_ARRAY, Some(len)),
+            Token::MapStart(len) |
+            Token::StructStart(_, len) => self.open(MAJOR_MAP, len),
+            Token::EnumSeqStart(_, variant, len) => {
+                try!(self.head(MAJOR_MAP, 1));
+                try!(self.text(variant));
+                self.open(MAJOR_ARRAY, len)
+            }
+            Token::EnumMapStart(_, variant, len) => {
+                try!(self.head(MAJOR_MAP, 1));
+                try!(self.text(variant));
+                self.open(MAJOR_MAP, len)
+            }
+
+            Token::SeqSep |
+            Token::TupleSep |
+            Token::TupleStructSep |
+            Token::MapSep |
+            Token::StructSep |
+            Token::EnumSeqSep |
+            Token::EnumMapSep => Ok(()),
+
+            Token::SeqEnd |
+            Token::TupleEnd |
+            Token::TupleStructEnd |
+            Token::MapEnd |
+            Token::StructEnd |
+            Token::EnumSeqEnd |
+            Token::EnumMapEnd => {
+                if self.indefinite.pop() == Some(true) {
+                    self.writer.write_all(&[BREAK])
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// Writes the initial byte of a data item followed by its argument in the shortest form.
+    fn head(&mut self, major: u8, value: u64) -> io::Result<()> {
+        let major = major << 5;
+        if value < 24 {
+            self.writer.write_all(&[major | value as u8])
+        } else if value <= 0xff {
+            self.writer.write_all(&[major | 24, value as u8])
+        } else if value <= 0xffff {
+            try!(self.writer.write_all(&[major | 25]));
+            self.writer.write_all(&be_u16(value as u16))
+        } else if value <= 0xffff_ffff {
+            try!(self.writer.write_all(&[major | 26]));
+            self.writer.write_all(&be_u32(value as u32))
+        } else {
+            try!(self.writer.write_all(&[major | 27]));
+            self.writer.write_all(&be_u64(value))
+        }
+    }
+
+    fn signed(&mut self, value: i64) -> io::Result<()> {
+        if value < 0 {
+            self.head(MAJOR_NEGATIVE, !(value as u64))
+        } else {
+            self.head(MAJOR_UNSIGNED, value as u64)
+        }
+    }
+
+    fn text(&mut self, value: &str) -> io::Result<()> {
+        try!(self.head(MAJOR_TEXT, value.len() as u64));
+        self.writer.write_all(value.as_bytes())
+    }
+
+    fn bytes(&mut self, value: &[u8]) -> io::Result<()> {
+        try!(self.head(MAJOR_BYTES, value.len() as u64));
+        self.writer.write_all(value)
+    }
+
+    fn open(&mut self, major: u8, len: Option<usize>) -> io::Result<()> {
+        self.indefinite.push(len.is_none());
+        match len {
+            Some(len) => self.head(major, len as u64),
+            None => self.writer.write_all(&[major << 5 | INDEFINITE]),
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// The error returned when decoding CBOR fails.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    offset: usize,
+    message: String,
+}
+
+impl Error {
+    /// Returns the offset of the byte at which the problem was found.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns a description of the problem.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{} at offset {}", self.message, self.offset)
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        "invalid CBOR"
+    }
+}
+
+/// Decodes the single CBOR data item in `input`, rejecting unknown tags.
+pub fn from_cbor(input: &[u8]) -> Result<Vec<Token>, Error> {
+    Decoder::new(input).decode()
+}
+
+/// A CBOR decoder.
+///
+/// By default unknown tags are rejected and arrays, maps and tags may nest 128 levels deep.
+pub struct Decoder<'a> {
+    input: &'a [u8],
+    pos: usize,
+    lenient: bool,
+    max_depth: usize,
+    tokens: Vec<Token<'a>>,
+}
+
+impl<'a> Decoder<'a> {
+    /// Construct a new `Decoder` reading from `input`.
+    pub fn new(input: &'a [u8]) -> Self {
+        Decoder {
+            input: input,
+            pos: 0,
+            lenient: false,
+            max_depth: 128,
+            tokens: Vec::new(),
+        }
+    }
+
+    /// Sets whether unknown tags are skipped, leaving just the tagged data item, instead of
+    /// rejected.
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Sets how many levels deep arrays, maps and tags may nest.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Decodes the single data item in the input.
+    pub fn decode(mut self) -> Result<Vec<Token<'a>>, Error> {
+        try!(self.item(0));
+        if self.pos != self.input.len() {
+            return Err(self.error("trailing bytes"));
+        }
+        Ok(self.tokens)
+    }
+
+    fn item(&mut self, depth: usize) -> Result<(), Error> {
+        let start = self.pos;
+        let initial = try!(self.byte());
+        let major = initial >> 5;
+        let info = initial & 0x1f;
+
+        match major {
+            MAJOR_UNSIGNED => {
+                let v = try!(self.definite(info, start));
+                self.tokens.push(if v <= i64::MAX as u64 {
+                    Token::I64(v as i64)
+                } else {
+                    Token::U64(v)
+                });
+            }
+            MAJOR_NEGATIVE => {
+                let n = try!(self.definite(info, start));
+                self.tokens.push(if n <= i64::MAX as u64 {
+                    Token::I64(-1 - n as i64)
+                } else {
+                    Token::String(negative_decimal(&be_u64(n)))
+                });
+            }
+            MAJOR_BYTES => {
+                let token = match try!(self.string(MAJOR_BYTES, info, start)) {
+                    Ok(v) => Token::Bytes(v),
+                    Err(v) => Token::ByteBuf(v),
+                };
+                self.tokens.push(token);
+            }
+            MAJOR_TEXT => {
+                let token = match try!(self.string(MAJOR_TEXT, info, start)) {
+                    Ok(v) => str::from_utf8(v).ok().map(Token::Str),
+                    Err(v) => String::from_utf8(v).ok().map(Token::String),
+                };
+                match token {
+                    Some(token) => self.tokens.push(token),
+                    None => return Err(self.error_at(start, "invalid UTF-8 in text string")),
+                }
+            }
+            MAJOR_ARRAY | MAJOR_MAP => {
+                if depth >= self.max_depth {
+                    return Err(self.depth_error(start));
+                }
+                let len = try!(self.argument(info, start));
+                if major == MAJOR_ARRAY {
+                    try!(self.array(len, depth + 1));
+                } else {
+                    try!(self.map(len, depth + 1));
+                }
+            }
+            MAJOR_TAG => {
+                if depth >= self.max_depth {
+                    return Err(self.depth_error(start));
+                }
+                let tag = try!(self.definite(info, start));
+                try!(self.tag(tag, start, depth + 1));
+            }
+            _ => try!(self.simple(initial, start)),
+        }
+
+        Ok(())
+    }
+
+    fn array(&mut self, len: Option<u64>, depth: usize) -> Result<(), Error> {
+        self.tokens.push(Token::SeqStart(len.map(|len| len as usize)));
+        match len {
+            Some(len) => {
+                for _ in 0..len {
+                    self.tokens.push(Token::SeqSep);
+                    try!(self.item(depth));
+                }
+            }
+            None => {
+                while !try!(self.eat_break()) {
+                    self.tokens.push(Token::SeqSep);
+                    try!(self.item(depth));
+                }
+            }
+        }
+        self.tokens.push(Token::SeqEnd);
+        Ok(())
+    }
+
+    fn map(&mut self, len: Option<u64>, depth: usize) -> Result<(), Error> {
+        self.tokens.push(Token::MapStart(len.map(|len| len as usize)));
+        match len {
+            Some(len) => {
+                for _ in 0..len {
+                    self.tokens.push(Token::MapSep);
+                    try!(self.item(depth));
+                    try!(self.item(depth));
+                }
+            }
+            None => {
+                while !try!(self.eat_break()) {
+                    self.tokens.push(Token::MapSep);
+                    try!(self.item(depth));
+                    try!(self.item(depth));
+                }
+            }
+        }
+        self.tokens.push(Token::MapEnd);
+        Ok(())
+    }
+
+    fn tag(&mut self, tag: u64, start: usize, depth: usize) -> Result<(), Error> {
+        let mark = self.tokens.len();
+        try!(self.item(depth));
+
+        let replacement = match tag {
+            TAG_DATETIME => {
+                let timestamp = self.tagged_str(mark).and_then(time::parse_rfc3339);
+                match timestamp {
+                    Some((secs, nanos)) => Token::Timestamp(secs, nanos),
+                    None => return Err(self.error_at(start, "invalid date-time string")),
+                }
+            }
+            TAG_EPOCH => {
+                match self.tagged_epoch(mark) {
+                    Some((secs, nanos)) => Token::Timestamp(secs, nanos),
+                    None => return Err(self.error_at(start, "invalid epoch-based date-time")),
+                }
+            }
+            TAG_POSITIVE_BIGNUM | TAG_NEGATIVE_BIGNUM => {
+                let digits = match self.tagged_bytes(mark) {
+                    Some(bytes) if tag == TAG_POSITIVE_BIGNUM => decimal(bytes),
+                    Some(bytes) => negative_decimal(bytes),
+                    None => return Err(self.error_at(start, "bignum must be a byte string")),
+                };
+                Token::String(digits)
+            }
+            TAG_SELF_DESCRIBE => return Ok(()),
+            _ if self.lenient => return Ok(()),
+            _ => return Err(self.error_at(start, &format!("unknown tag {}", tag))),
+        };
+
+        self.tokens.truncate(mark);
+        self.tokens.push(replacement);
+        Ok(())
+    }
+
+    /// Returns the tagged data item starting at token `mark` if it is a single string token.
+    fn tagged_str(&self, mark: usize) -> Option<&str> {
+        if self.tokens.len() == mark + 1 {
+            self.tokens[mark].as_str()
+        } else {
+            None
+        }
+    }
+
+    /// Returns the tagged data item starting at token `mark` if it is a single byte string token.
+    fn tagged_bytes(&self, mark: usize) -> Option<&[u8]> {
+        if self.tokens.len() == mark + 1 {
+            self.tokens[mark].as_bytes()
+        } else {
+            None
+        }
+    }
+
+    /// Converts the tagged number starting at token `mark` into seconds and nanoseconds.
+    fn tagged_epoch(&self, mark: usize) -> Option<(i64, u32)> {
+        if self.tokens.len() != mark + 1 {
+            return None;
+        }
+
+        let v = match self.tokens[mark] {
+            Token::I64(v) => return Some((v, 0)),
+            Token::F32(v) => v as f64,
+            Token::F64(v) => v,
+            _ => return None,
+        };
+
+        let secs = v.floor();
+        if !(secs >= i64::MIN as f64 && secs < i64::MAX as f64) {
+            return None;
+        }
+
+        let nanos = ((v - secs) * 1e9).round() as u32;
+        if nanos >= 1_000_000_000 {
+            Some((secs as i64 + 1, 0))
+        } else {
+            Some((secs as i64, nanos))
+        }
+    }
+
+    fn simple(&mut self, initial: u8, start: usize) -> Result<(), Error> {
+        let token = match initial {
+            FALSE => Token::Bool(false),
+            TRUE => Token::Bool(true),
+            NULL | UNDEFINED => Token::Unit,
+            FLOAT16 => Token::F32(f16_to_f32(try!(self.uint(2)) as u16)),
+            FLOAT32 => Token::F32(f32_from_bits(try!(self.uint(4)) as u32)),
+            FLOAT64 => Token::F64(f64_from_bits(try!(self.uint(8)))),
+            BREAK => return Err(self.error_at(start, "unexpected break")),
+            _ => return Err(self.error_at(start, "unsupported simple value")),
+        };
+        self.tokens.push(token);
+        Ok(())
+    }
+
+    /// Reads a string of the given major type. Returns `Ok` with a slice of the input for a
+    /// definite-length string, and `Err` with the concatenated chunks for an indefinite-length
+    /// one.
+    fn string(&mut self,
+              major: u8,
+              info: u8,
+              start: usize) -> Result<Result<&'a [u8], Vec<u8>>, Error> {
+        if let Some(len) = try!(self.argument(info, start)) {
+            return self.slice(len).map(Ok);
+        }
+
+        let mut out = Vec::new();
+        while !try!(self.eat_break()) {
+            let chunk_start = self.pos;
+            let initial = try!(self.byte());
+            if initial >> 5 != major || initial & 0x1f == INDEFINITE {
+                return Err(self.error_at(chunk_start, "invalid chunk in indefinite-length string"));
+            }
+            let len = try!(self.definite(initial & 0x1f, chunk_start));
+            out.extend(try!(self.slice(len)).iter().cloned());
+        }
+        Ok(Err(out))
+    }
+
+    /// Reads the argument of a data item, which is `None` for an indefinite length.
+    fn argument(&mut self, info: u8, start: usize) -> Result<Option<u64>, Error> {
+        match info {
+            0 ... 23 => Ok(Some(info as u64)),
+            24 => self.uint(1).map(Some),
+            25 => self.uint(2).map(Some),
+            26 => self.uint(4).map(Some),
+            27 => self.uint(8).map(Some),
+            INDEFINITE => Ok(None),
+            _ => Err(self.error_at(start, "reserved additional information")),
+        }
+    }
+
+    /// Reads the argument of a data item that can't have an indefinite length.
+    fn definite(&mut self, info: u8, start: usize) -> Result<u64, Error> {
+        match try!(self.argument(info, start)) {
+            Some(v) => Ok(v),
+            None => Err(self.error_at(start, "unexpected indefinite length")),
+        }
+    }
+
+    /// Consumes a break if it is next, and returns whether it was.
+    fn eat_break(&mut self) -> Result<bool, Error> {
+        match self.input.get(self.pos) {
+            Some(&BREAK) => {
+                self.pos += 1;
+                Ok(true)
+            }
+            Some(_) => Ok(false),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        let bytes = try!(self.slice(1));
+        Ok(bytes[0])
+    }
+
+    /// Reads a big endian unsigned integer of `len` bytes.
+    fn uint(&mut self, len: u64) -> Result<u64, Error> {
+        let bytes = try!(self.slice(len));
+        Ok(bytes.iter().fold(0, |n, &byte| n << 8 | byte as u64))
+    }
+
+    fn slice(&mut self, len: u64) -> Result<&'a [u8], Error> {
+        let input = self.input;
+        if len > (input.len() - self.pos) as u64 {
+            self.pos = input.len();
+            return Err(self.error("unexpected end of input"));
+        }
+        let start = self.pos;
+        self.pos += len as usize;
+        Ok(&input[start..self.pos])
+    }
+
+    fn depth_error(&self, start: usize) -> Error {
+        let message = format!("nesting exceeds the depth limit of {}", self.max_depth);
+        self.error_at(start, &message)
+    }
+
+    fn error(&self, message: &str) -> Error {
+        self.error_at(self.pos, message)
+    }
+
+    fn error_at(&self, offset: usize, message: &str) -> Error {
+        Error {
+            offset: offset,
+            message: message.to_owned(),
+        }
+    }
+}
+
+/// Converts an IEEE 754 half precision float.
+fn f16_to_f32(half: u16) -> f32 {
+    let exponent = (half >> 10) & 0x1f;
+    let mantissa = (half & 0x3ff) as f32;
+
+    let value = match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        31 if mantissa == 0.0 => f32::INFINITY,
+        31 => f32::NAN,
+        _ => (mantissa + 1024.0) * 2f32.powi(exponent as i32 - 25),
+    };
+
+    if half & 0x8000 != 0 { -value } else { value }
+}
+
+/// Returns the decimal digits of the big endian unsigned integer `bytes`.
+fn decimal(bytes: &[u8]) -> String {
+    // Little endian base 10 digits.
+    let mut digits = vec![0u8];
+    for &byte in bytes {
+        let mut carry = byte as u32;
+        for digit in &mut digits {
+            let v = *digit as u32 * 256 + carry;
+            *digit = (v % 10) as u8;
+            carry = v / 10;
+        }
+        while carry > 0 {
+            digits.push((carry % 10) as u8);
+            carry /= 10;
+        }
+    }
+
+    while digits.len() > 1 && digits[digits.len() - 1] == 0 {
+        digits.pop();
+    }
+    digits.iter().rev().map(|&digit| (b'0' + digit) as char).collect()
+}
+
+/// Returns the decimal digits of `-1 - n`, where `n` is the big endian unsigned integer `bytes`.
+fn negative_decimal(bytes: &[u8]) -> String {
+    let mut n = bytes.to_vec();
+    let mut carry = true;
+    for byte in n.iter_mut().rev() {
+        if !carry {
+            break;
+        }
+        let (sum, overflow) = byte.overflowing_add(1);
+        *byte = sum;
+        carry = overflow;
+    }
+    if carry {
+        n.insert(0, 1);
+    }
+    format!("-{}", decimal(&n))
+}
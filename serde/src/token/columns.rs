@@ -0,0 +1,161 @@
+//! Transpose a sequence of structs into columns.
+
+use std::collections::BTreeMap;
+
+use super::{Error, Token};
+
+/// Turns the sequence of structs in `iter` into columns, one per field, each holding the values
+/// of that field in the order of the structs.
+///
+/// Every struct has to have the same set of fields, though not necessarily in the same order.
+/// A value is kept as the tokens it was made of, so a column entry of a nested value holds all
+/// of its tokens. An empty sequence has no columns.
+///
+/// ```rust
+/// use serde::token::{self, Token};
+///
+/// let tokens = vec![
+///     Token::SeqStart(Some(2)),
+///     Token::SeqSep,
+///     Token::StructStart("P", Some(2)),
+///     Token::StructSep, Token::Str("x"), Token::I32(1),
+///     Token::StructSep, Token::Str("y"), Token::I32(2),
+///     Token::StructEnd,
+///     Token::SeqSep,
+///     Token::StructStart("P", Some(2)),
+///     Token::StructSep, Token::Str("y"), Token::I32(4),
+///     Token::StructSep, Token::Str("x"), Token::I32(3),
+///     Token::StructEnd,
+///     Token::SeqEnd,
+/// ];
+/// let columns = token::to_columns(tokens).unwrap();
+/// assert_eq!(columns["x"], vec![vec![Token::I32(1)], vec![Token::I32(3)]]);
+/// assert_eq!(columns["y"], vec![vec![Token::I32(2)], vec![Token::I32(4)]]);
+/// ```
+pub fn to_columns<'a, I>(iter: I) -> Result<BTreeMap<String, Vec<Vec<Token<'a>>>>, Error>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let mut tokens = iter.into_iter();
+    match try!(next(&mut tokens)) {
+        Token::SeqStart(_) | Token::SeqArrayStart(_) => { }
+        token => {
+            return Err(Error::InvalidValue(format!("expected a sequence of structs, found {:?}",
+                                                   token)));
+        }
+    }
+
+    let mut columns = BTreeMap::new();
+    // The fields of the first struct, in their order.
+    let mut fields: Option<Vec<String>> = None;
+    let mut row = 0;
+    loop {
+        match try!(next(&mut tokens)) {
+            Token::SeqSep => { }
+            Token::SeqEnd => break,
+            token => return Err(unexpected(&token)),
+        }
+
+        match try!(next(&mut tokens)) {
+            Token::StructStart(_, _) => { }
+            token => {
+                return Err(Error::InvalidValue(format!("expected a struct at index {}, found {:?}",
+                                                       row, token)));
+            }
+        }
+        let values = try!(read_struct(&mut tokens, row));
+
+        match fields {
+            None => {
+                fields = Some(values.iter().map(|&(ref field, _)| field.clone()).collect());
+                for (field, value) in values {
+                    columns.insert(field, vec![value]);
+                }
+            }
+            Some(ref fields) => {
+                let same = values.len() == fields.len() &&
+                    values.iter().all(|&(ref field, _)| columns.contains_key(field));
+                if !same {
+                    let found: Vec<_> = values.iter().map(|&(ref field, _)| field).collect();
+                    return Err(Error::InvalidValue(
+                        format!("struct at index {} has the fields {:?}, expected {:?}",
+                                row, found, fields)));
+                }
+                for (field, value) in values {
+                    columns.get_mut(&field).expect("field was checked").push(value);
+                }
+            }
+        }
+        row += 1;
+    }
+
+    if let Some(token) = tokens.next() {
+        return Err(unexpected(&token));
+    }
+    Ok(columns)
+}
+
+/// Reads the fields of the struct whose start token was just read, with the tokens of their
+/// values.
+fn read_struct<'a, I>(tokens: &mut I, row: usize) -> Result<Vec<(String, Vec<Token<'a>>)>, Error>
+    where I: Iterator<Item=Token<'a>>,
+{
+    let mut values: Vec<(String, Vec<Token<'a>>)> = Vec::new();
+    loop {
+        match try!(next(tokens)) {
+            Token::StructSep => { }
+            Token::StructEnd => return Ok(values),
+            token => return Err(unexpected(&token)),
+        }
+
+        let key = try!(next(tokens));
+        let field = match key.as_str() {
+            Some(field) => field.to_owned(),
+            None => return Err(unexpected(&key)),
+        };
+        if values.iter().any(|&(ref seen, _)| *seen == field) {
+            return Err(Error::InvalidValue(format!("duplicate field {:?} in struct at index {}",
+                                                   field, row)));
+        }
+
+        let value = try!(read_value(tokens));
+        values.push((field, value));
+    }
+}
+
+/// Reads the tokens of a single value.
+fn read_value<'a, I>(tokens: &mut I) -> Result<Vec<Token<'a>>, Error>
+    where I: Iterator<Item=Token<'a>>,
+{
+    let mut value = Vec::new();
+    let mut depth = 0;
+    loop {
+        let token = try!(next(tokens));
+        if token.is_start() {
+            depth += 1;
+        } else if token.is_end() {
+            depth -= 1;
+        }
+        let wraps_value = match token {
+            Token::Option(true) |
+            Token::StructNewType(_) |
+            Token::TypeTag(_) |
+            Token::EnumNewType(_, _) => true,
+            _ => false,
+        };
+        value.push(token);
+
+        if depth == 0 && !wraps_value {
+            return Ok(value);
+        }
+    }
+}
+
+fn next<'a, I>(tokens: &mut I) -> Result<Token<'a>, Error>
+    where I: Iterator<Item=Token<'a>>,
+{
+    tokens.next().ok_or_else(|| Error::InvalidValue("unexpected end of stream".to_owned()))
+}
+
+fn unexpected(token: &Token) -> Error {
+    Error::InvalidValue(format!("unexpected token {:?}", token))
+}
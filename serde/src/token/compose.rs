@@ -0,0 +1,328 @@
+//! Assemble token streams from pieces that were serialized separately.
+//!
+//! A response is often made of parts that already exist as tokens, such as a cached recording
+//! or the output of a reader, next to values that still have to be serialized. `Object` and
+//! `Array` splice them into a single stream without deserializing anything, and check that every
+//! piece is a single well formed value so that the result is one too. `DisplayList` builds a
+//! sequence out of pieces of different types without collecting them.
+
+use std::vec;
+
+use ser::Serialize;
+
+use super::{Error, Token, to_tokens};
+use super::validate::validate;
+
+/// Builds a map, or a struct, out of fields whose values are given as token streams or as
+/// values to serialize.
+///
+/// Errors are kept until `finish`, which reports the first one.
+///
+/// ```rust
+/// use serde::token::{self, Token};
+/// use serde::token::compose::Object;
+///
+/// let cached = token::to_tokens(&vec![1, 2]).unwrap();
+/// let tokens = Object::new()
+///     .field("items", cached)
+///     .field_value("count", &2)
+///     .finish()
+///     .unwrap();
+/// assert_eq!(token::to_json(tokens).unwrap(), r#"{"items":[1,2],"count":2}"#);
+/// ```
+pub struct Object<'a> {
+    name: Option<&'a str>,
+    tokens: Vec<Token<'a>>,
+    len: usize,
+    error: Option<Error>,
+}
+
+impl<'a> Object<'a> {
+    /// Starts a map with string keys.
+    pub fn new() -> Self {
+        Object {
+            name: None,
+            tokens: Vec::new(),
+            len: 0,
+            error: None,
+        }
+    }
+
+    /// Starts a struct called `name`.
+    pub fn named(name: &'a str) -> Self {
+        Object {
+            name: Some(name),
+            ..Object::new()
+        }
+    }
+
+    /// Adds the field `name` with the value recorded in `value`, which has to be a single well
+    /// formed value.
+    pub fn field<I>(mut self, name: &'a str, value: I) -> Self
+        where I: IntoIterator<Item=Token<'a>>,
+    {
+        if self.error.is_none() {
+            match piece(value, || format!("field {:?}", name)) {
+                Ok(value) => {
+                    self.tokens.push(if self.name.is_some() {
+                        Token::StructSep
+                    } else {
+                        Token::MapSep
+                    });
+                    self.tokens.push(Token::Str(name));
+                    self.tokens.extend(value);
+                    self.len += 1;
+                }
+                Err(err) => self.error = Some(err),
+            }
+        }
+        self
+    }
+
+    /// Adds the field `name` with `value` serialized into tokens.
+    pub fn field_value<T: ?Sized>(self, name: &'a str, value: &T) -> Self
+        where T: Serialize,
+    {
+        match to_tokens(value) {
+            Ok(tokens) => self.field(name, tokens),
+            Err(err) => self.fail(err),
+        }
+    }
+
+    /// Returns the tokens of the whole map or struct, or the first error.
+    pub fn finish(self) -> Result<Vec<Token<'a>>, Error> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+
+        let (start, end) = match self.name {
+            Some(name) => (Token::StructStart(name, Some(self.len)), Token::StructEnd),
+            None => (Token::MapStart(Some(self.len)), Token::MapEnd),
+        };
+        let mut tokens = Vec::with_capacity(self.tokens.len() + 2);
+        tokens.push(start);
+        tokens.extend(self.tokens);
+        tokens.push(end);
+        Ok(tokens)
+    }
+
+    fn fail(mut self, err: Error) -> Self {
+        if self.error.is_none() {
+            self.error = Some(err);
+        }
+        self
+    }
+}
+
+impl<'a> Default for Object<'a> {
+    fn default() -> Self {
+        Object::new()
+    }
+}
+
+/// Builds a sequence out of elements given as token streams or as values to serialize.
+///
+/// Errors are kept until `finish`, which reports the first one.
+///
+/// ```rust
+/// use serde::token::{self, Token};
+/// use serde::token::compose::Array;
+///
+/// let tokens = Array::new()
+///     .element(vec![Token::Str("a")])
+///     .element_value(&Some(1))
+///     .finish()
+///     .unwrap();
+/// assert_eq!(token::to_json(tokens).unwrap(), r#"["a",1]"#);
+/// ```
+pub struct Array<'a> {
+    tokens: Vec<Token<'a>>,
+    len: usize,
+    error: Option<Error>,
+}
+
+impl<'a> Array<'a> {
+    /// Starts an empty sequence.
+    pub fn new() -> Self {
+        Array {
+            tokens: Vec::new(),
+            len: 0,
+            error: None,
+        }
+    }
+
+    /// Adds the element recorded in `value`, which has to be a single well formed value.
+    pub fn element<I>(mut self, value: I) -> Self
+        where I: IntoIterator<Item=Token<'a>>,
+    {
+        if self.error.is_none() {
+            let index = self.len;
+            match piece(value, || format!("element {}", index)) {
+                Ok(value) => {
+                    self.tokens.push(Token::SeqSep);
+                    self.tokens.extend(value);
+                    self.len += 1;
+                }
+                Err(err) => self.error = Some(err),
+            }
+        }
+        self
+    }
+
+    /// Adds `value` serialized into tokens.
+    pub fn element_value<T: ?Sized>(self, value: &T) -> Self
+        where T: Serialize,
+    {
+        match to_tokens(value) {
+            Ok(tokens) => self.element(tokens),
+            Err(err) => self.fail(err),
+        }
+    }
+
+    /// Returns the tokens of the whole sequence, or the first error.
+    pub fn finish(self) -> Result<Vec<Token<'a>>, Error> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+
+        let mut tokens = Vec::with_capacity(self.tokens.len() + 2);
+        tokens.push(Token::SeqStart(Some(self.len)));
+        tokens.extend(self.tokens);
+        tokens.push(Token::SeqEnd);
+        Ok(tokens)
+    }
+
+    fn fail(mut self, err: Error) -> Self {
+        if self.error.is_none() {
+            self.error = Some(err);
+        }
+        self
+    }
+}
+
+impl<'a> Default for Array<'a> {
+    fn default() -> Self {
+        Array::new()
+    }
+}
+
+/// A sequence of elements of different types, each kept as a boxed token stream until the
+/// sequence is iterated.
+///
+/// Unlike `Array`, which collects and validates every element, this only boxes the streams it is
+/// given, so streams read lazily, e.g. from a `json::Stream`, stay lazy. They are not checked to
+/// be single well formed values.
+///
+/// ```rust
+/// use serde::token::{self, Token};
+/// use serde::token::compose::DisplayList;
+///
+/// let mut list = DisplayList::new();
+/// list.push(&1).unwrap();
+/// list.push_tokens(vec![Token::Str("two")]);
+/// assert_eq!(token::to_json(list).unwrap(), r#"[1,"two"]"#);
+/// ```
+pub struct DisplayList<'a> {
+    items: Vec<Box<Iterator<Item=Token<'a>> + 'a>>,
+}
+
+impl<'a> DisplayList<'a> {
+    /// Construct an empty `DisplayList`.
+    pub fn new() -> Self {
+        DisplayList {
+            items: Vec::new(),
+        }
+    }
+
+    /// Adds `value` serialized into tokens.
+    pub fn push<T: ?Sized>(&mut self, value: &T) -> Result<(), Error>
+        where T: Serialize,
+    {
+        let tokens = try!(to_tokens(value));
+        self.items.push(Box::new(tokens.into_iter()));
+        Ok(())
+    }
+
+    /// Adds the element that `value` yields the tokens of.
+    pub fn push_tokens<I>(&mut self, value: I)
+        where I: IntoIterator<Item=Token<'a>>,
+              I::IntoIter: 'a,
+    {
+        self.items.push(Box::new(value.into_iter()));
+    }
+
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns whether there are no elements.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+impl<'a> Default for DisplayList<'a> {
+    fn default() -> Self {
+        DisplayList::new()
+    }
+}
+
+impl<'a> IntoIterator for DisplayList<'a> {
+    type Item = Token<'a>;
+    type IntoIter = DisplayTokens<'a>;
+
+    fn into_iter(self) -> DisplayTokens<'a> {
+        DisplayTokens {
+            start: Some(Token::SeqStart(Some(self.items.len()))),
+            items: self.items.into_iter(),
+            current: None,
+            done: false,
+        }
+    }
+}
+
+/// Iterator over the tokens of a `DisplayList`.
+pub struct DisplayTokens<'a> {
+    start: Option<Token<'a>>,
+    items: vec::IntoIter<Box<Iterator<Item=Token<'a>> + 'a>>,
+    /// The element being yielded.
+    current: Option<Box<Iterator<Item=Token<'a>> + 'a>>,
+    done: bool,
+}
+
+impl<'a> Iterator for DisplayTokens<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        if let Some(start) = self.start.take() {
+            return Some(start);
+        }
+        if let Some(token) = self.current.as_mut().and_then(|current| current.next()) {
+            return Some(token);
+        }
+
+        self.current = self.items.next();
+        if self.current.is_some() {
+            Some(Token::SeqSep)
+        } else if !self.done {
+            self.done = true;
+            Some(Token::SeqEnd)
+        } else {
+            None
+        }
+    }
+}
+
+/// Collects `value` and checks that it is a single well formed value, describing it with what
+/// `describe` returns in the error.
+fn piece<'a, I, F>(value: I, describe: F) -> Result<Vec<Token<'a>>, Error>
+    where I: IntoIterator<Item=Token<'a>>,
+          F: FnOnce() -> String,
+{
+    let tokens: Vec<Token<'a>> = value.into_iter().collect();
+    match validate(tokens.iter().cloned()) {
+        Ok(()) => Ok(tokens),
+        Err(err) => Err(Error::InvalidValue(format!("{}: {}", describe(), err))),
+    }
+}
@@ -0,0 +1,210 @@
+//! Override how particular types serialize, for one context at a time.
+//!
+//! A type that isn't ours serializes one way, but one API may want it another way, like a
+//! duration as a number of milliseconds. `Overrides` maps the names types serialize under, as
+//! they appear in `StructStart` and the other named tokens, to functions that rewrite the tokens
+//! of such a value. A `ContextSerializer` applies them on the way to a backend:
+//!
+//! ```rust
+//! use serde::ser::Serialize;
+//! use serde::token::{Token, Serializer};
+//! use serde::token::context::Overrides;
+//!
+//! struct Meters(f64);
+//!
+//! impl Serialize for Meters {
+//!     fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+//!         where S: serde::Serializer,
+//!     {
+//!         serializer.serialize_newtype_struct("Meters", self.0)
+//!     }
+//! }
+//!
+//! let overrides = Overrides::new().with("Meters", |tokens| {
+//!     Ok(match tokens[1] {
+//!         Token::F64(v) => vec![Token::String(format!("{}m", v))],
+//!         _ => tokens,
+//!     })
+//! });
+//!
+//! let mut ser = Serializer::with_overrides(Vec::new(), overrides);
+//! vec![Meters(2.5)].serialize(&mut ser).unwrap();
+//! assert_eq!(ser.into_inner().into_inner(), vec![
+//!     Token::SeqStart(Some(1)),
+//!     Token::SeqSep, Token::String("2.5m".to_owned()),
+//!     Token::SeqEnd,
+//! ]);
+//! ```
+//!
+//! The rewrite receives the whole value, starting with the token that names the type. Values
+//! inside it are passed along as they are, even if an override matches them, and the tokens a
+//! rewrite returns aren't rewritten again.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+
+use super::{Error, Serializer, Token, TokenSink};
+
+/// A function that rewrites the tokens of a value.
+pub type Rewrite = Fn(Vec<Token<'static>>) -> Result<Vec<Token<'static>>, Error>;
+
+/// The rewrites of a context, by the name of the type they apply to.
+///
+/// Cloning an `Overrides` only bumps a reference count, and adding to a clone leaves the
+/// original untouched, so one set of overrides can be shared between contexts and extended
+/// for some of them.
+#[derive(Clone, Default)]
+pub struct Overrides {
+    rewrites: Rc<BTreeMap<String, Rc<Rewrite>>>,
+}
+
+impl Overrides {
+    /// Construct a new `Overrides` that doesn't change anything.
+    pub fn new() -> Self {
+        Overrides::default()
+    }
+
+    /// Rewrites the values of the type named `name` with `f`, replacing any earlier rewrite for
+    /// that name.
+    pub fn with<F>(mut self, name: &str, f: F) -> Self
+        where F: Fn(Vec<Token<'static>>) -> Result<Vec<Token<'static>>, Error> + 'static,
+    {
+        Rc::make_mut(&mut self.rewrites).insert(name.to_owned(), Rc::new(f));
+        self
+    }
+
+    /// Adds the rewrites of `other`, which take the place of those for the same names.
+    pub fn extend(mut self, other: &Overrides) -> Self {
+        if self.rewrites.is_empty() {
+            return other.clone();
+        }
+        let rewrites = Rc::make_mut(&mut self.rewrites);
+        for (name, f) in other.rewrites.iter() {
+            rewrites.insert(name.clone(), f.clone());
+        }
+        self
+    }
+
+    /// Returns true if the values of the type named `name` are rewritten.
+    pub fn contains(&self, name: &str) -> bool {
+        self.rewrites.contains_key(name)
+    }
+
+    /// Returns true if nothing is rewritten.
+    pub fn is_empty(&self) -> bool {
+        self.rewrites.is_empty()
+    }
+}
+
+impl fmt::Debug for Overrides {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.debug_set().entries(self.rewrites.keys()).finish()
+    }
+}
+
+/// A `Serializer` that applies `Overrides` before passing the tokens on to a backend.
+pub type ContextSerializer<S> = Serializer<Context<S>>;
+
+impl<S> Serializer<Context<S>>
+    where S: TokenSink,
+{
+    /// Construct a new `ContextSerializer` that rewrites values as `overrides` says and passes
+    /// the tokens on to `sink`.
+    pub fn with_overrides(sink: S, overrides: Overrides) -> Self {
+        Serializer::with_sink(Context {
+            sink: sink,
+            overrides: overrides,
+            rewrite: None,
+            value: Vec::new(),
+            depth: 0,
+        })
+    }
+}
+
+/// The `TokenSink` behind `ContextSerializer`.
+pub struct Context<S> {
+    sink: S,
+    overrides: Overrides,
+    /// The rewrite of the value being collected, if any.
+    rewrite: Option<Rc<Rewrite>>,
+    /// The tokens of the value so far.
+    value: Vec<Token<'static>>,
+    /// The number of compound values open in the value.
+    depth: usize,
+}
+
+impl<S> Context<S> {
+    /// Returns the overrides.
+    pub fn overrides(&self) -> &Overrides {
+        &self.overrides
+    }
+
+    /// Unwraps the backend.
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+}
+
+impl<S> fmt::Debug for Context<S> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.debug_struct("Context")
+            .field("overrides", &self.overrides)
+            .field("value", &self.value)
+            .finish()
+    }
+}
+
+impl<S> TokenSink for Context<S>
+    where S: TokenSink,
+{
+    fn push(&mut self, token: Token<'static>) -> Result<(), Error> {
+        if self.rewrite.is_none() {
+            let rewrite = type_name(&token).and_then(|name| self.overrides.rewrites.get(name));
+            match rewrite {
+                Some(rewrite) => self.rewrite = Some(rewrite.clone()),
+                None => return self.sink.push(token),
+            }
+        }
+
+        if token.is_start() {
+            self.depth += 1;
+        } else if token.is_end() {
+            self.depth -= 1;
+        }
+        let wraps_value = match token {
+            Token::Option(true) |
+            Token::StructNewType(_) |
+            Token::TypeTag(_) |
+            Token::EnumNewType(_, _) => true,
+            _ => false,
+        };
+        self.value.push(token);
+
+        if self.depth == 0 && !wraps_value {
+            let rewrite = self.rewrite.take().unwrap();
+            let value = mem::replace(&mut self.value, Vec::new());
+            for token in try!(rewrite(value)) {
+                try!(self.sink.push(token));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the name of the type whose value `token` starts.
+fn type_name<'a>(token: &Token<'a>) -> Option<&'a str> {
+    match *token {
+        Token::UnitStruct(name) |
+        Token::StructNewType(name) |
+        Token::TypeTag(name) |
+        Token::TupleStructStart(name, _) |
+        Token::StructStart(name, _) |
+        Token::EnumUnit(name, _) |
+        Token::EnumNewType(name, _) |
+        Token::EnumSeqStart(name, _, _) |
+        Token::EnumMapStart(name, _, _) => Some(name),
+        _ => None,
+    }
+}
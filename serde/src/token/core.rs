@@ -0,0 +1,702 @@
+//! The token types and the recording `Serializer`.
+//!
+//! Nothing in here depends on `HashMap`, `BTreeMap` or I/O, so this module is also available
+//! without the `std` feature when the `collections` feature provides `String` and `Vec`.
+
+#[cfg(feature = "std")]
+use std::error;
+#[cfg(not(feature = "std"))]
+use error;
+
+use core::fmt;
+
+#[cfg(all(feature = "collections", not(feature = "std")))]
+use collections::{String, Vec};
+#[cfg(all(feature = "collections", not(feature = "std")))]
+use collections::borrow::ToOwned;
+
+use ser::{self, Serialize};
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A single step of the serialization protocol.
+///
+/// Strings and byte arrays come in a borrowed and an owned flavor. The two flavors compare equal
+/// when their contents do, so a recorded stream can be checked against a hand written one.
+#[derive(Clone, Debug)]
+pub enum Token<'a> {
+    /// A `bool`.
+    Bool(bool),
+    /// An `isize`.
+    Isize(isize),
+    /// An `i8`.
+    I8(i8),
+    /// An `i16`.
+    I16(i16),
+    /// An `i32`.
+    I32(i32),
+    /// An `i64`.
+    I64(i64),
+    /// A `usize`.
+    Usize(usize),
+    /// A `u8`.
+    U8(u8),
+    /// A `u16`.
+    U16(u16),
+    /// A `u32`.
+    U32(u32),
+    /// A `u64`.
+    U64(u64),
+    /// An `f32`.
+    F32(f32),
+    /// An `f64`.
+    F64(f64),
+    /// A `char`.
+    Char(char),
+    /// A borrowed string.
+    Str(&'a str),
+    /// An owned string.
+    String(String),
+    /// A borrowed byte array.
+    Bytes(&'a [u8]),
+    /// An owned byte array.
+    ByteBuf(Vec<u8>),
+    /// A point in time, given as seconds and nanoseconds since 1970-01-01T00:00:00Z. Produced by
+    /// readers of formats with a native timestamp type and by `serialize_timestamp`.
+    Timestamp(i64, u32),
+    /// An integer too large for the integer tokens, as its decimal digits with an optional
+    /// leading `-`. Only produced by readers of textual formats that allow such integers.
+    BigNum(String),
+
+    /// Marks an `Option`. `Option(true)` is followed by the contained value.
+    Option(bool),
+
+    /// A `()`.
+    Unit,
+    /// A unit struct with the given name.
+    UnitStruct(&'a str),
+
+    /// A newtype struct with the given name, followed by the wrapped value.
+    StructNewType(&'a str),
+    /// The name of the type of the value that follows, produced by `serialize_type_tag`.
+    TypeTag(&'a str),
+
+    /// Announces an enum by name. Only used when deserializing.
+    EnumStart(&'a str),
+    /// A unit variant, given as the enum and variant names.
+    EnumUnit(&'a str, &'a str),
+    /// A newtype variant, followed by the wrapped value.
+    EnumNewType(&'a str, &'a str),
+
+    /// Starts a sequence of the given length, if known.
+    SeqStart(Option<usize>),
+    /// Starts a fixed size array.
+    SeqArrayStart(usize),
+    /// Precedes each sequence element.
+    SeqSep,
+    /// Ends a sequence or array.
+    SeqEnd,
+
+    /// Starts a tuple.
+    TupleStart(usize),
+    /// Precedes each tuple element.
+    TupleSep,
+    /// Ends a tuple.
+    TupleEnd,
+
+    /// Starts a tuple struct.
+    TupleStructStart(&'a str, Option<usize>),
+    /// Precedes each tuple struct element.
+    TupleStructSep,
+    /// Ends a tuple struct.
+    TupleStructEnd,
+
+    /// Starts a map.
+    MapStart(Option<usize>),
+    /// Precedes each key-value pair.
+    MapSep,
+    /// Ends a map.
+    MapEnd,
+
+    /// Starts a struct.
+    StructStart(&'a str, Option<usize>),
+    /// Precedes each field, which is a `Str` with the field name followed by the value.
+    StructSep,
+    /// Ends a struct.
+    StructEnd,
+
+    /// Starts a tuple variant.
+    EnumSeqStart(&'a str, &'a str, Option<usize>),
+    /// Precedes each tuple variant element.
+    EnumSeqSep,
+    /// Ends a tuple variant.
+    EnumSeqEnd,
+
+    /// Starts a struct variant.
+    EnumMapStart(&'a str, &'a str, Option<usize>),
+    /// Precedes each struct variant field.
+    EnumMapSep,
+    /// Ends a struct variant.
+    EnumMapEnd,
+}
+
+impl<'a> Token<'a> {
+    /// Returns the string contents of a `Str` or `String` token.
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Token::Str(v) => Some(v),
+            Token::String(ref v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns the contents of a `Bytes` or `ByteBuf` token.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match *self {
+            Token::Bytes(v) => Some(v),
+            Token::ByteBuf(ref v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns true if this token opens a compound value.
+    pub fn is_start(&self) -> bool {
+        match *self {
+            Token::SeqStart(_) |
+            Token::SeqArrayStart(_) |
+            Token::TupleStart(_) |
+            Token::TupleStructStart(_, _) |
+            Token::MapStart(_) |
+            Token::StructStart(_, _) |
+            Token::EnumSeqStart(_, _, _) |
+            Token::EnumMapStart(_, _, _) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if this token separates the elements of a compound value.
+    pub fn is_sep(&self) -> bool {
+        match *self {
+            Token::SeqSep |
+            Token::TupleSep |
+            Token::TupleStructSep |
+            Token::MapSep |
+            Token::StructSep |
+            Token::EnumSeqSep |
+            Token::EnumMapSep => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if this token closes a compound value.
+    pub fn is_end(&self) -> bool {
+        match *self {
+            Token::SeqEnd |
+            Token::TupleEnd |
+            Token::TupleStructEnd |
+            Token::MapEnd |
+            Token::StructEnd |
+            Token::EnumSeqEnd |
+            Token::EnumMapEnd => true,
+            _ => false,
+        }
+    }
+}
+
+impl<'a, 'b> PartialEq<Token<'b>> for Token<'a> {
+    fn eq(&self, other: &Token<'b>) -> bool {
+        match (self, other) {
+            (&Token::Bool(a), &Token::Bool(b)) => a == b,
+            (&Token::Isize(a), &Token::Isize(b)) => a == b,
+            (&Token::I8(a), &Token::I8(b)) => a == b,
+            (&Token::I16(a), &Token::I16(b)) => a == b,
+            (&Token::I32(a), &Token::I32(b)) => a == b,
+            (&Token::I64(a), &Token::I64(b)) => a == b,
+            (&Token::Usize(a), &Token::Usize(b)) => a == b,
+            (&Token::U8(a), &Token::U8(b)) => a == b,
+            (&Token::U16(a), &Token::U16(b)) => a == b,
+            (&Token::U32(a), &Token::U32(b)) => a == b,
+            (&Token::U64(a), &Token::U64(b)) => a == b,
+            (&Token::F32(a), &Token::F32(b)) => a == b,
+            (&Token::F64(a), &Token::F64(b)) => a == b,
+            (&Token::Char(a), &Token::Char(b)) => a == b,
+            (&Token::Str(_), _) |
+            (&Token::String(_), _) => self.as_str().is_some() && self.as_str() == other.as_str(),
+            (&Token::Bytes(_), _) |
+            (&Token::ByteBuf(_), _) => {
+                self.as_bytes().is_some() && self.as_bytes() == other.as_bytes()
+            }
+            (&Token::Timestamp(a0, a1), &Token::Timestamp(b0, b1)) => a0 == b0 && a1 == b1,
+            (&Token::BigNum(ref a), &Token::BigNum(ref b)) => a == b,
+            (&Token::Option(a), &Token::Option(b)) => a == b,
+            (&Token::Unit, &Token::Unit) => true,
+            (&Token::UnitStruct(a), &Token::UnitStruct(b)) => a == b,
+            (&Token::StructNewType(a), &Token::StructNewType(b)) => a == b,
+            (&Token::TypeTag(a), &Token::TypeTag(b)) => a == b,
+            (&Token::EnumStart(a), &Token::EnumStart(b)) => a == b,
+            (&Token::EnumUnit(a0, a1), &Token::EnumUnit(b0, b1)) => a0 == b0 && a1 == b1,
+            (&Token::EnumNewType(a0, a1), &Token::EnumNewType(b0, b1)) => a0 == b0 && a1 == b1,
+            (&Token::SeqStart(a), &Token::SeqStart(b)) => a == b,
+            (&Token::SeqArrayStart(a), &Token::SeqArrayStart(b)) => a == b,
+            (&Token::SeqSep, &Token::SeqSep) => true,
+            (&Token::SeqEnd, &Token::SeqEnd) => true,
+            (&Token::TupleStart(a), &Token::TupleStart(b)) => a == b,
+            (&Token::TupleSep, &Token::TupleSep) => true,
+            (&Token::TupleEnd, &Token::TupleEnd) => true,
+            (&Token::TupleStructStart(a0, a1), &Token::TupleStructStart(b0, b1)) => {
+                a0 == b0 && a1 == b1
+            }
+            (&Token::TupleStructSep, &Token::TupleStructSep) => true,
+            (&Token::TupleStructEnd, &Token::TupleStructEnd) => true,
+            (&Token::MapStart(a), &Token::MapStart(b)) => a == b,
+            (&Token::MapSep, &Token::MapSep) => true,
+            (&Token::MapEnd, &Token::MapEnd) => true,
+            (&Token::StructStart(a0, a1), &Token::StructStart(b0, b1)) => a0 == b0 && a1 == b1,
+            (&Token::StructSep, &Token::StructSep) => true,
+            (&Token::StructEnd, &Token::StructEnd) => true,
+            (&Token::EnumSeqStart(a0, a1, a2), &Token::EnumSeqStart(b0, b1, b2)) => {
+                a0 == b0 && a1 == b1 && a2 == b2
+            }
+            (&Token::EnumSeqSep, &Token::EnumSeqSep) => true,
+            (&Token::EnumSeqEnd, &Token::EnumSeqEnd) => true,
+            (&Token::EnumMapStart(a0, a1, a2), &Token::EnumMapStart(b0, b1, b2)) => {
+                a0 == b0 && a1 == b1 && a2 == b2
+            }
+            (&Token::EnumMapSep, &Token::EnumMapSep) => true,
+            (&Token::EnumMapEnd, &Token::EnumMapEnd) => true,
+            _ => false,
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// This represents all the possible errors that can occur while recording tokens.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// The value had some custom error.
+    Custom(String),
+
+    /// The value is invalid and cannot be serialized.
+    InvalidValue(String),
+}
+
+impl ser::Error for Error {
+    fn custom<T: Into<String>>(msg: T) -> Self { Error::Custom(msg.into()) }
+
+    fn invalid_value(msg: &str) -> Self { Error::InvalidValue(msg.to_owned()) }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Error::Custom(ref s) => write!(formatter, "{}", s),
+            Error::InvalidValue(ref value) => write!(formatter, "Invalid value: {}", value),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        "Serde Token Error"
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        None
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A destination for the tokens produced by a `Serializer`.
+pub trait TokenSink {
+    /// Accepts the next token.
+    fn push(&mut self, token: Token<'static>) -> Result<(), Error>;
+}
+
+impl TokenSink for Vec<Token<'static>> {
+    fn push(&mut self, token: Token<'static>) -> Result<(), Error> {
+        Vec::push(self, token);
+        Ok(())
+    }
+}
+
+impl<'a, S: ?Sized> TokenSink for &'a mut S where S: TokenSink {
+    fn push(&mut self, token: Token<'static>) -> Result<(), Error> {
+        (**self).push(token)
+    }
+}
+
+/// A `Serializer` that turns every call into a `Token` and passes it on to a `TokenSink`. By
+/// default the tokens are recorded into a `Vec`.
+///
+/// Names of structs, variants and fields are `&'static str` in the serialization protocol, so
+/// they are kept borrowed. Strings and bytes are copied into `String` and `ByteBuf` tokens.
+#[derive(Clone, Debug, Default)]
+pub struct Serializer<S = Vec<Token<'static>>> {
+    sink: S,
+    /// Whether to report the format as not human readable.
+    compact: bool,
+}
+
+impl Serializer {
+    /// Construct a new `Serializer` that records into an empty `Vec`.
+    pub fn new() -> Self {
+        Serializer {
+            sink: Vec::new(),
+            compact: false,
+        }
+    }
+
+    /// Returns the tokens recorded so far.
+    pub fn tokens(&self) -> &[Token<'static>] {
+        &self.sink
+    }
+
+    /// Unwraps this `Serializer`, returning the recorded tokens.
+    pub fn into_tokens(self) -> Vec<Token<'static>> {
+        self.sink
+    }
+}
+
+impl<S> Serializer<S>
+    where S: TokenSink,
+{
+    /// Construct a new `Serializer` that passes its tokens to `sink`.
+    pub fn with_sink(sink: S) -> Self {
+        Serializer {
+            sink: sink,
+            compact: false,
+        }
+    }
+
+    /// Sets whether `is_human_readable` returns `true`, which it does by default. Turn it off to
+    /// record the compact forms of types that have one, for a binary writer.
+    pub fn human_readable(mut self, human_readable: bool) -> Self {
+        self.compact = !human_readable;
+        self
+    }
+
+    /// Gets a reference to the underlying sink.
+    pub fn get_ref(&self) -> &S { &self.sink }
+
+    /// Gets a mutable reference to the underlying sink.
+    pub fn get_mut(&mut self) -> &mut S { &mut self.sink }
+
+    /// Unwraps this `Serializer`, returning the underlying sink.
+    pub fn into_inner(self) -> S { self.sink }
+
+    /// Visits every element and closes the sequence with `end`, returning the number of elements.
+    fn visit_seq<V>(&mut self, mut visitor: V, end: Token<'static>) -> Result<usize, Error>
+        where V: ser::SeqVisitor
+    {
+        let mut count = 0;
+        while let Some(()) = try!(visitor.visit(self)) {
+            count += 1;
+        }
+        try!(self.sink.push(end));
+        Ok(count)
+    }
+
+    /// Visits every entry and closes the map with `end`, returning the number of entries.
+    fn visit_map<V>(&mut self, mut visitor: V, end: Token<'static>) -> Result<usize, Error>
+        where V: ser::MapVisitor
+    {
+        let mut count = 0;
+        while let Some(()) = try!(visitor.visit(self)) {
+            count += 1;
+        }
+        try!(self.sink.push(end));
+        Ok(count)
+    }
+}
+
+/// Checks in debug builds that an enum variant serialized as many fields as it declared, which
+/// is easy to get wrong in a hand written `Serialize` implementation.
+fn check_variant_len(name: &str, variant: &str, len: Option<usize>, count: usize) {
+    if let Some(len) = len {
+        debug_assert!(len == count,
+                      "variant {}::{} declared {} fields but serialized {}",
+                      name, variant, len, count);
+    }
+}
+
+impl<S> ser::Serializer for Serializer<S>
+    where S: TokenSink,
+{
+    type Error = Error;
+
+    fn serialize_bool(&mut self, v: bool) -> Result<(), Error> {
+        self.sink.push(Token::Bool(v))
+    }
+
+    fn serialize_isize(&mut self, v: isize) -> Result<(), Error> {
+        self.sink.push(Token::Isize(v))
+    }
+
+    fn serialize_i8(&mut self, v: i8) -> Result<(), Error> {
+        self.sink.push(Token::I8(v))
+    }
+
+    fn serialize_i16(&mut self, v: i16) -> Result<(), Error> {
+        self.sink.push(Token::I16(v))
+    }
+
+    fn serialize_i32(&mut self, v: i32) -> Result<(), Error> {
+        self.sink.push(Token::I32(v))
+    }
+
+    fn serialize_i64(&mut self, v: i64) -> Result<(), Error> {
+        self.sink.push(Token::I64(v))
+    }
+
+    fn serialize_usize(&mut self, v: usize) -> Result<(), Error> {
+        self.sink.push(Token::Usize(v))
+    }
+
+    fn serialize_u8(&mut self, v: u8) -> Result<(), Error> {
+        self.sink.push(Token::U8(v))
+    }
+
+    fn serialize_u16(&mut self, v: u16) -> Result<(), Error> {
+        self.sink.push(Token::U16(v))
+    }
+
+    fn serialize_u32(&mut self, v: u32) -> Result<(), Error> {
+        self.sink.push(Token::U32(v))
+    }
+
+    fn serialize_u64(&mut self, v: u64) -> Result<(), Error> {
+        self.sink.push(Token::U64(v))
+    }
+
+    fn serialize_f32(&mut self, v: f32) -> Result<(), Error> {
+        self.sink.push(Token::F32(v))
+    }
+
+    fn serialize_f64(&mut self, v: f64) -> Result<(), Error> {
+        self.sink.push(Token::F64(v))
+    }
+
+    fn serialize_char(&mut self, v: char) -> Result<(), Error> {
+        self.sink.push(Token::Char(v))
+    }
+
+    fn serialize_str(&mut self, v: &str) -> Result<(), Error> {
+        self.sink.push(Token::String(v.to_owned()))
+    }
+
+    fn serialize_bytes(&mut self, v: &[u8]) -> Result<(), Error> {
+        self.sink.push(Token::ByteBuf(v.to_vec()))
+    }
+
+    fn is_human_readable(&self) -> bool {
+        !self.compact
+    }
+
+    fn serialize_timestamp(&mut self, secs: i64, nanos: u32) -> Result<(), Error> {
+        self.sink.push(Token::Timestamp(secs, nanos))
+    }
+
+    fn serialize_unit(&mut self) -> Result<(), Error> {
+        self.sink.push(Token::Unit)
+    }
+
+    fn serialize_unit_struct(&mut self, name: &'static str) -> Result<(), Error> {
+        self.sink.push(Token::UnitStruct(name))
+    }
+
+    fn serialize_unit_variant(&mut self,
+                              name: &'static str,
+                              _variant_index: usize,
+                              variant: &'static str) -> Result<(), Error> {
+        self.sink.push(Token::EnumUnit(name, variant))
+    }
+
+    fn serialize_newtype_struct<T>(&mut self,
+                                   name: &'static str,
+                                   value: T) -> Result<(), Error>
+        where T: Serialize,
+    {
+        try!(self.sink.push(Token::StructNewType(name)));
+        value.serialize(self)
+    }
+
+    fn serialize_type_tag(&mut self, name: &'static str) -> Result<(), Error> {
+        self.sink.push(Token::TypeTag(name))
+    }
+
+    fn serialize_newtype_variant<T>(&mut self,
+                                    name: &'static str,
+                                    _variant_index: usize,
+                                    variant: &'static str,
+                                    value: T) -> Result<(), Error>
+        where T: Serialize,
+    {
+        try!(self.sink.push(Token::EnumNewType(name, variant)));
+        value.serialize(self)
+    }
+
+    fn serialize_none(&mut self) -> Result<(), Error> {
+        self.sink.push(Token::Option(false))
+    }
+
+    fn serialize_some<V>(&mut self, value: V) -> Result<(), Error>
+        where V: Serialize,
+    {
+        try!(self.sink.push(Token::Option(true)));
+        value.serialize(self)
+    }
+
+    fn serialize_seq<V>(&mut self, visitor: V) -> Result<(), Error>
+        where V: ser::SeqVisitor
+    {
+        try!(self.sink.push(Token::SeqStart(visitor.len())));
+        try!(self.visit_seq(visitor, Token::SeqEnd));
+        Ok(())
+    }
+
+    fn serialize_seq_elt<T>(&mut self, value: T) -> Result<(), Error>
+        where T: Serialize
+    {
+        try!(self.sink.push(Token::SeqSep));
+        value.serialize(self)
+    }
+
+    fn serialize_fixed_size_array<V>(&mut self, visitor: V) -> Result<(), Error>
+        where V: ser::SeqVisitor
+    {
+        let len = visitor.len().expect("arrays must have a length");
+        try!(self.sink.push(Token::SeqArrayStart(len)));
+        try!(self.visit_seq(visitor, Token::SeqEnd));
+        Ok(())
+    }
+
+    fn serialize_tuple<V>(&mut self, visitor: V) -> Result<(), Error>
+        where V: ser::SeqVisitor
+    {
+        let len = visitor.len().expect("tuples must have a length");
+        try!(self.sink.push(Token::TupleStart(len)));
+        try!(self.visit_seq(visitor, Token::TupleEnd));
+        Ok(())
+    }
+
+    fn serialize_tuple_elt<T>(&mut self, value: T) -> Result<(), Error>
+        where T: Serialize
+    {
+        try!(self.sink.push(Token::TupleSep));
+        value.serialize(self)
+    }
+
+    fn serialize_tuple_struct<V>(&mut self, name: &'static str, visitor: V) -> Result<(), Error>
+        where V: ser::SeqVisitor
+    {
+        try!(self.sink.push(Token::TupleStructStart(name, visitor.len())));
+        try!(self.visit_seq(visitor, Token::TupleStructEnd));
+        Ok(())
+    }
+
+    fn serialize_tuple_struct_elt<T>(&mut self, value: T) -> Result<(), Error>
+        where T: Serialize,
+    {
+        try!(self.sink.push(Token::TupleStructSep));
+        value.serialize(self)
+    }
+
+    fn serialize_tuple_variant<V>(&mut self,
+                                  name: &'static str,
+                                  _variant_index: usize,
+                                  variant: &'static str,
+                                  visitor: V) -> Result<(), Error>
+        where V: ser::SeqVisitor
+    {
+        let len = visitor.len();
+        try!(self.sink.push(Token::EnumSeqStart(name, variant, len)));
+        let count = try!(self.visit_seq(visitor, Token::EnumSeqEnd));
+        check_variant_len(name, variant, len, count);
+        Ok(())
+    }
+
+    fn serialize_tuple_variant_elt<T>(&mut self, value: T) -> Result<(), Error>
+        where T: Serialize,
+    {
+        try!(self.sink.push(Token::EnumSeqSep));
+        value.serialize(self)
+    }
+
+    fn serialize_map<V>(&mut self, visitor: V) -> Result<(), Error>
+        where V: ser::MapVisitor
+    {
+        try!(self.sink.push(Token::MapStart(visitor.len())));
+        try!(self.visit_map(visitor, Token::MapEnd));
+        Ok(())
+    }
+
+    fn serialize_map_elt<K, V>(&mut self, key: K, value: V) -> Result<(), Error>
+        where K: Serialize,
+              V: Serialize,
+    {
+        try!(self.sink.push(Token::MapSep));
+        try!(key.serialize(self));
+        value.serialize(self)
+    }
+
+    fn serialize_struct<V>(&mut self, name: &'static str, visitor: V) -> Result<(), Error>
+        where V: ser::MapVisitor
+    {
+        try!(self.sink.push(Token::StructStart(name, visitor.len())));
+        try!(self.visit_map(visitor, Token::StructEnd));
+        Ok(())
+    }
+
+    fn serialize_struct_elt<T>(&mut self, key: &'static str, value: T) -> Result<(), Error>
+        where T: Serialize,
+    {
+        try!(self.sink.push(Token::StructSep));
+        try!(self.sink.push(Token::Str(key)));
+        value.serialize(self)
+    }
+
+    fn serialize_struct_variant<V>(&mut self,
+                                   name: &'static str,
+                                   _variant_index: usize,
+                                   variant: &'static str,
+                                   visitor: V) -> Result<(), Error>
+        where V: ser::MapVisitor
+    {
+        let len = visitor.len();
+        try!(self.sink.push(Token::EnumMapStart(name, variant, len)));
+        let count = try!(self.visit_map(visitor, Token::EnumMapEnd));
+        check_variant_len(name, variant, len, count);
+        Ok(())
+    }
+
+    fn serialize_struct_variant_elt<T>(&mut self, key: &'static str, value: T) -> Result<(), Error>
+        where T: Serialize,
+    {
+        try!(self.sink.push(Token::EnumMapSep));
+        try!(self.sink.push(Token::Str(key)));
+        value.serialize(self)
+    }
+}
+
+/// Records the tokens produced by serializing `value`.
+pub fn to_tokens<T: ?Sized>(value: &T) -> Result<Vec<Token<'static>>, Error>
+    where T: Serialize,
+{
+    let mut ser = Serializer::new();
+    try!(value.serialize(&mut ser));
+    Ok(ser.into_tokens())
+}
+
+/// Records the tokens of a single map entry, the key followed by the value, without the
+/// `MapSep` that precedes them in a map. Useful for streaming a map one entry at a time.
+pub fn entry_tokens<K: ?Sized, V: ?Sized>(key: &K, value: &V) -> Result<Vec<Token<'static>>, Error>
+    where K: Serialize,
+          V: Serialize,
+{
+    let mut ser = Serializer::new();
+    try!(key.serialize(&mut ser));
+    try!(value.serialize(&mut ser));
+    Ok(ser.into_tokens())
+}
@@ -0,0 +1,128 @@
+//! Know the exact number of tokens of a stream before reading it.
+//!
+//! The lengths in `SeqStart` and `MapStart` count elements, not tokens, and adapters like
+//! `Filter` or `FlatMap` lose the size hint of the stream they wrap. A writer that has to put the
+//! size of a whole message in front of it, like a length prefixed frame, would otherwise have to
+//! read the stream twice by hand. `counted` does the counting pass and hands back an iterator
+//! whose `len` is exact.
+//!
+//! `counted` only pays off for lazy adapters over a stream that is already recorded, whose
+//! clones are cheap. A value that still has to be serialized is counted with `count_tokens`,
+//! which serializes it without keeping the tokens, and can then be serialized again into the
+//! writer.
+
+use std::vec;
+
+use ser::Serialize;
+
+use super::{Error, Serializer, Token, TokenSink};
+
+/// Returns the number of tokens serializing `value` produces, without recording them.
+///
+/// ```rust
+/// use serde::token;
+///
+/// let value = vec![(1, "one"), (2, "two")];
+/// let len = token::count_tokens(&value).unwrap();
+/// assert_eq!(len, 16);
+/// assert_eq!(len, token::to_tokens(&value).unwrap().len());
+/// ```
+pub fn count_tokens<T: ?Sized>(value: &T) -> Result<usize, Error>
+    where T: Serialize,
+{
+    let mut ser = Serializer::with_sink(Counter(0));
+    try!(value.serialize(&mut ser));
+    Ok(ser.into_inner().0)
+}
+
+/// A `TokenSink` that drops the tokens and only counts them.
+struct Counter(usize);
+
+impl TokenSink for Counter {
+    fn push(&mut self, _token: Token<'static>) -> Result<(), Error> {
+        self.0 += 1;
+        Ok(())
+    }
+}
+
+/// Counts the tokens of `iter` on a clone of the iterator, and returns the count together with
+/// the original iterator, which hasn't been advanced.
+///
+/// This is cheap for iterators that borrow a recorded stream, like `tokens.iter().cloned()` and
+/// the standard adapters wrapping it, since counting doesn't keep any of the tokens. Cloning an
+/// iterator that owns its tokens, like `vec::IntoIter`, copies all of them, which is no cheaper
+/// than `counted_recorded`. That one also takes iterators that can't be cloned.
+///
+/// ```rust
+/// use serde::token::{self, Token};
+///
+/// let tokens = token::to_tokens(&vec![1, -2, 3]).unwrap();
+/// let positive = tokens.iter().cloned().filter(|token| *token != Token::I32(-2));
+/// assert_eq!(positive.size_hint(), (0, Some(8)));
+///
+/// let (len, positive) = token::counted(positive);
+/// assert_eq!(len, 7);
+/// assert_eq!(positive.len(), 7);
+/// ```
+pub fn counted<'a, I>(iter: I) -> (usize, Counted<I::IntoIter>)
+    where I: IntoIterator<Item=Token<'a>>,
+          I::IntoIter: Clone,
+{
+    let iter = iter.into_iter();
+    let len = iter.clone().count();
+    (len, Counted::new(iter, len))
+}
+
+/// Records the tokens of `iter` and returns their count together with an iterator over them,
+/// for iterators that can't be cloned.
+pub fn counted_recorded<'a, I>(iter: I) -> (usize, Counted<vec::IntoIter<Token<'a>>>)
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let tokens: Vec<_> = iter.into_iter().collect();
+    let len = tokens.len();
+    (len, Counted::new(tokens.into_iter(), len))
+}
+
+/// Iterator returned by `counted` and `counted_recorded`, which knows how many tokens are left.
+#[derive(Clone, Debug)]
+pub struct Counted<I> {
+    iter: I,
+    remaining: usize,
+}
+
+impl<I> Counted<I> {
+    fn new(iter: I, len: usize) -> Self {
+        Counted {
+            iter: iter,
+            remaining: len,
+        }
+    }
+
+    /// Unwraps the underlying iterator.
+    pub fn into_inner(self) -> I {
+        self.iter
+    }
+}
+
+impl<'a, I> Iterator for Counted<I>
+    where I: Iterator<Item=Token<'a>>,
+{
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let token = self.iter.next();
+        if token.is_some() {
+            self.remaining = self.remaining.saturating_sub(1);
+        }
+        token
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, I> ExactSizeIterator for Counted<I>
+    where I: Iterator<Item=Token<'a>>,
+{
+}
@@ -0,0 +1,175 @@
+//! Reject map keys that appear more than once in the same map.
+//!
+//! The readers keep every entry of their input, repeated keys included. `reject_dup_keys`
+//! rejects them in a token stream, and `DuplicateKeyPolicy` decides which entry wins when
+//! building a `Value` or deserializing a map or struct.
+
+use std::collections::BTreeMap;
+
+use super::{Error, Token};
+
+/// What to do with a key that appears more than once in the same map or struct.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DuplicateKeyPolicy {
+    /// Fail with an error naming the key and the index of its second occurrence in the stream.
+    Error,
+    /// Keep the value of the first occurrence.
+    FirstWins,
+    /// Keep the value of the last occurrence. This is the default.
+    LastWins,
+}
+
+impl Default for DuplicateKeyPolicy {
+    fn default() -> Self {
+        DuplicateKeyPolicy::LastWins
+    }
+}
+
+impl DuplicateKeyPolicy {
+    /// Inserts `value` under `key` unless the policy keeps an earlier value. `name` and
+    /// `position`, the index of the key token in the stream, describe the key in the error.
+    pub fn insert<K, V>(self,
+                        map: &mut BTreeMap<K, V>,
+                        key: K,
+                        value: V,
+                        name: &str,
+                        position: usize)
+                        -> Result<(), Error>
+        where K: Ord,
+    {
+        if map.contains_key(&key) && !try!(self.replace(name, position)) {
+            return Ok(());
+        }
+        map.insert(key, value);
+        Ok(())
+    }
+
+    /// Stores `value` into the slot of a struct field unless the policy keeps an earlier value.
+    pub fn set<T>(self, slot: &mut Option<T>, value: T, name: &str, position: usize)
+                  -> Result<(), Error>
+    {
+        if slot.is_some() && !try!(self.replace(name, position)) {
+            return Ok(());
+        }
+        *slot = Some(value);
+        Ok(())
+    }
+
+    /// Returns whether a repeated key replaces the value it already has.
+    fn replace(self, name: &str, position: usize) -> Result<bool, Error> {
+        match self {
+            DuplicateKeyPolicy::Error => {
+                Err(Error::InvalidValue(format!("duplicate key {:?} at token {}", name, position)))
+            }
+            DuplicateKeyPolicy::FirstWins => Ok(false),
+            DuplicateKeyPolicy::LastWins => Ok(true),
+        }
+    }
+}
+
+/// Returns an iterator that passes the tokens of `iter` through, and yields an error in place of
+/// the first key that repeats a key of the same map.
+///
+/// Every map has its own set of keys, so a nested map may reuse the keys of the map that
+/// contains it. Only keys made of a single scalar token are compared, keys that are compound
+/// values, options or newtypes are passed through unchecked. Keys compare the way tokens do,
+/// which means `Str("a")` and `String("a")` are the same key, but `I32(1)` and `I64(1)` are not.
+/// Iteration stops after the error.
+///
+/// ```rust
+/// use serde::token::{self, Token};
+///
+/// let tokens = vec![
+///     Token::MapStart(Some(2)),
+///     Token::MapSep, Token::Str("a"), Token::I32(1),
+///     Token::MapSep, Token::Str("a"), Token::I32(2),
+///     Token::MapEnd,
+/// ];
+/// let result: Result<Vec<_>, _> = token::reject_dup_keys(tokens).collect();
+/// assert!(result.is_err());
+/// ```
+pub fn reject_dup_keys<'a, I>(iter: I) -> RejectDupKeys<'a, I::IntoIter>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    RejectDupKeys {
+        iter: iter.into_iter(),
+        stack: Vec::new(),
+        key_next: false,
+        failed: false,
+    }
+}
+
+/// An open compound value.
+enum Frame<'a> {
+    /// A map, with the keys seen so far.
+    Map(Vec<Token<'a>>),
+    /// Any other compound value.
+    Other,
+}
+
+/// Iterator returned by `reject_dup_keys`.
+pub struct RejectDupKeys<'a, I> {
+    iter: I,
+    stack: Vec<Frame<'a>>,
+    /// Whether the next token starts a key of the innermost map.
+    key_next: bool,
+    failed: bool,
+}
+
+impl<'a, I> Iterator for RejectDupKeys<'a, I>
+    where I: Iterator<Item=Token<'a>>,
+{
+    type Item = Result<Token<'a>, Error>;
+
+    fn next(&mut self) -> Option<Result<Token<'a>, Error>> {
+        if self.failed {
+            return None;
+        }
+        let token = match self.iter.next() {
+            Some(token) => token,
+            None => return None,
+        };
+
+        let is_key = self.key_next;
+        self.key_next = false;
+
+        match token {
+            Token::MapStart(_) => self.stack.push(Frame::Map(Vec::new())),
+            Token::MapSep => {
+                self.key_next = match self.stack.last() {
+                    Some(&Frame::Map(_)) => true,
+                    _ => false,
+                };
+            }
+            ref token if token.is_start() => self.stack.push(Frame::Other),
+            ref token if token.is_end() => {
+                self.stack.pop();
+            }
+            Token::Option(true) |
+            Token::StructNewType(_) |
+            Token::TypeTag(_) |
+            Token::EnumNewType(..) => { }
+            ref token if is_key && !token.is_sep() => {
+                if let Some(&mut Frame::Map(ref mut keys)) = self.stack.last_mut() {
+                    if keys.contains(token) {
+                        self.failed = true;
+                        return Some(Err(Error::InvalidValue(format!("duplicate map key {:?}",
+                                                                    token))));
+                    }
+                    keys.push(token.clone());
+                }
+            }
+            _ => { }
+        }
+
+        Some(Ok(token))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.failed {
+            (0, Some(0))
+        } else {
+            self.iter.size_hint()
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Limit how deeply compound values nest in a token stream.
+//!
+//! The writers and most consumers of token streams recurse once per level of nesting, so a
+//! stream read from untrusted input can overflow the stack simply by opening enough sequences.
+//! `guard_depth` stops such a stream before it reaches them.
+
+use std::error;
+use std::fmt;
+
+use super::Token;
+
+/// The error yielded by `GuardedTokens` when the stream nests too deeply.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DepthError {
+    index: usize,
+    max_depth: usize,
+}
+
+impl DepthError {
+    /// Returns the index of the token that opened one compound value too many.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the limit that was exceeded.
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+}
+
+impl fmt::Display for DepthError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "token #{}: nesting exceeds the maximum depth of {}",
+               self.index, self.max_depth)
+    }
+}
+
+impl error::Error for DepthError {
+    fn description(&self) -> &str {
+        "token stream nests too deeply"
+    }
+}
+
+/// Returns an iterator that passes the tokens of `iter` through as long as no more than
+/// `max_depth` compound values are open at once, and yields an error in place of the token that
+/// opens one more. Iteration stops after the error.
+///
+/// Only tokens that open a compound value count towards the depth. A scalar at the top of the
+/// stream has a depth of 0, a sequence of scalars a depth of 1.
+///
+/// ```rust
+/// use serde::token::{self, Token};
+///
+/// let tokens = vec![
+///     Token::SeqStart(Some(1)),
+///     Token::SeqSep, Token::SeqStart(Some(0)), Token::SeqEnd,
+///     Token::SeqEnd,
+/// ];
+/// let result: Result<Vec<_>, _> = token::guard_depth(tokens, 1).collect();
+/// assert_eq!(result.unwrap_err().index(), 2);
+/// ```
+pub fn guard_depth<'a, I>(iter: I, max_depth: usize) -> GuardedTokens<I::IntoIter>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    GuardedTokens {
+        iter: iter.into_iter(),
+        max_depth: max_depth,
+        depth: 0,
+        index: 0,
+        failed: false,
+    }
+}
+
+/// Iterator returned by `guard_depth`.
+pub struct GuardedTokens<I> {
+    iter: I,
+    max_depth: usize,
+    /// The number of compound values that are open.
+    depth: usize,
+    /// The index of the next token.
+    index: usize,
+    failed: bool,
+}
+
+impl<'a, I> Iterator for GuardedTokens<I>
+    where I: Iterator<Item=Token<'a>>,
+{
+    type Item = Result<Token<'a>, DepthError>;
+
+    fn next(&mut self) -> Option<Result<Token<'a>, DepthError>> {
+        if self.failed {
+            return None;
+        }
+        let token = match self.iter.next() {
+            Some(token) => token,
+            None => return None,
+        };
+        let index = self.index;
+        self.index += 1;
+
+        if token.is_start() {
+            if self.depth == self.max_depth {
+                self.failed = true;
+                return Some(Err(DepthError {
+                    index: index,
+                    max_depth: self.max_depth,
+                }));
+            }
+            self.depth += 1;
+        } else if token.is_end() && self.depth > 0 {
+            self.depth -= 1;
+        }
+
+        Some(Ok(token))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.failed {
+            (0, Some(0))
+        } else {
+            let (_, upper) = self.iter.size_hint();
+            (0, upper)
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Compare two token streams scalar by scalar.
+
+use std::collections::BTreeMap;
+
+use super::{Error, Token};
+use super::flatten::to_path_index;
+
+/// How a scalar differs between the two streams given to `diff_tokens`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiffKind {
+    /// The path only exists in the new stream.
+    Added,
+    /// The path only exists in the old stream.
+    Removed,
+    /// The path exists in both streams with different values.
+    Changed,
+}
+
+/// A difference found by `diff_tokens`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diff<'a, 'b> {
+    /// The path of the scalar, as written by `to_path_index`.
+    pub path: String,
+    /// What changed.
+    pub kind: DiffKind,
+    /// The value in the old stream, unless it was added.
+    pub old: Option<Token<'a>>,
+    /// The value in the new stream, unless it was removed.
+    pub new: Option<Token<'b>>,
+}
+
+/// Lists the scalars that differ between the `old` and the `new` stream.
+///
+/// Both streams are indexed with `to_path_index`, so differences are reported per scalar and
+/// carry its path. Removed and changed paths come first, in the order of `old`, followed by the
+/// added paths in the order of `new`. Values are compared with `==`, so integers of different
+/// widths count as changed. Empty compound values have no path, so adding or removing one is not
+/// a difference.
+///
+/// ```rust
+/// use serde::token::{self, json, DiffKind, Token};
+///
+/// let old = json::from_str(r#"{"a": 1, "b": [true]}"#).unwrap();
+/// let new = json::from_str(r#"{"a": 2, "b": [true, false]}"#).unwrap();
+/// let diffs = token::diff_tokens(old, new).unwrap();
+/// assert_eq!(diffs.len(), 2);
+/// assert_eq!((&*diffs[0].path, diffs[0].kind), ("a", DiffKind::Changed));
+/// assert_eq!((&*diffs[1].path, diffs[1].kind), ("b[1]", DiffKind::Added));
+/// assert_eq!(diffs[1].new, Some(Token::Bool(false)));
+/// ```
+pub fn diff_tokens<'a, 'b, A, B>(old: A, new: B) -> Result<Vec<Diff<'a, 'b>>, Error>
+    where A: IntoIterator<Item=Token<'a>>,
+          B: IntoIterator<Item=Token<'b>>,
+{
+    let old = try!(to_path_index(old));
+    let new = try!(to_path_index(new));
+
+    let mut new_by_path = BTreeMap::new();
+    for (index, &(ref path, _)) in new.iter().enumerate() {
+        new_by_path.insert(path.clone(), index);
+    }
+    let mut seen = vec![false; new.len()];
+
+    let mut diffs = Vec::new();
+    for (path, old_value) in old {
+        match new_by_path.get(&path) {
+            Some(&index) => {
+                seen[index] = true;
+                if old_value != new[index].1 {
+                    diffs.push(Diff {
+                        path: path,
+                        kind: DiffKind::Changed,
+                        old: Some(old_value),
+                        new: Some(new[index].1.clone()),
+                    });
+                }
+            }
+            None => {
+                diffs.push(Diff {
+                    path: path,
+                    kind: DiffKind::Removed,
+                    old: Some(old_value),
+                    new: None,
+                });
+            }
+        }
+    }
+
+    for ((path, new_value), seen) in new.into_iter().zip(seen) {
+        if !seen {
+            diffs.push(Diff {
+                path: path,
+                kind: DiffKind::Added,
+                old: None,
+                new: Some(new_value),
+            });
+        }
+    }
+    Ok(diffs)
+}
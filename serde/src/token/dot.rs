@@ -0,0 +1,190 @@
+//! Render a token stream as a Graphviz digraph.
+
+use std::fmt::Write;
+
+use super::{Token, time};
+
+/// Renders the values in `iter` as a Graphviz `digraph`.
+///
+/// Every compound value becomes a node with an edge to each of its elements. Sequence edges are
+/// labeled with the element index, struct edges with the field name, and map entries get a `key`
+/// and a `value` edge. Scalars become leaf nodes labeled with their value.
+pub fn to_dot<'a, I>(iter: I) -> String
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let mut graph = Graph {
+        tokens: iter.into_iter(),
+        next_id: 0,
+        out: String::from("digraph tokens {\n"),
+    };
+
+    while let Some(token) = graph.tokens.next() {
+        graph.value(token);
+    }
+
+    graph.out.push_str("}\n");
+    graph.out
+}
+
+struct Graph<I> {
+    tokens: I,
+    next_id: usize,
+    out: String,
+}
+
+impl<'a, I> Graph<I>
+    where I: Iterator<Item=Token<'a>>,
+{
+    fn node(&mut self, label: &str) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        writeln!(self.out, "    n{} [label=\"{}\"];", id, escape(label)).unwrap();
+        id
+    }
+
+    fn edge(&mut self, from: usize, to: usize, label: &str) {
+        writeln!(self.out, "    n{} -> n{} [label=\"{}\"];", from, to, escape(label)).unwrap();
+    }
+
+    /// Emits the value starting with `token` and returns the id of its node.
+    fn value(&mut self, token: Token<'a>) -> usize {
+        match token {
+            Token::Option(true) => {
+                let id = self.node("Some");
+                self.child(id, "");
+                id
+            }
+            Token::StructNewType(name) | Token::TypeTag(name) => {
+                let id = self.node(name);
+                self.child(id, "");
+                id
+            }
+            Token::EnumNewType(name, variant) => {
+                let id = self.node(&format!("{}::{}", name, variant));
+                self.child(id, "");
+                id
+            }
+            Token::SeqStart(_) | Token::SeqArrayStart(_) => {
+                let id = self.node("seq");
+                self.elements(id);
+                id
+            }
+            Token::TupleStart(_) => {
+                let id = self.node("tuple");
+                self.elements(id);
+                id
+            }
+            Token::TupleStructStart(name, _) => {
+                let id = self.node(name);
+                self.elements(id);
+                id
+            }
+            Token::EnumSeqStart(name, variant, _) => {
+                let id = self.node(&format!("{}::{}", name, variant));
+                self.elements(id);
+                id
+            }
+            Token::MapStart(_) => {
+                let id = self.node("map");
+                self.entries(id);
+                id
+            }
+            Token::StructStart(name, _) => {
+                let id = self.node(name);
+                self.fields(id);
+                id
+            }
+            Token::EnumMapStart(name, variant, _) => {
+                let id = self.node(&format!("{}::{}", name, variant));
+                self.fields(id);
+                id
+            }
+            token => self.node(&label(&token)),
+        }
+    }
+
+    fn child(&mut self, parent: usize, label: &str) {
+        if let Some(token) = self.tokens.next() {
+            let id = self.value(token);
+            self.edge(parent, id, label);
+        }
+    }
+
+    fn elements(&mut self, parent: usize) {
+        let mut index = 0;
+        while let Some(token) = self.tokens.next() {
+            if token.is_end() {
+                break;
+            }
+            self.child(parent, &index.to_string());
+            index += 1;
+        }
+    }
+
+    fn entries(&mut self, parent: usize) {
+        while let Some(token) = self.tokens.next() {
+            if token.is_end() {
+                break;
+            }
+            self.child(parent, "key");
+            self.child(parent, "value");
+        }
+    }
+
+    fn fields(&mut self, parent: usize) {
+        while let Some(token) = self.tokens.next() {
+            if token.is_end() {
+                break;
+            }
+            let field = match self.tokens.next() {
+                Some(key) => key.as_str().map(String::from).unwrap_or_else(|| label(&key)),
+                None => break,
+            };
+            self.child(parent, &field);
+        }
+    }
+}
+
+/// Returns a human readable label for a scalar token.
+fn label(token: &Token) -> String {
+    match *token {
+        Token::Bool(v) => v.to_string(),
+        Token::Isize(v) => v.to_string(),
+        Token::I8(v) => v.to_string(),
+        Token::I16(v) => v.to_string(),
+        Token::I32(v) => v.to_string(),
+        Token::I64(v) => v.to_string(),
+        Token::Usize(v) => v.to_string(),
+        Token::U8(v) => v.to_string(),
+        Token::U16(v) => v.to_string(),
+        Token::U32(v) => v.to_string(),
+        Token::U64(v) => v.to_string(),
+        Token::F32(v) => v.to_string(),
+        Token::F64(v) => v.to_string(),
+        Token::Char(v) => format!("{:?}", v),
+        Token::Str(v) => format!("{:?}", v),
+        Token::String(ref v) => format!("{:?}", v),
+        Token::Bytes(v) => format!("{:?}", v),
+        Token::ByteBuf(ref v) => format!("{:?}", v),
+        Token::Timestamp(secs, nanos) => time::format_rfc3339(secs, nanos),
+        Token::BigNum(ref v) => v.clone(),
+        Token::Option(false) => String::from("None"),
+        Token::Unit => String::from("()"),
+        Token::UnitStruct(name) => String::from(name),
+        Token::EnumUnit(name, variant) => format!("{}::{}", name, variant),
+        ref token => format!("{:?}", token),
+    }
+}
+
+fn escape(label: &str) -> String {
+    let mut escaped = String::with_capacity(label.len());
+    for c in label.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,240 @@
+//! Textual encodings of byte strings.
+//!
+//! Text formats have no byte strings of their own, so the writers encode `Bytes` and `ByteBuf`
+//! tokens as strings and the deserializers decode such strings back into bytes when the target
+//! type asks for bytes. Decoding skips ASCII whitespace anywhere in the input, so encoded blobs
+//! may be wrapped over several lines, and accepts a missing padding.
+
+use std::error;
+use std::fmt;
+
+/// How byte strings are written as text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BytesEncoding {
+    /// Standard base64 with `+` and `/`, padded with `=` to a multiple of 4 characters.
+    Base64,
+    /// Standard base64 without padding.
+    Base64NoPad,
+    /// URL and file name safe base64 with `-` and `_`, padded with `=`.
+    Base64Url,
+    /// URL and file name safe base64 without padding.
+    Base64UrlNoPad,
+    /// Base32 with the upper case RFC 4648 alphabet, padded with `=` to a multiple of 8
+    /// characters. Decoding accepts lower case too.
+    Base32,
+    /// Two lower case hex digits per byte. Decoding accepts upper case too.
+    Hex,
+}
+
+impl Default for BytesEncoding {
+    fn default() -> Self {
+        BytesEncoding::Base64
+    }
+}
+
+const BASE64: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const BASE64_URL: &'static [u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+const BASE32: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+const HEX: &'static [u8] = b"0123456789abcdef";
+
+impl BytesEncoding {
+    /// Returns the alphabet, the number of bits per character and the number of characters
+    /// padding rounds up to, if the encoding pads.
+    fn params(self) -> (&'static [u8], u32, Option<usize>) {
+        match self {
+            BytesEncoding::Base64 => (BASE64, 6, Some(4)),
+            BytesEncoding::Base64NoPad => (BASE64, 6, None),
+            BytesEncoding::Base64Url => (BASE64_URL, 6, Some(4)),
+            BytesEncoding::Base64UrlNoPad => (BASE64_URL, 6, None),
+            BytesEncoding::Base32 => (BASE32, 5, Some(8)),
+            BytesEncoding::Hex => (HEX, 4, None),
+        }
+    }
+
+    /// Returns the number of characters padding rounds up to when decoding, which accepts a
+    /// padding with the variants that don't write one too.
+    fn group(self) -> Option<usize> {
+        match self {
+            BytesEncoding::Hex => None,
+            BytesEncoding::Base32 => Some(8),
+            _ => Some(4),
+        }
+    }
+
+    /// Returns the value of the character `c`, or `None` if it isn't part of the alphabet.
+    fn value_of(self, c: u8) -> Option<u32> {
+        let (alphabet, _, _) = self.params();
+        let position = match self {
+            BytesEncoding::Base32 | BytesEncoding::Hex => {
+                alphabet.iter().position(|&a| to_ascii_lower(a) == to_ascii_lower(c))
+            }
+            _ => alphabet.iter().position(|&a| a == c),
+        };
+        position.map(|v| v as u32)
+    }
+}
+
+fn to_ascii_lower(c: u8) -> u8 {
+    match c {
+        b'A'...b'Z' => c + (b'a' - b'A'),
+        _ => c,
+    }
+}
+
+/// Encodes `bytes` as text.
+///
+/// ```rust
+/// use serde::token::encoding::{self, BytesEncoding};
+///
+/// assert_eq!(encoding::encode(b"hi?", BytesEncoding::Base64), "aGk/");
+/// assert_eq!(encoding::encode(b"hi", BytesEncoding::Base64), "aGk=");
+/// assert_eq!(encoding::encode(b"hi", BytesEncoding::Base64UrlNoPad), "aGk");
+/// assert_eq!(encoding::encode(b"hi", BytesEncoding::Base32), "NBUQ====");
+/// assert_eq!(encoding::encode(b"hi", BytesEncoding::Hex), "6869");
+/// ```
+pub fn encode(bytes: &[u8], encoding: BytesEncoding) -> String {
+    let (alphabet, bits, padding) = encoding.params();
+    let mask = (1 << bits) - 1;
+    let mut out = String::with_capacity((bytes.len() * 8 + bits as usize - 1) / bits as usize);
+
+    // The bits read but not written yet, of which there are fewer than `bits` between bytes.
+    let mut acc: u32 = 0;
+    let mut pending = 0;
+    for &byte in bytes {
+        acc = (acc << 8) | byte as u32;
+        pending += 8;
+        while pending >= bits {
+            pending -= bits;
+            out.push(alphabet[((acc >> pending) & mask) as usize] as char);
+        }
+        acc &= (1 << pending) - 1;
+    }
+    if pending > 0 {
+        out.push(alphabet[((acc << (bits - pending)) & mask) as usize] as char);
+    }
+
+    if let Some(group) = padding {
+        while out.len() % group != 0 {
+            out.push('=');
+        }
+    }
+    out
+}
+
+/// Decodes the text `text` into bytes.
+///
+/// ```rust
+/// use serde::token::encoding::{self, BytesEncoding};
+///
+/// assert_eq!(encoding::decode("aGk=", BytesEncoding::Base64).unwrap(), b"hi");
+/// assert_eq!(encoding::decode("aG\n k", BytesEncoding::Base64).unwrap(), b"hi");
+///
+/// let err = encoding::decode("aG*k", BytesEncoding::Base64).unwrap_err();
+/// assert_eq!(err.offset(), 2);
+/// assert_eq!(err.to_string(), "invalid character '*' at offset 2");
+/// ```
+pub fn decode(text: &str, encoding: BytesEncoding) -> Result<Vec<u8>, DecodeError> {
+    let (_, bits, _) = encoding.params();
+    let padding = encoding.group();
+    let mut out = Vec::with_capacity(text.len() * bits as usize / 8);
+
+    let mut acc: u32 = 0;
+    let mut pending = 0;
+    let mut digits = 0;
+    // The offset of the first `=` and the number of them.
+    let mut pad_start = None;
+    let mut pads = 0;
+
+    for (offset, c) in text.char_indices() {
+        match c {
+            ' ' | '\t' | '\n' | '\x0c' | '\r' => continue,
+            _ => {}
+        }
+        if c == '=' && padding.is_some() {
+            if pad_start.is_none() {
+                pad_start = Some(offset);
+            }
+            pads += 1;
+            continue;
+        }
+
+        let value = if (c as u32) < 0x80 { encoding.value_of(c as u8) } else { None };
+        let value = match value {
+            Some(value) if pad_start.is_none() => value,
+            Some(_) => return Err(DecodeError::new(offset, Reason::AfterPadding(c))),
+            None => return Err(DecodeError::new(offset, Reason::InvalidChar(c))),
+        };
+        acc = (acc << bits) | value;
+        pending += bits;
+        digits += 1;
+        if pending >= 8 {
+            pending -= 8;
+            out.push((acc >> pending) as u8);
+            acc &= (1 << pending) - 1;
+        }
+    }
+
+    // A character whose bits all fall short of a byte can't end a valid encoding.
+    if pending >= bits {
+        return Err(DecodeError::new(pad_start.unwrap_or(text.len()), Reason::Truncated));
+    }
+    if let (Some(offset), Some(group)) = (pad_start, padding) {
+        if (digits + pads) % group != 0 || pads >= group {
+            return Err(DecodeError::new(offset, Reason::Padding));
+        }
+    }
+    Ok(out)
+}
+
+/// The error returned by `decode` for text that isn't a valid encoding.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecodeError {
+    offset: usize,
+    reason: Reason,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Reason {
+    InvalidChar(char),
+    AfterPadding(char),
+    Padding,
+    Truncated,
+}
+
+impl DecodeError {
+    fn new(offset: usize, reason: Reason) -> Self {
+        DecodeError {
+            offset: offset,
+            reason: reason,
+        }
+    }
+
+    /// Returns the byte offset in the text where decoding failed.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self.reason {
+            Reason::InvalidChar(c) => {
+                write!(formatter, "invalid character {:?} at offset {}", c, self.offset)
+            }
+            Reason::AfterPadding(c) => {
+                write!(formatter, "character {:?} after padding at offset {}", c, self.offset)
+            }
+            Reason::Padding => write!(formatter, "invalid padding at offset {}", self.offset),
+            Reason::Truncated => {
+                write!(formatter, "incomplete final character at offset {}", self.offset)
+            }
+        }
+    }
+}
+
+impl error::Error for DecodeError {
+    fn description(&self) -> &str {
+        "invalid encoded bytes"
+    }
+}
@@ -0,0 +1,337 @@
+//! Serialize values behind trait objects.
+//!
+//! `Serialize::serialize` is generic over the serializer, so `Serialize` can't be made into a
+//! trait object and `Vec<Box<Serialize>>` isn't a type. `ErasedSerialize` records the value into
+//! tokens instead, which needs no type parameter. It is implemented for every `Serialize` type,
+//! and its trait objects implement `Serialize` by replaying the recorded tokens into the
+//! serializer:
+//!
+//! ```rust
+//! use serde::token::json;
+//! use serde::token::erased::ErasedSerialize;
+//!
+//! let values: Vec<Box<ErasedSerialize>> = vec![Box::new(1), Box::new("two"), Box::new(vec![3])];
+//! assert_eq!(json::serialize(&values).unwrap(), r#"[1,"two",[3]]"#);
+//! ```
+//!
+//! Tokens don't record the index of enum variants, so variants are replayed with index 0.
+
+use std::any;
+
+use ser::{self, Serialize, Serializer};
+
+use super::{Error, Token, to_tokens};
+
+/// A value that can be serialized through a trait object.
+pub trait ErasedSerialize {
+    /// Serializes the value into tokens.
+    fn erased_tokens(&self) -> Result<Vec<Token<'static>>, Error>;
+
+    /// Returns the name of the type of the value, without its module path.
+    fn erased_type_name(&self) -> &'static str;
+}
+
+impl<T> ErasedSerialize for T
+    where T: Serialize,
+{
+    fn erased_tokens(&self) -> Result<Vec<Token<'static>>, Error> {
+        to_tokens(self)
+    }
+
+    fn erased_type_name(&self) -> &'static str {
+        let name = any::type_name::<T>();
+        let path_end = name.find('<').unwrap_or(name.len());
+        match name[..path_end].rfind("::") {
+            Some(separator) => &name[separator + 2..],
+            None => name,
+        }
+    }
+}
+
+impl<'a> Serialize for ErasedSerialize + 'a {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer,
+    {
+        let tokens = try!(self.erased_tokens().map_err(|err| ser::Error::custom(err.to_string())));
+        Replay(&tokens).serialize(serializer)
+    }
+}
+
+/// Returns a wrapper that serializes `value` as a newtype variant of an enum called
+/// `ErasedSerialize`, with the name of the type of `value` as the variant. The stream then
+/// records which type produced the value.
+///
+/// ```rust
+/// use serde::token::json;
+/// use serde::token::erased::{self, ErasedSerialize};
+///
+/// let values: Vec<Box<ErasedSerialize>> = vec![Box::new(1u8), Box::new("two")];
+/// let tagged: Vec<_> = values.iter().map(|value| erased::with_type_name(&**value)).collect();
+/// assert_eq!(json::serialize(&tagged).unwrap(), r#"[{"u8":1},{"&str":"two"}]"#);
+/// ```
+pub fn with_type_name(value: &ErasedSerialize) -> TypeNamed {
+    TypeNamed {
+        value: value,
+    }
+}
+
+/// Wrapper returned by `with_type_name`.
+pub struct TypeNamed<'a> {
+    value: &'a ErasedSerialize,
+}
+
+impl<'a> Serialize for TypeNamed<'a> {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer,
+    {
+        serializer.serialize_newtype_variant("ErasedSerialize",
+                                             0,
+                                             self.value.erased_type_name(),
+                                             self.value)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// The tokens of a single value, serialized by making the calls that recorded them.
+#[derive(Clone, Copy)]
+struct Replay<'t>(&'t [Token<'static>]);
+
+impl<'t> Serialize for Replay<'t> {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer,
+    {
+        let (first, rest) = match self.0.split_first() {
+            Some(split) => split,
+            None => return Err(ser::Error::custom("unexpected end of tokens")),
+        };
+        let elements = |kind| {
+            Elements {
+                tokens: rest,
+                kind: kind,
+            }
+        };
+
+        match *first {
+            Token::Bool(v) => serializer.serialize_bool(v),
+            Token::Isize(v) => serializer.serialize_isize(v),
+            Token::I8(v) => serializer.serialize_i8(v),
+            Token::I16(v) => serializer.serialize_i16(v),
+            Token::I32(v) => serializer.serialize_i32(v),
+            Token::I64(v) => serializer.serialize_i64(v),
+            Token::Usize(v) => serializer.serialize_usize(v),
+            Token::U8(v) => serializer.serialize_u8(v),
+            Token::U16(v) => serializer.serialize_u16(v),
+            Token::U32(v) => serializer.serialize_u32(v),
+            Token::U64(v) => serializer.serialize_u64(v),
+            Token::F32(v) => serializer.serialize_f32(v),
+            Token::F64(v) => serializer.serialize_f64(v),
+            Token::Char(v) => serializer.serialize_char(v),
+            Token::Str(v) => serializer.serialize_str(v),
+            Token::String(ref v) | Token::BigNum(ref v) => serializer.serialize_str(v),
+            Token::Bytes(v) => serializer.serialize_bytes(v),
+            Token::ByteBuf(ref v) => serializer.serialize_bytes(v),
+            Token::Timestamp(secs, nanos) => serializer.serialize_timestamp(secs, nanos),
+            Token::Option(false) => serializer.serialize_none(),
+            Token::Option(true) => serializer.serialize_some(Replay(rest)),
+            Token::Unit => serializer.serialize_unit(),
+            Token::UnitStruct(name) => serializer.serialize_unit_struct(name),
+            Token::StructNewType(name) => serializer.serialize_newtype_struct(name, Replay(rest)),
+            Token::TypeTag(name) => {
+                try!(serializer.serialize_type_tag(name));
+                Replay(rest).serialize(serializer)
+            }
+            Token::EnumUnit(name, variant) => serializer.serialize_unit_variant(name, 0, variant),
+            Token::EnumNewType(name, variant) => {
+                serializer.serialize_newtype_variant(name, 0, variant, Replay(rest))
+            }
+            Token::SeqStart(len) => {
+                serializer.serialize_seq(SeqReplay { elements: elements(Kind::Seq), len: len })
+            }
+            Token::SeqArrayStart(len) => {
+                serializer.serialize_fixed_size_array(SeqReplay {
+                    elements: elements(Kind::Seq),
+                    len: Some(len),
+                })
+            }
+            Token::TupleStart(len) => {
+                serializer.serialize_tuple(SeqReplay {
+                    elements: elements(Kind::Tuple),
+                    len: Some(len),
+                })
+            }
+            Token::TupleStructStart(name, len) => {
+                serializer.serialize_tuple_struct(name, SeqReplay {
+                    elements: elements(Kind::TupleStruct),
+                    len: len,
+                })
+            }
+            Token::EnumSeqStart(name, variant, len) => {
+                serializer.serialize_tuple_variant(name, 0, variant, SeqReplay {
+                    elements: elements(Kind::TupleVariant),
+                    len: len,
+                })
+            }
+            Token::MapStart(len) => {
+                serializer.serialize_map(MapReplay { elements: elements(Kind::Map), len: len })
+            }
+            Token::StructStart(name, len) => {
+                serializer.serialize_struct(name, MapReplay {
+                    elements: elements(Kind::Struct),
+                    len: len,
+                })
+            }
+            Token::EnumMapStart(name, variant, len) => {
+                serializer.serialize_struct_variant(name, 0, variant, MapReplay {
+                    elements: elements(Kind::StructVariant),
+                    len: len,
+                })
+            }
+            ref token => Err(ser::Error::custom(format!("unexpected token {:?}", token))),
+        }
+    }
+}
+
+/// The kinds of compound values, which differ in the calls that serialize their elements.
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Seq,
+    Tuple,
+    TupleStruct,
+    TupleVariant,
+    Map,
+    Struct,
+    StructVariant,
+}
+
+/// The tokens of a compound value after its start token.
+struct Elements<'t> {
+    tokens: &'t [Token<'static>],
+    kind: Kind,
+}
+
+impl<'t> Elements<'t> {
+    /// Returns whether another element follows, consuming its separator, or `false` after
+    /// consuming the end token.
+    fn next<E>(&mut self) -> Result<bool, E>
+        where E: ser::Error,
+    {
+        match self.tokens.split_first() {
+            Some((token, rest)) if token.is_sep() || token.is_end() => {
+                self.tokens = rest;
+                Ok(token.is_sep())
+            }
+            Some((token, _)) => Err(ser::Error::custom(format!("unexpected token {:?}", token))),
+            None => Err(ser::Error::custom("unexpected end of tokens")),
+        }
+    }
+
+    /// Returns the next value and moves past it.
+    fn value<E>(&mut self) -> Result<Replay<'t>, E>
+        where E: ser::Error,
+    {
+        match value_len(self.tokens) {
+            Some(len) => {
+                let value = Replay(&self.tokens[..len]);
+                self.tokens = &self.tokens[len..];
+                Ok(value)
+            }
+            None => Err(ser::Error::custom("unexpected end of tokens")),
+        }
+    }
+}
+
+struct SeqReplay<'t> {
+    elements: Elements<'t>,
+    len: Option<usize>,
+}
+
+impl<'t> ser::SeqVisitor for SeqReplay<'t> {
+    fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+        where S: Serializer,
+    {
+        if !try!(self.elements.next()) {
+            return Ok(None);
+        }
+        let value = try!(self.elements.value());
+        try!(match self.elements.kind {
+            Kind::Tuple => serializer.serialize_tuple_elt(value),
+            Kind::TupleStruct => serializer.serialize_tuple_struct_elt(value),
+            Kind::TupleVariant => serializer.serialize_tuple_variant_elt(value),
+            _ => serializer.serialize_seq_elt(value),
+        });
+        Ok(Some(()))
+    }
+
+    fn len(&self) -> Option<usize> {
+        self.len
+    }
+}
+
+struct MapReplay<'t> {
+    elements: Elements<'t>,
+    len: Option<usize>,
+}
+
+impl<'t> ser::MapVisitor for MapReplay<'t> {
+    fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+        where S: Serializer,
+    {
+        if !try!(self.elements.next()) {
+            return Ok(None);
+        }
+        let key = try!(self.elements.value());
+        let value = try!(self.elements.value());
+        if self.elements.kind == Kind::Map {
+            try!(serializer.serialize_map_elt(key, value));
+            return Ok(Some(()));
+        }
+
+        let field = match key.0.first() {
+            Some(&Token::Str(field)) if key.0.len() == 1 => field,
+            _ => {
+                return Err(ser::Error::custom(format!("expected a field name, found {:?}", key.0)));
+            }
+        };
+        try!(if self.elements.kind == Kind::Struct {
+            serializer.serialize_struct_elt(field, value)
+        } else {
+            serializer.serialize_struct_variant_elt(field, value)
+        });
+        Ok(Some(()))
+    }
+
+    fn len(&self) -> Option<usize> {
+        self.len
+    }
+}
+
+/// Returns the number of tokens of the value at the start of `tokens`.
+fn value_len(tokens: &[Token]) -> Option<usize> {
+    let mut depth = 0;
+    for (index, token) in tokens.iter().enumerate() {
+        if token.is_start() {
+            depth += 1;
+            continue;
+        } else if token.is_end() {
+            if depth == 0 {
+                return None;
+            }
+            depth -= 1;
+        } else if token.is_sep() {
+            continue;
+        } else {
+            match *token {
+                Token::Option(true) |
+                Token::StructNewType(_) |
+                Token::TypeTag(_) |
+                Token::EnumNewType(_, _) => continue,
+                _ => { }
+            }
+        }
+        if depth == 0 {
+            return Some(index + 1);
+        }
+    }
+    None
+}
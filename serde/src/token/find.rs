@@ -0,0 +1,110 @@
+//! Look up a field without reading the rest of a value.
+
+use super::{Error, Token};
+
+/// Returns the value of the field `name` of the struct or map in `iter`, which has to be a
+/// scalar, or `None` if there is no such field.
+///
+/// Only the top level of the value is walked: the values of other fields are skipped with
+/// `skip_value` without being looked at, and the tokens after the field are not read at all.
+/// This makes it a cheap filter for records that are only deserialized when they match. A value
+/// wrapped in `Some` or a newtype struct is unwrapped.
+///
+/// ```rust
+/// use serde::token::{self, json, Token};
+///
+/// let tokens = json::from_str(r#"{"payload": [1, {"type": "x"}], "type": "b"}"#).unwrap();
+/// assert_eq!(token::find_field(tokens, "type").unwrap(), Some(Token::Str("b")));
+/// ```
+pub fn find_field<'a, I>(iter: I, name: &str) -> Result<Option<Token<'a>>, Error>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let mut tokens = iter.into_iter();
+    match try!(next(&mut tokens)) {
+        Token::StructStart(_, _) | Token::MapStart(_) => { }
+        token => {
+            return Err(Error::InvalidValue(format!("expected a struct or map, found {:?}",
+                                                   token)));
+        }
+    }
+
+    loop {
+        match try!(next(&mut tokens)) {
+            Token::StructSep | Token::MapSep => { }
+            Token::StructEnd | Token::MapEnd => return Ok(None),
+            token => return Err(unexpected(&token)),
+        }
+
+        let key = try!(next(&mut tokens));
+        let found = key.as_str() == Some(name);
+        if key.is_start() {
+            try!(skip_compound(&mut tokens));
+        }
+        if !found {
+            try!(skip_value(&mut tokens));
+            continue;
+        }
+
+        let mut value = try!(next(&mut tokens));
+        loop {
+            match value {
+                Token::Option(true) | Token::StructNewType(_) | Token::TypeTag(_) => {
+                    value = try!(next(&mut tokens))
+                }
+                _ => break,
+            }
+        }
+        if value.is_start() || value.is_sep() || value.is_end() {
+            return Err(Error::InvalidValue(format!("field {:?} is not a scalar, found {:?}",
+                                                   name, value)));
+        }
+        return Ok(Some(value));
+    }
+}
+
+/// Consumes the tokens of the next value in `tokens` without looking at them, counting only
+/// start and end tokens to find where the value ends.
+pub fn skip_value<'a, I>(tokens: &mut I) -> Result<(), Error>
+    where I: Iterator<Item=Token<'a>>,
+{
+    loop {
+        let token = try!(next(tokens));
+        match token {
+            Token::Option(true) |
+            Token::StructNewType(_) |
+            Token::TypeTag(_) |
+            Token::EnumNewType(_, _) => { }
+            // The variant, followed by the payload.
+            Token::EnumStart(_) => try!(skip_value(tokens)),
+            ref token if token.is_start() => return skip_compound(tokens),
+            ref token if token.is_sep() || token.is_end() => return Err(unexpected(token)),
+            _ => return Ok(()),
+        }
+    }
+}
+
+/// Consumes the rest of a compound value whose start token was just read.
+fn skip_compound<'a, I>(tokens: &mut I) -> Result<(), Error>
+    where I: Iterator<Item=Token<'a>>,
+{
+    let mut depth = 1;
+    while depth > 0 {
+        let token = try!(next(tokens));
+        if token.is_start() {
+            depth += 1;
+        } else if token.is_end() {
+            depth -= 1;
+        }
+    }
+    Ok(())
+}
+
+fn next<'a, I>(tokens: &mut I) -> Result<Token<'a>, Error>
+    where I: Iterator<Item=Token<'a>>,
+{
+    tokens.next().ok_or_else(|| Error::InvalidValue("unexpected end of stream".to_owned()))
+}
+
+fn unexpected(token: &Token) -> Error {
+    Error::InvalidValue(format!("unexpected token {:?}", token))
+}
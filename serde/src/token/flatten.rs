@@ -0,0 +1,527 @@
+//! Flatten nested values into a single map with joined keys, or into a list of paths, and
+//! redact values by their path.
+
+use std::fmt::Write;
+use std::iter::{self, Peekable};
+
+use super::{Error, RecountingBuffer, Token};
+use super::find::skip_value;
+
+/// Flattens the map or struct in `iter` into a single map, e.g. `{"a": {"b": 1}}` into
+/// `{"a.b": 1}` when `sep` is `"."`.
+///
+/// The keys of nested maps and the fields of nested structs are appended to the key of the value
+/// that contains them, joined with `sep`. Map keys have to be strings, characters, booleans,
+/// integers or unit variants. Sequences, tuples, enum variants and scalars are kept as values,
+/// and so are empty maps and structs, which would otherwise disappear. `Some` and newtype
+/// structs around a nested map or struct are dropped.
+///
+/// The keys of the result are owned `String` tokens, the values are the tokens of the input.
+///
+/// ```rust
+/// use serde::token::{self, json};
+///
+/// let tokens = json::from_str(r#"{"db": {"host": "localhost", "port": 5432}}"#).unwrap();
+/// let flat = token::flatten_keys(tokens, ".").unwrap();
+/// assert_eq!(token::to_json(flat).unwrap(), r#"{"db.host":"localhost","db.port":5432}"#);
+/// ```
+pub fn flatten_keys<'a, I>(iter: I, sep: &str) -> Result<Vec<Token<'a>>, Error>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let mut flatten = Flatten {
+        tokens: iter.into_iter().peekable(),
+        sep: sep,
+        out: RecountingBuffer::new(Token::MapStart(None)),
+    };
+
+    match try!(flatten.next()) {
+        Token::MapStart(_) | Token::StructStart(_, _) => try!(flatten.entries("")),
+        token => {
+            return Err(Error::InvalidValue(format!("expected a map or struct, found {:?}",
+                                                   token)));
+        }
+    }
+    if let Some(token) = flatten.tokens.next() {
+        return Err(unexpected(&token));
+    }
+
+    flatten.out.push(Token::MapEnd);
+    Ok(flatten.out.finish())
+}
+
+struct Flatten<'a, 's, I>
+    where I: Iterator<Item=Token<'a>>,
+{
+    tokens: Peekable<I>,
+    sep: &'s str,
+    out: RecountingBuffer<'a>,
+}
+
+impl<'a, 's, I> Flatten<'a, 's, I>
+    where I: Iterator<Item=Token<'a>>,
+{
+    fn next(&mut self) -> Result<Token<'a>, Error> {
+        self.tokens.next().ok_or_else(|| {
+            Error::InvalidValue("unexpected end of stream".to_owned())
+        })
+    }
+
+    /// Flattens the entries of the map or struct whose start token was just read, with `prefix`
+    /// being the joined key of the map or struct itself.
+    fn entries(&mut self, prefix: &str) -> Result<(), Error> {
+        loop {
+            let key = match try!(self.next()) {
+                Token::MapSep => {
+                    let key = try!(self.next());
+                    try!(key_string(&key))
+                }
+                Token::StructSep => {
+                    let key = try!(self.next());
+                    match key.as_str() {
+                        Some(key) => key.to_owned(),
+                        None => return Err(unexpected(&key)),
+                    }
+                }
+                Token::MapEnd | Token::StructEnd => return Ok(()),
+                token => return Err(unexpected(&token)),
+            };
+
+            let key = if prefix.is_empty() {
+                key
+            } else {
+                format!("{}{}{}", prefix, self.sep, key)
+            };
+            try!(self.value(key));
+        }
+    }
+
+    /// Flattens the value of the entry with the joined key `key`.
+    fn value(&mut self, key: String) -> Result<(), Error> {
+        let mut wrappers = Vec::new();
+        loop {
+            match self.tokens.peek() {
+                Some(&Token::Option(true)) |
+                Some(&Token::StructNewType(_)) |
+                Some(&Token::TypeTag(_)) => { }
+                _ => break,
+            }
+            wrappers.push(try!(self.next()));
+        }
+
+        let first = try!(self.next());
+        match first {
+            Token::MapStart(_) | Token::StructStart(_, _) => {
+                let len = self.out.len();
+                try!(self.entries(&key));
+                if self.out.len() == len {
+                    // Keep the empty map or struct, with the wrappers it had.
+                    let end = if let Token::MapStart(_) = first {
+                        Token::MapEnd
+                    } else {
+                        Token::StructEnd
+                    };
+                    self.entry(key);
+                    self.out.extend(wrappers);
+                    self.out.push(first);
+                    self.out.push(end);
+                }
+                Ok(())
+            }
+            first => {
+                self.entry(key);
+                self.out.extend(wrappers);
+                self.copy(first)
+            }
+        }
+    }
+
+    fn entry(&mut self, key: String) {
+        self.out.push(Token::MapSep);
+        self.out.push(Token::String(key));
+    }
+
+    /// Copies the value starting with `first` to the output unchanged.
+    fn copy(&mut self, first: Token<'a>) -> Result<(), Error> {
+        let mut depth = 0;
+        let mut token = first;
+        loop {
+            if token.is_start() {
+                depth += 1;
+            } else if token.is_end() {
+                depth -= 1;
+            }
+            let wraps_value = match token {
+                Token::Option(true) |
+                Token::StructNewType(_) |
+                Token::TypeTag(_) |
+                Token::EnumNewType(_, _) => true,
+                _ => false,
+            };
+            self.out.push(token);
+
+            if depth == 0 && !wraps_value {
+                return Ok(());
+            }
+            token = try!(self.next());
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Lists every scalar in `iter` together with its path, in the order of the stream.
+///
+/// Map keys and struct fields are appended as `.key`, or as `["key"]` when the key isn't made of
+/// ASCII letters, digits and underscores or starts with a digit. Elements of sequences, tuples
+/// and tuple structs are appended as `[index]`. A variant of an enum is appended like a key,
+/// followed by the path into its payload. `Some` and newtype structs don't appear in paths. A
+/// scalar at the top of the stream has the empty path, and empty compound values don't appear
+/// at all.
+///
+/// ```rust
+/// use serde::token::{self, json, Token};
+///
+/// let tokens = json::from_str(r#"{"a": {"b": [1, true]}, "c d": null}"#).unwrap();
+/// assert_eq!(token::to_path_index(tokens).unwrap(), vec![
+///     ("a.b[0]".to_owned(), Token::I64(1)),
+///     ("a.b[1]".to_owned(), Token::Bool(true)),
+///     ("[\"c d\"]".to_owned(), Token::Unit),
+/// ]);
+/// ```
+pub fn to_path_index<'a, I>(iter: I) -> Result<Vec<(String, Token<'a>)>, Error>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let mut index = PathIndex {
+        tokens: iter.into_iter(),
+        path: String::new(),
+        out: Vec::new(),
+    };
+
+    let first = try!(index.next());
+    try!(index.value(first));
+    if let Some(token) = index.tokens.next() {
+        return Err(unexpected(&token));
+    }
+    Ok(index.out)
+}
+
+struct PathIndex<'a, I> {
+    tokens: I,
+    /// The path of the value being read.
+    path: String,
+    out: Vec<(String, Token<'a>)>,
+}
+
+impl<'a, I> PathIndex<'a, I>
+    where I: Iterator<Item=Token<'a>>,
+{
+    fn next(&mut self) -> Result<Token<'a>, Error> {
+        self.tokens.next().ok_or_else(|| {
+            Error::InvalidValue("unexpected end of stream".to_owned())
+        })
+    }
+
+    /// Indexes the value starting with `token`, which has the current path.
+    fn value(&mut self, token: Token<'a>) -> Result<(), Error> {
+        match token {
+            Token::Option(true) | Token::StructNewType(_) | Token::TypeTag(_) => {
+                let token = try!(self.next());
+                self.value(token)
+            }
+            Token::EnumNewType(_, variant) => {
+                let len = self.push_key(variant);
+                let token = try!(self.next());
+                try!(self.value(token));
+                self.path.truncate(len);
+                Ok(())
+            }
+            Token::EnumStart(_) => {
+                let variant = try!(self.next());
+                let len = self.push_key(&try!(key_string(&variant)));
+                let token = try!(self.next());
+                try!(self.value(token));
+                self.path.truncate(len);
+                Ok(())
+            }
+            Token::SeqStart(_) | Token::SeqArrayStart(_) => {
+                self.elements(Token::SeqSep, Token::SeqEnd)
+            }
+            Token::TupleStart(_) => self.elements(Token::TupleSep, Token::TupleEnd),
+            Token::TupleStructStart(_, _) => {
+                self.elements(Token::TupleStructSep, Token::TupleStructEnd)
+            }
+            Token::EnumSeqStart(_, variant, _) => {
+                let len = self.push_key(variant);
+                try!(self.elements(Token::EnumSeqSep, Token::EnumSeqEnd));
+                self.path.truncate(len);
+                Ok(())
+            }
+            Token::MapStart(_) => self.entries(Token::MapSep, Token::MapEnd, true),
+            Token::StructStart(_, _) => self.entries(Token::StructSep, Token::StructEnd, false),
+            Token::EnumMapStart(_, variant, _) => {
+                let len = self.push_key(variant);
+                try!(self.entries(Token::EnumMapSep, Token::EnumMapEnd, false));
+                self.path.truncate(len);
+                Ok(())
+            }
+            token => {
+                if token.is_sep() || token.is_end() {
+                    return Err(unexpected(&token));
+                }
+                self.out.push((self.path.clone(), token));
+                Ok(())
+            }
+        }
+    }
+
+    /// Indexes the elements of the sequence whose start token was just read.
+    fn elements(&mut self, sep: Token<'static>, end: Token<'static>) -> Result<(), Error> {
+        let mut index = 0;
+        loop {
+            let token = try!(self.next());
+            if token == end {
+                return Ok(());
+            }
+            if token != sep {
+                return Err(unexpected(&token));
+            }
+
+            let len = self.path.len();
+            write!(self.path, "[{}]", index).expect("writing to a String cannot fail");
+            let token = try!(self.next());
+            try!(self.value(token));
+            self.path.truncate(len);
+            index += 1;
+        }
+    }
+
+    /// Indexes the entries of the map or the fields of the struct whose start token was just
+    /// read.
+    fn entries(&mut self, sep: Token<'static>, end: Token<'static>, is_map: bool)
+               -> Result<(), Error> {
+        loop {
+            let token = try!(self.next());
+            if token == end {
+                return Ok(());
+            }
+            if token != sep {
+                return Err(unexpected(&token));
+            }
+
+            let key = try!(self.next());
+            let key = if is_map {
+                try!(key_string(&key))
+            } else {
+                match key.as_str() {
+                    Some(key) => key.to_owned(),
+                    None => return Err(unexpected(&key)),
+                }
+            };
+            let len = self.push_key(&key);
+            let token = try!(self.next());
+            try!(self.value(token));
+            self.path.truncate(len);
+        }
+    }
+
+    fn push_key(&mut self, key: &str) -> usize {
+        push_key(&mut self.path, key)
+    }
+}
+
+/// The string that `redact` puts in place of a value.
+pub const REDACTED: &'static str = "[REDACTED]";
+
+/// Replaces the values at `paths` in `iter` with the string `REDACTED`, e.g. to log a request
+/// without its password.
+///
+/// Paths are written like the paths of `to_path_index`, so `"user.ssn"` is the field `ssn` of
+/// the field `user` and `"cards[0]"` the first element of `cards`. A path may also name a
+/// compound value, whose tokens are then skipped up to its end without being looked at.
+/// Everything else is passed through unchanged.
+///
+/// ```rust
+/// use serde::token::{self, json};
+///
+/// let tokens = json::from_str(r#"{"user": {"name": "ann", "ssn": "078-05-1120"}}"#).unwrap();
+/// let redacted = token::redact(tokens, &["user.ssn"]).unwrap();
+/// assert_eq!(token::to_json(redacted).unwrap(),
+///            r#"{"user":{"name":"ann","ssn":"[REDACTED]"}}"#);
+/// ```
+pub fn redact<'a, I>(iter: I, paths: &[&str]) -> Result<Vec<Token<'a>>, Error>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let mut redact = Redact {
+        tokens: iter.into_iter(),
+        paths: paths,
+        path: String::new(),
+        out: Vec::new(),
+    };
+
+    while let Some(token) = redact.tokens.next() {
+        try!(redact.value(token));
+    }
+    Ok(redact.out)
+}
+
+struct Redact<'a, 'p, I> {
+    tokens: I,
+    paths: &'p [&'p str],
+    /// The path of the value being read.
+    path: String,
+    out: Vec<Token<'a>>,
+}
+
+impl<'a, 'p, I> Redact<'a, 'p, I>
+    where I: Iterator<Item=Token<'a>>,
+{
+    fn next(&mut self) -> Result<Token<'a>, Error> {
+        self.tokens.next().ok_or_else(|| {
+            Error::InvalidValue("unexpected end of stream".to_owned())
+        })
+    }
+
+    /// Copies the value starting with `token`, which has the current path, unless it is
+    /// redacted.
+    fn value(&mut self, token: Token<'a>) -> Result<(), Error> {
+        if self.paths.contains(&&*self.path) {
+            self.out.push(Token::Str(REDACTED));
+            return skip_value(&mut iter::once(token).chain(&mut self.tokens));
+        }
+
+        match token {
+            Token::Option(true) | Token::StructNewType(_) | Token::TypeTag(_) => {
+                self.out.push(token);
+                let token = try!(self.next());
+                self.value(token)
+            }
+            Token::EnumNewType(_, variant) => {
+                self.out.push(token);
+                let len = push_key(&mut self.path, variant);
+                let token = try!(self.next());
+                try!(self.value(token));
+                self.path.truncate(len);
+                Ok(())
+            }
+            Token::EnumStart(_) => {
+                self.out.push(token);
+                let variant = try!(self.next());
+                let len = push_key(&mut self.path, &try!(key_string(&variant)));
+                self.out.push(variant);
+                let token = try!(self.next());
+                try!(self.value(token));
+                self.path.truncate(len);
+                Ok(())
+            }
+            Token::EnumSeqStart(_, variant, _) | Token::EnumMapStart(_, variant, _) => {
+                let len = push_key(&mut self.path, variant);
+                try!(self.compound(token));
+                self.path.truncate(len);
+                Ok(())
+            }
+            token => {
+                if token.is_start() {
+                    self.compound(token)
+                } else if token.is_sep() || token.is_end() {
+                    Err(unexpected(&token))
+                } else {
+                    self.out.push(token);
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// Copies the compound value that starts with `start`.
+    fn compound(&mut self, start: Token<'a>) -> Result<(), Error> {
+        let (is_map, is_struct) = match start {
+            Token::MapStart(_) => (true, false),
+            Token::StructStart(_, _) | Token::EnumMapStart(_, _, _) => (false, true),
+            _ => (false, false),
+        };
+        self.out.push(start);
+
+        let mut index = 0;
+        loop {
+            let token = try!(self.next());
+            if token.is_end() {
+                self.out.push(token);
+                return Ok(());
+            }
+            if !token.is_sep() {
+                return Err(unexpected(&token));
+            }
+            self.out.push(token);
+
+            let len = if is_map || is_struct {
+                let key = try!(self.next());
+                let text = if is_map {
+                    try!(key_string(&key))
+                } else {
+                    match key.as_str() {
+                        Some(key) => key.to_owned(),
+                        None => return Err(unexpected(&key)),
+                    }
+                };
+                self.out.push(key);
+                push_key(&mut self.path, &text)
+            } else {
+                let len = self.path.len();
+                write!(self.path, "[{}]", index).expect("writing to a String cannot fail");
+                index += 1;
+                len
+            };
+            let token = try!(self.next());
+            try!(self.value(token));
+            self.path.truncate(len);
+        }
+    }
+}
+
+/// Appends `key` to `path`, returning the length the path had before.
+fn push_key(path: &mut String, key: &str) -> usize {
+    let len = path.len();
+    let is_identifier = key.chars().next().map_or(false, |c| !c.is_digit(10)) &&
+        key.chars().all(|c| match c {
+            'a'...'z' | 'A'...'Z' | '0'...'9' | '_' => true,
+            _ => false,
+        });
+    if is_identifier {
+        if !path.is_empty() {
+            path.push('.');
+        }
+        path.push_str(key);
+    } else {
+        write!(path, "[{:?}]", key).expect("writing to a String cannot fail");
+    }
+    len
+}
+
+/// Returns the text of a map key.
+fn key_string(key: &Token) -> Result<String, Error> {
+    let key = match *key {
+        Token::Str(v) => v.to_owned(),
+        Token::String(ref v) => v.clone(),
+        Token::Char(v) => v.to_string(),
+        Token::Bool(v) => v.to_string(),
+        Token::Isize(v) => v.to_string(),
+        Token::I8(v) => v.to_string(),
+        Token::I16(v) => v.to_string(),
+        Token::I32(v) => v.to_string(),
+        Token::I64(v) => v.to_string(),
+        Token::Usize(v) => v.to_string(),
+        Token::U8(v) => v.to_string(),
+        Token::U16(v) => v.to_string(),
+        Token::U32(v) => v.to_string(),
+        Token::U64(v) => v.to_string(),
+        Token::EnumUnit(_, variant) => variant.to_owned(),
+        ref key => {
+            return Err(Error::InvalidValue(format!("map key must be a scalar, found {:?}", key)));
+        }
+    };
+    Ok(key)
+}
+
+fn unexpected(token: &Token) -> Error {
+    Error::InvalidValue(format!("unexpected token {:?}", token))
+}
@@ -0,0 +1,286 @@
+//! Hash the content of a token stream, independently of how it was produced.
+//!
+//! `content_hash` first brings the stream into the form of `canonicalize`, so that the order of
+//! map entries and the integer types in use don't matter, then encodes it to bytes as described
+//! below and hashes those with the chosen `Algorithm`. The encoding is meant to be reproducible
+//! outside of Rust, and `canonical_bytes` returns it for comparison.
+//!
+//! # Canonical encoding
+//!
+//! Every token is written as a tag byte followed by its payload. All integers in payloads are
+//! big-endian. A *string* is its length in bytes as a `u64`, followed by its UTF-8 bytes, and a
+//! *length* is a `u64`.
+//!
+//! | Tag    | Token                             | Payload                                  |
+//! |--------|-----------------------------------|------------------------------------------|
+//! | `0x00` | `Unit`                            |                                          |
+//! | `0x01` | `Option(false)`                   |                                          |
+//! | `0x02` | `Option(true)`                    |                                          |
+//! | `0x03` | `Bool(false)`                     |                                          |
+//! | `0x04` | `Bool(true)`                      |                                          |
+//! | `0x05` | an integer that is not negative   | the value as a `u64`                     |
+//! | `0x06` | a negative integer                | the value as an `i64`, two's complement  |
+//! | `0x07` | `F32`, `F64`                      | the IEEE 754 bits of the value as `f64`  |
+//! | `0x08` | `Char`                            | the scalar value as a `u32`              |
+//! | `0x09` | `Str`, `String`                   | the string                               |
+//! | `0x0a` | `Bytes`, `ByteBuf`                | the length, then the bytes               |
+//! | `0x0b` | `Timestamp`                       | the seconds as `i64`, the nanos as `u32` |
+//! | `0x0c` | `BigNum`                          | the digits as a string                   |
+//! | `0x10` | `UnitStruct`                      | the name                                 |
+//! | `0x11` | `StructNewType`                   | the name                                 |
+//! | `0x12` | `EnumStart`                       | the name                                 |
+//! | `0x13` | `EnumUnit`                        | the name, the variant                    |
+//! | `0x14` | `EnumNewType`                     | the name, the variant                    |
+//! | `0x15` | `TypeTag`                         | the name                                 |
+//! | `0x20` | `SeqStart`, `SeqArrayStart`       | the length                               |
+//! | `0x21` | `TupleStart`                      | the length                               |
+//! | `0x22` | `TupleStructStart`                | the name, the length                     |
+//! | `0x23` | `MapStart`                        | the length                               |
+//! | `0x24` | `StructStart`                     | the name, the length                     |
+//! | `0x25` | `EnumSeqStart`                    | the name, the variant, the length        |
+//! | `0x26` | `EnumMapStart`                    | the name, the variant, the length        |
+//! | `0x30` | every separator                   |                                          |
+//! | `0x31` | every end                         |                                          |
+//!
+//! Names and variants are strings. The lengths of compound values are always known in canonical
+//! form. Floats are widened to `f64` without any other normalization, so `0.0` and `-0.0` hash
+//! differently, as do NaNs with different payloads.
+
+use super::{CanonError, Token, canonicalize, f64_to_bits};
+
+/// A hash function for `content_hash`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    /// SipHash-2-4 with the two halves of the 128 bit key, the first 8 bytes of the key read as
+    /// a little-endian `u64` and then the last 8.
+    SipHash(u64, u64),
+    /// The 64 bit FNV-1a hash.
+    Fnv1a,
+    /// The CRC-32 used by zlib and PNG, with the polynomial `0x04c11db7`, widened to `u64`.
+    Crc32,
+}
+
+/// Hashes the content of the value in `iter` with `algorithm`.
+///
+/// Equal values hash equal however their streams were produced, e.g. from two `HashMap`s that
+/// iterate in different orders or from a `u8` and an `i64`. Malformed streams and maps with two
+/// equal keys are errors, as for `canonicalize`.
+///
+/// ```rust
+/// use serde::token::{self, Token};
+/// use serde::token::hash::{self, Algorithm};
+///
+/// let small = hash::content_hash(vec![Token::U8(5)], Algorithm::Fnv1a).unwrap();
+/// let large = hash::content_hash(token::to_tokens(&5i64).unwrap(), Algorithm::Fnv1a).unwrap();
+/// assert_eq!(small, large);
+/// ```
+pub fn content_hash<'a, I>(iter: I, algorithm: Algorithm) -> Result<u64, CanonError>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let bytes = try!(canonical_bytes(iter));
+    Ok(match algorithm {
+        Algorithm::SipHash(k0, k1) => siphash(k0, k1, &bytes),
+        Algorithm::Fnv1a => fnv1a(&bytes),
+        Algorithm::Crc32 => crc32(&bytes) as u64,
+    })
+}
+
+/// Returns the bytes `content_hash` hashes for the value in `iter`, in the encoding described
+/// in the module documentation.
+pub fn canonical_bytes<'a, I>(iter: I) -> Result<Vec<u8>, CanonError>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let mut out = Vec::new();
+    for token in try!(canonicalize(iter)) {
+        encode(&mut out, &token);
+    }
+    Ok(out)
+}
+
+fn encode(out: &mut Vec<u8>, token: &Token) {
+    match *token {
+        Token::Unit => out.push(0x00),
+        Token::Option(false) => out.push(0x01),
+        Token::Option(true) => out.push(0x02),
+        Token::Bool(false) => out.push(0x03),
+        Token::Bool(true) => out.push(0x04),
+
+        Token::U8(v) => unsigned(out, v as u64),
+        Token::U16(v) => unsigned(out, v as u64),
+        Token::U32(v) => unsigned(out, v as u64),
+        Token::U64(v) => unsigned(out, v),
+        Token::Usize(v) => unsigned(out, v as u64),
+        Token::I8(v) => signed(out, v as i64),
+        Token::I16(v) => signed(out, v as i64),
+        Token::I32(v) => signed(out, v as i64),
+        Token::I64(v) => signed(out, v),
+        Token::Isize(v) => signed(out, v as i64),
+
+        Token::F32(v) => float(out, v as f64),
+        Token::F64(v) => float(out, v),
+        Token::Char(v) => {
+            out.push(0x08);
+            be(out, v as u64, 4);
+        }
+        Token::Str(v) => {
+            out.push(0x09);
+            string(out, v);
+        }
+        Token::String(ref v) => {
+            out.push(0x09);
+            string(out, v);
+        }
+        Token::Bytes(v) => {
+            out.push(0x0a);
+            bytes(out, v);
+        }
+        Token::ByteBuf(ref v) => {
+            out.push(0x0a);
+            bytes(out, v);
+        }
+        Token::Timestamp(secs, nanos) => {
+            out.push(0x0b);
+            be(out, secs as u64, 8);
+            be(out, nanos as u64, 4);
+        }
+        Token::BigNum(ref v) => {
+            out.push(0x0c);
+            string(out, v);
+        }
+
+        Token::UnitStruct(name) => named(out, 0x10, &[name], None),
+        Token::StructNewType(name) => named(out, 0x11, &[name], None),
+        Token::EnumStart(name) => named(out, 0x12, &[name], None),
+        Token::EnumUnit(name, variant) => named(out, 0x13, &[name, variant], None),
+        Token::EnumNewType(name, variant) => named(out, 0x14, &[name, variant], None),
+        Token::TypeTag(name) => named(out, 0x15, &[name], None),
+
+        Token::SeqStart(len) => named(out, 0x20, &[], Some(len.unwrap_or(0))),
+        Token::SeqArrayStart(len) => named(out, 0x20, &[], Some(len)),
+        Token::TupleStart(len) => named(out, 0x21, &[], Some(len)),
+        Token::TupleStructStart(name, len) => named(out, 0x22, &[name], Some(len.unwrap_or(0))),
+        Token::MapStart(len) => named(out, 0x23, &[], Some(len.unwrap_or(0))),
+        Token::StructStart(name, len) => named(out, 0x24, &[name], Some(len.unwrap_or(0))),
+        Token::EnumSeqStart(name, variant, len) => {
+            named(out, 0x25, &[name, variant], Some(len.unwrap_or(0)))
+        }
+        Token::EnumMapStart(name, variant, len) => {
+            named(out, 0x26, &[name, variant], Some(len.unwrap_or(0)))
+        }
+
+        ref token if token.is_sep() => out.push(0x30),
+        _ => out.push(0x31),
+    }
+}
+
+fn be(out: &mut Vec<u8>, v: u64, width: usize) {
+    for i in (0..width).rev() {
+        out.push((v >> (8 * i)) as u8);
+    }
+}
+
+fn unsigned(out: &mut Vec<u8>, v: u64) {
+    out.push(0x05);
+    be(out, v, 8);
+}
+
+fn signed(out: &mut Vec<u8>, v: i64) {
+    if v < 0 {
+        out.push(0x06);
+        be(out, v as u64, 8);
+    } else {
+        unsigned(out, v as u64);
+    }
+}
+
+fn float(out: &mut Vec<u8>, v: f64) {
+    out.push(0x07);
+    be(out, f64_to_bits(v), 8);
+}
+
+fn string(out: &mut Vec<u8>, v: &str) {
+    bytes(out, v.as_bytes());
+}
+
+fn bytes(out: &mut Vec<u8>, v: &[u8]) {
+    be(out, v.len() as u64, 8);
+    out.extend(v.iter().cloned());
+}
+
+fn named(out: &mut Vec<u8>, tag: u8, names: &[&str], len: Option<usize>) {
+    out.push(tag);
+    for name in names {
+        string(out, name);
+    }
+    if let Some(len) = len {
+        be(out, len as u64, 8);
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+fn siphash(k0: u64, k1: u64, bytes: &[u8]) -> u64 {
+    let mut v = [
+        k0 ^ 0x736f6d6570736575,
+        k1 ^ 0x646f72616e646f6d,
+        k0 ^ 0x6c7967656e657261,
+        k1 ^ 0x7465646279746573,
+    ];
+
+    let mut last = (bytes.len() as u64) << 56;
+    for chunk in bytes.chunks(8) {
+        let mut word = 0;
+        for (i, &byte) in chunk.iter().enumerate() {
+            word |= (byte as u64) << (8 * i);
+        }
+        if chunk.len() < 8 {
+            last |= word;
+            break;
+        }
+        v[3] ^= word;
+        sip_rounds(&mut v, 2);
+        v[0] ^= word;
+    }
+
+    v[3] ^= last;
+    sip_rounds(&mut v, 2);
+    v[0] ^= last;
+    v[2] ^= 0xff;
+    sip_rounds(&mut v, 4);
+    v[0] ^ v[1] ^ v[2] ^ v[3]
+}
+
+fn sip_rounds(v: &mut [u64; 4], rounds: usize) {
+    for _ in 0..rounds {
+        v[0] = v[0].wrapping_add(v[1]);
+        v[1] = v[1].rotate_left(13) ^ v[0];
+        v[0] = v[0].rotate_left(32);
+        v[2] = v[2].wrapping_add(v[3]);
+        v[3] = v[3].rotate_left(16) ^ v[2];
+        v[0] = v[0].wrapping_add(v[3]);
+        v[3] = v[3].rotate_left(21) ^ v[0];
+        v[2] = v[2].wrapping_add(v[1]);
+        v[1] = v[1].rotate_left(17) ^ v[2];
+        v[2] = v[2].rotate_left(32);
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf29ce484222325;
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
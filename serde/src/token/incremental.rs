@@ -0,0 +1,166 @@
+//! Serialize a large map again after a few of its values changed, without serializing the
+//! values that didn't.
+
+use std::collections::BTreeMap;
+use std::collections::btree_map;
+
+use ser::Serialize;
+
+use super::{Error, Token, to_tokens};
+use super::compose::Object;
+
+/// A map with string keys that keeps the tokens of every value from the last time it was
+/// serialized.
+///
+/// `to_tokens` only serializes the values that changed since, and splices the recorded tokens of
+/// the others around them with `compose::Object`. Changes made through `insert`, `remove` and
+/// `get_mut` are tracked. A value that changes in another way, e.g. through a `Cell`, has to be
+/// marked with `invalidate`.
+///
+/// The entries are written in the order of their keys.
+///
+/// ```rust
+/// use serde::token::{self, Incremental};
+///
+/// let mut prices = Incremental::new();
+/// prices.insert("apple".to_owned(), 3);
+/// prices.insert("pear".to_owned(), 4);
+/// assert_eq!(token::to_json(prices.to_tokens().unwrap()).unwrap(), r#"{"apple":3,"pear":4}"#);
+///
+/// // Only the price of pears is serialized again.
+/// *prices.get_mut("pear").unwrap() += 1;
+/// assert_eq!(token::to_json(prices.to_tokens().unwrap()).unwrap(), r#"{"apple":3,"pear":5}"#);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Incremental<V> {
+    name: Option<String>,
+    entries: BTreeMap<String, Entry<V>>,
+}
+
+#[derive(Clone, Debug)]
+struct Entry<V> {
+    value: V,
+    /// The tokens of `value`, or `None` if they have to be serialized again.
+    tokens: Option<Vec<Token<'static>>>,
+}
+
+impl<V> Incremental<V>
+    where V: Serialize,
+{
+    /// Construct a new empty `Incremental`, serialized as a map.
+    pub fn new() -> Self {
+        Incremental {
+            name: None,
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// Serializes the entries as the fields of a struct called `name` instead of a map.
+    pub fn named(mut self, name: &str) -> Self {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if there are no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the value of `key`.
+    pub fn get(&self, key: &str) -> Option<&V> {
+        self.entries.get(key).map(|entry| &entry.value)
+    }
+
+    /// Returns the value of `key` for changing it, which invalidates its tokens.
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut V> {
+        self.entries.get_mut(key).map(|entry| {
+            entry.tokens = None;
+            &mut entry.value
+        })
+    }
+
+    /// Sets the value of `key`, returning the previous one.
+    pub fn insert(&mut self, key: String, value: V) -> Option<V> {
+        let entry = Entry {
+            value: value,
+            tokens: None,
+        };
+        self.entries.insert(key, entry).map(|entry| entry.value)
+    }
+
+    /// Removes `key`, returning its value.
+    pub fn remove(&mut self, key: &str) -> Option<V> {
+        self.entries.remove(key).map(|entry| entry.value)
+    }
+
+    /// Marks the value of `key` as changed, so that the next `to_tokens` serializes it again.
+    /// Returns false if there is no such key.
+    pub fn invalidate(&mut self, key: &str) -> bool {
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                entry.tokens = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns an iterator over the keys and values, in the order of the keys.
+    pub fn iter(&self) -> Iter<V> {
+        Iter {
+            iter: self.entries.iter(),
+        }
+    }
+
+    /// Returns the tokens of the whole map, serializing only the values that changed since the
+    /// last call.
+    ///
+    /// The values serialized before an error keep their new tokens.
+    pub fn to_tokens(&mut self) -> Result<Vec<Token>, Error> {
+        for entry in self.entries.values_mut() {
+            if entry.tokens.is_none() {
+                entry.tokens = Some(try!(to_tokens(&entry.value)));
+            }
+        }
+
+        let mut object = match self.name {
+            Some(ref name) => Object::named(name),
+            None => Object::new(),
+        };
+        for (key, entry) in &self.entries {
+            let tokens = entry.tokens.as_ref().expect("every value was serialized");
+            object = object.field(key, tokens.iter().cloned());
+        }
+        object.finish()
+    }
+}
+
+impl<V> Default for Incremental<V>
+    where V: Serialize,
+{
+    fn default() -> Self {
+        Incremental::new()
+    }
+}
+
+/// Iterator returned by `Incremental::iter`.
+pub struct Iter<'a, V: 'a> {
+    iter: btree_map::Iter<'a, String, Entry<V>>,
+}
+
+impl<'a, V> Iterator for Iter<'a, V> {
+    type Item = (&'a str, &'a V);
+
+    fn next(&mut self) -> Option<(&'a str, &'a V)> {
+        self.iter.next().map(|(key, entry)| (&**key, &entry.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
@@ -0,0 +1,199 @@
+//! Check that integers fit into the range a format can represent exactly.
+
+use std::error;
+use std::fmt;
+use std::fmt::Write;
+
+use super::Token;
+
+/// The largest integer that a double precision float, and thus JavaScript and many JSON readers,
+/// represent exactly: 2^53 - 1.
+pub const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// The error returned by `check_int_range`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RangeError {
+    path: String,
+    value: String,
+    max_abs: u64,
+}
+
+impl RangeError {
+    /// Returns the path to the integer, written like the paths of `to_path_index`. The path of
+    /// the outermost value is empty.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the integer, in decimal.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+impl fmt::Display for RangeError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let path = if self.path.is_empty() { "(root)" } else { &self.path };
+        write!(formatter,
+               "at {}: integer {} is out of the range -{} to {}",
+               path,
+               self.value,
+               self.max_abs,
+               self.max_abs)
+    }
+}
+
+impl error::Error for RangeError {
+    fn description(&self) -> &str {
+        "integer out of range"
+    }
+}
+
+/// Checks that no integer in `iter` is further than `max_abs` from zero, reporting the first
+/// one that is.
+///
+/// `BigNum` tokens count as integers too. Floats are not checked, and neither are map keys,
+/// which textual formats write as strings. The stream is not validated, and the path in the
+/// error leaves out enum variants.
+///
+/// ```rust
+/// use serde::token::{self, Token};
+/// use serde::token::int_range::MAX_SAFE_INTEGER;
+///
+/// let ids = vec![1u64, 1 << 53];
+/// let err = token::check_int_range(token::to_tokens(&ids).unwrap(), MAX_SAFE_INTEGER)
+///     .unwrap_err();
+/// assert_eq!(err.path(), "[1]");
+/// assert_eq!(err.value(), "9007199254740992");
+/// ```
+pub fn check_int_range<'a, I>(iter: I, max_abs: u64) -> Result<(), RangeError>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let mut path: Vec<Segment> = Vec::new();
+    // Whether the next token is the variant after an `EnumStart`.
+    let mut expect_variant = false;
+    // Whether the next value is a map key or a field name.
+    let mut expect_key = false;
+
+    for token in iter {
+        if expect_variant {
+            expect_variant = false;
+            continue;
+        }
+        if expect_key {
+            match token {
+                Token::Option(true) | Token::StructNewType(_) | Token::TypeTag(_) => continue,
+                _ => expect_key = false,
+            }
+            // Compound keys are checked like values, their entry has no key in the path.
+            let key = if token.is_start() {
+                None
+            } else {
+                token.as_str().map(String::from).or_else(|| {
+                    magnitude(&token).map(|(value, _)| value)
+                })
+            };
+            if let Some(&mut Segment::Key(ref mut current)) = path.last_mut() {
+                *current = key;
+            }
+            if !token.is_start() {
+                continue;
+            }
+        }
+
+        match token {
+            Token::EnumStart(_) => expect_variant = true,
+            Token::SeqStart(_) |
+            Token::SeqArrayStart(_) |
+            Token::TupleStart(_) |
+            Token::TupleStructStart(_, _) |
+            Token::EnumSeqStart(_, _, _) => path.push(Segment::Index(None)),
+            Token::MapStart(_) | Token::StructStart(_, _) | Token::EnumMapStart(_, _, _) => {
+                path.push(Segment::Key(None));
+            }
+            ref token if token.is_sep() => {
+                match path.last_mut() {
+                    Some(&mut Segment::Index(ref mut index)) => {
+                        *index = Some(index.map_or(0, |index| index + 1));
+                    }
+                    Some(&mut Segment::Key(_)) => expect_key = true,
+                    None => { }
+                }
+            }
+            ref token if token.is_end() => {
+                path.pop();
+            }
+            ref token => {
+                if let Some((value, abs)) = magnitude(token) {
+                    if abs.map_or(true, |abs| abs > max_abs) {
+                        return Err(RangeError {
+                            path: format_path(&path),
+                            value: value,
+                            max_abs: max_abs,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A step of the path to the current value.
+enum Segment {
+    /// An element of a sequence, with its index once its separator was read.
+    Index(Option<usize>),
+    /// An entry of a map or a struct, with its key once it was read and if it is a scalar.
+    Key(Option<String>),
+}
+
+fn format_path(path: &[Segment]) -> String {
+    let mut out = String::new();
+    for segment in path {
+        match *segment {
+            Segment::Index(Some(index)) => {
+                write!(out, "[{}]", index).expect("writing to a String cannot fail");
+            }
+            Segment::Key(Some(ref key)) => {
+                let is_identifier = key.chars().next().map_or(false, |c| !c.is_digit(10)) &&
+                    key.chars().all(|c| match c {
+                        'a'...'z' | 'A'...'Z' | '0'...'9' | '_' => true,
+                        _ => false,
+                    });
+                if !is_identifier {
+                    write!(out, "[{:?}]", key).expect("writing to a String cannot fail");
+                } else {
+                    if !out.is_empty() {
+                        out.push('.');
+                    }
+                    out.push_str(key);
+                }
+            }
+            Segment::Index(None) | Segment::Key(None) => { }
+        }
+    }
+    out
+}
+
+/// Returns the decimal text and the distance from zero of an integer token, or `None` if the
+/// token isn't an integer. The distance is `None` when it doesn't fit into a `u64`.
+fn magnitude(token: &Token) -> Option<(String, Option<u64>)> {
+    let signed = match *token {
+        Token::Isize(v) => v as i64,
+        Token::I8(v) => v as i64,
+        Token::I16(v) => v as i64,
+        Token::I32(v) => v as i64,
+        Token::I64(v) => v,
+        Token::Usize(v) => return Some((v.to_string(), Some(v as u64))),
+        Token::U8(v) => return Some((v.to_string(), Some(v as u64))),
+        Token::U16(v) => return Some((v.to_string(), Some(v as u64))),
+        Token::U32(v) => return Some((v.to_string(), Some(v as u64))),
+        Token::U64(v) => return Some((v.to_string(), Some(v))),
+        Token::BigNum(ref v) => {
+            let digits = if v.starts_with('-') { &v[1..] } else { &v[..] };
+            return Some((v.clone(), digits.parse().ok()));
+        }
+        _ => return None,
+    };
+    Some((signed.to_string(), Some(signed.wrapping_abs() as u64)))
+}
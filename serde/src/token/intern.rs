@@ -0,0 +1,163 @@
+//! Deduplicate repeated map keys and field names in recorded token streams.
+//!
+//! A recorded sequence of maps or structs repeats the same key strings over and over again.
+//! `Interned` stores each distinct key once in an `Interner` and refers to it by index. The
+//! binary format uses an `Interner` the same way to write a string table and indices instead of
+//! repeating the names, see `binary::Encoder::string_table`.
+
+use std::collections::HashMap;
+use std::slice;
+
+use super::Token;
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A pool of distinct strings, each identified by the index it was first added at.
+#[derive(Clone, Debug, Default)]
+pub struct Interner {
+    strings: Vec<String>,
+    ids: HashMap<String, usize>,
+}
+
+impl Interner {
+    /// Construct a new, empty `Interner`.
+    pub fn new() -> Self {
+        Interner {
+            strings: Vec::new(),
+            ids: HashMap::new(),
+        }
+    }
+
+    /// Returns the index of `s`, adding it to the pool if it isn't there yet.
+    pub fn intern(&mut self, s: &str) -> usize {
+        if let Some(&id) = self.ids.get(s) {
+            return id;
+        }
+
+        let id = self.strings.len();
+        self.strings.push(s.to_owned());
+        self.ids.insert(s.to_owned(), id);
+        id
+    }
+
+    /// Returns the string with the given index.
+    pub fn get(&self, id: usize) -> Option<&str> {
+        self.strings.get(id).map(|s| &s[..])
+    }
+
+    /// Returns all strings in the pool, ordered by index.
+    pub fn strings(&self) -> &[String] {
+        &self.strings
+    }
+
+    /// Returns the number of strings in the pool.
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    /// Returns true if the pool is empty.
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// One element of an `Interned` stream.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Entry {
+    /// A token that is stored as is.
+    Token(Token<'static>),
+    /// A string map key or struct field name, stored as an index into the `Interner`.
+    Key(usize),
+}
+
+/// A recorded token stream whose string map keys and struct field names are interned.
+#[derive(Clone, Debug, Default)]
+pub struct Interned {
+    interner: Interner,
+    entries: Vec<Entry>,
+}
+
+impl Interned {
+    /// Records `iter`, replacing every string map key and struct field name with a reference
+    /// into the pool.
+    pub fn from_tokens<I>(iter: I) -> Self
+        where I: IntoIterator<Item=Token<'static>>,
+    {
+        let mut interner = Interner::new();
+        let mut entries = Vec::new();
+        let mut key_position = false;
+
+        for token in iter {
+            let entry = match token {
+                Token::Str(s) if key_position => Entry::Key(interner.intern(s)),
+                Token::String(ref s) if key_position => Entry::Key(interner.intern(s)),
+                token => Entry::Token(token),
+            };
+            key_position = match entry {
+                Entry::Token(Token::MapSep) |
+                Entry::Token(Token::StructSep) |
+                Entry::Token(Token::EnumMapSep) => true,
+                _ => false,
+            };
+            entries.push(entry);
+        }
+
+        Interned {
+            interner: interner,
+            entries: entries,
+        }
+    }
+
+    /// Returns the pool of interned keys.
+    pub fn interner(&self) -> &Interner {
+        &self.interner
+    }
+
+    /// Returns the recorded entries.
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Returns an iterator that replays the original token stream. Interned keys are yielded as
+    /// `Str` tokens borrowing from the pool.
+    pub fn iter(&self) -> Iter {
+        Iter {
+            interner: &self.interner,
+            entries: self.entries.iter(),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a Interned {
+    type Item = Token<'a>;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+/// Iterator returned by `Interned::iter`.
+pub struct Iter<'a> {
+    interner: &'a Interner,
+    entries: slice::Iter<'a, Entry>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        self.entries.next().map(|entry| {
+            match *entry {
+                Entry::Token(ref token) => token.clone(),
+                Entry::Key(id) => Token::Str(&self.interner.strings[id]),
+            }
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
@@ -0,0 +1,2030 @@
+//! Read and write token streams as JSON.
+//!
+//! When writing, maps and structs become objects, sequences, tuples and tuple structs become
+//! arrays, and `None` and `()` become `null`. `Writer::tuple_structs` can write tuple structs as
+//! objects keyed by index instead. Enum variants are written as a single entry object
+//! from the variant name to the payload, with unit variants getting an empty array as payload
+//! unless `Writer::unit_variants` asks for bare strings.
+//! Map keys have to be scalars and are always written as strings. Timestamps are written as RFC
+//! 3339 strings, unless `Writer::timestamp_format` asks for another `TimestampFormat`, and byte
+//! strings as base64 strings, unless `Writer::bytes_encoding` asks for another `BytesEncoding`.
+//!
+//! When reading, objects become maps with string keys, arrays become sequences and `null`
+//! becomes `()`. Integers become `I64`, or `U64` when they are too large for an `i64`, and all
+//! other numbers become `F64`. Strings without escapes become `Str` tokens borrowing from the
+//! input, and only strings with escapes are copied into `String` tokens. An object with a
+//! repeated key keeps every entry, `DuplicateKeyPolicy` decides which one wins when building a
+//! value from it.
+//!
+//! `from_str` only accepts strict JSON. `from_str_with` can allow comments, trailing commas and
+//! the `NaN` and `Infinity` literals, which hand-edited files tend to contain. It can also keep
+//! integers too large for a `u64` exact as `BigNum` tokens instead of reading them as floats.
+//! `Stream` reads inputs holding several values, one after another, and `ReadStream` reads them
+//! from an `io::Read` one at a time.
+
+use std::char;
+use std::cmp;
+use std::error;
+use std::f64;
+use std::fmt;
+use std::io;
+use std::marker::PhantomData;
+use std::str;
+
+use ser::Serialize;
+
+use super::{Snippet, Token, TokenSink, into_owned, line_column};
+use super::encoding::{self, BytesEncoding};
+use super::order::{FieldOrders, order_fields};
+use super::time::{Rendered, TimestampFormat};
+use super::borrowed::{self, DeserializeBorrowed};
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// This represents all the possible errors that can occur while reading or writing JSON.
+#[derive(Debug)]
+pub enum Error {
+    /// Writing to the underlying writer failed.
+    Io(io::Error),
+
+    /// A map key was not a scalar.
+    KeyMustBeAString,
+
+    /// A token appeared where it isn't allowed, for example an end token without a matching
+    /// start token.
+    UnexpectedToken(String),
+
+    /// The input is not valid JSON. Contains a description of the problem, the line and column
+    /// at which it was found, and the line of the input it is in. Formatting the error with
+    /// `{:#}` shows that line.
+    Syntax(String, usize, usize, Snippet),
+
+    /// The input is valid JSON, but doesn't match the type it is deserialized into.
+    Value(super::Error),
+
+    /// Reading the document with the given index of a `Stream` failed.
+    Document(usize, Box<Error>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Error::Io(ref err) => write!(formatter, "{}", err),
+            Error::KeyMustBeAString => formatter.write_str("Key must be a string"),
+            Error::UnexpectedToken(ref token) => write!(formatter, "Unexpected token: {}", token),
+            Error::Syntax(ref msg, line, column, ref snippet) => {
+                try!(write!(formatter, "{} at line {} column {}", msg, line, column));
+                if formatter.alternate() {
+                    try!(write!(formatter, "\n{}", snippet));
+                }
+                Ok(())
+            }
+            Error::Value(ref err) => write!(formatter, "{}", err),
+            Error::Document(index, ref err) => {
+                try!(write!(formatter, "document {}: ", index));
+                fmt::Display::fmt(err, formatter)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Io(ref err) => error::Error::description(err),
+            Error::KeyMustBeAString => "key must be a string",
+            Error::UnexpectedToken(_) => "unexpected token",
+            Error::Syntax(..) => "syntax error",
+            Error::Value(ref err) => error::Error::description(err),
+            Error::Document(_, ref err) => error::Error::description(&**err),
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Io(ref err) => Some(err),
+            Error::Value(ref err) => Some(err),
+            Error::Document(_, ref err) => Some(&**err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Writes the values in `iter` as JSON into a `String`.
+pub fn to_json<'a, I>(iter: I) -> Result<String, Error>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let mut out = Vec::new();
+    try!(write_json(iter, &mut out));
+    Ok(String::from_utf8(out).expect("JSON output is always valid UTF-8"))
+}
+
+/// Writes the values in `iter` as JSON into a `String`, with the options of `config`.
+///
+/// ```rust
+/// use serde::token::{self, json};
+/// use serde::token::json::JsonConfig;
+///
+/// let config = JsonConfig { float_precision: Some(2), ..JsonConfig::default() };
+/// assert_eq!(json::to_json_with(token::to_tokens(&1.2345).unwrap(), config).unwrap(), "1.23");
+/// ```
+pub fn to_json_with<'a, I>(iter: I, config: JsonConfig) -> Result<String, Error>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let mut json = Writer::new(Vec::new()).config(config);
+    for token in iter {
+        try!(json.write_token(token));
+    }
+    Ok(String::from_utf8(json.into_inner()).expect("JSON output is always valid UTF-8"))
+}
+
+/// Writes the values in `iter` as JSON into a `String`, with the fields of structs reordered as
+/// `order_fields` does.
+///
+/// `Writer` writes each token as soon as it gets it, so it can't reorder fields. This has to read
+/// the whole value first instead.
+///
+/// ```rust
+/// use serde::token::{FieldOrder, FieldOrders, Token, json};
+///
+/// let tokens = vec![
+///     Token::StructStart("Point", Some(2)),
+///     Token::StructSep, Token::Str("y"), Token::I32(2),
+///     Token::StructSep, Token::Str("x"), Token::I32(1),
+///     Token::StructEnd,
+/// ];
+/// let orders = FieldOrders::new(FieldOrder::Sorted);
+/// assert_eq!(json::to_json_ordered(tokens, &orders).unwrap(), r#"{"x":1,"y":2}"#);
+/// ```
+pub fn to_json_ordered<'a, I>(iter: I, orders: &FieldOrders) -> Result<String, Error>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let tokens = try!(order_fields(iter, orders).map_err(Error::Value));
+    to_json(tokens)
+}
+
+/// Writes the values in `iter` as JSON into `writer`.
+pub fn write_json<'a, I, W>(iter: I, writer: W) -> Result<(), Error>
+    where I: IntoIterator<Item=Token<'a>>,
+          W: io::Write,
+{
+    let mut json = Writer::new(writer);
+    for token in iter {
+        try!(json.write_token(token));
+    }
+    Ok(())
+}
+
+/// Writes each of `documents` as a JSON value on its own line, the format read by `Stream`.
+///
+/// ```rust
+/// use serde::token::{self, json};
+///
+/// let documents = vec![token::to_tokens(&1).unwrap(), token::to_tokens(&"two").unwrap()];
+/// assert_eq!(json::to_json_stream(documents).unwrap(), "1\n\"two\"\n");
+/// ```
+pub fn to_json_stream<'a, D, I>(documents: D) -> Result<String, Error>
+    where D: IntoIterator<Item=I>,
+          I: IntoIterator<Item=Token<'a>>,
+{
+    let mut out = Vec::new();
+    for document in documents {
+        try!(write_json(document, &mut out));
+        out.push(b'\n');
+    }
+    Ok(String::from_utf8(out).expect("JSON output is always valid UTF-8"))
+}
+
+/// Writes the sequence in `iter` as JSON arrays of at most `max_bytes` bytes each, splitting it
+/// between elements.
+///
+/// Elements are added to the current array until the next one would make it longer than
+/// `max_bytes`, at which point a new array is started. An element that doesn't fit into an array
+/// on its own still gets one, which is the only way a chunk can exceed `max_bytes`, so callers
+/// that can't accept those should compare the length of each chunk against their budget. An
+/// empty sequence produces no chunks.
+///
+/// ```rust
+/// use serde::token::{self, json};
+///
+/// let tokens = token::to_tokens(&vec![1, 22, 333]).unwrap();
+/// let chunks = json::to_json_chunks(tokens, 8).unwrap();
+/// assert_eq!(chunks, vec!["[1,22]", "[333]"]);
+/// ```
+pub fn to_json_chunks<'a, I>(iter: I, max_bytes: usize) -> Result<Vec<String>, Error>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let mut tokens = iter.into_iter();
+    match tokens.next() {
+        Some(Token::SeqStart(_)) | Some(Token::SeqArrayStart(_)) => { }
+        Some(token) => return Err(unexpected(&token)),
+        None => return Err(Error::UnexpectedToken("end of stream".to_owned())),
+    }
+
+    let mut chunks = Vec::new();
+    // The current chunk without its closing `]`.
+    let mut chunk = Vec::new();
+    loop {
+        match tokens.next() {
+            Some(Token::SeqSep) => { }
+            Some(Token::SeqEnd) => break,
+            Some(token) => return Err(unexpected(&token)),
+            None => return Err(Error::UnexpectedToken("end of stream".to_owned())),
+        }
+
+        let element = try!(write_element(&mut tokens));
+        if !chunk.is_empty() && chunk.len() + 1 + element.len() + 1 > max_bytes {
+            chunks.push(close_chunk(chunk));
+            chunk = Vec::new();
+        }
+        chunk.push(if chunk.is_empty() { b'[' } else { b',' });
+        chunk.extend(element);
+    }
+
+    if let Some(token) = tokens.next() {
+        return Err(unexpected(&token));
+    }
+    if !chunk.is_empty() {
+        chunks.push(close_chunk(chunk));
+    }
+    Ok(chunks)
+}
+
+/// Writes the next value in `tokens`.
+fn write_element<'a, I>(tokens: &mut I) -> Result<Vec<u8>, Error>
+    where I: Iterator<Item=Token<'a>>,
+{
+    let mut writer = Writer::new(Vec::new());
+    loop {
+        let token = match tokens.next() {
+            Some(token) => token,
+            None => return Err(Error::UnexpectedToken("end of stream".to_owned())),
+        };
+        let wraps_value = match token {
+            Token::Option(true) |
+            Token::StructNewType(_) |
+            Token::TypeTag(_) |
+            Token::EnumStart(_) => true,
+            _ => false,
+        };
+        try!(writer.write_token(token));
+
+        if writer.depth() == 0 && !wraps_value {
+            return Ok(writer.into_inner());
+        }
+    }
+}
+
+fn close_chunk(mut chunk: Vec<u8>) -> String {
+    chunk.push(b']');
+    String::from_utf8(chunk).expect("JSON output is always valid UTF-8")
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Frame {
+    /// An array. The flag is true until the first element has been written.
+    Array(bool),
+    /// An object. The flag is true until the first entry has been written.
+    Object(bool),
+    /// An enum variant wrapping an array or object, which needs an extra `}` when it ends.
+    Variant(bool, bool),
+    /// A newtype variant, closed as soon as its value is complete.
+    NewtypeVariant,
+    /// A tuple struct written as an object, with the index of the next element.
+    IndexedObject(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Value,
+    /// The next scalar is a map key.
+    MapKey,
+    /// The next token is a struct field name.
+    FieldKey,
+}
+
+/// How a `Writer` writes tuple structs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TupleStructs {
+    /// As arrays, e.g. `[1,2]`. This is the default.
+    Arrays,
+    /// As objects keyed by the index of each element, e.g. `{"0":1,"1":2}`.
+    Objects,
+}
+
+impl Default for TupleStructs {
+    fn default() -> Self {
+        TupleStructs::Arrays
+    }
+}
+
+/// How a `Writer` writes unit variants of enums. Variants with a payload are always written as
+/// single entry objects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnitVariants {
+    /// As a single entry object with an empty array as payload, e.g. `{"Red":[]}`. This is the
+    /// default.
+    Objects,
+    /// As the bare variant name, e.g. `"Red"`.
+    Strings,
+}
+
+impl Default for UnitVariants {
+    fn default() -> Self {
+        UnitVariants::Objects
+    }
+}
+
+/// Options for writing JSON, set with `Writer::config` or passed to `to_json_with`. The
+/// `Writer` builder methods of the same names set single options.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct JsonConfig {
+    /// Rounds `F32` and `F64` values to this many decimal places, and writes the shortest number
+    /// that reads back as the rounded value. `None`, the default, writes the shortest number
+    /// that reads back as the value itself.
+    pub float_precision: Option<usize>,
+    /// How `Timestamp` tokens are written. Integer formats are written as JSON numbers, except
+    /// in map keys.
+    pub timestamp_format: TimestampFormat,
+    /// How tuple structs are written.
+    pub tuple_structs: TupleStructs,
+    /// How unit variants of enums are written.
+    pub unit_variants: UnitVariants,
+    /// How `Bytes` and `ByteBuf` tokens are encoded as strings.
+    pub bytes_encoding: BytesEncoding,
+}
+
+/// A push based JSON writer that is fed one token at a time.
+pub struct Writer<W> {
+    writer: W,
+    stack: Vec<Frame>,
+    state: State,
+    config: JsonConfig,
+}
+
+impl<W> Writer<W>
+    where W: io::Write,
+{
+    /// Construct a new `Writer` writing into `writer`.
+    pub fn new(writer: W) -> Self {
+        Writer {
+            writer: writer,
+            stack: Vec::new(),
+            state: State::Value,
+            config: JsonConfig::default(),
+        }
+    }
+
+    /// Sets how `Timestamp` tokens are written, see `JsonConfig::timestamp_format`.
+    pub fn timestamp_format(mut self, format: TimestampFormat) -> Self {
+        self.config.timestamp_format = format;
+        self
+    }
+
+    /// Sets how tuple structs are written, see `JsonConfig::tuple_structs`.
+    ///
+    /// ```rust
+    /// use serde::token::Token;
+    /// use serde::token::json::{TupleStructs, Writer};
+    ///
+    /// let mut writer = Writer::new(Vec::new()).tuple_structs(TupleStructs::Objects);
+    /// for token in vec![
+    ///     Token::TupleStructStart("Pair", Some(2)),
+    ///     Token::TupleStructSep, Token::I32(1),
+    ///     Token::TupleStructSep, Token::I32(2),
+    ///     Token::TupleStructEnd,
+    /// ] {
+    ///     writer.write_token(token).unwrap();
+    /// }
+    /// assert_eq!(writer.into_inner(), br#"{"0":1,"1":2}"#);
+    /// ```
+    pub fn tuple_structs(mut self, tuple_structs: TupleStructs) -> Self {
+        self.config.tuple_structs = tuple_structs;
+        self
+    }
+
+    /// Sets how unit variants of enums are written, see `JsonConfig::unit_variants`.
+    /// `borrowed::Deserializer::unit_variant` reads either form back.
+    ///
+    /// ```rust
+    /// use serde::token::Token;
+    /// use serde::token::json::{UnitVariants, Writer};
+    ///
+    /// let mut writer = Writer::new(Vec::new()).unit_variants(UnitVariants::Strings);
+    /// writer.write_token(Token::EnumUnit("Color", "Red")).unwrap();
+    /// assert_eq!(writer.into_inner(), br#""Red""#);
+    /// ```
+    pub fn unit_variants(mut self, unit_variants: UnitVariants) -> Self {
+        self.config.unit_variants = unit_variants;
+        self
+    }
+
+    /// Sets how `Bytes` and `ByteBuf` tokens are encoded as strings, see
+    /// `JsonConfig::bytes_encoding`.
+    ///
+    /// ```rust
+    /// use serde::token::{BytesEncoding, Token};
+    /// use serde::token::json::Writer;
+    ///
+    /// let mut writer = Writer::new(Vec::new()).bytes_encoding(BytesEncoding::Hex);
+    /// writer.write_token(Token::Bytes(b"\x01\xff")).unwrap();
+    /// assert_eq!(writer.into_inner(), br#""01ff""#);
+    /// ```
+    pub fn bytes_encoding(mut self, encoding: BytesEncoding) -> Self {
+        self.config.bytes_encoding = encoding;
+        self
+    }
+
+    /// Sets all options of `config` at once, replacing the ones set before.
+    pub fn config(mut self, config: JsonConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Gets a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W { &self.writer }
+
+    /// Gets a mutable reference to the underlying writer.
+    pub fn get_mut(&mut self) -> &mut W { &mut self.writer }
+
+    /// Unwraps this `Writer`, returning the underlying writer.
+    pub fn into_inner(self) -> W { self.writer }
+
+    /// Returns the number of containers that have been opened but not closed yet.
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Writes the next token.
+    pub fn write_token(&mut self, token: Token) -> Result<(), Error> {
+        match self.state {
+            State::MapKey => return self.write_key(token),
+            State::FieldKey => {
+                return match token.as_str() {
+                    Some(key) => {
+                        self.state = State::Value;
+                        try!(write_str(&mut self.writer, key));
+                        try!(self.writer.write_all(b":"));
+                        Ok(())
+                    }
+                    None => Err(unexpected(&token)),
+                };
+            }
+            State::Value => { }
+        }
+
+        match token {
+            Token::Bool(v) => try!(self.scalar(if v { "true" } else { "false" })),
+            Token::Isize(v) => try!(self.scalar(&v.to_string())),
+            Token::I8(v) => try!(self.scalar(&v.to_string())),
+            Token::I16(v) => try!(self.scalar(&v.to_string())),
+            Token::I32(v) => try!(self.scalar(&v.to_string())),
+            Token::I64(v) => try!(self.scalar(&v.to_string())),
+            Token::Usize(v) => try!(self.scalar(&v.to_string())),
+            Token::U8(v) => try!(self.scalar(&v.to_string())),
+            Token::U16(v) => try!(self.scalar(&v.to_string())),
+            Token::U32(v) => try!(self.scalar(&v.to_string())),
+            Token::U64(v) => try!(self.scalar(&v.to_string())),
+            Token::F32(v) => try!(self.scalar(&self.fmt_float(v))),
+            Token::F64(v) => try!(self.scalar(&self.fmt_float(v))),
+            Token::BigNum(ref v) => try!(self.scalar(v)),
+            Token::Char(v) => {
+                try!(write_str(&mut self.writer, ::utils::encode_utf8(v).as_str()));
+                try!(self.value_done());
+            }
+            Token::Str(v) => {
+                try!(write_str(&mut self.writer, v));
+                try!(self.value_done());
+            }
+            Token::String(ref v) => {
+                try!(write_str(&mut self.writer, v));
+                try!(self.value_done());
+            }
+            Token::Bytes(v) => try!(self.write_bytes(v)),
+            Token::ByteBuf(ref v) => try!(self.write_bytes(v)),
+            Token::Timestamp(secs, nanos) => {
+                match self.config.timestamp_format.render(secs, nanos) {
+                    Rendered::Integer(v) => try!(self.writer.write_all(v.to_string().as_bytes())),
+                    Rendered::Text(v) => try!(write_str(&mut self.writer, &v)),
+                }
+                try!(self.value_done());
+            }
+
+            Token::Option(false) | Token::Unit | Token::UnitStruct(_) => try!(self.scalar("null")),
+            Token::Option(true) |
+            Token::StructNewType(_) |
+            Token::TypeTag(_) |
+            Token::EnumStart(_) => { }
+
+            Token::EnumUnit(_, variant) if self.config.unit_variants == UnitVariants::Strings => {
+                try!(write_str(&mut self.writer, variant));
+                try!(self.value_done());
+            }
+            Token::EnumUnit(_, variant) => {
+                try!(self.writer.write_all(b"{"));
+                try!(write_str(&mut self.writer, variant));
+                try!(self.writer.write_all(b":[]}"));
+                try!(self.value_done());
+            }
+            Token::EnumNewType(_, variant) => {
+                try!(self.writer.write_all(b"{"));
+                try!(write_str(&mut self.writer, variant));
+                try!(self.writer.write_all(b":"));
+                self.stack.push(Frame::NewtypeVariant);
+            }
+
+            Token::TupleStructStart(_, _) if self.config.tuple_structs == TupleStructs::Objects => {
+                try!(self.writer.write_all(b"{"));
+                self.stack.push(Frame::IndexedObject(0));
+            }
+            Token::SeqStart(_) |
+            Token::SeqArrayStart(_) |
+            Token::TupleStart(_) |
+            Token::TupleStructStart(_, _) => {
+                try!(self.writer.write_all(b"["));
+                self.stack.push(Frame::Array(true));
+            }
+            Token::MapStart(_) | Token::StructStart(_, _) => {
+                try!(self.writer.write_all(b"{"));
+                self.stack.push(Frame::Object(true));
+            }
+            Token::EnumSeqStart(_, variant, _) => {
+                try!(self.writer.write_all(b"{"));
+                try!(write_str(&mut self.writer, variant));
+                try!(self.writer.write_all(b":["));
+                self.stack.push(Frame::Variant(false, true));
+            }
+            Token::EnumMapStart(_, variant, _) => {
+                try!(self.writer.write_all(b"{"));
+                try!(write_str(&mut self.writer, variant));
+                try!(self.writer.write_all(b":{"));
+                self.stack.push(Frame::Variant(true, true));
+            }
+
+            Token::SeqSep | Token::TupleSep | Token::TupleStructSep | Token::EnumSeqSep => {
+                try!(self.separator(&token));
+            }
+            Token::MapSep => {
+                try!(self.separator(&token));
+                self.state = State::MapKey;
+            }
+            Token::StructSep | Token::EnumMapSep => {
+                try!(self.separator(&token));
+                self.state = State::FieldKey;
+            }
+
+            Token::SeqEnd |
+            Token::TupleEnd |
+            Token::TupleStructEnd |
+            Token::EnumSeqEnd => {
+                match self.stack.pop() {
+                    Some(Frame::Array(_)) => try!(self.writer.write_all(b"]")),
+                    Some(Frame::IndexedObject(_)) if token == Token::TupleStructEnd => {
+                        try!(self.writer.write_all(b"}"))
+                    }
+                    Some(Frame::Variant(false, _)) => try!(self.writer.write_all(b"]}")),
+                    _ => return Err(unexpected(&token)),
+                }
+                try!(self.value_done());
+            }
+            Token::MapEnd | Token::StructEnd | Token::EnumMapEnd => {
+                match self.stack.pop() {
+                    Some(Frame::Object(_)) => try!(self.writer.write_all(b"}")),
+                    Some(Frame::Variant(true, _)) => try!(self.writer.write_all(b"}}")),
+                    _ => return Err(unexpected(&token)),
+                }
+                try!(self.value_done());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn scalar(&mut self, text: &str) -> Result<(), Error> {
+        try!(self.writer.write_all(text.as_bytes()));
+        self.value_done()
+    }
+
+    /// Formats an `f32` or `f64` in its own shortest form, so that an `F32` isn't written with
+    /// the digits of the `f64` it widens to.
+    fn fmt_float<F>(&self, v: F) -> String
+        where F: Copy + Into<f64> + fmt::Debug + fmt::Display + str::FromStr,
+              F::Err: fmt::Debug,
+    {
+        match self.config.float_precision {
+            // Formatting with a precision rounds the exact binary value correctly, which
+            // multiplying by a power of ten wouldn't.
+            Some(precision) if v.into().is_finite() => {
+                let rounded = format!("{:.*}", precision, v);
+                fmt_number(rounded.parse::<F>().expect("a formatted float parses"))
+            }
+            _ => fmt_number(v),
+        }
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let text = encoding::encode(bytes, self.config.bytes_encoding);
+        try!(self.writer.write_all(b"\""));
+        try!(self.writer.write_all(text.as_bytes()));
+        try!(self.writer.write_all(b"\""));
+        self.value_done()
+    }
+
+    fn write_key(&mut self, token: Token) -> Result<(), Error> {
+        let key = match token {
+            Token::Option(true) | Token::StructNewType(_) | Token::TypeTag(_) => return Ok(()),
+            Token::Str(v) => v.to_owned(),
+            Token::String(v) => v,
+            Token::Char(v) => v.to_string(),
+            Token::Bool(v) => v.to_string(),
+            Token::Isize(v) => v.to_string(),
+            Token::I8(v) => v.to_string(),
+            Token::I16(v) => v.to_string(),
+            Token::I32(v) => v.to_string(),
+            Token::I64(v) => v.to_string(),
+            Token::Usize(v) => v.to_string(),
+            Token::U8(v) => v.to_string(),
+            Token::U16(v) => v.to_string(),
+            Token::U32(v) => v.to_string(),
+            Token::U64(v) => v.to_string(),
+            Token::F32(v) => self.fmt_float(v),
+            Token::F64(v) => self.fmt_float(v),
+            Token::BigNum(v) => v,
+            Token::Bytes(v) => encoding::encode(v, self.config.bytes_encoding),
+            Token::ByteBuf(ref v) => encoding::encode(v, self.config.bytes_encoding),
+            Token::EnumUnit(_, variant) => variant.to_owned(),
+            Token::Timestamp(secs, nanos) => {
+                match self.config.timestamp_format.render(secs, nanos) {
+                    Rendered::Integer(v) => v.to_string(),
+                    Rendered::Text(v) => v,
+                }
+            }
+            _ => return Err(Error::KeyMustBeAString),
+        };
+
+        self.state = State::Value;
+        try!(write_str(&mut self.writer, &key));
+        try!(self.writer.write_all(b":"));
+        Ok(())
+    }
+
+    /// Writes a comma unless this is the first element of the innermost container, and the key
+    /// of the element if the container is a tuple struct written as an object.
+    fn separator(&mut self, token: &Token) -> Result<(), Error> {
+        if let Some(&mut Frame::IndexedObject(ref mut index)) = self.stack.last_mut() {
+            if *token != Token::TupleStructSep {
+                return Err(unexpected(token));
+            }
+            if *index != 0 {
+                try!(self.writer.write_all(b","));
+            }
+            try!(write!(self.writer, "\"{}\":", index));
+            *index += 1;
+            return Ok(());
+        }
+
+        let first = match self.stack.last_mut() {
+            Some(&mut Frame::Array(ref mut first)) |
+            Some(&mut Frame::Object(ref mut first)) |
+            Some(&mut Frame::Variant(_, ref mut first)) => {
+                let was_first = *first;
+                *first = false;
+                was_first
+            }
+            _ => return Err(unexpected(token)),
+        };
+
+        if !first {
+            try!(self.writer.write_all(b","));
+        }
+        Ok(())
+    }
+
+    /// Closes the newtype variants whose value was just completed.
+    fn value_done(&mut self) -> Result<(), Error> {
+        while self.stack.last() == Some(&Frame::NewtypeVariant) {
+            self.stack.pop();
+            try!(self.writer.write_all(b"}"));
+        }
+        Ok(())
+    }
+}
+
+/// Lets a `token::Serializer` write straight into a `Writer`, without recording the tokens.
+impl<W> TokenSink for Writer<W>
+    where W: io::Write,
+{
+    fn push(&mut self, token: Token<'static>) -> Result<(), super::Error> {
+        self.write_token(token).map_err(|err| {
+            match err {
+                Error::Value(err) => err,
+                err => super::Error::Custom(err.to_string()),
+            }
+        })
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A JSON writer that can resume after the underlying writer failed, without writing any byte
+/// twice.
+///
+/// `write_json` reads tokens from the stream until it is exhausted or writing fails. Every token
+/// it reads counts towards `tokens_written`, and the bytes of a token that the writer didn't
+/// accept are kept, along with the open containers and whether a comma or colon is due. After an
+/// `Error::Io`, call `write_json` again with a new writer and the stream without its first
+/// `tokens_written` tokens; the bytes the writer received in both calls then add up to the
+/// output of an uninterrupted run.
+///
+/// ```rust
+/// use serde::token::{self, json};
+///
+/// let tokens = token::to_tokens(&vec![1, 2, 3]).unwrap();
+/// let mut state = json::StreamState::new();
+/// let mut first = Vec::new();
+/// state.write_json(tokens.iter().cloned().take(5), &mut first).unwrap();
+/// let mut second = Vec::new();
+/// state.write_json(tokens.iter().cloned().skip(state.tokens_written()), &mut second).unwrap();
+/// assert_eq!((&*first, &*second), (&b"[1,2"[..], &b",3]"[..]));
+/// ```
+pub struct StreamState {
+    /// Renders each token before it is written, keeping the bytes the writer hasn't accepted.
+    renderer: Writer<Vec<u8>>,
+    tokens: usize,
+    bytes: u64,
+}
+
+impl StreamState {
+    /// Construct a new `StreamState` at the start of a token stream.
+    pub fn new() -> Self {
+        StreamState {
+            renderer: Writer::new(Vec::new()),
+            tokens: 0,
+            bytes: 0,
+        }
+    }
+
+    /// Sets how `Timestamp` tokens are written, like `Writer::timestamp_format`.
+    pub fn timestamp_format(mut self, format: TimestampFormat) -> Self {
+        self.renderer = self.renderer.timestamp_format(format);
+        self
+    }
+
+    /// Sets how `Bytes` and `ByteBuf` tokens are encoded, like `Writer::bytes_encoding`.
+    pub fn bytes_encoding(mut self, encoding: BytesEncoding) -> Self {
+        self.renderer = self.renderer.bytes_encoding(encoding);
+        self
+    }
+
+    /// Returns the number of tokens read so far, which a resumed stream has to skip.
+    pub fn tokens_written(&self) -> usize {
+        self.tokens
+    }
+
+    /// Returns the number of bytes the writers have accepted so far.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Returns the number of containers that have been opened but not closed yet.
+    pub fn depth(&self) -> usize {
+        self.renderer.depth()
+    }
+
+    /// Writes the bytes left over from the last call, followed by the values in `iter`, into
+    /// `writer`.
+    pub fn write_json<'a, I, W>(&mut self, iter: I, mut writer: W) -> Result<(), Error>
+        where I: IntoIterator<Item=Token<'a>>,
+              W: io::Write,
+    {
+        try!(self.flush(&mut writer));
+        for token in iter {
+            try!(self.renderer.write_token(token));
+            self.tokens += 1;
+            try!(self.flush(&mut writer));
+        }
+        Ok(())
+    }
+
+    /// Writes the rendered bytes into `writer`, dropping each byte as soon as it is accepted.
+    fn flush<W>(&mut self, writer: &mut W) -> Result<(), Error>
+        where W: io::Write,
+    {
+        let pending = self.renderer.get_mut();
+        while !pending.is_empty() {
+            match writer.write(pending) {
+                Ok(0) => {
+                    return Err(Error::Io(io::Error::new(io::ErrorKind::WriteZero,
+                                                        "failed to write whole buffer")));
+                }
+                Ok(n) => {
+                    *pending = pending.split_off(n);
+                    self.bytes += n as u64;
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => { }
+                Err(err) => return Err(Error::Io(err)),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn unexpected(token: &Token) -> Error {
+    Error::UnexpectedToken(format!("{:?}", token))
+}
+
+/// Formats a float so that it reads back as the same value. JSON has no representation for NaN
+/// and the infinities, so they are written as `null`.
+fn fmt_number<F>(v: F) -> String
+    where F: Copy + Into<f64> + fmt::Debug,
+{
+    let wide: f64 = v.into();
+    if wide.is_nan() || wide.is_infinite() {
+        String::from("null")
+    } else {
+        format!("{:?}", v)
+    }
+}
+
+fn write_str<W>(writer: &mut W, value: &str) -> Result<(), Error>
+    where W: io::Write,
+{
+    try!(writer.write_all(b"\""));
+
+    let bytes = value.as_bytes();
+    let mut start = 0;
+
+    for (i, &byte) in bytes.iter().enumerate() {
+        let escape = match byte {
+            b'"' => "\\\"",
+            b'\\' => "\\\\",
+            b'\n' => "\\n",
+            b'\r' => "\\r",
+            b'\t' => "\\t",
+            0x08 => "\\b",
+            0x0c => "\\f",
+            0x00 ... 0x1f => "",
+            _ => continue,
+        };
+
+        if start < i {
+            try!(writer.write_all(&bytes[start..i]));
+        }
+
+        if escape.is_empty() {
+            try!(write!(writer, "\\u{:04x}", byte));
+        } else {
+            try!(writer.write_all(escape.as_bytes()));
+        }
+
+        start = i + 1;
+    }
+
+    if start < bytes.len() {
+        try!(writer.write_all(&bytes[start..]));
+    }
+
+    try!(writer.write_all(b"\""));
+    Ok(())
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Extensions to strict JSON that `from_str_with` accepts, all of them off by default, and how
+/// deeply arrays and objects may nest, 128 levels by default.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParserConfig {
+    /// Allow `//` comments up to the end of the line and `/* */` block comments wherever
+    /// whitespace is allowed.
+    pub allow_comments: bool,
+    /// Allow a comma after the last element of an array or the last entry of an object.
+    pub allow_trailing_comma: bool,
+    /// Allow the literals `NaN`, `Infinity` and `-Infinity`, read as `F64` tokens.
+    pub allow_nan_inf: bool,
+    /// What to do with integers too large for an `i64` or `u64`.
+    pub big_ints: BigInts,
+    /// How many levels deep arrays and objects may nest. Deeper input is a syntax error.
+    pub max_depth: usize,
+}
+
+impl Default for ParserConfig {
+    fn default() -> Self {
+        ParserConfig {
+            allow_comments: false,
+            allow_trailing_comma: false,
+            allow_nan_inf: false,
+            big_ints: BigInts::default(),
+            max_depth: 128,
+        }
+    }
+}
+
+/// How the JSON reader handles integers that don't fit into an `i64` or `u64`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BigInts {
+    /// Read them as the nearest `F64`, losing precision. This is the default.
+    Float,
+    /// Fail with a syntax error.
+    Error,
+    /// Read them as `BigNum` tokens holding their digits, which the writer writes back as the
+    /// same number.
+    BigNum,
+}
+
+impl Default for BigInts {
+    fn default() -> Self {
+        BigInts::Float
+    }
+}
+
+/// Reads the single JSON value in `input` as a token stream.
+pub fn from_str(input: &str) -> Result<Vec<Token>, Error> {
+    from_str_with(input, ParserConfig::default())
+}
+
+/// Reads the single JSON value in `input` as a token stream, accepting the extensions enabled
+/// in `config`.
+///
+/// ```rust
+/// use serde::token::{self, json};
+/// use serde::token::json::ParserConfig;
+///
+/// let input = "[1, 2, /* three */ 3,] // done";
+/// assert!(json::from_str(input).is_err());
+///
+/// let config = ParserConfig {
+///     allow_comments: true,
+///     allow_trailing_comma: true,
+///     ..ParserConfig::default()
+/// };
+/// let tokens = json::from_str_with(input, config).unwrap();
+/// assert_eq!(token::to_json(tokens).unwrap(), "[1,2,3]");
+/// ```
+pub fn from_str_with(input: &str, config: ParserConfig) -> Result<Vec<Token>, Error> {
+    let mut parser = Parser {
+        text: input,
+        input: input.as_bytes(),
+        pos: 0,
+        config: config,
+        tokens: Vec::new(),
+    };
+
+    try!(parser.parse_value(0));
+    try!(parser.skip_whitespace());
+    if parser.pos != parser.input.len() {
+        return Err(parser.error("trailing characters"));
+    }
+
+    Ok(parser.tokens)
+}
+
+/// Serializes `value` as JSON. JSON is human readable, so types with a readable form, like
+/// `SystemTime`, are written in it.
+///
+/// ```rust
+/// use std::time::{Duration, UNIX_EPOCH};
+/// use serde::token::json;
+///
+/// let time = UNIX_EPOCH + Duration::new(86_400, 500_000_000);
+/// assert_eq!(json::serialize(&time).unwrap(), r#""1970-01-02T00:00:00.500Z""#);
+/// ```
+pub fn serialize<T: ?Sized>(value: &T) -> Result<String, Error>
+    where T: Serialize,
+{
+    let mut serializer = super::Serializer::new();
+    try!(value.serialize(&mut serializer).map_err(Error::Value));
+    to_json(serializer.into_tokens())
+}
+
+/// Deserializes the single JSON value in `input` into a `T`.
+///
+/// Strings without escapes are borrowed from `input`. JSON object keys are always strings, so
+/// they are parsed into numbers, booleans and characters where `T` asks for them.
+///
+/// ```rust
+/// use std::collections::BTreeMap;
+/// use serde::token::json;
+///
+/// let map: BTreeMap<u32, &str> = json::deserialize(r#"{"1": "one", "2": "two"}"#).unwrap();
+/// assert_eq!(map[&2], "two");
+/// ```
+pub fn deserialize<'a, T>(input: &'a str) -> Result<T, Error>
+    where T: DeserializeBorrowed<'a>,
+{
+    deserialize_tokens(try!(from_str(input)))
+}
+
+fn deserialize_tokens<'a, T>(tokens: Vec<Token<'a>>) -> Result<T, Error>
+    where T: DeserializeBorrowed<'a>,
+{
+    let mut deserializer = borrowed::Deserializer::new(tokens.into_iter()).coerce_keys(true);
+    let value = try!(T::deserialize_borrowed(&mut deserializer).map_err(Error::Value));
+    try!(deserializer.end().map_err(Error::Value));
+    Ok(value)
+}
+
+/// An iterator over the JSON values in a string, separated by whitespace or simply concatenated,
+/// as in newline delimited JSON logs. Every value is yielded as its own token stream.
+///
+/// Errors are wrapped in `Error::Document` with the index of the document they are in, and end
+/// the iteration unless `Stream::skip_invalid` is set. Line and column numbers count from the
+/// start of the whole input.
+///
+/// ```rust
+/// use serde::token::{self, json};
+///
+/// let documents: Vec<_> = json::Stream::new("{\"a\": 1}\n[2] 3").collect();
+/// assert_eq!(documents.len(), 3);
+/// assert_eq!(token::to_json(documents[1].as_ref().unwrap().clone()).unwrap(), "[2]");
+/// ```
+pub struct Stream<'a> {
+    text: &'a str,
+    pos: usize,
+    /// The position of the last document.
+    offset: usize,
+    config: ParserConfig,
+    /// The index of the next document.
+    index: usize,
+    skip_invalid: bool,
+    done: bool,
+}
+
+impl<'a> Stream<'a> {
+    /// Reads the values in `input` as strict JSON.
+    pub fn new(input: &'a str) -> Self {
+        Stream {
+            text: input,
+            pos: 0,
+            offset: 0,
+            config: ParserConfig::default(),
+            index: 0,
+            skip_invalid: false,
+            done: false,
+        }
+    }
+
+    /// Accepts the extensions enabled in `config`.
+    pub fn config(mut self, config: ParserConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Keeps going after a document that fails to parse, skipping to the end of the value it
+    /// starts: past the bracket that closes its first bracket, or past the next whitespace when
+    /// it doesn't start with one. Strings are assumed to end at the end of their line at the
+    /// latest.
+    pub fn skip_invalid(mut self) -> Self {
+        self.skip_invalid = true;
+        self
+    }
+
+    /// Returns the byte offset at which the last document that was yielded starts, or 0 before
+    /// the first one.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns an iterator that yields every document together with its byte offset, so that an
+    /// index of the offsets can be used to parse a single document later. `ReadStream::scan`
+    /// finds the offsets in inputs too large to hold in memory, without parsing the documents.
+    ///
+    /// ```rust
+    /// use serde::token::json;
+    ///
+    /// let input = "{\"id\": 1}\n{\"id\": 2}\n";
+    /// let offsets: Vec<_> = json::Stream::new(input).scan().map(|r| r.unwrap().0).collect();
+    /// assert_eq!(offsets, vec![0, 10]);
+    /// let second = json::Stream::new(&input[offsets[1]..]).next().unwrap().unwrap();
+    /// assert_eq!(json::to_json(second).unwrap(), r#"{"id":2}"#);
+    /// ```
+    pub fn scan(self) -> Scan<'a> {
+        Scan {
+            stream: self,
+        }
+    }
+
+    /// Returns an iterator that deserializes every document into a `T`, the way `deserialize`
+    /// does.
+    ///
+    /// ```rust
+    /// use serde::token::json;
+    ///
+    /// let values: Vec<Vec<u32>> = json::Stream::new("[1]\n[2, 3]\n")
+    ///     .deserialize()
+    ///     .map(Result::unwrap)
+    ///     .collect();
+    /// assert_eq!(values, vec![vec![1], vec![2, 3]]);
+    /// ```
+    pub fn deserialize<T>(self) -> Values<Self, T>
+        where T: DeserializeBorrowed<'a>,
+    {
+        Values::new(self)
+    }
+}
+
+/// Iterator returned by `Stream::scan`.
+pub struct Scan<'a> {
+    stream: Stream<'a>,
+}
+
+impl<'a> Iterator for Scan<'a> {
+    type Item = Result<(usize, Vec<Token<'a>>), Error>;
+
+    fn next(&mut self) -> Option<Result<(usize, Vec<Token<'a>>), Error>> {
+        self.stream.next().map(|document| {
+            document.map(|tokens| (self.stream.offset, tokens))
+        })
+    }
+}
+
+impl<'a> Iterator for Stream<'a> {
+    type Item = Result<Vec<Token<'a>>, Error>;
+
+    fn next(&mut self) -> Option<Result<Vec<Token<'a>>, Error>> {
+        if self.done {
+            return None;
+        }
+
+        let mut parser = Parser {
+            text: self.text,
+            input: self.text.as_bytes(),
+            pos: self.pos,
+            config: self.config,
+            tokens: Vec::new(),
+        };
+        let index = self.index;
+        let result = parser.skip_whitespace().and_then(|()| {
+            if parser.pos == parser.input.len() {
+                Ok(false)
+            } else {
+                // Where the recovery after an error starts.
+                self.pos = parser.pos;
+                self.offset = parser.pos;
+                parser.parse_value(0).map(|()| true)
+            }
+        });
+
+        match result {
+            Ok(false) => {
+                self.done = true;
+                None
+            }
+            Ok(true) => {
+                self.pos = parser.pos;
+                self.index += 1;
+                Some(Ok(parser.tokens))
+            }
+            Err(err) => {
+                if self.skip_invalid && self.pos < self.text.len() {
+                    self.pos = skip_value(self.text.as_bytes(), self.pos);
+                } else {
+                    self.done = true;
+                }
+                self.index += 1;
+                Some(Err(Error::Document(index, Box::new(err))))
+            }
+        }
+    }
+}
+
+/// How many bytes `ReadStream` reads at once.
+const CHUNK: usize = 8 * 1024;
+
+/// An iterator over the JSON values read from an `io::Read`, like `Stream` but without holding
+/// the whole input in memory. Every value is yielded as its own token stream, with owned strings.
+///
+/// The end of a value is found by balancing its brackets and strings, and only then the value
+/// is parsed. Numbers and literals end at whitespace or at a bracket, quote, comma or colon, so
+/// two of them in a row have to be separated by whitespace. Errors are reported like the errors
+/// of `Stream`, and `ReadStream::skip_invalid` continues after the end of the invalid value.
+/// Failing to read from the reader ends the iteration with an `Error::Io`.
+///
+/// ```rust
+/// use serde::token::{self, json};
+///
+/// let input: &[u8] = b"{\"a\": 1}\n[2] 3";
+/// let documents: Vec<_> = json::ReadStream::new(input).map(Result::unwrap).collect();
+/// assert_eq!(documents.len(), 3);
+/// assert_eq!(token::to_json(documents[1].clone()).unwrap(), "[2]");
+/// ```
+pub struct ReadStream<R> {
+    reader: R,
+    /// The bytes read from `reader` that weren't consumed yet.
+    buf: Vec<u8>,
+    /// Where `fill` reads to, so that short reads don't clear a whole chunk of `buf` each time.
+    chunk: Vec<u8>,
+    eof: bool,
+    /// The byte offset, line and column of `buf[0]` in the input.
+    consumed: usize,
+    line: usize,
+    column: usize,
+    /// The position of the last document.
+    offset: usize,
+    config: ParserConfig,
+    /// The index of the next document.
+    index: usize,
+    skip_invalid: bool,
+    done: bool,
+}
+
+impl<R> ReadStream<R>
+    where R: io::Read,
+{
+    /// Reads the values in `reader` as strict JSON.
+    pub fn new(reader: R) -> Self {
+        ReadStream {
+            reader: reader,
+            buf: Vec::new(),
+            chunk: vec![0; CHUNK],
+            eof: false,
+            consumed: 0,
+            line: 1,
+            column: 1,
+            offset: 0,
+            config: ParserConfig::default(),
+            index: 0,
+            skip_invalid: false,
+            done: false,
+        }
+    }
+
+    /// Accepts the extensions enabled in `config`.
+    pub fn config(mut self, config: ParserConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Keeps going after a document that fails to parse, with the document after it.
+    pub fn skip_invalid(mut self) -> Self {
+        self.skip_invalid = true;
+        self
+    }
+
+    /// Returns the byte offset at which the last document that was yielded starts, or 0 before
+    /// the first one.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns an iterator over the byte offsets at which the documents start. Documents are
+    /// skipped by balancing their brackets and strings, without parsing them or producing any
+    /// tokens, so only their syntax errors go unnoticed. Seeking to an offset and reading from
+    /// there parses a single document.
+    ///
+    /// ```rust
+    /// use std::io::{Cursor, Seek, SeekFrom};
+    /// use serde::token::json;
+    ///
+    /// let mut input = Cursor::new(b"{\"id\": 1}\n{\"id\": 2}\n".to_vec());
+    /// let offsets: Vec<_> = json::ReadStream::new(&mut input)
+    ///     .scan()
+    ///     .map(Result::unwrap)
+    ///     .collect();
+    /// assert_eq!(offsets, vec![0, 10]);
+    ///
+    /// input.seek(SeekFrom::Start(offsets[1] as u64)).unwrap();
+    /// let second = json::ReadStream::new(input).next().unwrap().unwrap();
+    /// assert_eq!(json::to_json(second).unwrap(), r#"{"id":2}"#);
+    /// ```
+    pub fn scan(self) -> ReadScan<R> {
+        ReadScan {
+            stream: self,
+        }
+    }
+
+    /// Returns an iterator that deserializes every document into a `T`, the way `deserialize`
+    /// does.
+    ///
+    /// ```rust
+    /// use std::collections::BTreeMap;
+    /// use serde::token::json;
+    ///
+    /// let input: &[u8] = b"{\"id\": 1}\n{\"id\": 2}\n";
+    /// let ids: Vec<u32> = json::ReadStream::new(input)
+    ///     .deserialize::<BTreeMap<String, u32>>()
+    ///     .map(|record| record.unwrap()["id"])
+    ///     .collect();
+    /// assert_eq!(ids, vec![1, 2]);
+    /// ```
+    pub fn deserialize<T>(self) -> Values<Self, T>
+        where T: DeserializeBorrowed<'static>,
+    {
+        Values::new(self)
+    }
+
+    /// Reads more of the input into `buf`, returning false at the end of the input.
+    fn fill(&mut self) -> io::Result<bool> {
+        if self.eof {
+            return Ok(false);
+        }
+        loop {
+            match self.reader.read(&mut self.chunk) {
+                Ok(read) => {
+                    self.buf.extend(self.chunk[..read].iter().cloned());
+                    self.eof = read == 0;
+                    return Ok(read > 0);
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => { }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Drops the first `len` bytes of `buf`, keeping track of where in the input it starts.
+    fn consume(&mut self, len: usize) {
+        for &byte in &self.buf[..len] {
+            if byte == b'\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+        self.consumed += len;
+        self.buf = self.buf.split_off(len);
+    }
+
+    /// Finds the next value, skipping the whitespace and comments before it, and returns where
+    /// it starts and ends in `buf`. Only brackets and strings are looked at, so that the value
+    /// can be skipped without parsing it. A value cut off by the end of the input ends there.
+    fn frame(&mut self) -> io::Result<Option<(usize, usize)>> {
+        let mut start = 0;
+        loop {
+            if start == self.buf.len() && !try!(self.fill()) {
+                return Ok(None);
+            }
+            match self.buf[start] {
+                b' ' | b'\t' | b'\n' | b'\r' => start += 1,
+                b'/' if self.config.allow_comments => {
+                    match try!(self.comment(start)) {
+                        Some(end) => start = end,
+                        None => break,
+                    }
+                }
+                _ => break,
+            }
+        }
+
+        let scalar = match self.buf[start] {
+            b'[' | b'{' | b'"' => false,
+            _ => true,
+        };
+        let mut end = start;
+        let mut depth = 0usize;
+        let mut in_string = false;
+        let mut escaped = false;
+        loop {
+            if end == self.buf.len() && !try!(self.fill()) {
+                return Ok(Some((start, end)));
+            }
+            let byte = self.buf[end];
+            if scalar {
+                match byte {
+                    b' ' | b'\t' | b'\n' | b'\r' | b'[' | b']' | b'{' | b'}' | b'"' | b',' |
+                    b':' if end > start => return Ok(Some((start, end))),
+                    b'/' if end > start && self.config.allow_comments => {
+                        return Ok(Some((start, end)));
+                    }
+                    _ => end += 1,
+                }
+                continue;
+            }
+
+            end += 1;
+            if in_string {
+                match byte {
+                    _ if escaped => escaped = false,
+                    b'\\' => escaped = true,
+                    // Strings are assumed to end at the end of their line at the latest.
+                    b'"' | b'\n' => in_string = false,
+                    _ => { }
+                }
+            } else {
+                match byte {
+                    b'"' => in_string = true,
+                    b'[' | b'{' => depth += 1,
+                    b']' | b'}' => depth = depth.saturating_sub(1),
+                    b'/' if self.config.allow_comments => {
+                        if let Some(after) = try!(self.comment(end - 1)) {
+                            end = after;
+                        }
+                    }
+                    _ => { }
+                }
+            }
+            if depth == 0 && !in_string {
+                return Ok(Some((start, end)));
+            }
+        }
+    }
+
+    /// Returns the position after the comment that starts at `buf[start]`, or `None` if no
+    /// comment starts there or it isn't closed.
+    fn comment(&mut self, start: usize) -> io::Result<Option<usize>> {
+        let mut end = start + 1;
+        if end == self.buf.len() && !try!(self.fill()) {
+            return Ok(None);
+        }
+        let block = match self.buf[end] {
+            b'/' => false,
+            b'*' => true,
+            _ => return Ok(None),
+        };
+        end += 1;
+        loop {
+            if end == self.buf.len() && !try!(self.fill()) {
+                return Ok(if block { None } else { Some(end) });
+            }
+            end += 1;
+            match self.buf[end - 1] {
+                b'\n' if !block => return Ok(Some(end)),
+                b'/' if block && self.buf[end - 2] == b'*' && end - 2 > start + 1 => {
+                    return Ok(Some(end));
+                }
+                _ => { }
+            }
+        }
+    }
+
+    /// Parses the value in the first `len` bytes of `buf`.
+    fn parse(&self, len: usize) -> Result<Vec<Token<'static>>, Error> {
+        let input = &self.buf[..len];
+        let text = match str::from_utf8(input) {
+            Ok(text) => text,
+            Err(err) => {
+                let pos = err.valid_up_to();
+                let (line, column) = line_column(input, pos);
+                let snippet = Snippet::new(input, pos);
+                return Err(self.relocate(Error::Syntax("invalid UTF-8".to_owned(),
+                                                       line,
+                                                       column,
+                                                       snippet)));
+            }
+        };
+
+        let mut parser = Parser {
+            text: text,
+            input: input,
+            pos: 0,
+            config: self.config,
+            tokens: Vec::new(),
+        };
+        let result = parser.parse_value(0)
+            .and_then(|()| parser.skip_whitespace())
+            .and_then(|()| {
+                if parser.pos == len {
+                    Ok(())
+                } else {
+                    Err(parser.error("trailing characters"))
+                }
+            });
+        match result {
+            Ok(()) => Ok(parser.tokens.into_iter().map(into_owned).collect()),
+            Err(err) => Err(self.relocate(err)),
+        }
+    }
+
+    /// Moves the position of an error in the value at the start of `buf` to its position in
+    /// the whole input.
+    fn relocate(&self, err: Error) -> Error {
+        match err {
+            Error::Syntax(msg, line, column, snippet) => {
+                let column = if line == 1 { self.column + column - 1 } else { column };
+                Error::Syntax(msg, self.line + line - 1, column, snippet.shift_lines(self.line - 1))
+            }
+            err => err,
+        }
+    }
+}
+
+impl<R> Iterator for ReadStream<R>
+    where R: io::Read,
+{
+    type Item = Result<Vec<Token<'static>>, Error>;
+
+    fn next(&mut self) -> Option<Result<Vec<Token<'static>>, Error>> {
+        if self.done {
+            return None;
+        }
+
+        let (start, end) = match self.frame() {
+            Ok(Some(frame)) => frame,
+            Ok(None) => {
+                self.done = true;
+                return None;
+            }
+            Err(err) => {
+                self.done = true;
+                return Some(Err(Error::Io(err)));
+            }
+        };
+        self.consume(start);
+        self.offset = self.consumed;
+        let result = self.parse(end - start);
+        self.consume(end - start);
+
+        let index = self.index;
+        self.index += 1;
+        match result {
+            Ok(tokens) => Some(Ok(tokens)),
+            Err(err) => {
+                self.done = !self.skip_invalid;
+                Some(Err(Error::Document(index, Box::new(err))))
+            }
+        }
+    }
+}
+
+/// Iterator returned by `ReadStream::scan`. Failing to read from the reader ends the iteration
+/// with an `Error::Io`.
+pub struct ReadScan<R> {
+    stream: ReadStream<R>,
+}
+
+impl<R> Iterator for ReadScan<R>
+    where R: io::Read,
+{
+    type Item = Result<usize, Error>;
+
+    fn next(&mut self) -> Option<Result<usize, Error>> {
+        let stream = &mut self.stream;
+        if stream.done {
+            return None;
+        }
+
+        match stream.frame() {
+            Ok(Some((start, end))) => {
+                stream.consume(start);
+                stream.offset = stream.consumed;
+                stream.consume(end - start);
+                Some(Ok(stream.offset))
+            }
+            Ok(None) => {
+                stream.done = true;
+                None
+            }
+            Err(err) => {
+                stream.done = true;
+                Some(Err(Error::Io(err)))
+            }
+        }
+    }
+}
+
+/// Iterator returned by `Stream::deserialize` and `ReadStream::deserialize`. Errors are wrapped
+/// in `Error::Document` with the index of the document they are in.
+pub struct Values<S, T> {
+    stream: S,
+    /// The index of the next document.
+    index: usize,
+    marker: PhantomData<T>,
+}
+
+impl<S, T> Values<S, T> {
+    fn new(stream: S) -> Self {
+        Values {
+            stream: stream,
+            index: 0,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, S, T> Iterator for Values<S, T>
+    where S: Iterator<Item=Result<Vec<Token<'a>>, Error>>,
+          T: DeserializeBorrowed<'a>,
+{
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Result<T, Error>> {
+        let index = self.index;
+        let document = match self.stream.next() {
+            Some(document) => document,
+            None => return None,
+        };
+        self.index += 1;
+        Some(document.and_then(|tokens| {
+            deserialize_tokens(tokens).map_err(|err| Error::Document(index, Box::new(err)))
+        }))
+    }
+}
+
+/// Returns the position after the value that starts at `pos`, as far as brackets and strings
+/// tell without parsing it.
+fn skip_value(input: &[u8], mut pos: usize) -> usize {
+    let mut depth = 0;
+    let mut in_string = false;
+    while pos < input.len() {
+        let byte = input[pos];
+        pos += 1;
+        if in_string {
+            match byte {
+                b'\\' => pos += 1,
+                b'"' | b'\n' => {
+                    in_string = false;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => { }
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'[' | b'{' => depth += 1,
+            b']' | b'}' => {
+                if depth > 0 {
+                    depth -= 1;
+                }
+                if depth == 0 {
+                    break;
+                }
+            }
+            b' ' | b'\t' | b'\r' | b'\n' if depth == 0 => break,
+            _ => { }
+        }
+    }
+    cmp::min(pos, input.len())
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    input: &'a [u8],
+    pos: usize,
+    config: ParserConfig,
+    tokens: Vec<Token<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn parse_value(&mut self, depth: usize) -> Result<(), Error> {
+        try!(self.skip_whitespace());
+
+        let token = match self.peek() {
+            Some(b'n') => { try!(self.parse_ident(b"null")); Token::Unit }
+            Some(b't') => { try!(self.parse_ident(b"true")); Token::Bool(true) }
+            Some(b'f') => { try!(self.parse_ident(b"false")); Token::Bool(false) }
+            Some(b'N') if self.config.allow_nan_inf => {
+                try!(self.parse_ident(b"NaN"));
+                Token::F64(f64::NAN)
+            }
+            Some(b'I') if self.config.allow_nan_inf => {
+                try!(self.parse_ident(b"Infinity"));
+                Token::F64(f64::INFINITY)
+            }
+            Some(b'-') | Some(b'0' ... b'9') => try!(self.parse_number()),
+            Some(b'"') => try!(self.parse_string()),
+            Some(b'[') => return self.parse_array(depth),
+            Some(b'{') => return self.parse_object(depth),
+            Some(_) => return Err(self.error("expected value")),
+            None => return Err(self.error("EOF while parsing a value")),
+        };
+
+        self.tokens.push(token);
+        Ok(())
+    }
+
+    fn parse_array(&mut self, depth: usize) -> Result<(), Error> {
+        if depth >= self.config.max_depth {
+            return Err(self.depth_error());
+        }
+        self.pos += 1;
+        self.tokens.push(Token::SeqStart(None));
+
+        try!(self.skip_whitespace());
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            self.tokens.push(Token::SeqEnd);
+            return Ok(());
+        }
+
+        loop {
+            self.tokens.push(Token::SeqSep);
+            try!(self.parse_value(depth + 1));
+
+            try!(self.skip_whitespace());
+            match self.next() {
+                Some(b',') => {
+                    try!(self.skip_whitespace());
+                    if self.config.allow_trailing_comma && self.peek() == Some(b']') {
+                        self.pos += 1;
+                        break;
+                    }
+                }
+                Some(b']') => break,
+                Some(_) => return Err(self.error_before("expected `,` or `]`")),
+                None => return Err(self.error("EOF while parsing a list")),
+            }
+        }
+
+        self.tokens.push(Token::SeqEnd);
+        Ok(())
+    }
+
+    fn parse_object(&mut self, depth: usize) -> Result<(), Error> {
+        if depth >= self.config.max_depth {
+            return Err(self.depth_error());
+        }
+        self.pos += 1;
+        self.tokens.push(Token::MapStart(None));
+
+        try!(self.skip_whitespace());
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            self.tokens.push(Token::MapEnd);
+            return Ok(());
+        }
+
+        loop {
+            try!(self.skip_whitespace());
+            match self.peek() {
+                Some(b'"') => { }
+                // Only reached after a comma, the empty object was handled above.
+                Some(b'}') if self.config.allow_trailing_comma => {
+                    self.pos += 1;
+                    break;
+                }
+                Some(_) => return Err(self.error("key must be a string")),
+                None => return Err(self.error("EOF while parsing an object")),
+            }
+
+            self.tokens.push(Token::MapSep);
+            let key = try!(self.parse_string());
+            self.tokens.push(key);
+
+            try!(self.skip_whitespace());
+            match self.next() {
+                Some(b':') => { }
+                Some(_) => return Err(self.error_before("expected `:`")),
+                None => return Err(self.error("EOF while parsing an object")),
+            }
+
+            try!(self.parse_value(depth + 1));
+
+            try!(self.skip_whitespace());
+            match self.next() {
+                Some(b',') => { }
+                Some(b'}') => break,
+                Some(_) => return Err(self.error_before("expected `,` or `}`")),
+                None => return Err(self.error("EOF while parsing an object")),
+            }
+        }
+
+        self.tokens.push(Token::MapEnd);
+        Ok(())
+    }
+
+    fn parse_ident(&mut self, ident: &[u8]) -> Result<(), Error> {
+        if self.input[self.pos..].starts_with(ident) {
+            self.pos += ident.len();
+            Ok(())
+        } else {
+            Err(self.error("expected value"))
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<Token<'static>, Error> {
+        let start = self.pos;
+        let mut float = false;
+
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+            if self.config.allow_nan_inf && self.peek() == Some(b'I') {
+                try!(self.parse_ident(b"Infinity"));
+                return Ok(Token::F64(f64::NEG_INFINITY));
+            }
+        }
+
+        match self.next() {
+            Some(b'0') => { }
+            Some(b'1' ... b'9') => self.skip_digits(),
+            _ => return Err(self.error_before("invalid number")),
+        }
+
+        if self.peek() == Some(b'.') {
+            float = true;
+            self.pos += 1;
+            if !self.eat_digits() {
+                return Err(self.error("invalid number"));
+            }
+        }
+
+        if let Some(b'e') | Some(b'E') = self.peek() {
+            float = true;
+            self.pos += 1;
+            if let Some(b'+') | Some(b'-') = self.peek() {
+                self.pos += 1;
+            }
+            if !self.eat_digits() {
+                return Err(self.error("invalid number"));
+            }
+        }
+
+        let text = str::from_utf8(&self.input[start..self.pos])
+            .expect("numbers are ASCII");
+
+        if !float {
+            if let Ok(v) = text.parse::<i64>() {
+                return Ok(Token::I64(v));
+            }
+            if let Ok(v) = text.parse::<u64>() {
+                return Ok(Token::U64(v));
+            }
+            match self.config.big_ints {
+                BigInts::Float => { }
+                BigInts::Error => {
+                    self.pos = start;
+                    return Err(self.error("integer out of range"));
+                }
+                BigInts::BigNum => return Ok(Token::BigNum(text.to_owned())),
+            }
+        }
+
+        match text.parse::<f64>() {
+            Ok(v) if v.is_finite() => Ok(Token::F64(v)),
+            _ => Err(self.error("number out of range")),
+        }
+    }
+
+    fn skip_digits(&mut self) {
+        while let Some(b'0' ... b'9') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn eat_digits(&mut self) -> bool {
+        let start = self.pos;
+        self.skip_digits();
+        self.pos != start
+    }
+
+    fn parse_string(&mut self) -> Result<Token<'a>, Error> {
+        self.pos += 1;
+        let start = self.pos;
+
+        // Bytes are only copied into `out` once the first escape shows up.
+        let mut out = Vec::new();
+        let mut copied = false;
+
+        loop {
+            match self.next() {
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    if !copied {
+                        out.extend(self.input[start..self.pos - 1].iter().cloned());
+                        copied = true;
+                    }
+
+                    let escaped = match self.next() {
+                        Some(b'"') => b'"',
+                        Some(b'\\') => b'\\',
+                        Some(b'/') => b'/',
+                        Some(b'b') => 0x08,
+                        Some(b'f') => 0x0c,
+                        Some(b'n') => b'\n',
+                        Some(b'r') => b'\r',
+                        Some(b't') => b'\t',
+                        Some(b'u') => {
+                            let c = try!(self.parse_unicode_escape());
+                            out.extend(::utils::encode_utf8(c).as_str().bytes());
+                            continue;
+                        }
+                        Some(_) => return Err(self.error_before("invalid escape")),
+                        None => return Err(self.error("EOF while parsing a string")),
+                    };
+                    out.push(escaped);
+                }
+                Some(0x00 ... 0x1f) => {
+                    return Err(self.error_before("control character in string"));
+                }
+                Some(byte) => {
+                    if copied {
+                        out.push(byte);
+                    }
+                }
+                None => return Err(self.error("EOF while parsing a string")),
+            }
+        }
+
+        if !copied {
+            return Ok(Token::Str(&self.text[start..self.pos - 1]));
+        }
+
+        // The input is a `str` and escapes always produce valid UTF-8.
+        Ok(Token::String(String::from_utf8(out).expect("JSON strings are valid UTF-8")))
+    }
+
+    fn parse_unicode_escape(&mut self) -> Result<char, Error> {
+        let first = try!(self.parse_hex4());
+
+        let code = match first {
+            0xd800 ... 0xdbff => {
+                if !self.input[self.pos..].starts_with(b"\\u") {
+                    return Err(self.error("lone leading surrogate in hex escape"));
+                }
+                self.pos += 2;
+
+                let second = try!(self.parse_hex4());
+                if second < 0xdc00 || second > 0xdfff {
+                    return Err(self.error("invalid trailing surrogate in hex escape"));
+                }
+                0x10000 + ((first - 0xd800) << 10) + (second - 0xdc00)
+            }
+            0xdc00 ... 0xdfff => return Err(self.error("lone trailing surrogate in hex escape")),
+            code => code,
+        };
+
+        char::from_u32(code).ok_or_else(|| self.error("invalid unicode code point"))
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, Error> {
+        let mut n = 0;
+        for _ in 0..4 {
+            let digit = match self.next() {
+                Some(c @ b'0' ... b'9') => c - b'0',
+                Some(c @ b'a' ... b'f') => c - b'a' + 10,
+                Some(c @ b'A' ... b'F') => c - b'A' + 10,
+                Some(_) => return Err(self.error_before("invalid hex escape")),
+                None => return Err(self.error("EOF while parsing a string")),
+            };
+            n = n * 16 + digit as u32;
+        }
+        Ok(n)
+    }
+
+    /// Skips whitespace, and comments if they are allowed.
+    fn skip_whitespace(&mut self) -> Result<(), Error> {
+        loop {
+            match self.peek() {
+                Some(b' ') | Some(b'\n') | Some(b'\t') | Some(b'\r') => self.pos += 1,
+                Some(b'/') if self.config.allow_comments => {
+                    if !try!(self.skip_comment()) {
+                        return Ok(());
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Skips the comment at the current position, returning false if there is none.
+    fn skip_comment(&mut self) -> Result<bool, Error> {
+        let rest = &self.input[self.pos..];
+        if rest.starts_with(b"//") {
+            match rest.iter().position(|&b| b == b'\n') {
+                Some(newline) => self.pos += newline + 1,
+                None => self.pos = self.input.len(),
+            }
+            Ok(true)
+        } else if rest.starts_with(b"/*") {
+            match rest[2..].windows(2).position(|w| w == b"*/") {
+                Some(end) => {
+                    self.pos += end + 4;
+                    Ok(true)
+                }
+                None => Err(self.error("EOF while parsing a comment")),
+            }
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).cloned()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek();
+        if byte.is_some() {
+            self.pos += 1;
+        }
+        byte
+    }
+
+    /// Returns a syntax error at the current position.
+    fn error(&self, msg: &str) -> Error {
+        let (line, column) = line_column(self.input, self.pos);
+        Error::Syntax(msg.to_owned(), line, column, Snippet::new(self.input, self.pos))
+    }
+
+    /// Returns a syntax error at the current position for nesting deeper than allowed.
+    fn depth_error(&self) -> Error {
+        self.error(&format!("nesting exceeds the depth limit of {}", self.config.max_depth))
+    }
+
+    /// Returns a syntax error at the byte that was just consumed.
+    fn error_before(&self, msg: &str) -> Error {
+        let (line, column) = line_column(self.input, self.pos - 1);
+        Error::Syntax(msg.to_owned(), line, column, Snippet::new(self.input, self.pos - 1))
+    }
+}
@@ -0,0 +1,175 @@
+//! Limit how many elements a sequence or map in a token stream holds.
+//!
+//! Consumers that collect a sequence or map usually reserve room for the length it announces,
+//! and keep growing the collection for as long as elements arrive. A stream read from untrusted
+//! input can exhaust the memory of such a consumer with a single huge length, or with a sequence
+//! of unknown length that never ends. `limit_size` stops such a stream before it reaches them.
+
+use std::error;
+use std::fmt;
+
+use super::Token;
+
+/// The error yielded by `LimitedTokens` when a sequence or map holds too many elements.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LimitError {
+    index: usize,
+    max_elems: usize,
+    declared: Option<usize>,
+}
+
+impl LimitError {
+    /// Returns the index of the token that starts the sequence or map with a length over the
+    /// limit, or of the separator of the element that is one too many.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the limit that was exceeded.
+    pub fn max_elems(&self) -> usize {
+        self.max_elems
+    }
+
+    /// Returns the length the sequence or map announced, or `None` if its announced length was
+    /// within the limit but it went on to hold more elements than that.
+    pub fn declared_len(&self) -> Option<usize> {
+        self.declared
+    }
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self.declared {
+            Some(len) => {
+                write!(formatter, "token #{}: length {} exceeds the maximum of {} elements",
+                       self.index, len, self.max_elems)
+            }
+            None => {
+                write!(formatter, "token #{}: more than the maximum of {} elements",
+                       self.index, self.max_elems)
+            }
+        }
+    }
+}
+
+impl error::Error for LimitError {
+    fn description(&self) -> &str {
+        "sequence or map has too many elements"
+    }
+}
+
+/// Returns an iterator that passes the tokens of `iter` through as long as no sequence or map
+/// holds more than `max_elems` elements, and yields an error in place of the token that breaks
+/// the limit. Iteration stops after the error.
+///
+/// A sequence or map breaks the limit as soon as it announces a longer length, before any of its
+/// elements are read. Otherwise its elements are counted as they pass, which also catches those
+/// of unknown length. Tuples, structs and enum variants have a length fixed by their type and
+/// aren't limited.
+///
+/// ```rust
+/// use serde::token::{self, Token};
+///
+/// let tokens = vec![
+///     Token::SeqStart(None),
+///     Token::SeqSep, Token::I32(1),
+///     Token::SeqSep, Token::I32(2),
+///     Token::SeqEnd,
+/// ];
+/// let result: Result<Vec<_>, _> = token::limit_size(tokens.clone(), 2).collect();
+/// assert_eq!(result.unwrap(), tokens);
+///
+/// let result: Result<Vec<_>, _> = token::limit_size(tokens, 1).collect();
+/// assert_eq!(result.unwrap_err().index(), 3);
+/// ```
+pub fn limit_size<'a, I>(iter: I, max_elems: usize) -> LimitedTokens<I::IntoIter>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    LimitedTokens {
+        iter: iter.into_iter(),
+        max_elems: max_elems,
+        counts: Vec::new(),
+        index: 0,
+        failed: false,
+    }
+}
+
+/// Iterator returned by `limit_size`.
+pub struct LimitedTokens<I> {
+    iter: I,
+    max_elems: usize,
+    /// The number of elements so far of every open compound value, or `None` for those that
+    /// aren't limited.
+    counts: Vec<Option<usize>>,
+    /// The index of the next token.
+    index: usize,
+    failed: bool,
+}
+
+impl<I> LimitedTokens<I> {
+    fn fail(&mut self, index: usize, declared: Option<usize>) -> LimitError {
+        self.failed = true;
+        LimitError {
+            index: index,
+            max_elems: self.max_elems,
+            declared: declared,
+        }
+    }
+}
+
+impl<'a, I> Iterator for LimitedTokens<I>
+    where I: Iterator<Item=Token<'a>>,
+{
+    type Item = Result<Token<'a>, LimitError>;
+
+    fn next(&mut self) -> Option<Result<Token<'a>, LimitError>> {
+        if self.failed {
+            return None;
+        }
+        let token = match self.iter.next() {
+            Some(token) => token,
+            None => return None,
+        };
+        let index = self.index;
+        self.index += 1;
+
+        match token {
+            Token::SeqStart(Some(len)) |
+            Token::SeqArrayStart(len) |
+            Token::MapStart(Some(len)) if len > self.max_elems => {
+                return Some(Err(self.fail(index, Some(len))));
+            }
+            Token::SeqStart(_) | Token::SeqArrayStart(_) | Token::MapStart(_) => {
+                self.counts.push(Some(0));
+            }
+            ref token if token.is_start() => self.counts.push(None),
+            ref token if token.is_end() => {
+                self.counts.pop();
+            }
+            ref token if token.is_sep() => {
+                let over = match self.counts.last_mut() {
+                    Some(&mut Some(ref mut count)) => {
+                        *count += 1;
+                        *count > self.max_elems
+                    }
+                    _ => false,
+                };
+                if over {
+                    return Some(Err(self.fail(index, None)));
+                }
+            }
+            _ => { }
+        }
+
+        Some(Ok(token))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.failed {
+            (0, Some(0))
+        } else {
+            let (_, upper) = self.iter.size_hint();
+            (0, upper)
+        }
+    }
+}
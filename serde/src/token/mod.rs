@@ -0,0 +1,285 @@
+//! A flat token representation of the serialization protocol.
+//!
+//! Every call a `Serialize` implementation makes on its `Serializer` corresponds to one or more
+//! `Token`s. Recording those calls with `to_tokens` produces a stream that can be inspected,
+//! rewritten or rendered without having to write a full `Serializer` for each task.
+//!
+//! The `Token` type and the recording `Serializer` live in `token::core`, which only needs
+//! `String` and `Vec`. Everything else, the readers, writers and checks for token streams,
+//! requires the `std` feature.
+//!
+//! Integer tokens keep the type of the value that produced them: an `i32` is recorded as `I32`
+//! and an `i64` as `I64`, so consumers have to accept every width. `isize` and `usize` are
+//! recorded as `Isize` and `Usize`, whose range depends on the target. Writers that store the
+//! type of an integer, like the binary format, replace them with the fixed-width token of the
+//! target's pointer width through `normalize_width`, so `Isize(5)` is written as `I64(5)` on a
+//! 64-bit target and as `I32(5)` on a 32-bit one. Writers that only store the value, like JSON
+//! and MessagePack, are unaffected. `normalize_numbers` goes further and maps every integer to
+//! `I64` or `U64`.
+
+pub mod core;
+#[cfg(feature = "std")]
+pub mod binary;
+#[cfg(feature = "std")]
+pub mod borrowed;
+#[cfg(feature = "std")]
+pub mod canon;
+#[cfg(feature = "std")]
+pub mod cbor;
+#[cfg(feature = "std")]
+pub mod columns;
+#[cfg(feature = "std")]
+pub mod compose;
+#[cfg(feature = "std")]
+pub mod context;
+#[cfg(feature = "std")]
+pub mod counted;
+#[cfg(feature = "std")]
+pub mod dedup;
+#[cfg(feature = "std")]
+pub mod depth;
+#[cfg(feature = "std")]
+pub mod diff;
+#[cfg(feature = "std")]
+pub mod dot;
+#[cfg(feature = "std")]
+pub mod encoding;
+#[cfg(feature = "std")]
+pub mod erased;
+#[cfg(feature = "std")]
+pub mod find;
+#[cfg(feature = "std")]
+pub mod flatten;
+#[cfg(feature = "std")]
+pub mod hash;
+#[cfg(feature = "std")]
+pub mod incremental;
+#[cfg(feature = "std")]
+pub mod int_range;
+#[cfg(feature = "std")]
+pub mod intern;
+#[cfg(feature = "std")]
+pub mod json;
+#[cfg(feature = "std")]
+pub mod limit;
+#[cfg(feature = "std")]
+pub mod msgpack;
+#[cfg(feature = "std")]
+pub mod normalize;
+#[cfg(feature = "std")]
+pub mod numbered;
+#[cfg(feature = "std")]
+pub mod order;
+#[cfg(feature = "std")]
+pub mod par;
+#[cfg(feature = "std")]
+pub mod range;
+#[cfg(feature = "std")]
+pub mod recount;
+#[cfg(feature = "std")]
+pub mod rename;
+#[cfg(feature = "std")]
+pub mod reshape;
+#[cfg(feature = "std")]
+pub mod schema;
+#[cfg(feature = "std")]
+pub mod shape;
+#[cfg(feature = "std")]
+pub mod skip;
+#[cfg(feature = "std")]
+pub mod snippet;
+#[cfg(feature = "std")]
+pub mod strings;
+#[cfg(feature = "std")]
+pub mod table;
+#[cfg(feature = "std")]
+pub mod tagging;
+#[cfg(feature = "std")]
+pub mod tee;
+#[cfg(feature = "std")]
+pub mod testing;
+#[cfg(feature = "std")]
+mod time;
+#[cfg(feature = "std")]
+pub mod toml;
+#[cfg(feature = "std")]
+pub mod validate;
+#[cfg(feature = "std")]
+pub mod value;
+#[cfg(feature = "std")]
+pub mod xml;
+#[cfg(feature = "std")]
+pub mod yaml;
+
+pub use self::core::{Error, Serializer, Token, TokenSink, entry_tokens, to_tokens};
+
+#[cfg(feature = "std")]
+pub use self::binary::{from_binary, to_binary};
+#[cfg(feature = "std")]
+pub use self::borrowed::{deserialize_into, from_tokens_borrowed};
+#[cfg(feature = "std")]
+pub use self::canon::{CanonError, canonicalize, is_canonical};
+#[cfg(feature = "std")]
+pub use self::cbor::{from_cbor, to_cbor};
+#[cfg(feature = "std")]
+pub use self::columns::to_columns;
+#[cfg(feature = "std")]
+pub use self::context::{ContextSerializer, Overrides};
+#[cfg(feature = "std")]
+pub use self::counted::{count_tokens, counted, counted_recorded};
+#[cfg(feature = "std")]
+pub use self::dedup::{DuplicateKeyPolicy, reject_dup_keys};
+#[cfg(feature = "std")]
+pub use self::depth::guard_depth;
+#[cfg(feature = "std")]
+pub use self::diff::{Diff, DiffKind, diff_tokens};
+#[cfg(feature = "std")]
+pub use self::dot::to_dot;
+#[cfg(feature = "std")]
+pub use self::encoding::BytesEncoding;
+#[cfg(feature = "std")]
+pub use self::find::find_field;
+#[cfg(feature = "std")]
+pub use self::flatten::{flatten_keys, redact, to_path_index};
+#[cfg(feature = "std")]
+pub use self::incremental::Incremental;
+#[cfg(feature = "std")]
+pub use self::int_range::{RangeError, check_int_range};
+#[cfg(feature = "std")]
+pub use self::json::to_json;
+#[cfg(feature = "std")]
+pub use self::limit::limit_size;
+#[cfg(feature = "std")]
+pub use self::msgpack::{from_msgpack, to_msgpack};
+#[cfg(feature = "std")]
+pub use self::normalize::{normalize_numbers, normalize_width};
+#[cfg(feature = "std")]
+pub use self::numbered::{FieldNumbered, field_numbered};
+#[cfg(feature = "std")]
+pub use self::order::{FieldOrder, FieldOrders, order_fields};
+#[cfg(feature = "std")]
+pub use self::range::range_map_tokens;
+#[cfg(feature = "std")]
+pub use self::recount::RecountingBuffer;
+#[cfg(feature = "std")]
+pub use self::rename::{Renamed, rename};
+#[cfg(feature = "std")]
+pub use self::reshape::{as_map, as_struct, tuples_as_seqs};
+#[cfg(feature = "std")]
+pub use self::schema::{Schema, infer_schema};
+#[cfg(feature = "std")]
+pub use self::shape::check_shape;
+#[cfg(feature = "std")]
+pub use self::snippet::Snippet;
+#[cfg(feature = "std")]
+pub use self::strings::{ControlCharPolicy, check_strings, escape_strings};
+#[cfg(feature = "std")]
+pub use self::table::to_table;
+#[cfg(feature = "std")]
+pub use self::tagging::{TypeRegistry, adjacently_tagged, internally_tagged, with_type_names};
+#[cfg(feature = "std")]
+pub use self::tee::tee;
+#[cfg(feature = "std")]
+pub use self::time::{OffsetTime, Rfc3339, TimestampFormat, ToRfc3339};
+#[cfg(feature = "std")]
+pub use self::validate::validate;
+#[cfg(feature = "std")]
+pub use self::value::Value;
+#[cfg(feature = "std")]
+pub use self::xml::to_xml;
+#[cfg(feature = "std")]
+pub use self::yaml::to_yaml_flow;
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(feature = "std")]
+fn be_u16(v: u16) -> [u8; 2] {
+    [(v >> 8) as u8, v as u8]
+}
+
+#[cfg(feature = "std")]
+fn be_u32(v: u32) -> [u8; 4] {
+    [(v >> 24) as u8, (v >> 16) as u8, (v >> 8) as u8, v as u8]
+}
+
+#[cfg(feature = "std")]
+fn be_u64(v: u64) -> [u8; 8] {
+    [
+        (v >> 56) as u8, (v >> 48) as u8, (v >> 40) as u8, (v >> 32) as u8,
+        (v >> 24) as u8, (v >> 16) as u8, (v >> 8) as u8, v as u8,
+    ]
+}
+
+// `to_bits` and `from_bits` on floats need a newer compiler than the crate supports.
+#[cfg(feature = "std")]
+fn f32_to_bits(v: f32) -> u32 {
+    unsafe { ::std::mem::transmute(v) }
+}
+
+#[cfg(feature = "std")]
+fn f32_from_bits(v: u32) -> f32 {
+    unsafe { ::std::mem::transmute(v) }
+}
+
+#[cfg(feature = "std")]
+fn f64_to_bits(v: f64) -> u64 {
+    unsafe { ::std::mem::transmute(v) }
+}
+
+#[cfg(feature = "std")]
+fn f64_from_bits(v: u64) -> f64 {
+    unsafe { ::std::mem::transmute(v) }
+}
+
+/// Returns the one based line and column of the byte at `pos`.
+#[cfg(feature = "std")]
+fn line_column(input: &[u8], pos: usize) -> (usize, usize) {
+    let before = &input[..pos];
+    let line = before.iter().filter(|&&b| b == b'\n').count() + 1;
+    let column = match before.iter().rposition(|&b| b == b'\n') {
+        Some(newline) => pos - newline,
+        None => pos + 1,
+    };
+    (line, column)
+}
+
+/// Copies the strings and byte arrays that a token read from an input borrows from it, so that
+/// the token outlives the input. Readers never produce tokens holding type or variant names.
+#[cfg(feature = "std")]
+fn into_owned(token: Token) -> Token<'static> {
+    match token {
+        Token::Bool(v) => Token::Bool(v),
+        Token::Isize(v) => Token::Isize(v),
+        Token::I8(v) => Token::I8(v),
+        Token::I16(v) => Token::I16(v),
+        Token::I32(v) => Token::I32(v),
+        Token::I64(v) => Token::I64(v),
+        Token::Usize(v) => Token::Usize(v),
+        Token::U8(v) => Token::U8(v),
+        Token::U16(v) => Token::U16(v),
+        Token::U32(v) => Token::U32(v),
+        Token::U64(v) => Token::U64(v),
+        Token::F32(v) => Token::F32(v),
+        Token::F64(v) => Token::F64(v),
+        Token::Char(v) => Token::Char(v),
+        Token::Str(v) => Token::String(v.to_owned()),
+        Token::String(v) => Token::String(v),
+        Token::Bytes(v) => Token::ByteBuf(v.to_owned()),
+        Token::ByteBuf(v) => Token::ByteBuf(v),
+        Token::Timestamp(secs, nanos) => Token::Timestamp(secs, nanos),
+        Token::BigNum(v) => Token::BigNum(v),
+        Token::Option(v) => Token::Option(v),
+        Token::Unit => Token::Unit,
+        Token::SeqStart(len) => Token::SeqStart(len),
+        Token::SeqArrayStart(len) => Token::SeqArrayStart(len),
+        Token::SeqSep => Token::SeqSep,
+        Token::SeqEnd => Token::SeqEnd,
+        Token::TupleStart(len) => Token::TupleStart(len),
+        Token::TupleSep => Token::TupleSep,
+        Token::TupleEnd => Token::TupleEnd,
+        Token::MapStart(len) => Token::MapStart(len),
+        Token::MapSep => Token::MapSep,
+        Token::MapEnd => Token::MapEnd,
+        token => unreachable!("readers don't produce named tokens like {:?}", token),
+    }
+}
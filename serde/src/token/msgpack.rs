@@ -0,0 +1,962 @@
+//! Encode and decode token streams as MessagePack.
+//!
+//! MessagePack headers carry the number of elements, so every sequence, map and struct has to
+//! come with a known length. Structs become maps keyed by field name, tuples and tuple structs
+//! become arrays, and `None` and `()` become `nil`. An enum variant with a payload becomes a
+//! single entry map from the variant name to the payload. Timestamps are written as RFC 3339
+//! strings, or as the timestamp extension type when `Encoder::timestamp_ext` is set. Integers
+//! too large for MessagePack, `BigNum` tokens, are written as strings of their digits.
+//!
+//! Decoding turns arrays into sequences and maps into maps. Integers become `I64`, or `U64` when
+//! they are too large for an `i64`, and strings and binaries borrow from the input. Extension
+//! types without a handler become the generic representation of `EXT_STRUCT`, which the encoder
+//! writes back as the same extension type. Like the JSON reader, the decoder keeps every entry
+//! of a map with a repeated key.
+
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+use std::i64;
+use std::io;
+use std::mem;
+use std::str;
+
+use ser::Serialize;
+
+use super::{Token, be_u16, be_u32, be_u64, f32_from_bits, f32_to_bits, f64_from_bits, f64_to_bits,
+            normalize_width, time};
+
+const NIL: u8 = 0xc0;
+const FALSE: u8 = 0xc2;
+const TRUE: u8 = 0xc3;
+const BIN8: u8 = 0xc4;
+const BIN16: u8 = 0xc5;
+const BIN32: u8 = 0xc6;
+const FLOAT32: u8 = 0xca;
+const FLOAT64: u8 = 0xcb;
+const UINT8: u8 = 0xcc;
+const UINT16: u8 = 0xcd;
+const UINT32: u8 = 0xce;
+const UINT64: u8 = 0xcf;
+const INT8: u8 = 0xd0;
+const INT16: u8 = 0xd1;
+const INT32: u8 = 0xd2;
+const INT64: u8 = 0xd3;
+const STR8: u8 = 0xd9;
+const STR16: u8 = 0xda;
+const STR32: u8 = 0xdb;
+const ARRAY16: u8 = 0xdc;
+const ARRAY32: u8 = 0xdd;
+const MAP16: u8 = 0xde;
+const MAP32: u8 = 0xdf;
+const EXT8: u8 = 0xc7;
+const EXT16: u8 = 0xc8;
+const EXT32: u8 = 0xc9;
+const FIXEXT1: u8 = 0xd4;
+const FIXEXT2: u8 = 0xd5;
+const FIXEXT4: u8 = 0xd6;
+const FIXEXT8: u8 = 0xd7;
+const FIXEXT16: u8 = 0xd8;
+
+const FIXMAP: u8 = 0x80;
+const FIXARRAY: u8 = 0x90;
+const FIXSTR: u8 = 0xa0;
+
+/// The extension type of timestamps.
+pub const TIMESTAMP_EXT: i8 = -1;
+
+/// The struct name of the generic representation of an extension type, a struct with the fields
+/// `type`, an integer, and `data`, a byte array:
+///
+/// ```text
+/// StructStart(EXT_STRUCT, Some(2)),
+///     StructSep, Str("type"), I8(42),
+///     StructSep, Str("data"), Bytes(..),
+/// StructEnd,
+/// ```
+pub const EXT_STRUCT: &'static str = "msgpack::Ext";
+
+/// Encodes the values in `iter` as MessagePack.
+pub fn to_msgpack<'a, I>(iter: I) -> io::Result<Vec<u8>>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let mut out = Vec::new();
+    try!(write_msgpack(iter, &mut out));
+    Ok(out)
+}
+
+/// Serializes `value` as MessagePack. MessagePack is not human readable, so types with a compact
+/// form use it, and timestamps are written as the timestamp extension type.
+///
+/// ```rust
+/// use std::time::{Duration, UNIX_EPOCH};
+/// use serde::token::msgpack;
+///
+/// let time = UNIX_EPOCH + Duration::from_secs(1);
+/// assert_eq!(msgpack::serialize(&time).unwrap(), vec![0xd6, 0xff, 0, 0, 0, 1]);
+/// ```
+pub fn serialize<T: ?Sized>(value: &T) -> io::Result<Vec<u8>>
+    where T: Serialize,
+{
+    let mut serializer = super::Serializer::new().human_readable(false);
+    try!(value.serialize(&mut serializer).map_err(|err| {
+        io::Error::new(io::ErrorKind::InvalidInput, err.to_string())
+    }));
+    let mut out = Vec::new();
+    try!(Encoder::new(&mut out).timestamp_ext(true).encode(serializer.into_tokens()));
+    Ok(out)
+}
+
+/// Encodes the values in `iter` as MessagePack into `writer`.
+///
+/// Every token is written as soon as it arrives, so errors of the writer are returned right
+/// away. A sequence, map or struct with an unknown length results in an `InvalidInput` error.
+pub fn write_msgpack<'a, I, W>(iter: I, writer: &mut W) -> io::Result<()>
+    where I: IntoIterator<Item=Token<'a>>,
+          W: io::Write,
+{
+    Encoder::new(writer).encode(iter)
+}
+
+/// A MessagePack encoder with options.
+///
+/// ```rust
+/// use serde::token::Token;
+/// use serde::token::msgpack::Encoder;
+///
+/// let mut encoder = Encoder::new(Vec::new()).timestamp_ext(true);
+/// encoder.encode(vec![Token::Timestamp(1, 0)]).unwrap();
+/// assert_eq!(encoder.into_inner(), vec![0xd6, 0xff, 0, 0, 0, 1]);
+/// ```
+pub struct Encoder<W> {
+    writer: W,
+    timestamp_ext: bool,
+    bytes_ext: Option<i8>,
+    /// The generic representation of an extension type being read.
+    ext: Option<Ext>,
+}
+
+/// The parts of an extension type seen so far.
+struct Ext {
+    key: Option<String>,
+    ty: Option<i8>,
+    data: Option<Vec<u8>>,
+}
+
+impl<W> Encoder<W>
+    where W: io::Write,
+{
+    /// Construct a new `Encoder` writing into `writer`.
+    pub fn new(writer: W) -> Self {
+        Encoder {
+            writer: writer,
+            timestamp_ext: false,
+            bytes_ext: None,
+            ext: None,
+        }
+    }
+
+    /// Sets whether `Timestamp` tokens are written as the timestamp extension type instead of
+    /// RFC 3339 strings, in the smallest of its three formats that can hold them.
+    pub fn timestamp_ext(mut self, timestamp_ext: bool) -> Self {
+        self.timestamp_ext = timestamp_ext;
+        self
+    }
+
+    /// Sets an extension type for byte arrays to be written as, instead of binaries.
+    pub fn bytes_ext(mut self, ty: Option<i8>) -> Self {
+        self.bytes_ext = ty;
+        self
+    }
+
+    /// Unwraps this `Encoder`, returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Encodes all tokens of `iter`.
+    pub fn encode<'a, I>(&mut self, iter: I) -> io::Result<()>
+        where I: IntoIterator<Item=Token<'a>>,
+    {
+        for token in iter {
+            try!(self.write_token(token));
+        }
+        Ok(())
+    }
+
+    /// Encodes the next token.
+    pub fn write_token(&mut self, token: Token) -> io::Result<()> {
+        if self.ext.is_some() {
+            return self.ext_token(token);
+        }
+
+        match normalize_width(token) {
+            Token::Bool(v) => self.writer.write_all(&[if v { TRUE } else { FALSE }]),
+            Token::Isize(_) | Token::Usize(_) => {
+                unreachable!("pointer sized integers are normalized")
+            }
+            Token::I8(v) => self.signed(v as i64),
+            Token::I16(v) => self.signed(v as i64),
+            Token::I32(v) => self.signed(v as i64),
+            Token::I64(v) => self.signed(v),
+            Token::U8(v) => self.unsigned(v as u64),
+            Token::U16(v) => self.unsigned(v as u64),
+            Token::U32(v) => self.unsigned(v as u64),
+            Token::U64(v) => self.unsigned(v),
+            Token::F32(v) => {
+                try!(self.writer.write_all(&[FLOAT32]));
+                self.writer.write_all(&be_u32(f32_to_bits(v)))
+            }
+            Token::F64(v) => {
+                try!(self.writer.write_all(&[FLOAT64]));
+                self.writer.write_all(&be_u64(f64_to_bits(v)))
+            }
+            Token::Char(v) => self.text(::utils::encode_utf8(v).as_str()),
+            Token::Str(v) => self.text(v),
+            Token::String(ref v) => self.text(v),
+            Token::Bytes(v) => self.bytes(v),
+            Token::ByteBuf(ref v) => self.bytes(v),
+            Token::BigNum(ref v) => self.text(v),
+            Token::Timestamp(secs, nanos) if self.timestamp_ext => self.timestamp(secs, nanos),
+            Token::Timestamp(secs, nanos) => self.text(&time::format_rfc3339(secs, nanos)),
+
+            Token::Option(false) | Token::Unit | Token::UnitStruct(_) => {
+                self.writer.write_all(&[NIL])
+            }
+            Token::Option(true) |
+            Token::StructNewType(_) |
+            Token::TypeTag(_) |
+            Token::EnumStart(_) => Ok(()),
+
+            Token::EnumUnit(_, variant) => self.text(variant),
+            Token::EnumNewType(_, variant) => {
+                try!(self.map(Some(1)));
+                self.text(variant)
+            }
+
+            Token::SeqStart(len) |
+            Token::TupleStructStart(_, len) => self.array(len),
+            Token::SeqArrayStart(len) |
+            Token::TupleStart(len) => self.array(Some(len)),
+            Token::StructStart(EXT_STRUCT, _) => {
+                self.ext = Some(Ext { key: None, ty: None, data: None });
+                Ok(())
+            }
+            Token::MapStart(len) |
+            Token::StructStart(_, len) => self.map(len),
+            Token::EnumSeqStart(_, variant, len) => {
+                try!(self.map(Some(1)));
+                try!(self.text(variant));
+                self.array(len)
+            }
+            Token::EnumMapStart(_, variant, len) => {
+                try!(self.map(Some(1)));
+                try!(self.text(variant));
+                self.map(len)
+            }
+
+            Token::SeqSep |
+            Token::TupleSep |
+            Token::TupleStructSep |
+            Token::MapSep |
+            Token::StructSep |
+            Token::EnumSeqSep |
+            Token::EnumMapSep |
+            Token::SeqEnd |
+            Token::TupleEnd |
+            Token::TupleStructEnd |
+            Token::MapEnd |
+            Token::StructEnd |
+            Token::EnumSeqEnd |
+            Token::EnumMapEnd => Ok(()),
+        }
+    }
+
+    fn unsigned(&mut self, value: u64) -> io::Result<()> {
+        if value < 0x80 {
+            self.writer.write_all(&[value as u8])
+        } else if value <= 0xff {
+            self.writer.write_all(&[UINT8, value as u8])
+        } else if value <= 0xffff {
+            try!(self.writer.write_all(&[UINT16]));
+            self.writer.write_all(&be_u16(value as u16))
+        } else if value <= 0xffff_ffff {
+            try!(self.writer.write_all(&[UINT32]));
+            self.writer.write_all(&be_u32(value as u32))
+        } else {
+            try!(self.writer.write_all(&[UINT64]));
+            self.writer.write_all(&be_u64(value))
+        }
+    }
+
+    fn signed(&mut self, value: i64) -> io::Result<()> {
+        if value >= 0 {
+            self.unsigned(value as u64)
+        } else if value >= -32 {
+            self.writer.write_all(&[value as u8])
+        } else if value >= -0x80 {
+            self.writer.write_all(&[INT8, value as u8])
+        } else if value >= -0x8000 {
+            try!(self.writer.write_all(&[INT16]));
+            self.writer.write_all(&be_u16(value as u16))
+        } else if value >= -0x8000_0000 {
+            try!(self.writer.write_all(&[INT32]));
+            self.writer.write_all(&be_u32(value as u32))
+        } else {
+            try!(self.writer.write_all(&[INT64]));
+            self.writer.write_all(&be_u64(value as u64))
+        }
+    }
+
+    fn text(&mut self, value: &str) -> io::Result<()> {
+        let len = value.len();
+        if len < 32 {
+            try!(self.writer.write_all(&[FIXSTR | len as u8]));
+        } else {
+            try!(self.header(len, Some(STR8), STR16, STR32));
+        }
+        self.writer.write_all(value.as_bytes())
+    }
+
+    fn bytes(&mut self, value: &[u8]) -> io::Result<()> {
+        if let Some(ty) = self.bytes_ext {
+            return self.ext(ty, value);
+        }
+        try!(self.header(value.len(), Some(BIN8), BIN16, BIN32));
+        self.writer.write_all(value)
+    }
+
+    /// Writes an extension type, with a fixext header when the payload has one of their sizes.
+    fn ext(&mut self, ty: i8, data: &[u8]) -> io::Result<()> {
+        let fixext = match data.len() {
+            1 => Some(FIXEXT1),
+            2 => Some(FIXEXT2),
+            4 => Some(FIXEXT4),
+            8 => Some(FIXEXT8),
+            16 => Some(FIXEXT16),
+            _ => None,
+        };
+        match fixext {
+            Some(marker) => try!(self.writer.write_all(&[marker])),
+            None => try!(self.header(data.len(), Some(EXT8), EXT16, EXT32)),
+        }
+        try!(self.writer.write_all(&[ty as u8]));
+        self.writer.write_all(data)
+    }
+
+    /// Writes a timestamp in the 32 bit format if it is a whole number of seconds that fits into
+    /// a `u32`, in the 64 bit format if the seconds fit into 34 bits, and in the 96 bit format
+    /// otherwise.
+    fn timestamp(&mut self, secs: i64, nanos: u32) -> io::Result<()> {
+        if secs >= 0 && secs >> 34 == 0 {
+            if nanos == 0 && secs >> 32 == 0 {
+                self.ext(TIMESTAMP_EXT, &be_u32(secs as u32))
+            } else {
+                self.ext(TIMESTAMP_EXT, &be_u64((nanos as u64) << 34 | secs as u64))
+            }
+        } else {
+            let mut data = be_u32(nanos).to_vec();
+            data.extend(be_u64(secs as u64).iter().cloned());
+            self.ext(TIMESTAMP_EXT, &data)
+        }
+    }
+
+    /// Collects the generic representation of an extension type, and writes it once it is
+    /// complete.
+    fn ext_token(&mut self, token: Token) -> io::Result<()> {
+        let mut ext = self.ext.take().expect("ext_token called outside of an extension type");
+        match (ext.key.take(), token) {
+            (None, Token::StructSep) => { }
+            (None, Token::StructEnd) => {
+                return match (ext.ty, ext.data) {
+                    (Some(ty), Some(data)) => self.ext(ty, &data),
+                    _ => Err(invalid_ext("missing field")),
+                };
+            }
+            (None, ref key) if key.as_str().is_some() => {
+                ext.key = key.as_str().map(str::to_owned);
+            }
+            (Some(ref key), ref token) if key == "type" => {
+                let ty = match *token {
+                    Token::I8(v) => Some(v),
+                    Token::I16(v) if v as i8 as i16 == v => Some(v as i8),
+                    Token::I32(v) if v as i8 as i32 == v => Some(v as i8),
+                    Token::I64(v) if v as i8 as i64 == v => Some(v as i8),
+                    _ => None,
+                };
+                match ty {
+                    Some(ty) => ext.ty = Some(ty),
+                    None => return Err(invalid_ext("`type` must be an i8")),
+                }
+            }
+            (Some(ref key), ref token) if key == "data" => {
+                match token.as_bytes() {
+                    Some(data) if token.as_str().is_none() => ext.data = Some(data.to_vec()),
+                    _ => return Err(invalid_ext("`data` must be a byte array")),
+                }
+            }
+            (_, token) => return Err(invalid_ext(&format!("unexpected token {:?}", token))),
+        }
+        self.ext = Some(ext);
+        Ok(())
+    }
+
+    fn array(&mut self, len: Option<usize>) -> io::Result<()> {
+        match len {
+            Some(len) if len < 16 => self.writer.write_all(&[FIXARRAY | len as u8]),
+            Some(len) => self.header(len, None, ARRAY16, ARRAY32),
+            None => Err(unknown_length()),
+        }
+    }
+
+    fn map(&mut self, len: Option<usize>) -> io::Result<()> {
+        match len {
+            Some(len) if len < 16 => self.writer.write_all(&[FIXMAP | len as u8]),
+            Some(len) => self.header(len, None, MAP16, MAP32),
+            None => Err(unknown_length()),
+        }
+    }
+
+    /// Writes a type byte followed by a length in the shortest available width. Arrays and maps
+    /// have no 8 bit width.
+    fn header(&mut self,
+              len: usize,
+              marker8: Option<u8>,
+              marker16: u8,
+              marker32: u8) -> io::Result<()> {
+        match marker8 {
+            Some(marker8) if len <= 0xff => return self.writer.write_all(&[marker8, len as u8]),
+            _ => { }
+        }
+
+        if len <= 0xffff {
+            try!(self.writer.write_all(&[marker16]));
+            self.writer.write_all(&be_u16(len as u16))
+        } else if len as u64 <= 0xffff_ffff {
+            try!(self.writer.write_all(&[marker32]));
+            self.writer.write_all(&be_u32(len as u32))
+        } else {
+            Err(io::Error::new(io::ErrorKind::InvalidInput,
+                               "length does not fit into a MessagePack header"))
+        }
+    }
+}
+
+fn unknown_length() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput,
+                   "MessagePack requires the length of every sequence and map")
+}
+
+fn invalid_ext(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput,
+                   format!("invalid extension type representation: {}", message))
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// The error returned when decoding MessagePack fails.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    offset: usize,
+    message: String,
+}
+
+impl Error {
+    /// Returns the offset of the byte at which the problem was found.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns a description of the problem.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{} at offset {}", self.message, self.offset)
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        "invalid MessagePack"
+    }
+}
+
+/// Decodes the single MessagePack value in `input`, turning timestamp extension types into
+/// `Timestamp` tokens.
+pub fn from_msgpack(input: &[u8]) -> Result<Vec<Token>, Error> {
+    Decoder::new(input).decode()
+}
+
+/// Turns the payload of an extension type into tokens, or returns `None` if it is malformed.
+pub type ExtHandler = fn(&[u8]) -> Option<Vec<Token<'static>>>;
+
+/// Decodes the payload of the timestamp extension type into a `Timestamp` token.
+pub fn decode_timestamp(data: &[u8]) -> Option<Vec<Token<'static>>> {
+    let (secs, nanos) = match data.len() {
+        4 => (read_be(data) as i64, 0),
+        8 => {
+            let v = read_be(data);
+            ((v & 0x3_ffff_ffff) as i64, (v >> 34) as u32)
+        }
+        12 => (read_be(&data[4..]) as i64, read_be(&data[..4]) as u32),
+        _ => return None,
+    };
+    if nanos >= 1_000_000_000 {
+        return None;
+    }
+    Some(vec![Token::Timestamp(secs, nanos)])
+}
+
+/// A MessagePack decoder.
+///
+/// By default the timestamp extension type becomes `Timestamp` tokens, other extension types
+/// become their generic representation, and arrays and maps may nest 128 levels deep.
+pub struct Decoder<'a> {
+    input: &'a [u8],
+    pos: usize,
+    max_depth: usize,
+    handlers: BTreeMap<i8, ExtHandler>,
+    tokens: Vec<Token<'a>>,
+}
+
+impl<'a> Decoder<'a> {
+    /// Construct a new `Decoder` reading from `input`.
+    pub fn new(input: &'a [u8]) -> Self {
+        let mut handlers = BTreeMap::new();
+        handlers.insert(TIMESTAMP_EXT, decode_timestamp as ExtHandler);
+        Decoder {
+            input: input,
+            pos: 0,
+            max_depth: 128,
+            handlers: handlers,
+            tokens: Vec::new(),
+        }
+    }
+
+    /// Registers a handler for the extension type `ty`, or removes the handler with `None` so
+    /// the type decodes to its generic representation.
+    pub fn ext_handler(mut self, ty: i8, handler: Option<ExtHandler>) -> Self {
+        match handler {
+            Some(handler) => self.handlers.insert(ty, handler),
+            None => self.handlers.remove(&ty),
+        };
+        self
+    }
+
+    /// Sets how many levels deep arrays and maps may nest.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Decodes the single value in the input.
+    pub fn decode(mut self) -> Result<Vec<Token<'a>>, Error> {
+        try!(self.value(0));
+        if self.pos != self.input.len() {
+            return Err(self.error("trailing bytes"));
+        }
+        Ok(self.tokens)
+    }
+
+    /// Returns an iterator over the values in an input that holds several of them one after
+    /// another, yielding every value together with its byte offset. Iteration ends after an
+    /// error.
+    ///
+    /// ```rust
+    /// use serde::token::{self, msgpack};
+    ///
+    /// let mut input = token::to_msgpack(token::to_tokens(&"one").unwrap()).unwrap();
+    /// input.extend(token::to_msgpack(token::to_tokens(&2).unwrap()).unwrap());
+    /// let offsets: Vec<_> = msgpack::Decoder::new(&input).scan().map(|r| r.unwrap().0).collect();
+    /// assert_eq!(offsets, vec![0, 4]);
+    /// ```
+    ///
+    /// `ReadScan` finds the offsets in inputs too large to hold in memory.
+    pub fn scan(self) -> Scan<'a> {
+        Scan {
+            decoder: self,
+            done: false,
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<(), Error> {
+        let start = self.pos;
+        let marker = try!(self.byte());
+        let token = match marker {
+            0x00 ... 0x7f => Token::I64(marker as i64),
+            0xe0 ... 0xff => Token::I64(marker as i8 as i64),
+            0x80 ... 0x8f => return self.map((marker & 0x0f) as usize, depth, start),
+            0x90 ... 0x9f => return self.array((marker & 0x0f) as usize, depth, start),
+            0xa0 ... 0xbf => try!(self.str((marker & 0x1f) as usize, start)),
+            NIL => Token::Unit,
+            FALSE => Token::Bool(false),
+            TRUE => Token::Bool(true),
+            BIN8 | BIN16 | BIN32 => {
+                let len = try!(self.len(1 << (marker - BIN8)));
+                Token::Bytes(try!(self.slice(len)))
+            }
+            EXT8 | EXT16 | EXT32 => {
+                let len = try!(self.len(1 << (marker - EXT8)));
+                return self.ext(len, start);
+            }
+            FLOAT32 => Token::F32(f32_from_bits(try!(self.uint(4)) as u32)),
+            FLOAT64 => Token::F64(f64_from_bits(try!(self.uint(8)))),
+            UINT8 | UINT16 | UINT32 | UINT64 => {
+                let v = try!(self.uint(1 << (marker - UINT8)));
+                if v <= i64::MAX as u64 {
+                    Token::I64(v as i64)
+                } else {
+                    Token::U64(v)
+                }
+            }
+            INT8 | INT16 | INT32 | INT64 => {
+                let len = 1 << (marker - INT8);
+                let v = try!(self.uint(len));
+                // Sign extend from `len` bytes.
+                let shift = 64 - 8 * len as u32;
+                Token::I64((v << shift) as i64 >> shift)
+            }
+            FIXEXT1 | FIXEXT2 | FIXEXT4 | FIXEXT8 | FIXEXT16 => {
+                return self.ext(1 << (marker - FIXEXT1), start);
+            }
+            STR8 | STR16 | STR32 => {
+                let len = try!(self.len(1 << (marker - STR8)));
+                try!(self.str(len, start))
+            }
+            ARRAY16 | ARRAY32 => {
+                let len = try!(self.len(2 << (marker - ARRAY16)));
+                return self.array(len, depth, start);
+            }
+            MAP16 | MAP32 => {
+                let len = try!(self.len(2 << (marker - MAP16)));
+                return self.map(len, depth, start);
+            }
+            _ => return Err(self.error_at(start, &format!("unknown type byte 0x{:02x}", marker))),
+        };
+        self.tokens.push(token);
+        Ok(())
+    }
+
+    fn array(&mut self, len: usize, depth: usize, start: usize) -> Result<(), Error> {
+        if depth >= self.max_depth {
+            return Err(self.depth_error(start));
+        }
+        // Every element takes at least one byte.
+        if len > self.input.len() - self.pos {
+            return Err(self.error_at(start, "length exceeds the rest of the input"));
+        }
+
+        self.tokens.push(Token::SeqStart(Some(len)));
+        for _ in 0..len {
+            self.tokens.push(Token::SeqSep);
+            try!(self.value(depth + 1));
+        }
+        self.tokens.push(Token::SeqEnd);
+        Ok(())
+    }
+
+    fn map(&mut self, len: usize, depth: usize, start: usize) -> Result<(), Error> {
+        if depth >= self.max_depth {
+            return Err(self.depth_error(start));
+        }
+        if len > (self.input.len() - self.pos) / 2 {
+            return Err(self.error_at(start, "length exceeds the rest of the input"));
+        }
+
+        self.tokens.push(Token::MapStart(Some(len)));
+        for _ in 0..len {
+            self.tokens.push(Token::MapSep);
+            try!(self.value(depth + 1));
+            try!(self.value(depth + 1));
+        }
+        self.tokens.push(Token::MapEnd);
+        Ok(())
+    }
+
+    fn str(&mut self, len: usize, start: usize) -> Result<Token<'a>, Error> {
+        let bytes = try!(self.slice(len));
+        match str::from_utf8(bytes) {
+            Ok(v) => Ok(Token::Str(v)),
+            Err(_) => Err(self.error_at(start, "invalid UTF-8 in string")),
+        }
+    }
+
+    /// Reads the type and payload of an extension type of `len` bytes.
+    fn ext(&mut self, len: usize, start: usize) -> Result<(), Error> {
+        let ty = try!(self.byte()) as i8;
+        let data = try!(self.slice(len));
+
+        match self.handlers.get(&ty) {
+            Some(handler) => {
+                match handler(data) {
+                    Some(tokens) => self.tokens.extend(tokens),
+                    None => {
+                        let message = format!("invalid payload for extension type {}", ty);
+                        return Err(self.error_at(start, &message));
+                    }
+                }
+            }
+            None => {
+                self.tokens.extend(vec![
+                    Token::StructStart(EXT_STRUCT, Some(2)),
+                    Token::StructSep,
+                    Token::Str("type"),
+                    Token::I8(ty),
+                    Token::StructSep,
+                    Token::Str("data"),
+                    Token::Bytes(data),
+                    Token::StructEnd,
+                ]);
+            }
+        }
+        Ok(())
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        let bytes = try!(self.slice(1));
+        Ok(bytes[0])
+    }
+
+    /// Reads a big endian unsigned integer of `len` bytes.
+    fn uint(&mut self, len: usize) -> Result<u64, Error> {
+        let bytes = try!(self.slice(len));
+        Ok(read_be(bytes))
+    }
+
+    /// Reads the length of a string, binary, array, map or extension type.
+    fn len(&mut self, len: usize) -> Result<usize, Error> {
+        self.uint(len).map(|v| v as usize)
+    }
+
+    fn slice(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let input = self.input;
+        if len > input.len() - self.pos {
+            self.pos = input.len();
+            return Err(self.error("unexpected end of input"));
+        }
+        let start = self.pos;
+        self.pos += len;
+        Ok(&input[start..self.pos])
+    }
+
+    fn depth_error(&self, start: usize) -> Error {
+        let message = format!("nesting exceeds the depth limit of {}", self.max_depth);
+        self.error_at(start, &message)
+    }
+
+    fn error(&self, message: &str) -> Error {
+        self.error_at(self.pos, message)
+    }
+
+    fn error_at(&self, offset: usize, message: &str) -> Error {
+        Error {
+            offset: offset,
+            message: message.to_owned(),
+        }
+    }
+}
+
+/// Iterator returned by `Decoder::scan`.
+pub struct Scan<'a> {
+    decoder: Decoder<'a>,
+    done: bool,
+}
+
+impl<'a> Iterator for Scan<'a> {
+    type Item = Result<(usize, Vec<Token<'a>>), Error>;
+
+    fn next(&mut self) -> Option<Result<(usize, Vec<Token<'a>>), Error>> {
+        if self.done || self.decoder.pos == self.decoder.input.len() {
+            return None;
+        }
+
+        let offset = self.decoder.pos;
+        match self.decoder.value(0) {
+            Ok(()) => Some(Ok((offset, mem::replace(&mut self.decoder.tokens, Vec::new())))),
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// An iterator over the byte offsets at which the values in an `io::Read` start, for inputs
+/// that hold several values one after another and are too large to hold in memory.
+///
+/// Values are skipped by their headers, without decoding them or producing any tokens, and
+/// without recursing into arrays and maps. An unknown type byte and a value cut off by the end
+/// of the input are `InvalidData` errors wrapping an `Error` with the offset of the problem.
+/// Iteration ends after an error.
+///
+/// ```rust
+/// use std::io::{Cursor, Seek, SeekFrom};
+/// use serde::token::{self, msgpack};
+///
+/// let mut bytes = token::to_msgpack(token::to_tokens(&"one").unwrap()).unwrap();
+/// bytes.extend(token::to_msgpack(token::to_tokens(&vec![2, 3]).unwrap()).unwrap());
+/// let mut input = Cursor::new(bytes);
+/// let offsets: Vec<_> = msgpack::ReadScan::new(&mut input).map(Result::unwrap).collect();
+/// assert_eq!(offsets, vec![0, 4]);
+///
+/// input.seek(SeekFrom::Start(offsets[1] as u64)).unwrap();
+/// let second = msgpack::from_msgpack(&input.get_ref()[offsets[1]..]).unwrap();
+/// assert_eq!(token::to_json(second).unwrap(), "[2,3]");
+/// ```
+pub struct ReadScan<R> {
+    reader: R,
+    /// The number of bytes read so far.
+    offset: usize,
+    done: bool,
+}
+
+impl<R> ReadScan<R>
+    where R: io::Read,
+{
+    /// Construct a new `ReadScan` reading from `reader`.
+    pub fn new(reader: R) -> Self {
+        ReadScan {
+            reader: reader,
+            offset: 0,
+            done: false,
+        }
+    }
+
+    /// Skips the value at the current offset, returning false at the end of the input.
+    fn skip_value(&mut self) -> io::Result<bool> {
+        let mut marker = [0];
+        if try!(self.read(&mut marker)) == 0 {
+            return Ok(false);
+        }
+
+        // The number of values still to skip, which grows by the elements of every array and
+        // map that is skipped.
+        let mut pending = 1u64;
+        loop {
+            let start = self.offset - 1;
+            let byte = marker[0];
+            pending -= 1;
+            match byte {
+                0x00 ... 0x7f | 0xe0 ... 0xff | NIL | FALSE | TRUE => { }
+                0x80 ... 0x8f => pending += 2 * (byte & 0x0f) as u64,
+                0x90 ... 0x9f => pending += (byte & 0x0f) as u64,
+                0xa0 ... 0xbf => try!(self.skip((byte & 0x1f) as u64)),
+                BIN8 | BIN16 | BIN32 => {
+                    let len = try!(self.uint(1 << (byte - BIN8)));
+                    try!(self.skip(len));
+                }
+                EXT8 | EXT16 | EXT32 => {
+                    let len = try!(self.uint(1 << (byte - EXT8)));
+                    try!(self.skip(len + 1));
+                }
+                FLOAT32 => try!(self.skip(4)),
+                FLOAT64 => try!(self.skip(8)),
+                UINT8 | UINT16 | UINT32 | UINT64 => try!(self.skip(1 << (byte - UINT8))),
+                INT8 | INT16 | INT32 | INT64 => try!(self.skip(1 << (byte - INT8))),
+                FIXEXT1 | FIXEXT2 | FIXEXT4 | FIXEXT8 | FIXEXT16 => {
+                    try!(self.skip(1 + (1 << (byte - FIXEXT1))));
+                }
+                STR8 | STR16 | STR32 => {
+                    let len = try!(self.uint(1 << (byte - STR8)));
+                    try!(self.skip(len));
+                }
+                ARRAY16 | ARRAY32 => pending += try!(self.uint(2 << (byte - ARRAY16))),
+                MAP16 | MAP32 => pending += 2 * try!(self.uint(2 << (byte - MAP16))),
+                _ => {
+                    let message = format!("unknown type byte 0x{:02x}", byte);
+                    return Err(invalid(start, &message));
+                }
+            }
+
+            if pending == 0 {
+                return Ok(true);
+            }
+            try!(self.read_exact(&mut marker));
+        }
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.reader.read(buf) {
+                Ok(read) => {
+                    self.offset += read;
+                    return Ok(read);
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => { }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn read_exact(&mut self, mut buf: &mut [u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            match try!(self.read(buf)) {
+                0 => return Err(self.end_of_input()),
+                read => buf = &mut {buf}[read..],
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a big endian unsigned integer of `len` bytes.
+    fn uint(&mut self, len: usize) -> io::Result<u64> {
+        let mut bytes = [0; 8];
+        try!(self.read_exact(&mut bytes[..len]));
+        Ok(read_be(&bytes[..len]))
+    }
+
+    fn skip(&mut self, len: u64) -> io::Result<()> {
+        let skipped = try!(io::copy(&mut io::Read::take(&mut self.reader, len), &mut io::sink()));
+        self.offset += skipped as usize;
+        if skipped < len {
+            return Err(self.end_of_input());
+        }
+        Ok(())
+    }
+
+    fn end_of_input(&self) -> io::Error {
+        invalid(self.offset, "unexpected end of input")
+    }
+}
+
+impl<R> Iterator for ReadScan<R>
+    where R: io::Read,
+{
+    type Item = io::Result<usize>;
+
+    fn next(&mut self) -> Option<io::Result<usize>> {
+        if self.done {
+            return None;
+        }
+
+        let offset = self.offset;
+        match self.skip_value() {
+            Ok(true) => Some(Ok(offset)),
+            Ok(false) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Returns an `InvalidData` I/O error wrapping an `Error` at `offset`.
+fn invalid(offset: usize, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, Error {
+        offset: offset,
+        message: message.to_owned(),
+    })
+}
+
+/// Reads a big endian unsigned integer.
+fn read_be(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |n, &byte| n << 8 | byte as u64)
+}
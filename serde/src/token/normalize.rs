@@ -0,0 +1,124 @@
+//! Rewrite numeric tokens into a single canonical width.
+
+use std::i64;
+
+use super::Token;
+
+/// Rewrites `Isize` and `Usize`, whose width depends on the target, to the fixed-width token of
+/// the target's pointer width: `I64` and `U64` on 64-bit targets, `I32` and `U32` on 32-bit
+/// targets and `I16` and `U16` on 16-bit targets. All other tokens are returned unchanged.
+///
+/// The value always fits, so nothing is lost, and a reader on any target sees the same
+/// fixed-width token a reader on the writing target would. Binary writers that tag integers with
+/// their type call this on every token, so their output doesn't contain pointer sized integers.
+///
+/// ```rust
+/// use serde::token::{self, Token};
+///
+/// # #[cfg(target_pointer_width = "64")]
+/// assert_eq!(token::normalize_width(Token::Isize(5)), Token::I64(5));
+/// # #[cfg(target_pointer_width = "32")]
+/// assert_eq!(token::normalize_width(Token::Isize(5)), Token::I32(5));
+/// assert_eq!(token::normalize_width(Token::U8(5)), Token::U8(5));
+/// ```
+pub fn normalize_width<'a>(token: Token<'a>) -> Token<'a> {
+    match token {
+        Token::Isize(v) => isize_token(v),
+        Token::Usize(v) => usize_token(v),
+        token => token,
+    }
+}
+
+#[cfg(target_pointer_width = "64")]
+fn isize_token<'a>(v: isize) -> Token<'a> {
+    Token::I64(v as i64)
+}
+
+#[cfg(target_pointer_width = "64")]
+fn usize_token<'a>(v: usize) -> Token<'a> {
+    Token::U64(v as u64)
+}
+
+#[cfg(target_pointer_width = "32")]
+fn isize_token<'a>(v: isize) -> Token<'a> {
+    Token::I32(v as i32)
+}
+
+#[cfg(target_pointer_width = "32")]
+fn usize_token<'a>(v: usize) -> Token<'a> {
+    Token::U32(v as u32)
+}
+
+#[cfg(target_pointer_width = "16")]
+fn isize_token<'a>(v: isize) -> Token<'a> {
+    Token::I16(v as i16)
+}
+
+#[cfg(target_pointer_width = "16")]
+fn usize_token<'a>(v: usize) -> Token<'a> {
+    Token::U16(v as u16)
+}
+
+/// Returns an iterator that rewrites every integer token in `iter` to `I64`, or to `U64` when
+/// the value is larger than `i64::MAX`. All other tokens pass through untouched.
+///
+/// Two `Serialize` implementations of the same logical value may pick different integer types,
+/// for example `Vec<u8>` and `Vec<i64>`. After normalization their token streams are identical.
+pub fn normalize_numbers<'a, I>(iter: I) -> NormalizeNumbers<I::IntoIter>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    NormalizeNumbers {
+        iter: iter.into_iter(),
+        widen_floats: false,
+    }
+}
+
+/// Iterator returned by `normalize_numbers`.
+pub struct NormalizeNumbers<I> {
+    iter: I,
+    widen_floats: bool,
+}
+
+impl<I> NormalizeNumbers<I> {
+    /// Also rewrite `F32` tokens to `F64`.
+    pub fn widen_floats(mut self) -> Self {
+        self.widen_floats = true;
+        self
+    }
+}
+
+impl<'a, I> Iterator for NormalizeNumbers<I>
+    where I: Iterator<Item=Token<'a>>,
+{
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        self.iter.next().map(|token| {
+            match token {
+                Token::Isize(v) => Token::I64(v as i64),
+                Token::I8(v) => Token::I64(v as i64),
+                Token::I16(v) => Token::I64(v as i64),
+                Token::I32(v) => Token::I64(v as i64),
+                Token::Usize(v) => unsigned(v as u64),
+                Token::U8(v) => Token::I64(v as i64),
+                Token::U16(v) => Token::I64(v as i64),
+                Token::U32(v) => Token::I64(v as i64),
+                Token::U64(v) => unsigned(v),
+                Token::F32(v) if self.widen_floats => Token::F64(v as f64),
+                token => token,
+            }
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+fn unsigned<'a>(v: u64) -> Token<'a> {
+    if v <= i64::MAX as u64 {
+        Token::I64(v as i64)
+    } else {
+        Token::U64(v)
+    }
+}
@@ -0,0 +1,156 @@
+//! Replace struct field names with numbers, the way protobuf identifies fields.
+
+use std::collections::VecDeque;
+
+use super::{RecountingBuffer, Token};
+
+/// Returns an iterator that passes the tokens of `iter` through, with every struct turned into
+/// a map from the field numbers `f` returns for its field names to the field values.
+///
+/// Fields that `f` returns `None` for are dropped, and the length of the map counts only the
+/// fields that are left, so every struct is buffered until its end. Structs nested anywhere are
+/// numbered too. Struct variants are passed through with their field names.
+///
+/// ```rust
+/// use serde::token::{self, Token};
+///
+/// let tokens = vec![
+///     Token::StructStart("User", Some(2)),
+///     Token::StructSep, Token::Str("id"), Token::U64(7),
+///     Token::StructSep, Token::Str("cache"), Token::Bool(true),
+///     Token::StructEnd,
+/// ];
+/// let numbered: Vec<Token> = token::field_numbered(tokens, |field| {
+///     match field {
+///         "id" => Some(1),
+///         _ => None,
+///     }
+/// }).collect();
+/// assert_eq!(numbered, vec![
+///     Token::MapStart(Some(1)),
+///     Token::MapSep, Token::U32(1), Token::U64(7),
+///     Token::MapEnd,
+/// ]);
+/// ```
+pub fn field_numbered<'a, I, F>(iter: I, f: F) -> FieldNumbered<'a, I::IntoIter, F>
+    where I: IntoIterator<Item=Token<'a>>,
+          F: FnMut(&str) -> Option<u32>,
+{
+    FieldNumbered {
+        iter: iter.into_iter(),
+        f: f,
+        buffer: VecDeque::new(),
+    }
+}
+
+/// Iterator returned by `field_numbered`.
+pub struct FieldNumbered<'a, I, F> {
+    iter: I,
+    f: F,
+    /// The numbered tokens of the last struct.
+    buffer: VecDeque<Token<'a>>,
+}
+
+impl<'a, I, F> FieldNumbered<'a, I, F>
+    where I: Iterator<Item=Token<'a>>,
+          F: FnMut(&str) -> Option<u32>,
+{
+    /// Reads the value that starts with `first` into `out`, numbering the structs inside it.
+    fn value(&mut self, first: Token<'a>, out: &mut Vec<Token<'a>>) {
+        match first {
+            Token::StructStart(_, _) => out.extend(self.number_struct()),
+            Token::Option(true) |
+            Token::StructNewType(_) |
+            Token::TypeTag(_) |
+            Token::EnumNewType(_, _) => {
+                out.push(first);
+                if let Some(token) = self.iter.next() {
+                    self.value(token, out);
+                }
+            }
+            Token::EnumStart(_) => {
+                out.push(first);
+                // The variant, followed by the payload.
+                out.extend(self.iter.next());
+                if let Some(token) = self.iter.next() {
+                    self.value(token, out);
+                }
+            }
+            first => {
+                let compound = first.is_start();
+                out.push(first);
+                if !compound {
+                    return;
+                }
+
+                while let Some(token) = self.iter.next() {
+                    if token.is_end() {
+                        out.push(token);
+                        return;
+                    } else if token.is_sep() {
+                        out.push(token);
+                    } else {
+                        self.value(token, out);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reads the rest of a struct whose start token was just read, returning it as a map.
+    fn number_struct(&mut self) -> Vec<Token<'a>> {
+        let mut buffer = RecountingBuffer::new(Token::MapStart(None));
+
+        while let Some(token) = self.iter.next() {
+            match token {
+                Token::StructSep => {
+                    let number = match self.iter.next() {
+                        Some(key) => key.as_str().and_then(|field| (self.f)(field)),
+                        None => break,
+                    };
+                    let mut value = Vec::new();
+                    if let Some(first) = self.iter.next() {
+                        self.value(first, &mut value);
+                    }
+
+                    if let Some(number) = number {
+                        buffer.push(Token::MapSep);
+                        buffer.push(Token::U32(number));
+                        buffer.extend(value);
+                    }
+                }
+                Token::StructEnd => {
+                    buffer.push(Token::MapEnd);
+                    break;
+                }
+                token => {
+                    buffer.push(token);
+                }
+            }
+        }
+
+        buffer.finish()
+    }
+}
+
+impl<'a, I, F> Iterator for FieldNumbered<'a, I, F>
+    where I: Iterator<Item=Token<'a>>,
+          F: FnMut(&str) -> Option<u32>,
+{
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        if let Some(token) = self.buffer.pop_front() {
+            return Some(token);
+        }
+
+        match self.iter.next() {
+            Some(Token::StructStart(_, _)) => {
+                let tokens = self.number_struct();
+                self.buffer.extend(tokens);
+                self.buffer.pop_front()
+            }
+            token => token,
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! Reorder the fields of structs.
+//!
+//! Serializers write the fields of a struct in the order they are declared in. `order_fields`
+//! can sort them by name instead, or move a few of them to the front, for every struct or only
+//! for the structs with a given name. Map entries are never reordered, `canonicalize` sorts
+//! those.
+
+use std::collections::BTreeMap;
+
+use super::{Error, Token};
+
+/// The order in which the fields of a struct are written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FieldOrder {
+    /// The order in which the serializer wrote them, which is usually the order of their
+    /// declaration. This is the default.
+    Declaration,
+    /// Sorted by name.
+    Sorted,
+    /// The listed fields first, in the order of the list, followed by all other fields in the
+    /// order in which the serializer wrote them.
+    Custom(Vec<&'static str>),
+}
+
+impl Default for FieldOrder {
+    fn default() -> Self {
+        FieldOrder::Declaration
+    }
+}
+
+impl FieldOrder {
+    /// Sorts `fields`, which are in the order in which the serializer wrote them.
+    fn sort<T>(&self, fields: &mut Vec<(String, T)>) {
+        match *self {
+            FieldOrder::Declaration => { }
+            FieldOrder::Sorted => fields.sort_by(|a, b| a.0.cmp(&b.0)),
+            FieldOrder::Custom(ref first) => {
+                // The sort is stable, so the fields that aren't listed keep their order.
+                let rank = |name: &String| {
+                    first.iter().position(|first| first == name).unwrap_or(first.len())
+                };
+                fields.sort_by(|a, b| rank(&a.0).cmp(&rank(&b.0)));
+            }
+        }
+    }
+}
+
+/// The `FieldOrder` of every struct, by struct name.
+///
+/// ```rust
+/// use serde::token::{FieldOrder, FieldOrders};
+///
+/// // Sort the fields of all structs, but write `id` first in `User`.
+/// let orders = FieldOrders::new(FieldOrder::Sorted)
+///     .for_struct("User", FieldOrder::Custom(vec!["id"]));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FieldOrders {
+    default: FieldOrder,
+    by_name: BTreeMap<&'static str, FieldOrder>,
+}
+
+impl FieldOrders {
+    /// Construct a new `FieldOrders` that applies `order` to every struct.
+    pub fn new(order: FieldOrder) -> Self {
+        FieldOrders {
+            default: order,
+            by_name: BTreeMap::new(),
+        }
+    }
+
+    /// Applies `order` to the structs called `name` instead. The name of a struct variant is the
+    /// name of the variant.
+    pub fn for_struct(mut self, name: &'static str, order: FieldOrder) -> Self {
+        self.by_name.insert(name, order);
+        self
+    }
+
+    /// Returns the order of the fields of the structs called `name`.
+    pub fn get(&self, name: &str) -> &FieldOrder {
+        self.by_name.get(name).unwrap_or(&self.default)
+    }
+}
+
+/// Reorders the fields of every struct and struct variant in `iter` according to `orders`.
+///
+/// Structs nested in other values, including in the fields of other structs, are reordered as
+/// well, each one according to its own name.
+///
+/// ```rust
+/// use serde::token::{self, FieldOrder, FieldOrders, Token};
+///
+/// let tokens = vec![
+///     Token::StructStart("User", Some(3)),
+///     Token::StructSep, Token::Str("name"), Token::Str("Henry"),
+///     Token::StructSep, Token::Str("age"), Token::U8(3),
+///     Token::StructSep, Token::Str("id"), Token::U32(7),
+///     Token::StructEnd,
+/// ];
+/// let orders = FieldOrders::new(FieldOrder::Custom(vec!["id"]));
+/// let ordered = token::order_fields(tokens, &orders).unwrap();
+/// assert_eq!(token::to_json(ordered).unwrap(), r#"{"id":7,"name":"Henry","age":3}"#);
+/// ```
+pub fn order_fields<'a, I>(iter: I, orders: &FieldOrders) -> Result<Vec<Token<'a>>, Error>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let mut tokens = iter.into_iter();
+    let mut out = Vec::new();
+    try!(value(&mut tokens, orders, &mut out));
+    match tokens.next() {
+        None => Ok(out),
+        Some(token) => Err(unexpected(&token)),
+    }
+}
+
+/// Reads a value and appends it to `out` with its fields reordered.
+fn value<'a, I>(tokens: &mut I,
+                orders: &FieldOrders,
+                out: &mut Vec<Token<'a>>)
+                -> Result<(), Error>
+    where I: Iterator<Item=Token<'a>>,
+{
+    let token = try!(next(tokens));
+    match token {
+        Token::Option(true) |
+        Token::StructNewType(_) |
+        Token::TypeTag(_) |
+        Token::EnumNewType(_, _) => {
+            out.push(token);
+            value(tokens, orders, out)
+        }
+        Token::EnumStart(_) => {
+            out.push(token);
+            try!(value(tokens, orders, out));
+            value(tokens, orders, out)
+        }
+        Token::StructStart(name, _) | Token::EnumMapStart(_, name, _) => {
+            out.push(token);
+            fields(tokens, orders, orders.get(name), out)
+        }
+        Token::MapStart(_) => {
+            out.push(token);
+            elements(tokens, orders, 2, out)
+        }
+        ref token if token.is_start() => {
+            out.push(token.clone());
+            elements(tokens, orders, 1, out)
+        }
+        ref token if token.is_sep() || token.is_end() => Err(unexpected(token)),
+        token => {
+            out.push(token);
+            Ok(())
+        }
+    }
+}
+
+/// Copies the elements of a compound value up to its end token, each made of `values` values.
+fn elements<'a, I>(tokens: &mut I,
+                   orders: &FieldOrders,
+                   values: usize,
+                   out: &mut Vec<Token<'a>>)
+                   -> Result<(), Error>
+    where I: Iterator<Item=Token<'a>>,
+{
+    loop {
+        let token = try!(next(tokens));
+        if token.is_end() {
+            out.push(token);
+            return Ok(());
+        } else if !token.is_sep() {
+            return Err(unexpected(&token));
+        }
+        out.push(token);
+        for _ in 0..values {
+            try!(value(tokens, orders, out));
+        }
+    }
+}
+
+/// Reads the fields of a struct up to its end token and appends them to `out` in `order`.
+fn fields<'a, I>(tokens: &mut I,
+                 orders: &FieldOrders,
+                 order: &FieldOrder,
+                 out: &mut Vec<Token<'a>>)
+                 -> Result<(), Error>
+    where I: Iterator<Item=Token<'a>>,
+{
+    // Each field with its separator, name and value tokens.
+    let mut fields = Vec::new();
+    let end;
+    loop {
+        let sep = try!(next(tokens));
+        match sep {
+            Token::StructSep | Token::EnumMapSep => { }
+            Token::StructEnd | Token::EnumMapEnd => {
+                end = sep;
+                break;
+            }
+            token => return Err(unexpected(&token)),
+        }
+
+        let key = try!(next(tokens));
+        let name = match key.as_str() {
+            Some(name) => name.to_owned(),
+            None => return Err(unexpected(&key)),
+        };
+        let mut field = vec![sep, key];
+        try!(value(tokens, orders, &mut field));
+        fields.push((name, field));
+    }
+
+    order.sort(&mut fields);
+    for (_, field) in fields {
+        out.extend(field);
+    }
+    out.push(end);
+    Ok(())
+}
+
+fn next<'a, I>(tokens: &mut I) -> Result<Token<'a>, Error>
+    where I: Iterator<Item=Token<'a>>,
+{
+    tokens.next().ok_or_else(|| Error::InvalidValue("unexpected end of stream".to_owned()))
+}
+
+fn unexpected(token: &Token) -> Error {
+    Error::InvalidValue(format!("unexpected token {:?}", token))
+}
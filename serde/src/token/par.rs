@@ -0,0 +1,134 @@
+//! Split the serialization of a large sequence into independent chunks.
+//!
+//! The elements of a sequence don't depend on each other, so they can be serialized on separate
+//! tasks. `split_seq` partitions a slice into chunks that can each be turned into a complete
+//! sequence, and `concat_tokens` and `concat_json` stitch the results back together so the
+//! output is identical to serializing the whole slice at once. Spawning the tasks is left to the
+//! caller.
+
+use ser::Serialize;
+
+use super::{Error, Token, to_tokens};
+
+/// Splits `values` into at most `chunks` chunks of nearly equal size.
+///
+/// A `chunks` of zero is treated as one. Empty chunks are never produced, except that an empty
+/// slice results in a single empty chunk so there always is something to serialize.
+pub fn split_seq<T>(values: &[T], chunks: usize) -> Split<T> {
+    let chunks = if chunks == 0 { 1 } else { chunks };
+    let size = (values.len() + chunks - 1) / chunks;
+
+    let parts = if size == 0 {
+        vec![values]
+    } else {
+        values.chunks(size).collect()
+    };
+
+    Split {
+        len: values.len(),
+        chunks: parts,
+    }
+}
+
+/// The chunks returned by `split_seq`.
+pub struct Split<'a, T: 'a> {
+    len: usize,
+    chunks: Vec<&'a [T]>,
+}
+
+impl<'a, T> Split<'a, T> {
+    /// Returns the number of elements in the whole sequence.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the whole sequence is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the chunks.
+    pub fn chunks(&self) -> &[&'a [T]] {
+        &self.chunks
+    }
+
+    /// Returns the token that starts the whole sequence.
+    pub fn seq_start(&self) -> Token<'static> {
+        Token::SeqStart(Some(self.len))
+    }
+}
+
+impl<'a, T> Split<'a, T>
+    where T: Serialize,
+{
+    /// Records the tokens of the chunk at `index` as a complete sequence of its own.
+    pub fn chunk_tokens(&self, index: usize) -> Result<Vec<Token<'static>>, Error> {
+        to_tokens(self.chunks[index])
+    }
+}
+
+/// Joins sequences that were serialized separately into a single sequence.
+///
+/// Every part has to be a complete sequence as produced by `Split::chunk_tokens`. The result
+/// starts with a `SeqStart` whose length is the sum of the parts' lengths, or unknown when any
+/// part has an unknown length.
+pub fn concat_tokens<'a, I>(parts: I) -> Vec<Token<'a>>
+    where I: IntoIterator<Item=Vec<Token<'a>>>,
+{
+    let mut len = Some(0);
+    let mut tokens = vec![Token::SeqStart(None)];
+
+    for part in parts {
+        assert!(part.len() >= 2 && part[part.len() - 1] == Token::SeqEnd,
+                "chunk is not a sequence");
+        let middle = part.len() - 2;
+        let mut part = part.into_iter();
+
+        match part.next() {
+            Some(Token::SeqStart(part_len)) => {
+                len = match (len, part_len) {
+                    (Some(len), Some(part_len)) => Some(len + part_len),
+                    _ => None,
+                };
+            }
+            Some(Token::SeqArrayStart(part_len)) => len = len.map(|len| len + part_len),
+            _ => panic!("chunk is not a sequence"),
+        }
+
+        tokens.extend(part.take(middle));
+    }
+
+    tokens[0] = Token::SeqStart(len);
+    tokens.push(Token::SeqEnd);
+    tokens
+}
+
+/// Joins JSON arrays that were written separately into a single array.
+///
+/// Every part has to be a complete JSON array as written by `json::to_json`. The brackets of the
+/// parts are removed and the elements are joined with commas, so the result is byte for byte
+/// the same as writing the whole sequence at once.
+pub fn concat_json<I, S>(parts: I) -> String
+    where I: IntoIterator<Item=S>,
+          S: AsRef<str>,
+{
+    let mut out = String::from("[");
+
+    for part in parts {
+        let part = part.as_ref().trim();
+        assert!(part.starts_with('[') && part.ends_with(']'), "chunk is not a JSON array");
+
+        let elements = &part[1..part.len() - 1];
+        if elements.is_empty() {
+            continue;
+        }
+
+        if out.len() > 1 {
+            out.push(',');
+        }
+        out.push_str(elements);
+    }
+
+    out.push(']');
+    out
+}
@@ -0,0 +1,44 @@
+//! Record a key range of a `BTreeMap`, e.g. one page of a paginated listing.
+
+use std::collections::BTreeMap;
+
+use ser::{self, Serialize};
+use ser::impls::MapIteratorVisitor;
+
+use super::{Error, Serializer, Token};
+
+/// Records the entries of `map` whose keys lie in `[lower, upper)` as a map.
+///
+/// The entries in the range are counted before they are serialized, so the `MapStart` token
+/// carries the number of entries that actually follow.
+///
+/// ```rust
+/// use std::collections::BTreeMap;
+/// use serde::token::{self, Token};
+///
+/// let mut map = BTreeMap::new();
+/// map.insert(1, 'a');
+/// map.insert(2, 'b');
+/// let tokens = token::range_map_tokens(&map, &2, &10).unwrap();
+/// assert_eq!(tokens[0], Token::MapStart(Some(1)));
+/// ```
+pub fn range_map_tokens<K, V>(map: &BTreeMap<K, V>,
+                              lower: &K,
+                              upper: &K)
+                              -> Result<Vec<Token<'static>>, Error>
+    where K: Ord + Serialize,
+          V: Serialize,
+{
+    // `BTreeMap::range` needs a newer compiler, so walk the keys up to the range instead. A
+    // reversed range selects nothing.
+    let range = || {
+        map.iter()
+            .skip_while(move |&(key, _)| key < lower)
+            .take_while(move |&(key, _)| key < upper)
+    };
+    let len = range().count();
+
+    let mut ser = Serializer::new();
+    try!(ser::Serializer::serialize_map(&mut ser, MapIteratorVisitor::new(range(), Some(len))));
+    Ok(ser.into_tokens())
+}
@@ -0,0 +1,105 @@
+//! Buffer a single container to announce the number of elements it really has.
+//!
+//! Adapters that add or drop the fields of a struct or the entries of a map can't know the new
+//! length until the container ends, but readers of binary formats trust the length in the start
+//! token. `RecountingBuffer` holds the tokens of one container and rewrites its start token once
+//! the container is complete.
+
+use std::iter::Extend;
+
+use super::Token;
+
+/// Buffers the tokens of a container and counts its elements.
+///
+/// ```rust
+/// use serde::token::{RecountingBuffer, Token};
+///
+/// let mut buffer = RecountingBuffer::new(Token::StructStart("P", Some(2)));
+/// buffer.extend(vec![Token::StructSep, Token::Str("x"), Token::I32(1), Token::StructEnd]);
+/// assert!(buffer.is_complete());
+/// assert_eq!(buffer.finish(), vec![
+///     Token::StructStart("P", Some(1)),
+///     Token::StructSep, Token::Str("x"), Token::I32(1),
+///     Token::StructEnd,
+/// ]);
+/// ```
+pub struct RecountingBuffer<'a> {
+    tokens: Vec<Token<'a>>,
+    /// How many containers are open, the buffered one included.
+    depth: usize,
+    /// The number of separators of the buffered container.
+    len: usize,
+}
+
+impl<'a> RecountingBuffer<'a> {
+    /// Starts buffering the container opened by `start`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `start` doesn't open a container.
+    pub fn new(start: Token<'a>) -> Self {
+        assert!(start.is_start(), "expected the start of a container, found {:?}", start);
+        RecountingBuffer {
+            tokens: vec![start],
+            depth: 1,
+            len: 0,
+        }
+    }
+
+    /// Appends the next token of the container, returning true once the token that ends it was
+    /// pushed. Tokens after the end are buffered but not counted.
+    pub fn push(&mut self, token: Token<'a>) -> bool {
+        if token.is_start() {
+            self.depth += 1;
+        } else if token.is_end() {
+            self.depth = self.depth.saturating_sub(1);
+        } else if token.is_sep() && self.depth == 1 {
+            self.len += 1;
+        }
+        self.tokens.push(token);
+        self.is_complete()
+    }
+
+    /// Returns true if the token that ends the container was pushed.
+    pub fn is_complete(&self) -> bool {
+        self.depth == 0
+    }
+
+    /// Returns the number of elements, entries or fields pushed so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if no element was pushed yet.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the buffered tokens, with a start token that announces the number of elements
+    /// that were pushed.
+    pub fn finish(mut self) -> Vec<Token<'a>> {
+        let len = self.len;
+        self.tokens[0] = match self.tokens[0] {
+            Token::SeqStart(_) => Token::SeqStart(Some(len)),
+            Token::SeqArrayStart(_) => Token::SeqArrayStart(len),
+            Token::TupleStart(_) => Token::TupleStart(len),
+            Token::TupleStructStart(name, _) => Token::TupleStructStart(name, Some(len)),
+            Token::MapStart(_) => Token::MapStart(Some(len)),
+            Token::StructStart(name, _) => Token::StructStart(name, Some(len)),
+            Token::EnumSeqStart(name, variant, _) => Token::EnumSeqStart(name, variant, Some(len)),
+            Token::EnumMapStart(name, variant, _) => Token::EnumMapStart(name, variant, Some(len)),
+            ref token => unreachable!("not the start of a container: {:?}", token),
+        };
+        self.tokens
+    }
+}
+
+impl<'a> Extend<Token<'a>> for RecountingBuffer<'a> {
+    fn extend<T>(&mut self, iter: T)
+        where T: IntoIterator<Item=Token<'a>>,
+    {
+        for token in iter {
+            self.push(token);
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! Rename structs, enums and enum variants in token streams.
+//!
+//! The names a `Serialize` implementation passes to its `Serializer` are fixed by the type. The
+//! adapters in this module rewrite them afterwards, so the same type can be written under
+//! different names without a wrapper type for every format.
+
+use ser::Serialize;
+
+use super::{Error, Token, to_tokens};
+
+/// A name that appears in a token stream.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Name<'a> {
+    /// The name of a struct, tuple struct, unit struct, newtype struct or enum.
+    Type(&'a str),
+    /// The name of a variant, together with the name of its enum.
+    Variant(&'a str, &'a str),
+}
+
+/// Returns an iterator that passes the tokens of `iter` through, with every struct, enum and
+/// variant name replaced by what `f` returns for it.
+///
+/// Replacements have to live as long as the tokens. Owned replacements can be kept in an
+/// `intern::Interner` that outlives the stream.
+///
+/// ```rust
+/// use serde::token::{self, Token};
+/// use serde::token::rename::Name;
+///
+/// let tokens = vec![Token::UnitStruct("Marker")];
+/// let renamed: Vec<Token> = token::rename(tokens, |name| {
+///     match name {
+///         Name::Type("Marker") => "Tag",
+///         Name::Type(name) | Name::Variant(_, name) => name,
+///     }
+/// }).collect();
+/// assert_eq!(renamed, vec![Token::UnitStruct("Tag")]);
+/// ```
+pub fn rename<'a, I, F>(iter: I, f: F) -> Rename<I::IntoIter, F>
+    where I: IntoIterator<Item=Token<'a>>,
+          F: FnMut(Name<'a>) -> &'a str,
+{
+    Rename {
+        iter: iter.into_iter(),
+        f: f,
+    }
+}
+
+/// Iterator returned by `rename`.
+pub struct Rename<I, F> {
+    iter: I,
+    f: F,
+}
+
+impl<'a, I, F> Iterator for Rename<I, F>
+    where I: Iterator<Item=Token<'a>>,
+          F: FnMut(Name<'a>) -> &'a str,
+{
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let f = &mut self.f;
+        self.iter.next().map(|token| {
+            match token {
+                Token::UnitStruct(name) => Token::UnitStruct(f(Name::Type(name))),
+                Token::StructNewType(name) => Token::StructNewType(f(Name::Type(name))),
+                Token::TypeTag(name) => Token::TypeTag(f(Name::Type(name))),
+                Token::EnumStart(name) => Token::EnumStart(f(Name::Type(name))),
+                Token::TupleStructStart(name, len) => {
+                    Token::TupleStructStart(f(Name::Type(name)), len)
+                }
+                Token::StructStart(name, len) => Token::StructStart(f(Name::Type(name)), len),
+                Token::EnumUnit(name, variant) => {
+                    let variant = f(Name::Variant(name, variant));
+                    Token::EnumUnit(f(Name::Type(name)), variant)
+                }
+                Token::EnumNewType(name, variant) => {
+                    let variant = f(Name::Variant(name, variant));
+                    Token::EnumNewType(f(Name::Type(name)), variant)
+                }
+                Token::EnumSeqStart(name, variant, len) => {
+                    let variant = f(Name::Variant(name, variant));
+                    Token::EnumSeqStart(f(Name::Type(name)), variant, len)
+                }
+                Token::EnumMapStart(name, variant, len) => {
+                    let variant = f(Name::Variant(name, variant));
+                    Token::EnumMapStart(f(Name::Type(name)), variant, len)
+                }
+                token => token,
+            }
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A value together with the names it should be recorded under.
+///
+/// ```rust
+/// use serde::token::{Renamed, Token};
+///
+/// let tokens = Renamed::new(&Some(())).rename_type("Unused", "Other").to_tokens().unwrap();
+/// assert_eq!(tokens, vec![Token::Option(true), Token::Unit]);
+/// ```
+pub struct Renamed<'a, T: ?Sized + 'a> {
+    value: &'a T,
+    types: Vec<(&'a str, &'a str)>,
+    variants: Vec<(&'a str, &'a str, &'a str)>,
+}
+
+impl<'a, T: ?Sized> Renamed<'a, T>
+    where T: Serialize,
+{
+    /// Wraps `value` without renaming anything yet.
+    pub fn new(value: &'a T) -> Self {
+        Renamed {
+            value: value,
+            types: Vec::new(),
+            variants: Vec::new(),
+        }
+    }
+
+    /// Renames the struct or enum `from` to `to`.
+    pub fn rename_type(mut self, from: &'a str, to: &'a str) -> Self {
+        self.types.push((from, to));
+        self
+    }
+
+    /// Renames the variant `from` of the enum `name` to `to`. `name` is the original name of the
+    /// enum, even if it is renamed too.
+    pub fn rename_variant(mut self, name: &'a str, from: &'a str, to: &'a str) -> Self {
+        self.variants.push((name, from, to));
+        self
+    }
+
+    /// Records the tokens of the value with the new names.
+    pub fn to_tokens(&self) -> Result<Vec<Token<'a>>, Error> {
+        let tokens: Vec<Token<'a>> = try!(to_tokens(self.value));
+        let types = &self.types;
+        let variants = &self.variants;
+
+        Ok(rename(tokens, |name| {
+            match name {
+                Name::Type(name) => {
+                    types.iter().find(|entry| entry.0 == name).map_or(name, |entry| entry.1)
+                }
+                Name::Variant(name, variant) => {
+                    variants.iter()
+                        .find(|entry| entry.0 == name && entry.1 == variant)
+                        .map_or(variant, |entry| entry.2)
+                }
+            }
+        }).collect())
+    }
+}
@@ -0,0 +1,357 @@
+//! Convert structs into maps and maps into structs, and tuples into sequences.
+//!
+//! Many formats write structs and maps the same way, and tools that only care about the data
+//! are simpler when they only have to handle one of them. `as_map` degrades every struct in a
+//! stream into a map with string keys, `as_struct` promotes a map back into a struct of a given
+//! type. Likewise `tuples_as_seqs` degrades every tuple into a sequence, for formats like JSON
+//! that only have arrays.
+
+use std::collections::VecDeque;
+
+use super::{Error, RecountingBuffer, Token};
+
+/// The key under which `AsMap::keep_name` records the name of a struct.
+pub const NAME_KEY: &'static str = "__name__";
+
+/// Returns an iterator that rewrites every struct in `iter` into a map with the field names as
+/// keys. The name of the struct is dropped unless `AsMap::keep_name` is set. Struct variants of
+/// enums are left alone.
+///
+/// ```rust
+/// use serde::token::{self, Token};
+///
+/// let tokens = vec![
+///     Token::StructStart("P", Some(1)),
+///     Token::StructSep, Token::Str("x"), Token::I32(1),
+///     Token::StructEnd,
+/// ];
+/// let map: Vec<Token> = token::as_map(tokens).collect();
+/// assert_eq!(map, vec![
+///     Token::MapStart(Some(1)),
+///     Token::MapSep, Token::Str("x"), Token::I32(1),
+///     Token::MapEnd,
+/// ]);
+/// ```
+pub fn as_map<'a, I>(iter: I) -> AsMap<'a, I::IntoIter>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    AsMap {
+        iter: iter.into_iter(),
+        keep_name: false,
+        pending: VecDeque::new(),
+    }
+}
+
+/// Iterator returned by `as_map`.
+pub struct AsMap<'a, I> {
+    iter: I,
+    keep_name: bool,
+    /// Tokens to yield before reading more of `iter`.
+    pending: VecDeque<Token<'a>>,
+}
+
+impl<'a, I> AsMap<'a, I> {
+    /// Records the name of every struct as the first entry of its map, under `NAME_KEY`, so that
+    /// `as_struct` can restore nested structs.
+    pub fn keep_name(mut self) -> Self {
+        self.keep_name = true;
+        self
+    }
+}
+
+impl<'a, I> Iterator for AsMap<'a, I>
+    where I: Iterator<Item=Token<'a>>,
+{
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        if let Some(token) = self.pending.pop_front() {
+            return Some(token);
+        }
+
+        self.iter.next().map(|token| {
+            match token {
+                Token::StructStart(name, len) => {
+                    if self.keep_name {
+                        self.pending.push_back(Token::MapSep);
+                        self.pending.push_back(Token::Str(NAME_KEY));
+                        self.pending.push_back(Token::Str(name));
+                        Token::MapStart(len.map(|len| len + 1))
+                    } else {
+                        Token::MapStart(len)
+                    }
+                }
+                Token::StructSep => Token::MapSep,
+                Token::StructEnd => Token::MapEnd,
+                token => token,
+            }
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.iter.size_hint();
+        let pending = self.pending.len();
+        (lower + pending, if self.keep_name { None } else { upper.map(|upper| upper + pending) })
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Returns an iterator that rewrites every tuple in `iter` into a sequence of the same length.
+/// Tuple structs and tuple variants of enums are left alone.
+///
+/// ```rust
+/// use serde::token::{self, Token};
+///
+/// let tokens = token::to_tokens(&(1, "a")).unwrap();
+/// let seq: Vec<Token> = token::tuples_as_seqs(tokens).collect();
+/// assert_eq!(seq, vec![
+///     Token::SeqStart(Some(2)),
+///     Token::SeqSep, Token::I32(1),
+///     Token::SeqSep, Token::String("a".to_owned()),
+///     Token::SeqEnd,
+/// ]);
+/// ```
+pub fn tuples_as_seqs<'a, I>(iter: I) -> TuplesAsSeqs<I::IntoIter>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    TuplesAsSeqs {
+        iter: iter.into_iter(),
+    }
+}
+
+/// Iterator returned by `tuples_as_seqs`.
+pub struct TuplesAsSeqs<I> {
+    iter: I,
+}
+
+impl<'a, I> Iterator for TuplesAsSeqs<I>
+    where I: Iterator<Item=Token<'a>>,
+{
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        self.iter.next().map(|token| {
+            match token {
+                Token::TupleStart(len) => Token::SeqStart(Some(len)),
+                Token::TupleSep => Token::SeqSep,
+                Token::TupleEnd => Token::SeqEnd,
+                token => token,
+            }
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Rewrites the map in `iter` into the struct `name` with the given `fields`.
+///
+/// The entries are put in the order of `fields`. Fields that the map lacks are left out, keys
+/// that are not in `fields` are an error, and a `NAME_KEY` entry is dropped. Nested maps that
+/// carry a `NAME_KEY` entry with a borrowed string, as written by `AsMap::keep_name`, become
+/// structs of that name in turn. Other nested maps stay maps.
+///
+/// ```rust
+/// use serde::token::{self, Token};
+///
+/// let tokens = vec![
+///     Token::MapStart(Some(2)),
+///     Token::MapSep, Token::Str("y"), Token::I32(2),
+///     Token::MapSep, Token::Str("x"), Token::I32(1),
+///     Token::MapEnd,
+/// ];
+/// assert_eq!(token::as_struct(tokens, "P", &["x", "y"]).unwrap(), vec![
+///     Token::StructStart("P", Some(2)),
+///     Token::StructSep, Token::Str("x"), Token::I32(1),
+///     Token::StructSep, Token::Str("y"), Token::I32(2),
+///     Token::StructEnd,
+/// ]);
+/// ```
+pub fn as_struct<'a, I>(iter: I, name: &'a str, fields: &[&str]) -> Result<Vec<Token<'a>>, Error>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let mut tokens = iter.into_iter();
+    match try!(next(&mut tokens)) {
+        Token::MapStart(_) => { }
+        token => {
+            return Err(Error::InvalidValue(format!("expected a map, found {:?}", token)));
+        }
+    }
+
+    let mut entries = try!(read_entries(&mut tokens));
+    let mut out = RecountingBuffer::new(Token::StructStart(name, None));
+    for (index, &(ref key, _)) in entries.iter().enumerate() {
+        let seen = |field| entries[..index].iter().any(|entry| field_name(&entry.0) == Some(field));
+        match field_name(key) {
+            Some(field) if seen(field) => {
+                return Err(Error::InvalidValue(format!("duplicate field {:?} of {}", field, name)));
+            }
+            Some(field) if field == NAME_KEY || fields.contains(&field) => { }
+            Some(field) => {
+                return Err(Error::InvalidValue(format!("unknown field {:?} of {}", field, name)));
+            }
+            None => {
+                return Err(Error::InvalidValue(format!("expected a field name, found {:?}",
+                                                       key)));
+            }
+        }
+    }
+    for field in fields {
+        let position = entries.iter().position(|&(ref key, _)| field_name(key) == Some(*field));
+        if let Some(position) = position {
+            let (key, value) = entries.remove(position);
+            out.push(Token::StructSep);
+            out.extend(key);
+            out.extend(value);
+        }
+    }
+    out.push(Token::StructEnd);
+
+    if let Some(token) = tokens.next() {
+        return Err(unexpected(&token));
+    }
+    Ok(out.finish())
+}
+
+/// Reads the entries of the map whose start token was just read, promoting the nested maps
+/// that name their struct.
+fn read_entries<'a, I>(tokens: &mut I) -> Result<Vec<(Vec<Token<'a>>, Vec<Token<'a>>)>, Error>
+    where I: Iterator<Item=Token<'a>>,
+{
+    let mut entries = Vec::new();
+    loop {
+        match try!(next(tokens)) {
+            Token::MapSep => { }
+            Token::MapEnd => return Ok(entries),
+            token => return Err(unexpected(&token)),
+        }
+        let key = try!(read_value(tokens));
+        let value = try!(read_value(tokens));
+        entries.push((key, value));
+    }
+}
+
+/// Reads the tokens of a single value.
+fn read_value<'a, I>(tokens: &mut I) -> Result<Vec<Token<'a>>, Error>
+    where I: Iterator<Item=Token<'a>>,
+{
+    let mut out = Vec::new();
+    try!(copy_value(tokens, &mut out));
+    Ok(out)
+}
+
+fn copy_value<'a, I>(tokens: &mut I, out: &mut Vec<Token<'a>>) -> Result<(), Error>
+    where I: Iterator<Item=Token<'a>>,
+{
+    let token = try!(next(tokens));
+    match token {
+        Token::MapStart(len) => {
+            let entries = try!(read_entries(tokens));
+            promote(entries, len, out);
+            Ok(())
+        }
+        Token::Option(true) |
+        Token::StructNewType(_) |
+        Token::TypeTag(_) |
+        Token::EnumNewType(_, _) => {
+            out.push(token);
+            copy_value(tokens, out)
+        }
+        Token::EnumStart(_) => {
+            // The variant identifier followed by its payload.
+            out.push(token);
+            try!(copy_value(tokens, out));
+            copy_value(tokens, out)
+        }
+        token => {
+            if !token.is_start() {
+                if token.is_sep() || token.is_end() {
+                    return Err(unexpected(&token));
+                }
+                out.push(token);
+                return Ok(());
+            }
+
+            let named_fields = match token {
+                Token::StructStart(_, _) | Token::EnumMapStart(_, _, _) => true,
+                _ => false,
+            };
+            out.push(token);
+            loop {
+                let token = try!(next(tokens));
+                if token.is_end() {
+                    out.push(token);
+                    return Ok(());
+                }
+                if !token.is_sep() {
+                    return Err(unexpected(&token));
+                }
+                out.push(token);
+                if named_fields {
+                    out.push(try!(next(tokens)));
+                }
+                try!(copy_value(tokens, out));
+            }
+        }
+    }
+}
+
+/// Appends the map made of `entries` to `out`, as a struct if it names one.
+fn promote<'a>(entries: Vec<(Vec<Token<'a>>, Vec<Token<'a>>)>,
+               len: Option<usize>,
+               out: &mut Vec<Token<'a>>) {
+    let name = entries.iter()
+        .find(|&&(ref key, _)| field_name(key) == Some(NAME_KEY))
+        .and_then(|&(_, ref value)| {
+            match value.first() {
+                Some(&Token::Str(name)) if value.len() == 1 => Some(name),
+                _ => None,
+            }
+        });
+    let all_named = entries.iter().all(|&(ref key, _)| field_name(key).is_some());
+
+    match name {
+        Some(name) if all_named => {
+            out.push(Token::StructStart(name, Some(entries.len() - 1)));
+            for (key, value) in entries {
+                if field_name(&key) != Some(NAME_KEY) {
+                    out.push(Token::StructSep);
+                    out.extend(key);
+                    out.extend(value);
+                }
+            }
+            out.push(Token::StructEnd);
+        }
+        _ => {
+            out.push(Token::MapStart(len));
+            for (key, value) in entries {
+                out.push(Token::MapSep);
+                out.extend(key);
+                out.extend(value);
+            }
+            out.push(Token::MapEnd);
+        }
+    }
+}
+
+/// Returns the key if it is a single string.
+fn field_name<'b>(key: &'b [Token]) -> Option<&'b str> {
+    match key.len() {
+        1 => key[0].as_str(),
+        _ => None,
+    }
+}
+
+fn next<'a, I>(tokens: &mut I) -> Result<Token<'a>, Error>
+    where I: Iterator<Item=Token<'a>>,
+{
+    tokens.next().ok_or_else(|| Error::InvalidValue("unexpected end of stream".to_owned()))
+}
+
+fn unexpected(token: &Token) -> Error {
+    Error::InvalidValue(format!("unexpected token {:?}", token))
+}
@@ -0,0 +1,379 @@
+//! Infer a rough description of the types in a sample value.
+//!
+//! `infer_schema` walks a token stream and records what kind of value it found at every place,
+//! e.g. "a sequence of structs with an integer field `id` and a string field `name`". Where the
+//! sample disagrees with itself, like a sequence holding both integers and strings, the types
+//! are merged into a `Union`. `Schema::to_json_schema` renders the result in the vocabulary of
+//! JSON Schema.
+
+use std::collections::BTreeMap;
+use std::mem;
+
+use super::{Error, Token, Value};
+
+/// The inferred type of a value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Schema {
+    /// Nothing was seen, e.g. the elements of an empty sequence.
+    Unknown,
+    /// `None`, `()` or a unit struct.
+    Null,
+    /// A boolean.
+    Bool,
+    /// An integer of any width.
+    Int,
+    /// A floating point number.
+    Float,
+    /// A string, a character or a timestamp.
+    Str,
+    /// A byte array.
+    Bytes,
+    /// A sequence, tuple or tuple struct whose elements have the given type.
+    Seq(Box<Schema>),
+    /// A map whose keys and values have the given types.
+    Map(Box<Schema>, Box<Schema>),
+    /// A struct with the given fields, in the order they were first seen. A field that is
+    /// missing from some of the samples is merged with `Null`.
+    Struct(Vec<(String, Schema)>),
+    /// An enum with the given variants and the types of their payloads, `Null` for unit
+    /// variants.
+    Enum(Vec<(String, Schema)>),
+    /// Any of the given types, which are all of different kinds.
+    Union(Vec<Schema>),
+}
+
+impl Schema {
+    /// Returns the type that describes both `self` and `other`.
+    ///
+    /// ```rust
+    /// use serde::token::schema::Schema;
+    ///
+    /// let seq = Schema::Seq(Box::new(Schema::Int));
+    /// assert_eq!(seq.clone().merge(Schema::Seq(Box::new(Schema::Unknown))), seq);
+    /// assert_eq!(Schema::Int.merge(Schema::Null), Schema::Union(vec![Schema::Int, Schema::Null]));
+    /// ```
+    pub fn merge(self, other: Schema) -> Schema {
+        match (self, other) {
+            (Schema::Unknown, other) => other,
+            (schema, Schema::Unknown) => schema,
+            (Schema::Seq(a), Schema::Seq(b)) => Schema::Seq(Box::new(a.merge(*b))),
+            (Schema::Map(ak, av), Schema::Map(bk, bv)) => {
+                Schema::Map(Box::new(ak.merge(*bk)), Box::new(av.merge(*bv)))
+            }
+            (Schema::Struct(a), Schema::Struct(b)) => Schema::Struct(merge_fields(a, b, true)),
+            (Schema::Enum(a), Schema::Enum(b)) => Schema::Enum(merge_fields(a, b, false)),
+            (Schema::Union(members), other) => union(members, other),
+            (schema, Schema::Union(members)) => {
+                let mut merged = vec![schema];
+                for member in members {
+                    merged = match union(merged, member) {
+                        Schema::Union(merged) => merged,
+                        schema => vec![schema],
+                    };
+                }
+                Schema::Union(merged)
+            }
+            (a, b) => {
+                if a.kind() == b.kind() {
+                    a
+                } else {
+                    Schema::Union(vec![a, b])
+                }
+            }
+        }
+    }
+
+    /// Returns true if `None` is allowed.
+    pub fn is_nullable(&self) -> bool {
+        match *self {
+            Schema::Unknown | Schema::Null => true,
+            Schema::Union(ref members) => members.iter().any(Schema::is_nullable),
+            _ => false,
+        }
+    }
+
+    /// Renders the schema in the vocabulary of JSON Schema, describing the JSON the JSON writer
+    /// produces for the sample.
+    ///
+    /// Structs list the fields that are never null as required. Byte arrays are arrays of
+    /// integers, and enums objects with a single property named after the variant.
+    pub fn to_json_schema(&self) -> Value {
+        match *self {
+            Schema::Unknown => Value::Map(BTreeMap::new()),
+            Schema::Null => type_schema("null"),
+            Schema::Bool => type_schema("boolean"),
+            Schema::Int => type_schema("integer"),
+            Schema::Float => type_schema("number"),
+            Schema::Str => type_schema("string"),
+            Schema::Bytes => array_schema(type_schema("integer")),
+            Schema::Seq(ref element) => array_schema(element.to_json_schema()),
+            Schema::Map(_, ref value) => {
+                let mut schema = type_map("object");
+                schema.insert("additionalProperties".to_owned(), value.to_json_schema());
+                Value::Map(schema)
+            }
+            Schema::Struct(ref fields) => object_schema(fields, true),
+            Schema::Enum(ref variants) => {
+                let variants = variants.iter().map(|variant| {
+                    object_schema(&[(variant.0.clone(), variant.1.clone())], false)
+                });
+                let mut schema = BTreeMap::new();
+                schema.insert("oneOf".to_owned(), Value::Seq(variants.collect()));
+                Value::Map(schema)
+            }
+            Schema::Union(ref members) => {
+                let mut schema = BTreeMap::new();
+                let members = members.iter().map(Schema::to_json_schema).collect();
+                schema.insert("anyOf".to_owned(), Value::Seq(members));
+                Value::Map(schema)
+            }
+        }
+    }
+
+    /// Identifies the variant, so that two types of the same kind are merged rather than put
+    /// into a union next to each other.
+    fn kind(&self) -> usize {
+        match *self {
+            Schema::Unknown => 0,
+            Schema::Null => 1,
+            Schema::Bool => 2,
+            Schema::Int => 3,
+            Schema::Float => 4,
+            Schema::Str => 5,
+            Schema::Bytes => 6,
+            Schema::Seq(_) => 7,
+            Schema::Map(_, _) => 8,
+            Schema::Struct(_) => 9,
+            Schema::Enum(_) => 10,
+            Schema::Union(_) => 11,
+        }
+    }
+}
+
+/// Adds `schema` to the members of a union, merging it with the member of the same kind.
+fn union(mut members: Vec<Schema>, schema: Schema) -> Schema {
+    if let Schema::Union(others) = schema {
+        let mut merged = Schema::Union(members);
+        for other in others {
+            merged = merged.merge(other);
+        }
+        return merged;
+    }
+
+    match members.iter().position(|member| member.kind() == schema.kind()) {
+        Some(index) => {
+            let member = mem::replace(&mut members[index], Schema::Unknown);
+            members[index] = member.merge(schema);
+        }
+        None => members.push(schema),
+    }
+    if members.len() == 1 {
+        members.pop().expect("a union has members")
+    } else {
+        Schema::Union(members)
+    }
+}
+
+/// Merges the fields of two structs or the variants of two enums. Struct fields that only one
+/// side has become nullable.
+fn merge_fields(a: Vec<(String, Schema)>,
+                b: Vec<(String, Schema)>,
+                optional: bool) -> Vec<(String, Schema)> {
+    let mut b: Vec<Option<(String, Schema)>> = b.into_iter().map(Some).collect();
+    let mut merged = Vec::new();
+
+    for (name, schema) in a {
+        let other = b.iter_mut()
+            .find(|field| field.as_ref().map_or(false, |field| field.0 == name))
+            .and_then(Option::take);
+        let schema = match other {
+            Some((_, other)) => schema.merge(other),
+            None if optional => schema.merge(Schema::Null),
+            None => schema,
+        };
+        merged.push((name, schema));
+    }
+    for (name, schema) in b.into_iter().filter_map(|field| field) {
+        let schema = if optional { schema.merge(Schema::Null) } else { schema };
+        merged.push((name, schema));
+    }
+    merged
+}
+
+fn type_map(name: &str) -> BTreeMap<String, Value> {
+    let mut schema = BTreeMap::new();
+    schema.insert("type".to_owned(), Value::String(name.to_owned()));
+    schema
+}
+
+fn type_schema(name: &str) -> Value {
+    Value::Map(type_map(name))
+}
+
+fn array_schema(items: Value) -> Value {
+    let mut schema = type_map("array");
+    schema.insert("items".to_owned(), items);
+    Value::Map(schema)
+}
+
+fn object_schema(fields: &[(String, Schema)], only_non_null: bool) -> Value {
+    let mut properties = BTreeMap::new();
+    let mut required = Vec::new();
+    for &(ref name, ref schema) in fields {
+        properties.insert(name.clone(), schema.to_json_schema());
+        if !only_non_null || !schema.is_nullable() {
+            required.push(Value::String(name.clone()));
+        }
+    }
+
+    let mut schema = type_map("object");
+    schema.insert("properties".to_owned(), Value::Map(properties));
+    schema.insert("required".to_owned(), Value::Seq(required));
+    Value::Map(schema)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Infers the type of the single value in `iter`.
+///
+/// The types of the elements of a sequence, and of the keys and values of a map, are merged
+/// into one. Field names and variant names are kept.
+///
+/// ```rust
+/// use serde::token::{self, json};
+/// use serde::token::schema::Schema;
+///
+/// let tokens = json::from_str(r#"[{"id": 1, "tags": []}, {"id": 2.5, "tags": ["a"]}]"#).unwrap();
+/// assert_eq!(token::infer_schema(tokens).unwrap(), Schema::Seq(Box::new(
+///     Schema::Map(Box::new(Schema::Str), Box::new(Schema::Union(vec![
+///         Schema::Int,
+///         Schema::Seq(Box::new(Schema::Str)),
+///         Schema::Float,
+///     ]))),
+/// )));
+/// ```
+pub fn infer_schema<'a, I>(iter: I) -> Result<Schema, Error>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let mut tokens = iter.into_iter();
+    let schema = try!(value(&mut tokens));
+    match tokens.next() {
+        None => Ok(schema),
+        Some(token) => Err(unexpected(&token)),
+    }
+}
+
+fn next<'a, I>(tokens: &mut I) -> Result<Token<'a>, Error>
+    where I: Iterator<Item=Token<'a>>,
+{
+    tokens.next().ok_or_else(|| Error::InvalidValue("unexpected end of stream".to_owned()))
+}
+
+fn value<'a, I>(tokens: &mut I) -> Result<Schema, Error>
+    where I: Iterator<Item=Token<'a>>,
+{
+    let schema = match try!(next(tokens)) {
+        Token::Bool(_) => Schema::Bool,
+        Token::Isize(_) | Token::I8(_) | Token::I16(_) | Token::I32(_) | Token::I64(_) |
+        Token::Usize(_) | Token::U8(_) | Token::U16(_) | Token::U32(_) | Token::U64(_) |
+        Token::BigNum(_) => Schema::Int,
+        Token::F32(_) | Token::F64(_) => Schema::Float,
+        Token::Char(_) | Token::Str(_) | Token::String(_) | Token::Timestamp(_, _) => Schema::Str,
+        Token::Bytes(_) | Token::ByteBuf(_) => Schema::Bytes,
+
+        Token::Option(false) | Token::Unit | Token::UnitStruct(_) => Schema::Null,
+        Token::Option(true) | Token::StructNewType(_) | Token::TypeTag(_) => try!(value(tokens)),
+
+        Token::EnumStart(_) => {
+            let variant = try!(next(tokens));
+            let name = match variant.as_str() {
+                Some(name) => name.to_owned(),
+                None => return Err(unexpected(&variant)),
+            };
+            Schema::Enum(vec![(name, try!(value(tokens)))])
+        }
+        Token::EnumUnit(_, variant) => Schema::Enum(vec![(variant.to_owned(), Schema::Null)]),
+        Token::EnumNewType(_, variant) => {
+            Schema::Enum(vec![(variant.to_owned(), try!(value(tokens)))])
+        }
+
+        Token::SeqStart(_) |
+        Token::SeqArrayStart(_) |
+        Token::TupleStart(_) |
+        Token::TupleStructStart(_, _) => try!(elements(tokens)),
+        Token::EnumSeqStart(_, variant, _) => {
+            Schema::Enum(vec![(variant.to_owned(), try!(elements(tokens)))])
+        }
+        Token::MapStart(_) => try!(entries(tokens)),
+        Token::StructStart(_, _) => try!(fields(tokens)),
+        Token::EnumMapStart(_, variant, _) => {
+            Schema::Enum(vec![(variant.to_owned(), try!(fields(tokens)))])
+        }
+
+        token => return Err(unexpected(&token)),
+    };
+    Ok(schema)
+}
+
+/// Infers the element type of the sequence whose start token was just read.
+fn elements<'a, I>(tokens: &mut I) -> Result<Schema, Error>
+    where I: Iterator<Item=Token<'a>>,
+{
+    let mut element = Schema::Unknown;
+    loop {
+        match try!(next(tokens)) {
+            ref token if token.is_sep() => element = element.merge(try!(value(tokens))),
+            ref token if token.is_end() => return Ok(Schema::Seq(Box::new(element))),
+            token => return Err(unexpected(&token)),
+        }
+    }
+}
+
+/// Infers the key and value types of the map whose start token was just read.
+fn entries<'a, I>(tokens: &mut I) -> Result<Schema, Error>
+    where I: Iterator<Item=Token<'a>>,
+{
+    let (mut key, mut val) = (Schema::Unknown, Schema::Unknown);
+    loop {
+        match try!(next(tokens)) {
+            Token::MapSep => {
+                key = key.merge(try!(value(tokens)));
+                val = val.merge(try!(value(tokens)));
+            }
+            Token::MapEnd => return Ok(Schema::Map(Box::new(key), Box::new(val))),
+            token => return Err(unexpected(&token)),
+        }
+    }
+}
+
+/// Infers the field types of the struct whose start token was just read.
+fn fields<'a, I>(tokens: &mut I) -> Result<Schema, Error>
+    where I: Iterator<Item=Token<'a>>,
+{
+    let mut fields: Vec<(String, Schema)> = Vec::new();
+    loop {
+        match try!(next(tokens)) {
+            Token::StructSep | Token::EnumMapSep => {
+                let key = try!(next(tokens));
+                let name = match key.as_str() {
+                    Some(name) => name.to_owned(),
+                    None => return Err(unexpected(&key)),
+                };
+                let schema = try!(value(tokens));
+                match fields.iter().position(|field| field.0 == name) {
+                    Some(index) => {
+                        let field = mem::replace(&mut fields[index].1, Schema::Unknown);
+                        fields[index].1 = field.merge(schema);
+                    }
+                    None => fields.push((name, schema)),
+                }
+            }
+            Token::StructEnd | Token::EnumMapEnd => return Ok(Schema::Struct(fields)),
+            token => return Err(unexpected(&token)),
+        }
+    }
+}
+
+fn unexpected(token: &Token) -> Error {
+    Error::InvalidValue(format!("unexpected token {:?}", token))
+}
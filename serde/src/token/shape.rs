@@ -0,0 +1,213 @@
+//! Check a token stream against a simple description of its shape.
+//!
+//! A `Shape` describes what a value has to look like, e.g. "a map whose values are all
+//! scalars", without caring about the concrete values. `check_shape` walks the stream and the
+//! shape in lockstep and reports the first place where they disagree.
+
+use std::error;
+use std::fmt;
+
+use super::Token;
+
+/// The expected shape of a value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shape {
+    /// A primitive, a string, a byte array, a unit or a unit variant.
+    Scalar,
+    /// A sequence, tuple or tuple struct whose elements all have the given shape.
+    Seq(Box<Shape>),
+    /// A map whose keys and values have the given shapes.
+    Map(Box<Shape>, Box<Shape>),
+    /// Any struct, regardless of its fields.
+    AnyStruct,
+}
+
+impl Shape {
+    fn description(&self) -> &'static str {
+        match *self {
+            Shape::Scalar => "a scalar",
+            Shape::Seq(_) => "a sequence",
+            Shape::Map(_, _) => "a map",
+            Shape::AnyStruct => "a struct",
+        }
+    }
+}
+
+/// The error returned by `check_shape`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShapeError {
+    path: String,
+    expected: &'static str,
+    found: String,
+}
+
+impl ShapeError {
+    /// Returns the path to the mismatching value, e.g. `map.value.seq[2]`. The path of the
+    /// outermost value is empty.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns a description of what was expected.
+    pub fn expected(&self) -> &str {
+        self.expected
+    }
+
+    /// Returns a description of the token that was found instead.
+    pub fn found(&self) -> &str {
+        &self.found
+    }
+}
+
+impl fmt::Display for ShapeError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let path = if self.path.is_empty() { "(root)" } else { &self.path };
+        write!(formatter, "at {}: expected {}, found {}", path, self.expected, self.found)
+    }
+}
+
+impl error::Error for ShapeError {
+    fn description(&self) -> &str {
+        "token stream does not match the expected shape"
+    }
+}
+
+/// Checks that the single value in `iter` has the given `shape`.
+///
+/// `Some` and newtype structs are checked against the shape of their contents, and `None` is
+/// accepted in place of any shape.
+pub fn check_shape<'a, I>(iter: I, shape: &Shape) -> Result<(), ShapeError>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let mut checker = Checker {
+        tokens: iter.into_iter(),
+        path: Vec::new(),
+    };
+
+    try!(checker.value(shape));
+
+    match checker.tokens.next() {
+        None => Ok(()),
+        Some(token) => Err(checker.error("the end of the stream", Some(token))),
+    }
+}
+
+struct Checker<I> {
+    tokens: I,
+    path: Vec<String>,
+}
+
+impl<'a, I> Checker<I>
+    where I: Iterator<Item=Token<'a>>,
+{
+    fn value(&mut self, shape: &Shape) -> Result<(), ShapeError> {
+        let token = match self.tokens.next() {
+            Some(token) => token,
+            None => return Err(self.error(shape.description(), None)),
+        };
+
+        match (token, shape) {
+            (Token::Option(false), _) => Ok(()),
+            (Token::Option(true), _) |
+            (Token::StructNewType(_), _) |
+            (Token::TypeTag(_), _) => self.value(shape),
+
+            (Token::Bool(_), &Shape::Scalar) |
+            (Token::Isize(_), &Shape::Scalar) |
+            (Token::I8(_), &Shape::Scalar) |
+            (Token::I16(_), &Shape::Scalar) |
+            (Token::I32(_), &Shape::Scalar) |
+            (Token::I64(_), &Shape::Scalar) |
+            (Token::Usize(_), &Shape::Scalar) |
+            (Token::U8(_), &Shape::Scalar) |
+            (Token::U16(_), &Shape::Scalar) |
+            (Token::U32(_), &Shape::Scalar) |
+            (Token::U64(_), &Shape::Scalar) |
+            (Token::F32(_), &Shape::Scalar) |
+            (Token::F64(_), &Shape::Scalar) |
+            (Token::Char(_), &Shape::Scalar) |
+            (Token::Str(_), &Shape::Scalar) |
+            (Token::String(_), &Shape::Scalar) |
+            (Token::Bytes(_), &Shape::Scalar) |
+            (Token::ByteBuf(_), &Shape::Scalar) |
+            (Token::Timestamp(_, _), &Shape::Scalar) |
+            (Token::BigNum(_), &Shape::Scalar) |
+            (Token::Unit, &Shape::Scalar) |
+            (Token::UnitStruct(_), &Shape::Scalar) |
+            (Token::EnumUnit(_, _), &Shape::Scalar) => Ok(()),
+
+            (Token::SeqStart(_), &Shape::Seq(ref elem)) |
+            (Token::SeqArrayStart(_), &Shape::Seq(ref elem)) |
+            (Token::TupleStart(_), &Shape::Seq(ref elem)) |
+            (Token::TupleStructStart(_, _), &Shape::Seq(ref elem)) => self.seq(elem),
+
+            (Token::MapStart(_), &Shape::Map(ref key, ref value)) => self.map(key, value),
+
+            (Token::StructStart(_, _), &Shape::AnyStruct) => self.skip_to_end(),
+
+            (token, shape) => Err(self.error(shape.description(), Some(token))),
+        }
+    }
+
+    fn seq(&mut self, elem: &Shape) -> Result<(), ShapeError> {
+        let mut index = 0;
+        loop {
+            match self.tokens.next() {
+                Some(ref token) if token.is_sep() => {
+                    self.path.push(format!("seq[{}]", index));
+                    try!(self.value(elem));
+                    self.path.pop();
+                    index += 1;
+                }
+                Some(ref token) if token.is_end() => return Ok(()),
+                token => return Err(self.error("a sequence element", token)),
+            }
+        }
+    }
+
+    fn map(&mut self, key: &Shape, value: &Shape) -> Result<(), ShapeError> {
+        loop {
+            match self.tokens.next() {
+                Some(Token::MapSep) => {
+                    self.path.push("map.key".to_owned());
+                    try!(self.value(key));
+                    self.path.pop();
+
+                    self.path.push("map.value".to_owned());
+                    try!(self.value(value));
+                    self.path.pop();
+                }
+                Some(Token::MapEnd) => return Ok(()),
+                token => return Err(self.error("a map entry", token)),
+            }
+        }
+    }
+
+    fn skip_to_end(&mut self) -> Result<(), ShapeError> {
+        let mut depth = 0;
+        loop {
+            match self.tokens.next() {
+                Some(ref token) if token.is_start() => depth += 1,
+                Some(ref token) if token.is_end() => {
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                    depth -= 1;
+                }
+                Some(_) => {}
+                None => return Err(self.error("the end of the struct", None)),
+            }
+        }
+    }
+
+    fn error(&self, expected: &'static str, found: Option<Token>) -> ShapeError {
+        ShapeError {
+            path: self.path.join("."),
+            expected: expected,
+            found: match found {
+                Some(token) => format!("{:?}", token),
+                None => "the end of the stream".to_owned(),
+            },
+        }
+    }
+}
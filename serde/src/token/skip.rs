@@ -0,0 +1,202 @@
+//! Drop struct fields from token streams depending on their values.
+//!
+//! `Option` fields that are `None` can be left out of a compact output format, and so can empty
+//! collections or zero counters. `skip_if` decides this per field with a `SkipRule`, which names
+//! a struct, one of its fields and a predicate over the tokens of the field's value.
+
+use std::collections::VecDeque;
+
+use super::{RecountingBuffer, Token};
+
+/// Drops the field `field` of the struct `name` whenever its value matches a predicate.
+pub struct SkipRule {
+    name: &'static str,
+    field: &'static str,
+    predicate: Box<Fn(&[Token]) -> bool>,
+}
+
+impl SkipRule {
+    /// Construct a new `SkipRule`. The predicate is handed all tokens of the field's value, so it
+    /// can look inside compound values too.
+    pub fn new<P>(name: &'static str, field: &'static str, predicate: P) -> Self
+        where P: Fn(&[Token]) -> bool + 'static,
+    {
+        SkipRule {
+            name: name,
+            field: field,
+            predicate: Box::new(predicate),
+        }
+    }
+}
+
+/// A predicate for `SkipRule` that matches `None`.
+pub fn is_none(value: &[Token]) -> bool {
+    value.len() == 1 && value[0] == Token::Option(false)
+}
+
+/// A predicate for `SkipRule` that matches empty sequences, arrays and tuples.
+pub fn is_empty_seq(value: &[Token]) -> bool {
+    if value.len() != 2 {
+        return false;
+    }
+    match (&value[0], &value[1]) {
+        (&Token::SeqStart(_), &Token::SeqEnd) |
+        (&Token::SeqArrayStart(_), &Token::SeqEnd) |
+        (&Token::TupleStart(_), &Token::TupleEnd) => true,
+        _ => false,
+    }
+}
+
+/// A predicate for `SkipRule` that matches numbers equal to zero.
+pub fn is_zero(value: &[Token]) -> bool {
+    if value.len() != 1 {
+        return false;
+    }
+    match value[0] {
+        Token::Isize(0) | Token::I8(0) | Token::I16(0) | Token::I32(0) | Token::I64(0) |
+        Token::Usize(0) | Token::U8(0) | Token::U16(0) | Token::U32(0) | Token::U64(0) => true,
+        Token::F32(v) => v == 0.0,
+        Token::F64(v) => v == 0.0,
+        _ => false,
+    }
+}
+
+/// Returns an iterator that passes the tokens of `iter` through, except for the struct fields
+/// that one of `rules` says to skip.
+///
+/// The `StructStart` token of every struct that a rule names announces the number of fields
+/// that are left, which means the struct is buffered in a `RecountingBuffer` until its
+/// `StructEnd`. Other
+/// values pass through one token at a time. Struct variants are never touched.
+///
+/// ```rust
+/// use serde::token::{self, Token};
+/// use serde::token::skip::{self, SkipRule};
+///
+/// let tokens = vec![
+///     Token::StructStart("Page", Some(2)),
+///         Token::StructSep, Token::Str("title"), Token::Str("Home"),
+///         Token::StructSep, Token::Str("parent"), Token::Option(false),
+///     Token::StructEnd,
+/// ];
+/// let rules = vec![SkipRule::new("Page", "parent", skip::is_none)];
+/// let json = token::to_json(skip::skip_if(tokens, rules)).unwrap();
+/// assert_eq!(json, r#"{"title":"Home"}"#);
+/// ```
+pub fn skip_if<'a, I>(iter: I, rules: Vec<SkipRule>) -> SkipIf<'a, I::IntoIter>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    SkipIf {
+        iter: iter.into_iter(),
+        rules: rules,
+        buffer: VecDeque::new(),
+    }
+}
+
+/// Iterator returned by `skip_if`.
+pub struct SkipIf<'a, I> {
+    iter: I,
+    rules: Vec<SkipRule>,
+    /// The filtered tokens of the last struct a rule applied to.
+    buffer: VecDeque<Token<'a>>,
+}
+
+impl<'a, I> SkipIf<'a, I>
+    where I: Iterator<Item=Token<'a>>,
+{
+    fn has_rules(&self, name: &str) -> bool {
+        self.rules.iter().any(|rule| rule.name == name)
+    }
+
+    /// Reads the value that starts with `first` into `out`, filtering the structs inside it.
+    fn value(&mut self, first: Token<'a>, out: &mut Vec<Token<'a>>) {
+        match first {
+            Token::StructStart(name, len) if self.has_rules(name) => {
+                self.filter_struct(name, len, out)
+            }
+            first => {
+                let compound = first.is_start();
+                out.push(first);
+                if !compound {
+                    return;
+                }
+
+                while let Some(token) = self.iter.next() {
+                    if token.is_end() {
+                        out.push(token);
+                        return;
+                    } else if token.is_sep() {
+                        out.push(token);
+                    } else {
+                        self.value(token, out);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reads the rest of a struct that rules apply to into `out`, leaving out the skipped fields.
+    fn filter_struct(&mut self, name: &'a str, len: Option<usize>, out: &mut Vec<Token<'a>>) {
+        let mut buffer = RecountingBuffer::new(Token::StructStart(name, len));
+
+        while let Some(token) = self.iter.next() {
+            match token {
+                Token::StructSep => {
+                    let key = match self.iter.next() {
+                        Some(key) => key,
+                        None => {
+                            buffer.push(Token::StructSep);
+                            break;
+                        }
+                    };
+                    let mut value = Vec::new();
+                    if let Some(first) = self.iter.next() {
+                        self.value(first, &mut value);
+                    }
+
+                    let skip = key.as_str().map_or(false, |field| {
+                        self.rules.iter().any(|rule| {
+                            rule.name == name && rule.field == field && (rule.predicate)(&value)
+                        })
+                    });
+                    if !skip {
+                        buffer.push(Token::StructSep);
+                        buffer.push(key);
+                        buffer.extend(value);
+                    }
+                }
+                Token::StructEnd => {
+                    buffer.push(Token::StructEnd);
+                    break;
+                }
+                token => {
+                    buffer.push(token);
+                }
+            }
+        }
+
+        out.extend(buffer.finish());
+    }
+}
+
+impl<'a, I> Iterator for SkipIf<'a, I>
+    where I: Iterator<Item=Token<'a>>,
+{
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        if let Some(token) = self.buffer.pop_front() {
+            return Some(token);
+        }
+
+        match self.iter.next() {
+            Some(Token::StructStart(name, len)) if self.has_rules(name) => {
+                let mut out = Vec::new();
+                self.filter_struct(name, len, &mut out);
+                self.buffer.extend(out);
+                self.buffer.pop_front()
+            }
+            token => token,
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Render the line of an input that a parse error points at.
+
+use std::cmp;
+use std::fmt;
+
+/// The widest line, in characters, that a `Snippet` shows in full.
+pub const MAX_WIDTH: usize = 80;
+
+/// The line of the input that an error was found in, kept so that the error can show it.
+///
+/// It displays like a compiler diagnostic, with a caret under the column of the error. Lines
+/// wider than `MAX_WIDTH` characters are cut down to the part around the caret, with `...` where
+/// text was left out and a note saying so.
+///
+/// ```rust
+/// use serde::token::json;
+///
+/// let err = json::from_str("{\"a\": tru}").unwrap_err();
+/// assert_eq!(format!("{:#}", err), concat!("expected value at line 1 column 7\n",
+///                                          "  |\n",
+///                                          "1 | {\"a\": tru}\n",
+///                                          "  |       ^"));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snippet {
+    line: usize,
+    text: String,
+    /// The number of characters of `text` before the caret.
+    caret: usize,
+    truncated: bool,
+}
+
+impl Snippet {
+    /// Cuts the line containing the byte at `pos` out of `input`. A `pos` at the end of `input`
+    /// points just past its last character.
+    pub fn new(input: &[u8], pos: usize) -> Snippet {
+        let start = input[..pos].iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |newline| newline + 1);
+        let end = input[pos..].iter()
+            .position(|&b| b == b'\n')
+            .map_or(input.len(), |len| pos + len);
+        let line = input[..start].iter().filter(|&&b| b == b'\n').count() + 1;
+
+        let before: Vec<char> = String::from_utf8_lossy(&input[start..pos]).chars().collect();
+        let after: Vec<char> = String::from_utf8_lossy(&input[pos..end])
+            .trim_right_matches('\r')
+            .chars()
+            .collect();
+
+        let mut chars = before.clone();
+        chars.extend(after);
+        if chars.len() <= MAX_WIDTH {
+            return Snippet {
+                line: line,
+                text: chars.into_iter().collect(),
+                caret: before.len(),
+                truncated: false,
+            };
+        }
+
+        // Keep the caret in the middle of the window, or as close to it as the line allows.
+        let from = cmp::min(before.len().saturating_sub(MAX_WIDTH / 2), chars.len() - MAX_WIDTH);
+        let to = from + MAX_WIDTH;
+        let mut text = String::new();
+        let mut caret = before.len() - from;
+        if from > 0 {
+            text.push_str("...");
+            caret += 3;
+        }
+        text.extend(&chars[from..to]);
+        if to < chars.len() {
+            text.push_str("...");
+        }
+        Snippet {
+            line: line,
+            text: text,
+            caret: caret,
+            truncated: true,
+        }
+    }
+
+    /// Returns the snippet with its line numbered `lines` further, for an input that was cut out
+    /// of a larger one after its first `lines` lines.
+    pub fn shift_lines(mut self, lines: usize) -> Snippet {
+        self.line += lines;
+        self
+    }
+
+    /// Returns the one based number of the line.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Returns the text of the line as it is shown.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns whether the line was too wide to be shown in full.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+impl fmt::Display for Snippet {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let number = self.line.to_string();
+        let margin: String = number.chars().map(|_| ' ').collect();
+        // Tabs before the caret stay tabs, so that the caret lines up however they are shown.
+        let indent: String = self.text.chars()
+            .take(self.caret)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        try!(write!(formatter, "{} |\n{} |", margin, number));
+        if !self.text.is_empty() {
+            try!(write!(formatter, " {}", self.text));
+        }
+        try!(write!(formatter, "\n{} | {}^", margin, indent));
+        if self.truncated {
+            try!(write!(formatter, "\n{} = note: the line is longer than {} characters and was \
+                                    shortened",
+                        margin,
+                        MAX_WIDTH));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,209 @@
+//! Check strings for control characters before writing them.
+//!
+//! Strict consumers of JSON and of line based formats choke on raw control characters in
+//! strings. `check_strings` finds them in a stream without changing it, and `escape_strings`
+//! rewrites the strings that contain them, for writers like `to_table` that don't escape
+//! anything themselves. A control character is one that `char::is_control` says is, i.e. U+0000
+//! to U+001F and U+007F to U+009F.
+//!
+//! The payloads of `Str` and `String` tokens are Rust strings, so they are valid UTF-8 by
+//! construction. Bytes that might not be are carried by `Bytes` tokens, which aren't checked.
+
+use std::borrow::Cow;
+use std::error;
+use std::fmt;
+
+use super::Token;
+
+/// What `check_strings` and `escape_strings` do with control characters in strings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ControlCharPolicy {
+    /// Fail on the first string that contains one.
+    Reject,
+    /// Replace each with a `\u0000` style escape. This is the default, and what the JSON writer
+    /// does on its own.
+    Escape,
+    /// Leave them as they are.
+    Allow,
+}
+
+impl Default for ControlCharPolicy {
+    fn default() -> Self {
+        ControlCharPolicy::Escape
+    }
+}
+
+/// The error returned for a control character under `ControlCharPolicy::Reject`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StringError {
+    index: usize,
+    offset: usize,
+    c: char,
+}
+
+impl StringError {
+    /// Returns the index of the token holding the string.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the byte offset of the control character in the string.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the control character.
+    pub fn char(&self) -> char {
+        self.c
+    }
+}
+
+impl fmt::Display for StringError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter,
+               "token #{}: control character U+{:04X} at byte {} of the string",
+               self.index,
+               self.c as u32,
+               self.offset)
+    }
+}
+
+impl error::Error for StringError {
+    fn description(&self) -> &str {
+        "control character in a string"
+    }
+}
+
+/// Checks every `Str`, `String` and `Char` token of `iter`, including map keys and field names,
+/// against `policy`. Only `ControlCharPolicy::Reject` can fail; the other policies accept
+/// every string, since `Escape` is up to the writer or to `escape_strings`.
+///
+/// ```rust
+/// use serde::token::{self, ControlCharPolicy, Token};
+///
+/// let tokens = vec![Token::Str("tab\there")];
+/// let err = token::check_strings(tokens.clone(), ControlCharPolicy::Reject).unwrap_err();
+/// assert_eq!(err.offset(), 3);
+/// assert!(token::check_strings(tokens, ControlCharPolicy::Escape).is_ok());
+/// ```
+pub fn check_strings<'a, I>(iter: I, policy: ControlCharPolicy) -> Result<(), StringError>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    if policy != ControlCharPolicy::Reject {
+        return Ok(());
+    }
+    for (index, token) in iter.into_iter().enumerate() {
+        try!(check(index, &token));
+    }
+    Ok(())
+}
+
+/// Applies `policy` to the strings of `iter`: rejecting a control character ends the stream
+/// with an error, and escaping one turns its token into a `String` with the escape in its
+/// place. Strings without control characters pass through as they are.
+///
+/// ```rust
+/// use serde::token::{self, ControlCharPolicy, Token};
+///
+/// let tokens: Vec<_> = token::escape_strings(vec![Token::Str("a\u{7}b")],
+///                                             ControlCharPolicy::Escape)
+///     .collect::<Result<_, _>>()
+///     .unwrap();
+/// assert_eq!(tokens, vec![Token::String("a\\u0007b".to_owned())]);
+/// ```
+pub fn escape_strings<'a, I>(iter: I, policy: ControlCharPolicy) -> EscapeStrings<I::IntoIter>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    EscapeStrings {
+        iter: iter.into_iter(),
+        policy: policy,
+        index: 0,
+        done: false,
+    }
+}
+
+/// Iterator returned by `escape_strings`.
+#[derive(Clone, Debug)]
+pub struct EscapeStrings<I> {
+    iter: I,
+    policy: ControlCharPolicy,
+    index: usize,
+    done: bool,
+}
+
+impl<'a, I> Iterator for EscapeStrings<I>
+    where I: Iterator<Item=Token<'a>>,
+{
+    type Item = Result<Token<'a>, StringError>;
+
+    fn next(&mut self) -> Option<Result<Token<'a>, StringError>> {
+        if self.done {
+            return None;
+        }
+        let token = match self.iter.next() {
+            Some(token) => token,
+            None => return None,
+        };
+        let index = self.index;
+        self.index += 1;
+
+        match self.policy {
+            ControlCharPolicy::Allow => Some(Ok(token)),
+            ControlCharPolicy::Reject => {
+                if let Err(err) = check(index, &token) {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+                Some(Ok(token))
+            }
+            ControlCharPolicy::Escape => {
+                let escaped = match token {
+                    Token::Char(c) if c.is_control() => Some(escape(&c.to_string())),
+                    ref token => token.as_str().and_then(|v| {
+                        if v.chars().any(char::is_control) { Some(escape(v)) } else { None }
+                    }),
+                };
+                Some(Ok(escaped.map_or(token, Token::String)))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.done { (0, Some(0)) } else { self.iter.size_hint() }
+    }
+}
+
+/// Returns an error for the first control character in the string of `token`, if any.
+fn check(index: usize, token: &Token) -> Result<(), StringError> {
+    let text = match *token {
+        Token::Char(c) => Cow::Owned(c.to_string()),
+        ref token => {
+            match token.as_str() {
+                Some(v) => Cow::Borrowed(v),
+                None => return Ok(()),
+            }
+        }
+    };
+    match text.char_indices().find(|&(_, c)| c.is_control()) {
+        Some((offset, c)) => {
+            Err(StringError {
+                index: index,
+                offset: offset,
+                c: c,
+            })
+        }
+        None => Ok(()),
+    }
+}
+
+fn escape(v: &str) -> String {
+    let mut out = String::with_capacity(v.len() + 5);
+    for c in v.chars() {
+        if c.is_control() {
+            out.push_str(&format!("\\u{:04x}", c as u32));
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
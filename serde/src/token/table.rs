@@ -0,0 +1,117 @@
+//! Render a struct as an aligned table for terminal output.
+
+use super::{Error, Token};
+use super::time;
+
+/// Renders the struct or map in `iter` as a table with a row per entry, the field names in the
+/// first column and the values in the second.
+///
+/// The names are padded on the left to the width of the widest one, so that they line up with
+/// their values, which are separated from them by two spaces. Every row ends with a newline and
+/// has no trailing spaces. Strings are written without quotes and `None` as an empty cell. Only
+/// scalar values fit into a cell, so nested compound values are an error.
+///
+/// ```rust
+/// use serde::token::{self, Token};
+///
+/// let tokens = vec![
+///     Token::StructStart("User", Some(2)),
+///     Token::StructSep, Token::Str("name"), Token::Str("Henry"),
+///     Token::StructSep, Token::Str("id"), Token::U32(7),
+///     Token::StructEnd,
+/// ];
+/// assert_eq!(token::to_table(tokens).unwrap(), "name  Henry\n  id  7\n");
+/// ```
+pub fn to_table<'a, I>(iter: I) -> Result<String, Error>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let mut tokens = iter.into_iter();
+    match try!(next(&mut tokens)) {
+        Token::StructStart(_, _) | Token::MapStart(_) => { }
+        token => {
+            return Err(Error::InvalidValue(format!("expected a struct or map, found {:?}",
+                                                   token)));
+        }
+    }
+
+    let mut rows = Vec::new();
+    loop {
+        match try!(next(&mut tokens)) {
+            Token::StructSep | Token::MapSep => { }
+            Token::StructEnd | Token::MapEnd => break,
+            token => return Err(unexpected(&token)),
+        }
+
+        let key = try!(next(&mut tokens));
+        let name = match key.as_str() {
+            Some(name) => name.to_owned(),
+            None => try!(cell(&key)),
+        };
+        let mut value = try!(next(&mut tokens));
+        loop {
+            match value {
+                Token::Option(true) | Token::StructNewType(_) | Token::TypeTag(_) => {
+                    value = try!(next(&mut tokens))
+                }
+                _ => break,
+            }
+        }
+        let value = try!(cell(&value).map_err(|_| {
+            Error::InvalidValue(format!("field {:?} is not a scalar, found {:?}", name, value))
+        }));
+        rows.push((name, value));
+    }
+
+    if let Some(token) = tokens.next() {
+        return Err(unexpected(&token));
+    }
+
+    let width = rows.iter().map(|&(ref name, _)| name.chars().count()).max().unwrap_or(0);
+    let mut table = String::new();
+    for (name, value) in rows {
+        let row = format!("{:>width$}  {}", name, value, width = width);
+        table.push_str(row.trim_right());
+        table.push('\n');
+    }
+    Ok(table)
+}
+
+/// Returns the text of a scalar token.
+fn cell(token: &Token) -> Result<String, Error> {
+    let text = match *token {
+        Token::Bool(v) => v.to_string(),
+        Token::Isize(v) => v.to_string(),
+        Token::I8(v) => v.to_string(),
+        Token::I16(v) => v.to_string(),
+        Token::I32(v) => v.to_string(),
+        Token::I64(v) => v.to_string(),
+        Token::Usize(v) => v.to_string(),
+        Token::U8(v) => v.to_string(),
+        Token::U16(v) => v.to_string(),
+        Token::U32(v) => v.to_string(),
+        Token::U64(v) => v.to_string(),
+        Token::F32(v) => v.to_string(),
+        Token::F64(v) => v.to_string(),
+        Token::Char(v) => v.to_string(),
+        Token::Str(v) => v.to_owned(),
+        Token::String(ref v) | Token::BigNum(ref v) => v.clone(),
+        Token::Bytes(v) => format!("{:?}", v),
+        Token::ByteBuf(ref v) => format!("{:?}", v),
+        Token::Timestamp(secs, nanos) => time::format_rfc3339(secs, nanos),
+        Token::Option(false) | Token::Unit => String::new(),
+        Token::UnitStruct(name) => name.to_owned(),
+        Token::EnumUnit(_, variant) => variant.to_owned(),
+        ref token => return Err(unexpected(token)),
+    };
+    Ok(text)
+}
+
+fn next<'a, I>(tokens: &mut I) -> Result<Token<'a>, Error>
+    where I: Iterator<Item=Token<'a>>,
+{
+    tokens.next().ok_or_else(|| Error::InvalidValue("unexpected end of stream".to_owned()))
+}
+
+fn unexpected(token: &Token) -> Error {
+    Error::InvalidValue(format!("unexpected token {:?}", token))
+}
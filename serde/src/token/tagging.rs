@@ -0,0 +1,670 @@
+//! Rewrite enums into the tagged map forms that many JSON consumers expect.
+//!
+//! The writers render a variant as a map with the variant name as its single key, e.g.
+//! `{"Frog": ["Henry", 349]}`. `adjacently_tagged` puts the name and the payload under keys of
+//! their own instead: `{"type": "Frog", "value": ["Henry", 349]}`. `internally_tagged` adds the
+//! name to the fields of the payload: `{"type": "Settings", "host": "x", "port": 1}`.
+//!
+//! `with_type_names` records the type of every struct and enum instead, wrapping it as
+//! `{"__type__": "Point", "value": {"x": 1, "y": 2}}`, and a `TypeRegistry` reads such values
+//! back into boxed trait objects of the registered types.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use super::{Error, Token};
+use super::find::skip_value;
+
+/// The default key of the variant name.
+pub const TAG_KEY: &'static str = "type";
+
+/// The default key of the payload of a variant.
+pub const CONTENT_KEY: &'static str = "value";
+
+/// Returns an iterator that rewrites every enum variant in `iter` into a map with the variant
+/// name under `TAG_KEY` and the payload under `CONTENT_KEY`. The keys can be changed with
+/// `AdjacentlyTagged::keys`.
+///
+/// Unit variants only get the tag. The elements of tuple variants become a sequence, the fields
+/// of struct variants a map. Both the variants written by serializers, like `EnumSeqStart`, and
+/// the `EnumStart` token followed by the variant and its payload that deserializers read are
+/// rewritten.
+///
+/// ```rust
+/// use serde::token::{self, Token};
+///
+/// let tokens = vec![
+///     Token::EnumSeqStart("Animal", "Frog", Some(2)),
+///     Token::EnumSeqSep, Token::Str("Henry"),
+///     Token::EnumSeqSep, Token::I32(349),
+///     Token::EnumSeqEnd,
+/// ];
+/// let tagged = token::adjacently_tagged(tokens);
+/// assert_eq!(token::to_json(tagged).unwrap(), r#"{"type":"Frog","value":["Henry",349]}"#);
+/// ```
+pub fn adjacently_tagged<'a, I>(iter: I) -> AdjacentlyTagged<'a, I::IntoIter>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    AdjacentlyTagged {
+        iter: iter.into_iter(),
+        tag: TAG_KEY,
+        content: CONTENT_KEY,
+        pending: VecDeque::new(),
+        depth: 0,
+        open: Vec::new(),
+        expect_variant: false,
+    }
+}
+
+/// Iterator returned by `adjacently_tagged`.
+pub struct AdjacentlyTagged<'a, I> {
+    iter: I,
+    tag: &'a str,
+    content: &'a str,
+    /// Tokens to yield before reading more of `iter`.
+    pending: VecDeque<Token<'a>>,
+    /// The number of compound values of the input that are open.
+    depth: usize,
+    /// The depths at which newtype variants and `EnumStart` variants wait for their payload, whose
+    /// end closes their map.
+    open: Vec<usize>,
+    /// True when the token after an `EnumStart` is the variant.
+    expect_variant: bool,
+}
+
+impl<'a, I> AdjacentlyTagged<'a, I> {
+    /// Sets the keys of the variant name and of the payload.
+    pub fn keys(mut self, tag: &'a str, content: &'a str) -> Self {
+        self.tag = tag;
+        self.content = content;
+        self
+    }
+
+    /// Queues the start of a tagged map whose payload follows.
+    fn start(&mut self, variant: Token<'a>) {
+        self.pending.push_back(Token::MapStart(Some(2)));
+        self.pending.push_back(Token::MapSep);
+        self.pending.push_back(Token::Str(self.tag));
+        self.pending.push_back(variant);
+        self.pending.push_back(Token::MapSep);
+        self.pending.push_back(Token::Str(self.content));
+    }
+
+    /// Closes the maps of the variants whose payload ended with the last token.
+    fn value_done(&mut self) {
+        while self.open.last() == Some(&self.depth) {
+            self.open.pop();
+            self.pending.push_back(Token::MapEnd);
+        }
+    }
+}
+
+impl<'a, I> Iterator for AdjacentlyTagged<'a, I>
+    where I: Iterator<Item=Token<'a>>,
+{
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        while self.pending.is_empty() {
+            let token = match self.iter.next() {
+                Some(token) => token,
+                None => return None,
+            };
+
+            if self.expect_variant {
+                self.expect_variant = false;
+                self.start(token);
+                continue;
+            }
+
+            match token {
+                Token::EnumStart(_) => {
+                    self.open.push(self.depth);
+                    self.expect_variant = true;
+                }
+                Token::EnumUnit(_, variant) => {
+                    self.pending.push_back(Token::MapStart(Some(1)));
+                    self.pending.push_back(Token::MapSep);
+                    self.pending.push_back(Token::Str(self.tag));
+                    self.pending.push_back(Token::Str(variant));
+                    self.pending.push_back(Token::MapEnd);
+                    self.value_done();
+                }
+                Token::EnumNewType(_, variant) => {
+                    self.open.push(self.depth);
+                    self.start(Token::Str(variant));
+                }
+                Token::EnumSeqStart(_, variant, len) => {
+                    self.depth += 1;
+                    self.start(Token::Str(variant));
+                    self.pending.push_back(Token::SeqStart(len));
+                }
+                Token::EnumMapStart(_, variant, len) => {
+                    self.depth += 1;
+                    self.start(Token::Str(variant));
+                    self.pending.push_back(Token::MapStart(len));
+                }
+                Token::EnumSeqSep => self.pending.push_back(Token::SeqSep),
+                Token::EnumMapSep => self.pending.push_back(Token::MapSep),
+                Token::EnumSeqEnd | Token::EnumMapEnd => {
+                    self.depth = self.depth.saturating_sub(1);
+                    self.pending.push_back(if token == Token::EnumSeqEnd {
+                        Token::SeqEnd
+                    } else {
+                        Token::MapEnd
+                    });
+                    self.pending.push_back(Token::MapEnd);
+                    self.value_done();
+                }
+                token => {
+                    let done = if token.is_start() {
+                        self.depth += 1;
+                        false
+                    } else if token.is_end() {
+                        self.depth = self.depth.saturating_sub(1);
+                        true
+                    } else {
+                        match token {
+                            Token::Option(true) |
+                            Token::StructNewType(_) |
+                            Token::TypeTag(_) => false,
+                            ref token => !token.is_sep(),
+                        }
+                    };
+                    self.pending.push_back(token);
+                    if done {
+                        self.value_done();
+                    }
+                }
+            }
+        }
+
+        self.pending.pop_front()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, _) = self.iter.size_hint();
+        (lower + self.pending.len(), None)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Returns an iterator that rewrites every enum variant in `iter` whose payload is a map or a
+/// struct into that map or struct, with the variant name added as its first entry under
+/// `TAG_KEY`. The key can be changed with `InternallyTagged::tag`.
+///
+/// Struct variants become maps. Newtype variants around a map or a struct keep it, and unit
+/// variants become a map holding only the tag. Any other payload has no place to put the tag,
+/// so it is an error, after which iteration stops.
+///
+/// ```rust
+/// use serde::token::{self, Token};
+///
+/// let tokens = vec![
+///     Token::EnumMapStart("Config", "Settings", Some(1)),
+///     Token::EnumMapSep, Token::Str("port"), Token::I32(1),
+///     Token::EnumMapEnd,
+/// ];
+/// let tagged: Result<Vec<_>, _> = token::internally_tagged(tokens).collect();
+/// assert_eq!(token::to_json(tagged.unwrap()).unwrap(), r#"{"type":"Settings","port":1}"#);
+/// ```
+pub fn internally_tagged<'a, I>(iter: I) -> InternallyTagged<'a, I::IntoIter>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    InternallyTagged {
+        iter: iter.into_iter(),
+        tag: TAG_KEY,
+        pending: VecDeque::new(),
+        variant: None,
+        expect_variant: false,
+        failed: false,
+    }
+}
+
+/// Iterator returned by `internally_tagged`.
+pub struct InternallyTagged<'a, I> {
+    iter: I,
+    tag: &'a str,
+    /// Tokens to yield before reading more of `iter`.
+    pending: VecDeque<Token<'a>>,
+    /// The name of the variant whose payload is the next token.
+    variant: Option<Token<'a>>,
+    /// True when the token after an `EnumStart` is the variant.
+    expect_variant: bool,
+    failed: bool,
+}
+
+impl<'a, I> InternallyTagged<'a, I> {
+    /// Sets the key of the variant name.
+    pub fn tag(mut self, tag: &'a str) -> Self {
+        self.tag = tag;
+        self
+    }
+
+    /// Queues the start of a map, or of a struct called `name`, with the tag as its first entry.
+    fn start(&mut self, name: Option<&'a str>, len: Option<usize>, variant: Token<'a>) {
+        let len = len.map(|len| len + 1);
+        match name {
+            Some(name) => {
+                self.pending.push_back(Token::StructStart(name, len));
+                self.pending.push_back(Token::StructSep);
+            }
+            None => {
+                self.pending.push_back(Token::MapStart(len));
+                self.pending.push_back(Token::MapSep);
+            }
+        }
+        self.pending.push_back(Token::Str(self.tag));
+        self.pending.push_back(variant);
+    }
+
+    /// Queues the map of a unit variant.
+    fn unit(&mut self, variant: Token<'a>) {
+        self.start(None, Some(0), variant);
+        self.pending.push_back(Token::MapEnd);
+    }
+
+    /// Tags the payload `token` of `variant`.
+    fn payload(&mut self, variant: Token<'a>, token: Token<'a>) -> Result<(), Error> {
+        match token {
+            Token::MapStart(len) => self.start(None, len, variant),
+            Token::StructStart(name, len) => self.start(Some(name), len, variant),
+            Token::Unit => self.unit(variant),
+            token => {
+                let variant = match variant.as_str() {
+                    Some(variant) => format!("{:?}", variant),
+                    None => format!("{:?}", variant),
+                };
+                return Err(Error::InvalidValue(format!("internally tagged variant {} needs a map \
+                                                        or struct, found {:?}",
+                                                       variant,
+                                                       token)));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a, I> Iterator for InternallyTagged<'a, I>
+    where I: Iterator<Item=Token<'a>>,
+{
+    type Item = Result<Token<'a>, Error>;
+
+    fn next(&mut self) -> Option<Result<Token<'a>, Error>> {
+        while self.pending.is_empty() {
+            if self.failed {
+                return None;
+            }
+            let token = match self.iter.next() {
+                Some(token) => token,
+                None => return None,
+            };
+
+            if self.expect_variant {
+                self.expect_variant = false;
+                self.variant = Some(token);
+                continue;
+            }
+            if let Some(variant) = self.variant.take() {
+                if let Err(err) = self.payload(variant, token) {
+                    self.failed = true;
+                    return Some(Err(err));
+                }
+                continue;
+            }
+
+            match token {
+                Token::EnumStart(_) => self.expect_variant = true,
+                Token::EnumUnit(_, variant) => self.unit(Token::Str(variant)),
+                Token::EnumNewType(_, variant) => self.variant = Some(Token::Str(variant)),
+                Token::EnumMapStart(_, variant, len) => self.start(None, len, Token::Str(variant)),
+                Token::EnumMapSep => self.pending.push_back(Token::MapSep),
+                Token::EnumMapEnd => self.pending.push_back(Token::MapEnd),
+                Token::EnumSeqStart(_, variant, _) => {
+                    self.failed = true;
+                    return Some(Err(Error::InvalidValue(format!(
+                        "internally tagged variant {:?} needs a map or struct, found a tuple \
+                         variant",
+                        variant))));
+                }
+                token => self.pending.push_back(token),
+            }
+        }
+
+        self.pending.pop_front().map(Ok)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.failed && self.pending.is_empty() {
+            (0, Some(0))
+        } else {
+            (self.pending.len(), None)
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// The default field of the type name in `with_type_names`.
+pub const TYPE_FIELD: &'static str = "__type__";
+
+/// The default field of the wrapped value in `with_type_names`.
+pub const VALUE_FIELD: &'static str = "value";
+
+/// Returns an iterator that wraps every struct and enum in `iter` in a struct with two fields,
+/// the name of the type under `TYPE_FIELD` and the value itself under `VALUE_FIELD`. The wrapper
+/// struct is named after the type too. The fields can be changed with `TypeNames::fields`.
+///
+/// Structs and enums nested inside of a wrapped value are wrapped as well. Other named values,
+/// like tuple structs and newtype structs, are left alone.
+///
+/// ```rust
+/// use serde::token::{self, Token};
+/// use serde::token::tagging;
+///
+/// let tokens = vec![Token::EnumUnit("Color", "Red")];
+/// assert_eq!(tagging::with_type_names(tokens).collect::<Vec<_>>(), vec![
+///     Token::StructStart("Color", Some(2)),
+///     Token::StructSep, Token::Str("__type__"), Token::Str("Color"),
+///     Token::StructSep, Token::Str("value"), Token::EnumUnit("Color", "Red"),
+///     Token::StructEnd,
+/// ]);
+/// ```
+pub fn with_type_names<'a, I>(iter: I) -> TypeNames<'a, I::IntoIter>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    TypeNames {
+        iter: iter.into_iter(),
+        type_field: TYPE_FIELD,
+        value_field: VALUE_FIELD,
+        pending: VecDeque::new(),
+        depth: 0,
+        open: Vec::new(),
+        expect_variant: false,
+    }
+}
+
+/// Iterator returned by `with_type_names`.
+pub struct TypeNames<'a, I> {
+    iter: I,
+    type_field: &'a str,
+    value_field: &'a str,
+    /// Tokens to yield before reading more of `iter`.
+    pending: VecDeque<Token<'a>>,
+    /// The number of compound values of the input that are open.
+    depth: usize,
+    /// The depths at which wrapped values end, closing their wrapper.
+    open: Vec<usize>,
+    /// True when the token after an `EnumStart` is the variant.
+    expect_variant: bool,
+}
+
+impl<'a, I> TypeNames<'a, I> {
+    /// Sets the fields of the type name and of the wrapped value.
+    pub fn fields(mut self, type_field: &'a str, value_field: &'a str) -> Self {
+        self.type_field = type_field;
+        self.value_field = value_field;
+        self
+    }
+
+    /// Queues the start of the wrapper of a value of the type `name`, which ends at the current
+    /// depth.
+    fn wrap(&mut self, name: &'a str) {
+        self.open.push(self.depth);
+        self.pending.push_back(Token::StructStart(name, Some(2)));
+        self.pending.push_back(Token::StructSep);
+        self.pending.push_back(Token::Str(self.type_field));
+        self.pending.push_back(Token::Str(name));
+        self.pending.push_back(Token::StructSep);
+        self.pending.push_back(Token::Str(self.value_field));
+    }
+
+    /// Closes the wrappers of the values that ended with the last token.
+    fn value_done(&mut self) {
+        while self.open.last() == Some(&self.depth) {
+            self.open.pop();
+            self.pending.push_back(Token::StructEnd);
+        }
+    }
+}
+
+impl<'a, I> Iterator for TypeNames<'a, I>
+    where I: Iterator<Item=Token<'a>>,
+{
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        while self.pending.is_empty() {
+            let token = match self.iter.next() {
+                Some(token) => token,
+                None => return None,
+            };
+
+            if self.expect_variant {
+                self.expect_variant = false;
+                self.pending.push_back(token);
+                continue;
+            }
+
+            let done = match token {
+                Token::StructStart(name, _) |
+                Token::EnumSeqStart(name, _, _) |
+                Token::EnumMapStart(name, _, _) => {
+                    self.wrap(name);
+                    self.depth += 1;
+                    false
+                }
+                Token::EnumUnit(name, _) => {
+                    self.wrap(name);
+                    true
+                }
+                Token::EnumNewType(name, _) => {
+                    self.wrap(name);
+                    false
+                }
+                Token::EnumStart(name) => {
+                    self.wrap(name);
+                    self.expect_variant = true;
+                    false
+                }
+                Token::Option(true) | Token::StructNewType(_) | Token::TypeTag(_) => false,
+                ref token if token.is_start() => {
+                    self.depth += 1;
+                    false
+                }
+                ref token if token.is_end() => {
+                    self.depth = self.depth.saturating_sub(1);
+                    true
+                }
+                ref token => !token.is_sep(),
+            };
+            self.pending.push_back(token);
+            if done {
+                self.value_done();
+            }
+        }
+
+        self.pending.pop_front()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, _) = self.iter.size_hint();
+        (lower + self.pending.len(), None)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Reads values wrapped by `with_type_names` into boxed values of the types registered for
+/// their type names, usually trait objects.
+///
+/// The function registered for a type gets the tokens of the wrapped value. Structs and enums
+/// nested inside of it still carry their wrappers, so types whose fields are structs or enums
+/// have to unwrap them.
+///
+/// ```rust
+/// use std::fmt::Debug;
+/// use serde::token::{self, Token};
+/// use serde::token::tagging::{self, TypeRegistry};
+///
+/// let registry = TypeRegistry::<Debug>::new()
+///     .register("Color", |tokens| {
+///         match tokens.first() {
+///             Some(&Token::EnumUnit(_, variant)) => {
+///                 Ok(Box::new(variant.to_owned()) as Box<Debug>)
+///             }
+///             _ => Err(token::Error::InvalidValue("expected a color".to_owned())),
+///         }
+///     });
+/// let tokens = tagging::with_type_names(vec![Token::EnumUnit("Color", "Red")]);
+/// assert_eq!(format!("{:?}", registry.deserialize(tokens).unwrap()), r#""Red""#);
+/// ```
+pub struct TypeRegistry<T: ?Sized> {
+    constructors: HashMap<String, Box<for<'a> Fn(Vec<Token<'a>>) -> Result<Box<T>, Error>>>,
+    type_field: String,
+    value_field: String,
+}
+
+impl<T: ?Sized> fmt::Debug for TypeRegistry<T> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let mut names: Vec<&String> = self.constructors.keys().collect();
+        names.sort();
+        formatter.debug_struct("TypeRegistry")
+            .field("types", &names)
+            .field("type_field", &self.type_field)
+            .field("value_field", &self.value_field)
+            .finish()
+    }
+}
+
+impl<T: ?Sized> TypeRegistry<T> {
+    /// Construct a new `TypeRegistry` without any types, reading the default fields.
+    pub fn new() -> Self {
+        TypeRegistry {
+            constructors: HashMap::new(),
+            type_field: TYPE_FIELD.to_owned(),
+            value_field: VALUE_FIELD.to_owned(),
+        }
+    }
+
+    /// Sets the fields of the type name and of the wrapped value, as for `TypeNames::fields`.
+    pub fn fields(mut self, type_field: &str, value_field: &str) -> Self {
+        self.type_field = type_field.to_owned();
+        self.value_field = value_field.to_owned();
+        self
+    }
+
+    /// Registers `constructor` to read the values tagged with the type name `name`, replacing
+    /// any earlier registration of the name.
+    pub fn register<F>(mut self, name: &str, constructor: F) -> Self
+        where F: for<'a> Fn(Vec<Token<'a>>) -> Result<Box<T>, Error> + 'static,
+    {
+        self.constructors.insert(name.to_owned(), Box::new(constructor));
+        self
+    }
+
+    /// Reads the single wrapped value in `iter`.
+    ///
+    /// A type name that isn't registered is an error naming it. The wrapper can be a struct or
+    /// a map, so values that went through a format without structs are read too.
+    pub fn deserialize<'a, I>(&self, iter: I) -> Result<Box<T>, Error>
+        where I: IntoIterator<Item=Token<'a>>,
+    {
+        let mut tokens = iter.into_iter();
+        let value = try!(self.read(&mut tokens));
+        match tokens.next() {
+            None => Ok(value),
+            Some(token) => Err(unexpected(&token)),
+        }
+    }
+
+    /// Reads a sequence of wrapped values in `iter`.
+    pub fn deserialize_seq<'a, I>(&self, iter: I) -> Result<Vec<Box<T>>, Error>
+        where I: IntoIterator<Item=Token<'a>>,
+    {
+        let mut tokens = iter.into_iter();
+        let mut values = Vec::new();
+        match try!(next(&mut tokens)) {
+            Token::SeqStart(_) | Token::SeqArrayStart(_) => {}
+            token => return Err(unexpected(&token)),
+        }
+        loop {
+            match try!(next(&mut tokens)) {
+                Token::SeqSep => values.push(try!(self.read(&mut tokens))),
+                Token::SeqEnd => break,
+                token => return Err(unexpected(&token)),
+            }
+        }
+        match tokens.next() {
+            None => Ok(values),
+            Some(token) => Err(unexpected(&token)),
+        }
+    }
+
+    fn read<'a, I>(&self, tokens: &mut I) -> Result<Box<T>, Error>
+        where I: Iterator<Item=Token<'a>>,
+    {
+        match try!(next(tokens)) {
+            Token::StructStart(_, _) | Token::MapStart(_) => {}
+            token => return Err(unexpected(&token)),
+        }
+
+        let mut name = None;
+        let mut value = None;
+        loop {
+            match try!(next(tokens)) {
+                Token::StructSep | Token::MapSep => {}
+                Token::StructEnd | Token::MapEnd => break,
+                token => return Err(unexpected(&token)),
+            }
+
+            let key = try!(next(tokens));
+            if key.as_str() == Some(&self.type_field) {
+                let token = try!(next(tokens));
+                match token.as_str() {
+                    Some(type_name) => name = Some(type_name.to_owned()),
+                    None => return Err(unexpected(&token)),
+                }
+            } else if key.as_str() == Some(&self.value_field) {
+                let mut tokens_of_value = Vec::new();
+                try!(skip_value(&mut tokens.inspect(|token| tokens_of_value.push(token.clone()))));
+                value = Some(tokens_of_value);
+            } else {
+                return Err(Error::InvalidValue(format!("unexpected field {:?} in a type name \
+                                                        wrapper",
+                                                       key)));
+            }
+        }
+
+        let (name, value) = match (name, value) {
+            (Some(name), Some(value)) => (name, value),
+            (None, _) => return Err(missing(&self.type_field)),
+            (_, None) => return Err(missing(&self.value_field)),
+        };
+        match self.constructors.get(&name) {
+            Some(constructor) => constructor(value),
+            None => Err(Error::InvalidValue(format!("unknown type name {:?}", name))),
+        }
+    }
+}
+
+impl<T: ?Sized> Default for TypeRegistry<T> {
+    fn default() -> Self {
+        TypeRegistry::new()
+    }
+}
+
+fn next<'a, I>(tokens: &mut I) -> Result<Token<'a>, Error>
+    where I: Iterator<Item=Token<'a>>,
+{
+    tokens.next().ok_or_else(|| Error::InvalidValue("unexpected end of stream".to_owned()))
+}
+
+fn missing(field: &str) -> Error {
+    Error::InvalidValue(format!("missing field {:?} in a type name wrapper", field))
+}
+
+fn unexpected(token: &Token) -> Error {
+    Error::InvalidValue(format!("unexpected token {:?}", token))
+}
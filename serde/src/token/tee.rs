@@ -0,0 +1,44 @@
+//! Pass one token stream on to several sinks.
+
+use super::{Error, Token, TokenSink};
+
+/// Reads `iter` once and pushes every token to each of `sinks`, in order. Returns the outcome
+/// for every sink, in the order of `sinks`.
+///
+/// A sink that fails receives no more tokens and its error is returned in its place, while the
+/// other sinks go on receiving the rest of the stream.
+///
+/// `TokenSink` takes tokens that own or statically borrow their data, like those of a
+/// `token::Serializer`, so the stream has to be made of those too.
+///
+/// ```rust
+/// use serde::token::{self, Token, TokenSink};
+///
+/// let mut first = Vec::new();
+/// let mut second = Vec::new();
+/// let results = {
+///     let mut sinks: [&mut TokenSink; 2] = [&mut first, &mut second];
+///     token::tee(token::to_tokens(&Some(5)).unwrap(), &mut sinks)
+/// };
+/// assert!(results.iter().all(Result::is_ok));
+/// assert_eq!(first, vec![Token::Option(true), Token::I32(5)]);
+/// assert_eq!(first, second);
+/// ```
+pub fn tee<I>(iter: I, sinks: &mut [&mut TokenSink]) -> Vec<Result<(), Error>>
+    where I: IntoIterator<Item=Token<'static>>,
+{
+    let mut results: Vec<Result<(), Error>> = sinks.iter().map(|_| Ok(())).collect();
+
+    for token in iter {
+        for (sink, result) in sinks.iter_mut().zip(results.iter_mut()) {
+            if result.is_ok() {
+                *result = sink.push(token.clone());
+            }
+        }
+        if results.iter().all(Result::is_err) {
+            break;
+        }
+    }
+
+    results
+}
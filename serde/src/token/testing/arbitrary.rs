@@ -0,0 +1,162 @@
+//! Random values for property tests.
+//!
+//! Every generator takes an explicitly seeded `Rng`, so a failing case can be reproduced from
+//! its seed alone. `check` runs a property over many seeds and reports the seed of the first
+//! failing case:
+//!
+//! ```rust
+//! use serde::token;
+//! use serde::token::testing::arbitrary;
+//!
+//! arbitrary::check(100, 0, |rng| {
+//!     let value = arbitrary::value(rng, 3, 4);
+//!     token::validate(arbitrary::tokens(&value)).map_err(|err| err.to_string())
+//! });
+//! ```
+
+use std::char;
+use std::collections::BTreeMap;
+use std::f64;
+use std::i64;
+
+use token::{Token, Value, f64_from_bits, to_tokens};
+
+/// A small, fast and deterministic pseudo random number generator (SplitMix64). It is not
+/// suitable for anything but tests.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Construct a new `Rng` from a seed. Equal seeds produce equal sequences.
+    pub fn new(seed: u64) -> Self {
+        Rng {
+            state: seed,
+        }
+    }
+
+    /// Returns the next random `u64`.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a random number in `0..n`. `n` must not be zero.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// Returns a random boolean.
+    pub fn gen_bool(&mut self) -> bool {
+        self.next_u64() & 1 == 1
+    }
+}
+
+/// Runs `property` for `cases` consecutive seeds starting at `seed`, panicking with the seed of
+/// the first case that returns an error. Passing that seed and a `cases` of one reruns just the
+/// failing case.
+pub fn check<F>(cases: usize, seed: u64, mut property: F)
+    where F: FnMut(&mut Rng) -> Result<(), String>,
+{
+    for case in 0..cases as u64 {
+        let case_seed = seed.wrapping_add(case);
+        if let Err(msg) = property(&mut Rng::new(case_seed)) {
+            panic!("property failed for seed {}: {}", case_seed, msg);
+        }
+    }
+}
+
+/// Characters that need escaping in most text formats.
+const SPECIAL_CHARS: &'static [char] = &[
+    '"', '\\', '/', '\n', '\r', '\t', '\u{0}', '\u{8}', '\u{c}', '\u{1f}', '\u{7f}',
+];
+
+/// Characters that take more than one byte in UTF-8.
+const MULTI_BYTE_CHARS: &'static [char] = &[
+    'é', 'ß', 'Ω', '€', '中', '\u{fffd}', '😀', '\u{10ffff}',
+];
+
+/// Returns a random string of at most `max_len` characters, biased towards characters that
+/// need escaping and characters that take more than one byte.
+pub fn string(rng: &mut Rng, max_len: usize) -> String {
+    let len = rng.below(max_len + 1);
+    let mut s = String::new();
+
+    for _ in 0..len {
+        let c = match rng.below(4) {
+            0 => SPECIAL_CHARS[rng.below(SPECIAL_CHARS.len())],
+            1 => MULTI_BYTE_CHARS[rng.below(MULTI_BYTE_CHARS.len())],
+            2 => {
+                // Any scalar value; surrogates are mapped onto the ASCII range.
+                let code = rng.below(0x110000) as u32;
+                char::from_u32(code).unwrap_or((code % 0x80) as u8 as char)
+            }
+            _ => (b'a' + rng.below(26) as u8) as char,
+        };
+        s.push(c);
+    }
+
+    s
+}
+
+/// Returns a random finite `f64`, covering both small numbers and the full exponent range.
+pub fn float(rng: &mut Rng) -> f64 {
+    match rng.below(3) {
+        0 => (rng.below(2001) as f64 - 1000.0) / 8.0,
+        1 => rng.next_u64() as i64 as f64,
+        _ => {
+            loop {
+                let v = f64_from_bits(rng.next_u64());
+                if v.is_finite() {
+                    return v;
+                }
+            }
+        }
+    }
+}
+
+/// Returns a random `Value` nested at most `depth` levels deep, with at most `width` elements
+/// in every sequence and map.
+///
+/// Integers are generated the way `Value::from_tokens` normalizes them: `U64` only holds values
+/// that don't fit into an `i64`.
+pub fn value(rng: &mut Rng, depth: usize, width: usize) -> Value {
+    let kinds = if depth == 0 { 6 } else { 8 };
+
+    match rng.below(kinds) {
+        0 => Value::Null,
+        1 => Value::Bool(rng.gen_bool()),
+        2 => {
+            if rng.gen_bool() {
+                Value::I64(rng.below(201) as i64 - 100)
+            } else {
+                Value::I64(rng.next_u64() as i64)
+            }
+        }
+        3 => Value::U64(i64::MAX as u64 + 1 + rng.next_u64() % (i64::MAX as u64)),
+        4 => Value::F64(float(rng)),
+        5 => Value::String(string(rng, 8)),
+        6 => {
+            let len = rng.below(width + 1);
+            Value::Seq((0..len).map(|_| value(rng, depth - 1, width)).collect())
+        }
+        _ => {
+            let len = rng.below(width + 1);
+            let mut map = BTreeMap::new();
+            for _ in 0..len {
+                let key = string(rng, 8);
+                map.insert(key, value(rng, depth - 1, width));
+            }
+            Value::Map(map)
+        }
+    }
+}
+
+/// Returns the token stream of `value`.
+pub fn tokens(value: &Value) -> Vec<Token<'static>> {
+    to_tokens(value).expect("serializing a Value can't fail")
+}
@@ -0,0 +1,304 @@
+//! A corpus of token streams that every backend should round trip.
+//!
+//! Each `Fixture` is a single value with a name and a short description. `roundtrip_backend`
+//! encodes and decodes every fixture with a backend and compares the result in the data model
+//! of `Value`, which is what a self-describing format can be expected to preserve: integers of
+//! different widths, `None` and `()`, or a struct and a map with the same entries are equal
+//! there.
+//!
+//! ```rust
+//! use serde::token::{self, Value, json};
+//! use serde::token::testing::corpus;
+//!
+//! corpus::roundtrip_backend(|tokens| {
+//!     token::to_json(corpus::bytes_as_seqs(tokens)).map_err(|e| e.to_string())
+//! }, |text| {
+//!     let tokens = try!(json::from_str(text).map_err(|e| e.to_string()));
+//!     Value::from_tokens(tokens).map_err(|e| e.to_string())
+//! });
+//! ```
+
+use std::{f32, f64, i16, i32, i64, i8, u16, u32, u64, u8};
+use std::iter;
+
+use token::{Token, Value};
+
+/// A named token stream in the corpus.
+#[derive(Clone, Debug)]
+pub struct Fixture {
+    /// A unique name, used to report failures.
+    pub name: &'static str,
+    /// What the fixture covers.
+    pub description: &'static str,
+    /// The tokens of a single value.
+    pub tokens: Vec<Token<'static>>,
+}
+
+fn fixture(name: &'static str,
+           description: &'static str,
+           tokens: Vec<Token<'static>>) -> Fixture {
+    Fixture {
+        name: name,
+        description: description,
+        tokens: tokens,
+    }
+}
+
+/// Wraps the values of `elements` into a sequence.
+fn seq(elements: Vec<Vec<Token<'static>>>) -> Vec<Token<'static>> {
+    let mut tokens = vec![Token::SeqStart(Some(elements.len()))];
+    for element in elements {
+        tokens.push(Token::SeqSep);
+        tokens.extend(element);
+    }
+    tokens.push(Token::SeqEnd);
+    tokens
+}
+
+/// Wraps the keys and values of `entries` into a map.
+fn map(entries: Vec<(Token<'static>, Vec<Token<'static>>)>) -> Vec<Token<'static>> {
+    let mut tokens = vec![Token::MapStart(Some(entries.len()))];
+    for (key, value) in entries {
+        tokens.push(Token::MapSep);
+        tokens.push(key);
+        tokens.extend(value);
+    }
+    tokens.push(Token::MapEnd);
+    tokens
+}
+
+/// Wraps the fields of `fields` into the struct `name`.
+fn struct_(name: &'static str, fields: Vec<(&'static str, Vec<Token<'static>>)>)
+           -> Vec<Token<'static>> {
+    let mut tokens = vec![Token::StructStart(name, Some(fields.len()))];
+    for (field, value) in fields {
+        tokens.push(Token::StructSep);
+        tokens.push(Token::Str(field));
+        tokens.extend(value);
+    }
+    tokens.push(Token::StructEnd);
+    tokens
+}
+
+fn some(value: Vec<Token<'static>>) -> Vec<Token<'static>> {
+    iter::once(Token::Option(true)).chain(value).collect()
+}
+
+/// Returns the corpus, with every fixture in its own, unique, name.
+pub fn corpus() -> Vec<Fixture> {
+    let deep_seq = (0..64).fold(vec![Token::Bool(true)], |inner, _| seq(vec![inner]));
+    let deep_map = (0..32).fold(vec![Token::I32(0)], |inner, _| {
+        map(vec![(Token::Str("next"), inner)])
+    });
+    let mixed = (0..16).fold(vec![Token::Unit], |inner, depth| {
+        if depth % 2 == 0 {
+            seq(vec![vec![Token::I32(depth)], inner])
+        } else {
+            struct_("Level", vec![("depth", vec![Token::I32(depth)]), ("inner", inner)])
+        }
+    });
+
+    vec![
+        fixture("bool", "both booleans", seq(vec![vec![Token::Bool(false)],
+                                                  vec![Token::Bool(true)]])),
+        fixture("i8_extremes", "the smallest and largest i8", seq(vec![
+            vec![Token::I8(i8::MIN)], vec![Token::I8(0)], vec![Token::I8(i8::MAX)],
+        ])),
+        fixture("i16_extremes", "the smallest and largest i16", seq(vec![
+            vec![Token::I16(i16::MIN)], vec![Token::I16(i16::MAX)],
+        ])),
+        fixture("i32_extremes", "the smallest and largest i32", seq(vec![
+            vec![Token::I32(i32::MIN)], vec![Token::I32(i32::MAX)],
+        ])),
+        fixture("i64_extremes", "the smallest and largest i64", seq(vec![
+            vec![Token::I64(i64::MIN)], vec![Token::I64(-1)], vec![Token::I64(i64::MAX)],
+        ])),
+        fixture("unsigned_extremes", "the largest value of every unsigned type", seq(vec![
+            vec![Token::U8(u8::MAX)],
+            vec![Token::U16(u16::MAX)],
+            vec![Token::U32(u32::MAX)],
+            vec![Token::U64(i64::MAX as u64 + 1)],
+            vec![Token::U64(u64::MAX)],
+        ])),
+        fixture("floats", "fractions, zeros and the extremes of f32 and f64", seq(vec![
+            vec![Token::F32(1.5)],
+            vec![Token::F32(f32::MAX)],
+            vec![Token::F64(0.0)],
+            vec![Token::F64(-0.1)],
+            vec![Token::F64(f64::MIN_POSITIVE)],
+            vec![Token::F64(f64::EPSILON)],
+            vec![Token::F64(f64::MAX)],
+            vec![Token::F64(f64::MIN)],
+        ])),
+        fixture("chars", "characters of one to four bytes", seq(vec![
+            vec![Token::Char('a')], vec![Token::Char('é')], vec![Token::Char('€')],
+            vec![Token::Char('😀')],
+        ])),
+        fixture("empty_string", "the empty string", vec![Token::Str("")]),
+        fixture("escapes", "characters that text formats escape", vec![
+            Token::Str("quote \" backslash \\ slash / newline \n tab \t nul \u{0} del \u{7f}"),
+        ]),
+        fixture("unicode", "strings of multi-byte characters", seq(vec![
+            vec![Token::Str("Grüße, Ωμέγα, 中文, العربية")],
+            vec![Token::String("😀🎉 \u{fffd} \u{10ffff} e\u{301}".to_owned())],
+            vec![Token::Str("\u{feff}leading byte order mark")],
+        ])),
+        fixture("bytes", "byte arrays with every kind of byte", seq(vec![
+            vec![Token::Bytes(b"")],
+            vec![Token::ByteBuf((0..256).map(|b| b as u8).collect())],
+        ])),
+        fixture("units", "unit, unit structs and None", seq(vec![
+            vec![Token::Unit], vec![Token::UnitStruct("Marker")], vec![Token::Option(false)],
+        ])),
+        fixture("empty_containers", "empty sequences, tuples, maps and structs", seq(vec![
+            vec![Token::SeqStart(Some(0)), Token::SeqEnd],
+            vec![Token::SeqStart(None), Token::SeqEnd],
+            vec![Token::SeqArrayStart(0), Token::SeqEnd],
+            vec![Token::TupleStart(0), Token::TupleEnd],
+            vec![Token::MapStart(Some(0)), Token::MapEnd],
+            vec![Token::StructStart("Empty", Some(0)), Token::StructEnd],
+        ])),
+        fixture("tuples", "tuples and tuple structs", seq(vec![
+            vec![
+                Token::TupleStart(2),
+                Token::TupleSep, Token::I32(1),
+                Token::TupleSep, Token::Str("two"),
+                Token::TupleEnd,
+            ],
+            vec![
+                Token::TupleStructStart("Pair", Some(2)),
+                Token::TupleStructSep, Token::Bool(true),
+                Token::TupleStructSep, Token::Unit,
+                Token::TupleStructEnd,
+            ],
+            vec![Token::StructNewType("Meters"), Token::F64(2.5)],
+        ])),
+        fixture("deep_seq", "sequences nested 64 levels deep", deep_seq),
+        fixture("deep_map", "maps nested 32 levels deep", deep_map),
+        fixture("deep_mixed", "sequences and structs alternating 16 levels deep", mixed),
+        fixture("enum_unit", "a unit variant", vec![Token::EnumUnit("Color", "Red")]),
+        fixture("enum_newtype", "a newtype variant", vec![
+            Token::EnumNewType("Shape", "Circle"), Token::F64(1.0),
+        ]),
+        fixture("enum_tuple", "a tuple variant", vec![
+            Token::EnumSeqStart("Shape", "Rect", Some(2)),
+            Token::EnumSeqSep, Token::I32(3),
+            Token::EnumSeqSep, Token::I32(4),
+            Token::EnumSeqEnd,
+        ]),
+        fixture("enum_struct", "a struct variant", vec![
+            Token::EnumMapStart("Shape", "Polygon", Some(2)),
+            Token::EnumMapSep, Token::Str("sides"), Token::U8(6),
+            Token::EnumMapSep, Token::Str("regular"), Token::Bool(true),
+            Token::EnumMapEnd,
+        ]),
+        fixture("enums_nested", "variants inside variants and containers", seq(vec![
+            vec![Token::EnumNewType("Outer", "Inner"), Token::EnumUnit("Inner", "Leaf")],
+            vec![
+                Token::EnumNewType("Outer", "List"),
+                Token::SeqStart(Some(1)),
+                Token::SeqSep, Token::EnumNewType("Inner", "Value"), Token::I64(-7),
+                Token::SeqEnd,
+            ],
+        ])),
+        fixture("options", "Option in every nesting combination", seq(vec![
+            vec![Token::Option(false)],
+            some(vec![Token::I32(1)]),
+            some(vec![Token::Option(false)]),
+            some(some(vec![Token::Str("twice")])),
+            some(seq(vec![some(vec![Token::I32(1)]), vec![Token::Option(false)]])),
+            some(struct_("Opt", vec![("a", vec![Token::Option(false)]),
+                                     ("b", some(vec![Token::Bool(false)]))])),
+            map(vec![(Token::Str("none"), vec![Token::Option(false)]),
+                     (Token::Str("some"), some(seq(vec![])))]),
+        ])),
+        fixture("awkward_keys", "empty, escaped, unicode and non-string map keys", seq(vec![
+            map(vec![
+                (Token::Str(""), vec![Token::I32(0)]),
+                (Token::Str("with \"quotes\" and \\"), vec![Token::I32(1)]),
+                (Token::Str("new\nline"), vec![Token::I32(2)]),
+                (Token::Str("ключ"), vec![Token::I32(3)]),
+                (Token::Str("😀"), vec![Token::I32(4)]),
+                (Token::Str("a.b[0]"), vec![Token::I32(5)]),
+            ]),
+            map(vec![
+                (Token::I32(-1), vec![Token::Str("negative")]),
+                (Token::U64(u64::MAX), vec![Token::Str("large")]),
+            ]),
+            map(vec![(Token::Char('c'), vec![Token::Unit])]),
+        ])),
+        fixture("struct", "a struct with fields of every kind", struct_("Record", vec![
+            ("id", vec![Token::U32(7)]),
+            ("name", vec![Token::Str("seven")]),
+            ("tags", seq(vec![vec![Token::Str("a")], vec![Token::Str("b")]])),
+            ("parent", vec![Token::Option(false)]),
+            ("score", vec![Token::F64(0.25)]),
+        ])),
+    ]
+}
+
+/// Replaces the `Bytes` and `ByteBuf` tokens of `tokens` with sequences of `U8`, for backends
+/// that write byte strings as encoded text, which reads back as a plain string.
+pub fn bytes_as_seqs(tokens: &[Token<'static>]) -> Vec<Token<'static>> {
+    let mut out = Vec::with_capacity(tokens.len());
+    for token in tokens {
+        match token.as_bytes() {
+            Some(bytes) => {
+                out.push(Token::SeqStart(Some(bytes.len())));
+                for &byte in bytes {
+                    out.push(Token::SeqSep);
+                    out.push(Token::U8(byte));
+                }
+                out.push(Token::SeqEnd);
+            }
+            _ => out.push(token.clone()),
+        }
+    }
+    out
+}
+
+/// Replaces the `F32` tokens of `tokens` with the `F64` that their shortest decimal form reads
+/// back as, which is what a textual backend reads instead of the widened `f32`.
+fn f32s_as_text(tokens: &[Token<'static>]) -> Vec<Token<'static>> {
+    tokens.iter()
+        .map(|token| match *token {
+            Token::F32(v) => {
+                Token::F64(format!("{:?}", v).parse().expect("a formatted float parses"))
+            }
+            ref token => token.clone(),
+        })
+        .collect()
+}
+
+/// Round trips every fixture of the corpus through a backend, panicking with the name of the
+/// first fixture that doesn't come back equal.
+///
+/// `encode` turns a fixture's tokens into the backend's representation, and `decode` reads that
+/// back and builds a `Value` from it, usually with `Value::from_tokens`. The original tokens
+/// are converted to a `Value` too, and the two have to be equal. An `f32` may also come back as
+/// the `f64` its shortest decimal form reads as.
+pub fn roundtrip_backend<T, E, D>(mut encode: E, mut decode: D)
+    where E: FnMut(&[Token<'static>]) -> Result<T, String>,
+          D: FnMut(&T) -> Result<Value, String>,
+{
+    for fixture in corpus() {
+        let expected = Value::from_tokens(fixture.tokens.clone())
+            .expect("every fixture is a valid value");
+        let as_text = Value::from_tokens(f32s_as_text(&fixture.tokens))
+            .expect("every fixture is a valid value");
+        let result = encode(&fixture.tokens).and_then(|encoded| decode(&encoded));
+        match result {
+            Ok(ref actual) if *actual == expected || *actual == as_text => { }
+            Ok(actual) => {
+                panic!("fixture `{}` ({}) came back as {:?}, expected {:?}",
+                       fixture.name,
+                       fixture.description,
+                       actual,
+                       expected);
+            }
+            Err(err) => {
+                panic!("fixture `{}` ({}) failed: {}", fixture.name, fixture.description, err);
+            }
+        }
+    }
+}
@@ -0,0 +1,350 @@
+//! Test push-style `Serialize` implementations against an expected token stream.
+//!
+//! An `AssertSerializer` is fed the expected tokens up front and lets the value drive it. Every
+//! token the value produces is compared with the next expectation as soon as it arrives, so a
+//! failure points at the exact call that went wrong:
+//!
+//! ```rust
+//! use serde::ser::Serialize;
+//! use serde::token::Token;
+//! use serde::token::testing::AssertSerializer;
+//!
+//! let mut ser = AssertSerializer::expecting(vec![
+//!     Token::TupleStart(2),
+//!     Token::TupleSep,
+//!     Token::I32(5),
+//!     Token::TupleSep,
+//!     Token::Str("x"),
+//!     Token::TupleEnd,
+//! ]);
+//! (5i32, "x").serialize(&mut ser).unwrap();
+//! ser.finish();
+//! ```
+//!
+//! A serializer created with `AssertSerializer::recording` checks nothing and only collects the
+//! tokens, which is handy for generating the expectations of a new test.
+//!
+//! Large token streams are easier to keep in a file than inline. `assert_snapshot` compares a
+//! stream against `tests/snapshots/<name>.tokens` and rewrites the file when the
+//! `UPDATE_SNAPSHOTS` environment variable is set. When a stream is small enough to keep inline,
+//! `to_rust_literal` prints it as the `vec![...]` to paste into the test instead.
+//!
+//! The `arbitrary` module generates random values for property tests, and the `corpus` module
+//! holds fixtures that every backend should round trip.
+
+use std::cmp;
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::vec;
+
+use ser::Serialize;
+
+use super::{Error, Serializer, Token, TokenSink};
+
+pub mod arbitrary;
+pub mod corpus;
+
+pub use self::corpus::{Fixture, bytes_as_seqs, corpus, roundtrip_backend};
+
+/// A `Serializer` that checks every token against an expectation.
+pub type AssertSerializer<I> = Serializer<Expect<I>>;
+
+/// The `TokenSink` behind `AssertSerializer`.
+pub struct Expect<I> {
+    expected: Option<I>,
+    recorded: Vec<Token<'static>>,
+}
+
+impl<I> fmt::Debug for Expect<I> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.debug_struct("Expect")
+            .field("asserting", &self.expected.is_some())
+            .field("recorded", &self.recorded)
+            .finish()
+    }
+}
+
+impl<'a, I> TokenSink for Expect<I>
+    where I: Iterator<Item=Token<'a>>,
+{
+    fn push(&mut self, token: Token<'static>) -> Result<(), Error> {
+        if let Some(ref mut expected) = self.expected {
+            match expected.next() {
+                Some(ref next) if *next == token => {}
+                next => {
+                    panic!("token #{}: expected {} but got {:?}{}",
+                           self.recorded.len(),
+                           match next {
+                               Some(next) => format!("{:?}", next),
+                               None => "end of stream".to_owned(),
+                           },
+                           token,
+                           remaining(expected));
+                }
+            }
+        }
+
+        self.recorded.push(token);
+        Ok(())
+    }
+}
+
+impl<'a, I> Serializer<Expect<I>>
+    where I: Iterator<Item=Token<'a>>,
+{
+    /// Construct a new `AssertSerializer` that expects the tokens in `expected`.
+    pub fn expecting<T>(expected: T) -> Self
+        where T: IntoIterator<IntoIter=I, Item=Token<'a>>,
+    {
+        Serializer::with_sink(Expect {
+            expected: Some(expected.into_iter()),
+            recorded: Vec::new(),
+        })
+    }
+
+    /// Returns the tokens produced so far.
+    pub fn recorded(&self) -> &[Token<'static>] {
+        &self.get_ref().recorded
+    }
+
+    /// Asserts that every expected token has been produced and returns the produced tokens.
+    pub fn finish(self) -> Vec<Token<'static>> {
+        let Expect { expected, recorded } = self.into_inner();
+
+        if let Some(mut expected) = expected {
+            if let Some(next) = expected.next() {
+                panic!("token #{}: expected {:?} but the value is done{}",
+                       recorded.len(),
+                       next,
+                       remaining(expected));
+            }
+        }
+
+        recorded
+    }
+}
+
+impl Serializer<Expect<vec::IntoIter<Token<'static>>>> {
+    /// Construct a new `AssertSerializer` that records the tokens without checking them.
+    pub fn recording() -> Self {
+        Serializer::with_sink(Expect {
+            expected: None,
+            recorded: Vec::new(),
+        })
+    }
+}
+
+/// Serializes `value` and asserts that it produces exactly the tokens in `expected`.
+pub fn assert_ser_tokens<'a, T: ?Sized, I>(value: &T, expected: I)
+    where T: Serialize,
+          I: IntoIterator<Item=Token<'a>>,
+{
+    let mut ser = Serializer::expecting(expected);
+    value.serialize(&mut ser).unwrap();
+    ser.finish();
+}
+
+/// Renders a token stream with one token per line, indenting the contents of every compound
+/// value the same way the token lists in the tests are laid out.
+pub fn pretty_tokens<'a, I>(iter: I) -> String
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let mut out = String::new();
+    let mut depth = 0;
+
+    for token in iter {
+        if token.is_end() && depth > 0 {
+            depth -= 1;
+        }
+
+        for _ in 0..depth {
+            out.push_str("    ");
+        }
+        out.push_str(&format!("{:?}\n", token));
+
+        if token.is_start() {
+            depth += 1;
+        }
+    }
+
+    out
+}
+
+/// Renders a token stream as a Rust expression that evaluates to an equal `Vec<Token>`, laid out
+/// like `pretty_tokens`, for pasting into a test when the expected stream of a fixture changes.
+///
+/// The expression names the variants through `Token::`, so `Token` has to be in scope where it
+/// is used. Owned strings and byte buffers stay owned, and floats that have no literal, like
+/// infinity, are written as the constants in `std::f32` and `std::f64`.
+///
+/// ```rust
+/// use serde::token::{self, Token};
+/// use serde::token::testing::to_rust_literal;
+///
+/// let tokens = token::to_tokens(&Some("it's")).unwrap();
+/// assert_eq!(to_rust_literal(tokens), "\
+/// vec![
+///     Token::Option(true),
+///     Token::String(\"it's\".to_owned()),
+/// ]
+/// ");
+/// ```
+pub fn to_rust_literal<'a, I>(iter: I) -> String
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let mut out = String::from("vec![\n");
+    let mut depth = 1;
+
+    for token in iter {
+        if token.is_end() && depth > 1 {
+            depth -= 1;
+        }
+
+        for _ in 0..depth {
+            out.push_str("    ");
+        }
+        out.push_str("Token::");
+        match token {
+            Token::F32(v) if !v.is_finite() => {
+                out.push_str(&format!("F32({})", float_constant(v as f64, "f32")))
+            }
+            Token::F64(v) if !v.is_finite() => {
+                out.push_str(&format!("F64({})", float_constant(v, "f64")))
+            }
+            Token::String(ref v) => out.push_str(&format!("String({:?}.to_owned())", v)),
+            Token::BigNum(ref v) => out.push_str(&format!("BigNum({:?}.to_owned())", v)),
+            Token::Bytes(v) => out.push_str(&format!("Bytes(&{:?})", v)),
+            Token::ByteBuf(ref v) => out.push_str(&format!("ByteBuf(vec!{:?})", v)),
+            ref token => out.push_str(&format!("{:?}", token)),
+        }
+        out.push_str(",\n");
+
+        if token.is_start() {
+            depth += 1;
+        }
+    }
+
+    out.push_str("]\n");
+    out
+}
+
+/// Names the constant of the float module `ty` for a `v` that has no literal.
+fn float_constant(v: f64, ty: &str) -> String {
+    let name = if v.is_nan() {
+        "NAN"
+    } else if v > 0.0 {
+        "INFINITY"
+    } else {
+        "NEG_INFINITY"
+    };
+    format!("::std::{}::{}", ty, name)
+}
+
+/// Asserts that `iter` renders to the contents of the snapshot `tests/snapshots/<name>.tokens`,
+/// relative to the directory of the crate under test.
+///
+/// When the `UPDATE_SNAPSHOTS` environment variable is set the snapshot is written instead. On a
+/// mismatch the panic message contains a unified diff between the snapshot and the stream.
+///
+/// The rendering is only as deterministic as the stream, so values like `HashMap` whose
+/// iteration order is unspecified shouldn't be snapshotted directly.
+pub fn assert_snapshot<'a, I>(name: &str, iter: I)
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let actual = pretty_tokens(iter);
+
+    let mut path = match env::var_os("CARGO_MANIFEST_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::new(),
+    };
+    path.push("tests");
+    path.push("snapshots");
+    path.push(format!("{}.tokens", name));
+
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        if let Err(err) = write_snapshot(&path, &actual) {
+            panic!("failed to write snapshot {}: {}", path.display(), err);
+        }
+        return;
+    }
+
+    let expected = match read_snapshot(&path) {
+        Ok(expected) => expected,
+        Err(err) => {
+            panic!("failed to read snapshot {}: {}; set UPDATE_SNAPSHOTS=1 to create it",
+                   path.display(),
+                   err);
+        }
+    };
+
+    if expected != actual {
+        panic!("snapshot `{}` does not match; set UPDATE_SNAPSHOTS=1 to accept the changes\n{}",
+               name,
+               unified_diff(&expected, &actual));
+    }
+}
+
+fn read_snapshot(path: &PathBuf) -> io::Result<String> {
+    let mut contents = String::new();
+    try!(try!(File::open(path)).read_to_string(&mut contents));
+    Ok(contents)
+}
+
+fn write_snapshot(path: &PathBuf, contents: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        try!(fs::create_dir_all(dir));
+    }
+    try!(File::create(path)).write_all(contents.as_bytes())
+}
+
+/// Returns a line based diff from `expected` to `actual` in the unified format, with the whole
+/// file as a single hunk.
+fn unified_diff(expected: &str, actual: &str) -> String {
+    let old: Vec<&str> = expected.lines().collect();
+    let new: Vec<&str> = actual.lines().collect();
+
+    // lcs[i][j] is the length of the longest common subsequence of old[i..] and new[j..].
+    let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                cmp::max(lcs[i + 1][j], lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = format!("--- snapshot\n+++ actual\n@@ -1,{} +1,{} @@\n", old.len(), new.len());
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            out.push_str(&format!(" {}\n", old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out.push_str(&format!("-{}\n", old[i]));
+            i += 1;
+        } else {
+            out.push_str(&format!("+{}\n", new[j]));
+            j += 1;
+        }
+    }
+
+    out
+}
+
+/// Describes the expectations that are left after a mismatch.
+fn remaining<'a, I>(expected: I) -> String
+    where I: Iterator<Item=Token<'a>>,
+{
+    let rest: Vec<_> = expected.collect();
+    if rest.is_empty() {
+        String::new()
+    } else {
+        format!("; remaining expectations: {:?}", rest)
+    }
+}
@@ -0,0 +1,475 @@
+//! Conversions between `Timestamp` tokens and their textual forms.
+//!
+//! The calendar arithmetic is the proleptic Gregorian calendar in `i64` days, so dates far before
+//! 1970 or after 2038 convert just as well as recent ones.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ser::{Serialize, Serializer};
+use ser::impls::TupleVisitor2;
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// 1601-01-01T00:00:00Z, the earliest time `to_system_time` accepts.
+const MIN_SYSTEM_TIME: i64 = -11_644_473_600;
+/// 9999-12-31T23:59:59Z, the latest time `to_system_time` accepts.
+const MAX_SYSTEM_TIME: i64 = 253_402_300_799;
+
+macro_rules! try_opt {
+    ($e:expr) => {
+        match $e {
+            Some(v) => v,
+            None => return None,
+        }
+    }
+}
+
+/// Returns the number of days between 1970-01-01 and the given date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 +
+                      day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Returns the year, month and day of the date `days` days after 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = if days >= 0 { days } else { days - 146_096 } / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 -
+                       day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 => if is_leap_year(year) { 29 } else { 28 },
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Splits a timestamp into the year, month and day and the seconds since midnight.
+fn split(secs: i64) -> (i64, u32, u32, i64) {
+    // Rounding the days towards the past, without multiplying them back, which overflows near
+    // the ends of the range.
+    let (days, secs_of_day) = match secs % SECONDS_PER_DAY {
+        rem if rem < 0 => (secs / SECONDS_PER_DAY - 1, rem + SECONDS_PER_DAY),
+        rem => (secs / SECONDS_PER_DAY, rem),
+    };
+    let (year, month, day) = civil_from_days(days);
+    (year, month, day, secs_of_day)
+}
+
+fn format_year(year: i64) -> String {
+    if year < 0 {
+        format!("-{:04}", -year)
+    } else {
+        format!("{:04}", year)
+    }
+}
+
+/// Formats a fraction of a second with as many groups of three digits as it needs, or not at all
+/// when it is zero.
+fn format_fraction(nanos: u32) -> String {
+    if nanos == 0 {
+        return String::new();
+    }
+
+    let fraction = format!("{:09}", nanos);
+    let mut digits = 9;
+    while digits > 3 && fraction[digits - 3..digits] == *"000" {
+        digits -= 3;
+    }
+    format!(".{}", &fraction[..digits])
+}
+
+/// Formats a timestamp as an RFC 3339 date-time in UTC, e.g. `1985-04-12T23:20:50.52Z`.
+///
+/// The fraction of a second is only written when it isn't zero, with as many groups of three
+/// digits as it needs.
+pub fn format_rfc3339(secs: i64, nanos: u32) -> String {
+    format_rfc3339_offset(secs, nanos, 0)
+}
+
+/// Formats a timestamp as an RFC 3339 date-time in the local time of a timezone `offset` minutes
+/// east of UTC, e.g. `1996-12-19T16:39:57-08:00`. An offset of zero is written as `Z`.
+fn format_rfc3339_offset(secs: i64, nanos: u32, offset: i32) -> String {
+    let (year, month, day, secs_of_day) = split(secs + offset as i64 * 60);
+    let zone = if offset == 0 {
+        String::from("Z")
+    } else {
+        let sign = if offset < 0 { '-' } else { '+' };
+        let minutes = offset.abs();
+        format!("{}{:02}:{:02}", sign, minutes / 60, minutes % 60)
+    };
+    format!("{}-{:02}-{:02}T{:02}:{:02}:{:02}{}{}",
+            format_year(year),
+            month,
+            day,
+            secs_of_day / 3600,
+            secs_of_day / 60 % 60,
+            secs_of_day % 60,
+            format_fraction(nanos),
+            zone)
+}
+
+/// Formats a timestamp according to a strftime-style pattern, see `TimestampFormat::Custom`.
+fn format_pattern(pattern: &str, secs: i64, nanos: u32) -> String {
+    let (year, month, day, secs_of_day) = split(secs);
+    let mut out = String::new();
+    let mut chars = pattern.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out.push_str(&format_year(year)),
+            Some('m') => out.push_str(&format!("{:02}", month)),
+            Some('d') => out.push_str(&format!("{:02}", day)),
+            Some('H') => out.push_str(&format!("{:02}", secs_of_day / 3600)),
+            Some('M') => out.push_str(&format!("{:02}", secs_of_day / 60 % 60)),
+            Some('S') => out.push_str(&format!("{:02}", secs_of_day % 60)),
+            Some('f') => out.push_str(&format!("{:09}", nanos)),
+            Some('.') => out.push_str(&format_fraction(nanos)),
+            Some('s') => out.push_str(&secs.to_string()),
+            Some('%') => out.push('%'),
+            Some(c) => {
+                out.push('%');
+                out.push(c);
+            }
+            None => out.push('%'),
+        }
+    }
+
+    out
+}
+
+/// How textual writers render `Timestamp` tokens.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TimestampFormat {
+    /// An RFC 3339 date-time string in UTC, e.g. `"1985-04-12T23:20:50.52Z"`. The fraction of a
+    /// second is only written when it isn't zero. This is the default.
+    Rfc3339,
+    /// An integer number of seconds since the Unix epoch. The fraction of a second is dropped,
+    /// rounding towards the past.
+    UnixSeconds,
+    /// An integer number of milliseconds since the Unix epoch, rounding towards the past.
+    UnixMillis,
+    /// A string built from a strftime-style pattern. `%Y`, `%m`, `%d`, `%H`, `%M` and `%S` are
+    /// the date and time in UTC, `%f` the nanoseconds as nine digits, `%.` the fraction of a
+    /// second as in RFC 3339, `%s` the seconds since the Unix epoch and `%%` a percent sign.
+    /// Anything else is copied as it is.
+    Custom(String),
+}
+
+impl Default for TimestampFormat {
+    fn default() -> Self {
+        TimestampFormat::Rfc3339
+    }
+}
+
+/// A rendered timestamp.
+pub enum Rendered {
+    /// An integer, written without quotes.
+    Integer(i64),
+    /// A string.
+    Text(String),
+}
+
+impl TimestampFormat {
+    /// Renders a timestamp in this format.
+    pub fn render(&self, secs: i64, nanos: u32) -> Rendered {
+        match *self {
+            TimestampFormat::Rfc3339 => Rendered::Text(format_rfc3339(secs, nanos)),
+            TimestampFormat::UnixSeconds => Rendered::Integer(secs),
+            TimestampFormat::UnixMillis => {
+                let millis = secs.saturating_mul(1000).saturating_add(nanos as i64 / 1_000_000);
+                Rendered::Integer(millis)
+            }
+            TimestampFormat::Custom(ref pattern) => {
+                Rendered::Text(format_pattern(pattern, secs, nanos))
+            }
+        }
+    }
+}
+
+/// Converts seconds and nanoseconds since the Unix epoch into a `SystemTime`, if they lie in
+/// the years 1601 through 9999.
+///
+/// Adding a `Duration` to a `SystemTime` panics when the platform can't represent the result,
+/// and there is no checked version to fall back on, so times are limited to a range that
+/// Windows and 64-bit Unix systems can both hold.
+pub fn to_system_time(secs: i64, nanos: u32) -> Option<SystemTime> {
+    if secs < MIN_SYSTEM_TIME || secs > MAX_SYSTEM_TIME {
+        return None;
+    }
+    if secs >= 0 {
+        Some(UNIX_EPOCH + Duration::new(secs as u64, nanos))
+    } else {
+        Some(UNIX_EPOCH - Duration::from_secs(secs.wrapping_neg() as u64) + Duration::new(0, nanos))
+    }
+}
+
+/// Parses an RFC 3339 date-time into seconds and nanoseconds since the Unix epoch.
+///
+/// Both `T` and a space are accepted between the date and the time. Digits of the fraction of a
+/// second beyond nanoseconds are ignored.
+pub fn parse_rfc3339(s: &str) -> Option<(i64, u32)> {
+    let bytes = s.as_bytes();
+    if bytes.len() < 20 {
+        return None;
+    }
+
+    let year = try_opt!(number(&bytes[0..4])) as i64;
+    let month = try_opt!(number(&bytes[5..7]));
+    let day = try_opt!(number(&bytes[8..10]));
+    let hour = try_opt!(number(&bytes[11..13]));
+    let minute = try_opt!(number(&bytes[14..16]));
+    let second = try_opt!(number(&bytes[17..19]));
+
+    if bytes[4] != b'-' || bytes[7] != b'-' || bytes[13] != b':' || bytes[16] != b':' {
+        return None;
+    }
+    match bytes[10] {
+        b'T' | b't' | b' ' => { }
+        _ => return None,
+    }
+    if month < 1 || month > 12 || day < 1 || day > days_in_month(year, month) || hour > 23 ||
+       minute > 59 || second > 60 {
+        return None;
+    }
+
+    let mut pos = 19;
+    let mut nanos = 0;
+    if bytes[pos] == b'.' {
+        pos += 1;
+        let start = pos;
+        while pos < bytes.len() && (bytes[pos] as char).is_digit(10) {
+            if pos - start < 9 {
+                nanos = nanos * 10 + (bytes[pos] - b'0') as u32;
+            }
+            pos += 1;
+        }
+        if pos == start {
+            return None;
+        }
+        for _ in pos - start..9 {
+            nanos *= 10;
+        }
+    }
+
+    let offset = match &bytes[pos..] {
+        b"Z" | b"z" => 0,
+        rest if rest.len() == 6 && rest[3] == b':' => {
+            let hours = try_opt!(number(&rest[1..3])) as i64;
+            let minutes = try_opt!(number(&rest[4..6])) as i64;
+            if hours > 23 || minutes > 59 {
+                return None;
+            }
+            match rest[0] {
+                b'+' => hours * 3600 + minutes * 60,
+                b'-' => -(hours * 3600 + minutes * 60),
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+
+    let secs = days_from_civil(year, month, day) * SECONDS_PER_DAY + hour as i64 * 3600 +
+               minute as i64 * 60 + second as i64 - offset;
+    Some((secs, nanos))
+}
+
+/// Parses a fixed number of ASCII digits.
+fn number(digits: &[u8]) -> Option<u32> {
+    let mut n = 0;
+    for &digit in digits {
+        if !(digit as char).is_digit(10) {
+            return None;
+        }
+        n = n * 10 + (digit - b'0') as u32;
+    }
+    Some(n)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Returns the seconds and nanoseconds since the Unix epoch of `time`, which may be before it.
+pub fn from_system_time(time: SystemTime) -> (i64, u32) {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => (duration.as_secs() as i64, duration.subsec_nanos()),
+        Err(err) => {
+            let duration = err.duration();
+            let secs = duration.as_secs() as i64;
+            match duration.subsec_nanos() {
+                0 => (-secs, 0),
+                nanos => (-secs - 1, 1_000_000_000 - nanos),
+            }
+        }
+    }
+}
+
+/// Formats a duration as seconds with the fraction of a second, e.g. `1.500s`.
+pub fn format_duration(duration: Duration) -> String {
+    format!("{}{}s", duration.as_secs(), format_fraction(duration.subsec_nanos()))
+}
+
+/// Parses a duration written by `format_duration`. Up to nine digits of the fraction of a second
+/// are accepted.
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    if !s.ends_with('s') {
+        return None;
+    }
+    let s = &s[..s.len() - 1];
+    let (secs, fraction) = match s.find('.') {
+        Some(dot) => (&s[..dot], &s[dot + 1..]),
+        None => (s, ""),
+    };
+    if secs.is_empty() || fraction.len() > 9 || (s.contains('.') && fraction.is_empty()) {
+        return None;
+    }
+    if !secs.bytes().all(|b| (b as char).is_digit(10)) {
+        return None;
+    }
+
+    let secs = try_opt!(secs.parse().ok());
+    let nanos = try_opt!(number(fraction.as_bytes())) * 10u32.pow(9 - fraction.len() as u32);
+    Some(Duration::new(secs, nanos))
+}
+
+/// A `SystemTime` is an RFC 3339 string for human readable formats, and goes through
+/// `serialize_timestamp` otherwise.
+impl Serialize for SystemTime {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer,
+    {
+        let (secs, nanos) = from_system_time(*self);
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&format_rfc3339(secs, nanos))
+        } else {
+            serializer.serialize_timestamp(secs, nanos)
+        }
+    }
+}
+
+/// A `Duration` is a string like `1.500s` for human readable formats, and a `(secs, nanos)` tuple
+/// otherwise.
+impl Serialize for Duration {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer,
+    {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&format_duration(*self))
+        } else {
+            serializer.serialize_tuple(TupleVisitor2::new(&(self.as_secs(), self.subsec_nanos())))
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A point in time that can be formatted as an RFC 3339 date-time.
+///
+/// This is where time types from other crates plug in: implementing it for a date-time type lets
+/// `Rfc3339` serialize that type as a string.
+pub trait ToRfc3339 {
+    /// Formats the point in time as an RFC 3339 date-time.
+    fn to_rfc3339(&self) -> String;
+}
+
+impl ToRfc3339 for SystemTime {
+    fn to_rfc3339(&self) -> String {
+        let (secs, nanos) = from_system_time(*self);
+        format_rfc3339(secs, nanos)
+    }
+}
+
+impl<'a, T: ?Sized> ToRfc3339 for &'a T
+    where T: ToRfc3339,
+{
+    fn to_rfc3339(&self) -> String {
+        (**self).to_rfc3339()
+    }
+}
+
+/// Serializes the wrapped point in time as an RFC 3339 string, for every serializer.
+///
+/// ```rust
+/// use serde::token::{self, OffsetTime, Rfc3339, Token};
+///
+/// let time = OffsetTime::new(851042397, 0, -8 * 60).unwrap();
+/// assert_eq!(token::to_tokens(&Rfc3339(time)).unwrap(),
+///            vec![Token::Str("1996-12-19T16:39:57-08:00")]);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rfc3339<T>(pub T);
+
+impl<T> Serialize for Rfc3339<T>
+    where T: ToRfc3339,
+{
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer,
+    {
+        serializer.serialize_str(&self.0.to_rfc3339())
+    }
+}
+
+/// A point in time together with the offset of the timezone it is shown in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OffsetTime {
+    secs: i64,
+    nanos: u32,
+    offset: i32,
+}
+
+impl OffsetTime {
+    /// Construct the point in time `secs` and `nanos` after the Unix epoch, shown in a timezone
+    /// `offset` minutes east of UTC. Returns `None` unless `nanos` is less than a second, the
+    /// offset is less than a day and the local time still fits in an `i64` of seconds.
+    pub fn new(secs: i64, nanos: u32, offset: i32) -> Option<Self> {
+        if nanos >= 1_000_000_000 || offset <= -24 * 60 || offset >= 24 * 60 {
+            return None;
+        }
+        if secs.checked_add(offset as i64 * 60).is_none() {
+            return None;
+        }
+        Some(OffsetTime {
+            secs: secs,
+            nanos: nanos,
+            offset: offset,
+        })
+    }
+
+    /// Returns the seconds and nanoseconds since the Unix epoch.
+    pub fn timestamp(&self) -> (i64, u32) {
+        (self.secs, self.nanos)
+    }
+
+    /// Returns the offset of the timezone in minutes east of UTC.
+    pub fn offset(&self) -> i32 {
+        self.offset
+    }
+}
+
+impl ToRfc3339 for OffsetTime {
+    fn to_rfc3339(&self) -> String {
+        format_rfc3339_offset(self.secs, self.nanos, self.offset)
+    }
+}
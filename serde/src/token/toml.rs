@@ -0,0 +1,839 @@
+//! Read TOML documents as token streams.
+//!
+//! A document becomes a map, tables and inline tables become nested maps, and arrays and arrays
+//! of tables become sequences, so the result can be consumed like any other map of maps. Keys
+//! keep the order in which they first appear in the document. Integers become `I64`, floats
+//! `F64` and offset date-times `Timestamp` tokens. Local dates and times have no token and are
+//! rejected.
+//!
+//! Keys and strings without escapes borrow from the input.
+//!
+//! Tables, arrays and inline tables may nest 128 levels deep by default, counting every part of
+//! a dotted key or table header as a level, see `Parser::max_depth`.
+
+use std::borrow::Cow;
+use std::char;
+use std::error;
+use std::f64;
+use std::fmt;
+
+use super::{Snippet, Token, line_column, time};
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// The error returned when parsing a TOML document fails.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    line: usize,
+    column: usize,
+    message: String,
+    snippet: Snippet,
+}
+
+impl Error {
+    /// Returns the one based line at which the problem was found.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Returns the one based column at which the problem was found.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Returns a description of the problem.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns the line of the input the problem was found in, which formatting the error with
+    /// `{:#}` shows.
+    pub fn snippet(&self) -> &Snippet {
+        &self.snippet
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(formatter, "{} at line {} column {}", self.message, self.line, self.column));
+        if formatter.alternate() {
+            try!(write!(formatter, "\n{}", self.snippet));
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        "invalid TOML"
+    }
+}
+
+/// Reads the TOML document in `input` as a token stream.
+///
+/// ```rust
+/// use serde::token::{self, Token};
+///
+/// let tokens = token::toml::from_str("[server]\nport = 8080\n").unwrap();
+/// assert_eq!(token::to_json(tokens).unwrap(), r#"{"server":{"port":8080}}"#);
+/// ```
+pub fn from_str(input: &str) -> Result<Vec<Token>, Error> {
+    Parser::new(input).parse()
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// How a table came into existence, which decides how it may still be extended.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    /// Created as the parent of a table header. It may still get a header of its own.
+    Implicit,
+    /// Defined by a table header, or the document itself.
+    Header,
+    /// Created by a dotted key.
+    Dotted,
+    /// An inline table, which can't be extended.
+    Inline,
+}
+
+struct Table<'a> {
+    entries: Vec<(Cow<'a, str>, Item<'a>)>,
+    kind: Kind,
+}
+
+enum Item<'a> {
+    Value(Token<'a>),
+    Array(Vec<Item<'a>>),
+    Table(Table<'a>),
+    Tables(Vec<Table<'a>>),
+}
+
+impl<'a> Table<'a> {
+    fn new(kind: Kind) -> Self {
+        Table {
+            entries: Vec::new(),
+            kind: kind,
+        }
+    }
+
+    fn get_mut(&mut self, key: &str) -> Option<&mut Item<'a>> {
+        self.entries.iter_mut().find(|entry| entry.0 == key).map(|entry| &mut entry.1)
+    }
+
+    /// Returns the table at `key`, creating it with the given kind if it doesn't exist yet. A
+    /// dotted key may only descend into tables that were created by dotted keys, a table header
+    /// may also descend into arrays of tables.
+    fn descend(&mut self, key: &Cow<'a, str>, kind: Kind) -> Result<&mut Table<'a>, String> {
+        if self.get_mut(key).is_none() {
+            self.entries.push((key.clone(), Item::Table(Table::new(kind))));
+        }
+
+        match self.get_mut(key) {
+            Some(&mut Item::Table(ref mut table)) if table.kind != Kind::Inline &&
+                                                     (kind != Kind::Dotted ||
+                                                      table.kind == Kind::Dotted) => Ok(table),
+            Some(&mut Item::Tables(ref mut tables)) if kind != Kind::Dotted => {
+                Ok(tables.last_mut().expect("arrays of tables are never empty"))
+            }
+            Some(&mut Item::Table(_)) | Some(&mut Item::Tables(_)) => {
+                Err(format!("table `{}` can't be extended here", key))
+            }
+            _ => Err(format!("key `{}` is already defined as a value", key)),
+        }
+    }
+
+    /// Inserts the value of a possibly dotted key.
+    fn insert(&mut self, path: Vec<Cow<'a, str>>, item: Item<'a>) -> Result<(), String> {
+        let (last, parents) = path.split_last().expect("keys are never empty");
+
+        let mut table = self;
+        for key in parents {
+            table = try!(table.descend(key, Kind::Dotted));
+        }
+
+        if table.get_mut(last).is_some() {
+            return Err(format!("duplicate key `{}`", last));
+        }
+        table.entries.push((last.clone(), item));
+        Ok(())
+    }
+
+    /// Finds the table named by a header that has been defined before.
+    fn find_mut(&mut self, path: &[Cow<'a, str>]) -> &mut Table<'a> {
+        let mut table = self;
+        for key in path {
+            table = match table.get_mut(key) {
+                Some(&mut Item::Table(ref mut table)) => table,
+                Some(&mut Item::Tables(ref mut tables)) => {
+                    tables.last_mut().expect("arrays of tables are never empty")
+                }
+                _ => unreachable!(),
+            };
+        }
+        table
+    }
+
+    /// Defines the table or the next element of the array of tables named by a header.
+    fn define(&mut self, path: &[Cow<'a, str>], array: bool) -> Result<(), String> {
+        let (last, parents) = path.split_last().expect("keys are never empty");
+
+        let mut table = self;
+        for key in parents {
+            table = try!(table.descend(key, Kind::Implicit));
+        }
+
+        let name = path.join(".");
+        match table.get_mut(last) {
+            None => { }
+            Some(&mut Item::Tables(ref mut tables)) if array => {
+                tables.push(Table::new(Kind::Header));
+                return Ok(());
+            }
+            Some(&mut Item::Table(ref mut table)) if !array && table.kind == Kind::Implicit => {
+                table.kind = Kind::Header;
+                return Ok(());
+            }
+            Some(&mut Item::Table(_)) if !array => {
+                return Err(format!("duplicate table `{}`", name));
+            }
+            Some(&mut Item::Table(_)) | Some(&mut Item::Tables(_)) => {
+                return Err(format!("`{}` is not an array of tables", name));
+            }
+            Some(_) => return Err(format!("key `{}` is already defined as a value", name)),
+        }
+
+        let item = if array {
+            Item::Tables(vec![Table::new(Kind::Header)])
+        } else {
+            Item::Table(Table::new(Kind::Header))
+        };
+        table.entries.push((last.clone(), item));
+        Ok(())
+    }
+
+    fn emit(self, tokens: &mut Vec<Token<'a>>) {
+        tokens.push(Token::MapStart(Some(self.entries.len())));
+        for (key, item) in self.entries {
+            tokens.push(Token::MapSep);
+            tokens.push(match key {
+                Cow::Borrowed(key) => Token::Str(key),
+                Cow::Owned(key) => Token::String(key),
+            });
+            item.emit(tokens);
+        }
+        tokens.push(Token::MapEnd);
+    }
+}
+
+impl<'a> Item<'a> {
+    fn emit(self, tokens: &mut Vec<Token<'a>>) {
+        match self {
+            Item::Value(token) => tokens.push(token),
+            Item::Array(items) => {
+                tokens.push(Token::SeqStart(Some(items.len())));
+                for item in items {
+                    tokens.push(Token::SeqSep);
+                    item.emit(tokens);
+                }
+                tokens.push(Token::SeqEnd);
+            }
+            Item::Table(table) => table.emit(tokens),
+            Item::Tables(tables) => {
+                tokens.push(Token::SeqStart(Some(tables.len())));
+                for table in tables {
+                    tokens.push(Token::SeqSep);
+                    table.emit(tokens);
+                }
+                tokens.push(Token::SeqEnd);
+            }
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A TOML parser.
+pub struct Parser<'a> {
+    input: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+    max_depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// Construct a new `Parser` reading from `input`.
+    pub fn new(input: &'a str) -> Self {
+        Parser {
+            input: input,
+            bytes: input.as_bytes(),
+            pos: 0,
+            max_depth: 128,
+        }
+    }
+
+    /// Sets how many levels deep tables and arrays may nest. Beyond that, parsing fails at the
+    /// key or bracket that opens the next level.
+    ///
+    /// ```rust
+    /// use serde::token::toml::Parser;
+    ///
+    /// assert!(Parser::new("a = [[1]]").max_depth(3).parse().is_ok());
+    /// let err = Parser::new("a = [[1]]").max_depth(2).parse().unwrap_err();
+    /// assert_eq!(err.to_string(), "nesting exceeds the depth limit of 2 at line 1 column 6");
+    /// ```
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Parses the whole document.
+    pub fn parse(mut self) -> Result<Vec<Token<'a>>, Error> {
+        let mut root = Table::new(Kind::Header);
+        let mut current = Vec::new();
+
+        loop {
+            self.skip_blank_lines();
+            let start = self.pos;
+
+            match self.peek() {
+                None => break,
+                Some(b'[') => {
+                    let array = self.bytes.get(self.pos + 1) == Some(&b'[');
+                    self.pos += if array { 2 } else { 1 };
+                    self.skip_whitespace();
+                    let path = try!(self.key());
+                    if path.len() >= self.max_depth {
+                        return Err(self.depth_error(start));
+                    }
+                    let close = if array { "]]" } else { "]" };
+                    if !self.input[self.pos..].starts_with(close) {
+                        return Err(self.error(&format!("expected `{}`", close)));
+                    }
+                    self.pos += close.len();
+                    try!(self.line_end());
+
+                    try!(root.define(&path, array).map_err(|msg| self.error_at(start, &msg)));
+                    current = path;
+                }
+                Some(_) => {
+                    let (path, item) = try!(self.key_value(current.len()));
+                    try!(self.line_end());
+
+                    let table = root.find_mut(&current);
+                    try!(table.insert(path, item).map_err(|msg| self.error_at(start, &msg)));
+                }
+            }
+        }
+
+        let mut tokens = Vec::new();
+        root.emit(&mut tokens);
+        Ok(tokens)
+    }
+
+    /// Parses a key and its value in a table that is `depth` levels deep.
+    fn key_value(&mut self, depth: usize) -> Result<(Vec<Cow<'a, str>>, Item<'a>), Error> {
+        let start = self.pos;
+        let path = try!(self.key());
+        // Every part of a dotted key but the last one is a table of its own.
+        if depth + path.len() > self.max_depth {
+            return Err(self.depth_error(start));
+        }
+        if self.peek() != Some(b'=') {
+            return Err(self.error("expected `=`"));
+        }
+        self.pos += 1;
+        self.skip_whitespace();
+        let item = try!(self.value(depth + path.len()));
+        Ok((path, item))
+    }
+
+    /// Parses a possibly dotted key and the whitespace after it.
+    fn key(&mut self) -> Result<Vec<Cow<'a, str>>, Error> {
+        let mut path = Vec::new();
+        loop {
+            let key = match self.peek() {
+                Some(b'"') | Some(b'\'') => try!(self.string(false)),
+                _ => {
+                    let start = self.pos;
+                    while let Some(b'A' ... b'Z') | Some(b'a' ... b'z') | Some(b'0' ... b'9') |
+                              Some(b'_') | Some(b'-') = self.peek() {
+                        self.pos += 1;
+                    }
+                    if self.pos == start {
+                        return Err(self.error("expected a key"));
+                    }
+                    Cow::Borrowed(&self.input[start..self.pos])
+                }
+            };
+            path.push(key);
+
+            self.skip_whitespace();
+            if self.peek() != Some(b'.') {
+                return Ok(path);
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Item<'a>, Error> {
+        let token = match self.peek() {
+            Some(b'"') | Some(b'\'') => {
+                match try!(self.string(true)) {
+                    Cow::Borrowed(v) => Token::Str(v),
+                    Cow::Owned(v) => Token::String(v),
+                }
+            }
+            Some(b'[') => return self.array(depth),
+            Some(b'{') => return self.inline_table(depth),
+            Some(_) => try!(self.scalar()),
+            None => return Err(self.error("expected a value")),
+        };
+        Ok(Item::Value(token))
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Item<'a>, Error> {
+        if depth >= self.max_depth {
+            return Err(self.depth_error(self.pos));
+        }
+        self.pos += 1;
+        let mut items = Vec::new();
+        loop {
+            self.skip_array_whitespace();
+            if self.peek() == Some(b']') {
+                self.pos += 1;
+                return Ok(Item::Array(items));
+            }
+
+            items.push(try!(self.value(depth + 1)));
+
+            self.skip_array_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => { }
+                _ => return Err(self.error("expected `,` or `]`")),
+            }
+        }
+    }
+
+    fn inline_table(&mut self, depth: usize) -> Result<Item<'a>, Error> {
+        if depth >= self.max_depth {
+            return Err(self.depth_error(self.pos));
+        }
+        self.pos += 1;
+        let mut table = Table::new(Kind::Inline);
+
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Item::Table(table));
+        }
+
+        loop {
+            let start = self.pos;
+            let (path, item) = try!(self.key_value(depth));
+            try!(table.insert(path, item).map_err(|msg| self.error_at(start, &msg)));
+
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => {
+                    self.pos += 1;
+                    self.skip_whitespace();
+                }
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Item::Table(table));
+                }
+                _ => return Err(self.error("expected `,` or `}`")),
+            }
+        }
+    }
+
+    /// Parses a boolean, number or date-time.
+    fn scalar(&mut self) -> Result<Token<'a>, Error> {
+        let start = self.pos;
+        self.skip_scalar_chars();
+
+        // A space may separate the date from the time.
+        if self.pos - start == 10 && looks_like_date(&self.bytes[start..self.pos]) &&
+           self.peek() == Some(b' ') &&
+           self.bytes.get(self.pos + 1).map_or(false, |b| (*b as char).is_digit(10)) {
+            self.pos += 1;
+            self.skip_scalar_chars();
+        }
+
+        let text = &self.input[start..self.pos];
+        let token = match text {
+            "true" => Token::Bool(true),
+            "false" => Token::Bool(false),
+            "inf" | "+inf" => Token::F64(f64::INFINITY),
+            "-inf" => Token::F64(f64::NEG_INFINITY),
+            "nan" | "+nan" | "-nan" => Token::F64(f64::NAN),
+            _ if text.len() >= 10 && looks_like_date(text.as_bytes()) => {
+                match time::parse_rfc3339(text) {
+                    Some((secs, nanos)) => Token::Timestamp(secs, nanos),
+                    None if has_offset(text.as_bytes()) => {
+                        return Err(self.error_at(start, "invalid date-time"));
+                    }
+                    None => {
+                        return Err(self.error_at(start,
+                                                 "local dates and times are not supported"));
+                    }
+                }
+            }
+            _ => {
+                match number(text) {
+                    Ok(token) => token,
+                    Err(msg) => return Err(self.error_at(start, msg)),
+                }
+            }
+        };
+        Ok(token)
+    }
+
+    fn skip_scalar_chars(&mut self) {
+        while let Some(b'A' ... b'Z') | Some(b'a' ... b'z') | Some(b'0' ... b'9') | Some(b'_') |
+                  Some(b'+') | Some(b'-') | Some(b'.') | Some(b':') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    /// Parses any of the four kinds of strings. Multiline strings are only allowed when
+    /// `multiline` is true, i.e. not in keys.
+    fn string(&mut self, multiline: bool) -> Result<Cow<'a, str>, Error> {
+        let quote = self.bytes[self.pos];
+        let triple = if quote == b'"' { b"\"\"\"" } else { b"'''" };
+
+        if multiline && self.bytes[self.pos..].starts_with(triple) {
+            self.pos += 3;
+            // A newline right after the opening delimiter is trimmed.
+            if self.bytes[self.pos..].starts_with(b"\r\n") {
+                self.pos += 2;
+            } else if self.peek() == Some(b'\n') {
+                self.pos += 1;
+            }
+            if quote == b'"' {
+                self.basic_string(true)
+            } else {
+                self.literal_string(true)
+            }
+        } else {
+            self.pos += 1;
+            if quote == b'"' {
+                self.basic_string(false)
+            } else {
+                self.literal_string(false)
+            }
+        }
+    }
+
+    /// Consumes the closing delimiter if it is next and returns where the content ends. A
+    /// multiline string may end with up to two quotes right before its closing delimiter.
+    fn close(&mut self, quote: u8, multiline: bool) -> Option<usize> {
+        if !multiline {
+            if self.peek() == Some(quote) {
+                self.pos += 1;
+                return Some(self.pos - 1);
+            }
+            return None;
+        }
+
+        let mut quotes = 0;
+        while self.bytes.get(self.pos + quotes) == Some(&quote) {
+            quotes += 1;
+        }
+        if quotes >= 3 && quotes <= 5 {
+            let end = self.pos + quotes - 3;
+            self.pos += quotes;
+            Some(end)
+        } else {
+            None
+        }
+    }
+
+    fn literal_string(&mut self, multiline: bool) -> Result<Cow<'a, str>, Error> {
+        let start = self.pos;
+        loop {
+            if let Some(end) = self.close(b'\'', multiline) {
+                return Ok(Cow::Borrowed(&self.input[start..end]));
+            }
+            try!(self.string_char(multiline));
+        }
+    }
+
+    fn basic_string(&mut self, multiline: bool) -> Result<Cow<'a, str>, Error> {
+        let start = self.pos;
+        // Only filled once the first escape shows up.
+        let mut out = String::new();
+        let mut copied = false;
+
+        loop {
+            let pos = self.pos;
+            if let Some(end) = self.close(b'"', multiline) {
+                if !copied {
+                    return Ok(Cow::Borrowed(&self.input[start..end]));
+                }
+                out.push_str(&self.input[pos..end]);
+                return Ok(Cow::Owned(out));
+            }
+
+            if self.peek() != Some(b'\\') {
+                try!(self.string_char(multiline));
+                if copied {
+                    out.push_str(&self.input[pos..self.pos]);
+                }
+                continue;
+            }
+
+            if !copied {
+                out.push_str(&self.input[start..self.pos]);
+                copied = true;
+            }
+            self.pos += 1;
+
+            let escaped = match self.next() {
+                Some(b'b') => '\u{8}',
+                Some(b't') => '\t',
+                Some(b'n') => '\n',
+                Some(b'f') => '\u{c}',
+                Some(b'r') => '\r',
+                Some(b'"') => '"',
+                Some(b'\\') => '\\',
+                Some(b'u') => try!(self.unicode_escape(4)),
+                Some(b'U') => try!(self.unicode_escape(8)),
+                Some(b' ') | Some(b'\t') | Some(b'\r') | Some(b'\n') if multiline => {
+                    // A backslash at the end of a line trims all whitespace up to the next
+                    // non-whitespace character.
+                    self.pos -= 1;
+                    self.skip_whitespace();
+                    if !self.bytes[self.pos..].starts_with(b"\n") &&
+                       !self.bytes[self.pos..].starts_with(b"\r\n") {
+                        return Err(self.error_at(pos, "invalid escape"));
+                    }
+                    while let Some(b' ') | Some(b'\t') | Some(b'\r') | Some(b'\n') = self.peek() {
+                        self.pos += 1;
+                    }
+                    continue;
+                }
+                Some(_) => return Err(self.error_at(pos, "invalid escape")),
+                None => return Err(self.error("EOF while parsing a string")),
+            };
+            out.push(escaped);
+        }
+    }
+
+    fn unicode_escape(&mut self, digits: usize) -> Result<char, Error> {
+        let start = self.pos - 2;
+        let hex = match self.input.get(self.pos..self.pos + digits) {
+            Some(hex) => hex,
+            None => return Err(self.error_at(start, "invalid unicode escape")),
+        };
+        self.pos += digits;
+
+        let code = match u32::from_str_radix(hex, 16) {
+            Ok(code) if hex.bytes().all(|b| (b as char).is_digit(16)) => code,
+            _ => return Err(self.error_at(start, "invalid unicode escape")),
+        };
+        char::from_u32(code).ok_or_else(|| self.error_at(start, "invalid unicode escape"))
+    }
+
+    /// Consumes the next character of a string's content.
+    fn string_char(&mut self, multiline: bool) -> Result<(), Error> {
+        match self.peek() {
+            None => Err(self.error("EOF while parsing a string")),
+            Some(b'\n') if multiline => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(b'\r') if multiline && self.bytes.get(self.pos + 1) == Some(&b'\n') => {
+                self.pos += 2;
+                Ok(())
+            }
+            Some(b'\n') => Err(self.error("newline in string")),
+            Some(b'\t') => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(0x00 ... 0x1f) | Some(0x7f) => Err(self.error("control character in string")),
+            Some(_) => {
+                let c = self.input[self.pos..].chars().next().expect("input is not empty");
+                self.pos += c.len_utf8();
+                Ok(())
+            }
+        }
+    }
+
+    /// Consumes the end of a line, which may have a comment.
+    fn line_end(&mut self) -> Result<(), Error> {
+        self.skip_whitespace();
+        self.skip_comment();
+        match self.peek() {
+            None => Ok(()),
+            Some(b'\n') => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(b'\r') if self.bytes.get(self.pos + 1) == Some(&b'\n') => {
+                self.pos += 2;
+                Ok(())
+            }
+            Some(_) => Err(self.error("expected a newline")),
+        }
+    }
+
+    fn skip_blank_lines(&mut self) {
+        loop {
+            self.skip_whitespace();
+            self.skip_comment();
+            match self.peek() {
+                Some(b'\n') => self.pos += 1,
+                Some(b'\r') if self.bytes.get(self.pos + 1) == Some(&b'\n') => self.pos += 2,
+                _ => return,
+            }
+        }
+    }
+
+    /// Skips whitespace, newlines and comments between array elements.
+    fn skip_array_whitespace(&mut self) {
+        loop {
+            self.skip_whitespace();
+            self.skip_comment();
+            match self.peek() {
+                Some(b'\n') | Some(b'\r') => self.pos += 1,
+                _ => return,
+            }
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\t') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some(b'#') {
+            while let Some(byte) = self.peek() {
+                if byte == b'\n' {
+                    break;
+                }
+                self.pos += 1;
+            }
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).cloned()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek();
+        if byte.is_some() {
+            self.pos += 1;
+        }
+        byte
+    }
+
+    fn depth_error(&self, pos: usize) -> Error {
+        let msg = format!("nesting exceeds the depth limit of {}", self.max_depth);
+        self.error_at(pos, &msg)
+    }
+
+    fn error(&self, msg: &str) -> Error {
+        self.error_at(self.pos, msg)
+    }
+
+    fn error_at(&self, pos: usize, msg: &str) -> Error {
+        let (line, column) = line_column(self.bytes, pos);
+        Error {
+            line: line,
+            column: column,
+            message: msg.to_owned(),
+            snippet: Snippet::new(self.bytes, pos),
+        }
+    }
+}
+
+/// Returns true if `text` starts with a `YYYY-MM-DD` date.
+fn looks_like_date(text: &[u8]) -> bool {
+    text.len() >= 10 && text[4] == b'-' && text[7] == b'-' &&
+    text[..10].iter().enumerate().all(|(i, b)| i == 4 || i == 7 || (*b as char).is_digit(10))
+}
+
+/// Returns true if the date-time `text` ends with a UTC offset, which local ones don't have.
+fn has_offset(text: &[u8]) -> bool {
+    let time = &text[10..];
+    match time.last() {
+        Some(&b'Z') | Some(&b'z') => true,
+        _ => time.len() > 6 && (time[time.len() - 6] == b'+' || time[time.len() - 6] == b'-'),
+    }
+}
+
+/// Parses an integer or a float.
+fn number(text: &str) -> Result<Token<'static>, &'static str> {
+    let (radix, digits) = if text.starts_with("0x") {
+        (16, &text[2..])
+    } else if text.starts_with("0o") {
+        (8, &text[2..])
+    } else if text.starts_with("0b") {
+        (2, &text[2..])
+    } else {
+        (10, text)
+    };
+
+    if !underscores_between_digits(digits, radix) {
+        return Err("invalid number");
+    }
+    let cleaned: String = digits.chars().filter(|&c| c != '_').collect();
+
+    if radix != 10 {
+        if cleaned.is_empty() || !cleaned.chars().all(|c| c.is_digit(radix)) {
+            return Err("invalid number");
+        }
+        return match i64::from_str_radix(&cleaned, radix) {
+            Ok(v) => Ok(Token::I64(v)),
+            Err(_) => Err("integer out of range"),
+        };
+    }
+
+    let unsigned = cleaned.trim_left_matches(|c| c == '+' || c == '-');
+    if cleaned.len() - unsigned.len() > 1 {
+        return Err("invalid number");
+    }
+
+    // The integer part of both integers and floats can't have leading zeros.
+    let integer_part = unsigned.split(|c| c == '.' || c == 'e' || c == 'E').next().unwrap_or("");
+    if integer_part.is_empty() || !integer_part.chars().all(|c| c.is_digit(10)) ||
+       (integer_part.len() > 1 && integer_part.starts_with('0')) {
+        return Err("invalid number");
+    }
+
+    if integer_part.len() == unsigned.len() {
+        return match cleaned.parse::<i64>() {
+            Ok(v) => Ok(Token::I64(v)),
+            Err(_) => Err("integer out of range"),
+        };
+    }
+
+    // A fraction needs digits on both sides of the dot.
+    if let Some(dot) = unsigned.find('.') {
+        if !unsigned[dot + 1..].starts_with(|c: char| c.is_digit(10)) {
+            return Err("invalid number");
+        }
+    }
+
+    match cleaned.parse::<f64>() {
+        Ok(v) if v.is_finite() => Ok(Token::F64(v)),
+        _ => Err("invalid number"),
+    }
+}
+
+/// Checks that every underscore in a number is surrounded by digits.
+fn underscores_between_digits(text: &str, radix: u32) -> bool {
+    let bytes = text.as_bytes();
+    bytes.iter().enumerate().all(|(i, &b)| {
+        b != b'_' ||
+        (i > 0 && i + 1 < bytes.len() && (bytes[i - 1] as char).is_digit(radix) &&
+         (bytes[i + 1] as char).is_digit(radix))
+    })
+}
@@ -0,0 +1,222 @@
+//! Check that a token stream is structurally well formed.
+//!
+//! Every `Serialize` implementation driving a `Serializer` correctly produces a well formed
+//! stream, so `validate` is mostly useful for streams that were written by hand or rewritten by
+//! an adapter. A stream is well formed when it contains exactly one value, every compound value
+//! is closed by its own end token, every element is introduced by its own separator, struct
+//! fields are named by strings, and every known length matches the number of elements.
+
+use std::error;
+use std::fmt;
+
+use super::Token;
+
+/// The error returned by `validate`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidateError {
+    index: usize,
+    message: String,
+}
+
+impl ValidateError {
+    /// Returns the index of the offending token in the stream, or of the last token when the
+    /// stream ended too early.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns a description of the problem.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for ValidateError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "token #{}: {}", self.index, self.message)
+    }
+}
+
+impl error::Error for ValidateError {
+    fn description(&self) -> &str {
+        "malformed token stream"
+    }
+}
+
+/// Checks that `iter` is a well formed stream containing a single value.
+pub fn validate<'a, I>(iter: I) -> Result<(), ValidateError>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let mut validator = Validator {
+        tokens: iter.into_iter(),
+        index: 0,
+    };
+
+    try!(validator.value());
+
+    match validator.next() {
+        None => Ok(()),
+        Some(token) => Err(validator.error(format!("expected the end of the stream, found {:?}",
+                                                   token))),
+    }
+}
+
+/// What follows each separator of a compound value.
+#[derive(Clone, Copy)]
+enum Element {
+    /// A single value.
+    Value,
+    /// A key value followed by a value.
+    Entry,
+    /// A field name followed by a value.
+    Field,
+}
+
+struct Validator<I> {
+    tokens: I,
+    /// The number of tokens consumed so far.
+    index: usize,
+}
+
+impl<'a, I> Validator<I>
+    where I: Iterator<Item=Token<'a>>,
+{
+    fn next(&mut self) -> Option<Token<'a>> {
+        let token = self.tokens.next();
+        if token.is_some() {
+            self.index += 1;
+        }
+        token
+    }
+
+    fn value(&mut self) -> Result<(), ValidateError> {
+        let token = match self.next() {
+            Some(token) => token,
+            None => return Err(self.error("expected a value, found the end of the stream")),
+        };
+
+        match token {
+            Token::Bool(_) |
+            Token::Isize(_) |
+            Token::I8(_) |
+            Token::I16(_) |
+            Token::I32(_) |
+            Token::I64(_) |
+            Token::Usize(_) |
+            Token::U8(_) |
+            Token::U16(_) |
+            Token::U32(_) |
+            Token::U64(_) |
+            Token::F32(_) |
+            Token::F64(_) |
+            Token::Char(_) |
+            Token::Str(_) |
+            Token::String(_) |
+            Token::Bytes(_) |
+            Token::ByteBuf(_) |
+            Token::Timestamp(_, _) |
+            Token::BigNum(_) |
+            Token::Option(false) |
+            Token::Unit |
+            Token::UnitStruct(_) |
+            Token::EnumUnit(_, _) => Ok(()),
+
+            Token::Option(true) |
+            Token::StructNewType(_) |
+            Token::TypeTag(_) |
+            Token::EnumNewType(_, _) => self.value(),
+
+            Token::EnumStart(_) => {
+                // The variant identifier followed by its payload.
+                try!(self.value());
+                self.value()
+            }
+
+            Token::SeqStart(len) => {
+                self.compound(len, Element::Value, Token::SeqSep, Token::SeqEnd)
+            }
+            Token::SeqArrayStart(len) => {
+                self.compound(Some(len), Element::Value, Token::SeqSep, Token::SeqEnd)
+            }
+            Token::TupleStart(len) => {
+                self.compound(Some(len), Element::Value, Token::TupleSep, Token::TupleEnd)
+            }
+            Token::TupleStructStart(_, len) => {
+                self.compound(len, Element::Value, Token::TupleStructSep, Token::TupleStructEnd)
+            }
+            Token::EnumSeqStart(_, _, len) => {
+                self.compound(len, Element::Value, Token::EnumSeqSep, Token::EnumSeqEnd)
+            }
+            Token::MapStart(len) => {
+                self.compound(len, Element::Entry, Token::MapSep, Token::MapEnd)
+            }
+            Token::StructStart(_, len) => {
+                self.compound(len, Element::Field, Token::StructSep, Token::StructEnd)
+            }
+            Token::EnumMapStart(_, _, len) => {
+                self.compound(len, Element::Field, Token::EnumMapSep, Token::EnumMapEnd)
+            }
+
+            token => Err(self.error(format!("expected a value, found {:?}", token))),
+        }
+    }
+
+    fn compound(&mut self,
+                len: Option<usize>,
+                element: Element,
+                sep: Token<'static>,
+                end: Token<'static>) -> Result<(), ValidateError> {
+        let mut count = 0;
+
+        loop {
+            match self.next() {
+                Some(ref token) if *token == sep => {
+                    match element {
+                        Element::Value => { }
+                        Element::Entry => try!(self.value()),
+                        Element::Field => {
+                            match self.next() {
+                                Some(ref token) if token.as_str().is_some() => { }
+                                Some(token) => {
+                                    return Err(self.error(format!(
+                                        "expected a field name, found {:?}", token)));
+                                }
+                                None => {
+                                    return Err(self.error(
+                                        "expected a field name, found the end of the stream"));
+                                }
+                            }
+                        }
+                    }
+                    try!(self.value());
+                    count += 1;
+                }
+                Some(ref token) if *token == end => {
+                    return match len {
+                        Some(len) if len != count => {
+                            Err(self.error(format!("expected {} elements, found {}", len, count)))
+                        }
+                        _ => Ok(()),
+                    };
+                }
+                Some(token) => {
+                    return Err(self.error(format!("expected {:?} or {:?}, found {:?}",
+                                                  sep, end, token)));
+                }
+                None => {
+                    return Err(self.error(format!("expected {:?} or {:?}, found the end of the \
+                                                   stream",
+                                                  sep, end)));
+                }
+            }
+        }
+    }
+
+    /// Returns an error pointing at the token that was consumed last.
+    fn error<M: Into<String>>(&self, message: M) -> ValidateError {
+        ValidateError {
+            index: if self.index == 0 { 0 } else { self.index - 1 },
+            message: message.into(),
+        }
+    }
+}
@@ -0,0 +1,260 @@
+//! A dynamically typed value that can hold anything a JSON document can.
+//!
+//! `Value` serializes into the same tokens the JSON reader produces, and `Value::from_tokens`
+//! builds one from any token stream, so it is a convenient common ground for comparing values
+//! that went through different formats. The `value!` macro writes one down like a JSON literal.
+
+use std::collections::BTreeMap;
+use std::i64;
+
+use ser::{Serialize, Serializer};
+
+use super::{DuplicateKeyPolicy, Error, Token, time, to_tokens};
+
+/// A dynamically typed value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// `()`, `None` and unit structs.
+    Null,
+    /// A boolean.
+    Bool(bool),
+    /// An integer that fits into an `i64`.
+    I64(i64),
+    /// An integer that is too large for an `i64`.
+    U64(u64),
+    /// A floating point number.
+    F64(f64),
+    /// A string, a character, a timestamp in RFC 3339 format or the digits of an integer that is
+    /// too large for a `u64`.
+    String(String),
+    /// A sequence, tuple, tuple struct or byte array.
+    Seq(Vec<Value>),
+    /// A map or struct. Keys that aren't strings are converted the same way the JSON writer
+    /// converts them.
+    Map(BTreeMap<String, Value>),
+}
+
+impl Serialize for Value {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer,
+    {
+        match *self {
+            Value::Null => serializer.serialize_unit(),
+            Value::Bool(v) => serializer.serialize_bool(v),
+            Value::I64(v) => serializer.serialize_i64(v),
+            Value::U64(v) => serializer.serialize_u64(v),
+            Value::F64(v) => serializer.serialize_f64(v),
+            Value::String(ref v) => serializer.serialize_str(v),
+            Value::Seq(ref v) => v.serialize(serializer),
+            Value::Map(ref v) => v.serialize(serializer),
+        }
+    }
+}
+
+impl Value {
+    /// Builds a `Value` from the single value in `iter`.
+    ///
+    /// Enum variants become single entry maps from the variant name to the payload, with unit
+    /// variants getting an empty sequence as payload, which is how the JSON writer writes them.
+    /// A key that appears more than once in the same map keeps its last value.
+    pub fn from_tokens<'a, I>(iter: I) -> Result<Value, Error>
+        where I: IntoIterator<Item=Token<'a>>,
+    {
+        Value::from_tokens_with(iter, DuplicateKeyPolicy::LastWins)
+    }
+
+    /// Builds a `Value` from the single value in `iter` like `from_tokens`, with `policy`
+    /// deciding what happens to keys that appear more than once in the same map.
+    ///
+    /// ```rust
+    /// use serde::token::{DuplicateKeyPolicy, Value, json};
+    ///
+    /// let tokens = json::from_str(r#"{"a": 1, "a": 2}"#).unwrap();
+    /// let value = Value::from_tokens_with(tokens.clone(), DuplicateKeyPolicy::FirstWins).unwrap();
+    /// assert_eq!(value, Value::Map(vec![("a".to_owned(), Value::I64(1))].into_iter().collect()));
+    /// assert!(Value::from_tokens_with(tokens, DuplicateKeyPolicy::Error).is_err());
+    /// ```
+    pub fn from_tokens_with<'a, I>(iter: I, policy: DuplicateKeyPolicy) -> Result<Value, Error>
+        where I: IntoIterator<Item=Token<'a>>,
+    {
+        let mut builder = Builder {
+            tokens: iter.into_iter(),
+            pos: 0,
+            policy: policy,
+        };
+        let value = try!(builder.build());
+
+        match builder.tokens.next() {
+            None => Ok(value),
+            Some(token) => Err(unexpected(&token)),
+        }
+    }
+
+    /// Builds a `Value` from the tokens `value` serializes into, which is how the `value!` macro
+    /// converts the Rust expressions in it.
+    pub fn from_serialize<T: ?Sized>(value: &T) -> Result<Value, Error>
+        where T: Serialize,
+    {
+        Value::from_tokens(try!(to_tokens(value)))
+    }
+}
+
+struct Builder<I> {
+    tokens: I,
+    /// The index of the next token.
+    pos: usize,
+    policy: DuplicateKeyPolicy,
+}
+
+impl<'a, I> Builder<I>
+    where I: Iterator<Item=Token<'a>>,
+{
+    fn next(&mut self) -> Option<Token<'a>> {
+        self.pos += 1;
+        self.tokens.next()
+    }
+
+    fn build(&mut self) -> Result<Value, Error> {
+        let token = match self.next() {
+            Some(token) => token,
+            None => return Err(eof()),
+        };
+
+        let value = match token {
+            Token::Bool(v) => Value::Bool(v),
+            Token::Isize(v) => Value::I64(v as i64),
+            Token::I8(v) => Value::I64(v as i64),
+            Token::I16(v) => Value::I64(v as i64),
+            Token::I32(v) => Value::I64(v as i64),
+            Token::I64(v) => Value::I64(v),
+            Token::Usize(v) => unsigned(v as u64),
+            Token::U8(v) => unsigned(v as u64),
+            Token::U16(v) => unsigned(v as u64),
+            Token::U32(v) => unsigned(v as u64),
+            Token::U64(v) => unsigned(v),
+            Token::F32(v) => Value::F64(v as f64),
+            Token::F64(v) => Value::F64(v),
+            Token::Char(v) => Value::String(v.to_string()),
+            Token::Str(v) => Value::String(v.to_owned()),
+            Token::String(v) => Value::String(v),
+            Token::Bytes(v) => bytes(v),
+            Token::ByteBuf(ref v) => bytes(v),
+            Token::Timestamp(secs, nanos) => Value::String(time::format_rfc3339(secs, nanos)),
+            Token::BigNum(v) => Value::String(v),
+
+            Token::Option(false) | Token::Unit | Token::UnitStruct(_) => Value::Null,
+            Token::Option(true) | Token::StructNewType(_) | Token::TypeTag(_) => {
+                return self.build()
+            }
+
+            Token::EnumUnit(_, variant) => variant_value(variant, Value::Seq(Vec::new())),
+            Token::EnumNewType(_, variant) => variant_value(variant, try!(self.build())),
+
+            Token::SeqStart(_) |
+            Token::SeqArrayStart(_) |
+            Token::TupleStart(_) |
+            Token::TupleStructStart(_, _) => try!(self.build_seq()),
+            Token::EnumSeqStart(_, variant, _) => variant_value(variant, try!(self.build_seq())),
+
+            Token::MapStart(_) => try!(self.build_map()),
+            Token::StructStart(_, _) => try!(self.build_struct()),
+            Token::EnumMapStart(_, variant, _) => {
+                variant_value(variant, try!(self.build_struct()))
+            }
+
+            token => return Err(unexpected(&token)),
+        };
+
+        Ok(value)
+    }
+
+    fn build_seq(&mut self) -> Result<Value, Error> {
+        let mut seq = Vec::new();
+        loop {
+            match self.next() {
+                Some(ref token) if token.is_sep() => seq.push(try!(self.build())),
+                Some(ref token) if token.is_end() => return Ok(Value::Seq(seq)),
+                Some(token) => return Err(unexpected(&token)),
+                None => return Err(eof()),
+            }
+        }
+    }
+
+    fn build_map(&mut self) -> Result<Value, Error> {
+        let mut map = BTreeMap::new();
+        loop {
+            match self.next() {
+                Some(Token::MapSep) => {
+                    let position = self.pos;
+                    let key = match try!(self.build()) {
+                        Value::Bool(v) => v.to_string(),
+                        Value::I64(v) => v.to_string(),
+                        Value::U64(v) => v.to_string(),
+                        Value::F64(v) => format!("{:?}", v),
+                        Value::String(v) => v,
+                        key => {
+                            return Err(Error::InvalidValue(
+                                format!("map key must be a scalar, found {:?}", key)));
+                        }
+                    };
+                    let value = try!(self.build());
+                    try!(self.policy.insert(&mut map, key.clone(), value, &key, position));
+                }
+                Some(Token::MapEnd) => return Ok(Value::Map(map)),
+                Some(token) => return Err(unexpected(&token)),
+                None => return Err(eof()),
+            }
+        }
+    }
+
+    fn build_struct(&mut self) -> Result<Value, Error> {
+        let mut map = BTreeMap::new();
+        loop {
+            match self.next() {
+                Some(Token::StructSep) | Some(Token::EnumMapSep) => {
+                    let position = self.pos;
+                    let key = match self.next() {
+                        Some(token) => {
+                            match token.as_str() {
+                                Some(key) => key.to_owned(),
+                                None => return Err(unexpected(&token)),
+                            }
+                        }
+                        None => return Err(eof()),
+                    };
+                    let value = try!(self.build());
+                    try!(self.policy.insert(&mut map, key.clone(), value, &key, position));
+                }
+                Some(Token::StructEnd) | Some(Token::EnumMapEnd) => return Ok(Value::Map(map)),
+                Some(token) => return Err(unexpected(&token)),
+                None => return Err(eof()),
+            }
+        }
+    }
+}
+
+fn unsigned(v: u64) -> Value {
+    if v <= i64::MAX as u64 {
+        Value::I64(v as i64)
+    } else {
+        Value::U64(v)
+    }
+}
+
+fn bytes(v: &[u8]) -> Value {
+    Value::Seq(v.iter().map(|&b| Value::I64(b as i64)).collect())
+}
+
+fn variant_value(variant: &str, payload: Value) -> Value {
+    let mut map = BTreeMap::new();
+    map.insert(variant.to_owned(), payload);
+    Value::Map(map)
+}
+
+fn eof() -> Error {
+    Error::InvalidValue("unexpected end of stream".to_owned())
+}
+
+fn unexpected(token: &Token) -> Error {
+    Error::InvalidValue(format!("unexpected token {:?}", token))
+}
@@ -0,0 +1,760 @@
+//! Read XML documents as token streams, and write token streams as XML documents.
+//!
+//! The root element becomes the value of the document, its own name is dropped. An element that
+//! has neither attributes nor child elements becomes a `String` token with its text content,
+//! empty elements becoming empty strings. Any other element becomes a map with string keys:
+//!
+//! * every attribute becomes an entry whose key is the attribute name behind a configurable
+//!   prefix, `@` by default, so `<user id="7">` has an entry `"@id": "7"`,
+//! * every child element becomes an entry under its name, and children that share a name are
+//!   grouped into a single entry holding a sequence, in document order,
+//! * text content becomes an entry under a configurable key, `$text` by default.
+//!
+//! The entries follow the order in which their names first appear in the document, attributes
+//! first. Since the text of an element with children ends up in a single entry, mixed content
+//! is lossy: all of an element's text segments are concatenated with their position between
+//! the children lost, leading and trailing whitespace is trimmed, and whitespace-only text is
+//! dropped altogether. `<p>Hello <b>world</b>!</p>` thus becomes
+//! `{"b": "world", "$text": "Hello !"}`.
+//!
+//! Character and entity references are decoded, CDATA sections are read as text, and comments,
+//! processing instructions and the document type declaration are skipped. Namespace prefixes
+//! aren't resolved and stay part of the name, as in `"soap:Body"`. Every value is text, there
+//! is no attempt to recognize numbers or booleans. Elements may nest 128 levels deep by
+//! default, see `Parser::max_depth`.
+//!
+//! `to_xml` goes the other way, writing everything as elements: the fields of structs and the
+//! entries of maps become child elements named after the field or key, and the elements of
+//! sequences become `<item>` children. Its output reads back as the same structure, with every
+//! scalar turned into a string and a sequence into an entry named `item`.
+
+use std::char;
+use std::error;
+use std::fmt;
+use std::io;
+
+use super::{Token, line_column};
+use super::Error as WriteError;
+use super::time::{Rendered, TimestampFormat};
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// This represents all the possible errors that can occur while reading XML.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading from the underlying reader failed, or the input is not valid UTF-8.
+    Io(io::Error),
+
+    /// The input is not well-formed XML. Contains a description of the problem and the line and
+    /// column at which it was found.
+    Syntax(String, usize, usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Error::Io(ref err) => write!(formatter, "{}", err),
+            Error::Syntax(ref msg, line, column) => {
+                write!(formatter, "{} at line {} column {}", msg, line, column)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Io(ref err) => error::Error::description(err),
+            Error::Syntax(..) => "syntax error",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Io(ref err) => Some(err),
+            Error::Syntax(..) => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error)
+    }
+}
+
+/// Reads the XML document in `input` as a token stream with the default attribute prefix and
+/// text key.
+///
+/// ```rust
+/// use serde::token::{self, xml};
+///
+/// let tokens = xml::from_str(r#"<user id="7"><name>Ada</name></user>"#).unwrap();
+/// assert_eq!(token::to_json(tokens).unwrap(), r#"{"@id":"7","name":"Ada"}"#);
+/// ```
+pub fn from_str(input: &str) -> Result<Vec<Token<'static>>, Error> {
+    Parser::new(input.as_bytes()).parse()
+}
+
+/// Writes the single value in `iter` as an XML document whose root element is named `root`.
+///
+/// A value is written as the content of the element it belongs to:
+///
+/// * scalars become text, with `<`, `>` and `&` escaped, and `None` and `()` leave the element
+///   empty, as in `<root/>`. Byte arrays are written in lowercase hex and timestamps in RFC 3339,
+/// * the fields of a struct become child elements named after the field, the struct name is
+///   not written, and the entries of a map become child elements named after the key, which has
+///   to be a string that is a valid element name,
+/// * the elements of sequences, tuples and tuple structs become `<item>` children,
+/// * a unit variant becomes its name as text, and any other variant a child element named after
+///   the variant holding the payload.
+///
+/// Control characters other than tab, line feed and carriage return can't appear in an XML 1.0
+/// document, not even escaped, and are an error.
+///
+/// ```rust
+/// use serde::token::{self, Token};
+///
+/// let tokens = vec![
+///     Token::MapStart(Some(1)),
+///     Token::MapSep, Token::Str("q"), Token::Str("a < b"),
+///     Token::MapEnd,
+/// ];
+/// assert_eq!(token::to_xml(tokens, "query").unwrap(), "<query><q>a &lt; b</q></query>");
+/// ```
+pub fn to_xml<'a, I>(iter: I, root: &str) -> Result<String, WriteError>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    let mut writer = Writer {
+        tokens: iter.into_iter(),
+        out: String::new(),
+    };
+
+    let token = try!(writer.next());
+    try!(writer.element(root, token));
+
+    match writer.tokens.next() {
+        None => Ok(writer.out),
+        Some(token) => Err(unexpected(&token)),
+    }
+}
+
+struct Writer<I> {
+    tokens: I,
+    out: String,
+}
+
+impl<'a, I> Writer<I>
+    where I: Iterator<Item=Token<'a>>,
+{
+    fn next(&mut self) -> Result<Token<'a>, WriteError> {
+        self.tokens.next().ok_or_else(|| {
+            WriteError::InvalidValue("unexpected end of stream".to_owned())
+        })
+    }
+
+    /// Writes the element `name` with `token` and the tokens of its value as its content.
+    fn element(&mut self, name: &str, token: Token<'a>) -> Result<(), WriteError> {
+        try!(self.open(name));
+        let start = self.out.len();
+
+        try!(self.content(token));
+
+        if self.out.len() == start {
+            self.out.pop();
+            self.out.push_str("/>");
+        } else {
+            self.close(name);
+        }
+        Ok(())
+    }
+
+    fn content(&mut self, token: Token<'a>) -> Result<(), WriteError> {
+        if let Some(text) = scalar(&token) {
+            return self.text(&text);
+        }
+
+        match token {
+            Token::Option(false) | Token::Unit | Token::UnitStruct(_) => Ok(()),
+            Token::Option(true) | Token::StructNewType(_) | Token::TypeTag(_) => {
+                let token = try!(self.next());
+                self.content(token)
+            }
+
+            Token::EnumUnit(_, variant) => self.text(variant),
+            Token::EnumStart(_) => {
+                let variant = try!(self.next());
+                let variant = match variant.as_str() {
+                    Some(variant) => variant.to_owned(),
+                    None => return Err(unexpected(&variant)),
+                };
+                let token = try!(self.next());
+                self.element(&variant, token)
+            }
+            Token::EnumNewType(_, variant) => {
+                let token = try!(self.next());
+                self.element(variant, token)
+            }
+            Token::EnumSeqStart(_, variant, _) => {
+                try!(self.open(variant));
+                try!(self.items());
+                self.close(variant);
+                Ok(())
+            }
+            Token::EnumMapStart(_, variant, _) => {
+                try!(self.open(variant));
+                try!(self.entries());
+                self.close(variant);
+                Ok(())
+            }
+
+            Token::SeqStart(_) |
+            Token::SeqArrayStart(_) |
+            Token::TupleStart(_) |
+            Token::TupleStructStart(_, _) => self.items(),
+            Token::MapStart(_) | Token::StructStart(_, _) => self.entries(),
+
+            token => Err(unexpected(&token)),
+        }
+    }
+
+    fn open(&mut self, name: &str) -> Result<(), WriteError> {
+        if !is_name(name) {
+            return Err(WriteError::InvalidValue(format!("{:?} is not a valid XML element name",
+                                                        name)));
+        }
+        self.out.push('<');
+        self.out.push_str(name);
+        self.out.push('>');
+        Ok(())
+    }
+
+    fn close(&mut self, name: &str) {
+        self.out.push_str("</");
+        self.out.push_str(name);
+        self.out.push('>');
+    }
+
+    /// Writes the elements of a sequence as `<item>` children, up to and including its end.
+    fn items(&mut self) -> Result<(), WriteError> {
+        loop {
+            match try!(self.next()) {
+                ref token if token.is_sep() => {
+                    let token = try!(self.next());
+                    try!(self.element("item", token));
+                }
+                ref token if token.is_end() => return Ok(()),
+                token => return Err(unexpected(&token)),
+            }
+        }
+    }
+
+    /// Writes the entries of a map or the fields of a struct as children named after the key,
+    /// up to and including its end.
+    fn entries(&mut self) -> Result<(), WriteError> {
+        loop {
+            match try!(self.next()) {
+                Token::MapSep | Token::StructSep | Token::EnumMapSep => {
+                    let key = try!(self.next());
+                    let name = match key.as_str() {
+                        Some(name) => name.to_owned(),
+                        None => {
+                            return Err(WriteError::InvalidValue(format!(
+                                "map key {:?} can't be an XML element name", key)));
+                        }
+                    };
+                    let token = try!(self.next());
+                    try!(self.element(&name, token));
+                }
+                Token::MapEnd | Token::StructEnd | Token::EnumMapEnd => return Ok(()),
+                token => return Err(unexpected(&token)),
+            }
+        }
+    }
+
+    /// Writes `text` as character data, escaping markup.
+    fn text(&mut self, text: &str) -> Result<(), WriteError> {
+        for c in text.chars() {
+            match c {
+                '<' => self.out.push_str("&lt;"),
+                '>' => self.out.push_str("&gt;"),
+                '&' => self.out.push_str("&amp;"),
+                '\t' | '\n' | '\r' => self.out.push(c),
+                c if c < ' ' || c == '\u{fffe}' || c == '\u{ffff}' => {
+                    return Err(WriteError::InvalidValue(format!(
+                        "character U+{:04X} can't appear in an XML document", c as u32)));
+                }
+                c => self.out.push(c),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the text of a scalar token, or `None` if the token doesn't start a scalar.
+fn scalar(token: &Token) -> Option<String> {
+    let text = match *token {
+        Token::Bool(v) => v.to_string(),
+        Token::Isize(v) => v.to_string(),
+        Token::I8(v) => v.to_string(),
+        Token::I16(v) => v.to_string(),
+        Token::I32(v) => v.to_string(),
+        Token::I64(v) => v.to_string(),
+        Token::Usize(v) => v.to_string(),
+        Token::U8(v) => v.to_string(),
+        Token::U16(v) => v.to_string(),
+        Token::U32(v) => v.to_string(),
+        Token::U64(v) => v.to_string(),
+        Token::F32(v) => float(v),
+        Token::F64(v) => float(v),
+        Token::Char(v) => v.to_string(),
+        Token::Str(v) => v.to_owned(),
+        Token::String(ref v) => v.clone(),
+        Token::Bytes(v) => hex(v),
+        Token::ByteBuf(ref v) => hex(v),
+        Token::Timestamp(secs, nanos) => {
+            match TimestampFormat::Rfc3339.render(secs, nanos) {
+                Rendered::Integer(v) => v.to_string(),
+                Rendered::Text(v) => v,
+            }
+        }
+        Token::BigNum(ref v) => v.clone(),
+        _ => return None,
+    };
+
+    Some(text)
+}
+
+/// Writes floats the way XML Schema spells them.
+fn float<F>(v: F) -> String
+    where F: Copy + Into<f64> + fmt::Debug,
+{
+    let wide: f64 = v.into();
+    if wide.is_nan() {
+        "NaN".to_owned()
+    } else if wide.is_infinite() {
+        if wide > 0.0 { "INF".to_owned() } else { "-INF".to_owned() }
+    } else {
+        format!("{:?}", v)
+    }
+}
+
+fn hex(v: &[u8]) -> String {
+    v.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Checks that `name` is an XML name without a namespace prefix or with a single one.
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.' || c == ':') &&
+    !name.ends_with(':') && name.matches(':').count() <= 1
+}
+
+fn unexpected(token: &Token) -> WriteError {
+    WriteError::InvalidValue(format!("unexpected token {:?}", token))
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// An element whose content has been read.
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+    text: String,
+}
+
+/// An XML parser.
+pub struct Parser<R> {
+    reader: R,
+    attribute_prefix: String,
+    text_key: String,
+    max_depth: usize,
+}
+
+impl<R> Parser<R>
+    where R: io::Read,
+{
+    /// Construct a new `Parser` reading from `reader`.
+    pub fn new(reader: R) -> Self {
+        Parser {
+            reader: reader,
+            attribute_prefix: "@".to_owned(),
+            text_key: "$text".to_owned(),
+            max_depth: 128,
+        }
+    }
+
+    /// Sets the prefix put in front of attribute names to tell them apart from child elements.
+    pub fn attribute_prefix(mut self, prefix: &str) -> Self {
+        self.attribute_prefix = prefix.to_owned();
+        self
+    }
+
+    /// Sets the key under which the text content of an element with attributes or children is
+    /// stored.
+    pub fn text_key(mut self, key: &str) -> Self {
+        self.text_key = key.to_owned();
+        self
+    }
+
+    /// Sets how many levels deep elements may nest, counting the root element. Beyond that,
+    /// parsing fails at the start tag that opens the next level.
+    ///
+    /// ```rust
+    /// use serde::token::xml::Parser;
+    ///
+    /// let input = "<a>\n  <b><c/></b>\n</a>";
+    /// assert!(Parser::new(input.as_bytes()).max_depth(3).parse().is_ok());
+    /// let err = Parser::new(input.as_bytes()).max_depth(2).parse().unwrap_err();
+    /// assert_eq!(err.to_string(), "nesting exceeds the depth limit of 2 at line 2 column 6");
+    /// ```
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Reads the whole document.
+    pub fn parse(mut self) -> Result<Vec<Token<'static>>, Error> {
+        let mut input = String::new();
+        try!(self.reader.read_to_string(&mut input));
+
+        let root = {
+            let mut reader = Reader {
+                input: &input,
+                bytes: input.as_bytes(),
+                pos: 0,
+                max_depth: self.max_depth,
+            };
+            try!(reader.document())
+        };
+
+        let mut tokens = Vec::new();
+        self.emit(root, &mut tokens);
+        Ok(tokens)
+    }
+
+    fn emit(&self, element: Element, tokens: &mut Vec<Token<'static>>) {
+        if element.attributes.is_empty() && element.children.is_empty() {
+            tokens.push(Token::String(element.text));
+            return;
+        }
+
+        // Group the children by name, in the order the names first appear.
+        let mut groups: Vec<(String, Vec<Element>)> = Vec::new();
+        for child in element.children {
+            match groups.iter().position(|group| group.0 == child.name) {
+                Some(index) => groups[index].1.push(child),
+                None => groups.push((child.name.clone(), vec![child])),
+            }
+        }
+
+        let text = element.text.trim();
+        let len = element.attributes.len() + groups.len() + if text.is_empty() { 0 } else { 1 };
+        tokens.push(Token::MapStart(Some(len)));
+
+        for (name, value) in element.attributes {
+            tokens.push(Token::MapSep);
+            tokens.push(Token::String(format!("{}{}", self.attribute_prefix, name)));
+            tokens.push(Token::String(value));
+        }
+
+        for (name, mut children) in groups {
+            tokens.push(Token::MapSep);
+            tokens.push(Token::String(name));
+            if children.len() == 1 {
+                self.emit(children.pop().expect("groups are never empty"), tokens);
+            } else {
+                tokens.push(Token::SeqStart(Some(children.len())));
+                for child in children {
+                    tokens.push(Token::SeqSep);
+                    self.emit(child, tokens);
+                }
+                tokens.push(Token::SeqEnd);
+            }
+        }
+
+        if !text.is_empty() {
+            tokens.push(Token::MapSep);
+            tokens.push(Token::String(self.text_key.clone()));
+            tokens.push(Token::String(text.to_owned()));
+        }
+
+        tokens.push(Token::MapEnd);
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+struct Reader<'a> {
+    input: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+    max_depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn document(&mut self) -> Result<Element, Error> {
+        // A byte order mark is allowed before the prolog.
+        if self.input.starts_with('\u{feff}') {
+            self.pos += 3;
+        }
+
+        try!(self.misc());
+        if self.peek() != Some(b'<') {
+            return Err(self.error("expected the root element"));
+        }
+        let root = try!(self.element(0));
+
+        try!(self.misc());
+        match self.peek() {
+            None => Ok(root),
+            Some(_) => Err(self.error("trailing characters after the root element")),
+        }
+    }
+
+    /// Skips whitespace, comments, processing instructions and the document type declaration.
+    fn misc(&mut self) -> Result<(), Error> {
+        loop {
+            self.skip_whitespace();
+            let rest = &self.input[self.pos..];
+            if rest.starts_with("<!--") {
+                try!(self.skip_past("-->", "unterminated comment"));
+            } else if rest.starts_with("<?") {
+                try!(self.skip_past("?>", "unterminated processing instruction"));
+            } else if rest.starts_with("<!DOCTYPE") {
+                try!(self.doctype());
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Skips a document type declaration, including an internal subset in brackets.
+    fn doctype(&mut self) -> Result<(), Error> {
+        let start = self.pos;
+        let mut depth = 0;
+        while let Some(byte) = self.next() {
+            match byte {
+                b'[' => depth += 1,
+                b']' => depth -= 1,
+                b'>' if depth == 0 => return Ok(()),
+                _ => { }
+            }
+        }
+        Err(self.error_at(start, "unterminated document type declaration"))
+    }
+
+    /// Reads an element that is `depth` levels below the root, starting at its `<`.
+    fn element(&mut self, depth: usize) -> Result<Element, Error> {
+        let start = self.pos;
+        if depth >= self.max_depth {
+            let msg = format!("nesting exceeds the depth limit of {}", self.max_depth);
+            return Err(self.error_at(start, &msg));
+        }
+        self.pos += 1;
+        let name = try!(self.name());
+
+        let mut element = Element {
+            name: name.to_owned(),
+            attributes: Vec::new(),
+            children: Vec::new(),
+            text: String::new(),
+        };
+
+        loop {
+            let had_whitespace = self.skip_whitespace();
+            match self.peek() {
+                Some(b'/') => {
+                    self.pos += 1;
+                    if self.next() != Some(b'>') {
+                        return Err(self.error_at(self.pos - 1, "expected `>`"));
+                    }
+                    return Ok(element);
+                }
+                Some(b'>') => {
+                    self.pos += 1;
+                    break;
+                }
+                Some(_) if had_whitespace => {
+                    let attribute_start = self.pos;
+                    let (name, value) = try!(self.attribute());
+                    if element.attributes.iter().any(|attribute| attribute.0 == name) {
+                        return Err(self.error_at(attribute_start,
+                                                 &format!("duplicate attribute `{}`", name)));
+                    }
+                    element.attributes.push((name, value));
+                }
+                Some(_) => return Err(self.error("expected whitespace, `>` or `/>`")),
+                None => return Err(self.error_at(start, "unterminated start tag")),
+            }
+        }
+
+        try!(self.content(&mut element, depth));
+
+        let end_start = self.pos;
+        self.pos += 2;
+        let end_name = try!(self.name());
+        if end_name != element.name {
+            return Err(self.error_at(end_start,
+                                     &format!("expected `</{}>`, found `</{}>`",
+                                              element.name,
+                                              end_name)));
+        }
+        self.skip_whitespace();
+        if self.next() != Some(b'>') {
+            return Err(self.error_at(self.pos.saturating_sub(1), "expected `>`"));
+        }
+        Ok(element)
+    }
+
+    /// Reads the content of an element up to its end tag, leaving the `</` unread.
+    fn content(&mut self, element: &mut Element, depth: usize) -> Result<(), Error> {
+        loop {
+            let rest = &self.input[self.pos..];
+            if rest.starts_with("</") {
+                return Ok(());
+            } else if rest.starts_with("<!--") {
+                try!(self.skip_past("-->", "unterminated comment"));
+            } else if rest.starts_with("<![CDATA[") {
+                let start = self.pos + 9;
+                self.pos = start;
+                try!(self.skip_past("]]>", "unterminated CDATA section"));
+                element.text.push_str(&self.input[start..self.pos - 3]);
+            } else if rest.starts_with("<?") {
+                try!(self.skip_past("?>", "unterminated processing instruction"));
+            } else if rest.starts_with('<') {
+                element.children.push(try!(self.element(depth + 1)));
+            } else if rest.starts_with('&') {
+                let c = try!(self.reference());
+                element.text.push(c);
+            } else if rest.is_empty() {
+                return Err(self.error(&format!("missing end tag `</{}>`", element.name)));
+            } else {
+                let len = rest.find(|c| c == '<' || c == '&').unwrap_or(rest.len());
+                element.text.push_str(&rest[..len]);
+                self.pos += len;
+            }
+        }
+    }
+
+    fn attribute(&mut self) -> Result<(String, String), Error> {
+        let name = try!(self.name()).to_owned();
+        self.skip_whitespace();
+        if self.next() != Some(b'=') {
+            return Err(self.error_at(self.pos.saturating_sub(1), "expected `=`"));
+        }
+        self.skip_whitespace();
+
+        let quote = match self.next() {
+            Some(quote @ b'"') | Some(quote @ b'\'') => quote,
+            _ => return Err(self.error_at(self.pos.saturating_sub(1), "expected a quote")),
+        };
+
+        let mut value = String::new();
+        loop {
+            match self.peek() {
+                Some(byte) if byte == quote => {
+                    self.pos += 1;
+                    return Ok((name, value));
+                }
+                Some(b'&') => value.push(try!(self.reference())),
+                Some(b'<') => return Err(self.error("`<` in attribute value")),
+                Some(_) => {
+                    let rest = &self.input[self.pos..];
+                    let len = rest.find(|c| c == quote as char || c == '&' || c == '<')
+                                  .unwrap_or(rest.len());
+                    value.push_str(&rest[..len]);
+                    self.pos += len;
+                }
+                None => return Err(self.error("unterminated attribute value")),
+            }
+        }
+    }
+
+    /// Decodes a character or predefined entity reference, starting at its `&`.
+    fn reference(&mut self) -> Result<char, Error> {
+        let start = self.pos;
+        let len = match self.input[start..].find(';') {
+            Some(len) => len,
+            None => return Err(self.error("unterminated reference")),
+        };
+        let body = &self.input[start + 1..start + len];
+        self.pos += len + 1;
+
+        let c = match body {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ if body.starts_with("#x") => {
+                u32::from_str_radix(&body[2..], 16).ok().and_then(char::from_u32)
+            }
+            _ if body.starts_with('#') => body[1..].parse().ok().and_then(char::from_u32),
+            _ => return Err(self.error_at(start, &format!("unknown entity `&{};`", body))),
+        };
+        c.ok_or_else(|| self.error_at(start, &format!("invalid character reference `&{};`", body)))
+    }
+
+    fn name(&mut self) -> Result<&'a str, Error> {
+        let start = self.pos;
+        let input = self.input;
+        let len = input[start..]
+            .find(|c: char| {
+                c.is_whitespace() || c == '/' || c == '>' || c == '=' || c == '<' || c == '"' ||
+                c == '\''
+            })
+            .unwrap_or(input.len() - start);
+        if len == 0 {
+            return Err(self.error("expected a name"));
+        }
+        self.pos += len;
+        Ok(&input[start..start + len])
+    }
+
+    /// Moves past the next occurrence of `end`.
+    fn skip_past(&mut self, end: &str, msg: &str) -> Result<(), Error> {
+        match self.input[self.pos..].find(end) {
+            Some(index) => {
+                self.pos += index + end.len();
+                Ok(())
+            }
+            None => Err(self.error(msg)),
+        }
+    }
+
+    /// Skips whitespace, returning whether there was any.
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.pos;
+        while let Some(b' ') | Some(b'\t') | Some(b'\r') | Some(b'\n') = self.peek() {
+            self.pos += 1;
+        }
+        self.pos != start
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).cloned()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek();
+        if byte.is_some() {
+            self.pos += 1;
+        }
+        byte
+    }
+
+    fn error(&self, msg: &str) -> Error {
+        self.error_at(self.pos, msg)
+    }
+
+    fn error_at(&self, pos: usize, msg: &str) -> Error {
+        let (line, column) = line_column(self.bytes, pos);
+        Error::Syntax(msg.to_owned(), line, column)
+    }
+}
@@ -0,0 +1,1533 @@
+//! Read and write token streams as YAML.
+//!
+//! The writer only supports the flow style: maps and structs become `{key: value}` mappings,
+//! sequences, tuples and tuple structs become `[a, b]` sequences, and `None` and `()` become
+//! `null`. Unit variants are written as their name, other enum variants as a single entry
+//! mapping from the variant name to the payload. Timestamps are written as RFC 3339 strings,
+//! unless `FlowWriter::timestamp_format` asks for another `TimestampFormat`, and byte strings as
+//! base64 strings, unless `FlowWriter::bytes_encoding` asks for another `BytesEncoding`.
+//!
+//! Strings are written plain whenever that reads back as the same string, and double quoted
+//! otherwise, e.g. when they would be mistaken for a number, a boolean or `null`.
+//!
+//! The reader supports the block style subset that configuration files are written in, see
+//! `from_str`. `Documents` reads inputs with several documents, and `ReadDocuments` reads them
+//! from an `io::Read` one at a time.
+
+use std::char;
+use std::error;
+use std::f64;
+use std::fmt;
+use std::io::{self, BufRead};
+use std::marker::PhantomData;
+
+use super::{Error, Snippet, Token, into_owned, line_column};
+use super::borrowed::{self, DeserializeBorrowed};
+use super::encoding::{self, BytesEncoding};
+use super::order::{FieldOrders, order_fields};
+use super::time::{Rendered, TimestampFormat};
+
+/// Writes the single value in `iter` as a YAML flow collection or scalar.
+///
+/// ```rust
+/// use serde::token::{self, yaml};
+///
+/// let value = vec![("a", 1)];
+/// let yaml = yaml::to_yaml_flow(token::to_tokens(&value).unwrap()).unwrap();
+/// assert_eq!(yaml, "[[a, 1]]");
+/// ```
+pub fn to_yaml_flow<'a, I>(iter: I) -> Result<String, Error>
+    where I: IntoIterator<Item=Token<'a>>,
+{
+    FlowWriter::new().write(iter)
+}
+
+/// Writes each of `documents` as a YAML flow collection or scalar, each one preceded by a `---`
+/// document marker.
+///
+/// ```rust
+/// use serde::token::{self, yaml};
+///
+/// let documents = vec![token::to_tokens(&1).unwrap(), token::to_tokens(&"two").unwrap()];
+/// assert_eq!(yaml::to_yaml_documents(documents).unwrap(), "---\n1\n---\ntwo\n");
+/// ```
+pub fn to_yaml_documents<'a, D, I>(documents: D) -> Result<String, Error>
+    where D: IntoIterator<Item=I>,
+          I: IntoIterator<Item=Token<'a>>,
+{
+    let writer = FlowWriter::new();
+    let mut out = String::new();
+    for document in documents {
+        out.push_str("---\n");
+        out.push_str(&try!(writer.write(document)));
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// Writes values in the YAML flow style, with options.
+///
+/// ```rust
+/// use serde::token::{Token, TimestampFormat};
+/// use serde::token::yaml::FlowWriter;
+///
+/// let yaml = FlowWriter::new()
+///     .timestamp_format(TimestampFormat::UnixSeconds)
+///     .write(vec![Token::Timestamp(1_000_000_000, 0)])
+///     .unwrap();
+/// assert_eq!(yaml, "1000000000");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct FlowWriter {
+    timestamp_format: TimestampFormat,
+    bytes_encoding: BytesEncoding,
+    field_order: Option<FieldOrders>,
+}
+
+impl FlowWriter {
+    /// Construct a new `FlowWriter` with the default options.
+    pub fn new() -> Self {
+        FlowWriter::default()
+    }
+
+    /// Sets how `Timestamp` tokens are written. Integer formats are written as plain integers.
+    pub fn timestamp_format(mut self, format: TimestampFormat) -> Self {
+        self.timestamp_format = format;
+        self
+    }
+
+    /// Sets how `Bytes` and `ByteBuf` tokens are encoded as strings.
+    pub fn bytes_encoding(mut self, encoding: BytesEncoding) -> Self {
+        self.bytes_encoding = encoding;
+        self
+    }
+
+    /// Reorders the fields of structs as `order_fields` does before writing them. The value has
+    /// to be read completely before any of it can be written.
+    pub fn field_order(mut self, orders: FieldOrders) -> Self {
+        self.field_order = Some(orders);
+        self
+    }
+
+    /// Writes the single value in `iter` as a YAML flow collection or scalar.
+    pub fn write<'a, I>(&self, iter: I) -> Result<String, Error>
+        where I: IntoIterator<Item=Token<'a>>,
+    {
+        match self.field_order {
+            Some(ref orders) => self.write_flow(try!(order_fields(iter, orders))),
+            None => self.write_flow(iter),
+        }
+    }
+
+    fn write_flow<'a, I>(&self, iter: I) -> Result<String, Error>
+        where I: IntoIterator<Item=Token<'a>>,
+    {
+        let mut flow = Flow {
+            tokens: iter.into_iter(),
+            out: String::new(),
+            timestamp_format: &self.timestamp_format,
+            bytes_encoding: self.bytes_encoding,
+        };
+
+        let token = try!(flow.next());
+        try!(flow.value(token));
+
+        match flow.tokens.next() {
+            None => Ok(flow.out),
+            Some(token) => Err(unexpected(&token)),
+        }
+    }
+}
+
+struct Flow<'f, I> {
+    tokens: I,
+    out: String,
+    timestamp_format: &'f TimestampFormat,
+    bytes_encoding: BytesEncoding,
+}
+
+impl<'a, 'f, I> Flow<'f, I>
+    where I: Iterator<Item=Token<'a>>,
+{
+    fn next(&mut self) -> Result<Token<'a>, Error> {
+        self.tokens.next().ok_or_else(|| {
+            Error::InvalidValue("unexpected end of stream".to_owned())
+        })
+    }
+
+    fn value(&mut self, token: Token<'a>) -> Result<(), Error> {
+        if let Some(text) = scalar(&token, self.timestamp_format, self.bytes_encoding) {
+            self.out.push_str(&text);
+            return Ok(());
+        }
+
+        match token {
+            Token::Option(true) | Token::StructNewType(_) | Token::TypeTag(_) => {
+                let token = try!(self.next());
+                self.value(token)
+            }
+
+            Token::EnumNewType(_, variant) => {
+                self.variant(variant);
+                let token = try!(self.next());
+                try!(self.value(token));
+                self.out.push('}');
+                Ok(())
+            }
+            Token::EnumSeqStart(_, variant, _) => {
+                self.variant(variant);
+                try!(self.seq());
+                self.out.push('}');
+                Ok(())
+            }
+            Token::EnumMapStart(_, variant, _) => {
+                self.variant(variant);
+                try!(self.fields());
+                self.out.push('}');
+                Ok(())
+            }
+
+            Token::SeqStart(_) |
+            Token::SeqArrayStart(_) |
+            Token::TupleStart(_) |
+            Token::TupleStructStart(_, _) => self.seq(),
+            Token::MapStart(_) => self.map(),
+            Token::StructStart(_, _) => self.fields(),
+
+            token => Err(unexpected(&token)),
+        }
+    }
+
+    /// Opens the single entry mapping that wraps the payload of an enum variant.
+    fn variant(&mut self, variant: &str) {
+        self.out.push('{');
+        self.out.push_str(&string(variant));
+        self.out.push_str(": ");
+    }
+
+    fn seq(&mut self) -> Result<(), Error> {
+        self.out.push('[');
+        let mut first = true;
+        loop {
+            match try!(self.next()) {
+                ref token if token.is_sep() => {
+                    if !first {
+                        self.out.push_str(", ");
+                    }
+                    first = false;
+                    let token = try!(self.next());
+                    try!(self.value(token));
+                }
+                ref token if token.is_end() => break,
+                token => return Err(unexpected(&token)),
+            }
+        }
+        self.out.push(']');
+        Ok(())
+    }
+
+    fn map(&mut self) -> Result<(), Error> {
+        self.out.push('{');
+        let mut first = true;
+        loop {
+            match try!(self.next()) {
+                Token::MapSep => {
+                    if !first {
+                        self.out.push_str(", ");
+                    }
+                    first = false;
+
+                    let key = try!(self.next());
+                    match scalar(&key, self.timestamp_format, self.bytes_encoding) {
+                        Some(text) => self.out.push_str(&text),
+                        None => {
+                            return Err(Error::InvalidValue(format!(
+                                "map key must be a scalar, found {:?}", key)));
+                        }
+                    }
+                    self.out.push_str(": ");
+
+                    let token = try!(self.next());
+                    try!(self.value(token));
+                }
+                Token::MapEnd => break,
+                token => return Err(unexpected(&token)),
+            }
+        }
+        self.out.push('}');
+        Ok(())
+    }
+
+    fn fields(&mut self) -> Result<(), Error> {
+        self.out.push('{');
+        let mut first = true;
+        loop {
+            match try!(self.next()) {
+                Token::StructSep | Token::EnumMapSep => {
+                    if !first {
+                        self.out.push_str(", ");
+                    }
+                    first = false;
+
+                    let key = try!(self.next());
+                    match key.as_str() {
+                        Some(key) => self.out.push_str(&string(key)),
+                        None => return Err(unexpected(&key)),
+                    }
+                    self.out.push_str(": ");
+
+                    let token = try!(self.next());
+                    try!(self.value(token));
+                }
+                Token::StructEnd | Token::EnumMapEnd => break,
+                token => return Err(unexpected(&token)),
+            }
+        }
+        self.out.push('}');
+        Ok(())
+    }
+}
+
+/// Returns the text of a scalar token, or `None` if the token doesn't start a scalar.
+fn scalar(token: &Token,
+          timestamp_format: &TimestampFormat,
+          bytes_encoding: BytesEncoding)
+          -> Option<String> {
+    let text = match *token {
+        Token::Bool(v) => v.to_string(),
+        Token::Isize(v) => v.to_string(),
+        Token::I8(v) => v.to_string(),
+        Token::I16(v) => v.to_string(),
+        Token::I32(v) => v.to_string(),
+        Token::I64(v) => v.to_string(),
+        Token::Usize(v) => v.to_string(),
+        Token::U8(v) => v.to_string(),
+        Token::U16(v) => v.to_string(),
+        Token::U32(v) => v.to_string(),
+        Token::U64(v) => v.to_string(),
+        Token::F32(v) => float(v),
+        Token::F64(v) => float(v),
+        Token::Char(v) => string(&v.to_string()),
+        Token::Str(v) => string(v),
+        Token::String(ref v) => string(v),
+        Token::Bytes(v) => string(&encoding::encode(v, bytes_encoding)),
+        Token::ByteBuf(ref v) => string(&encoding::encode(v, bytes_encoding)),
+        Token::Timestamp(secs, nanos) => {
+            match timestamp_format.render(secs, nanos) {
+                Rendered::Integer(v) => v.to_string(),
+                Rendered::Text(v) => string(&v),
+            }
+        }
+        Token::BigNum(ref v) => v.clone(),
+        Token::Option(false) | Token::Unit | Token::UnitStruct(_) => "null".to_owned(),
+        Token::EnumUnit(_, variant) => string(variant),
+        _ => return None,
+    };
+
+    Some(text)
+}
+
+fn float<F>(v: F) -> String
+    where F: Copy + Into<f64> + fmt::Debug,
+{
+    let wide: f64 = v.into();
+    if wide.is_nan() {
+        ".nan".to_owned()
+    } else if wide.is_infinite() {
+        if wide > 0.0 { ".inf".to_owned() } else { "-.inf".to_owned() }
+    } else {
+        format!("{:?}", v)
+    }
+}
+
+/// Words that a YAML 1.1 or 1.2 reader resolves to something other than a string.
+const RESERVED: &'static [&'static str] = &[
+    "~", "null", "Null", "NULL",
+    "true", "True", "TRUE", "false", "False", "FALSE",
+    "y", "Y", "yes", "Yes", "YES", "n", "N", "no", "No", "NO",
+    "on", "On", "ON", "off", "Off", "OFF",
+    ".nan", ".NaN", ".NAN", ".inf", ".Inf", ".INF",
+    "+.inf", "+.Inf", "+.INF", "-.inf", "-.Inf", "-.INF",
+];
+
+/// Writes a string plain if a reader would read it back as the same string, and double quoted
+/// otherwise.
+fn string(v: &str) -> String {
+    if is_plain_safe(v) {
+        return v.to_owned();
+    }
+
+    let mut out = String::from("\"");
+    for c in v.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\0' => out.push_str("\\0"),
+            c if (c as u32) < 0x20 || c == '\u{7f}' => {
+                out.push_str(&format!("\\x{:02x}", c as u32));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn is_plain_safe(v: &str) -> bool {
+    let first = match v.chars().next() {
+        Some(c) => c,
+        None => return false,
+    };
+
+    if RESERVED.contains(&v) || looks_like_number(v) {
+        return false;
+    }
+
+    // At the start of a line these are document markers.
+    if v.starts_with("---") || v.starts_with("...") {
+        return false;
+    }
+
+    // Indicator characters can't start a plain scalar.
+    if "-?:,[]{}#&*!|>'\"%@`".contains(first) || first.is_whitespace() {
+        return false;
+    }
+
+    if v.ends_with(char::is_whitespace) || v.ends_with(':') {
+        return false;
+    }
+
+    if v.contains(": ") || v.contains(" #") {
+        return false;
+    }
+
+    // Flow indicators end a plain scalar inside a flow collection.
+    !v.chars().any(|c| c.is_control() || ",[]{}".contains(c))
+}
+
+/// Returns true if a reader could resolve `v` to an integer or a float, in any of the notations
+/// of YAML 1.1 and 1.2.
+fn looks_like_number(v: &str) -> bool {
+    let digits = v.trim_left_matches(|c| c == '+' || c == '-');
+    let first = match digits.chars().next() {
+        Some(c) => c,
+        None => return false,
+    };
+
+    first.is_digit(10) || (first == '.' && digits[1..].starts_with(|c: char| c.is_digit(10)))
+}
+
+fn unexpected(token: &Token) -> Error {
+    Error::InvalidValue(format!("unexpected token {:?}", token))
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// The error returned when reading a YAML document fails.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    line: usize,
+    column: usize,
+    message: String,
+    snippet: Snippet,
+    document: Option<usize>,
+}
+
+impl ParseError {
+    /// Returns the index of the document the problem was found in, when reading `Documents`.
+    pub fn document(&self) -> Option<usize> {
+        self.document
+    }
+
+    /// Returns the one based line at which the problem was found.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Returns the one based column at which the problem was found.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Returns a description of the problem.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns the line of the input the problem was found in, which formatting the error with
+    /// `{:#}` shows.
+    pub fn snippet(&self) -> &Snippet {
+        &self.snippet
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(document) = self.document {
+            try!(write!(formatter, "document {}: ", document));
+        }
+        try!(write!(formatter, "{} at line {} column {}", self.message, self.line, self.column));
+        if formatter.alternate() {
+            try!(write!(formatter, "\n{}", self.snippet));
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for ParseError {
+    fn description(&self) -> &str {
+        "invalid YAML"
+    }
+}
+
+impl ParseError {
+    /// Moves the error down by `lines` lines, for a document that was read on its own after
+    /// `lines` lines of the input.
+    fn shift_lines(mut self, lines: usize) -> ParseError {
+        self.line += lines;
+        self.snippet = self.snippet.shift_lines(lines);
+        self
+    }
+}
+
+/// An error reading documents from an `io::Read` with `ReadDocuments`.
+#[derive(Debug)]
+pub enum ReadError {
+    /// Reading from the underlying reader failed, or the input is not UTF-8.
+    Io(io::Error),
+    /// A document is not valid YAML, or doesn't match the type it is deserialized into.
+    Parse(ParseError),
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReadError::Io(ref err) => fmt::Display::fmt(err, formatter),
+            ReadError::Parse(ref err) => fmt::Display::fmt(err, formatter),
+        }
+    }
+}
+
+impl error::Error for ReadError {
+    fn description(&self) -> &str {
+        match *self {
+            ReadError::Io(ref err) => error::Error::description(err),
+            ReadError::Parse(ref err) => error::Error::description(err),
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            ReadError::Io(ref err) => Some(err),
+            ReadError::Parse(ref err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for ReadError {
+    fn from(error: io::Error) -> ReadError {
+        ReadError::Io(error)
+    }
+}
+
+impl From<ParseError> for ReadError {
+    fn from(error: ParseError) -> ReadError {
+        ReadError::Parse(error)
+    }
+}
+
+/// Reads a YAML document written in block style as a token stream.
+///
+/// Block mappings become maps and block sequences become sequences, with lengths. Keys are
+/// always read as strings. Plain scalars are resolved with the core schema: `null`, `~` and
+/// empty values become `()`, `true` and `false` become `Bool`, integers in decimal, `0o` octal
+/// and `0x` hex notation become `I64`, or `U64` when they are too large for an `i64`, and floats,
+/// including `.inf` and `.nan`, become `F64`. Everything else, and every quoted scalar, is a
+/// string. Literal `|` and folded `>` block scalars are supported too.
+///
+/// Flow collections, anchors, aliases, tags, complex keys, directives, multi-line plain or
+/// quoted scalars and documents after the first are rejected as unsupported. `Documents` reads
+/// inputs with several documents. Duplicate keys are passed through, see `reject_dup_keys` and
+/// `DuplicateKeyPolicy`. Sequences and mappings may nest 128 levels deep, see
+/// `Documents::max_depth` for another limit.
+///
+/// ```rust
+/// use serde::token::{self, yaml};
+///
+/// let tokens = yaml::from_str("server:\n  port: 8080\n  hosts:\n  - a\n  - b\n").unwrap();
+/// assert_eq!(token::to_json(tokens).unwrap(), r#"{"server":{"port":8080,"hosts":["a","b"]}}"#);
+/// ```
+pub fn from_str(input: &str) -> Result<Vec<Token>, ParseError> {
+    let mut reader = Reader {
+        input: input,
+        lines: split_lines(input),
+        index: 0,
+        tokens: Vec::new(),
+        max_depth: 128,
+    };
+    try!(reader.document());
+    Ok(reader.tokens)
+}
+
+/// An iterator over the documents in a YAML string, separated by `---` markers. Every document
+/// is read the way `from_str` reads a single one, and yielded as its own token stream.
+///
+/// The first document doesn't need a marker, and a document may be closed by a `...` marker.
+/// Errors name the document they are in, with lines counted from the start of the whole input,
+/// and end the iteration unless `Documents::skip_invalid` is set.
+///
+/// ```rust
+/// use serde::token::{self, yaml};
+///
+/// let input = "a: 1\n---\n- 2\n---\nthree\n";
+/// let documents: Vec<_> = yaml::Documents::new(input).map(Result::unwrap).collect();
+/// assert_eq!(documents.len(), 3);
+/// assert_eq!(token::to_json(documents[1].clone()).unwrap(), "[2]");
+/// ```
+pub struct Documents<'a> {
+    input: &'a str,
+    lines: Vec<Line<'a>>,
+    /// The index of the line the next document starts on.
+    next: usize,
+    /// The byte offset of the last document.
+    offset: usize,
+    /// The index of the next document.
+    index: usize,
+    skip_invalid: bool,
+    max_depth: usize,
+    done: bool,
+}
+
+impl<'a> Documents<'a> {
+    /// Reads the documents in `input`.
+    pub fn new(input: &'a str) -> Self {
+        Documents {
+            input: input,
+            lines: split_lines(input),
+            next: 0,
+            offset: 0,
+            index: 0,
+            skip_invalid: false,
+            max_depth: 128,
+            done: false,
+        }
+    }
+
+    /// Keeps going after a document that fails to parse, with the document after it.
+    pub fn skip_invalid(mut self) -> Self {
+        self.skip_invalid = true;
+        self
+    }
+
+    /// Sets how many levels deep sequences and mappings may nest, 128 by default as with
+    /// `from_str`. Beyond that, parsing fails at the item or key that opens the next level.
+    ///
+    /// ```rust
+    /// use serde::token::yaml;
+    ///
+    /// let input = "a:\n  - - 1\n";
+    /// assert!(yaml::Documents::new(input).max_depth(3).next().unwrap().is_ok());
+    /// let err = yaml::Documents::new(input).max_depth(2).next().unwrap().unwrap_err();
+    /// assert_eq!(err.to_string(),
+    ///            "document 0: nesting exceeds the depth limit of 2 at line 2 column 5");
+    /// ```
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Returns an iterator that deserializes every document into a `T`. A document that doesn't
+    /// match `T` is reported as a `ParseError` at the start of the document.
+    ///
+    /// ```rust
+    /// use std::collections::BTreeMap;
+    /// use serde::token::yaml;
+    ///
+    /// let input = "port: 80\n---\nport: 443\n";
+    /// let ports: Vec<u16> = yaml::Documents::new(input)
+    ///     .deserialize::<BTreeMap<&str, u16>>()
+    ///     .map(|document| document.unwrap()["port"])
+    ///     .collect();
+    /// assert_eq!(ports, vec![80, 443]);
+    /// ```
+    pub fn deserialize<T>(self) -> Values<Self, T>
+        where T: DeserializeBorrowed<'a>,
+    {
+        Values::new(self)
+    }
+}
+
+impl<'a> Iterator for Documents<'a> {
+    type Item = Result<Vec<Token<'a>>, ParseError>;
+
+    fn next(&mut self) -> Option<Result<Vec<Token<'a>>, ParseError>> {
+        if self.done {
+            return None;
+        }
+
+        let start = match self.lines[self.next..].iter().position(|line| !line.is_blank()) {
+            Some(start) => self.next + start,
+            None => {
+                self.done = true;
+                return None;
+            }
+        };
+        let starts_document = |line: &Line| {
+            line.is_document_marker() && line.text.starts_with("---")
+        };
+        let end = match self.lines[start + 1..].iter().position(starts_document) {
+            Some(end) => start + 1 + end,
+            None => self.lines.len(),
+        };
+
+        let mut reader = Reader {
+            input: self.input,
+            lines: self.lines[start..end].to_vec(),
+            index: 0,
+            tokens: Vec::new(),
+            max_depth: self.max_depth,
+        };
+        let index = self.index;
+        self.index += 1;
+        self.next = end;
+        self.offset = self.lines[start].offset;
+
+        match reader.document() {
+            Ok(()) => Some(Ok(reader.tokens)),
+            Err(mut err) => {
+                self.done = !self.skip_invalid;
+                err.document = Some(index);
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// An iterator over the documents read from an `io::Read`, like `Documents` but without holding
+/// the whole input in memory. Every document is yielded as its own token stream, with owned
+/// strings.
+///
+/// ```rust
+/// use serde::token::{self, yaml};
+///
+/// let input: &[u8] = b"a: 1\n---\n- 2\n---\nthree\n";
+/// let documents: Vec<_> = yaml::ReadDocuments::new(input).map(Result::unwrap).collect();
+/// assert_eq!(documents.len(), 3);
+/// assert_eq!(token::to_json(documents[1].clone()).unwrap(), "[2]");
+/// ```
+pub struct ReadDocuments<R> {
+    lines: io::Lines<io::BufReader<R>>,
+    /// A line that was read but belongs to the next document.
+    pending: Option<String>,
+    /// The number of lines taken so far.
+    taken: usize,
+    /// The text of the last document, and the number of lines before it.
+    text: String,
+    start: usize,
+    /// The index of the next document.
+    index: usize,
+    skip_invalid: bool,
+    max_depth: usize,
+    done: bool,
+}
+
+impl<R> ReadDocuments<R>
+    where R: io::Read,
+{
+    /// Reads the documents in `reader`.
+    pub fn new(reader: R) -> Self {
+        ReadDocuments {
+            lines: io::BufReader::new(reader).lines(),
+            pending: None,
+            taken: 0,
+            text: String::new(),
+            start: 0,
+            index: 0,
+            skip_invalid: false,
+            max_depth: 128,
+            done: false,
+        }
+    }
+
+    /// Keeps going after a document that fails to parse, with the document after it.
+    pub fn skip_invalid(mut self) -> Self {
+        self.skip_invalid = true;
+        self
+    }
+
+    /// Sets how many levels deep sequences and mappings may nest, like `Documents::max_depth`.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Returns an iterator that deserializes every document into a `T`, like
+    /// `Documents::deserialize`.
+    pub fn deserialize<T>(self) -> Values<Self, T>
+        where T: DeserializeBorrowed<'static>,
+    {
+        Values::new(self)
+    }
+
+    fn take_line(&mut self) -> io::Result<Option<String>> {
+        let line = match self.pending.take() {
+            Some(line) => line,
+            None => {
+                match self.lines.next() {
+                    Some(line) => try!(line),
+                    None => return Ok(None),
+                }
+            }
+        };
+        self.taken += 1;
+        Ok(Some(line))
+    }
+
+    /// Reads the lines of the next document into `text`, returning false if there are none.
+    fn read_document(&mut self) -> io::Result<bool> {
+        self.text.clear();
+        while let Some(line) = try!(self.take_line()) {
+            if self.text.is_empty() {
+                if is_blank(&line) {
+                    continue;
+                }
+                self.start = self.taken - 1;
+            } else if split_lines(&line)[0].is_document_marker() && line.starts_with("---") {
+                self.pending = Some(line);
+                self.taken -= 1;
+                break;
+            }
+            self.text.push_str(&line);
+            self.text.push('\n');
+        }
+        Ok(!self.text.is_empty())
+    }
+}
+
+impl<R> Iterator for ReadDocuments<R>
+    where R: io::Read,
+{
+    type Item = Result<Vec<Token<'static>>, ReadError>;
+
+    fn next(&mut self) -> Option<Result<Vec<Token<'static>>, ReadError>> {
+        if self.done {
+            return None;
+        }
+
+        match self.read_document() {
+            Ok(true) => { }
+            Ok(false) => {
+                self.done = true;
+                return None;
+            }
+            Err(err) => {
+                self.done = true;
+                return Some(Err(ReadError::Io(err)));
+            }
+        }
+
+        let mut reader = Reader {
+            input: &self.text,
+            lines: split_lines(&self.text),
+            index: 0,
+            tokens: Vec::new(),
+            max_depth: self.max_depth,
+        };
+        let index = self.index;
+        self.index += 1;
+
+        match reader.document() {
+            Ok(()) => Some(Ok(reader.tokens.into_iter().map(into_owned).collect())),
+            Err(mut err) => {
+                self.done = !self.skip_invalid;
+                err.document = Some(index);
+                Some(Err(ReadError::Parse(err.shift_lines(self.start))))
+            }
+        }
+    }
+}
+
+/// Iterator returned by `Documents::deserialize` and `ReadDocuments::deserialize`.
+pub struct Values<D, T> {
+    documents: D,
+    /// The index of the next document.
+    index: usize,
+    marker: PhantomData<T>,
+}
+
+impl<D, T> Values<D, T> {
+    fn new(documents: D) -> Self {
+        Values {
+            documents: documents,
+            index: 0,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T> Iterator for Values<Documents<'a>, T>
+    where T: DeserializeBorrowed<'a>,
+{
+    type Item = Result<T, ParseError>;
+
+    fn next(&mut self) -> Option<Result<T, ParseError>> {
+        let index = self.index;
+        let document = match self.documents.next() {
+            Some(document) => document,
+            None => return None,
+        };
+        self.index += 1;
+        Some(document.and_then(|tokens| {
+            deserialize_tokens(tokens).map_err(|err| {
+                value_error(err, self.documents.input, self.documents.offset, index)
+            })
+        }))
+    }
+}
+
+impl<R, T> Iterator for Values<ReadDocuments<R>, T>
+    where R: io::Read,
+          T: DeserializeBorrowed<'static>,
+{
+    type Item = Result<T, ReadError>;
+
+    fn next(&mut self) -> Option<Result<T, ReadError>> {
+        let index = self.index;
+        let document = match self.documents.next() {
+            Some(document) => document,
+            None => return None,
+        };
+        self.index += 1;
+        Some(document.and_then(|tokens| {
+            deserialize_tokens(tokens).map_err(|err| {
+                let err = value_error(err, &self.documents.text, 0, index);
+                ReadError::Parse(err.shift_lines(self.documents.start))
+            })
+        }))
+    }
+}
+
+fn deserialize_tokens<'a, T>(tokens: Vec<Token<'a>>) -> Result<T, Error>
+    where T: DeserializeBorrowed<'a>,
+{
+    let mut deserializer = borrowed::Deserializer::new(tokens.into_iter()).coerce_keys(true);
+    let value = try!(T::deserialize_borrowed(&mut deserializer));
+    try!(deserializer.end());
+    Ok(value)
+}
+
+/// Returns an error for the document at `offset` of `input` not matching the type it is
+/// deserialized into.
+fn value_error(err: Error, input: &str, offset: usize, document: usize) -> ParseError {
+    let (line, column) = line_column(input.as_bytes(), offset);
+    ParseError {
+        line: line,
+        column: column,
+        message: err.to_string(),
+        snippet: Snippet::new(input.as_bytes(), offset),
+        document: Some(document),
+    }
+}
+
+/// Splits `input` into lines.
+fn split_lines(input: &str) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    for line in input.split('\n') {
+        // The line break ends the last line, rather than starting an empty one.
+        if offset == input.len() && offset > 0 {
+            break;
+        }
+        let text = line.trim_right_matches('\r');
+        let indent = text.len() - text.trim_left_matches(' ').len();
+        lines.push(Line {
+            indent: indent,
+            text: &text[indent..],
+            offset: offset + indent,
+        });
+        offset += line.len() + 1;
+    }
+    lines
+}
+
+/// A line of the document, split into its indentation and the rest.
+#[derive(Clone, Copy)]
+struct Line<'a> {
+    indent: usize,
+    text: &'a str,
+    /// The byte offset of `text` in the document.
+    offset: usize,
+}
+
+impl<'a> Line<'a> {
+    fn is_blank(&self) -> bool {
+        is_blank(self.text)
+    }
+
+    fn is_seq_item(&self) -> bool {
+        self.text == "-" || self.text.starts_with("- ") || self.text.starts_with("-\t")
+    }
+
+    fn is_document_marker(&self) -> bool {
+        self.indent == 0 &&
+        (self.text.starts_with("---") || self.text.starts_with("...")) &&
+        self.text[3..].chars().next().map_or(true, |c| c == ' ' || c == '\t')
+    }
+}
+
+struct Reader<'a> {
+    input: &'a str,
+    lines: Vec<Line<'a>>,
+    /// The index of the current line.
+    index: usize,
+    tokens: Vec<Token<'a>>,
+    max_depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn document(&mut self) -> Result<(), ParseError> {
+        let first = match try!(self.peek_line()) {
+            Some(line) => line,
+            None => {
+                self.tokens.push(Token::Unit);
+                return Ok(());
+            }
+        };
+
+        if first.text.starts_with('%') {
+            return Err(self.error(first.offset, "directives are not supported"));
+        }
+        if first.is_document_marker() && first.text.starts_with("---") {
+            if !is_blank(&first.text[3..]) {
+                return Err(self.error(first.offset + 4,
+                                      "content after the document marker is not supported"));
+            }
+            self.index += 1;
+        }
+
+        match try!(self.peek_line()) {
+            Some(line) if !line.is_document_marker() => try!(self.node(line, 0)),
+            _ => self.tokens.push(Token::Unit),
+        }
+
+        match try!(self.peek_line()) {
+            None => Ok(()),
+            Some(line) if line.is_document_marker() => {
+                self.index += 1;
+                match try!(self.peek_line()) {
+                    None if line.text.starts_with("...") => Ok(()),
+                    _ => Err(self.error(line.offset, "multiple documents are not supported")),
+                }
+            }
+            Some(line) => Err(self.error(line.offset, "unexpected indentation")),
+        }
+    }
+
+    /// Returns the next line with content, skipping blank lines and comments.
+    fn peek_line(&mut self) -> Result<Option<Line<'a>>, ParseError> {
+        while self.index < self.lines.len() && self.lines[self.index].is_blank() {
+            self.index += 1;
+        }
+        match self.lines.get(self.index) {
+            Some(line) if line.text.starts_with('\t') => {
+                Err(self.error(line.offset, "tab characters are not allowed in indentation"))
+            }
+            Some(line) => Ok(Some(*line)),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads the node that starts on `line`, `depth` levels below the top of the document.
+    fn node(&mut self, line: Line<'a>, depth: usize) -> Result<(), ParseError> {
+        if line.is_seq_item() {
+            self.seq(line.indent, depth)
+        } else if try!(self.key_end(line)).is_some() {
+            self.map(line.indent, depth)
+        } else {
+            self.index += 1;
+            self.scalar_value(line, line.text, line.offset, None)
+        }
+    }
+
+    fn seq(&mut self, indent: usize, depth: usize) -> Result<(), ParseError> {
+        try!(self.check_depth(depth));
+        let start = self.tokens.len();
+        self.tokens.push(Token::SeqStart(None));
+        let mut len = 0;
+
+        while let Some(line) = try!(self.peek_line()) {
+            if line.indent < indent || (line.indent == indent && !line.is_seq_item()) {
+                break;
+            }
+            if line.indent > indent || !line.is_seq_item() {
+                return Err(self.error(line.offset, "inconsistent indentation"));
+            }
+
+            self.tokens.push(Token::SeqSep);
+            len += 1;
+
+            let rest = line.text[1..].trim_left_matches(|c| c == ' ' || c == '\t');
+            let item = Line {
+                indent: line.indent + line.text.len() - rest.len(),
+                text: rest,
+                offset: line.offset + line.text.len() - rest.len(),
+            };
+            if item.is_blank() {
+                self.index += 1;
+                try!(self.nested(indent, false, depth + 1));
+            } else {
+                // The rest of the line is read as if it were a line of its own, indented as
+                // far as it is, so a mapping can continue on the lines below it.
+                self.lines[self.index] = item;
+                try!(self.node(item, depth + 1));
+            }
+        }
+
+        self.tokens[start] = Token::SeqStart(Some(len));
+        self.tokens.push(Token::SeqEnd);
+        Ok(())
+    }
+
+    fn map(&mut self, indent: usize, depth: usize) -> Result<(), ParseError> {
+        try!(self.check_depth(depth));
+        let start = self.tokens.len();
+        self.tokens.push(Token::MapStart(None));
+        let mut len = 0;
+
+        while let Some(line) = try!(self.peek_line()) {
+            if line.indent < indent || line.is_document_marker() {
+                break;
+            }
+            if line.indent > indent {
+                return Err(self.error(line.offset, "inconsistent indentation"));
+            }
+            let (key, value) = match try!(self.key_end(line)) {
+                Some(split) => split,
+                None => return Err(self.error(line.offset, "expected a mapping key")),
+            };
+
+            self.tokens.push(Token::MapSep);
+            len += 1;
+            self.tokens.push(key);
+
+            let rest = &line.text[value..];
+            self.index += 1;
+            if is_blank(rest) {
+                try!(self.nested(indent, true, depth + 1));
+            } else {
+                let rest_start = rest.len() - rest.trim_left_matches(|c| c == ' ' || c == '\t')
+                                                  .len();
+                try!(self.scalar_value(line,
+                                       &rest[rest_start..],
+                                       line.offset + value + rest_start,
+                                       Some(indent)));
+            }
+        }
+
+        self.tokens[start] = Token::MapStart(Some(len));
+        self.tokens.push(Token::MapEnd);
+        Ok(())
+    }
+
+    /// Reads the value of a sequence item or mapping entry that continues on the next lines, or
+    /// `()` if there is none. A mapping entry's value may be a sequence at the same indentation.
+    fn nested(&mut self, indent: usize, in_map: bool, depth: usize) -> Result<(), ParseError> {
+        match try!(self.peek_line()) {
+            Some(line) if line.indent > indent && !line.is_document_marker() => {
+                self.node(line, depth)
+            }
+            Some(line) if in_map && line.indent == indent && line.is_seq_item() => {
+                self.seq(indent, depth)
+            }
+            _ => {
+                self.tokens.push(Token::Unit);
+                Ok(())
+            }
+        }
+    }
+
+    /// If `line` starts with a mapping key, returns the key and the offset of the rest of the
+    /// line after the `:`.
+    fn key_end(&self, line: Line<'a>) -> Result<Option<(Token<'a>, usize)>, ParseError> {
+        let text = line.text;
+        let (key, end) = match text.chars().next() {
+            Some('"') | Some('\'') => {
+                let (key, len) = try!(self.quoted(text, line.offset));
+                (key, len)
+            }
+            Some('?') if text.len() == 1 || text[1..].starts_with(' ') => {
+                return Err(self.error(line.offset, "complex keys are not supported"));
+            }
+            _ => {
+                let mut end = None;
+                for (i, c) in text.char_indices() {
+                    if c == '#' && i > 0 && text[..i].ends_with(|c| c == ' ' || c == '\t') {
+                        break;
+                    }
+                    if c == ':' && text[i + 1..].chars().next().map_or(true, |c| {
+                        c == ' ' || c == '\t'
+                    }) {
+                        end = Some(i);
+                        break;
+                    }
+                }
+                match end {
+                    Some(end) => {
+                        let key = text[..end].trim_right_matches(|c| c == ' ' || c == '\t');
+                        try!(self.check_plain(key, line.offset));
+                        (Token::Str(key), end)
+                    }
+                    None => return Ok(None),
+                }
+            }
+        };
+
+        let rest = &text[end..];
+        let colon = rest.len() - rest.trim_left_matches(|c| c == ' ' || c == '\t').len();
+        let after = &rest[colon..];
+        if after.starts_with(':') &&
+           after[1..].chars().next().map_or(true, |c| c == ' ' || c == '\t') {
+            Ok(Some((key, end + colon + 1)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Reads the scalar `text` that starts at `offset` and ends the line. `parent` is the
+    /// indentation of the mapping the scalar belongs to, if any, which block scalars have to
+    /// be indented further than.
+    fn scalar_value(&mut self,
+                    line: Line<'a>,
+                    text: &'a str,
+                    offset: usize,
+                    parent: Option<usize>)
+                    -> Result<(), ParseError> {
+        let token = match text.chars().next() {
+            Some('"') | Some('\'') => {
+                let (token, len) = try!(self.quoted(text, offset));
+                if !is_blank(&text[len..]) {
+                    return Err(self.error(offset + len, "unexpected characters after a string"));
+                }
+                token
+            }
+            Some('|') | Some('>') => {
+                let parent = parent.unwrap_or(line.indent.saturating_sub(1));
+                try!(self.block_scalar(text, offset, parent))
+            }
+            _ => {
+                let end = text.find(" #").or_else(|| text.find("\t#")).unwrap_or(text.len());
+                let plain = text[..end].trim_right_matches(|c| c == ' ' || c == '\t');
+                try!(self.check_plain(plain, offset));
+                if plain.contains(": ") || plain.ends_with(':') {
+                    return Err(self.error(offset, "mapping values are not allowed here"));
+                }
+                resolve(plain)
+            }
+        };
+        self.tokens.push(token);
+
+        // A plain or quoted scalar would continue on a more indented line.
+        match try!(self.peek_line()) {
+            Some(next) if next.indent > line.indent && parent.is_some() => {
+                Err(self.error(next.offset, "multi-line scalars are not supported"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Rejects plain scalars that start with an indicator of an unsupported feature.
+    fn check_plain(&self, text: &str, offset: usize) -> Result<(), ParseError> {
+        let msg = match text.chars().next() {
+            Some('[') | Some('{') => "flow collections are not supported",
+            Some('&') | Some('*') => "anchors and aliases are not supported",
+            Some('!') => "tags are not supported",
+            Some('@') | Some('`') => "reserved indicator",
+            _ => return Ok(()),
+        };
+        Err(self.error(offset, msg))
+    }
+
+    /// Reads a single or double quoted scalar at the start of `text`, returning it together
+    /// with its length in `text`.
+    fn quoted(&self, text: &'a str, offset: usize) -> Result<(Token<'a>, usize), ParseError> {
+        let quote = text.as_bytes()[0];
+        let mut out = String::new();
+        let mut copied = false;
+        let mut chars = text.char_indices().skip(1);
+
+        while let Some((i, c)) = chars.next() {
+            if c as u32 == quote as u32 {
+                if quote == b'\'' && text[i + 1..].starts_with('\'') {
+                    // `''` is an escaped single quote.
+                    if !copied {
+                        out.push_str(&text[1..i]);
+                        copied = true;
+                    }
+                    out.push('\'');
+                    chars.next();
+                    continue;
+                }
+                if !copied {
+                    return Ok((Token::Str(&text[1..i]), i + 1));
+                }
+                return Ok((Token::String(out), i + 1));
+            }
+
+            if c == '\\' && quote == b'"' {
+                if !copied {
+                    out.push_str(&text[1..i]);
+                    copied = true;
+                }
+                let escaped = match chars.next() {
+                    Some((_, '0')) => '\0',
+                    Some((_, 'a')) => '\u{7}',
+                    Some((_, 'b')) => '\u{8}',
+                    Some((_, 't')) | Some((_, '\t')) => '\t',
+                    Some((_, 'n')) => '\n',
+                    Some((_, 'v')) => '\u{b}',
+                    Some((_, 'f')) => '\u{c}',
+                    Some((_, 'r')) => '\r',
+                    Some((_, 'e')) => '\u{1b}',
+                    Some((_, ' ')) => ' ',
+                    Some((_, '"')) => '"',
+                    Some((_, '/')) => '/',
+                    Some((_, '\\')) => '\\',
+                    Some((_, 'N')) => '\u{85}',
+                    Some((_, '_')) => '\u{a0}',
+                    Some((_, 'L')) => '\u{2028}',
+                    Some((_, 'P')) => '\u{2029}',
+                    Some((j, c @ 'x')) | Some((j, c @ 'u')) | Some((j, c @ 'U')) => {
+                        let digits = match c {
+                            'x' => 2,
+                            'u' => 4,
+                            _ => 8,
+                        };
+                        let escaped = try!(self.hex_escape(text, j + 1, digits, offset + i));
+                        for _ in 0..digits {
+                            chars.next();
+                        }
+                        escaped
+                    }
+                    Some(_) => return Err(self.error(offset + i, "invalid escape")),
+                    None => break,
+                };
+                out.push(escaped);
+                continue;
+            }
+
+            if copied {
+                out.push(c);
+            }
+        }
+
+        Err(self.error(offset, "multi-line quoted scalars are not supported"))
+    }
+
+    fn hex_escape(&self,
+                  text: &str,
+                  start: usize,
+                  digits: usize,
+                  offset: usize)
+                  -> Result<char, ParseError> {
+        let hex = match text.get(start..start + digits) {
+            Some(hex) if hex.chars().all(|c| c.is_digit(16)) => hex,
+            _ => return Err(self.error(offset, "invalid escape")),
+        };
+        u32::from_str_radix(hex, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| self.error(offset, "invalid escape"))
+    }
+
+    /// Reads a literal or folded block scalar whose header is `header`. The content lines follow
+    /// the current line and are indented further than `parent`.
+    fn block_scalar(&mut self,
+                    header: &str,
+                    offset: usize,
+                    parent: usize)
+                    -> Result<Token<'a>, ParseError> {
+        let folded = header.starts_with('>');
+        let indicator = header[1..].chars().next();
+        let chomping = match indicator {
+            Some('-') | Some('+') => indicator,
+            _ => None,
+        };
+        let rest = &header[1 + chomping.map_or(0, |_| 1)..];
+        if rest.starts_with(|c: char| c.is_digit(10)) {
+            return Err(self.error(offset + 1, "indentation indicators are not supported"));
+        }
+        let trimmed = rest.trim_left_matches(|c| c == ' ' || c == '\t');
+        if !trimmed.is_empty() && !(trimmed.starts_with('#') && trimmed.len() < rest.len()) {
+            return Err(self.error(offset + 1, "unexpected characters after a block scalar header"));
+        }
+
+        // The first line with content decides the indentation of the whole scalar.
+        let mut end = self.index;
+        let mut indent = None;
+        while end < self.lines.len() {
+            let line = self.lines[end];
+            let blank = line.text.trim_left_matches(|c| c == ' ' || c == '\t').is_empty();
+            match indent {
+                _ if blank => { }
+                None if line.indent > parent => indent = Some(line.indent),
+                Some(indent) if line.indent >= indent => { }
+                _ => break,
+            }
+            end += 1;
+        }
+
+        let mut lines: Vec<&str> = self.lines[self.index..end]
+            .iter()
+            .map(|line| {
+                match indent {
+                    Some(indent) if line.indent >= indent => {
+                        &self.input[line.offset - (line.indent - indent)..line.offset +
+                                                                          line.text.len()]
+                    }
+                    _ => "",
+                }
+            })
+            .collect();
+        self.index = end;
+
+        let mut trailing = 0;
+        while lines.last().map_or(false, |line| line.trim().is_empty()) {
+            lines.pop();
+            trailing += 1;
+        }
+
+        let mut out = String::new();
+        for (i, line) in lines.iter().enumerate() {
+            if i > 0 {
+                let prev = lines[i - 1];
+                let more_indented = |line: &str| line.starts_with(|c| c == ' ' || c == '\t');
+                if folded && !prev.is_empty() && !more_indented(prev) {
+                    if !line.is_empty() && !more_indented(line) {
+                        out.push(' ');
+                    } else if more_indented(line) {
+                        out.push('\n');
+                    }
+                } else {
+                    out.push('\n');
+                }
+            }
+            out.push_str(line);
+        }
+
+        match chomping {
+            Some('-') => { }
+            Some(_) => {
+                for _ in 0..trailing + if lines.is_empty() { 0 } else { 1 } {
+                    out.push('\n');
+                }
+            }
+            None if !lines.is_empty() => out.push('\n'),
+            None => { }
+        }
+        Ok(Token::String(out))
+    }
+
+    /// Fails if a sequence or mapping `depth` levels deep, starting on the current line, is too
+    /// deep.
+    fn check_depth(&self, depth: usize) -> Result<(), ParseError> {
+        if depth < self.max_depth {
+            return Ok(());
+        }
+        let msg = format!("nesting exceeds the depth limit of {}", self.max_depth);
+        Err(self.error(self.lines[self.index].offset, &msg))
+    }
+
+    fn error(&self, offset: usize, msg: &str) -> ParseError {
+        let (line, column) = line_column(self.input.as_bytes(), offset);
+        ParseError {
+            line: line,
+            column: column,
+            message: msg.to_owned(),
+            snippet: Snippet::new(self.input.as_bytes(), offset),
+            document: None,
+        }
+    }
+}
+
+/// Returns true if `text` has no content besides whitespace and a comment.
+fn is_blank(text: &str) -> bool {
+    let text = text.trim_left_matches(|c| c == ' ' || c == '\t');
+    text.is_empty() || text.starts_with('#')
+}
+
+/// Resolves a plain scalar with the core schema.
+fn resolve(text: &str) -> Token {
+    match text {
+        "" | "~" | "null" | "Null" | "NULL" => return Token::Unit,
+        "true" | "True" | "TRUE" => return Token::Bool(true),
+        "false" | "False" | "FALSE" => return Token::Bool(false),
+        ".inf" | ".Inf" | ".INF" | "+.inf" | "+.Inf" | "+.INF" => {
+            return Token::F64(f64::INFINITY);
+        }
+        "-.inf" | "-.Inf" | "-.INF" => return Token::F64(f64::NEG_INFINITY),
+        ".nan" | ".NaN" | ".NAN" => return Token::F64(f64::NAN),
+        _ => { }
+    }
+
+    let (radix, digits) = if text.starts_with("0x") {
+        (16, &text[2..])
+    } else if text.starts_with("0o") {
+        (8, &text[2..])
+    } else {
+        (10, text)
+    };
+    if radix != 10 {
+        if !digits.is_empty() && digits.chars().all(|c| c.is_digit(radix)) {
+            if let Ok(v) = i64::from_str_radix(digits, radix) {
+                return Token::I64(v);
+            }
+            if let Ok(v) = u64::from_str_radix(digits, radix) {
+                return Token::U64(v);
+            }
+        }
+        return Token::Str(text);
+    }
+
+    let unsigned = text.trim_left_matches(|c| c == '+' || c == '-');
+    if text.len() - unsigned.len() > 1 || unsigned.is_empty() {
+        return Token::Str(text);
+    }
+
+    if unsigned.chars().all(|c| c.is_digit(10)) {
+        if let Ok(v) = text.parse::<i64>() {
+            return Token::I64(v);
+        }
+        if let Ok(v) = text.trim_left_matches('+').parse::<u64>() {
+            return Token::U64(v);
+        }
+    }
+
+    // `[0-9]+(\.[0-9]*)?` or `\.[0-9]+`, followed by an optional exponent.
+    let mantissa_end = unsigned.find(|c| c == 'e' || c == 'E').unwrap_or(unsigned.len());
+    let (mantissa, exponent) = unsigned.split_at(mantissa_end);
+    let mut parts = mantissa.splitn(2, '.');
+    let integer = parts.next().unwrap_or("");
+    let fraction = parts.next();
+    let digits = |s: &str| s.chars().all(|c| c.is_digit(10));
+    let valid_mantissa = digits(integer) && fraction.map_or(true, |f| digits(f)) &&
+                         (!integer.is_empty() || fraction.map_or(false, |f| !f.is_empty()));
+    let valid_exponent = exponent.is_empty() || {
+        let exponent = &exponent[1..];
+        let exponent = if exponent.starts_with(|c| c == '+' || c == '-') {
+            &exponent[1..]
+        } else {
+            exponent
+        };
+        !exponent.is_empty() && digits(exponent)
+    };
+    if valid_mantissa && valid_exponent {
+        if let Ok(v) = text.parse::<f64>() {
+            return Token::F64(v);
+        }
+    }
+
+    Token::Str(text)
+}
@@ -0,0 +1,273 @@
+//! Serialize and deserialize UUIDs.
+//!
+//! `Uuid` serializes as the hyphenated lowercase string by default. `Uuid::format` picks one of
+//! the other `Format`s, like the raw 16 bytes for binary backends. Deserializing accepts a string
+//! in any of the textual forms, in any case, and 16 bytes.
+//!
+//! ```rust
+//! use serde::token::{self, json};
+//! use serde::uuid::{Format, Uuid};
+//!
+//! let uuid: Uuid = "67E55044-10B1-426F-9247-BB680E5FE0C8".parse().unwrap();
+//! assert_eq!(json::serialize(&uuid).unwrap(), r#""67e55044-10b1-426f-9247-bb680e5fe0c8""#);
+//! assert_eq!(json::serialize(&uuid.format(Format::Simple)).unwrap(),
+//!            r#""67e5504410b1426f9247bb680e5fe0c8""#);
+//! assert_eq!(json::deserialize::<Uuid>(r#""urn:uuid:67e55044-10b1-426f-9247-bb680e5fe0c8""#)
+//!                .unwrap(),
+//!            uuid);
+//! ```
+
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+
+use de;
+use ser;
+use token::{self, Token};
+use token::borrowed::{self, DeserializeBorrowed};
+
+const URN_PREFIX: &'static str = "urn:uuid:";
+
+/// The positions of the hyphens in the hyphenated form.
+const HYPHENS: [usize; 4] = [8, 13, 18, 23];
+
+/// A universally unique identifier, kept as its 16 bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Uuid {
+    bytes: [u8; 16],
+}
+
+/// The forms a `Uuid` can be serialized in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// `67e55044-10b1-426f-9247-bb680e5fe0c8`, the default.
+    Hyphenated,
+    /// `67e5504410b1426f9247bb680e5fe0c8`.
+    Simple,
+    /// `urn:uuid:67e55044-10b1-426f-9247-bb680e5fe0c8`.
+    Urn,
+    /// The 16 bytes, serialized with `serialize_bytes`.
+    Bytes,
+}
+
+impl Uuid {
+    /// Construct a `Uuid` from its bytes.
+    pub fn from_bytes(bytes: [u8; 16]) -> Self {
+        Uuid {
+            bytes: bytes,
+        }
+    }
+
+    /// Construct a `Uuid` from a slice, which has to be 16 bytes long.
+    pub fn from_slice(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != 16 {
+            return None;
+        }
+        let mut uuid = Uuid::default();
+        for (dst, &src) in uuid.bytes.iter_mut().zip(bytes) {
+            *dst = src;
+        }
+        Some(uuid)
+    }
+
+    /// Returns the bytes of the `Uuid`.
+    pub fn as_bytes(&self) -> &[u8; 16] {
+        &self.bytes
+    }
+
+    /// Returns a wrapper that serializes and displays the `Uuid` in `format`. `Format::Bytes`
+    /// displays as the hyphenated form.
+    pub fn format(&self, format: Format) -> Formatted {
+        Formatted {
+            uuid: self,
+            format: format,
+        }
+    }
+
+    /// Parses the hyphenated, simple or URN form of a UUID, with the hexadecimal digits in any
+    /// case. The hyphenated form may also be wrapped in braces.
+    pub fn parse_str(text: &str) -> Result<Uuid, ParseError> {
+        let error = |message| {
+            Err(ParseError {
+                text: text.to_owned(),
+                message: message,
+            })
+        };
+
+        let mut digits = text;
+        // The prefix is lowercase, so each byte of the text matches it or its uppercase.
+        let urn = digits.len() >= URN_PREFIX.len() &&
+            digits.bytes().zip(URN_PREFIX.bytes()).all(|(c, p)| {
+                c == p || (b'a' <= p && p <= b'z' && c == p - (b'a' - b'A'))
+            });
+        if urn {
+            digits = &digits[URN_PREFIX.len()..];
+        } else if digits.starts_with('{') && digits.ends_with('}') && digits.len() >= 2 {
+            digits = &digits[1..digits.len() - 1];
+        }
+
+        let hyphenated = match digits.len() {
+            32 => false,
+            36 => true,
+            _ => return error("expected 32 hexadecimal digits, optionally in groups of 8-4-4-4-12"),
+        };
+
+        let mut uuid = Uuid::default();
+        let mut nibbles = 0;
+        for (index, byte) in digits.bytes().enumerate() {
+            if hyphenated && HYPHENS.contains(&index) {
+                if byte != b'-' {
+                    return error("expected a hyphen between the groups of digits");
+                }
+                continue;
+            }
+            let nibble = match (byte as char).to_digit(16) {
+                Some(nibble) => nibble as u8,
+                None => return error("expected a hexadecimal digit"),
+            };
+            uuid.bytes[nibbles / 2] |= if nibbles % 2 == 0 { nibble << 4 } else { nibble };
+            nibbles += 1;
+        }
+        Ok(uuid)
+    }
+}
+
+impl fmt::Display for Uuid {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.format(Format::Hyphenated).fmt(formatter)
+    }
+}
+
+impl FromStr for Uuid {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<Uuid, ParseError> {
+        Uuid::parse_str(text)
+    }
+}
+
+impl ser::Serialize for Uuid {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: ser::Serializer,
+    {
+        self.format(Format::Hyphenated).serialize(serializer)
+    }
+}
+
+impl de::Deserialize for Uuid {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Uuid, D::Error>
+        where D: de::Deserializer,
+    {
+        deserializer.deserialize(UuidVisitor)
+    }
+}
+
+/// Accepts the same `Str`, `String`, `Bytes` and `ByteBuf` tokens as the `Deserialize` impl.
+impl<'a> DeserializeBorrowed<'a> for Uuid {
+    fn deserialize_borrowed<I>(deserializer: &mut borrowed::Deserializer<I>)
+                               -> Result<Self, token::Error>
+        where I: Iterator<Item=Token<'a>>,
+    {
+        let token = try!(deserializer.next_token());
+        if let Some(text) = token.as_str() {
+            return Uuid::parse_str(text).map_err(|err| token::Error::InvalidValue(err.to_string()));
+        }
+        match token.as_bytes() {
+            Some(bytes) => Uuid::from_slice(bytes).ok_or_else(|| invalid_length(bytes.len())),
+            None => Err(token::Error::InvalidValue(format!("expected a UUID, found {:?}", token))),
+        }
+    }
+}
+
+/// Visitor for the textual and byte forms of a `Uuid`.
+struct UuidVisitor;
+
+impl de::Visitor for UuidVisitor {
+    type Value = Uuid;
+
+    fn visit_str<E>(&mut self, v: &str) -> Result<Uuid, E>
+        where E: de::Error,
+    {
+        Uuid::parse_str(v).map_err(|err| E::invalid_value(&err.to_string()))
+    }
+
+    fn visit_bytes<E>(&mut self, v: &[u8]) -> Result<Uuid, E>
+        where E: de::Error,
+    {
+        Uuid::from_slice(v).ok_or_else(|| E::invalid_length(v.len()))
+    }
+
+    fn visit_byte_buf<E>(&mut self, v: Vec<u8>) -> Result<Uuid, E>
+        where E: de::Error,
+    {
+        self.visit_bytes(&v)
+    }
+}
+
+fn invalid_length(len: usize) -> token::Error {
+    token::Error::InvalidValue(format!("expected 16 bytes for a UUID, found {}", len))
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A `Uuid` together with the `Format` to serialize it in, returned by `Uuid::format`.
+#[derive(Clone, Copy, Debug)]
+pub struct Formatted<'a> {
+    uuid: &'a Uuid,
+    format: Format,
+}
+
+impl<'a> fmt::Display for Formatted<'a> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        if self.format == Format::Urn {
+            try!(formatter.write_str(URN_PREFIX));
+        }
+        let hyphens = self.format != Format::Simple;
+        for (index, byte) in self.uuid.bytes.iter().enumerate() {
+            if hyphens && (index == 4 || index == 6 || index == 8 || index == 10) {
+                try!(formatter.write_str("-"));
+            }
+            try!(write!(formatter, "{:02x}", byte));
+        }
+        Ok(())
+    }
+}
+
+impl<'a> ser::Serialize for Formatted<'a> {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: ser::Serializer,
+    {
+        match self.format {
+            Format::Bytes => serializer.serialize_bytes(&self.uuid.bytes),
+            _ => serializer.serialize_str(&self.to_string()),
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// The error returned when a string is not a UUID.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    text: String,
+    message: &'static str,
+}
+
+impl ParseError {
+    /// Returns the text that was being parsed.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "invalid UUID {:?}: {}", self.text, self.message)
+    }
+}
+
+impl error::Error for ParseError {
+    fn description(&self) -> &str {
+        self.message
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+use test::Bencher;
+
+use serde::ser::impls::StrMap;
+use serde::token::{self, Token, binary};
+use serde::token::binary::BinaryConfig;
+use serde::token::borrowed::{DeserializeBorrowed, Deserializer};
+use serde::token::intern::Interned;
+
+//////////////////////////////////////////////////////////////////////////////
+
+#[derive(Serialize)]
+struct Row {
+    id: usize,
+    parent: usize,
+    score: usize,
+}
+
+fn rows(count: usize) -> Vec<Row> {
+    (0 .. count).map(|i| {
+        Row {
+            id: i,
+            parent: i / 2,
+            score: i * 3,
+        }
+    }).collect()
+}
+
+fn maps(count: usize) -> Vec<BTreeMap<String, usize>> {
+    (0 .. count).map(|i| {
+        let mut row = BTreeMap::new();
+        row.insert("id".to_owned(), i);
+        row.insert("parent".to_owned(), i / 2);
+        row.insert("score".to_owned(), i * 3);
+        row
+    }).collect()
+}
+
+#[bench]
+fn bench_record_10000(b: &mut Bencher) {
+    let rows = rows(10_000);
+
+    b.iter(|| {
+        let tokens = token::to_tokens(&rows).unwrap();
+        tokens.iter().cloned().collect::<Vec<Token>>()
+    })
+}
+
+#[bench]
+fn bench_record_interned_10000(b: &mut Bencher) {
+    let rows = rows(10_000);
+
+    b.iter(|| {
+        let interned = Interned::from_tokens(token::to_tokens(&rows).unwrap());
+        interned.iter().collect::<Vec<Token>>()
+    })
+}
+
+#[bench]
+fn bench_binary_10000(b: &mut Bencher) {
+    let tokens = token::to_tokens(&rows(10_000)).unwrap();
+
+    b.iter(|| {
+        binary::to_binary(tokens.iter().cloned())
+    })
+}
+
+#[bench]
+fn bench_binary_string_table_10000(b: &mut Bencher) {
+    let tokens = token::to_tokens(&rows(10_000)).unwrap();
+    let config = BinaryConfig { string_table: true, ..BinaryConfig::default() };
+
+    b.iter(|| {
+        binary::to_binary_with(tokens.iter().cloned(), config)
+    })
+}
+
+#[bench]
+fn bench_str_map_generic_10000(b: &mut Bencher) {
+    let rows = maps(10_000);
+
+    b.iter(|| {
+        rows.iter().map(|row| token::to_tokens(row).unwrap().len()).sum::<usize>()
+    })
+}
+
+#[bench]
+fn bench_str_map_specialized_10000(b: &mut Bencher) {
+    let rows = maps(10_000);
+
+    b.iter(|| {
+        rows.iter().map(|row| token::to_tokens(&StrMap(row)).unwrap().len()).sum::<usize>()
+    })
+}
+
+/// The tokens of `count` records, one after another.
+fn records(count: usize) -> Vec<Token<'static>> {
+    let mut tokens = Vec::new();
+    for i in 0 .. count {
+        let record = vec![format!("record {}", i), "some tag".to_owned(), "another".to_owned()];
+        tokens.extend(token::to_tokens(&record).unwrap());
+    }
+    tokens
+}
+
+#[bench]
+fn bench_deserialize_fresh_10000(b: &mut Bencher) {
+    let tokens = records(10_000);
+
+    b.iter(|| {
+        let mut iter = tokens.iter().cloned();
+        let mut deserializer = Deserializer::new(iter.by_ref());
+        (0 .. 10_000).map(|_| {
+            let record: Vec<String> = DeserializeBorrowed::deserialize_borrowed(&mut deserializer)
+                .unwrap();
+            record.len()
+        }).sum::<usize>()
+    })
+}
+
+#[bench]
+fn bench_deserialize_into_10000(b: &mut Bencher) {
+    let tokens = records(10_000);
+
+    b.iter(|| {
+        let mut iter = tokens.iter().cloned();
+        let mut record: Vec<String> = Vec::new();
+        (0 .. 10_000).map(|_| {
+            token::deserialize_into(&mut record, &mut iter).unwrap();
+            record.len()
+        }).sum::<usize>()
+    })
+}
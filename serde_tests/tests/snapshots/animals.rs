@@ -0,0 +1,16 @@
+vec![
+    Token::SeqStart(Some(2)),
+        Token::SeqSep,
+        Token::EnumUnit("Animal", "Dog"),
+        Token::SeqSep,
+        Token::EnumNewType("Animal", "Cat"),
+        Token::StructStart("Point", Some(2)),
+            Token::StructSep,
+            Token::Str("x"),
+            Token::I32(1),
+            Token::StructSep,
+            Token::Str("y"),
+            Token::I32(-2),
+        Token::StructEnd,
+    Token::SeqEnd,
+]
@@ -0,0 +1,292 @@
+use std::collections::BTreeMap;
+use std::{i64, u32, u64, usize};
+
+extern crate serde;
+use self::serde::token::{self, Token};
+use self::serde::token::binary::{self, BinaryConfig, ByteOrder, Decoder, Encoder, IntEncoding};
+use self::serde::token::binary::LenWidth;
+
+//////////////////////////////////////////////////////////////////////////
+
+fn encode(int_encoding: IntEncoding, tokens: Vec<Token>) -> Vec<u8> {
+    let mut encoder = Encoder::new(Vec::new()).int_encoding(int_encoding);
+    encoder.encode(tokens).unwrap();
+    encoder.into_inner()
+}
+
+fn decode(int_encoding: IntEncoding, bytes: &[u8]) -> Result<Vec<Token>, binary::Error> {
+    Decoder::new(bytes).int_encoding(int_encoding).decode()
+}
+
+#[test]
+fn test_binary_int_encodings() {
+    let cases: Vec<(IntEncoding, Token, Vec<u8>)> = vec![
+        (IntEncoding::Fixed, Token::U16(300), vec![8, 0x2c, 0x01]),
+        (IntEncoding::Fixed, Token::I32(-1), vec![4, 0xff, 0xff, 0xff, 0xff]),
+        (IntEncoding::Varint, Token::U8(127), vec![7, 0x7f]),
+        (IntEncoding::Varint, Token::U8(128), vec![7, 0x80, 0x01]),
+        (IntEncoding::Varint, Token::U16(16383), vec![8, 0xff, 0x7f]),
+        (IntEncoding::Varint, Token::U16(16384), vec![8, 0x80, 0x80, 0x01]),
+        (IntEncoding::Varint, Token::I32(-1), vec![4, 0xff, 0xff, 0xff, 0xff]),
+        (IntEncoding::VarintZigzag, Token::I32(-1), vec![4, 0x01]),
+        (IntEncoding::VarintZigzag, Token::I32(1), vec![4, 0x02]),
+        (IntEncoding::VarintZigzag, Token::I8(-64), vec![2, 0x7f]),
+        (IntEncoding::VarintZigzag, Token::I8(64), vec![2, 0x80, 0x01]),
+        (IntEncoding::VarintZigzag, Token::U8(128), vec![7, 0x80, 0x01]),
+    ];
+
+    for (int_encoding, token, bytes) in cases {
+        assert_eq!(encode(int_encoding, vec![token.clone()]), bytes, "{:?}", token);
+        assert_eq!(decode(int_encoding, &bytes).unwrap(), vec![token]);
+    }
+
+    // Length prefixes follow the integer encoding.
+    assert_eq!(encode(IntEncoding::Fixed, vec![Token::Str("ab")]),
+               vec![14, 2, 0, 0, 0, 0, 0, 0, 0, b'a', b'b']);
+    assert_eq!(encode(IntEncoding::Varint, vec![Token::Str("ab")]), vec![14, 2, b'a', b'b']);
+    assert_eq!(encode(IntEncoding::Varint, vec![Token::SeqStart(Some(200)), Token::MapStart(None)]),
+               vec![24, 1, 0xc8, 0x01, 34, 0]);
+}
+
+#[test]
+fn test_binary_round_trip() {
+    let value = btreemap![
+        "small".to_owned() => vec![0i64, 1, -1],
+        "large".to_owned() => vec![i64::MIN, i64::MAX]
+    ];
+    let mut tokens = token::to_tokens(&value).unwrap();
+    tokens.extend(vec![
+        Token::U64(u64::MAX),
+        Token::U32(0),
+        Token::F32(-0.5),
+        Token::Char('\u{1f980}'),
+        Token::ByteBuf(vec![0, 255]),
+        Token::Timestamp(-1, 999_999_999),
+        Token::Option(true),
+        Token::EnumSeqStart("E", "V", None),
+        Token::EnumSeqSep,
+        Token::Unit,
+        Token::EnumSeqEnd,
+    ]);
+
+    let modes = vec![IntEncoding::Fixed, IntEncoding::Varint, IntEncoding::VarintZigzag];
+    let mut lens = Vec::new();
+    for int_encoding in modes {
+        let bytes = encode(int_encoding, tokens.clone());
+        assert_eq!(decode(int_encoding, &bytes).unwrap(), tokens, "{:?}", int_encoding);
+        lens.push(bytes.len());
+    }
+    assert!(lens[0] > lens[1] && lens[1] > lens[2], "{:?}", lens);
+
+    assert_eq!(binary::from_binary(&binary::to_binary(tokens.clone())).unwrap(), tokens);
+}
+
+#[test]
+fn test_binary_errors() {
+    fn error(int_encoding: IntEncoding, bytes: &[u8]) -> String {
+        decode(int_encoding, bytes).unwrap_err().to_string()
+    }
+
+    assert_eq!(error(IntEncoding::Varint, &[7, 0x80, 0x80, 0x01]),
+               "varint is longer than 2 bytes for u8 at offset 1");
+    assert_eq!(error(IntEncoding::Varint, &[10, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+                                            0xff, 0x81, 0x01]),
+               "varint is longer than 10 bytes for u64 at offset 1");
+    assert_eq!(error(IntEncoding::Varint, &[7, 0xac, 0x02]),
+               "varint is out of range for u8 at offset 1");
+    assert_eq!(error(IntEncoding::Varint, &[10, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+                                            0xff, 0x02]),
+               "varint is out of range for u64 at offset 1");
+    assert_eq!(error(IntEncoding::Varint, &[14, 0x85]), "unexpected end of input at offset 2");
+    assert_eq!(error(IntEncoding::Varint, &[14, 0x05, b'a']),
+               "length exceeds the rest of the input at offset 1");
+    assert_eq!(error(IntEncoding::Fixed, &[8, 0x01]), "unexpected end of input at offset 2");
+    assert_eq!(error(IntEncoding::Fixed, &[0, 2]), "invalid bool at offset 1");
+    assert_eq!(error(IntEncoding::Fixed, &[200]), "unknown tag 200 at offset 0");
+}
+
+#[test]
+fn test_binary_byte_order_and_len_width() {
+    let tokens = vec![
+        Token::StructStart("P", Some(1)),
+        Token::U32(0x01020304),
+        Token::I16(-2),
+        Token::F32(1.0),
+        Token::Str("ab"),
+        Token::StructEnd,
+    ];
+
+    let mut encoder = Encoder::new(Vec::new()).byte_order(ByteOrder::Big).len_width(LenWidth::U32);
+    encoder.encode(tokens.clone()).unwrap();
+    let bytes = encoder.into_inner();
+    assert_eq!(bytes, vec![
+        37, 0, 0, 0, 1, b'P', 1, 0, 0, 0, 1,
+        9, 0x01, 0x02, 0x03, 0x04,
+        3, 0xff, 0xfe,
+        11, 0x3f, 0x80, 0x00, 0x00,
+        14, 0, 0, 0, 2, b'a', b'b',
+        39,
+    ]);
+    let decoded = Decoder::new(&bytes)
+        .byte_order(ByteOrder::Big)
+        .len_width(LenWidth::U32)
+        .decode()
+        .unwrap();
+    assert_eq!(decoded, tokens);
+
+    // The defaults are unchanged.
+    assert_eq!(binary::to_binary(vec![Token::U16(1), Token::Str("")]),
+               vec![8, 1, 0, 14, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+    let configs = vec![
+        (IntEncoding::Fixed, ByteOrder::Little, LenWidth::U16),
+        (IntEncoding::Fixed, ByteOrder::Native, LenWidth::U64),
+        (IntEncoding::Varint, ByteOrder::Big, LenWidth::U16),
+        (IntEncoding::VarintZigzag, ByteOrder::Big, LenWidth::U32),
+    ];
+    for (int_encoding, byte_order, len_width) in configs {
+        let mut encoder = Encoder::new(Vec::new())
+            .int_encoding(int_encoding)
+            .byte_order(byte_order)
+            .len_width(len_width);
+        encoder.encode(tokens.clone()).unwrap();
+        let bytes = encoder.into_inner();
+        let decoded = Decoder::new(&bytes)
+            .int_encoding(int_encoding)
+            .byte_order(byte_order)
+            .len_width(len_width)
+            .decode()
+            .unwrap();
+        assert_eq!(decoded, tokens, "{:?} {:?} {:?}", int_encoding, byte_order, len_width);
+    }
+}
+
+#[test]
+fn test_binary_len_width_overflow() {
+    let long = "x".repeat(70_000);
+
+    let mut encoder = Encoder::new(Vec::new()).len_width(LenWidth::U16);
+    let err = encoder.write_token(Token::Str(&long)).unwrap_err();
+    assert_eq!(err.to_string(), "length 70000 does not fit into a u16 prefix");
+
+    let mut encoder = Encoder::new(Vec::new()).len_width(LenWidth::U16);
+    encoder.write_token(Token::Str(&long[..65_535])).unwrap();
+    assert_eq!(encoder.into_inner().len(), 1 + 2 + 65_535);
+
+    let mut encoder = Encoder::new(Vec::new()).len_width(LenWidth::U32);
+    encoder.write_token(Token::Str(&long)).unwrap();
+    let bytes = encoder.into_inner();
+    assert_eq!(&bytes[..5], &[14, 0x70, 0x11, 0x01, 0x00]);
+
+    // Read with the default u64 prefixes, the length takes in four bytes of the string and is
+    // far longer than the input.
+    let err = Decoder::new(&bytes).decode().unwrap_err();
+    assert_eq!(err.to_string(), "length exceeds the rest of the input at offset 1");
+}
+
+#[test]
+fn test_binary_config_varints() {
+    let fixed = BinaryConfig::default();
+    let varints = BinaryConfig { varints: true, ..BinaryConfig::default() };
+
+    // Tag byte plus payload: small values shrink, values near the top of their range grow.
+    let cases = vec![
+        (Token::U8(1), 2, 2),
+        (Token::U16(1), 3, 2),
+        (Token::U32(1), 5, 2),
+        (Token::U64(1), 9, 2),
+        (Token::U64(300), 9, 3),
+        (Token::I64(-1), 9, 2),
+        (Token::I32(-64), 5, 2),
+        (Token::I32(64), 5, 3),
+        (Token::U8(255), 2, 3),
+        (Token::U32(u32::MAX), 5, 6),
+        (Token::U64(u64::MAX), 9, 11),
+        (Token::I64(i64::MIN), 9, 11),
+    ];
+    for (token, fixed_len, varint_len) in cases {
+        let tokens = vec![token];
+        let bytes = binary::to_binary_with(tokens.clone(), fixed);
+        assert_eq!(bytes.len(), fixed_len, "{:?}", tokens);
+        assert_eq!(binary::from_binary_with(&bytes, fixed).unwrap(), tokens);
+
+        let bytes = binary::to_binary_with(tokens.clone(), varints);
+        assert_eq!(bytes.len(), varint_len, "{:?}", tokens);
+        assert_eq!(binary::from_binary_with(&bytes, varints).unwrap(), tokens);
+    }
+
+    // The default config is the format of `to_binary`, and varints match `VarintZigzag`.
+    let tokens = token::to_tokens(&vec![(1u32, -2i64, "a")]).unwrap();
+    assert_eq!(binary::to_binary_with(tokens.clone(), fixed), binary::to_binary(tokens.clone()));
+    assert_eq!(binary::to_binary_with(tokens.clone(), varints),
+               encode(IntEncoding::VarintZigzag, tokens.clone()));
+    assert!(binary::from_binary_with(&binary::to_binary(vec![Token::U64(1)]), varints).is_err());
+}
+
+#[test]
+fn test_binary_string_table() {
+    let config = BinaryConfig { string_table: true, ..BinaryConfig::default() };
+
+    let mut rows = Vec::new();
+    for id in 0..100u32 {
+        rows.push(btreemap!["id".to_owned() => id, "parent".to_owned() => id / 2]);
+    }
+    let tokens = token::to_tokens(&rows).unwrap();
+    let bytes = binary::to_binary_with(tokens.clone(), config);
+    assert_eq!(binary::from_binary_with(&bytes, config).unwrap(), tokens);
+    assert!(bytes.len() < binary::to_binary(tokens.clone()).len());
+    assert!(binary::from_binary(&bytes).is_err());
+
+    // Struct field names go through the table, other strings and keys that aren't strings don't.
+    let tokens = vec![
+        Token::SeqStart(Some(2)),
+            Token::SeqSep,
+            Token::StructStart("Row", Some(1)),
+                Token::StructSep,
+                Token::Str("name"),
+                Token::Str("name"),
+            Token::StructEnd,
+
+            Token::SeqSep,
+            Token::MapStart(Some(2)),
+                Token::MapSep,
+                Token::String("name".to_owned()),
+                Token::I32(1),
+
+                Token::MapSep,
+                Token::I32(2),
+                Token::Str("name"),
+            Token::MapEnd,
+        Token::SeqEnd,
+    ];
+    let mut encoder = Encoder::new(Vec::new()).string_table(true);
+    encoder.encode(tokens.clone()).unwrap();
+    assert_eq!(encoder.strings().unwrap().strings(), &["name".to_owned()]);
+    let bytes = encoder.into_inner();
+    assert_eq!(Decoder::new(&bytes).string_table(true).decode().unwrap(), tokens);
+    assert_eq!(bytes.iter().filter(|&&byte| byte == b'n').count(), 3);
+
+    let varints = BinaryConfig { varints: true, string_table: true };
+    let bytes = binary::to_binary_with(tokens.clone(), varints);
+    assert_eq!(binary::from_binary_with(&bytes, varints).unwrap(), tokens);
+
+    // An index past the keys read so far.
+    let err = Decoder::new(&[49, 0, 0, 0, 0, 0, 0, 0, 0]).string_table(true).decode().unwrap_err();
+    assert_eq!(err.to_string(), "unknown string table index at offset 1");
+}
+
+#[test]
+#[cfg(target_pointer_width = "64")]
+fn test_binary_normalize_width() {
+    assert_eq!(token::normalize_width(Token::Isize(5)), Token::I64(5));
+    assert_eq!(token::normalize_width(Token::Usize(5)), Token::U64(5));
+    assert_eq!(token::normalize_width(Token::I8(5)), Token::I8(5));
+
+    // Pointer sized integers are written like the fixed-width integers of the same width, and a
+    // reader on any target gets those back.
+    let tokens = vec![Token::Isize(-5), Token::Usize(usize::MAX)];
+    let bytes = binary::to_binary(tokens);
+    assert_eq!(bytes, binary::to_binary(vec![Token::I64(-5), Token::U64(u64::MAX)]));
+    assert_eq!(binary::from_binary(&bytes).unwrap(),
+               vec![Token::I64(-5), Token::U64(u64::MAX)]);
+}
@@ -0,0 +1,307 @@
+use std::collections::BTreeMap;
+use std::{f32, f64, u64};
+
+extern crate serde;
+use self::serde::token::{self, Token, Value};
+use self::serde::token::cbor::Decoder;
+use self::serde::token::testing::arbitrary;
+
+//////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_cbor_array() {
+    let value = vec![1, 2, 3];
+
+    assert_eq!(
+        token::to_cbor(token::to_tokens(&value).unwrap()),
+        vec![0x83, 0x01, 0x02, 0x03]
+    );
+}
+
+#[test]
+fn test_cbor_map() {
+    let value = btreemap![
+        "a" => vec![1],
+        "b" => vec![2, 3]
+    ];
+
+    assert_eq!(
+        token::to_cbor(token::to_tokens(&value).unwrap()),
+        vec![
+            0xa2,
+                0x61, b'a', 0x81, 0x01,
+                0x61, b'b', 0x82, 0x02, 0x03,
+        ]
+    );
+}
+
+#[test]
+fn test_cbor_scalars() {
+    let cases: Vec<(Token, Vec<u8>)> = vec![
+        (Token::U8(0), vec![0x00]),
+        (Token::U32(23), vec![0x17]),
+        (Token::U32(24), vec![0x18, 0x18]),
+        (Token::I32(1000), vec![0x19, 0x03, 0xe8]),
+        (Token::U64(1000000), vec![0x1a, 0x00, 0x0f, 0x42, 0x40]),
+        (Token::U64(1000000000000), vec![0x1b, 0x00, 0x00, 0x00, 0xe8, 0xd4, 0xa5, 0x10, 0x00]),
+        (Token::I8(-1), vec![0x20]),
+        (Token::I16(-500), vec![0x39, 0x01, 0xf3]),
+        (Token::F64(1.1), vec![0xfb, 0x3f, 0xf1, 0x99, 0x99, 0x99, 0x99, 0x99, 0x9a]),
+        (Token::F32(100000.0), vec![0xfa, 0x47, 0xc3, 0x50, 0x00]),
+        (Token::Bool(false), vec![0xf4]),
+        (Token::Bool(true), vec![0xf5]),
+        (Token::Option(false), vec![0xf6]),
+        (Token::Unit, vec![0xf6]),
+        (Token::Str("IETF"), vec![0x64, 0x49, 0x45, 0x54, 0x46]),
+        (Token::Char('ü'), vec![0x62, 0xc3, 0xbc]),
+        (Token::Bytes(&[1, 2, 3, 4]), vec![0x44, 0x01, 0x02, 0x03, 0x04]),
+    ];
+
+    for (token, bytes) in cases {
+        assert_eq!(token::to_cbor(vec![token.clone()]), bytes, "{:?}", token);
+    }
+}
+
+#[test]
+fn test_cbor_some() {
+    assert_eq!(token::to_cbor(token::to_tokens(&Some(5u8)).unwrap()), vec![0x05]);
+}
+
+#[test]
+fn test_cbor_indefinite_length() {
+    let tokens = vec![
+        Token::SeqStart(None),
+            Token::SeqSep,
+            Token::I32(1),
+
+            Token::SeqSep,
+            Token::MapStart(None),
+                Token::MapSep,
+                Token::Str("a"),
+                Token::I32(2),
+            Token::MapEnd,
+        Token::SeqEnd,
+    ];
+
+    assert_eq!(
+        token::to_cbor(tokens),
+        vec![0x9f, 0x01, 0xbf, 0x61, b'a', 0x02, 0xff, 0xff]
+    );
+}
+
+#[test]
+fn test_cbor_struct_and_enum() {
+    let tokens = vec![
+        Token::StructStart("Point", Some(2)),
+            Token::StructSep,
+            Token::Str("x"),
+            Token::I32(1),
+
+            Token::StructSep,
+            Token::Str("y"),
+            Token::EnumSeqStart("Shape", "Pair", Some(2)),
+                Token::EnumSeqSep,
+                Token::I32(2),
+
+                Token::EnumSeqSep,
+                Token::EnumUnit("Color", "Red"),
+            Token::EnumSeqEnd,
+        Token::StructEnd,
+    ];
+
+    assert_eq!(
+        token::to_cbor(tokens),
+        vec![
+            0xa2,
+                0x61, b'x', 0x01,
+                0x61, b'y', 0xa1,
+                    0x64, b'P', b'a', b'i', b'r',
+                    0x82, 0x02, 0x63, b'R', b'e', b'd',
+        ]
+    );
+}
+
+//////////////////////////////////////////////////////////////////////////
+
+fn hex(s: &str) -> Vec<u8> {
+    (0..s.len() / 2).map(|i| u8::from_str_radix(&s[2 * i..2 * i + 2], 16).unwrap()).collect()
+}
+
+#[test]
+fn test_cbor_decode_rfc_scalars() {
+    let cases: Vec<(&str, Token)> = vec![
+        ("00", Token::I64(0)),
+        ("17", Token::I64(23)),
+        ("1818", Token::I64(24)),
+        ("1903e8", Token::I64(1000)),
+        ("1b000000e8d4a51000", Token::I64(1000000000000)),
+        ("1bffffffffffffffff", Token::U64(u64::MAX)),
+        ("c249010000000000000000", Token::Str("18446744073709551616")),
+        ("3bffffffffffffffff", Token::Str("-18446744073709551616")),
+        ("c349010000000000000000", Token::Str("-18446744073709551617")),
+        ("20", Token::I64(-1)),
+        ("3903e7", Token::I64(-1000)),
+        ("f90000", Token::F32(0.0)),
+        ("f93c00", Token::F32(1.0)),
+        ("f93e00", Token::F32(1.5)),
+        ("f97bff", Token::F32(65504.0)),
+        ("f90001", Token::F32(5.960464477539063e-8)),
+        ("f90400", Token::F32(0.00006103515625)),
+        ("f9c400", Token::F32(-4.0)),
+        ("f97c00", Token::F32(f32::INFINITY)),
+        ("f9fc00", Token::F32(f32::NEG_INFINITY)),
+        ("fa47c35000", Token::F32(100000.0)),
+        ("fa7f7fffff", Token::F32(f32::MAX)),
+        ("fb3ff199999999999a", Token::F64(1.1)),
+        ("fb7e37e43c8800759c", Token::F64(1.0e+300)),
+        ("fbc010666666666666", Token::F64(-4.1)),
+        ("f4", Token::Bool(false)),
+        ("f5", Token::Bool(true)),
+        ("f6", Token::Unit),
+        ("f7", Token::Unit),
+        ("c074323031332d30332d32315432303a30343a30305a", Token::Timestamp(1363896240, 0)),
+        ("c11a514b67b0", Token::Timestamp(1363896240, 0)),
+        ("c1fb41d452d9ec200000", Token::Timestamp(1363896240, 500000000)),
+        ("40", Token::Bytes(b"")),
+        ("4401020304", Token::Bytes(b"\x01\x02\x03\x04")),
+        ("60", Token::Str("")),
+        ("6449455446", Token::Str("IETF")),
+        ("62225c", Token::Str("\"\\")),
+        ("62c3bc", Token::Str("\u{fc}")),
+        ("64f0908591", Token::Str("\u{10151}")),
+        ("5f42010243030405ff", Token::ByteBuf(vec![1, 2, 3, 4, 5])),
+        ("7f657374726561646d696e67ff", Token::String("streaming".to_owned())),
+    ];
+
+    for (input, expected) in cases {
+        assert_eq!(token::from_cbor(&hex(input)).unwrap(), vec![expected], "{}", input);
+    }
+
+    match &token::from_cbor(&hex("f97e00")).unwrap()[..] {
+        [Token::F32(v)] => assert!(v.is_nan()),
+        tokens => panic!("{:?}", tokens),
+    }
+}
+
+#[test]
+fn test_cbor_decode_rfc_containers() {
+    assert_eq!(token::from_cbor(&hex("80")).unwrap(),
+               vec![Token::SeqStart(Some(0)), Token::SeqEnd]);
+    assert_eq!(token::from_cbor(&hex("9fff")).unwrap(),
+               vec![Token::SeqStart(None), Token::SeqEnd]);
+
+    // [_ 1, [2, 3], [_ 4, 5]]
+    assert_eq!(
+        token::from_cbor(&hex("9f018202039f0405ffff")).unwrap(),
+        vec![
+            Token::SeqStart(None),
+                Token::SeqSep,
+                Token::I64(1),
+
+                Token::SeqSep,
+                Token::SeqStart(Some(2)),
+                    Token::SeqSep,
+                    Token::I64(2),
+                    Token::SeqSep,
+                    Token::I64(3),
+                Token::SeqEnd,
+
+                Token::SeqSep,
+                Token::SeqStart(None),
+                    Token::SeqSep,
+                    Token::I64(4),
+                    Token::SeqSep,
+                    Token::I64(5),
+                Token::SeqEnd,
+            Token::SeqEnd,
+        ]);
+
+    // {_ "Fun": true, "Amt": -2}
+    assert_eq!(
+        token::from_cbor(&hex("bf6346756ef563416d7421ff")).unwrap(),
+        vec![
+            Token::MapStart(None),
+                Token::MapSep,
+                Token::Str("Fun"),
+                Token::Bool(true),
+
+                Token::MapSep,
+                Token::Str("Amt"),
+                Token::I64(-2),
+            Token::MapEnd,
+        ]);
+
+    // {1: 2, 3: 4}
+    assert_eq!(
+        token::from_cbor(&hex("a201020304")).unwrap(),
+        vec![
+            Token::MapStart(Some(2)),
+                Token::MapSep,
+                Token::I64(1),
+                Token::I64(2),
+
+                Token::MapSep,
+                Token::I64(3),
+                Token::I64(4),
+            Token::MapEnd,
+        ]);
+}
+
+#[test]
+fn test_cbor_decode_unknown_tags() {
+    assert_eq!(token::from_cbor(&hex("d74401020304")).unwrap_err().to_string(),
+               "unknown tag 23 at offset 0");
+
+    let lenient = |input: &str| Decoder::new(&hex(input)).lenient(true).decode().unwrap()
+        .into_iter().map(|token| format!("{:?}", token)).collect::<Vec<_>>();
+    assert_eq!(lenient("d74401020304"), vec!["Bytes([1, 2, 3, 4])"]);
+    assert_eq!(lenient("d82076687474703a2f2f7777772e6578616d706c652e636f6d"),
+               vec!["Str(\"http://www.example.com\")"]);
+
+    // The self-describe tag is always skipped.
+    assert_eq!(token::from_cbor(&hex("d9d9f701")).unwrap(), vec![Token::I64(1)]);
+}
+
+#[test]
+fn test_cbor_decode_errors() {
+    fn error(input: &str) -> String {
+        token::from_cbor(&hex(input)).unwrap_err().to_string()
+    }
+
+    assert_eq!(error(""), "unexpected end of input at offset 0");
+    assert_eq!(error("1903"), "unexpected end of input at offset 2");
+    assert_eq!(error("9f01"), "unexpected end of input at offset 2");
+    assert_eq!(error("ff"), "unexpected break at offset 0");
+    assert_eq!(error("8201ff"), "unexpected break at offset 2");
+    assert_eq!(error("bf6161ff"), "unexpected break at offset 3");
+    assert_eq!(error("5f41016161ff"), "invalid chunk in indefinite-length string at offset 3");
+    assert_eq!(error("1c"), "reserved additional information at offset 0");
+    assert_eq!(error("0000"), "trailing bytes at offset 1");
+    assert_eq!(error("62c328"), "invalid UTF-8 in text string at offset 0");
+    assert_eq!(error("c06161"), "invalid date-time string at offset 0");
+    assert_eq!(error("c201"), "bignum must be a byte string at offset 0");
+
+    let nested = Decoder::new(&hex("81818100")).max_depth(2).decode().unwrap_err();
+    assert_eq!(nested.to_string(), "nesting exceeds the depth limit of 2 at offset 2");
+    assert!(Decoder::new(&hex("81818100")).max_depth(3).decode().is_ok());
+}
+
+#[test]
+fn test_cbor_round_trip() {
+    arbitrary::check(300, 0, |rng| {
+        let value = arbitrary::value(rng, 4, 5);
+        let encoded = token::to_cbor(arbitrary::tokens(&value));
+        let decoded = try!(token::from_cbor(&encoded).map_err(|err| err.to_string()));
+        let back = try!(Value::from_tokens(decoded).map_err(|err| err.to_string()));
+        if back == value {
+            Ok(())
+        } else {
+            Err(format!("{:?} came back as {:?}", value, back))
+        }
+    });
+
+    for &(secs, nanos) in &[(0, 0), (-1, 0), (1363896240, 500000000), (-86401, 250000000)] {
+        let encoded = token::to_cbor(vec![Token::Timestamp(secs, nanos)]);
+        assert_eq!(token::from_cbor(&encoded).unwrap(), vec![Token::Timestamp(secs, nanos)]);
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, LinkedList, VecDeque};
 use std::net;
 use std::path::PathBuf;
 
@@ -60,7 +60,7 @@ macro_rules! declare_tests {
 }
 
 macro_rules! declare_error_tests {
-    ($($name:ident<$target:ident> { $tokens:expr, $expected:expr, })+) => {
+    ($($name:ident<$target:ty> { $tokens:expr, $expected:expr, })+) => {
         $(
             #[test]
             fn $name() {
@@ -88,8 +88,6 @@ declare_tests! {
         0isize => vec![Token::U16(0)],
         0isize => vec![Token::U32(0)],
         0isize => vec![Token::U64(0)],
-        0isize => vec![Token::F32(0.)],
-        0isize => vec![Token::F64(0.)],
     }
     test_ints {
         0isize => vec![Token::Isize(0)],
@@ -256,6 +254,18 @@ declare_tests! {
             Token::TupleStructStart("Anything", Some(0)),
             Token::SeqEnd,
         ],
+        btreeset![1, 2] => vec![
+            Token::SeqStart(Some(3)),
+                Token::SeqSep,
+                Token::I32(2),
+
+                Token::SeqSep,
+                Token::I32(1),
+
+                Token::SeqSep,
+                Token::I32(2),
+            Token::SeqEnd,
+        ],
     }
     test_hashset {
         HashSet::<isize>::new() => vec![
@@ -284,6 +294,60 @@ declare_tests! {
             Token::TupleStructStart("Anything", Some(0)),
             Token::SeqEnd,
         ],
+        hashset![1, 2] => vec![
+            Token::SeqStart(Some(3)),
+                Token::SeqSep,
+                Token::I32(1),
+
+                Token::SeqSep,
+                Token::I32(2),
+
+                Token::SeqSep,
+                Token::I32(1),
+            Token::SeqEnd,
+        ],
+    }
+    test_vecdeque {
+        VecDeque::<isize>::new() => vec![
+            Token::Unit,
+        ],
+        VecDeque::<isize>::new() => vec![
+            Token::SeqStart(Some(0)),
+            Token::SeqEnd,
+        ],
+        vec![1, 2, 1].into_iter().collect::<VecDeque<_>>() => vec![
+            Token::SeqStart(Some(3)),
+                Token::SeqSep,
+                Token::I32(1),
+
+                Token::SeqSep,
+                Token::I32(2),
+
+                Token::SeqSep,
+                Token::I32(1),
+            Token::SeqEnd,
+        ],
+    }
+    test_linkedlist {
+        LinkedList::<isize>::new() => vec![
+            Token::Unit,
+        ],
+        LinkedList::<isize>::new() => vec![
+            Token::SeqStart(Some(0)),
+            Token::SeqEnd,
+        ],
+        vec![1, 2, 1].into_iter().collect::<LinkedList<_>>() => vec![
+            Token::SeqStart(None),
+                Token::SeqSep,
+                Token::I32(1),
+
+                Token::SeqSep,
+                Token::I32(2),
+
+                Token::SeqSep,
+                Token::I32(1),
+            Token::SeqEnd,
+        ],
     }
     test_vec {
         Vec::<isize>::new() => vec![
@@ -386,6 +450,21 @@ declare_tests! {
             Token::TupleStructStart("Anything", Some(0)),
             Token::SeqEnd,
         ],
+        [1u8, 2, 3, 4] => vec![
+            Token::SeqStart(None),
+                Token::SeqSep,
+                Token::U8(1),
+
+                Token::SeqSep,
+                Token::U8(2),
+
+                Token::SeqSep,
+                Token::U8(3),
+
+                Token::SeqSep,
+                Token::U8(4),
+            Token::SeqEnd,
+        ],
     }
     test_tuple {
         (1,) => vec![
@@ -424,6 +503,24 @@ declare_tests! {
                 Token::I32(3),
             Token::TupleEnd,
         ],
+        ((1, "a".to_owned()), (2u8,)) => vec![
+            Token::TupleStart(2),
+                Token::TupleSep,
+                Token::TupleStart(2),
+                    Token::TupleSep,
+                    Token::I32(1),
+
+                    Token::TupleSep,
+                    Token::Str("a"),
+                Token::TupleEnd,
+
+                Token::TupleSep,
+                Token::TupleStart(1),
+                    Token::TupleSep,
+                    Token::U8(2),
+                Token::TupleEnd,
+            Token::TupleEnd,
+        ],
     }
     test_btreemap {
         BTreeMap::<isize, isize>::new() => vec![
@@ -705,6 +802,15 @@ fn test_net_ipaddr() {
 }
 
 declare_error_tests! {
+    test_isize_from_float<isize> {
+        vec![Token::F64(0.)],
+        Error::InvalidValue(
+            "0.0 is a float, which only becomes isize with lenient coercion".to_owned()),
+    }
+    test_u8_out_of_range<u8> {
+        vec![Token::I32(-1)],
+        Error::InvalidValue("-1 is out of range for u8".to_owned()),
+    }
     test_unknown_variant<Enum> {
         vec![
             Token::EnumUnit("Enum", "Foo"),
@@ -750,4 +856,33 @@ declare_error_tests! {
         ],
         Error::DuplicateFieldError("a"),
     }
+    test_tuple_too_short<(i32, i32, i32)> {
+        vec![
+            Token::TupleStart(2),
+                Token::TupleSep, Token::I32(1),
+                Token::TupleSep, Token::I32(2),
+            Token::TupleEnd,
+        ],
+        Error::InvalidLength(3, 2),
+    }
+    test_tuple_too_long<(i32, i32)> {
+        vec![
+            Token::TupleStart(3),
+                Token::TupleSep, Token::I32(1),
+                Token::TupleSep, Token::I32(2),
+                Token::TupleSep, Token::I32(3),
+            Token::TupleEnd,
+        ],
+        Error::InvalidLength(2, 3),
+    }
+    test_array_too_short<[u8; 4]> {
+        vec![
+            Token::SeqStart(Some(3)),
+                Token::SeqSep, Token::U8(1),
+                Token::SeqSep, Token::U8(2),
+                Token::SeqSep, Token::U8(3),
+            Token::SeqEnd,
+        ],
+        Error::InvalidLength(4, 3),
+    }
 }
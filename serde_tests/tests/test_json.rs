@@ -0,0 +1,1080 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::{f64, i64, u64};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+extern crate serde;
+use self::serde::token::{self, TimestampFormat, Token, Value};
+use self::serde::token::testing;
+use self::serde::token::json::{self, BigInts, ParserConfig};
+use self::serde::ser::Serialize;
+use self::serde::ser::impls::LazySeq;
+
+//////////////////////////////////////////////////////////////////////////
+
+fn to_json<'a>(tokens: Vec<Token<'a>>) -> String {
+    token::to_json(tokens).unwrap()
+}
+
+#[test]
+fn test_json_scalars() {
+    assert_eq!(to_json(vec![Token::Bool(true)]), "true");
+    assert_eq!(to_json(vec![Token::I8(-5)]), "-5");
+    assert_eq!(to_json(vec![Token::U64(18446744073709551615)]), "18446744073709551615");
+    assert_eq!(to_json(vec![Token::F64(1.0)]), "1.0");
+    assert_eq!(to_json(vec![Token::F64(0.5)]), "0.5");
+    assert_eq!(to_json(vec![Token::F64(f64::NAN)]), "null");
+    assert_eq!(to_json(vec![Token::Char('x')]), "\"x\"");
+    assert_eq!(to_json(vec![Token::Unit]), "null");
+    assert_eq!(to_json(vec![Token::Option(false)]), "null");
+    assert_eq!(to_json(vec![Token::Option(true), Token::I32(1)]), "1");
+    assert_eq!(to_json(vec![Token::Bytes(b"\x01\x02")]), "\"AQI=\"");
+}
+
+#[test]
+fn test_json_escapes() {
+    assert_eq!(
+        to_json(vec![Token::Str("a\"b\\c\nd\u{1}é")]),
+        "\"a\\\"b\\\\c\\nd\\u0001é\""
+    );
+}
+
+#[test]
+fn test_json_compound() {
+    let value = btreemap![
+        "a" => vec![1, 2],
+        "b" => vec![]
+    ];
+
+    assert_eq!(
+        to_json(token::to_tokens(&value).unwrap()),
+        "{\"a\":[1,2],\"b\":[]}"
+    );
+
+    assert_eq!(
+        to_json(token::to_tokens(&btreemap![1 => (true, 'c')]).unwrap()),
+        "{\"1\":[true,\"c\"]}"
+    );
+}
+
+#[test]
+fn test_json_struct_and_enums() {
+    let tokens = vec![
+        Token::StructStart("Struct", Some(4)),
+            Token::StructSep,
+            Token::Str("unit"),
+            Token::EnumUnit("Enum", "Unit"),
+
+            Token::StructSep,
+            Token::Str("one"),
+            Token::EnumNewType("Enum", "One"),
+            Token::EnumNewType("Enum", "Two"),
+            Token::I32(1),
+
+            Token::StructSep,
+            Token::Str("seq"),
+            Token::EnumSeqStart("Enum", "Seq", Some(2)),
+                Token::EnumSeqSep,
+                Token::I32(1),
+
+                Token::EnumSeqSep,
+                Token::I32(2),
+            Token::EnumSeqEnd,
+
+            Token::StructSep,
+            Token::Str("map"),
+            Token::EnumMapStart("Enum", "Map", Some(1)),
+                Token::EnumMapSep,
+                Token::Str("a"),
+                Token::I32(1),
+            Token::EnumMapEnd,
+        Token::StructEnd,
+    ];
+
+    assert_eq!(
+        to_json(tokens),
+        "{\"unit\":{\"Unit\":[]},\"one\":{\"One\":{\"Two\":1}},\
+         \"seq\":{\"Seq\":[1,2]},\"map\":{\"Map\":{\"a\":1}}}"
+    );
+}
+
+#[test]
+fn test_json_errors() {
+    let tokens = vec![
+        Token::MapStart(Some(1)),
+            Token::MapSep,
+            Token::SeqStart(Some(0)),
+    ];
+    match token::to_json(tokens) {
+        Err(json::Error::KeyMustBeAString) => { }
+        result => panic!("unexpected result: {:?}", result),
+    }
+
+    match token::to_json(vec![Token::SeqEnd]) {
+        Err(json::Error::UnexpectedToken(_)) => { }
+        result => panic!("unexpected result: {:?}", result),
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_json_from_str() {
+    assert_eq!(json::from_str("null").unwrap(), vec![Token::Unit]);
+    assert_eq!(json::from_str(" true ").unwrap(), vec![Token::Bool(true)]);
+    assert_eq!(json::from_str("-5").unwrap(), vec![Token::I64(-5)]);
+    assert_eq!(json::from_str("18446744073709551615").unwrap(),
+               vec![Token::U64(18446744073709551615)]);
+    assert_eq!(json::from_str("1.5e3").unwrap(), vec![Token::F64(1500.0)]);
+    assert_eq!(json::from_str("\"a\\n\\u00e9\\ud83d\\ude00\"").unwrap(),
+               vec![Token::Str("a\né😀")]);
+
+    assert_eq!(
+        json::from_str("{\"a\": [1, {}], \"b\": []}").unwrap(),
+        vec![
+            Token::MapStart(None),
+                Token::MapSep,
+                Token::Str("a"),
+                Token::SeqStart(None),
+                    Token::SeqSep,
+                    Token::I64(1),
+
+                    Token::SeqSep,
+                    Token::MapStart(None),
+                    Token::MapEnd,
+                Token::SeqEnd,
+
+                Token::MapSep,
+                Token::Str("b"),
+                Token::SeqStart(None),
+                Token::SeqEnd,
+            Token::MapEnd,
+        ]);
+}
+
+#[test]
+fn test_json_from_str_errors() {
+    fn error(input: &str) -> String {
+        json::from_str(input).unwrap_err().to_string()
+    }
+
+    assert_eq!(error(""), "EOF while parsing a value at line 1 column 1");
+    assert_eq!(error("[1,\n 2"), "EOF while parsing a list at line 2 column 3");
+    assert_eq!(error("[1 2]"), "expected `,` or `]` at line 1 column 4");
+    assert_eq!(error("{1: 2}"), "key must be a string at line 1 column 2");
+    assert_eq!(error("tru"), "expected value at line 1 column 1");
+    assert_eq!(error("01"), "trailing characters at line 1 column 2");
+    assert_eq!(error("\"\\ud800\""), "lone leading surrogate in hex escape at line 1 column 8");
+}
+
+#[test]
+fn test_json_error_snippets() {
+    fn error(input: &str) -> String {
+        format!("{:#}", json::from_str(input).unwrap_err())
+    }
+
+    // Mid-line, on a later line of the input.
+    assert_eq!(error("{\n  \"port\": 80,\n  \"host\": tru\n}"),
+               concat!("expected value at line 3 column 11\n",
+                       "  |\n",
+                       "3 |   \"host\": tru\n",
+                       "  |           ^"));
+
+    // At the end of the input, after the last character of a line and on an empty last line.
+    assert_eq!(error("[1, 2"),
+               "EOF while parsing a list at line 1 column 6\n  |\n1 | [1, 2\n  |      ^");
+    assert_eq!(error("[1, 2,\n"),
+               "EOF while parsing a value at line 2 column 1\n  |\n2 |\n  | ^");
+
+    // Long lines are cut down to the part around the caret.
+    let long = format!("[{}, x]", vec!["1"; 60].join(", "));
+    let err = json::from_str(&long).unwrap_err();
+    match err {
+        json::Error::Syntax(_, 1, 182, ref snippet) => {
+            assert!(snippet.is_truncated());
+            assert_eq!(snippet.text().len(), 83);
+            assert!(snippet.text().starts_with("...1, 1"));
+            assert!(snippet.text().ends_with("1, x]"));
+        }
+        ref err => panic!("unexpected error {:?}", err),
+    }
+    assert!(format!("{:#}", err).ends_with(concat!(
+        "^\n",
+        "  = note: the line is longer than 80 characters and was shortened")));
+
+    // Without the alternate flag only the message is shown.
+    assert_eq!(json::from_str("[1, 2").unwrap_err().to_string(),
+               "EOF while parsing a list at line 1 column 6");
+}
+
+#[test]
+fn test_json_chunks() {
+    let names = vec!["ada", "bob", "cy", "dee", "eve"];
+    let tokens = token::to_tokens(&names).unwrap();
+
+    // `["ada","bob","cy"]` is 18 bytes.
+    let chunks = json::to_json_chunks(tokens.clone(), 18).unwrap();
+    assert_eq!(chunks, vec![r#"["ada","bob","cy"]"#, r#"["dee","eve"]"#]);
+
+    let chunks = json::to_json_chunks(tokens.clone(), 13).unwrap();
+    assert_eq!(chunks, vec![r#"["ada","bob"]"#, r#"["cy","dee"]"#, r#"["eve"]"#]);
+    assert!(chunks.iter().all(|chunk| chunk.len() <= 13));
+
+    // Every chunk reads back, and together they hold the whole sequence.
+    let mut back = Vec::new();
+    for chunk in &chunks {
+        back.extend(json::deserialize::<Vec<String>>(chunk).unwrap());
+    }
+    assert_eq!(back, names);
+
+    let everything = json::to_json_chunks(tokens, 1000).unwrap();
+    assert_eq!(everything, vec![r#"["ada","bob","cy","dee","eve"]"#]);
+}
+
+#[test]
+fn test_json_chunks_oversized() {
+    let value = vec![
+        btreemap!["a" => vec![1]],
+        btreemap!["b" => vec![1, 2, 3, 4, 5, 6]],
+        btreemap!["c" => vec![]],
+    ];
+    let chunks = json::to_json_chunks(token::to_tokens(&value).unwrap(), 12).unwrap();
+    assert_eq!(chunks, vec![
+        r#"[{"a":[1]}]"#,
+        r#"[{"b":[1,2,3,4,5,6]}]"#,
+        r#"[{"c":[]}]"#,
+    ]);
+    assert_eq!(chunks.iter().filter(|chunk| chunk.len() > 12).count(), 1);
+
+    // Options and newtypes are part of the element they wrap.
+    let value = vec![Some(1), None, Some(3)];
+    let chunks = json::to_json_chunks(token::to_tokens(&value).unwrap(), 8).unwrap();
+    assert_eq!(chunks, vec!["[1,null]", "[3]"]);
+
+    assert!(json::to_json_chunks(token::to_tokens(&Vec::<i32>::new()).unwrap(), 8)
+        .unwrap()
+        .is_empty());
+    assert_eq!(json::to_json_chunks(vec![Token::I32(1)], 8).unwrap_err().to_string(),
+               "Unexpected token: I32(1)");
+}
+
+#[test]
+fn test_json_big_ints() {
+    fn parse(input: &str, big_ints: BigInts) -> Result<Vec<Token>, String> {
+        let config = ParserConfig { big_ints: big_ints, ..ParserConfig::default() };
+        json::from_str_with(input, config).map_err(|err| err.to_string())
+    }
+
+    // Integers that fit are exact in every mode.
+    let max = u64::MAX.to_string();
+    for &mode in &[BigInts::Float, BigInts::Error, BigInts::BigNum] {
+        assert_eq!(parse(&max, mode), Ok(vec![Token::U64(u64::MAX)]));
+        assert_eq!(parse("-9223372036854775808", mode), Ok(vec![Token::I64(i64::MIN)]));
+        assert_eq!(parse("1e3", mode), Ok(vec![Token::F64(1000.0)]));
+        assert_eq!(parse("-2.5E-1", mode), Ok(vec![Token::F64(-0.25)]));
+    }
+    assert_eq!(token::to_json(json::from_str(&max).unwrap()).unwrap(), max);
+
+    let big = "1234567890123456789012345";
+    assert_eq!(parse(big, BigInts::Float), Ok(vec![Token::F64(1.2345678901234568e24)]));
+    assert_eq!(parse(big, BigInts::Error),
+               Err("integer out of range at line 1 column 1".to_owned()));
+    assert_eq!(parse(&format!("[1, -{}]", big), BigInts::Error),
+               Err("integer out of range at line 1 column 5".to_owned()));
+    assert_eq!(parse(big, BigInts::BigNum), Ok(vec![Token::BigNum(big.to_owned())]));
+
+    // The digits survive a round trip, also as map keys.
+    let input = format!(r#"{{"{}":[-{},{}]}}"#, 1, big, big);
+    let tokens = parse(&input, BigInts::BigNum).unwrap();
+    assert_eq!(token::to_json(tokens.clone()).unwrap(), input);
+    assert_eq!(token::to_json(vec![
+        Token::MapStart(Some(1)),
+        Token::MapSep, Token::BigNum(big.to_owned()), Token::Bool(true),
+        Token::MapEnd,
+    ]).unwrap(), format!(r#"{{"{}":true}}"#, big));
+
+    // Types that parse big integers themselves get the digits.
+    let config = ParserConfig { big_ints: BigInts::BigNum, ..ParserConfig::default() };
+    let input = format!("[{}]", big);
+    let tokens = json::from_str_with(&input, config).unwrap();
+    let digits: Vec<String> = token::from_tokens_borrowed(tokens.clone()).unwrap();
+    assert_eq!(digits, vec![big.to_owned()]);
+    assert!(token::from_tokens_borrowed::<Vec<u64>, _>(tokens).is_err());
+}
+
+#[test]
+fn test_json_parser_config() {
+    fn parse(input: &str, config: ParserConfig) -> Result<String, String> {
+        match json::from_str_with(input, config) {
+            Ok(tokens) => Ok(token::to_json(tokens).unwrap()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    let strict = ParserConfig::default();
+    let comments = ParserConfig { allow_comments: true, ..strict };
+    let trailing = ParserConfig { allow_trailing_comma: true, ..strict };
+    let nan_inf = ParserConfig { allow_nan_inf: true, ..strict };
+
+    // Comments.
+    let input = "// config\n{\"a\": /* one */ 1, \"b\" /* two */: [2 // two\n]}\n// end";
+    assert_eq!(parse(input, comments), Ok(r#"{"a":1,"b":[2]}"#.to_owned()));
+    assert_eq!(parse(input, strict), Err("expected value at line 1 column 1".to_owned()));
+    assert_eq!(parse(input, trailing), Err("expected value at line 1 column 1".to_owned()));
+    assert_eq!(parse("[1 /* open", comments),
+               Err("EOF while parsing a comment at line 1 column 4".to_owned()));
+    assert_eq!(parse("[1 / 2]", comments),
+               Err("expected `,` or `]` at line 1 column 4".to_owned()));
+    assert_eq!(parse("\"// not a comment\"", comments), Ok(r#""// not a comment""#.to_owned()));
+
+    // Trailing commas.
+    assert_eq!(parse("[1, 2,]", trailing), Ok("[1,2]".to_owned()));
+    assert_eq!(parse("{\"a\": [1,\n],\n}", trailing), Ok(r#"{"a":[1]}"#.to_owned()));
+    assert_eq!(parse("[1, 2,]", strict), Err("expected value at line 1 column 7".to_owned()));
+    assert_eq!(parse("{\"a\": 1,}", strict),
+               Err("key must be a string at line 1 column 9".to_owned()));
+    assert_eq!(parse("[,]", trailing), Err("expected value at line 1 column 2".to_owned()));
+    assert_eq!(parse("{,}", trailing), Err("key must be a string at line 1 column 2".to_owned()));
+    assert_eq!(parse("[1,,]", trailing), Err("expected value at line 1 column 4".to_owned()));
+
+    // NaN and infinities.
+    let tokens = json::from_str_with("[NaN, Infinity, -Infinity, -1]", nan_inf).unwrap();
+    match tokens[2] {
+        Token::F64(v) => assert!(v.is_nan()),
+        ref token => panic!("expected NaN, found {:?}", token),
+    }
+    assert_eq!(tokens[4], Token::F64(f64::INFINITY));
+    assert_eq!(tokens[6], Token::F64(f64::NEG_INFINITY));
+    assert_eq!(tokens[8], Token::I64(-1));
+    assert_eq!(parse("NaN", strict), Err("expected value at line 1 column 1".to_owned()));
+    assert_eq!(parse("Infinity", strict), Err("expected value at line 1 column 1".to_owned()));
+    assert_eq!(parse("-Infinity", strict), Err("invalid number at line 1 column 2".to_owned()));
+    assert_eq!(parse("Inf", nan_inf), Err("expected value at line 1 column 1".to_owned()));
+}
+
+#[test]
+fn test_json_deserialize_map_keys() {
+    let map: BTreeMap<i32, i32> = json::deserialize("{\"1\": 10, \"-2\": 20}").unwrap();
+    assert_eq!(map, btreemap![1 => 10, -2 => 20]);
+
+    let map: BTreeMap<bool, BTreeMap<char, f64>> =
+        json::deserialize("{\"true\": {\"x\": 1.5}, \"false\": {}}").unwrap();
+    assert_eq!(map, btreemap![true => btreemap!['x' => 1.5], false => btreemap![]]);
+
+    // Only keys are coerced.
+    assert!(json::deserialize::<BTreeMap<i32, i32>>("{\"1\": \"10\"}").is_err());
+}
+
+#[test]
+fn test_json_deserialize_bad_key() {
+    let err = json::deserialize::<BTreeMap<u8, i32>>("{\"1\": 1, \"256\": 2}").unwrap_err();
+    assert_eq!(err.to_string(), "Invalid value: invalid map key \"256\": expected u8");
+
+    let err = json::deserialize::<BTreeMap<i32, i32>>("{\"one\": 1}").unwrap_err();
+    assert_eq!(err.to_string(), "Invalid value: invalid map key \"one\": expected i32");
+}
+
+fn timestamp_json(format: TimestampFormat, secs: i64, nanos: u32) -> String {
+    let mut json = json::Writer::new(Vec::new()).timestamp_format(format);
+    json.write_token(Token::Timestamp(secs, nanos)).unwrap();
+    String::from_utf8(json.into_inner()).unwrap()
+}
+
+#[test]
+fn test_json_timestamp_formats() {
+    let cases = vec![
+        // The first moon landing, before the epoch.
+        (-14_182_940, 0,
+         r#""1969-07-20T20:17:40Z""#, "-14182940", "-14182940000", r#""20.07.1969 20:17""#),
+        (-1, 500_000_000,
+         r#""1969-12-31T23:59:59.500Z""#, "-1", "-500", r#""31.12.1969 23:59""#),
+        (1_500_000_000, 123_456_789,
+         r#""2017-07-14T02:40:00.123456789Z""#, "1500000000", "1500000000123",
+         r#""14.07.2017 02:40""#),
+        // Past the 32-bit rollover in 2038.
+        (4_102_444_800, 0,
+         r#""2100-01-01T00:00:00Z""#, "4102444800", "4102444800000", r#""01.01.2100 00:00""#),
+    ];
+
+    for (secs, nanos, rfc3339, seconds, millis, custom) in cases {
+        assert_eq!(timestamp_json(TimestampFormat::Rfc3339, secs, nanos), rfc3339);
+        assert_eq!(timestamp_json(TimestampFormat::UnixSeconds, secs, nanos), seconds);
+        assert_eq!(timestamp_json(TimestampFormat::UnixMillis, secs, nanos), millis);
+        let pattern = TimestampFormat::Custom("%d.%m.%Y %H:%M".to_owned());
+        assert_eq!(timestamp_json(pattern, secs, nanos), custom);
+    }
+
+    // The default is RFC 3339.
+    assert_eq!(to_json(vec![Token::Timestamp(0, 0)]), r#""1970-01-01T00:00:00Z""#);
+
+    let pattern = TimestampFormat::Custom("%s%. %f %%%Q".to_owned());
+    assert_eq!(timestamp_json(pattern, 1, 20_000_000), r#""1.020 020000000 %%Q""#);
+
+    // Map keys are always strings.
+    let mut json = json::Writer::new(Vec::new()).timestamp_format(TimestampFormat::UnixSeconds);
+    for token in vec![Token::MapStart(Some(1)), Token::MapSep, Token::Timestamp(60, 0),
+                      Token::Bool(true), Token::MapEnd] {
+        json.write_token(token).unwrap();
+    }
+    assert_eq!(String::from_utf8(json.into_inner()).unwrap(), r#"{"60":true}"#);
+}
+
+#[test]
+fn test_json_timestamp_range_ends() {
+    assert_eq!(to_json(vec![Token::Timestamp(i64::MIN, 0)]),
+               r#""-292277022657-01-27T08:29:52Z""#);
+    assert_eq!(to_json(vec![Token::Timestamp(i64::MAX, 999_999_999)]),
+               r#""292277026596-12-04T15:30:07.999999999Z""#);
+    let pattern = TimestampFormat::Custom("%Y-%m-%d %H:%M:%S".to_owned());
+    assert_eq!(timestamp_json(pattern, i64::MIN, 0), r#""-292277022657-01-27 08:29:52""#);
+
+    // The other writers share the calendar arithmetic.
+    for &secs in &[i64::MIN, i64::MAX] {
+        token::msgpack::to_msgpack(vec![Token::Timestamp(secs, 0)]).unwrap();
+        token::yaml::to_yaml_flow(vec![Token::Timestamp(secs, 0)]).unwrap();
+    }
+}
+
+#[test]
+fn test_json_timestamps_read_back() {
+    let moon_landing = UNIX_EPOCH - Duration::from_secs(14_182_940);
+    let precise = UNIX_EPOCH + Duration::new(1_500_000_000, 123_456_789);
+
+    let times: Vec<SystemTime> = json::deserialize(r#"[
+        "1969-07-20T20:17:40Z",
+        -14182940,
+        "2017-07-14T04:40:00.123456789+02:00",
+        1500000000
+    ]"#).unwrap();
+    assert_eq!(times, vec![
+        moon_landing,
+        moon_landing,
+        precise,
+        precise - Duration::new(0, 123_456_789),
+    ]);
+
+    // Every format that writes a string or seconds reads back as the same time.
+    for format in vec![TimestampFormat::Rfc3339, TimestampFormat::UnixSeconds] {
+        let mut json = json::Writer::new(Vec::new()).timestamp_format(format.clone());
+        json.write_token(Token::Timestamp(-14_182_940, 0)).unwrap();
+        let json = String::from_utf8(json.into_inner()).unwrap();
+        assert_eq!(json::deserialize::<SystemTime>(&json).unwrap(), moon_landing, "{:?}", format);
+    }
+
+    let err = json::deserialize::<SystemTime>(r#""1969-02-30T00:00:00Z""#).unwrap_err();
+    assert_eq!(err.to_string(),
+               r#"Invalid value: Str("1969-02-30T00:00:00Z") is not a valid timestamp"#);
+    assert!(json::deserialize::<SystemTime>("true").is_err());
+
+    // Times outside the years 1601 through 9999 are rejected rather than overflowing.
+    let last = json::deserialize::<SystemTime>(r#""9999-12-31T23:59:59.999999999Z""#).unwrap();
+    assert_eq!(last, UNIX_EPOCH + Duration::new(253_402_300_799, 999_999_999));
+    let first = json::deserialize::<SystemTime>(r#""1601-01-01T00:00:00Z""#).unwrap();
+    assert_eq!(first, UNIX_EPOCH - Duration::from_secs(11_644_473_600));
+    for input in vec!["253402300800", "-11644473601", "9223372036854775807", "-9223372036854775808",
+                      r#""1600-12-31T23:59:59Z""#] {
+        let err = json::deserialize::<SystemTime>(input).unwrap_err();
+        assert!(err.to_string().ends_with("is not a valid timestamp"), "{}", err);
+    }
+}
+
+/// Counts the bytes written to it and remembers the largest single write.
+struct CountingWriter {
+    len: usize,
+    largest_write: usize,
+}
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.len += buf.len();
+        self.largest_write = ::std::cmp::max(self.largest_write, buf.len());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_json_streams_lazy_seq() {
+    let count = 1_000_000u32;
+    let records = LazySeq::new(|| (0..count).map(|i| i * 2));
+
+    let writer = CountingWriter { len: 0, largest_write: 0 };
+    let mut ser = token::Serializer::with_sink(json::Writer::new(writer));
+    records.serialize(&mut ser).unwrap();
+    let writer = ser.into_inner().into_inner();
+
+    let digits: usize = (0..count).map(|i| (i * 2).to_string().len()).sum();
+    assert_eq!(writer.len, 1 + digits + (count as usize - 1) + 1);
+    // Every token goes straight to the writer, nothing is buffered along the way.
+    assert!(writer.largest_write <= 8, "largest write was {} bytes", writer.largest_write);
+}
+
+#[test]
+fn test_json_stream() {
+    let input = "{\"a\": 1}\n  [true, null]\"three\"  \n";
+    let documents: Vec<_> = json::Stream::new(input).map(Result::unwrap).collect();
+    assert_eq!(documents.len(), 3);
+    assert_eq!(token::to_json(documents[0].clone()).unwrap(), "{\"a\":1}");
+    assert_eq!(token::to_json(documents[1].clone()).unwrap(), "[true,null]");
+    assert_eq!(documents[2], vec![Token::Str("three")]);
+
+    assert_eq!(json::Stream::new("  \n").count(), 0);
+
+    let documents = vec![
+        token::to_tokens(&vec![1, 2]).unwrap(),
+        token::to_tokens(&"x").unwrap(),
+        token::to_tokens(&btreemap!["k" => 3]).unwrap(),
+    ];
+    let output = json::to_json_stream(documents).unwrap();
+    assert_eq!(output, "[1,2]\n\"x\"\n{\"k\":3}\n");
+    let read: Vec<String> = json::Stream::new(&output)
+        .map(|document| token::to_json(document.unwrap()).unwrap())
+        .collect();
+    assert_eq!(read, vec!["[1,2]", "\"x\"", "{\"k\":3}"]);
+}
+
+#[test]
+fn test_json_stream_errors() {
+    let input = "[1, 2]\n{\"a\": tru, \"b\": [3]}\n\"three\"\n";
+
+    let results: Vec<_> = json::Stream::new(input).collect();
+    assert_eq!(results.len(), 2);
+    assert!(results[0].is_ok());
+    assert_eq!(results[1].as_ref().unwrap_err().to_string(),
+               "document 1: expected value at line 2 column 7");
+
+    let results: Vec<_> = json::Stream::new(input).skip_invalid().collect();
+    assert_eq!(results.len(), 3);
+    match results[1] {
+        Err(json::Error::Document(1, _)) => { }
+        ref result => panic!("unexpected result {:?}", result),
+    }
+    assert_eq!(*results[2].as_ref().unwrap(), vec![Token::Str("three")]);
+}
+
+/// A reader that hands out one byte per call, to split values across reads.
+struct Trickle<'a>(&'a [u8]);
+
+impl<'a> io::Read for Trickle<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.0.is_empty() || buf.is_empty() {
+            return Ok(0);
+        }
+        buf[0] = self.0[0];
+        self.0 = &self.0[1..];
+        Ok(1)
+    }
+}
+
+#[test]
+fn test_json_read_stream() {
+    let input = "{\"a\": \"]}\\\"\"}\n  [true, null]\"three\" -4.5e1\n";
+    let documents: Vec<_> = json::ReadStream::new(Trickle(input.as_bytes()))
+        .map(Result::unwrap)
+        .collect();
+    let expected: Vec<_> = json::Stream::new(input).map(Result::unwrap).collect();
+    assert_eq!(documents, expected);
+    assert_eq!(documents.len(), 4);
+    assert_eq!(documents[2], vec![Token::String("three".to_owned())]);
+
+    assert_eq!(json::ReadStream::new(&b"  \n"[..]).count(), 0);
+
+    // Comments around and inside values.
+    let config = ParserConfig { allow_comments: true, ..ParserConfig::default() };
+    let input = "// first\n[1, /* ] */ 2] /* between */ 3 // last";
+    let documents: Vec<String> = json::ReadStream::new(Trickle(input.as_bytes()))
+        .config(config)
+        .map(|document| token::to_json(document.unwrap()).unwrap())
+        .collect();
+    assert_eq!(documents, vec!["[1,2]", "3"]);
+
+    // Writer and reader round trip.
+    let documents = vec![
+        token::to_tokens(&vec![1, 2]).unwrap(),
+        token::to_tokens(&"x").unwrap(),
+        token::to_tokens(&btreemap!["k" => 3]).unwrap(),
+    ];
+    let output = json::to_json_stream(documents).unwrap();
+    let read: Vec<String> = json::ReadStream::new(output.as_bytes())
+        .map(|document| token::to_json(document.unwrap()).unwrap())
+        .collect();
+    assert_eq!(read, vec!["[1,2]", "\"x\"", "{\"k\":3}"]);
+}
+
+#[test]
+fn test_json_read_stream_errors() {
+    let input = "[1, 2]\n{\"a\": tru, \"b\": [3]}\n\"three\"\n";
+
+    let results: Vec<_> = json::ReadStream::new(Trickle(input.as_bytes())).collect();
+    assert_eq!(results.len(), 2);
+    assert!(results[0].is_ok());
+    assert_eq!(results[1].as_ref().unwrap_err().to_string(),
+               "document 1: expected value at line 2 column 7");
+
+    let results: Vec<_> = json::ReadStream::new(input.as_bytes()).skip_invalid().collect();
+    assert_eq!(results.len(), 3);
+    match results[1] {
+        Err(json::Error::Document(1, ref err)) => {
+            assert_eq!(format!("{:#}", err), concat!("expected value at line 2 column 7\n",
+                                                     "  |\n",
+                                                     "2 | {\"a\": tru, \"b\": [3]}\n",
+                                                     "  |       ^"));
+        }
+        ref result => panic!("unexpected result {:?}", result),
+    }
+    assert_eq!(*results[2].as_ref().unwrap(), vec![Token::Str("three")]);
+
+    // Positions count from the start of the input, also in the middle of a line.
+    let err = json::ReadStream::new(&b"1 2\n3 [4 x]"[..]).nth(3).unwrap().unwrap_err();
+    assert_eq!(err.to_string(), "document 3: expected `,` or `]` at line 2 column 6");
+
+    let err = json::ReadStream::new(&b"[\"\xff\"]"[..]).next().unwrap().unwrap_err();
+    assert_eq!(err.to_string(), "document 0: invalid UTF-8 at line 1 column 3");
+
+    let err = json::ReadStream::new(&b"[1, [2"[..]).next().unwrap().unwrap_err();
+    assert_eq!(err.to_string(), "document 0: EOF while parsing a list at line 1 column 7");
+}
+
+#[test]
+fn test_json_stream_deserialize() {
+    let input = "[1, 2]\n[3]\n[\"four\"]\n[5]\n";
+
+    let values: Vec<_> = json::Stream::new(input).deserialize::<Vec<u32>>().collect();
+    assert_eq!(values.len(), 4);
+    assert_eq!(*values[1].as_ref().unwrap(), vec![3]);
+    match values[2] {
+        Err(json::Error::Document(2, ref err)) => {
+            match **err {
+                json::Error::Value(_) => { }
+                ref err => panic!("unexpected error {:?}", err),
+            }
+        }
+        ref result => panic!("unexpected result {:?}", result),
+    }
+    assert_eq!(*values[3].as_ref().unwrap(), vec![5]);
+
+    let values: Vec<Vec<String>> = json::ReadStream::new(Trickle(b"[\"a\"] [\"b\", \"c\"]"))
+        .deserialize()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(values, vec![vec!["a".to_owned()], vec!["b".to_owned(), "c".to_owned()]]);
+}
+
+#[test]
+fn test_json_max_depth() {
+    let nested = |depth: usize| {
+        let mut input = String::new();
+        for _ in 0..depth {
+            input.push_str("[{\"a\":");
+        }
+        input.push('1');
+        for _ in 0..depth {
+            input.push_str("}]");
+        }
+        input
+    };
+
+    assert!(json::from_str(&nested(64)).is_ok());
+    assert_eq!(json::from_str(&nested(65)).unwrap_err().to_string(),
+               "nesting exceeds the depth limit of 128 at line 1 column 385");
+
+    // Deep enough to overflow the stack without a limit.
+    let input: String = (0..200_000).map(|_| '[').collect();
+    assert_eq!(json::from_str(&input).unwrap_err().to_string(),
+               "nesting exceeds the depth limit of 128 at line 1 column 129");
+    assert!(json::deserialize::<Vec<u32>>(&input).is_err());
+    assert!(json::Stream::new(&input).next().unwrap().is_err());
+
+    let config = ParserConfig { max_depth: 2, ..ParserConfig::default() };
+    assert!(json::from_str_with("[[1]]", config).is_ok());
+    assert!(json::from_str_with("[[[1]]]", config).is_err());
+}
+
+/// A writer that accepts `limit` bytes, possibly in the middle of a token, and then fails.
+struct FailAfter {
+    written: Vec<u8>,
+    limit: usize,
+}
+
+impl Write for FailAfter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let room = self.limit - self.written.len();
+        if room == 0 {
+            return Err(io::Error::new(io::ErrorKind::Other, "connection reset"));
+        }
+        let len = if buf.len() < room { buf.len() } else { room };
+        self.written.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_json_stream_state_resume() {
+    let input = r#"{"name": "Henry \"the frog\"", "legs": [1, 2, 3, 4], "age": 3.5,
+                    "tags": {"a": null, "b": [[], {}]}}"#;
+    let tokens = json::from_str(input).unwrap();
+    let expected = token::to_json(tokens.clone()).unwrap();
+
+    for limit in 0..expected.len() {
+        let mut state = json::StreamState::new();
+        let mut first = FailAfter { written: Vec::new(), limit: limit };
+        match state.write_json(tokens.iter().cloned(), &mut first) {
+            Err(json::Error::Io(ref err)) => assert_eq!(err.to_string(), "connection reset"),
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert_eq!(state.bytes_written(), limit as u64);
+
+        let mut second = Vec::new();
+        let rest = tokens.iter().cloned().skip(state.tokens_written());
+        state.write_json(rest, &mut second).unwrap();
+        assert_eq!(state.tokens_written(), tokens.len());
+        assert_eq!(state.depth(), 0);
+
+        let mut output = first.written;
+        output.extend_from_slice(&second);
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output, expected);
+        assert_eq!(json::from_str(&output).unwrap(), tokens);
+    }
+}
+
+#[test]
+fn test_json_tuple_structs() {
+    fn write(tokens: Vec<Token>, tuple_structs: json::TupleStructs) -> String {
+        let mut writer = json::Writer::new(Vec::new()).tuple_structs(tuple_structs);
+        for token in tokens {
+            writer.write_token(token).unwrap();
+        }
+        String::from_utf8(writer.into_inner()).unwrap()
+    }
+
+    let pair = vec![
+        Token::TupleStructStart("Pair", Some(2)),
+        Token::TupleStructSep, Token::I32(1),
+        Token::TupleStructSep, Token::Str("two"),
+        Token::TupleStructEnd,
+    ];
+    assert_eq!(write(pair.clone(), json::TupleStructs::Arrays), r#"[1,"two"]"#);
+    assert_eq!(write(pair.clone(), json::TupleStructs::Objects), r#"{"0":1,"1":"two"}"#);
+    assert_eq!(to_json(pair.clone()), r#"[1,"two"]"#);
+
+    // Only tuple structs change, the tuples and sequences inside them stay arrays.
+    let mut nested = vec![
+        Token::TupleStructStart("Wrapper", Some(2)),
+        Token::TupleStructSep,
+        Token::TupleStart(1), Token::TupleSep, Token::Bool(true), Token::TupleEnd,
+        Token::TupleStructSep,
+    ];
+    nested.extend(pair);
+    nested.push(Token::TupleStructEnd);
+    assert_eq!(write(nested, json::TupleStructs::Objects),
+               r#"{"0":[true],"1":{"0":1,"1":"two"}}"#);
+
+    let empty = vec![Token::TupleStructStart("Empty", Some(0)), Token::TupleStructEnd];
+    assert_eq!(write(empty, json::TupleStructs::Objects), "{}");
+}
+
+#[test]
+fn test_json_scan_find_field() {
+    let kinds = ["click", "view", "purchase"];
+    let mut input = String::new();
+    for id in 0..10000 {
+        input.push_str(&format!("{{\"id\":{},\"tags\":[{{\"type\":\"x\"}}],\"type\":\"{}\"}}\n",
+                                id, kinds[id % 3]));
+    }
+
+    let mut offsets = Vec::new();
+    for record in json::Stream::new(&input).scan() {
+        let (offset, tokens) = record.unwrap();
+        // The nested "type" field is skipped over.
+        if token::find_field(tokens, "type").unwrap() == Some(Token::Str("purchase")) {
+            offsets.push(offset);
+        }
+    }
+    assert_eq!(offsets.len(), 3333);
+
+    let record = json::Stream::new(&input[offsets[1000]..]).next().unwrap().unwrap();
+    assert_eq!(token::find_field(record, "id").unwrap(), Some(Token::I64(3002)));
+
+    assert_eq!(token::find_field(json::from_str(r#"{"id":1}"#).unwrap(), "type").unwrap(),
+               None);
+    assert!(token::find_field(json::from_str(r#"{"type":[1]}"#).unwrap(), "type").is_err());
+    assert!(token::find_field(json::from_str("[1]").unwrap(), "type").is_err());
+}
+
+#[test]
+fn test_json_read_scan() {
+    use std::io::{Cursor, Seek, SeekFrom};
+
+    let kinds = ["click", "view", "purchase"];
+    let mut input = String::new();
+    for id in 0..10000 {
+        input.push_str(&format!("{{\"id\":{},\"note\":\"a ]}} \\\" b\",\"type\":\"{}\"}}\n",
+                                id, kinds[id % 3]));
+    }
+    let expected: Vec<_> = json::Stream::new(&input)
+        .scan()
+        .map(|record| record.unwrap().0)
+        .collect();
+
+    let mut input = Cursor::new(input.into_bytes());
+    let offsets: Vec<_> = json::ReadStream::new(Trickle(input.get_ref()))
+        .scan()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(offsets.len(), 10000);
+    assert_eq!(offsets, expected);
+
+    input.seek(SeekFrom::Start(offsets[3002] as u64)).unwrap();
+    let record = json::ReadStream::new(&mut input).next().unwrap().unwrap();
+    assert_eq!(token::find_field(record.clone(), "id").unwrap(), Some(Token::I64(3002)));
+    assert_eq!(token::find_field(record, "type").unwrap(),
+               Some(Token::String("purchase".to_owned())));
+
+    // Invalid documents are skipped over like valid ones.
+    let offsets: Vec<_> = json::ReadStream::new(&b"[1, x] {\"a\": } 2 ] 3"[..])
+        .scan()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(offsets, vec![0, 7, 15, 17, 19]);
+}
+
+#[test]
+fn test_json_corpus() {
+    testing::roundtrip_backend(|tokens| {
+        token::to_json(testing::bytes_as_seqs(tokens)).map_err(|e| e.to_string())
+    }, |text| {
+        let tokens = try!(json::from_str(text).map_err(|e| format!("{}: {}", e, text)));
+        Value::from_tokens(tokens).map_err(|e| e.to_string())
+    });
+}
+
+#[test]
+fn test_json_bytes_encoding() {
+    use self::serde::bytes::ByteBuf;
+    use self::serde::token::BytesEncoding;
+    use self::serde::token::borrowed::{DeserializeBorrowed, Deserializer};
+
+    let blob = ByteBuf::from(b"\x00\xffhi".to_vec());
+    assert_eq!(to_json(token::to_tokens(&blob).unwrap()), "\"AP9oaQ==\"");
+    let back: ByteBuf = json::deserialize("\"AP9oaQ==\"").unwrap();
+    assert_eq!(back, blob);
+
+    // Arrays of numbers, which older writers produced, still read as bytes.
+    let back: ByteBuf = json::deserialize("[0,255,104,105]").unwrap();
+    assert_eq!(back, blob);
+
+    let encodings = [
+        (BytesEncoding::Base64, "\"AP9oaQ==\""),
+        (BytesEncoding::Base64NoPad, "\"AP9oaQ\""),
+        (BytesEncoding::Base64Url, "\"AP9oaQ==\""),
+        (BytesEncoding::Base64UrlNoPad, "\"AP9oaQ\""),
+        (BytesEncoding::Base32, "\"AD7WQ2I=\""),
+        (BytesEncoding::Hex, "\"00ff6869\""),
+    ];
+    for &(encoding, expected) in &encodings {
+        let mut writer = json::Writer::new(Vec::new()).bytes_encoding(encoding);
+        writer.write_token(Token::Bytes(b"\x00\xffhi")).unwrap();
+        let text = String::from_utf8(writer.into_inner()).unwrap();
+        assert_eq!(text, expected);
+
+        let tokens = json::from_str(&text).unwrap();
+        let mut de = Deserializer::new(tokens.into_iter()).bytes_encoding(encoding);
+        let back: ByteBuf = DeserializeBorrowed::deserialize_borrowed(&mut de).unwrap();
+        assert_eq!(back, blob);
+    }
+
+    let empty: ByteBuf = json::deserialize("\"\"").unwrap();
+    assert!(empty.is_empty());
+
+    let err = json::deserialize::<ByteBuf>("\"AP9o*Q==\"").unwrap_err();
+    assert_eq!(err.to_string(),
+               "Invalid value: \"AP9o*Q==\": invalid character '*' at offset 4");
+}
+
+#[test]
+fn test_json_float_precision() {
+    use self::serde::token::json::JsonConfig;
+
+    let sum = 0.1 + 0.2;
+    assert_eq!(to_json(vec![Token::F64(sum)]), "0.30000000000000004");
+    assert_eq!(to_json(vec![Token::F32(0.1)]), "0.1");
+    assert_eq!(to_json(vec![Token::F32(16777216.0)]), "16777216.0");
+
+    let config = JsonConfig { float_precision: Some(2), ..JsonConfig::default() };
+    let cases = vec![
+        (Token::F64(sum), "0.3"),
+        (Token::F64(1.0), "1.0"),
+        (Token::F64(-2.675), "-2.67"),
+        (Token::F64(1e300), "1e300"),
+        (Token::F64(f64::NAN), "null"),
+        (Token::F32(0.125), "0.12"),
+        (Token::F32(0.1), "0.1"),
+        (Token::F32(2.675), "2.67"),
+    ];
+    for (token, expected) in cases {
+        assert_eq!(json::to_json_with(vec![token.clone()], config.clone()).unwrap(), expected,
+                   "{:?}", token);
+    }
+
+    let zero = JsonConfig { float_precision: Some(0), ..JsonConfig::default() };
+    assert_eq!(json::to_json_with(vec![Token::F64(2.5)], zero).unwrap(), "2.0");
+
+    // Keys are rounded too.
+    let mut writer = json::Writer::new(Vec::new()).config(config);
+    for token in vec![
+        Token::MapStart(Some(1)),
+        Token::MapSep, Token::F64(sum), Token::Bool(true),
+        Token::MapEnd,
+    ] {
+        writer.write_token(token).unwrap();
+    }
+    assert_eq!(writer.into_inner(), br#"{"0.3":true}"#);
+
+    let mut writer = json::Writer::new(Vec::new());
+    for token in vec![
+        Token::MapStart(Some(1)),
+        Token::MapSep, Token::F32(0.1), Token::Bool(true),
+        Token::MapEnd,
+    ] {
+        writer.write_token(token).unwrap();
+    }
+    assert_eq!(writer.into_inner(), br#"{"0.1":true}"#);
+}
+
+#[test]
+fn test_json_config() {
+    use self::serde::token::BytesEncoding;
+    use self::serde::token::json::{JsonConfig, TupleStructs, UnitVariants};
+
+    let tokens = vec![
+        Token::TupleStructStart("Event", Some(2)),
+            Token::TupleStructSep,
+            Token::Timestamp(1_500_000_000, 0),
+
+            Token::TupleStructSep,
+            Token::Bytes(b"\x01\xff"),
+        Token::TupleStructEnd,
+    ];
+    assert_eq!(json::to_json_with(tokens.clone(), JsonConfig::default()).unwrap(),
+               to_json(tokens.clone()));
+
+    let config = JsonConfig {
+        timestamp_format: TimestampFormat::UnixSeconds,
+        tuple_structs: TupleStructs::Objects,
+        bytes_encoding: BytesEncoding::Hex,
+        ..JsonConfig::default()
+    };
+    let expected = r#"{"0":1500000000,"1":"01ff"}"#;
+    assert_eq!(json::to_json_with(tokens.clone(), config.clone()).unwrap(), expected);
+
+    // The builder methods set the same options.
+    let mut writer = json::Writer::new(Vec::new())
+        .timestamp_format(TimestampFormat::UnixSeconds)
+        .tuple_structs(TupleStructs::Objects)
+        .bytes_encoding(BytesEncoding::Hex);
+    for token in tokens.clone() {
+        writer.write_token(token).unwrap();
+    }
+    assert_eq!(writer.into_inner(), expected.as_bytes());
+
+    // A config replaces the options set before it.
+    let mut writer = json::Writer::new(Vec::new())
+        .bytes_encoding(BytesEncoding::Base64)
+        .config(config)
+        .tuple_structs(TupleStructs::Arrays);
+    for token in tokens {
+        writer.write_token(token).unwrap();
+    }
+    assert_eq!(writer.into_inner(), br#"[1500000000,"01ff"]"#);
+
+    let red = vec![Token::EnumUnit("Color", "Red")];
+    assert_eq!(json::to_json_with(red.clone(), JsonConfig::default()).unwrap(), r#"{"Red":[]}"#);
+    let config = JsonConfig { unit_variants: UnitVariants::Strings, ..JsonConfig::default() };
+    assert_eq!(json::to_json_with(red, config).unwrap(), r#""Red""#);
+}
+
+#[test]
+fn test_json_unit_variants() {
+    use self::serde::token::borrowed::{DeserializeBorrowed, Deserializer};
+    use self::serde::token::json::UnitVariants;
+
+    #[derive(Debug, PartialEq)]
+    enum Color { Red, Green }
+
+    impl<'a> DeserializeBorrowed<'a> for Color {
+        fn deserialize_borrowed<I>(deserializer: &mut Deserializer<I>) -> Result<Self, token::Error>
+            where I: Iterator<Item=Token<'a>>,
+        {
+            match try!(deserializer.unit_variant("Color", &["Red", "Green"])) {
+                0 => Ok(Color::Red),
+                _ => Ok(Color::Green),
+            }
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Pen { name: String, color: Color }
+
+    impl<'a> DeserializeBorrowed<'a> for Pen {
+        fn deserialize_borrowed<I>(deserializer: &mut Deserializer<I>) -> Result<Self, token::Error>
+            where I: Iterator<Item=Token<'a>>,
+        {
+            let mut name = None;
+            let mut color = None;
+            try!(deserializer.struct_start());
+            while let Some(field) = try!(deserializer.struct_field()) {
+                match &*field {
+                    "name" => name = Some(try!(DeserializeBorrowed::deserialize_borrowed(
+                        deserializer))),
+                    "color" => color = Some(try!(Color::deserialize_borrowed(deserializer))),
+                    _ => return Err(token::Error::InvalidValue(field.into_owned())),
+                }
+            }
+            Ok(Pen { name: name.unwrap(), color: color.unwrap() })
+        }
+    }
+
+    // The name is a string that happens to be a variant name too.
+    let tokens = vec![
+        Token::StructStart("Pen", Some(2)),
+        Token::StructSep, Token::Str("name"), Token::Str("Red"),
+        Token::StructSep, Token::Str("color"), Token::EnumUnit("Color", "Red"),
+        Token::StructEnd,
+    ];
+    let write = |unit_variants| {
+        let mut writer = json::Writer::new(Vec::new()).unit_variants(unit_variants);
+        for token in tokens.clone() {
+            writer.write_token(token).unwrap();
+        }
+        String::from_utf8(writer.into_inner()).unwrap()
+    };
+    let strings = write(UnitVariants::Strings);
+    let objects = write(UnitVariants::Objects);
+    assert_eq!(strings, r#"{"name":"Red","color":"Red"}"#);
+    assert_eq!(objects, r#"{"name":"Red","color":{"Red":[]}}"#);
+
+    let pen = Pen { name: "Red".to_owned(), color: Color::Red };
+    assert_eq!(json::deserialize::<Pen>(&strings).unwrap(), pen);
+    assert_eq!(json::deserialize::<Pen>(&objects).unwrap(), pen);
+    assert_eq!(json::deserialize::<Color>(r#"{"Green":null}"#).unwrap(), Color::Green);
+
+    // Variants with a payload are still objects.
+    let mut writer = json::Writer::new(Vec::new()).unit_variants(UnitVariants::Strings);
+    for token in vec![Token::EnumNewType("Shape", "Circle"), Token::F64(1.5)] {
+        writer.write_token(token).unwrap();
+    }
+    assert_eq!(writer.into_inner(), br#"{"Circle":1.5}"#);
+
+    let err = json::deserialize::<Pen>(r#"{"name":"Red","color":"Purple"}"#).unwrap_err();
+    assert_eq!(err.to_string(),
+               "Invalid value: unknown variant `Purple` of Color, expected one of `Red`, `Green`");
+    assert!(json::deserialize::<Color>(r#"{"Red":[1]}"#).is_err());
+}
@@ -0,0 +1,400 @@
+use std::collections::BTreeMap;
+use std::io::{self, Cursor, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+extern crate serde;
+use self::serde::ser::{self, Serialize, Serializer};
+use self::serde::token::{self, Token};
+use self::serde::token::borrowed::{self, DeserializeBorrowed};
+use self::serde::token::{json, msgpack};
+
+//////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_msgpack_scalars() {
+    let cases: Vec<(Token, Vec<u8>)> = vec![
+        (Token::Unit, vec![0xc0]),
+        (Token::Bool(false), vec![0xc2]),
+        (Token::Bool(true), vec![0xc3]),
+        (Token::U8(0), vec![0x00]),
+        (Token::U8(127), vec![0x7f]),
+        (Token::U8(128), vec![0xcc, 0x80]),
+        (Token::U16(256), vec![0xcd, 0x01, 0x00]),
+        (Token::U32(65536), vec![0xce, 0x00, 0x01, 0x00, 0x00]),
+        (Token::U64(1 << 32), vec![0xcf, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00]),
+        (Token::I8(-1), vec![0xff]),
+        (Token::I8(-32), vec![0xe0]),
+        (Token::I8(-33), vec![0xd0, 0xdf]),
+        (Token::I16(-129), vec![0xd1, 0xff, 0x7f]),
+        (Token::I64(5), vec![0x05]),
+        (Token::F32(1.5), vec![0xca, 0x3f, 0xc0, 0x00, 0x00]),
+        (Token::F64(1.5), vec![0xcb, 0x3f, 0xf8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+        (Token::Str("abc"), vec![0xa3, b'a', b'b', b'c']),
+        (Token::Char('é'), vec![0xa2, 0xc3, 0xa9]),
+        (Token::Bytes(b"ab"), vec![0xc4, 0x02, b'a', b'b']),
+    ];
+
+    for (token, expected) in cases {
+        assert_eq!(token::to_msgpack(vec![token.clone()]).unwrap(), expected, "{:?}", token);
+    }
+
+    let long = "x".repeat(40);
+    let encoded = token::to_msgpack(vec![Token::Str(&long)]).unwrap();
+    assert_eq!(&encoded[..2], &[0xd9, 40]);
+    assert_eq!(encoded.len(), 42);
+}
+
+#[test]
+fn test_msgpack_compound() {
+    let value = btreemap![
+        "a" => vec![1],
+        "b" => vec![2, 3]
+    ];
+
+    assert_eq!(
+        token::to_msgpack(token::to_tokens(&value).unwrap()).unwrap(),
+        vec![
+            0x82,
+                0xa1, b'a', 0x91, 0x01,
+                0xa1, b'b', 0x92, 0x02, 0x03,
+        ]
+    );
+
+    let value = (0..20).collect::<Vec<u8>>();
+    let encoded = token::to_msgpack(token::to_tokens(&value).unwrap()).unwrap();
+    assert_eq!(&encoded[..3], &[0xdc, 0x00, 20]);
+}
+
+#[test]
+fn test_write_msgpack_matches_to_msgpack() {
+    let value = btreemap![
+        "name" => vec![Some("x".repeat(300)), None],
+        "list" => (0..100).map(|i| Some(i.to_string())).collect()
+    ];
+    let tokens = token::to_tokens(&value).unwrap();
+
+    let mut writer = Cursor::new(Vec::new());
+    msgpack::write_msgpack(tokens.clone(), &mut writer).unwrap();
+
+    assert_eq!(writer.into_inner(), token::to_msgpack(tokens).unwrap());
+}
+
+struct FailingWriter {
+    remaining: usize,
+}
+
+impl Write for FailingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.remaining < buf.len() {
+            return Err(io::Error::new(io::ErrorKind::Other, "full"));
+        }
+        self.remaining -= buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_msgpack_errors() {
+    let mut writer = FailingWriter { remaining: 3 };
+    let err = msgpack::write_msgpack(token::to_tokens(&vec![1u32, 1000]).unwrap(), &mut writer)
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Other);
+
+    let err = token::to_msgpack(vec![Token::SeqStart(None), Token::SeqEnd]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+fn encode_ext(tokens: Vec<Token>) -> Vec<u8> {
+    let mut encoder = msgpack::Encoder::new(Vec::new()).timestamp_ext(true);
+    encoder.encode(tokens).unwrap();
+    encoder.into_inner()
+}
+
+fn ext_tokens(ty: i8, data: &[u8]) -> Vec<Token> {
+    vec![
+        Token::StructStart(msgpack::EXT_STRUCT, Some(2)),
+        Token::StructSep, Token::Str("type"), Token::I8(ty),
+        Token::StructSep, Token::Str("data"), Token::Bytes(data),
+        Token::StructEnd,
+    ]
+}
+
+#[test]
+fn test_msgpack_timestamp_ext() {
+    let cases: Vec<(i64, u32, Vec<u8>)> = vec![
+        // 32 bit format.
+        (1, 0, vec![0xd6, 0xff, 0x00, 0x00, 0x00, 0x01]),
+        ((1 << 32) - 1, 0, vec![0xd6, 0xff, 0xff, 0xff, 0xff, 0xff]),
+        // 64 bit format, nanoseconds in the upper 30 bits.
+        (1, 1, vec![0xd7, 0xff, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x01]),
+        (1 << 32, 0, vec![0xd7, 0xff, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00]),
+        // 96 bit format, as ext8.
+        (-1, 500, vec![
+            0xc7, 12, 0xff,
+            0x00, 0x00, 0x01, 0xf4,
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        ]),
+    ];
+
+    for (secs, nanos, expected) in cases {
+        let encoded = encode_ext(vec![Token::Timestamp(secs, nanos)]);
+        assert_eq!(encoded, expected, "{} {}", secs, nanos);
+        assert_eq!(msgpack::from_msgpack(&encoded).unwrap(), vec![Token::Timestamp(secs, nanos)]);
+    }
+
+    // Without the option timestamps stay strings.
+    assert_eq!(token::to_msgpack(vec![Token::Timestamp(0, 0)]).unwrap()[0], 0xb4);
+
+    // Without the handler the timestamp decodes to the generic representation.
+    let decoder = msgpack::Decoder::new(&[0xd6, 0xff, 0, 0, 0, 1]).ext_handler(-1, None);
+    assert_eq!(decoder.decode().unwrap(), ext_tokens(-1, &[0, 0, 0, 1]));
+
+    let err = msgpack::from_msgpack(&[0xd5, 0xff, 0, 0]).unwrap_err();
+    assert_eq!(err.to_string(), "invalid payload for extension type -1 at offset 0");
+}
+
+#[test]
+fn test_msgpack_custom_ext() {
+    let uuid: Vec<u8> = (0..16).collect();
+    let tokens = vec![
+        Token::SeqStart(Some(2)),
+        Token::SeqSep,
+    ];
+    let tokens = tokens.into_iter()
+        .chain(ext_tokens(42, &uuid))
+        .chain(vec![Token::SeqSep, Token::I64(7), Token::SeqEnd])
+        .collect::<Vec<_>>();
+
+    let encoded = token::to_msgpack(tokens.clone()).unwrap();
+    assert_eq!(&encoded[..3], &[0x92, 0xd8, 42]);
+    assert_eq!(&encoded[3..19], &uuid[..]);
+    assert_eq!(msgpack::from_msgpack(&encoded).unwrap(), tokens);
+
+    fn decode_uuid(data: &[u8]) -> Option<Vec<Token<'static>>> {
+        Some(vec![Token::String(data.iter().map(|b| format!("{:02x}", b)).collect())])
+    }
+    let decoder = msgpack::Decoder::new(&encoded).ext_handler(42, Some(decode_uuid));
+    assert_eq!(decoder.decode().unwrap()[2],
+               Token::String("000102030405060708090a0b0c0d0e0f".to_owned()));
+
+    // Byte arrays tagged through the encoder.
+    let mut encoder = msgpack::Encoder::new(Vec::new()).bytes_ext(Some(42));
+    encoder.encode(vec![Token::Bytes(&uuid)]).unwrap();
+    assert_eq!(msgpack::from_msgpack(&encoder.into_inner()).unwrap(), ext_tokens(42, &uuid));
+
+    let err = token::to_msgpack(vec![
+        Token::StructStart(msgpack::EXT_STRUCT, Some(2)),
+        Token::StructSep, Token::Str("type"), Token::I32(1000),
+    ]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_msgpack_ext_size_classes() {
+    let cases: Vec<(usize, Vec<u8>)> = vec![
+        (0, vec![0xc7, 0x00]),
+        (1, vec![0xd4]),
+        (2, vec![0xd5]),
+        (3, vec![0xc7, 0x03]),
+        (4, vec![0xd6]),
+        (8, vec![0xd7]),
+        (16, vec![0xd8]),
+        (17, vec![0xc7, 0x11]),
+        (255, vec![0xc7, 0xff]),
+        (256, vec![0xc8, 0x01, 0x00]),
+        (65535, vec![0xc8, 0xff, 0xff]),
+        (65536, vec![0xc9, 0x00, 0x01, 0x00, 0x00]),
+    ];
+
+    for (len, header) in cases {
+        let data = vec![0xab; len];
+        let encoded = token::to_msgpack(ext_tokens(5, &data)).unwrap();
+        assert_eq!(&encoded[..header.len()], &header[..], "{}", len);
+        assert_eq!(encoded[header.len()], 5);
+        assert_eq!(encoded.len(), header.len() + 1 + len);
+        assert_eq!(msgpack::from_msgpack(&encoded).unwrap(), ext_tokens(5, &data));
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, PartialEq)]
+struct Event {
+    at: SystemTime,
+    took: Duration,
+}
+
+impl Serialize for Event {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer,
+    {
+        serializer.serialize_struct("Event", EventVisitor { value: self, state: 0 })
+    }
+}
+
+struct EventVisitor<'a> {
+    value: &'a Event,
+    state: u8,
+}
+
+impl<'a> ser::MapVisitor for EventVisitor<'a> {
+    fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+        where S: Serializer,
+    {
+        self.state += 1;
+        match self.state {
+            1 => Ok(Some(try!(serializer.serialize_struct_elt("at", self.value.at)))),
+            2 => Ok(Some(try!(serializer.serialize_struct_elt("took", self.value.took)))),
+            _ => Ok(None),
+        }
+    }
+
+    fn len(&self) -> Option<usize> {
+        Some(2)
+    }
+}
+
+impl<'a> DeserializeBorrowed<'a> for Event {
+    fn deserialize_borrowed<I>(deserializer: &mut borrowed::Deserializer<I>)
+                               -> Result<Self, token::Error>
+        where I: Iterator<Item=Token<'a>>,
+    {
+        try!(deserializer.struct_start());
+
+        let (mut at, mut took) = (None, None);
+        while let Some(field) = try!(deserializer.struct_field()) {
+            match &*field {
+                "at" => at = Some(try!(DeserializeBorrowed::deserialize_borrowed(deserializer))),
+                "took" => {
+                    took = Some(try!(DeserializeBorrowed::deserialize_borrowed(deserializer)));
+                }
+                _ => return Err(token::Error::InvalidValue(format!("unknown field {}", field))),
+            }
+        }
+
+        match (at, took) {
+            (Some(at), Some(took)) => Ok(Event { at: at, took: took }),
+            _ => Err(token::Error::InvalidValue("missing field".to_owned())),
+        }
+    }
+}
+
+#[test]
+fn test_human_readable() {
+    let event = Event {
+        at: UNIX_EPOCH + Duration::new(1_000_000_000, 250_000_000),
+        took: Duration::new(1, 500_000_000),
+    };
+
+    // JSON is human readable.
+    let text = json::serialize(&event).unwrap();
+    assert_eq!(text, r#"{"at":"2001-09-09T01:46:40.250Z","took":"1.500s"}"#);
+    assert_eq!(json::deserialize::<Event>(&text).unwrap(), event);
+
+    // MessagePack gets the timestamp extension type and a (secs, nanos) pair.
+    let encoded = msgpack::serialize(&event).unwrap();
+    let tokens = msgpack::from_msgpack(&encoded).unwrap();
+    assert!(tokens.contains(&Token::Timestamp(1_000_000_000, 250_000_000)));
+    assert!(!tokens.iter().any(|token| token.as_str().map_or(false, |s| s.ends_with('s'))));
+    assert_eq!(token::from_tokens_borrowed::<Event, _>(tokens).unwrap(), event);
+
+    // The token serializer records either form.
+    let mut serializer = token::Serializer::new().human_readable(false);
+    Duration::from_millis(1500).serialize(&mut serializer).unwrap();
+    assert_eq!(serializer.into_tokens(), vec![
+        Token::TupleStart(2),
+        Token::TupleSep, Token::U64(1),
+        Token::TupleSep, Token::U32(500_000_000),
+        Token::TupleEnd,
+    ]);
+    assert_eq!(token::to_tokens(&(UNIX_EPOCH - Duration::from_millis(1))).unwrap(),
+               vec![Token::String("1969-12-31T23:59:59.999Z".to_owned())]);
+
+    assert!(json::deserialize::<Duration>(r#""1.5""#).is_err());
+    assert_eq!(json::deserialize::<Duration>(r#""0.000001s""#).unwrap(), Duration::new(0, 1000));
+}
+
+#[test]
+fn test_msgpack_scan_find_field() {
+    let kinds = ["click", "view", "purchase"];
+    let mut input = Vec::new();
+    for id in 0..10000 {
+        let record = vec![
+            Token::MapStart(Some(3)),
+            Token::MapSep, Token::Str("id"), Token::I64(id as i64),
+            Token::MapSep, Token::Str("tags"),
+            Token::SeqStart(Some(1)), Token::SeqSep,
+            Token::MapStart(Some(1)), Token::MapSep, Token::Str("type"), Token::Str("x"),
+            Token::MapEnd,
+            Token::SeqEnd,
+            Token::MapSep, Token::Str("type"), Token::Str(kinds[id % 3]),
+            Token::MapEnd,
+        ];
+        input.extend(token::to_msgpack(record).unwrap());
+    }
+
+    let mut offsets = Vec::new();
+    for record in msgpack::Decoder::new(&input).scan() {
+        let (offset, tokens) = record.unwrap();
+        if token::find_field(tokens, "type").unwrap() == Some(Token::Str("purchase")) {
+            offsets.push(offset);
+        }
+    }
+    assert_eq!(offsets.len(), 3333);
+
+    let record = msgpack::Decoder::new(&input[offsets[1000]..]).scan().next().unwrap().unwrap();
+    assert_eq!(record.0, 0);
+    assert_eq!(token::find_field(record.1, "id").unwrap(), Some(Token::I64(3002)));
+
+    let mut truncated = msgpack::Decoder::new(&input[..input.len() - 1]).scan();
+    assert_eq!(truncated.by_ref().filter(|record| record.is_err()).count(), 1);
+    assert!(truncated.next().is_none());
+}
+
+#[test]
+fn test_msgpack_read_scan() {
+    let mut input = Vec::new();
+    for id in 0..10000 {
+        let record = vec![
+            Token::MapStart(Some(3)),
+            Token::MapSep, Token::Str("id"), Token::U64(id * 1_000_000),
+            Token::MapSep, Token::Str("tags"),
+            Token::SeqStart(Some(2)),
+            Token::SeqSep, Token::Bytes(&[1, 2, 3]),
+            Token::SeqSep, Token::F64(0.5),
+            Token::SeqEnd,
+            Token::MapSep, Token::Str("time"), Token::Timestamp(id as i64, 1),
+            Token::MapEnd,
+        ];
+        let mut encoded = Vec::new();
+        msgpack::Encoder::new(&mut encoded).timestamp_ext(true).encode(record).unwrap();
+        input.extend(encoded);
+    }
+    let expected: Vec<_> = msgpack::Decoder::new(&input)
+        .scan()
+        .map(|record| record.unwrap().0)
+        .collect();
+
+    let mut reader = Cursor::new(&input);
+    let offsets: Vec<_> = msgpack::ReadScan::new(&mut reader).map(Result::unwrap).collect();
+    assert_eq!(offsets.len(), 10000);
+    assert_eq!(offsets, expected);
+    assert_eq!(reader.position() as usize, input.len());
+
+    let record = msgpack::from_msgpack(&input[offsets[3002]..offsets[3003]]).unwrap();
+    assert_eq!(token::find_field(record, "id").unwrap(), Some(Token::I64(3_002_000_000)));
+
+    let mut truncated = msgpack::ReadScan::new(&input[..input.len() - 1]);
+    let err = truncated.by_ref().find(|record| record.is_err()).unwrap().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(err.to_string().starts_with("unexpected end of input at offset "), "{}", err);
+    assert!(truncated.next().is_none());
+
+    let err = msgpack::ReadScan::new(&[0x92, 0x01, 0xc1][..]).next().unwrap().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(err.to_string(), "unknown type byte 0xc1 at offset 2");
+}
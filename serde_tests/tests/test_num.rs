@@ -0,0 +1,221 @@
+use std::{f32, f64, i32, i64, u32, u64};
+
+extern crate serde;
+use self::serde::de::num::{Coercion, FromNumber, Number};
+use self::serde::token::{self, Token};
+use self::serde::token::borrowed::{DeserializeBorrowed, Deserializer};
+
+//////////////////////////////////////////////////////////////////////////
+
+/// Returns which of `i8`, `i16`, `i32`, `i64`, `u8`, `u16`, `u32` and `u64` accept the number.
+macro_rules! int_fits {
+    ($value:expr, $coercion:expr) => {
+        [
+            i8::from_number($value, $coercion).is_ok(),
+            i16::from_number($value, $coercion).is_ok(),
+            i32::from_number($value, $coercion).is_ok(),
+            i64::from_number($value, $coercion).is_ok(),
+            u8::from_number($value, $coercion).is_ok(),
+            u16::from_number($value, $coercion).is_ok(),
+            u32::from_number($value, $coercion).is_ok(),
+            u64::from_number($value, $coercion).is_ok(),
+        ]
+    }
+}
+
+/// The four combinations of the strict and lenient flags.
+fn coercions() -> Vec<Coercion> {
+    vec![
+        Coercion::new(),
+        Coercion::new().strict(true),
+        Coercion::new().lenient(true),
+        Coercion::new().strict(true).lenient(true),
+    ]
+}
+
+fn error<T: FromNumber>(value: Number, coercion: Coercion) -> String {
+    match T::from_number(value, coercion) {
+        Ok(_) => panic!("{} was accepted", value),
+        Err(err) => err.to_string(),
+    }
+}
+
+#[test]
+fn test_int_into_int() {
+    let (t, f) = (true, false);
+    let table = vec![
+        //                                         i8 i16 i32 i64 u8 u16 u32 u64
+        (Number::Signed(0),                       [t, t,  t,  t,  t, t,  t,  t]),
+        (Number::Unsigned(0),                     [t, t,  t,  t,  t, t,  t,  t]),
+        (Number::Signed(-1),                      [t, t,  t,  t,  f, f,  f,  f]),
+        (Number::Signed(127),                     [t, t,  t,  t,  t, t,  t,  t]),
+        (Number::Signed(128),                     [f, t,  t,  t,  t, t,  t,  t]),
+        (Number::Signed(-128),                    [t, t,  t,  t,  f, f,  f,  f]),
+        (Number::Signed(-129),                    [f, t,  t,  t,  f, f,  f,  f]),
+        (Number::Unsigned(255),                   [f, t,  t,  t,  t, t,  t,  t]),
+        (Number::Unsigned(256),                   [f, t,  t,  t,  f, t,  t,  t]),
+        (Number::Unsigned(65536),                 [f, f,  t,  t,  f, f,  t,  t]),
+        (Number::Signed(i32::MIN as i64),         [f, f,  t,  t,  f, f,  f,  f]),
+        (Number::Signed(i32::MIN as i64 - 1),     [f, f,  f,  t,  f, f,  f,  f]),
+        (Number::Unsigned(u32::MAX as u64),       [f, f,  f,  t,  f, f,  t,  t]),
+        (Number::Unsigned(u32::MAX as u64 + 1),   [f, f,  f,  t,  f, f,  f,  t]),
+        (Number::Signed(i64::MIN),                [f, f,  f,  t,  f, f,  f,  f]),
+        (Number::Signed(i64::MAX),                [f, f,  f,  t,  f, f,  f,  t]),
+        (Number::Unsigned(i64::MAX as u64),       [f, f,  f,  t,  f, f,  f,  t]),
+        (Number::Unsigned(i64::MAX as u64 + 1),   [f, f,  f,  f,  f, f,  f,  t]),
+        (Number::Unsigned(u64::MAX),              [f, f,  f,  f,  f, f,  f,  t]),
+    ];
+
+    // The flags don't change which integers fit.
+    for coercion in coercions() {
+        for &(value, expected) in &table {
+            assert_eq!(int_fits!(value, coercion), expected, "{}", value);
+        }
+    }
+
+    assert_eq!(u8::from_number(Number::Signed(200), Coercion::new()), Ok(200));
+    assert_eq!(i64::from_number(Number::Unsigned(7), Coercion::new()), Ok(7));
+    assert_eq!(u64::from_number(Number::Unsigned(u64::MAX), Coercion::new()), Ok(u64::MAX));
+    assert_eq!(isize::from_number(Number::Signed(-5), Coercion::new()), Ok(-5));
+    assert!(usize::from_number(Number::Signed(-1), Coercion::new()).is_err());
+
+    assert_eq!(error::<i64>(Number::Unsigned(u64::MAX), Coercion::new()),
+               "18446744073709551615 is out of range for i64");
+    assert_eq!(error::<usize>(Number::Signed(-1), Coercion::new()),
+               "-1 is out of range for usize");
+}
+
+#[test]
+fn test_int_into_float() {
+    let (t, f) = (true, false);
+    let table = vec![
+        //                                         f32 f64
+        (Number::Signed(0),                       [t,  t]),
+        (Number::Signed(-3),                      [t,  t]),
+        (Number::Signed(1 << 24),                 [t,  t]),
+        (Number::Signed((1 << 24) + 1),           [f,  t]),
+        (Number::Unsigned((1 << 24) + 1),         [f,  t]),
+        (Number::Signed(1 << 53),                 [t,  t]),
+        (Number::Signed((1 << 53) + 1),           [f,  f]),
+        (Number::Signed(-(1 << 53) - 1),          [f,  f]),
+        (Number::Signed(i64::MIN),                [t,  t]),
+        (Number::Signed(i64::MAX),                [f,  f]),
+        (Number::Unsigned(1 << 63),               [t,  t]),
+        (Number::Unsigned(u64::MAX),              [f,  f]),
+    ];
+
+    for coercion in coercions() {
+        let strict = coercion == Coercion::new().strict(true) ||
+                     coercion == Coercion::new().strict(true).lenient(true);
+        for &(value, exact) in &table {
+            let fits = [
+                f32::from_number(value, coercion).is_ok(),
+                f64::from_number(value, coercion).is_ok(),
+            ];
+            // Without the strict flag every integer becomes the nearest float.
+            let expected = if strict { exact } else { [true, true] };
+            assert_eq!(fits, expected, "{} with {:?}", value, coercion);
+        }
+    }
+
+    assert_eq!(f64::from_number(Number::Signed(-3), Coercion::new()), Ok(-3.0));
+    assert_eq!(f64::from_number(Number::Unsigned(u64::MAX), Coercion::new()),
+               Ok(18446744073709551615.0));
+    assert_eq!(error::<f64>(Number::Unsigned(u64::MAX), Coercion::new().strict(true)),
+               "18446744073709551615 can't be represented exactly as f64");
+}
+
+#[test]
+fn test_float_into_int() {
+    let (t, f) = (true, false);
+    let table = vec![
+        //                                         i8 i16 i32 i64 u8 u16 u32 u64
+        (Number::Float(0.0),                      [t, t,  t,  t,  t, t,  t,  t]),
+        (Number::Float(-0.0),                     [t, t,  t,  t,  t, t,  t,  t]),
+        (Number::Float(3.0),                      [t, t,  t,  t,  t, t,  t,  t]),
+        (Number::Float(3.5),                      [f, f,  f,  f,  f, f,  f,  f]),
+        (Number::Float(-1.0),                     [t, t,  t,  t,  f, f,  f,  f]),
+        (Number::Float(-0.5),                     [f, f,  f,  f,  f, f,  f,  f]),
+        (Number::Float(127.0),                    [t, t,  t,  t,  t, t,  t,  t]),
+        (Number::Float(128.0),                    [f, t,  t,  t,  t, t,  t,  t]),
+        (Number::Float(-128.0),                   [t, t,  t,  t,  f, f,  f,  f]),
+        (Number::Float(255.0),                    [f, t,  t,  t,  t, t,  t,  t]),
+        (Number::Float(256.0),                    [f, t,  t,  t,  f, t,  t,  t]),
+        (Number::Float(2147483647.0),             [f, f,  t,  t,  f, f,  t,  t]),
+        (Number::Float(2147483648.0),             [f, f,  f,  t,  f, f,  t,  t]),
+        (Number::Float(-2147483648.0),            [f, f,  t,  t,  f, f,  f,  f]),
+        (Number::Float(4294967296.0),             [f, f,  f,  t,  f, f,  f,  t]),
+        (Number::Float(-9223372036854775808.0),   [f, f,  f,  t,  f, f,  f,  f]),
+        (Number::Float(9223372036854775808.0),    [f, f,  f,  f,  f, f,  f,  t]),
+        (Number::Float(18446744073709549568.0),   [f, f,  f,  f,  f, f,  f,  t]),
+        (Number::Float(18446744073709551616.0),   [f, f,  f,  f,  f, f,  f,  f]),
+        (Number::Float(1e300),                    [f, f,  f,  f,  f, f,  f,  f]),
+        (Number::Float(f64::NAN),                 [f, f,  f,  f,  f, f,  f,  f]),
+        (Number::Float(f64::INFINITY),            [f, f,  f,  f,  f, f,  f,  f]),
+        (Number::Float(f64::NEG_INFINITY),        [f, f,  f,  f,  f, f,  f,  f]),
+    ];
+
+    for coercion in coercions() {
+        let lenient = coercion == Coercion::new().lenient(true) ||
+                      coercion == Coercion::new().strict(true).lenient(true);
+        for &(value, whole) in &table {
+            // Without the lenient flag no float becomes an integer.
+            let expected = if lenient { whole } else { [false; 8] };
+            assert_eq!(int_fits!(value, coercion), expected, "{} with {:?}", value, coercion);
+        }
+    }
+
+    let lenient = Coercion::new().lenient(true);
+    assert_eq!(i32::from_number(Number::Float(3.0), lenient), Ok(3));
+    assert_eq!(i8::from_number(Number::Float(-128.0), lenient), Ok(-128));
+    assert_eq!(i64::from_number(Number::Float(-9223372036854775808.0), lenient), Ok(i64::MIN));
+    assert_eq!(u64::from_number(Number::Float(18446744073709549568.0), lenient),
+               Ok(18446744073709549568));
+
+    assert_eq!(error::<i32>(Number::Float(3.0), Coercion::new()),
+               "3.0 is a float, which only becomes i32 with lenient coercion");
+    assert_eq!(error::<i32>(Number::Float(3.0), Coercion::new().strict(true)),
+               "3.0 is a float, which only becomes i32 with lenient coercion");
+    assert_eq!(error::<i32>(Number::Float(3.5), lenient),
+               "3.5 is not a whole number, as i32 requires");
+    assert_eq!(error::<u8>(Number::Float(256.0), lenient), "256.0 is out of range for u8");
+}
+
+#[test]
+fn test_float_into_float() {
+    for coercion in coercions() {
+        assert_eq!(f64::from_number(Number::Float(1e300), coercion), Ok(1e300));
+        assert_eq!(f32::from_number(Number::Float(0.5), coercion), Ok(0.5));
+        assert_eq!(f32::from_number(Number::Float(0.1), coercion), Ok(0.1));
+        assert_eq!(f32::from_number(Number::Float(f64::INFINITY), coercion), Ok(f32::INFINITY));
+        assert!(f32::from_number(Number::Float(f64::NAN), coercion).unwrap().is_nan());
+        assert_eq!(error::<f32>(Number::Float(1e300), coercion),
+                   "1e300 is out of range for f32");
+    }
+}
+
+#[test]
+fn test_num_tokens() {
+    fn deserialize<'a, T>(token: Token<'a>, coercion: Coercion) -> Result<T, token::Error>
+        where T: DeserializeBorrowed<'a>,
+    {
+        let mut deserializer = Deserializer::new(Some(token).into_iter()).coercion(coercion);
+        T::deserialize_borrowed(&mut deserializer)
+    }
+
+    let lenient = Coercion::new().lenient(true);
+    assert_eq!(deserialize::<u8>(Token::I64(200), Coercion::new()).unwrap(), 200);
+    assert_eq!(deserialize::<i32>(Token::F64(3.0), lenient).unwrap(), 3);
+    assert_eq!(deserialize::<f64>(Token::U8(3), Coercion::new()).unwrap(), 3.0);
+    assert_eq!(deserialize::<i64>(Token::U64(u64::MAX), lenient).unwrap_err().to_string(),
+               "Invalid value: 18446744073709551615 is out of range for i64");
+    assert_eq!(deserialize::<i32>(Token::F32(3.5), lenient).unwrap_err().to_string(),
+               "Invalid value: 3.5 is not a whole number, as i32 requires");
+
+    // A bool never becomes a number.
+    for coercion in coercions() {
+        assert!(deserialize::<u8>(Token::Bool(false), coercion).is_err());
+        assert!(deserialize::<i64>(Token::Bool(true), coercion).is_err());
+        assert!(deserialize::<f64>(Token::Bool(true), coercion).is_err());
+    }
+}
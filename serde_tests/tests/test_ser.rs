@@ -1,10 +1,14 @@
 use std::collections::BTreeMap;
+use std::fmt;
 use std::net;
 use std::path::{Path, PathBuf};
 use std::str;
 
 use token::{self, Token};
 
+extern crate serde;
+use self::serde::ser::impls::ViaDisplay;
+
 //////////////////////////////////////////////////////////////////////////
 
 #[derive(Serialize)]
@@ -28,6 +32,20 @@ enum Enum {
     Map { a: i32, b: i32 },
 }
 
+#[derive(Serialize)]
+enum Config {
+    Settings(BTreeMap<String, i32>),
+    Layers(Vec<i32>, BTreeMap<String, i32>),
+}
+
+struct Celsius(f64);
+
+impl fmt::Display for Celsius {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}°C", self.0)
+    }
+}
+
 //////////////////////////////////////////////////////////////////////////
 
 declare_ser_tests! {
@@ -143,6 +161,20 @@ declare_ser_tests! {
                 Token::SeqEnd,
             Token::SeqEnd,
         ],
+        vec![Some(1), None, Some(3)] => &[
+            Token::SeqStart(Some(3)),
+                Token::SeqSep,
+                Token::Option(true),
+                Token::I32(1),
+
+                Token::SeqSep,
+                Token::Option(false),
+
+                Token::SeqSep,
+                Token::Option(true),
+                Token::I32(3),
+            Token::SeqEnd,
+        ],
     }
     test_tuple {
         (1,) => &[
@@ -163,6 +195,15 @@ declare_ser_tests! {
                 Token::I32(3),
             Token::TupleEnd,
         ],
+        (&5, &"x") => &[
+            Token::TupleStart(2),
+                Token::TupleSep,
+                Token::I32(5),
+
+                Token::TupleSep,
+                Token::Str("x"),
+            Token::TupleEnd,
+        ],
     }
     test_btreemap {
         btreemap![1 => 2] => &[
@@ -262,6 +303,36 @@ declare_ser_tests! {
             Token::EnumMapEnd,
         ],
     }
+    test_enum_compound_payload {
+        Config::Settings(btreemap!["a".to_owned() => 1, "b".to_owned() => 2]) => &[
+            Token::EnumNewType("Config", "Settings"),
+            Token::MapStart(Some(2)),
+                Token::MapSep,
+                Token::Str("a"),
+                Token::I32(1),
+
+                Token::MapSep,
+                Token::Str("b"),
+                Token::I32(2),
+            Token::MapEnd,
+        ],
+        Config::Layers(vec![1], btreemap!["c".to_owned() => 3]) => &[
+            Token::EnumSeqStart("Config", "Layers", Some(2)),
+                Token::EnumSeqSep,
+                Token::SeqStart(Some(1)),
+                    Token::SeqSep,
+                    Token::I32(1),
+                Token::SeqEnd,
+
+                Token::EnumSeqSep,
+                Token::MapStart(Some(1)),
+                    Token::MapSep,
+                    Token::Str("c"),
+                    Token::I32(3),
+                Token::MapEnd,
+            Token::EnumSeqEnd,
+        ],
+    }
     test_box {
         Box::new(0i32) => &[Token::I32(0)],
     }
@@ -327,3 +398,12 @@ fn test_cannot_serialize_paths() {
         &[Token::Str("Hello �World")],
         token::Error::InvalidValue("Path contains invalid UTF-8 characters".to_owned()));
 }
+
+#[test]
+fn test_via_display() {
+    let celsius = Celsius(21.5);
+    let formatted = format!("{}", celsius);
+
+    token::assert_ser_tokens(&ViaDisplay(&celsius), &[Token::Str(&formatted)]);
+    token::assert_ser_tokens(&ViaDisplay(&celsius), &[Token::Str("21.5°C")]);
+}
@@ -0,0 +1,3298 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::i64;
+use std::{u64, usize};
+
+extern crate serde;
+use self::serde::bytes::Chunked;
+use self::serde::token::{self, DuplicateKeyPolicy, Token, Value};
+use self::serde::token::borrowed::{self, DeserializeBorrowed, DeserializeInto};
+use self::serde::token::intern::{Entry, Interned, Interner};
+use self::serde::ser::{self, Serialize, Serializer};
+use self::serde::ser::impls::{LazySeq, StrMap, StrMapVisitor, Tagged};
+use self::serde::token::json;
+use self::serde::token::par;
+use self::serde::token::rename::Name;
+use self::serde::token::shape::Shape;
+use self::serde::token::skip::{self, SkipRule};
+use self::serde::token::testing::{AssertSerializer, assert_ser_tokens, assert_snapshot};
+use self::serde::token::testing::to_rust_literal;
+use self::serde::token::testing::arbitrary;
+
+//////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_core_scalars() {
+    use self::serde::token::core;
+
+    let mut ser = core::Serializer::new();
+    5u8.serialize(&mut ser).unwrap();
+    'x'.serialize(&mut ser).unwrap();
+    "abc".serialize(&mut ser).unwrap();
+    Some(1.5f64).serialize(&mut ser).unwrap();
+    ().serialize(&mut ser).unwrap();
+
+    assert_eq!(ser.into_tokens(), vec![
+        core::Token::U8(5),
+        core::Token::Char('x'),
+        core::Token::Str("abc"),
+        core::Token::Option(true),
+        core::Token::F64(1.5),
+        core::Token::Unit,
+    ]);
+
+    // The core types are the ones re-exported from `token`.
+    let tokens: Vec<Token> = core::to_tokens(&true).unwrap();
+    assert_eq!(tokens, vec![Token::Bool(true)]);
+}
+
+#[test]
+fn test_to_tokens() {
+    let value = btreemap![
+        "a" => vec![1, 2],
+        "b" => vec![]
+    ];
+
+    assert_eq!(
+        token::to_tokens(&value).unwrap(),
+        vec![
+            Token::MapStart(Some(2)),
+                Token::MapSep,
+                Token::Str("a"),
+                Token::SeqStart(Some(2)),
+                    Token::SeqSep,
+                    Token::I32(1),
+
+                    Token::SeqSep,
+                    Token::I32(2),
+                Token::SeqEnd,
+
+                Token::MapSep,
+                Token::Str("b"),
+                Token::SeqStart(Some(0)),
+                Token::SeqEnd,
+            Token::MapEnd,
+        ]
+    );
+}
+
+#[test]
+fn test_entry_tokens() {
+    assert_eq!(token::entry_tokens(&"a", &1i32).unwrap(), vec![Token::Str("a"), Token::I32(1)]);
+
+    let map = btreemap!["x" => vec![1u8], "y" => vec![]];
+    let entries: Vec<Vec<Token>> = map.iter()
+        .map(|(k, v)| token::entry_tokens(k, v).unwrap())
+        .collect();
+    assert_eq!(entries, vec![
+        vec![
+            Token::Str("x"),
+            Token::SeqStart(Some(1)),
+                Token::SeqSep,
+                Token::U8(1),
+            Token::SeqEnd,
+        ],
+        vec![Token::Str("y"), Token::SeqStart(Some(0)), Token::SeqEnd],
+    ]);
+}
+
+#[test]
+fn test_lazy_seq() {
+    let items = vec![Point { x: 1, y: 2 }, Point { x: 3, y: 4 }];
+
+    let lazy = LazySeq::new(|| items.iter()).with_len(items.len());
+    assert_eq!(token::to_tokens(&lazy).unwrap(), token::to_tokens(&items).unwrap());
+
+    let lazy = LazySeq::new(|| (0..3).filter(|i| i % 2 == 0));
+    assert_eq!(token::to_tokens(&lazy).unwrap(), vec![
+        Token::SeqStart(None),
+            Token::SeqSep,
+            Token::I32(0),
+            Token::SeqSep,
+            Token::I32(2),
+        Token::SeqEnd,
+    ]);
+}
+
+#[test]
+fn test_str_map() {
+    let tree = btreemap![
+        "a".to_owned() => vec![1],
+        "b".to_owned() => vec![]
+    ];
+    assert_eq!(token::to_tokens(&StrMap(&tree)).unwrap(), token::to_tokens(&tree).unwrap());
+    assert_eq!(token::to_tokens(&StrMap(&tree)).unwrap()[2], Token::Str("a"));
+
+    let mut hash = HashMap::new();
+    hash.insert("only".to_owned(), Some(Point { x: 1, y: 2 }));
+    assert_eq!(token::to_tokens(&StrMap(&hash)).unwrap(), token::to_tokens(&hash).unwrap());
+
+    // The visitor takes any iterator of string keys.
+    let pairs = vec![("x", 1), ("y", 2)];
+    let visitor = StrMapVisitor::new(pairs.into_iter(), None);
+    let mut serializer = token::Serializer::new();
+    ser::Serializer::serialize_map(&mut serializer, visitor).unwrap();
+    assert_eq!(serializer.into_tokens()[0], Token::MapStart(None));
+}
+
+#[test]
+fn test_range_map_tokens() {
+    let map = btreemap![1 => "a", 2 => "b", 3 => "c", 4 => "d", 5 => "e"];
+
+    assert_eq!(token::range_map_tokens(&map, &2, &4).unwrap(), vec![
+        Token::MapStart(Some(2)),
+            Token::MapSep,
+            Token::I32(2),
+            Token::Str("b"),
+
+            Token::MapSep,
+            Token::I32(3),
+            Token::Str("c"),
+        Token::MapEnd,
+    ]);
+
+    let all = token::range_map_tokens(&map, &0, &10).unwrap();
+    assert_eq!(all, token::to_tokens(&map).unwrap());
+
+    let empty = vec![Token::MapStart(Some(0)), Token::MapEnd];
+    assert_eq!(token::range_map_tokens(&map, &6, &10).unwrap(), empty);
+    assert_eq!(token::range_map_tokens(&map, &4, &2).unwrap(), empty);
+}
+
+#[test]
+fn test_owned_and_borrowed_tokens_compare_equal() {
+    assert_eq!(Token::Str("a"), Token::String("a".to_owned()));
+    assert_eq!(Token::ByteBuf(vec![1]), Token::Bytes(b"\x01"));
+    assert!(Token::Str("a") != Token::String("b".to_owned()));
+    assert!(Token::Str("a") != Token::Bytes(b"a"));
+}
+
+//////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_to_dot() {
+    let value = btreemap![
+        "a" => btreemap!["x" => vec![1]],
+        "b" => btreemap![]
+    ];
+
+    let dot = token::to_dot(token::to_tokens(&value).unwrap());
+
+    assert!(dot.starts_with("digraph tokens {\n"));
+    assert!(dot.ends_with("}\n"));
+    assert!(dot.contains("[label=\"map\"]"));
+    assert!(dot.contains("[label=\"seq\"]"));
+    assert!(dot.contains("[label=\"\\\"a\\\"\"]"));
+    assert!(dot.contains("[label=\"\\\"x\\\"\"]"));
+    assert!(dot.contains("[label=\"1\"]"));
+
+    // outer map: 2 keys + 2 values, inner map: 1 key + 1 value, seq: 1 element
+    assert_eq!(dot.matches(" -> ").count(), 7);
+}
+
+#[test]
+fn test_to_dot_labels_fields_and_indices() {
+    let dot = token::to_dot(vec![
+        Token::StructStart("Point", Some(2)),
+            Token::StructSep,
+            Token::Str("x"),
+            Token::I32(1),
+
+            Token::StructSep,
+            Token::Str("y"),
+            Token::SeqStart(Some(1)),
+                Token::SeqSep,
+                Token::Option(false),
+            Token::SeqEnd,
+        Token::StructEnd,
+    ]);
+
+    assert_eq!(
+        dot,
+        "digraph tokens {\n\
+         \x20   n0 [label=\"Point\"];\n\
+         \x20   n1 [label=\"1\"];\n\
+         \x20   n0 -> n1 [label=\"x\"];\n\
+         \x20   n2 [label=\"seq\"];\n\
+         \x20   n3 [label=\"None\"];\n\
+         \x20   n2 -> n3 [label=\"0\"];\n\
+         \x20   n0 -> n2 [label=\"y\"];\n\
+         }\n"
+    );
+}
+
+//////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_normalize_numbers() {
+    let small: Vec<u8> = vec![1, 2, 255];
+    let wide: Vec<i64> = vec![1, 2, 255];
+
+    let small = token::normalize_numbers(token::to_tokens(&small).unwrap()).collect::<Vec<_>>();
+    let wide = token::normalize_numbers(token::to_tokens(&wide).unwrap()).collect::<Vec<_>>();
+
+    assert_eq!(small, wide);
+    assert_eq!(small[2], Token::I64(1));
+}
+
+#[test]
+fn test_normalize_numbers_keeps_large_u64() {
+    let tokens = vec![
+        Token::U64(u64::MAX),
+        Token::U64(i64::MAX as u64),
+        Token::U64(i64::MAX as u64 + 1),
+        Token::I8(-1),
+        Token::F32(1.5),
+        Token::Str("a"),
+    ];
+
+    assert_eq!(
+        token::normalize_numbers(tokens.clone()).collect::<Vec<_>>(),
+        vec![
+            Token::U64(u64::MAX),
+            Token::I64(i64::MAX),
+            Token::U64(i64::MAX as u64 + 1),
+            Token::I64(-1),
+            Token::F32(1.5),
+            Token::Str("a"),
+        ]
+    );
+
+    assert_eq!(
+        token::normalize_numbers(tokens).widen_floats().collect::<Vec<_>>()[4],
+        Token::F64(1.5)
+    );
+}
+
+//////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_interned_replay() {
+    let rows = vec![
+        btreemap!["id".to_owned() => 1, "count".to_owned() => 10],
+        btreemap!["id".to_owned() => 2, "count".to_owned() => 20],
+        btreemap!["id".to_owned() => 3, "count".to_owned() => 30],
+    ];
+    let tokens = token::to_tokens(&rows).unwrap();
+
+    let interned = Interned::from_tokens(tokens.clone());
+
+    assert_eq!(interned.iter().collect::<Vec<_>>(), tokens);
+    assert_eq!(interned.interner().strings(), &["count".to_owned(), "id".to_owned()]);
+    assert_eq!(
+        interned.entries().iter().filter(|entry| match **entry {
+            Entry::Key(_) => true,
+            Entry::Token(_) => false,
+        }).count(),
+        6
+    );
+}
+
+#[test]
+fn test_interned_only_replaces_keys() {
+    let tokens = vec![
+        Token::MapStart(Some(1)),
+            Token::MapSep,
+            Token::String("a".to_owned()),
+            Token::String("a".to_owned()),
+        Token::MapEnd,
+    ];
+
+    let interned = Interned::from_tokens(tokens.clone());
+
+    assert_eq!(
+        interned.entries(),
+        &[
+            Entry::Token(Token::MapStart(Some(1))),
+            Entry::Token(Token::MapSep),
+            Entry::Key(0),
+            Entry::Token(Token::String("a".to_owned())),
+            Entry::Token(Token::MapEnd),
+        ]
+    );
+    assert_eq!(interned.iter().collect::<Vec<_>>(), tokens);
+}
+
+#[test]
+fn test_interned_struct_fields() {
+    let mut tokens = vec![Token::SeqStart(Some(2))];
+    for id in 0..2 {
+        tokens.extend(vec![
+            Token::SeqSep,
+            Token::StructStart("Row", Some(2)),
+                Token::StructSep,
+                Token::Str("id"),
+                Token::I32(id),
+
+                Token::StructSep,
+                Token::Str("name"),
+                Token::Str("id"),
+            Token::StructEnd,
+        ]);
+    }
+    tokens.push(Token::SeqEnd);
+
+    let interned = Interned::from_tokens(tokens.clone());
+
+    assert_eq!(interned.iter().collect::<Vec<_>>(), tokens);
+    assert_eq!(interned.interner().strings(), &["id".to_owned(), "name".to_owned()]);
+    assert_eq!(&interned.entries()[2..9], &[
+        Entry::Token(Token::StructStart("Row", Some(2))),
+        Entry::Token(Token::StructSep),
+        Entry::Key(0),
+        Entry::Token(Token::I32(0)),
+        Entry::Token(Token::StructSep),
+        Entry::Key(1),
+        Entry::Token(Token::Str("id")),
+    ]);
+}
+
+#[test]
+fn test_interner() {
+    let mut interner = Interner::new();
+    assert!(interner.is_empty());
+
+    assert_eq!(interner.intern("a"), 0);
+    assert_eq!(interner.intern("b"), 1);
+    assert_eq!(interner.intern("a"), 0);
+
+    assert_eq!(interner.len(), 2);
+    assert_eq!(interner.get(1), Some("b"));
+    assert_eq!(interner.get(2), None);
+}
+
+//////////////////////////////////////////////////////////////////////////
+
+fn assert_split_matches(values: &[Vec<i32>], chunks: usize) {
+    let expected_tokens = token::to_tokens(values).unwrap();
+    let expected_json = token::to_json(expected_tokens.clone()).unwrap();
+
+    let split = par::split_seq(values, chunks);
+    assert_eq!(split.len(), values.len());
+    assert_eq!(split.seq_start(), expected_tokens[0]);
+
+    let parts = (0 .. split.chunks().len())
+        .map(|i| split.chunk_tokens(i).unwrap())
+        .collect::<Vec<_>>();
+
+    let json_parts = parts.iter()
+        .map(|part| token::to_json(part.clone()).unwrap())
+        .collect::<Vec<_>>();
+
+    assert_eq!(par::concat_tokens(parts), expected_tokens);
+    assert_eq!(par::concat_json(&json_parts), expected_json);
+}
+
+#[test]
+fn test_split_seq() {
+    let values = (0 .. 10).map(|i| vec![i; i as usize % 3]).collect::<Vec<_>>();
+
+    assert_split_matches(&[], 0);
+    assert_split_matches(&[], 4);
+    assert_split_matches(&values[..1], 1);
+    assert_split_matches(&values[..1], 4);
+    assert_split_matches(&values, 1);
+    assert_split_matches(&values, 3);
+    assert_split_matches(&values, 10);
+    assert_split_matches(&values, 20);
+
+    assert_eq!(par::split_seq(&values, 3).chunks().len(), 3);
+    assert_eq!(par::split_seq(&values, 20).chunks().len(), 10);
+}
+
+#[test]
+fn test_empty_compounds() {
+    fn assert_two_tokens<T: ?Sized>(value: &T, start: Token<'static>, end: Token<'static>)
+        where T: Serialize,
+    {
+        assert_eq!(token::to_tokens(value).unwrap(), vec![start, end]);
+    }
+
+    let empty: &[i32] = &[];
+    assert_two_tokens(&Vec::<i32>::new(), Token::SeqStart(Some(0)), Token::SeqEnd);
+    assert_two_tokens(empty, Token::SeqStart(Some(0)), Token::SeqEnd);
+    assert_two_tokens(&[0i32; 0], Token::SeqArrayStart(0), Token::SeqEnd);
+    assert_two_tokens(&BTreeSet::<i32>::new(), Token::SeqStart(Some(0)), Token::SeqEnd);
+    assert_two_tokens(&HashSet::<i32>::new(), Token::SeqStart(Some(0)), Token::SeqEnd);
+    assert_two_tokens(&BTreeMap::<i32, i32>::new(), Token::MapStart(Some(0)), Token::MapEnd);
+    assert_two_tokens(&HashMap::<i32, i32>::new(), Token::MapStart(Some(0)), Token::MapEnd);
+}
+
+#[test]
+fn test_option_vec() {
+    // A present but empty sequence is not the same as an absent one.
+    assert_ser_tokens(&Some(Vec::<i32>::new()), vec![
+        Token::Option(true),
+        Token::SeqStart(Some(0)),
+        Token::SeqEnd,
+    ]);
+    assert_ser_tokens(&None::<Vec<i32>>, vec![Token::Option(false)]);
+    assert_ser_tokens(&Some(vec![1i32]), vec![
+        Token::Option(true),
+        Token::SeqStart(Some(1)),
+            Token::SeqSep,
+            Token::I32(1),
+        Token::SeqEnd,
+    ]);
+
+    assert_eq!(token::to_json(token::to_tokens(&Some(Vec::<i32>::new())).unwrap()).unwrap(), "[]");
+    assert_eq!(token::to_json(token::to_tokens(&None::<Vec<i32>>).unwrap()).unwrap(), "null");
+}
+
+#[test]
+fn test_tagged() {
+    use self::serde::token::binary;
+
+    assert_ser_tokens(&Tagged("MyInt", &5i32), vec![Token::TypeTag("MyInt"), Token::I32(5)]);
+
+    // Consumers that don't know about the tag treat it like a wrapper around the value.
+    let tokens = token::to_tokens(&vec![Tagged("MyInt", &5i32)]).unwrap();
+    assert_eq!(token::to_json(tokens.clone()).unwrap(), "[5]");
+    assert_eq!(binary::from_binary(&binary::to_binary(tokens.clone())).unwrap(), tokens);
+    assert_eq!(token::from_tokens_borrowed::<Vec<i32>, _>(tokens).unwrap(), vec![5]);
+}
+
+//////////////////////////////////////////////////////////////////////////
+
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+impl Serialize for Point {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer,
+    {
+        serializer.serialize_struct("Point", PointVisitor { value: self, state: 0 })
+    }
+}
+
+struct PointVisitor<'a> {
+    value: &'a Point,
+    state: u8,
+}
+
+impl<'a> ser::MapVisitor for PointVisitor<'a> {
+    fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+        where S: Serializer,
+    {
+        self.state += 1;
+        match self.state {
+            1 => Ok(Some(try!(serializer.serialize_struct_elt("x", self.value.x)))),
+            2 => Ok(Some(try!(serializer.serialize_struct_elt("y", self.value.y)))),
+            _ => Ok(None),
+        }
+    }
+
+    fn len(&self) -> Option<usize> {
+        Some(2)
+    }
+}
+
+struct Account {
+    id: u64,
+    owner: String,
+    tags: Vec<&'static str>,
+}
+
+serializable_struct!(Account { id, owner, tags });
+
+#[test]
+fn test_serializable_struct() {
+    let account = Account {
+        id: 7,
+        owner: "ada".to_owned(),
+        tags: vec!["admin"],
+    };
+    assert_eq!(token::to_tokens(&account).unwrap(), vec![
+        Token::StructStart("Account", Some(3)),
+            Token::StructSep,
+            Token::Str("id"),
+            Token::U64(7),
+
+            Token::StructSep,
+            Token::Str("owner"),
+            Token::Str("ada"),
+
+            Token::StructSep,
+            Token::Str("tags"),
+            Token::SeqStart(Some(1)),
+                Token::SeqSep,
+                Token::Str("admin"),
+            Token::SeqEnd,
+        Token::StructEnd,
+    ]);
+}
+
+/// A tuple variant whose `Serialize` implementation declares `declared` fields but always
+/// serializes two.
+struct Pair {
+    declared: usize,
+}
+
+impl Serialize for Pair {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer,
+    {
+        serializer.serialize_tuple_variant("Shape", 0, "Pair", PairVisitor {
+            declared: self.declared,
+            state: 0,
+        })
+    }
+}
+
+struct PairVisitor {
+    declared: usize,
+    state: u8,
+}
+
+impl ser::SeqVisitor for PairVisitor {
+    fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+        where S: Serializer,
+    {
+        self.state += 1;
+        match self.state {
+            1 => Ok(Some(try!(serializer.serialize_tuple_variant_elt(1)))),
+            2 => Ok(Some(try!(serializer.serialize_tuple_variant_elt(2)))),
+            _ => Ok(None),
+        }
+    }
+
+    fn len(&self) -> Option<usize> {
+        Some(self.declared)
+    }
+}
+
+#[test]
+fn test_variant_len() {
+    let tokens = token::to_tokens(&Pair { declared: 2 }).unwrap();
+    assert_eq!(tokens, vec![
+        Token::EnumSeqStart("Shape", "Pair", Some(2)),
+            Token::EnumSeqSep,
+            Token::I32(1),
+
+            Token::EnumSeqSep,
+            Token::I32(2),
+        Token::EnumSeqEnd,
+    ]);
+    assert_eq!(token::validate(tokens), Ok(()));
+
+    let err = token::validate(vec![
+        Token::EnumSeqStart("Shape", "Pair", Some(3)),
+            Token::EnumSeqSep,
+            Token::I32(1),
+        Token::EnumSeqEnd,
+    ]).unwrap_err();
+    assert_eq!(err.to_string(), "token #3: expected 3 elements, found 1");
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "variant Shape::Pair declared 3 fields but serialized 2")]
+fn test_variant_len_mismatch() {
+    token::to_tokens(&Pair { declared: 3 }).unwrap();
+}
+
+#[test]
+fn test_assert_serializer_tuple() {
+    assert_ser_tokens(&(5, "x"), vec![
+        Token::TupleStart(2),
+            Token::TupleSep,
+            Token::I32(5),
+
+            Token::TupleSep,
+            Token::Str("x"),
+        Token::TupleEnd,
+    ]);
+
+    assert_ser_tokens(&((), (1u8,)), vec![
+        Token::TupleStart(2),
+            Token::TupleSep,
+            Token::Unit,
+
+            Token::TupleSep,
+            Token::TupleStart(1),
+                Token::TupleSep,
+                Token::U8(1),
+            Token::TupleEnd,
+        Token::TupleEnd,
+    ]);
+}
+
+#[test]
+fn test_assert_serializer_struct() {
+    let mut ser = AssertSerializer::expecting(vec![
+        Token::StructStart("Point", Some(2)),
+            Token::StructSep,
+            Token::Str("x"),
+            Token::I32(1),
+
+            Token::StructSep,
+            Token::Str("y"),
+            Token::I32(-1),
+        Token::StructEnd,
+    ]);
+    Point { x: 1, y: -1 }.serialize(&mut ser).unwrap();
+    assert_eq!(ser.recorded().len(), 8);
+    ser.finish();
+}
+
+#[test]
+#[should_panic(expected = "token #6: expected I32(2) but got I32(-1); \
+                           remaining expectations: [StructEnd]")]
+fn test_assert_serializer_mismatch() {
+    assert_ser_tokens(&Point { x: 1, y: -1 }, vec![
+        Token::StructStart("Point", Some(2)),
+            Token::StructSep,
+            Token::Str("x"),
+            Token::I32(1),
+
+            Token::StructSep,
+            Token::Str("y"),
+            Token::I32(2),
+        Token::StructEnd,
+    ]);
+}
+
+#[test]
+#[should_panic(expected = "token #2: expected SeqSep but the value is done")]
+fn test_assert_serializer_finish() {
+    let mut ser = AssertSerializer::expecting(vec![
+        Token::SeqStart(Some(0)),
+        Token::SeqEnd,
+        Token::SeqSep,
+    ]);
+    Vec::<i32>::new().serialize(&mut ser).unwrap();
+    ser.finish();
+}
+
+#[test]
+fn test_assert_serializer_recording() {
+    let mut ser = AssertSerializer::recording();
+    Point { x: 1, y: 2 }.serialize(&mut ser).unwrap();
+    assert_eq!(ser.finish(), token::to_tokens(&Point { x: 1, y: 2 }).unwrap());
+}
+
+//////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_check_shape() {
+    let shape = Shape::Map(
+        Box::new(Shape::Scalar),
+        Box::new(Shape::Seq(Box::new(Shape::Scalar))));
+
+    let value = btreemap![
+        "a" => vec![Some(1), None],
+        "b" => vec![]
+    ];
+    assert_eq!(token::check_shape(token::to_tokens(&value).unwrap(), &shape), Ok(()));
+
+    let points = vec![Point { x: 1, y: 2 }];
+    let shape = Shape::Seq(Box::new(Shape::AnyStruct));
+    assert_eq!(token::check_shape(token::to_tokens(&points).unwrap(), &shape), Ok(()));
+}
+
+#[test]
+fn test_check_shape_mismatch() {
+    let shape = Shape::Map(Box::new(Shape::Scalar), Box::new(Shape::Scalar));
+
+    let value = btreemap!["a" => vec![1]];
+    let err = token::check_shape(token::to_tokens(&value).unwrap(), &shape).unwrap_err();
+    assert_eq!(err.path(), "map.value");
+    assert_eq!(err.to_string(), "at map.value: expected a scalar, found SeqStart(Some(1))");
+
+    let value = btreemap!["a" => btreemap![1 => vec![1, 2, 3]]];
+    let shape = Shape::Map(
+        Box::new(Shape::Scalar),
+        Box::new(Shape::Map(
+            Box::new(Shape::Scalar),
+            Box::new(Shape::Seq(Box::new(Shape::AnyStruct))))));
+    let err = token::check_shape(token::to_tokens(&value).unwrap(), &shape).unwrap_err();
+    assert_eq!(err.path(), "map.value.map.value.seq[0]");
+    assert_eq!(err.expected(), "a struct");
+
+    let err = token::check_shape(vec![Token::I32(1), Token::I32(2)], &Shape::Scalar).unwrap_err();
+    assert_eq!(err.to_string(), "at (root): expected the end of the stream, found I32(2)");
+}
+
+//////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_pretty_tokens() {
+    let value = vec![Some(Point { x: 1, y: 2 })];
+
+    assert_eq!(
+        token::testing::pretty_tokens(token::to_tokens(&value).unwrap()),
+        concat!(
+            "SeqStart(Some(1))\n",
+            "    SeqSep\n",
+            "    Option(true)\n",
+            "    StructStart(\"Point\", Some(2))\n",
+            "        StructSep\n",
+            "        Str(\"x\")\n",
+            "        I32(1)\n",
+            "        StructSep\n",
+            "        Str(\"y\")\n",
+            "        I32(2)\n",
+            "    StructEnd\n",
+            "SeqEnd\n"));
+}
+
+#[test]
+fn test_snapshot_points() {
+    let points = vec![Point { x: 0, y: 0 }, Point { x: -3, y: 7 }];
+    assert_snapshot("points", token::to_tokens(&points).unwrap());
+}
+
+#[test]
+fn test_snapshot_nested_btreemap() {
+    let value = btreemap![
+        "a" => btreemap![],
+        "b" => btreemap![1 => vec!["x"], 2 => vec!["y", "z"]]
+    ];
+    assert_snapshot("nested_btreemap", token::to_tokens(&value).unwrap());
+}
+
+#[test]
+fn test_to_rust_literal() {
+    // The expected literal is both compared as text and compiled back into the stream.
+    let animals = vec![Animal::Dog, Animal::Cat(Point { x: 1, y: -2 })];
+    let tokens = token::to_tokens(&animals).unwrap();
+    assert_eq!(to_rust_literal(tokens.clone()), include_str!("snapshots/animals.rs"));
+    let literal: Vec<Token> = include!("snapshots/animals.rs");
+    assert_eq!(literal, tokens);
+
+    let tokens = vec![
+        Token::Str("say \"hi\"\n\\\u{0}"),
+        Token::Char('\''),
+        Token::F32(0.1),
+        Token::F64(-1e300),
+        Token::F64(::std::f64::NEG_INFINITY),
+        Token::Bytes(b"ab"),
+        Token::ByteBuf(vec![]),
+        Token::BigNum("123456789012345678901234567890".to_owned()),
+    ];
+    assert_eq!(to_rust_literal(tokens.clone()), r#"vec![
+    Token::Str("say \"hi\"\n\\\0"),
+    Token::Char('\''),
+    Token::F32(0.1),
+    Token::F64(-1e300),
+    Token::F64(::std::f64::NEG_INFINITY),
+    Token::Bytes(&[97, 98]),
+    Token::ByteBuf(vec![]),
+    Token::BigNum("123456789012345678901234567890".to_owned()),
+]
+"#);
+    let literal: Vec<Token> = vec![
+        Token::Str("say \"hi\"\n\\\0"),
+        Token::Char('\''),
+        Token::F32(0.1),
+        Token::F64(-1e300),
+        Token::F64(::std::f64::NEG_INFINITY),
+        Token::Bytes(&[97, 98]),
+        Token::ByteBuf(vec![]),
+        Token::BigNum("123456789012345678901234567890".to_owned()),
+    ];
+    assert_eq!(literal, tokens);
+}
+
+/// Prints the literals of the corpus fixtures and of the streams above, to refresh the
+/// expectations after an intentional change: `cargo test print_rust_literals -- --ignored
+/// --nocapture`.
+#[test]
+#[ignore]
+fn print_rust_literals() {
+    let animals = vec![Animal::Dog, Animal::Cat(Point { x: 1, y: -2 })];
+    println!("// animals\n{}", to_rust_literal(token::to_tokens(&animals).unwrap()));
+    for fixture in token::testing::corpus() {
+        println!("// {}\n{}", fixture.name, to_rust_literal(fixture.tokens));
+    }
+}
+
+#[test]
+fn test_nested_maps_mixed_keys() {
+    // The key types of the outer and the inner map are inferred independently.
+    let value: BTreeMap<isize, BTreeMap<String, isize>> = btreemap![
+        1 => btreemap!["a".to_owned() => 2]
+    ];
+    let tokens = token::to_tokens(&value).unwrap();
+    assert_eq!(tokens, vec![
+        Token::MapStart(Some(1)),
+            Token::MapSep,
+            Token::Isize(1),
+            Token::MapStart(Some(1)),
+                Token::MapSep,
+                Token::Str("a"),
+                Token::Isize(2),
+            Token::MapEnd,
+        Token::MapEnd,
+    ]);
+
+    let back: BTreeMap<isize, BTreeMap<String, isize>> =
+        borrowed::from_tokens_borrowed(tokens).unwrap();
+    assert_eq!(back, value);
+
+    // And the other way around.
+    let value: BTreeMap<String, BTreeMap<u8, Vec<bool>>> = btreemap![
+        "x".to_owned() => btreemap![7 => vec![true]],
+        "y".to_owned() => btreemap![]
+    ];
+    let tokens = token::to_tokens(&value).unwrap();
+    assert_eq!(tokens[2], Token::Str("x"));
+    assert_eq!(tokens[5], Token::U8(7));
+    let back: BTreeMap<String, BTreeMap<u8, Vec<bool>>> =
+        borrowed::from_tokens_borrowed(tokens).unwrap();
+    assert_eq!(back, value);
+}
+
+//////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_value_from_tokens() {
+    let points = vec![Point { x: 1, y: 2 }];
+    let value = Value::from_tokens(token::to_tokens(&points).unwrap()).unwrap();
+
+    assert_eq!(value, value!([{"x": 1, "y": 2}]));
+
+    assert_eq!(Value::from_tokens(vec![Token::U64(u64::MAX)]).unwrap(), Value::U64(u64::MAX));
+    assert_eq!(Value::from_tokens(vec![Token::U8(1)]).unwrap(), Value::I64(1));
+    assert!(Value::from_tokens(vec![Token::SeqStart(None)]).is_err());
+}
+
+#[test]
+fn test_value_macro() {
+    let text = r#"{"a": [1, 2], "b": null, "c": {"nested": true, "empty": [], "none": {}},
+                   "d": [-1.5, "x\"y", false, [[]]], "e": 18446744073709551615}"#;
+    let expected = Value::from_tokens(json::from_str(text).unwrap()).unwrap();
+    let value = value!({
+        "a": [1, 2],
+        "b": null,
+        "c": {"nested": true, "empty": [], "none": {},},
+        "d": [-1.5, "x\"y", false, [[]]],
+        "e": u64::MAX,
+    });
+    assert_eq!(value, expected);
+
+    // Rust expressions are converted with their `Serialize` implementation.
+    let point = Point { x: 1, y: 2 };
+    let key = 'k';
+    let value = value!([point, Some("s"), 1 + 1, vec![1u8]]);
+    assert_eq!(value, Value::Seq(vec![
+        value!({"x": 1, "y": 2}),
+        Value::String("s".to_owned()),
+        Value::I64(2),
+        value!([1]),
+    ]));
+    assert_eq!(value!({(key): [None::<i32>]}), value!({"k": [null]}));
+    assert_eq!(value!(3), Value::I64(3));
+}
+
+#[test]
+fn test_validate() {
+    assert_eq!(token::validate(token::to_tokens(&Point { x: 1, y: 2 }).unwrap()), Ok(()));
+    assert_eq!(token::validate(vec![Token::Option(true), Token::Unit]), Ok(()));
+
+    let err = token::validate(vec![
+        Token::SeqStart(Some(2)),
+            Token::SeqSep,
+            Token::I32(1),
+        Token::SeqEnd,
+    ]).unwrap_err();
+    assert_eq!(err.to_string(), "token #3: expected 2 elements, found 1");
+
+    let err = token::validate(vec![
+        Token::SeqStart(None),
+            Token::SeqSep,
+            Token::I32(1),
+        Token::TupleEnd,
+    ]).unwrap_err();
+    assert_eq!(err.to_string(), "token #3: expected SeqSep or SeqEnd, found TupleEnd");
+
+    let err = token::validate(vec![
+        Token::StructStart("Point", None),
+            Token::StructSep,
+            Token::I32(1),
+    ]).unwrap_err();
+    assert_eq!(err.index(), 2);
+
+    let err = token::validate(vec![Token::Unit, Token::Unit]).unwrap_err();
+    assert_eq!(err.to_string(), "token #1: expected the end of the stream, found Unit");
+}
+
+#[test]
+fn test_is_canonical() {
+    let canonical = vec![
+        Token::MapStart(Some(2)),
+            Token::MapSep,
+            Token::Str("a"),
+            Token::SeqStart(Some(2)),
+                Token::SeqSep,
+                Token::I8(-1),
+                Token::SeqSep,
+                Token::U16(300),
+            Token::SeqEnd,
+            Token::MapSep,
+            Token::Str("b"),
+            Token::U8(1),
+        Token::MapEnd,
+    ];
+    assert_eq!(token::is_canonical(canonical.clone()), Ok(()));
+    assert_eq!(token::canonicalize(canonical.clone()).unwrap(), canonical);
+
+    let unsorted = vec![
+        Token::MapStart(Some(2)),
+            Token::MapSep,
+            Token::Str("b"),
+            Token::U8(1),
+            Token::MapSep,
+            Token::Str("a"),
+            Token::U8(2),
+        Token::MapEnd,
+    ];
+    let err = token::is_canonical(unsorted.clone()).unwrap_err();
+    assert_eq!(err.to_string(), "token #5: map key Str(\"a\") is out of order after Str(\"b\")");
+    assert_eq!(token::canonicalize(unsorted).unwrap(), vec![
+        Token::MapStart(Some(2)),
+            Token::MapSep,
+            Token::Str("a"),
+            Token::U8(2),
+            Token::MapSep,
+            Token::Str("b"),
+            Token::U8(1),
+        Token::MapEnd,
+    ]);
+
+    let err = token::is_canonical(vec![Token::SeqStart(None), Token::SeqEnd]).unwrap_err();
+    assert_eq!(err.to_string(), "token #0: compound value without a length");
+
+    let err = token::is_canonical(token::to_tokens(&Point { x: 1, y: 2 }).unwrap()).unwrap_err();
+    assert_eq!(err.to_string(), "token #3: I32(1) is not in its narrowest representation U8(1)");
+
+    // Keys are ordered by value, not by type.
+    let keys = vec![
+        Token::MapStart(None),
+            Token::MapSep,
+            Token::I64(300),
+            Token::Unit,
+            Token::MapSep,
+            Token::I64(-5),
+            Token::Unit,
+        Token::MapEnd,
+    ];
+    assert_eq!(token::canonicalize(keys).unwrap(), vec![
+        Token::MapStart(Some(2)),
+            Token::MapSep,
+            Token::I8(-5),
+            Token::Unit,
+            Token::MapSep,
+            Token::U16(300),
+            Token::Unit,
+        Token::MapEnd,
+    ]);
+
+    let err = token::canonicalize(vec![
+        Token::MapStart(Some(2)),
+            Token::MapSep,
+            Token::U8(1),
+            Token::Unit,
+            Token::MapSep,
+            Token::I64(1),
+            Token::Unit,
+        Token::MapEnd,
+    ]).unwrap_err();
+    assert_eq!(err.message(), "duplicate map key U8(1)");
+}
+
+#[test]
+fn test_guard_depth() {
+    fn nested(depth: usize) -> Vec<Token<'static>> {
+        let mut tokens = Vec::new();
+        for _ in 0..depth {
+            tokens.extend(vec![Token::SeqStart(Some(1)), Token::SeqSep]);
+        }
+        tokens.push(Token::Unit);
+        for _ in 0..depth {
+            tokens.push(Token::SeqEnd);
+        }
+        tokens
+    }
+
+    let tokens = nested(3);
+    let guarded: Result<Vec<Token>, _> = token::guard_depth(tokens.clone(), 3).collect();
+    assert_eq!(guarded.unwrap(), tokens);
+
+    let mut guarded = token::guard_depth(nested(4), 3);
+    let err = guarded.by_ref().filter_map(Result::err).next().unwrap();
+    assert_eq!(err.index(), 6);
+    assert_eq!(err.to_string(), "token #6: nesting exceeds the maximum depth of 3");
+    assert_eq!(guarded.next(), None);
+
+    // Depth is the nesting at any one point, not the number of compound values.
+    let points = vec![Point { x: 1, y: 2 }, Point { x: 3, y: 4 }];
+    let tokens = token::to_tokens(&points).unwrap();
+    assert!(token::guard_depth(tokens.clone(), 2).all(|token| token.is_ok()));
+    assert!(token::guard_depth(tokens, 1).any(|token| token.is_err()));
+}
+
+#[test]
+fn test_limit_size() {
+    fn check(tokens: Vec<Token>, max_elems: usize) -> Result<Vec<Token>, token::limit::LimitError> {
+        token::limit_size(tokens, max_elems).collect()
+    }
+
+    let map = btreemap![1 => vec![1, 2, 3], 2 => vec![]];
+    let tokens = token::to_tokens(&map).unwrap();
+    assert_eq!(check(tokens.clone(), 3).unwrap(), tokens);
+
+    let err = check(tokens, 2).unwrap_err();
+    assert_eq!(err.index(), 3);
+    assert_eq!(err.declared_len(), Some(3));
+    assert_eq!(err.to_string(), "token #3: length 3 exceeds the maximum of 2 elements");
+
+    // A sequence of unknown length is cut off at the separator of the first extra element.
+    let unsized_seq = vec![
+        Token::SeqStart(None),
+            Token::SeqSep,
+            Token::TupleStart(3),
+                Token::TupleSep, Token::Unit,
+                Token::TupleSep, Token::Unit,
+                Token::TupleSep, Token::Unit,
+            Token::TupleEnd,
+            Token::SeqSep,
+            Token::Unit,
+        Token::SeqEnd,
+    ];
+    assert_eq!(check(unsized_seq.clone(), 2).unwrap(), unsized_seq);
+
+    let mut limited = token::limit_size(unsized_seq, 1);
+    let err = limited.by_ref().filter_map(Result::err).next().unwrap();
+    assert_eq!(err.index(), 10);
+    assert_eq!(err.declared_len(), None);
+    assert_eq!(err.to_string(), "token #10: more than the maximum of 1 elements");
+    assert_eq!(limited.next(), None);
+
+    // A map that announces fewer entries than it has is caught the same way.
+    let err = check(vec![
+        Token::MapStart(Some(1)),
+            Token::MapSep, Token::I32(1), Token::Unit,
+            Token::MapSep, Token::I32(2), Token::Unit,
+        Token::MapEnd,
+    ], 1).unwrap_err();
+    assert_eq!(err.index(), 4);
+}
+
+#[test]
+fn test_reject_dup_keys() {
+    fn check(tokens: Vec<Token>) -> Result<Vec<Token>, token::Error> {
+        token::reject_dup_keys(tokens).collect()
+    }
+
+    let unique = btreemap!["a" => btreemap!["a" => 1], "b" => btreemap!["a" => 2]];
+    let tokens = token::to_tokens(&unique).unwrap();
+    assert_eq!(check(tokens.clone()).unwrap(), tokens);
+
+    let err = check(vec![
+        Token::MapStart(Some(2)),
+            Token::MapSep,
+            Token::Str("a"),
+            Token::I32(1),
+
+            Token::MapSep,
+            Token::Str("a"),
+            Token::I32(2),
+        Token::MapEnd,
+    ]).unwrap_err();
+    assert_eq!(err, token::Error::InvalidValue("duplicate map key Str(\"a\")".to_owned()));
+
+    // The keys of a map inside a sequence belong to that map alone.
+    let tokens = vec![
+        Token::MapStart(Some(2)),
+            Token::MapSep,
+            Token::Str("x"),
+            Token::SeqStart(Some(1)),
+                Token::SeqSep,
+                Token::MapStart(Some(1)),
+                    Token::MapSep,
+                    Token::Str("y"),
+                    Token::Unit,
+                Token::MapEnd,
+            Token::SeqEnd,
+
+            Token::MapSep,
+            Token::Str("y"),
+            Token::Str("x"),
+        Token::MapEnd,
+    ];
+    assert_eq!(check(tokens.clone()).unwrap(), tokens);
+}
+
+#[test]
+fn test_duplicate_key_policy() {
+    type Nested = BTreeMap<String, BTreeMap<String, i64>>;
+
+    fn nested(policy: DuplicateKeyPolicy, input: &str) -> Result<Nested, token::Error> {
+        let tokens = json::from_str(input).unwrap();
+        let mut deserializer = borrowed::Deserializer::new(tokens.into_iter())
+            .duplicate_keys(policy);
+        let value = try!(DeserializeBorrowed::deserialize_borrowed(&mut deserializer));
+        try!(deserializer.end());
+        Ok(value)
+    }
+
+    let input = r#"{"a": {"x": 1}, "b": {}, "a": {"y": 2}}"#;
+    let tokens = json::from_str(input).unwrap();
+    let value = |policy| Value::from_tokens_with(tokens.clone(), policy);
+
+    let first = value!({"a": {"x": 1}, "b": {}});
+    let last = value!({"a": {"y": 2}, "b": {}});
+    let err = token::Error::InvalidValue("duplicate key \"a\" at token 13".to_owned());
+
+    assert_eq!(value(DuplicateKeyPolicy::FirstWins), Ok(first));
+    assert_eq!(value(DuplicateKeyPolicy::LastWins), Ok(last.clone()));
+    assert_eq!(value(DuplicateKeyPolicy::Error), Err(err.clone()));
+    assert_eq!(Value::from_tokens(tokens.clone()), Ok(last));
+
+    // The same policies apply to maps deserialized from tokens.
+    assert_eq!(nested(DuplicateKeyPolicy::FirstWins, input).unwrap()["a"],
+               btreemap!["x".to_owned() => 1]);
+    assert_eq!(nested(DuplicateKeyPolicy::LastWins, input).unwrap()["a"],
+               btreemap!["y".to_owned() => 2]);
+    assert_eq!(nested(DuplicateKeyPolicy::Error, input), Err(err));
+
+    // Keys only clash within their own map.
+    let input = r#"{"a": {"a": 1, "b": 2}, "b": {"a": 3}}"#;
+    assert!(nested(DuplicateKeyPolicy::Error, input).is_ok());
+    let err = nested(DuplicateKeyPolicy::Error, r#"{"a": {"k": 1, "k": 2}}"#).unwrap_err();
+    assert_eq!(err, token::Error::InvalidValue("duplicate key \"k\" at token 8".to_owned()));
+
+    // And to struct fields stored with `set_field`.
+    let input = r#"{"name": "Ada", "age": 36, "age": 37}"#;
+    let person = |policy| {
+        let tokens = json::from_str(input).unwrap();
+        let mut deserializer = borrowed::Deserializer::new(tokens.into_iter())
+            .duplicate_keys(policy);
+        Person::deserialize_borrowed(&mut deserializer)
+    };
+    assert_eq!(person(DuplicateKeyPolicy::FirstWins).unwrap().age, 36);
+    assert_eq!(person(DuplicateKeyPolicy::LastWins).unwrap().age, 37);
+    assert_eq!(person(DuplicateKeyPolicy::Error).unwrap_err(),
+               token::Error::InvalidValue("duplicate key \"age\" at token 8".to_owned()));
+}
+
+enum Animal {
+    Dog,
+    Cat(Point),
+}
+
+impl Serialize for Animal {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer,
+    {
+        match *self {
+            Animal::Dog => serializer.serialize_unit_variant("Animal", 0, "Dog"),
+            Animal::Cat(ref point) => {
+                serializer.serialize_newtype_variant("Animal", 1, "Cat", point)
+            }
+        }
+    }
+}
+
+/// A recursive enum whose payloads are themselves `Tree`s, through `Box`.
+enum Tree {
+    Leaf(i32),
+    Node(Box<Tree>, Box<Tree>),
+}
+
+impl Serialize for Tree {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer,
+    {
+        struct NodeVisitor<'a> {
+            left: &'a Box<Tree>,
+            right: &'a Box<Tree>,
+            state: u8,
+        }
+
+        impl<'a> ser::SeqVisitor for NodeVisitor<'a> {
+            fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+                where S: Serializer,
+            {
+                self.state += 1;
+                match self.state {
+                    1 => Ok(Some(try!(serializer.serialize_tuple_variant_elt(self.left)))),
+                    2 => Ok(Some(try!(serializer.serialize_tuple_variant_elt(self.right)))),
+                    _ => Ok(None),
+                }
+            }
+
+            fn len(&self) -> Option<usize> {
+                Some(2)
+            }
+        }
+
+        match *self {
+            Tree::Leaf(ref value) => serializer.serialize_newtype_variant("Tree", 0, "Leaf", value),
+            Tree::Node(ref left, ref right) => {
+                let visitor = NodeVisitor { left: left, right: right, state: 0 };
+                serializer.serialize_tuple_variant("Tree", 1, "Node", visitor)
+            }
+        }
+    }
+}
+
+#[test]
+fn test_recursive_enum() {
+    let leaf = |value| Box::new(Tree::Leaf(value));
+    let tree = Tree::Node(leaf(1), Box::new(Tree::Node(leaf(2), leaf(3))));
+
+    let tokens = token::to_tokens(&tree).unwrap();
+    assert_eq!(tokens, vec![
+        Token::EnumSeqStart("Tree", "Node", Some(2)),
+            Token::EnumSeqSep,
+            Token::EnumNewType("Tree", "Leaf"),
+            Token::I32(1),
+
+            Token::EnumSeqSep,
+            Token::EnumSeqStart("Tree", "Node", Some(2)),
+                Token::EnumSeqSep,
+                Token::EnumNewType("Tree", "Leaf"),
+                Token::I32(2),
+
+                Token::EnumSeqSep,
+                Token::EnumNewType("Tree", "Leaf"),
+                Token::I32(3),
+            Token::EnumSeqEnd,
+        Token::EnumSeqEnd,
+    ]);
+    assert_eq!(token::validate(tokens), Ok(()));
+}
+
+#[test]
+fn test_renamed() {
+    let animals = vec![Animal::Dog, Animal::Cat(Point { x: 1, y: 2 })];
+    let tokens = token::Renamed::new(&animals)
+        .rename_type("Animal", "Beast")
+        .rename_variant("Animal", "Cat", "Lion")
+        .to_tokens()
+        .unwrap();
+    assert_eq!(tokens, vec![
+        Token::SeqStart(Some(2)),
+            Token::SeqSep,
+            Token::EnumUnit("Beast", "Dog"),
+
+            Token::SeqSep,
+            Token::EnumNewType("Beast", "Lion"),
+            Token::StructStart("Point", Some(2)),
+                Token::StructSep,
+                Token::Str("x"),
+                Token::I32(1),
+
+                Token::StructSep,
+                Token::Str("y"),
+                Token::I32(2),
+            Token::StructEnd,
+        Token::SeqEnd,
+    ]);
+
+    // Owned replacements live in an interner that outlives the stream.
+    let mut names = Interner::new();
+    for name in &["Animal", "Point"] {
+        names.intern(&name.to_uppercase());
+    }
+    let tokens = token::to_tokens(&Animal::Cat(Point { x: 0, y: 0 })).unwrap();
+    let renamed: Vec<Token> = token::rename(tokens, |name| {
+        match name {
+            Name::Type(name) => {
+                names.strings().iter().find(|s| s.eq_ignore_ascii_case(name)).map_or(name, |s| s)
+            }
+            Name::Variant(_, variant) => variant,
+        }
+    }).collect();
+    assert_eq!(renamed[0], Token::EnumNewType("ANIMAL", "Cat"));
+    assert_eq!(renamed[1], Token::StructStart("POINT", Some(2)));
+}
+
+/// `Animal` again and an enum with a tuple variant, with `Serialize` generated by
+/// `serializable_enum!`.
+mod generated {
+    use super::Point;
+
+    pub enum Animal {
+        Dog,
+        Cat(Point),
+    }
+
+    serializable_enum!(Animal { Dog, Cat(point) });
+
+    pub enum Message {
+        Quit,
+        Move(i32, i32),
+        Write(String),
+    }
+
+    serializable_enum!(Message { Quit, Move(x, y), Write(text), });
+}
+
+#[test]
+fn test_serializable_enum() {
+    let hand_written = vec![Animal::Dog, Animal::Cat(Point { x: 1, y: 2 })];
+    let generated = vec![generated::Animal::Dog, generated::Animal::Cat(Point { x: 1, y: 2 })];
+    assert_eq!(token::to_tokens(&generated).unwrap(), token::to_tokens(&hand_written).unwrap());
+
+    let messages = vec![
+        generated::Message::Quit,
+        generated::Message::Move(3, -4),
+        generated::Message::Write("hi".to_owned()),
+    ];
+    assert_eq!(token::to_tokens(&messages).unwrap(), vec![
+        Token::SeqStart(Some(3)),
+            Token::SeqSep,
+            Token::EnumUnit("Message", "Quit"),
+
+            Token::SeqSep,
+            Token::EnumSeqStart("Message", "Move", Some(2)),
+                Token::EnumSeqSep,
+                Token::I32(3),
+                Token::EnumSeqSep,
+                Token::I32(-4),
+            Token::EnumSeqEnd,
+
+            Token::SeqSep,
+            Token::EnumNewType("Message", "Write"),
+            Token::Str("hi"),
+        Token::SeqEnd,
+    ]);
+}
+
+/// Fieldless enums with `Serialize` and `Deserialize` generated by `c_like_enum!`.
+mod c_like {
+    #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum Color {
+        Red = 1,
+        Green = 2,
+        Blue = 4,
+    }
+
+    c_like_enum!(Color as name { Red, Green, Blue });
+
+    #[derive(Debug, PartialEq)]
+    pub enum Level {
+        Low = -1,
+        High = 10,
+    }
+
+    c_like_enum!(Level as number { Low, High, });
+}
+
+#[test]
+fn test_c_like_enum() {
+    use self::c_like::{Color, Level};
+    use self::serde::de::Deserialize;
+    use self::serde::de::value::{self, ValueDeserializer};
+
+    fn from<T, V>(v: V) -> Result<T, value::Error>
+        where T: Deserialize,
+              V: ValueDeserializer<value::Error>,
+    {
+        T::deserialize(&mut v.into_deserializer())
+    }
+
+    assert_eq!(token::to_tokens(&Color::Green).unwrap(), vec![Token::Str("Green")]);
+    assert_eq!(from::<Color, _>("Blue"), Ok(Color::Blue));
+    assert_eq!(from::<Color, _>(4u8), Ok(Color::Blue));
+
+    assert_eq!(token::to_tokens(&Level::Low).unwrap(), vec![Token::I64(-1)]);
+    assert_eq!(from::<Level, _>(-1i64), Ok(Level::Low));
+    assert_eq!(from::<Level, _>("High"), Ok(Level::High));
+
+    // A set of members is a sequence of their encodings.
+    let set: BTreeSet<Color> = vec![Color::Blue, Color::Red].into_iter().collect();
+    assert_eq!(token::to_tokens(&set).unwrap(), vec![
+        Token::SeqStart(Some(2)),
+            Token::SeqSep,
+            Token::Str("Red"),
+            Token::SeqSep,
+            Token::Str("Blue"),
+        Token::SeqEnd,
+    ]);
+    assert_eq!(from::<BTreeSet<Color>, _>(vec!["Blue", "Red"]), Ok(set));
+
+    assert_eq!(from::<Color, _>("Purple"),
+               Err(value::Error::UnknownVariant("Purple".to_owned())));
+    assert_eq!(from::<Color, _>(3u64),
+               Err(value::Error::InvalidValue("unknown discriminant 3 for Color".to_owned())));
+    assert!(from::<Level, _>(u64::MAX).is_err());
+}
+
+fn profile(nickname: Option<&'static str>, tags: Vec<&'static str>, visits: u32, home: Point)
+           -> Vec<Token<'static>> {
+    let mut tokens = vec![
+        Token::StructStart("Profile", Some(4)),
+            Token::StructSep,
+            Token::Str("nickname"),
+    ];
+    tokens.extend(token::to_tokens(&nickname).unwrap());
+    tokens.extend(vec![Token::StructSep, Token::Str("tags")]);
+    tokens.extend(token::to_tokens(&tags).unwrap());
+    tokens.extend(vec![Token::StructSep, Token::Str("visits"), Token::U32(visits)]);
+    tokens.extend(vec![Token::StructSep, Token::Str("home")]);
+    tokens.extend(token::to_tokens(&home).unwrap());
+    tokens.push(Token::StructEnd);
+    tokens
+}
+
+fn profile_rules() -> Vec<SkipRule> {
+    vec![
+        SkipRule::new("Profile", "nickname", skip::is_none),
+        SkipRule::new("Profile", "tags", skip::is_empty_seq),
+        SkipRule::new("Profile", "visits", skip::is_zero),
+        // Looks inside the nested struct: the origin is the default home.
+        SkipRule::new("Profile", "home", |value| {
+            value.iter().filter(|token| **token == Token::I32(0)).count() == 2
+        }),
+    ]
+}
+
+#[test]
+fn test_skip_if() {
+    let tokens = profile(None, vec![], 0, Point { x: 0, y: 0 });
+    assert_eq!(skip::skip_if(tokens, profile_rules()).collect::<Vec<_>>(), vec![
+        Token::StructStart("Profile", Some(0)),
+        Token::StructEnd,
+    ]);
+
+    let tokens = profile(Some("ada"), vec![], 3, Point { x: 0, y: 1 });
+    let json = token::to_json(skip::skip_if(tokens, profile_rules())).unwrap();
+    assert_eq!(json, r#"{"nickname":"ada","visits":3,"home":{"x":0,"y":1}}"#);
+
+    let tokens = profile(Some("ada"), vec!["x"], 3, Point { x: 1, y: 1 });
+    assert_eq!(skip::skip_if(tokens.clone(), profile_rules()).collect::<Vec<_>>(), tokens);
+}
+
+#[test]
+fn test_skip_if_nested() {
+    // Rules apply to structs at any depth, and a skipped field of an inner struct only changes
+    // the length of that struct.
+    let points = vec![Point { x: 0, y: 5 }, Point { x: 2, y: 0 }];
+    let rules = vec![
+        SkipRule::new("Point", "x", skip::is_zero),
+        SkipRule::new("Point", "y", skip::is_zero),
+    ];
+    let tokens: Vec<Token> = skip::skip_if(token::to_tokens(&points).unwrap(), rules).collect();
+    assert_eq!(tokens, vec![
+        Token::SeqStart(Some(2)),
+            Token::SeqSep,
+            Token::StructStart("Point", Some(1)),
+                Token::StructSep,
+                Token::Str("y"),
+                Token::I32(5),
+            Token::StructEnd,
+
+            Token::SeqSep,
+            Token::StructStart("Point", Some(1)),
+                Token::StructSep,
+                Token::Str("x"),
+                Token::I32(2),
+            Token::StructEnd,
+        Token::SeqEnd,
+    ]);
+    assert_eq!(token::validate(tokens), Ok(()));
+}
+
+#[test]
+fn test_skip_if_recounts() {
+    use self::serde::token::binary;
+
+    // Readers of binary formats trust the announced lengths, so the output of `skip_if` has to
+    // announce the fields that are left, even when the input didn't announce any.
+    let tokens = vec![
+        Token::SeqStart(Some(2)),
+        Token::SeqSep,
+        Token::StructStart("Profile", None),
+        Token::StructSep, Token::Str("nickname"), Token::Option(false),
+        Token::StructSep, Token::Str("home"),
+        Token::StructStart("Point", Some(2)),
+        Token::StructSep, Token::Str("x"), Token::I32(0),
+        Token::StructSep, Token::Str("y"), Token::I32(1),
+        Token::StructEnd,
+        Token::StructEnd,
+        Token::SeqSep,
+        Token::StructStart("Profile", Some(1)),
+        Token::StructSep, Token::Str("nickname"), Token::Option(true), Token::Str("ada"),
+        Token::StructEnd,
+        Token::SeqEnd,
+    ];
+    let rules = vec![
+        SkipRule::new("Profile", "nickname", skip::is_none),
+        SkipRule::new("Point", "x", skip::is_zero),
+    ];
+    let skipped: Vec<Token> = skip::skip_if(tokens, rules).collect();
+    assert_eq!(skipped[2], Token::StructStart("Profile", Some(1)));
+    assert_eq!(skipped[5], Token::StructStart("Point", Some(1)));
+    assert_eq!(token::validate(skipped.clone()), Ok(()));
+    let bytes = binary::to_binary(skipped.clone());
+    assert_eq!(binary::from_binary(&bytes).unwrap(), skipped);
+}
+
+#[test]
+fn test_recounting_buffer() {
+    let mut buffer = token::RecountingBuffer::new(Token::MapStart(Some(5)));
+    assert!(buffer.is_empty());
+    assert!(!buffer.push(Token::MapSep));
+    buffer.extend(vec![
+        Token::Str("a"),
+        Token::SeqStart(None), Token::SeqSep, Token::I32(1), Token::SeqSep, Token::I32(2),
+        Token::SeqEnd,
+    ]);
+    assert_eq!(buffer.len(), 1);
+    assert!(!buffer.is_complete());
+    assert!(buffer.push(Token::MapEnd));
+    assert_eq!(buffer.finish()[0], Token::MapStart(Some(1)));
+
+    let mut buffer = token::RecountingBuffer::new(Token::TupleStart(3));
+    buffer.extend(vec![Token::TupleSep, Token::Unit, Token::TupleEnd]);
+    assert_eq!(buffer.finish(), vec![Token::TupleStart(1), Token::TupleSep, Token::Unit,
+                                     Token::TupleEnd]);
+}
+
+#[test]
+fn test_to_path_index() {
+    let mut users = BTreeMap::new();
+    users.insert("ada", vec![Point { x: 1, y: 2 }]);
+    users.insert("bob smith", vec![]);
+    let value = (users, Some(Animal::Cat(Point { x: 3, y: 4 })), btreemap![7 => 'c']);
+
+    let index = token::to_path_index(token::to_tokens(&value).unwrap()).unwrap();
+    assert_eq!(index, vec![
+        ("[0].ada[0].x".to_owned(), Token::I32(1)),
+        ("[0].ada[0].y".to_owned(), Token::I32(2)),
+        ("[1].Cat.x".to_owned(), Token::I32(3)),
+        ("[1].Cat.y".to_owned(), Token::I32(4)),
+        ("[2][\"7\"]".to_owned(), Token::Char('c')),
+    ]);
+
+    assert_eq!(token::to_path_index(vec![Token::Bool(true)]).unwrap(),
+               vec![(String::new(), Token::Bool(true))]);
+    assert!(token::to_path_index(vec![Token::SeqStart(None), Token::SeqSep]).is_err());
+}
+
+#[test]
+fn test_flatten_keys() {
+    let value = btreemap![
+        "server" => btreemap![
+            "listen" => btreemap!["host" => vec![127, 0, 0, 1], "port" => vec![8080]],
+            "workers" => btreemap![]
+        ],
+        "debug" => btreemap!["level" => btreemap!["value" => vec![]]]
+    ];
+    let flat = token::flatten_keys(token::to_tokens(&value).unwrap(), ".").unwrap();
+    assert_eq!(flat, vec![
+        Token::MapStart(Some(4)),
+            Token::MapSep,
+            Token::Str("debug.level.value"),
+            Token::SeqStart(Some(0)),
+            Token::SeqEnd,
+
+            Token::MapSep,
+            Token::Str("server.listen.host"),
+            Token::SeqStart(Some(4)),
+                Token::SeqSep,
+                Token::I32(127),
+                Token::SeqSep,
+                Token::I32(0),
+                Token::SeqSep,
+                Token::I32(0),
+                Token::SeqSep,
+                Token::I32(1),
+            Token::SeqEnd,
+
+            Token::MapSep,
+            Token::Str("server.listen.port"),
+            Token::SeqStart(Some(1)),
+                Token::SeqSep,
+                Token::I32(8080),
+            Token::SeqEnd,
+
+            // Empty maps are kept as values.
+            Token::MapSep,
+            Token::Str("server.workers"),
+            Token::MapStart(Some(0)),
+            Token::MapEnd,
+        Token::MapEnd,
+    ]);
+
+    // Struct fields and integer map keys are flattened too, `Some` around a struct is dropped.
+    let value = btreemap![1 => Some(Point { x: 5, y: 6 })];
+    let flat = token::flatten_keys(token::to_tokens(&value).unwrap(), "/").unwrap();
+    assert_eq!(token::to_json(flat).unwrap(), r#"{"1/x":5,"1/y":6}"#);
+
+    let err = token::flatten_keys(token::to_tokens(&vec![1]).unwrap(), ".").unwrap_err();
+    assert_eq!(err, token::Error::InvalidValue(
+        "expected a map or struct, found SeqStart(Some(1))".to_owned()));
+
+    let value = btreemap![vec![1] => 2];
+    let err = token::flatten_keys(token::to_tokens(&value).unwrap(), ".").unwrap_err();
+    assert_eq!(err, token::Error::InvalidValue(
+        "map key must be a scalar, found SeqStart(Some(1))".to_owned()));
+}
+
+#[test]
+fn test_chunked_bytes() {
+    let tokens = token::to_tokens(&Chunked::new(b"hello", 2)).unwrap();
+    assert_eq!(tokens, vec![
+        Token::SeqStart(Some(3)),
+            Token::SeqSep,
+            Token::Bytes(b"he"),
+            Token::SeqSep,
+            Token::Bytes(b"ll"),
+            Token::SeqSep,
+            Token::Bytes(b"o"),
+        Token::SeqEnd,
+    ]);
+
+    let tokens = token::to_tokens(&Chunked::new(b"", 2)).unwrap();
+    assert_eq!(tokens, vec![Token::SeqStart(Some(0)), Token::SeqEnd]);
+}
+
+#[test]
+fn test_compose() {
+    use self::serde::token::compose::{Array, Object};
+
+    // A recording kept around as owned tokens, a live value and a literal scalar.
+    let cached: Vec<Token<'static>> = token::to_tokens(&Point { x: 1, y: 2 }).unwrap();
+    let items = vec![Some(1), None];
+    let tokens = Object::named("Response")
+        .field("user", cached)
+        .field_value("items", &items)
+        .field("at", vec![Token::Timestamp(0, 0)])
+        .finish()
+        .unwrap();
+    assert_eq!(tokens[0], Token::StructStart("Response", Some(3)));
+    assert_eq!(token::validate(tokens.clone()), Ok(()));
+    assert_eq!(token::to_json(tokens).unwrap(),
+               r#"{"user":{"x":1,"y":2},"items":[1,null],"at":"1970-01-01T00:00:00Z"}"#);
+
+    let tokens = Array::new()
+        .element(Object::new().field_value("a", &1).finish().unwrap())
+        .element_value("b")
+        .finish()
+        .unwrap();
+    assert_eq!(token::validate(tokens.clone()), Ok(()));
+    assert_eq!(token::to_json(tokens).unwrap(), r#"[{"a":1},"b"]"#);
+
+    let err = Object::new()
+        .field_value("a", &1)
+        .field("b", vec![Token::SeqStart(Some(1)), Token::SeqSep, Token::Unit])
+        .finish()
+        .unwrap_err();
+    assert_eq!(err, token::Error::InvalidValue(
+        "field \"b\": token #2: expected SeqSep or SeqEnd, found the end of the stream"
+            .to_owned()));
+
+    let err = Array::new().element(vec![Token::Unit, Token::Unit]).finish().unwrap_err();
+    assert_eq!(err, token::Error::InvalidValue(
+        "element 0: token #1: expected the end of the stream, found Unit".to_owned()));
+}
+
+#[test]
+fn test_to_columns() {
+    let accounts = vec![
+        Account { id: 1, owner: "ada".to_owned(), tags: vec![] },
+        Account { id: 2, owner: "bob".to_owned(), tags: vec!["admin"] },
+        Account { id: 3, owner: "cy".to_owned(), tags: vec!["a", "b"] },
+    ];
+    let columns = token::to_columns(token::to_tokens(&accounts).unwrap()).unwrap();
+
+    assert_eq!(columns.keys().collect::<Vec<_>>(), vec!["id", "owner", "tags"]);
+    assert_eq!(columns["id"], vec![
+        vec![Token::U64(1)],
+        vec![Token::U64(2)],
+        vec![Token::U64(3)],
+    ]);
+    assert_eq!(columns["owner"], vec![
+        vec![Token::Str("ada")],
+        vec![Token::Str("bob")],
+        vec![Token::Str("cy")],
+    ]);
+    assert_eq!(columns["tags"], vec![
+        vec![Token::SeqStart(Some(0)), Token::SeqEnd],
+        vec![Token::SeqStart(Some(1)), Token::SeqSep, Token::Str("admin"), Token::SeqEnd],
+        vec![
+            Token::SeqStart(Some(2)),
+            Token::SeqSep, Token::Str("a"),
+            Token::SeqSep, Token::Str("b"),
+            Token::SeqEnd,
+        ],
+    ]);
+
+    assert!(token::to_columns(token::to_tokens(&Vec::<Account>::new()).unwrap())
+        .unwrap()
+        .is_empty());
+
+    let mut tokens = token::to_tokens(&vec![Point { x: 1, y: 2 }]).unwrap();
+    tokens.pop();
+    tokens.extend(vec![
+        Token::SeqSep,
+        Token::StructStart("Point", Some(1)),
+        Token::StructSep, Token::Str("x"), Token::I32(3),
+        Token::StructEnd,
+        Token::SeqEnd,
+    ]);
+    assert_eq!(token::to_columns(tokens).unwrap_err(), token::Error::InvalidValue(
+        r#"struct at index 1 has the fields ["x"], expected ["x", "y"]"#.to_owned()));
+
+    let err = token::to_columns(token::to_tokens(&vec![1]).unwrap()).unwrap_err();
+    assert_eq!(err, token::Error::InvalidValue(
+        "expected a struct at index 0, found I32(1)".to_owned()));
+}
+
+#[test]
+fn test_arbitrary_json_round_trip() {
+    arbitrary::check(300, 0, |rng| {
+        let value = arbitrary::value(rng, 4, 5);
+        let text = token::to_json(arbitrary::tokens(&value)).unwrap();
+        let tokens = try!(json::from_str(&text).map_err(|err| format!("{}: {}", err, text)));
+        let back = try!(Value::from_tokens(tokens).map_err(|err| err.to_string()));
+
+        if back == value {
+            Ok(())
+        } else {
+            Err(format!("{:?} came back as {:?}", value, back))
+        }
+    });
+}
+
+#[test]
+fn test_arbitrary_msgpack_round_trip() {
+    arbitrary::check(300, 2000, |rng| {
+        let value = arbitrary::value(rng, 4, 5);
+        let bytes = token::to_msgpack(arbitrary::tokens(&value)).unwrap();
+        let tokens = try!(token::from_msgpack(&bytes).map_err(|err| err.to_string()));
+        let back = try!(Value::from_tokens(tokens).map_err(|err| err.to_string()));
+
+        if back == value {
+            Ok(())
+        } else {
+            Err(format!("{:?} came back as {:?}", value, back))
+        }
+    });
+}
+
+#[test]
+fn test_arbitrary_streams_validate() {
+    arbitrary::check(300, 1000, |rng| {
+        let value = arbitrary::value(rng, 4, 5);
+        try!(token::validate(arbitrary::tokens(&value)).map_err(|err| err.to_string()));
+
+        let points = (0..rng.below(4))
+            .map(|i| Some(Point { x: i as i32, y: rng.next_u64() as i32 }))
+            .collect::<Vec<_>>();
+        token::validate(token::to_tokens(&points).unwrap()).map_err(|err| err.to_string())
+    });
+}
+
+#[test]
+#[should_panic(expected = "property failed for seed 7: ")]
+fn test_arbitrary_reports_seed() {
+    arbitrary::check(10, 5, |rng| {
+        if rng.clone().next_u64() == arbitrary::Rng::new(7).next_u64() {
+            Err("found it".to_owned())
+        } else {
+            Ok(())
+        }
+    });
+}
+
+//////////////////////////////////////////////////////////////////////////
+
+struct Id([u8; 16]);
+
+impl Serialize for Id {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer,
+    {
+        serde::bytes::Bytes::from(&self.0).serialize(serializer)
+    }
+}
+
+#[test]
+fn test_fixed_size_id_as_bytes() {
+    let id = Id([0x67, 0xe5, 0x50, 0x44, 0x10, 0xb1, 0x42, 0x6f,
+                 0x92, 0x47, 0xbb, 0x68, 0x0e, 0x5f, 0xe0, 0xc8]);
+
+    let tokens = token::to_tokens(&id).unwrap();
+    assert_eq!(tokens, vec![Token::Bytes(&id.0)]);
+
+    let hex = tokens[0].as_bytes().unwrap().iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    assert_eq!(hex, "67e5504410b1426f9247bb680e5fe0c8");
+
+    // Without the wrapper the array is a sequence of sixteen numbers.
+    assert_eq!(token::to_tokens(&id.0).unwrap().len(), 1 + 16 * 2 + 1);
+}
+
+//////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, PartialEq)]
+struct Person<'a> {
+    name: &'a str,
+    age: u8,
+}
+
+impl<'a> DeserializeBorrowed<'a> for Person<'a> {
+    fn deserialize_borrowed<I>(deserializer: &mut borrowed::Deserializer<I>)
+                               -> Result<Self, token::Error>
+        where I: Iterator<Item=Token<'a>>,
+    {
+        try!(deserializer.struct_start());
+
+        let mut name = None;
+        let mut age = None;
+        while let Some(field) = try!(deserializer.struct_field()) {
+            match &*field {
+                "name" => {
+                    let value = try!(DeserializeBorrowed::deserialize_borrowed(deserializer));
+                    try!(deserializer.set_field(&mut name, value, &field));
+                }
+                "age" => {
+                    let value = try!(DeserializeBorrowed::deserialize_borrowed(deserializer));
+                    try!(deserializer.set_field(&mut age, value, &field));
+                }
+                _ => return Err(token::Error::InvalidValue(format!("unknown field {}", field))),
+            }
+        }
+
+        match (name, age) {
+            (Some(name), Some(age)) => Ok(Person { name: name, age: age }),
+            _ => Err(token::Error::InvalidValue("missing field".to_owned())),
+        }
+    }
+}
+
+#[test]
+fn test_from_tokens_borrowed() {
+    let input = "{\"name\": \"Ada\", \"age\": 36}".to_owned();
+    let person: Person = token::from_tokens_borrowed(json::from_str(&input).unwrap()).unwrap();
+    assert_eq!(person, Person { name: "Ada", age: 36 });
+
+    // The name points into the input rather than into a copy.
+    let offset = person.name.as_ptr() as usize - input.as_ptr() as usize;
+    assert_eq!(&input[offset..offset + 3], "Ada");
+
+    let tokens = vec![
+        Token::StructStart("Person", Some(2)),
+            Token::StructSep,
+            Token::Str("name"),
+            Token::Str("Bob"),
+
+            Token::StructSep,
+            Token::Str("age"),
+            Token::U8(7),
+        Token::StructEnd,
+    ];
+    assert_eq!(token::from_tokens_borrowed::<Person, _>(tokens).unwrap(),
+               Person { name: "Bob", age: 7 });
+
+    let bytes: &[u8] = token::from_tokens_borrowed(vec![Token::Bytes(b"abc")]).unwrap();
+    assert_eq!(bytes, b"abc");
+}
+
+#[test]
+fn test_as_map_as_struct() {
+    let points = vec![Point { x: 1, y: 2 }, Point { x: -3, y: 4 }];
+    let tokens = token::to_tokens(&points).unwrap();
+    let maps: Vec<Token> = token::as_map(tokens.clone()).collect();
+    assert!(!maps.iter().any(|token| *token == Token::StructSep));
+    assert_eq!(token::to_json(maps).unwrap(), token::to_json(tokens).unwrap());
+
+    // Keeping the names restores nested structs too.
+    let tokens = profile(Some("ada"), vec!["x"], 3, Point { x: 1, y: 2 });
+    let maps: Vec<Token> = token::as_map(tokens.clone()).keep_name().collect();
+    assert_eq!(&maps[..4], &[
+        Token::MapStart(Some(5)),
+            Token::MapSep,
+            Token::Str("__name__"),
+            Token::Str("Profile"),
+    ]);
+    let fields = ["nickname", "tags", "visits", "home"];
+    assert_eq!(token::as_struct(maps, "Profile", &fields).unwrap(), tokens);
+
+    let tokens = vec![
+        Token::MapStart(Some(2)),
+            Token::MapSep,
+            Token::Str("age"),
+            Token::U8(7),
+
+            Token::MapSep,
+            Token::Str("name"),
+            Token::Str("Bob"),
+        Token::MapEnd,
+    ];
+    let promoted = token::as_struct(tokens, "Person", &["name", "age"]).unwrap();
+    assert_eq!(promoted[0], Token::StructStart("Person", Some(2)));
+    assert_eq!(token::from_tokens_borrowed::<Person, _>(promoted).unwrap(),
+               Person { name: "Bob", age: 7 });
+
+    let tokens = vec![
+        Token::MapStart(Some(1)),
+            Token::MapSep,
+            Token::Str("height"),
+            Token::U8(7),
+        Token::MapEnd,
+    ];
+    assert_eq!(token::as_struct(tokens, "Person", &["name", "age"]),
+               Err(token::Error::InvalidValue("unknown field \"height\" of Person".to_owned())));
+}
+
+#[test]
+fn test_tuples_as_seqs() {
+    let tokens = token::to_tokens(&(1i32, "a")).unwrap();
+    let seqs: Vec<Token> = token::tuples_as_seqs(tokens).collect();
+    assert_eq!(seqs, vec![
+        Token::SeqStart(Some(2)),
+            Token::SeqSep,
+            Token::I32(1),
+            Token::SeqSep,
+            Token::Str("a"),
+        Token::SeqEnd,
+    ]);
+
+    // Nested tuples are rewritten too, tuple structs are not.
+    let tokens = vec![
+        Token::TupleStructStart("Pair", Some(2)),
+            Token::TupleStructSep,
+            Token::TupleStart(1),
+                Token::TupleSep,
+                Token::Unit,
+            Token::TupleEnd,
+            Token::TupleStructSep,
+            Token::Unit,
+        Token::TupleStructEnd,
+    ];
+    let seqs: Vec<Token> = token::tuples_as_seqs(tokens).collect();
+    assert_eq!(seqs, vec![
+        Token::TupleStructStart("Pair", Some(2)),
+            Token::TupleStructSep,
+            Token::SeqStart(Some(1)),
+                Token::SeqSep,
+                Token::Unit,
+            Token::SeqEnd,
+            Token::TupleStructSep,
+            Token::Unit,
+        Token::TupleStructEnd,
+    ]);
+}
+
+#[test]
+fn test_from_tokens_borrowed_owned_fallback() {
+    let input = "{\"name\": \"A\\u0064a\", \"age\": 36}";
+    let tokens = json::from_str(input).unwrap();
+
+    assert_eq!(token::from_tokens_borrowed::<Person, _>(tokens.clone()).unwrap_err(),
+               token::Error::InvalidValue("cannot borrow the owned string \"Ada\"".to_owned()));
+
+    let mut deserializer = borrowed::Deserializer::new(tokens.into_iter());
+    deserializer.struct_start().unwrap();
+    assert_eq!(deserializer.struct_field().unwrap().unwrap(), "name");
+    let name: Cow<str> = DeserializeBorrowed::deserialize_borrowed(&mut deserializer).unwrap();
+    assert_eq!(name, Cow::Owned::<str>("Ada".to_owned()));
+
+    assert_eq!(token::from_tokens_borrowed::<&[u8], _>(vec![Token::ByteBuf(vec![1])]).unwrap_err(),
+               token::Error::InvalidValue("cannot borrow the owned bytes [1]".to_owned()));
+    assert!(token::from_tokens_borrowed::<u8, _>(vec![Token::I64(256)]).is_err());
+    assert!(token::from_tokens_borrowed::<i8, _>(vec![Token::I64(-129)]).is_err());
+    assert_eq!(token::from_tokens_borrowed::<i8, _>(vec![Token::I64(-128)]), Ok(-128));
+}
+
+#[test]
+fn test_from_tokens_borrowed_map_keys() {
+    let value = btreemap![1u16 => true, 300 => false];
+    let map: BTreeMap<u16, bool> = token::from_tokens_borrowed(token::to_tokens(&value).unwrap())
+        .unwrap();
+    assert_eq!(map, value);
+
+    // Token faithful sources don't coerce string keys unless asked to.
+    let tokens = vec![
+        Token::MapStart(Some(1)),
+            Token::MapSep,
+            Token::Str("1"),
+            Token::Bool(true),
+        Token::MapEnd,
+    ];
+    assert_eq!(token::from_tokens_borrowed::<BTreeMap<u16, bool>, _>(tokens.clone()).unwrap_err(),
+               token::Error::InvalidValue("unexpected token Str(\"1\")".to_owned()));
+
+    let mut deserializer = borrowed::Deserializer::new(tokens.into_iter()).coerce_keys(true);
+    let map: BTreeMap<u16, bool> = DeserializeBorrowed::deserialize_borrowed(&mut deserializer)
+        .unwrap();
+    assert_eq!(map, btreemap![1 => true]);
+}
+
+#[test]
+fn test_integer_extremes_through_backends() {
+    use self::serde::token::{binary, cbor, msgpack, yaml};
+    use self::serde::token::binary::{Decoder, Encoder, IntEncoding};
+
+    let values = vec![u64::MAX, i64::MAX as u64 + 1];
+    let tokens = token::to_tokens(&(values.clone(), i64::MIN, i64::MAX)).unwrap();
+    // What the self-describing readers produce, with integers in `I64` where they fit.
+    let read_back = token::normalize_numbers(tokens.clone()).collect::<Vec<_>>();
+    assert_eq!(read_back[4], Token::U64(u64::MAX));
+    assert_eq!(read_back[6], Token::U64(1 << 63));
+    assert_eq!(read_back[9], Token::I64(i64::MIN));
+
+    let json = token::to_json(tokens.clone()).unwrap();
+    assert_eq!(json, "[[18446744073709551615,9223372036854775808],\
+                      -9223372036854775808,9223372036854775807]");
+    let from_json = json::from_str(&json).unwrap();
+    assert_eq!(from_json[4], Token::U64(u64::MAX));
+    assert_eq!(from_json[6], Token::U64(1 << 63));
+    assert_eq!(from_json[9], Token::I64(i64::MIN));
+    assert_eq!(token::to_json(from_json).unwrap(), json);
+
+    let encoded = token::to_msgpack(tokens.clone()).unwrap();
+    let mut uint64 = vec![0xcf];
+    uint64.extend_from_slice(&[0xff; 8]);
+    assert_eq!(&encoded[2..11], &uint64[..]);
+    let decoded = msgpack::from_msgpack(&encoded).unwrap();
+    assert_eq!(token::to_json(decoded).unwrap(), json);
+
+    let encoded = cbor::to_cbor(tokens.clone());
+    let decoded = cbor::from_cbor(&encoded).unwrap();
+    assert_eq!(token::to_json(decoded).unwrap(), json);
+
+    for &int_encoding in &[IntEncoding::Fixed, IntEncoding::Varint, IntEncoding::VarintZigzag] {
+        let mut encoder = Encoder::new(Vec::new()).int_encoding(int_encoding);
+        encoder.encode(tokens.clone()).unwrap();
+        let bytes = encoder.into_inner();
+        let decoded = Decoder::new(&bytes).int_encoding(int_encoding).decode().unwrap();
+        assert_eq!(decoded, tokens, "{:?}", int_encoding);
+    }
+    assert_eq!(binary::from_binary(&binary::to_binary(tokens.clone())).unwrap(), tokens);
+
+    for &v in &values {
+        let text = token::to_yaml_flow(vec![Token::U64(v)]).unwrap();
+        assert_eq!(text, v.to_string());
+        assert_eq!(yaml::from_str(&text).unwrap(), vec![Token::U64(v)]);
+    }
+
+    assert_eq!(Value::from_tokens(vec![Token::U64(u64::MAX)]), Ok(Value::U64(u64::MAX)));
+    assert_eq!(Value::from_tokens(vec![Token::I64(i64::MIN)]), Ok(Value::I64(i64::MIN)));
+    assert_eq!(token::normalize_numbers(vec![Token::Usize(usize::MAX)]).collect::<Vec<_>>(),
+               vec![Token::U64(usize::MAX as u64)]);
+
+    // Deserializing into a type that can't hold the value fails instead of wrapping.
+    for &v in &values {
+        assert!(token::from_tokens_borrowed::<i64, _>(vec![Token::U64(v)]).is_err());
+        assert_eq!(token::from_tokens_borrowed::<u64, _>(vec![Token::U64(v)]), Ok(v));
+    }
+    assert_eq!(token::from_tokens_borrowed::<i64, _>(vec![Token::U64(i64::MAX as u64)]),
+               Ok(i64::MAX));
+    assert!(token::from_tokens_borrowed::<u64, _>(vec![Token::I64(i64::MIN)]).is_err());
+    assert!(json::deserialize::<i64>("9223372036854775808").is_err());
+    assert_eq!(json::deserialize::<u64>("18446744073709551615").unwrap(), u64::MAX);
+}
+
+#[test]
+fn test_adjacently_tagged() {
+    // `Animal::Frog("Henry".to_owned(), 349)` as a serializer writes it...
+    let serialized = vec![
+        Token::EnumSeqStart("Animal", "Frog", Some(2)),
+        Token::EnumSeqSep, Token::Str("Henry"),
+        Token::EnumSeqSep, Token::Isize(349),
+        Token::EnumSeqEnd,
+    ];
+    // ...and as a deserializer reads it.
+    let framed = vec![
+        Token::EnumStart("Animal"),
+        Token::Str("Frog"),
+        Token::SeqStart(Some(2)),
+        Token::SeqSep, Token::Str("Henry"),
+        Token::SeqSep, Token::Isize(349),
+        Token::SeqEnd,
+    ];
+    let expected = vec![
+        Token::MapStart(Some(2)),
+        Token::MapSep, Token::Str("type"), Token::Str("Frog"),
+        Token::MapSep, Token::Str("value"),
+        Token::SeqStart(Some(2)),
+        Token::SeqSep, Token::Str("Henry"),
+        Token::SeqSep, Token::Isize(349),
+        Token::SeqEnd,
+        Token::MapEnd,
+    ];
+    assert_eq!(token::adjacently_tagged(serialized).collect::<Vec<_>>(), expected);
+    assert_eq!(token::adjacently_tagged(framed).collect::<Vec<_>>(), expected);
+
+    // Variants nested in newtype variants, unit variants and struct variants.
+    let tokens = vec![
+        Token::SeqStart(Some(3)),
+        Token::SeqSep, Token::EnumNewType("E", "Outer"), Token::EnumNewType("E", "Inner"),
+        Token::I32(1),
+        Token::SeqSep, Token::EnumUnit("E", "Empty"),
+        Token::SeqSep, Token::EnumMapStart("E", "Point", Some(1)),
+        Token::EnumMapSep, Token::Str("x"), Token::EnumNewType("E", "Wrap"),
+        Token::SeqStart(Some(0)), Token::SeqEnd,
+        Token::EnumMapEnd,
+        Token::SeqEnd,
+    ];
+    let tagged = token::adjacently_tagged(tokens).keys("t", "c");
+    assert_eq!(token::to_json(tagged).unwrap(),
+               concat!(r#"[{"t":"Outer","c":{"t":"Inner","c":1}},{"t":"Empty"},"#,
+                       r#"{"t":"Point","c":{"x":{"t":"Wrap","c":[]}}}]"#));
+}
+
+#[test]
+fn test_internally_tagged() {
+    fn tag(tokens: Vec<Token>) -> Result<Vec<Token>, token::Error> {
+        token::internally_tagged(tokens).collect()
+    }
+
+    let settings = vec![
+        Token::EnumMapStart("Config", "Settings", Some(2)),
+        Token::EnumMapSep, Token::Str("host"), Token::Str("x"),
+        Token::EnumMapSep, Token::Str("port"), Token::I32(1),
+        Token::EnumMapEnd,
+    ];
+    let tagged = tag(settings).unwrap();
+    assert_eq!(tagged, vec![
+        Token::MapStart(Some(3)),
+        Token::MapSep, Token::Str("type"), Token::Str("Settings"),
+        Token::MapSep, Token::Str("host"), Token::Str("x"),
+        Token::MapSep, Token::Str("port"), Token::I32(1),
+        Token::MapEnd,
+    ]);
+    assert_eq!(token::to_json(tagged).unwrap(), r#"{"type":"Settings","host":"x","port":1}"#);
+
+    // Newtype variants around structs, as read by deserializers too, and unit variants.
+    let point = token::to_tokens(&Point { x: 1, y: 2 }).unwrap();
+    let mut tokens = vec![Token::SeqStart(Some(3)), Token::SeqSep, Token::EnumNewType("E", "P")];
+    tokens.extend(point.clone());
+    tokens.extend(vec![Token::SeqSep, Token::EnumStart("E"), Token::Str("P")]);
+    tokens.extend(point);
+    tokens.extend(vec![Token::SeqSep, Token::EnumUnit("E", "None"), Token::SeqEnd]);
+    let tagged = token::internally_tagged(tokens).tag("kind").collect::<Result<Vec<_>, _>>();
+    assert_eq!(token::to_json(tagged.unwrap()).unwrap(),
+               r#"[{"kind":"P","x":1,"y":2},{"kind":"P","x":1,"y":2},{"kind":"None"}]"#);
+
+    // A tuple variant has nowhere to put the tag.
+    let frog = vec![
+        Token::EnumSeqStart("Animal", "Frog", Some(2)),
+        Token::EnumSeqSep, Token::Str("Henry"),
+        Token::EnumSeqSep, Token::Isize(349),
+        Token::EnumSeqEnd,
+    ];
+    assert_eq!(tag(frog).unwrap_err(), token::Error::InvalidValue(
+        "internally tagged variant \"Frog\" needs a map or struct, found a tuple variant"
+            .to_owned()));
+    let err = tag(vec![Token::EnumNewType("E", "N"), Token::I32(1)]).unwrap_err();
+    assert_eq!(err, token::Error::InvalidValue(
+        "internally tagged variant \"N\" needs a map or struct, found I32(1)".to_owned()));
+}
+
+#[test]
+fn test_diff_tokens() {
+    let old = btreemap![
+        "host".to_owned() => "db1".to_owned(),
+        "port".to_owned() => "5432".to_owned(),
+        "user".to_owned() => "admin".to_owned()
+    ];
+    let mut new = old.clone();
+    new.insert("port".to_owned(), "5433".to_owned());
+
+    let diffs = token::diff_tokens(token::to_tokens(&old).unwrap(),
+                                   token::to_tokens(&new).unwrap()).unwrap();
+    assert_eq!(diffs, vec![token::Diff {
+        path: "port".to_owned(),
+        kind: token::DiffKind::Changed,
+        old: Some(Token::Str("5432")),
+        new: Some(Token::Str("5433")),
+    }]);
+
+    let old = json::from_str(r#"{"a": {"b": 1, "c": [1, 2]}, "d": null}"#).unwrap();
+    let new = json::from_str(r#"{"a": {"b": 1, "c": [1]}, "e": "x"}"#).unwrap();
+    let diffs = token::diff_tokens(old, new).unwrap();
+    let summary: Vec<_> = diffs.iter().map(|diff| (&*diff.path, diff.kind)).collect();
+    assert_eq!(summary, vec![
+        ("a.c[1]", token::DiffKind::Removed),
+        ("d", token::DiffKind::Removed),
+        ("e", token::DiffKind::Added),
+    ]);
+    assert_eq!(diffs[2].old, None);
+
+    assert!(token::diff_tokens(vec![Token::SeqEnd], vec![Token::Unit]).is_err());
+}
+
+#[test]
+fn test_erased_serialize() {
+    use self::serde::token::erased::{self, ErasedSerialize};
+
+    let shapes: Vec<Box<ErasedSerialize>> = vec![
+        Box::new(Point { x: 1, y: 2 }),
+        Box::new(Some(vec![(3u8, 'c')])),
+    ];
+    assert_eq!(json::serialize(&shapes).unwrap(), r#"[{"x":1,"y":2},[[3,"c"]]]"#);
+
+    // Replaying the recorded tokens makes the same calls as serializing the value itself.
+    let point: &ErasedSerialize = &Point { x: 1, y: 2 };
+    assert_eq!(token::to_tokens(&point).unwrap(),
+               token::to_tokens(&Point { x: 1, y: 2 }).unwrap());
+    assert_eq!(token::to_tokens(&shapes[1]).unwrap(),
+               token::to_tokens(&Some(vec![(3u8, 'c')])).unwrap());
+
+    // With the type name the stream records which type produced each element.
+    let tagged: Vec<_> = shapes.iter().map(|shape| erased::with_type_name(&**shape)).collect();
+    let tokens = token::to_tokens(&tagged).unwrap();
+    assert_eq!(&tokens[..4], &[
+        Token::SeqStart(Some(2)),
+        Token::SeqSep,
+        Token::EnumNewType("ErasedSerialize", "Point"),
+        Token::StructStart("Point", Some(2)),
+    ]);
+    let json = json::serialize(&tagged).unwrap();
+    assert!(json.starts_with(r#"[{"Point":{"x":1,"y":2}},{"Option<"#), "{}", json);
+}
+
+#[test]
+fn test_type_registry() {
+    use std::fmt::Debug;
+    use self::serde::token::borrowed::DeserializeInto;
+    use self::serde::token::erased::ErasedSerialize;
+    use self::serde::token::tagging::{self, TypeRegistry};
+
+    #[derive(Debug, Default)]
+    struct Circle {
+        radius: u32,
+    }
+
+    serializable_struct!(Circle { radius });
+    deserialize_into_struct!(Circle { radius });
+
+    #[derive(Debug, Default)]
+    struct Label {
+        text: String,
+    }
+
+    serializable_struct!(Label { text });
+    deserialize_into_struct!(Label { text });
+
+    fn read<T>(tokens: Vec<Token>) -> Result<Box<Debug>, token::Error>
+        where T: Debug + Default + for<'a> DeserializeInto<'a> + 'static,
+    {
+        let mut value = T::default();
+        try!(token::deserialize_into(&mut value, &mut tokens.into_iter()));
+        Ok(Box::new(value))
+    }
+
+    let registry = TypeRegistry::<Debug>::new()
+        .register("Circle", read::<Circle>)
+        .register("Label", read::<Label>);
+
+    let shapes: Vec<Box<ErasedSerialize>> = vec![
+        Box::new(Circle { radius: 2 }),
+        Box::new(Label { text: "hi".to_owned() }),
+    ];
+    let tagged: Vec<Token> = tagging::with_type_names(token::to_tokens(&shapes).unwrap()).collect();
+    let json = token::to_json(tagged.clone()).unwrap();
+    assert_eq!(json, concat!(r#"[{"__type__":"Circle","value":{"radius":2}},"#,
+                             r#"{"__type__":"Label","value":{"text":"hi"}}]"#));
+
+    let expected = r#"[Circle { radius: 2 }, Label { text: "hi" }]"#;
+    assert_eq!(format!("{:?}", registry.deserialize_seq(tagged).unwrap()), expected);
+    let parsed = json::from_str(&json).unwrap();
+    assert_eq!(format!("{:?}", registry.deserialize_seq(parsed).unwrap()), expected);
+
+    // Other field names, read by a registry that knows them.
+    let tokens = token::to_tokens(&Circle { radius: 5 }).unwrap();
+    let tagged = tagging::with_type_names(tokens).fields("t", "v");
+    let circle = registry.fields("t", "v").deserialize(tagged).unwrap();
+    assert_eq!(format!("{:?}", circle), "Circle { radius: 5 }");
+
+    let registry = TypeRegistry::<Debug>::new().register("Circle", read::<Circle>);
+    let tokens = token::to_tokens(&Label { text: "hi".to_owned() }).unwrap();
+    assert_eq!(registry.deserialize(tagging::with_type_names(tokens)).unwrap_err(),
+               token::Error::InvalidValue("unknown type name \"Label\"".to_owned()));
+}
+
+#[test]
+fn test_rfc3339() {
+    use std::time::{Duration, UNIX_EPOCH};
+    use self::serde::token::{OffsetTime, Rfc3339, ToRfc3339};
+
+    // 1996-12-20T00:39:57Z, the example instant of RFC 3339.
+    let secs = 851042397;
+    let utc = OffsetTime::new(secs, 0, 0).unwrap();
+    assert_eq!(token::to_tokens(&Rfc3339(utc)).unwrap(),
+               vec![Token::Str("1996-12-20T00:39:57Z")]);
+
+    // The local time moves with the offset, into the previous day here.
+    let pacific = OffsetTime::new(secs, 0, -8 * 60).unwrap();
+    assert_eq!(token::to_tokens(&Rfc3339(pacific)).unwrap(),
+               vec![Token::Str("1996-12-19T16:39:57-08:00")]);
+    let nepal = OffsetTime::new(secs, 500_000_000, 5 * 60 + 45).unwrap();
+    assert_eq!(nepal.to_rfc3339(), "1996-12-20T06:24:57.500+05:45");
+
+    assert_eq!(OffsetTime::new(secs, 0, 24 * 60), None);
+    assert_eq!(OffsetTime::new(secs, 1_000_000_000, 0), None);
+    assert_eq!(OffsetTime::new(i64::MAX, 0, 23 * 60 + 59), None);
+    assert_eq!(OffsetTime::new(i64::MIN, 0, -1), None);
+    let last = OffsetTime::new(i64::MAX - 60, 0, 1).unwrap();
+    assert_eq!(last.to_rfc3339(), "292277026596-12-04T15:30:07+00:01");
+
+    // Unlike `SystemTime` itself, the wrapper is a string for every serializer.
+    let time = UNIX_EPOCH + Duration::new(secs as u64, 250_000_000);
+    let mut serializer = token::Serializer::new().human_readable(false);
+    Rfc3339(&time).serialize(&mut serializer).unwrap();
+    assert_eq!(serializer.into_tokens(), vec![Token::Str("1996-12-20T00:39:57.250Z")]);
+}
+
+#[test]
+fn test_to_table() {
+    let mut map = BTreeMap::new();
+    map.insert("id", Some(7));
+    map.insert("retries", None);
+    map.insert("timeout_secs", Some(30));
+    let table = token::to_table(token::to_tokens(&map).unwrap()).unwrap();
+    assert_eq!(table,
+               concat!("          id  7\n",
+                       "     retries\n",
+                       "timeout_secs  30\n"));
+
+    let tokens = vec![
+        Token::StructStart("User", Some(3)),
+        Token::StructSep, Token::Str("name"), Token::Str("Henry"),
+        Token::StructSep, Token::Str("x"), Token::F64(1.5),
+        Token::StructSep, Token::Str("active"), Token::Bool(true),
+        Token::StructEnd,
+    ];
+    assert_eq!(token::to_table(tokens).unwrap(), "  name  Henry\n     x  1.5\nactive  true\n");
+
+    let nested = token::to_tokens(&Point { x: 1, y: 2 }).unwrap();
+    let mut tokens = vec![Token::MapStart(Some(1)), Token::MapSep, Token::Str("at")];
+    tokens.extend(nested);
+    tokens.push(Token::MapEnd);
+    assert_eq!(token::to_table(tokens).unwrap_err().to_string(),
+               "Invalid value: field \"at\" is not a scalar, found \
+                StructStart(\"Point\", Some(2))");
+    assert!(token::to_table(vec![Token::I32(1)]).is_err());
+}
+
+#[test]
+fn test_order_fields() {
+    use self::serde::token::{FieldOrder, FieldOrders};
+    use self::serde::token::yaml::FlowWriter;
+
+    let tokens = vec![
+        Token::StructStart("Record", Some(4)),
+        Token::StructSep, Token::Str("name"), Token::Str("frog"),
+        Token::StructSep, Token::Str("type"), Token::Str("animal"),
+        Token::StructSep, Token::Str("id"), Token::U32(7),
+        Token::StructSep, Token::Str("inner"),
+        Token::SeqStart(Some(1)),
+        Token::SeqSep,
+        Token::StructStart("Inner", Some(3)),
+        Token::StructSep, Token::Str("z"), Token::I32(1),
+        Token::StructSep, Token::Str("id"), Token::I32(2),
+        Token::StructSep, Token::Str("labels"),
+        Token::MapStart(Some(2)),
+        Token::MapSep, Token::Str("b"), Token::I32(3),
+        Token::MapSep, Token::Str("a"), Token::I32(4),
+        Token::MapEnd,
+        Token::StructEnd,
+        Token::SeqEnd,
+        Token::StructEnd,
+    ];
+    let ordered = |orders: FieldOrders| {
+        json::to_json_ordered(tokens.clone(), &orders).unwrap()
+    };
+
+    assert_eq!(ordered(FieldOrders::new(FieldOrder::Declaration)),
+               concat!(r#"{"name":"frog","type":"animal","id":7,"#,
+                       r#""inner":[{"z":1,"id":2,"labels":{"b":3,"a":4}}]}"#));
+    // Nested structs are sorted too, but the entries of maps keep their order.
+    assert_eq!(ordered(FieldOrders::new(FieldOrder::Sorted)),
+               concat!(r#"{"id":7,"#,
+                       r#""inner":[{"id":2,"labels":{"b":3,"a":4},"z":1}],"#,
+                       r#""name":"frog","type":"animal"}"#));
+    assert_eq!(ordered(FieldOrders::new(FieldOrder::Custom(vec!["id", "type"]))),
+               concat!(r#"{"id":7,"type":"animal","name":"frog","#,
+                       r#""inner":[{"id":2,"z":1,"labels":{"b":3,"a":4}}]}"#));
+
+    // A policy for one struct name overrides the global one.
+    let orders = FieldOrders::new(FieldOrder::Sorted)
+        .for_struct("Inner", FieldOrder::Declaration);
+    assert_eq!(ordered(orders.clone()),
+               concat!(r#"{"id":7,"#,
+                       r#""inner":[{"z":1,"id":2,"labels":{"b":3,"a":4}}],"#,
+                       r#""name":"frog","type":"animal"}"#));
+    assert_eq!(FlowWriter::new().field_order(orders).write(tokens.clone()).unwrap(),
+               "{id: 7, inner: [{z: 1, id: 2, labels: {b: 3, a: 4}}], name: frog, type: animal}");
+
+    let variant = vec![
+        Token::EnumMapStart("Shape", "Circle", Some(2)),
+        Token::EnumMapSep, Token::Str("r"), Token::I32(1),
+        Token::EnumMapSep, Token::Str("center"), Token::Unit,
+        Token::EnumMapEnd,
+    ];
+    let orders = FieldOrders::new(FieldOrder::Sorted);
+    assert_eq!(token::order_fields(variant, &orders).unwrap()[1..4],
+               [Token::EnumMapSep, Token::Str("center"), Token::Unit]);
+    assert!(token::order_fields(vec![Token::StructStart("S", None)], &orders).is_err());
+}
+
+/// A struct holding borrowed bytes.
+struct Packet<'a> {
+    payload: &'a [u8],
+}
+
+impl<'a> Serialize for Packet<'a> {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer,
+    {
+        serializer.serialize_struct("Packet", PacketVisitor { value: self, done: false })
+    }
+}
+
+struct PacketVisitor<'a, 'b: 'a> {
+    value: &'a Packet<'b>,
+    done: bool,
+}
+
+impl<'a, 'b> ser::MapVisitor for PacketVisitor<'a, 'b> {
+    fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+        where S: Serializer,
+    {
+        if self.done {
+            return Ok(None);
+        }
+        self.done = true;
+        Ok(Some(try!(serializer.serialize_struct_elt("payload", self.value.payload))))
+    }
+
+    fn len(&self) -> Option<usize> {
+        Some(1)
+    }
+}
+
+#[test]
+fn test_nested_borrows() {
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    let text = "frog";
+    let text_ref = &text;
+    assert_eq!(token::to_tokens(&text_ref).unwrap(),
+               token::to_tokens(&String::from("frog")).unwrap());
+    assert_eq!(token::to_tokens(&Some(text)).unwrap(),
+               token::to_tokens(&Some(String::from("frog"))).unwrap());
+    let none: Option<&str> = None;
+    assert_eq!(token::to_tokens(&none).unwrap(), vec![Token::Option(false)]);
+
+    let owned = vec![String::from("a"), String::from("b")];
+    let borrowed: Vec<&str> = owned.iter().map(|s| &s[..]).collect();
+    let expected = token::to_tokens(&owned).unwrap();
+    assert_eq!(token::to_tokens(&borrowed).unwrap(), expected);
+    assert_eq!(token::to_tokens(&&borrowed).unwrap(), expected);
+    assert_eq!(token::to_tokens(&&borrowed[..]).unwrap(), expected);
+
+    let mut owned_map = BTreeMap::new();
+    owned_map.insert(String::from("k"), String::from("v"));
+    let mut map: BTreeMap<&str, &str> = BTreeMap::new();
+    map.insert("k", "v");
+    assert_eq!(token::to_tokens(&&map).unwrap(), token::to_tokens(&owned_map).unwrap());
+
+    let bytes = vec![1u8, 2];
+    assert_eq!(token::to_tokens(&Packet { payload: &bytes }).unwrap(), vec![
+        Token::StructStart("Packet", Some(1)),
+        Token::StructSep,
+        Token::Str("payload"),
+        Token::SeqStart(Some(2)),
+        Token::SeqSep, Token::U8(1),
+        Token::SeqSep, Token::U8(2),
+        Token::SeqEnd,
+        Token::StructEnd,
+    ]);
+
+    // Shared pointers to unsized values serialize like the values.
+    let shared: Rc<str> = Rc::from("frog");
+    assert_eq!(token::to_tokens(&shared).unwrap(), vec![Token::Str("frog")]);
+    let shared: Arc<[u8]> = Arc::from(&bytes[..]);
+    assert_eq!(token::to_tokens(&shared).unwrap(), token::to_tokens(&bytes).unwrap());
+}
+
+#[test]
+fn test_check_int_range() {
+    use self::serde::token::int_range::MAX_SAFE_INTEGER;
+
+    let within = json::from_str(r#"{"ids": [1, -9007199254740991, 9007199254740991]}"#).unwrap();
+    assert_eq!(token::check_int_range(within, MAX_SAFE_INTEGER), Ok(()));
+
+    let mut map = BTreeMap::new();
+    map.insert("small", vec![1u64]);
+    map.insert("big id", vec![2, 3, 1 << 53 | 1]);
+    let err = token::check_int_range(token::to_tokens(&map).unwrap(), MAX_SAFE_INTEGER)
+        .unwrap_err();
+    assert_eq!(err.path(), "[\"big id\"][2]");
+    assert_eq!(err.value(), "9007199254740993");
+    assert_eq!(err.to_string(),
+               "at [\"big id\"][2]: integer 9007199254740993 is out of the range \
+                -9007199254740991 to 9007199254740991");
+
+    // Negative values are checked by their distance from zero, and so are struct fields.
+    let err = token::check_int_range(token::to_tokens(&Point { x: 1, y: -300 }).unwrap(), 255)
+        .unwrap_err();
+    assert_eq!((err.path(), err.value()), ("y", "-300"));
+    let err = token::check_int_range(vec![Token::I64(i64::MIN)], u64::MAX >> 1).unwrap_err();
+    assert_eq!(err.path(), "");
+    assert!(token::check_int_range(vec![Token::BigNum("123456789012345678901".to_owned())],
+                                   u64::MAX)
+        .is_err());
+
+    // Floats and map keys are not checked.
+    let mut keys = BTreeMap::new();
+    keys.insert(u64::MAX, 1e300);
+    assert_eq!(token::check_int_range(token::to_tokens(&keys).unwrap(), 1), Ok(()));
+}
+
+struct Record {
+    name: String,
+    tags: Vec<String>,
+    scores: HashMap<String, u32>,
+    parent: Option<String>,
+}
+
+deserialize_into_struct!(Record { name, tags, scores, parent });
+
+#[test]
+fn test_deserialize_into() {
+    let inputs = [
+        r#"{"name": "first record", "tags": ["a", "bb", "ccc"], "scores": {"x": 1},
+            "parent": "root"}"#,
+        r#"{"tags": ["dd"], "name": "second", "scores": {}, "parent": null}"#,
+        r#"{"name": "third", "tags": ["e", "f"], "scores": {"y": 2, "z": 3}, "parent": "p"}"#,
+    ];
+    let mut tokens = Vec::new();
+    for input in &inputs {
+        tokens.extend(json::from_str(input).unwrap());
+    }
+    let mut tokens = tokens.into_iter();
+
+    let mut record = Record {
+        name: String::new(),
+        tags: Vec::new(),
+        scores: HashMap::new(),
+        parent: None,
+    };
+    token::deserialize_into(&mut record, &mut tokens).unwrap();
+    assert_eq!(record.name, "first record");
+    assert_eq!(record.tags, vec!["a", "bb", "ccc"]);
+    assert_eq!(record.scores.get("x"), Some(&1));
+    assert_eq!(record.parent, Some("root".to_owned()));
+    let name_capacity = record.name.capacity();
+    let tags_capacity = record.tags.capacity();
+
+    // A shorter record truncates the sequence and keeps the allocations.
+    token::deserialize_into(&mut record, &mut tokens).unwrap();
+    assert_eq!(record.name, "second");
+    assert_eq!(record.tags, vec!["dd"]);
+    assert!(record.scores.is_empty());
+    assert_eq!(record.parent, None);
+    assert_eq!(record.name.capacity(), name_capacity);
+    assert_eq!(record.tags.capacity(), tags_capacity);
+
+    token::deserialize_into(&mut record, &mut tokens).unwrap();
+    assert_eq!(record.name, "third");
+    assert_eq!(record.tags, vec!["e", "f"]);
+    assert_eq!(record.scores.len(), 2);
+    assert_eq!(record.scores.get("z"), Some(&3));
+    assert_eq!(record.parent, Some("p".to_owned()));
+    assert_eq!(tokens.next(), None);
+
+    let missing = json::from_str(r#"{"name": "x", "tags": [], "scores": {}}"#).unwrap();
+    assert_eq!(token::deserialize_into(&mut record, &mut missing.into_iter()).unwrap_err()
+                   .to_string(),
+               "Invalid value: missing field \"parent\" of Record");
+    let unknown = json::from_str(r#"{"id": 1}"#).unwrap();
+    assert_eq!(token::deserialize_into(&mut record, &mut unknown.into_iter()).unwrap_err()
+                   .to_string(),
+               "Invalid value: unknown field \"id\" of Record");
+}
+
+/// A least recently used cache that lists its most recently used entry first.
+struct Recent<K, V> {
+    capacity: usize,
+    entries: VecDeque<(K, V)>,
+}
+
+impl<K, V> Recent<K, V>
+    where K: PartialEq,
+{
+    fn new(capacity: usize) -> Self {
+        Recent { capacity: capacity, entries: VecDeque::new() }
+    }
+
+    fn put(&mut self, key: K, value: V) {
+        if let Some(index) = self.entries.iter().position(|entry| entry.0 == key) {
+            self.entries.remove(index);
+        }
+        self.entries.push_front((key, value));
+        self.entries.truncate(self.capacity);
+    }
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        let index = match self.entries.iter().position(|entry| entry.0 == *key) {
+            Some(index) => index,
+            None => return None,
+        };
+        let entry = self.entries.remove(index).unwrap();
+        self.entries.push_front(entry);
+        Some(&self.entries[0].1)
+    }
+}
+
+impl<K, V> Recent<K, V> {
+    fn iter<'a>(&'a self) -> Box<Iterator<Item=(&'a K, &'a V)> + 'a> {
+        Box::new(self.entries.iter().map(|&(ref key, ref value)| (key, value)))
+    }
+}
+
+impl_map_serializable!(Recent<K, V>, iter);
+
+impl<'a, K, V> DeserializeInto<'a> for Recent<K, V>
+    where K: DeserializeBorrowed<'a> + PartialEq,
+          V: DeserializeBorrowed<'a>,
+{
+    fn deserialize_into<I>(&mut self, deserializer: &mut borrowed::Deserializer<I>)
+                           -> Result<(), token::Error>
+        where I: Iterator<Item=Token<'a>>,
+    {
+        try!(deserializer.map_start());
+        let mut entries = Vec::new();
+        while let Some(entry) = try!(deserializer.map_entry()) {
+            entries.push(entry);
+        }
+        // Put the least recently used entry first, so the first entry ends up the most recent.
+        self.entries.clear();
+        for (key, value) in entries.into_iter().rev() {
+            self.put(key, value);
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_map_serializable() {
+    let mut cache = Recent::new(3);
+    cache.put("a".to_owned(), 1);
+    cache.put("b".to_owned(), 2);
+    cache.put("c".to_owned(), 3);
+    cache.put("d".to_owned(), 4);
+    assert_eq!(cache.get(&"b".to_owned()), Some(&2));
+
+    let tokens = token::to_tokens(&cache).unwrap();
+    assert_eq!(tokens, vec![
+        Token::MapStart(Some(3)),
+        Token::MapSep, Token::Str("b"), Token::I32(2),
+        Token::MapSep, Token::Str("d"), Token::I32(4),
+        Token::MapSep, Token::Str("c"), Token::I32(3),
+        Token::MapEnd,
+    ]);
+
+    // The capacity isn't part of the stream, so the caller builds the empty cache.
+    let mut restored: Recent<String, i32> = Recent::new(3);
+    token::deserialize_into(&mut restored, &mut tokens.into_iter()).unwrap();
+    let order: Vec<_> = restored.iter().map(|(key, _)| &**key).collect();
+    assert_eq!(order, vec!["b", "d", "c"]);
+
+    restored.put("e".to_owned(), 5);
+    let json = token::to_json(token::to_tokens(&restored).unwrap()).unwrap();
+    assert_eq!(json, r#"{"e":5,"b":2,"d":4}"#);
+
+    let mut smaller: Recent<String, i32> = Recent::new(2);
+    token::deserialize_into(&mut smaller, &mut json::from_str(&json).unwrap().into_iter())
+        .unwrap();
+    assert_eq!(smaller.iter().map(|(key, _)| &**key).collect::<Vec<_>>(), vec!["e", "b"]);
+}
+
+#[test]
+fn test_display_list() {
+    use self::serde::token::compose::DisplayList;
+
+    let name = String::from("frog");
+    let mut list = DisplayList::new();
+    assert!(list.is_empty());
+    list.push(&7).unwrap();
+    list.push(&name).unwrap();
+    list.push(&vec![true, false]).unwrap();
+    list.push_tokens(json::from_str("{\"a\": null}").unwrap());
+    assert_eq!(list.len(), 4);
+
+    assert_eq!(list.into_iter().collect::<Vec<_>>(), vec![
+        Token::SeqStart(Some(4)),
+        Token::SeqSep, Token::I32(7),
+        Token::SeqSep, Token::Str("frog"),
+        Token::SeqSep,
+        Token::SeqStart(Some(2)),
+        Token::SeqSep, Token::Bool(true),
+        Token::SeqSep, Token::Bool(false),
+        Token::SeqEnd,
+        Token::SeqSep,
+        Token::MapStart(None),
+        Token::MapSep, Token::Str("a"), Token::Unit,
+        Token::MapEnd,
+        Token::SeqEnd,
+    ]);
+
+    assert_eq!(DisplayList::new().into_iter().collect::<Vec<_>>(),
+               vec![Token::SeqStart(Some(0)), Token::SeqEnd]);
+}
+
+#[test]
+fn test_find_field_skips_enums() {
+    let tokens = vec![
+        Token::StructStart("Event", Some(2)),
+        Token::StructSep, Token::Str("payload"),
+        Token::EnumStart("Payload"), Token::Str("Text"), Token::Str("type"),
+        Token::StructSep, Token::Str("type"), Token::Str("message"),
+        Token::StructEnd,
+    ];
+    assert_eq!(token::find_field(tokens, "type").unwrap(), Some(Token::Str("message")));
+}
+
+#[test]
+fn test_redact() {
+    let tokens = json::from_str(concat!(r#"{"user": {"name": "ann", "ssn": "078-05-1120", "#,
+                                        r#""cards": [{"pan": "4111"}, [1, 2]]}, "#,
+                                        r#""password": {"hash": [1, {"salt": 2}]}}"#)).unwrap();
+    let paths = ["password", "user.ssn", "user.cards[1]", "user.cards[0].pan", "missing"];
+    let redacted = token::redact(tokens, &paths).unwrap();
+    assert_eq!(token::to_json(redacted).unwrap(),
+               concat!(r#"{"user":{"name":"ann","ssn":"[REDACTED]","#,
+                       r#""cards":[{"pan":"[REDACTED]"},"[REDACTED]"]},"#,
+                       r#""password":"[REDACTED]"}"#));
+
+    // Structs, `Some` and the payload of variants.
+    let tokens = vec![
+        Token::StructStart("Login", Some(2)),
+        Token::StructSep, Token::Str("secret"),
+        Token::Option(true), Token::EnumStart("Secret"), Token::Str("Token"),
+        Token::SeqStart(Some(1)), Token::SeqSep, Token::Str("abc"), Token::SeqEnd,
+        Token::StructSep, Token::Str("user"), Token::Str("ann"),
+        Token::StructEnd,
+    ];
+    assert_eq!(token::redact(tokens.clone(), &["secret.Token"]).unwrap(), vec![
+        Token::StructStart("Login", Some(2)),
+        Token::StructSep, Token::Str("secret"),
+        Token::Option(true), Token::EnumStart("Secret"), Token::Str("Token"),
+        Token::Str("[REDACTED]"),
+        Token::StructSep, Token::Str("user"), Token::Str("ann"),
+        Token::StructEnd,
+    ]);
+    assert_eq!(token::redact(tokens, &["secret"]).unwrap()[3], Token::Str("[REDACTED]"));
+
+    let truncated = json::from_str(r#"{"password": [1, 2]}"#).unwrap();
+    assert!(token::redact(truncated[..5].to_vec(), &["password"]).is_err());
+}
+
+#[test]
+fn test_field_numbered() {
+    let tokens = vec![
+        Token::StructStart("Order", Some(5)),
+        Token::StructSep, Token::Str("id"), Token::U64(42),
+        Token::StructSep, Token::Str("debug"), Token::Str("not sent"),
+        Token::StructSep, Token::Str("customer"),
+        Token::Option(true),
+        Token::StructStart("Customer", None),
+        Token::StructSep, Token::Str("name"), Token::Str("ann"),
+        Token::StructSep, Token::Str("cache"),
+        Token::StructStart("Cache", Some(1)),
+        Token::StructSep, Token::Str("hits"), Token::U32(3),
+        Token::StructEnd,
+        Token::StructEnd,
+        Token::StructSep, Token::Str("internal"),
+        Token::SeqStart(Some(1)), Token::SeqSep, Token::I32(1), Token::SeqEnd,
+        Token::StructSep, Token::Str("items"),
+        Token::SeqStart(Some(1)),
+        Token::SeqSep,
+        Token::StructStart("Item", Some(1)),
+        Token::StructSep, Token::Str("name"), Token::Str("tea"),
+        Token::StructEnd,
+        Token::SeqEnd,
+        Token::StructEnd,
+    ];
+    let numbered: Vec<Token> = token::field_numbered(tokens, |field| {
+        match field {
+            "id" => Some(1),
+            "customer" => Some(2),
+            "items" => Some(3),
+            "name" => Some(1),
+            _ => None,
+        }
+    }).collect();
+    assert_eq!(numbered, vec![
+        Token::MapStart(Some(3)),
+        Token::MapSep, Token::U32(1), Token::U64(42),
+        Token::MapSep, Token::U32(2),
+        Token::Option(true),
+        Token::MapStart(Some(1)),
+        Token::MapSep, Token::U32(1), Token::Str("ann"),
+        Token::MapEnd,
+        Token::MapSep, Token::U32(3),
+        Token::SeqStart(Some(1)),
+        Token::SeqSep,
+        Token::MapStart(Some(1)),
+        Token::MapSep, Token::U32(1), Token::Str("tea"),
+        Token::MapEnd,
+        Token::SeqEnd,
+        Token::MapEnd,
+    ]);
+    assert_eq!(token::validate(numbered), Ok(()));
+
+    // Structs below the top level are numbered as they are reached.
+    let points = vec![Point { x: 1, y: 2 }];
+    let numbered: Vec<Token> = token::field_numbered(token::to_tokens(&points).unwrap(), |field| {
+        if field == "y" { Some(2) } else { None }
+    }).collect();
+    assert_eq!(token::to_json(numbered).unwrap(), r#"[{"2":2}]"#);
+}
+
+#[test]
+fn test_infer_schema() {
+    use self::serde::token::schema::Schema;
+
+    let mut zones = BTreeMap::new();
+    zones.insert("north", vec![Point { x: 1, y: 2 }]);
+    zones.insert("south", vec![]);
+    let mut sample = BTreeMap::new();
+    sample.insert("zones".to_owned(), zones);
+    assert_eq!(token::infer_schema(token::to_tokens(&sample).unwrap()).unwrap(), Schema::Map(
+        Box::new(Schema::Str),
+        Box::new(Schema::Map(Box::new(Schema::Str), Box::new(Schema::Seq(Box::new(
+            Schema::Struct(vec![("x".to_owned(), Schema::Int), ("y".to_owned(), Schema::Int)]),
+        ))))),
+    ));
+
+    // Heterogeneous elements become a union, and fields missing from some elements nullable.
+    let tokens = json::from_str(r#"[{"id": 1, "name": "a"}, {"id": 2}, 3, "four", 5]"#).unwrap();
+    let schema = token::infer_schema(tokens).unwrap();
+    assert_eq!(schema, Schema::Seq(Box::new(Schema::Union(vec![
+        Schema::Map(Box::new(Schema::Str), Box::new(Schema::Union(vec![Schema::Int, Schema::Str]))),
+        Schema::Int,
+        Schema::Str,
+    ]))));
+
+    let tokens = token::to_tokens(&vec![Some(Point { x: 1, y: 2 }), None]).unwrap();
+    let schema = token::infer_schema(tokens).unwrap();
+    assert_eq!(schema, Schema::Seq(Box::new(Schema::Union(vec![
+        Schema::Struct(vec![("x".to_owned(), Schema::Int), ("y".to_owned(), Schema::Int)]),
+        Schema::Null,
+    ]))));
+    assert_eq!(schema.to_json_schema(), value!({
+        "type": "array",
+        "items": {
+            "anyOf": [
+                {
+                    "type": "object",
+                    "properties": { "x": { "type": "integer" }, "y": { "type": "integer" } },
+                    "required": ["x", "y"]
+                },
+                { "type": "null" }
+            ]
+        }
+    }));
+
+    assert!(token::infer_schema(vec![Token::SeqStart(None), Token::SeqSep]).is_err());
+}
+
+#[test]
+fn test_content_hash() {
+    use self::serde::token::hash::{self, Algorithm};
+
+    let algorithms = [
+        Algorithm::SipHash(0x0706050403020100, 0x0f0e0d0c0b0a0908),
+        Algorithm::Fnv1a,
+        Algorithm::Crc32,
+    ];
+
+    // Built in opposite orders and with different capacities, so they iterate differently.
+    let mut forward = HashMap::new();
+    for i in 0..100u32 {
+        forward.insert(format!("key{}", i), i);
+    }
+    let mut backward = HashMap::with_capacity(1000);
+    for i in (0..100u32).rev() {
+        backward.insert(format!("key{}", i), i);
+    }
+    let widened: BTreeMap<String, i64> =
+        forward.iter().map(|(k, &v)| (k.clone(), v as i64)).collect();
+
+    for &algorithm in &algorithms {
+        let hash = |tokens: Vec<Token>| hash::content_hash(tokens, algorithm).unwrap();
+        let expected = hash(token::to_tokens(&forward).unwrap());
+        assert_eq!(hash(token::to_tokens(&backward).unwrap()), expected);
+        assert_eq!(hash(token::to_tokens(&widened).unwrap()), expected);
+
+        *backward.get_mut("key42").unwrap() ^= 1;
+        assert!(hash(token::to_tokens(&backward).unwrap()) != expected);
+        *backward.get_mut("key42").unwrap() ^= 1;
+    }
+
+    // Pinned, so that a change to the encoding or to an algorithm doesn't go unnoticed.
+    let mut map = BTreeMap::new();
+    map.insert("a", 1u8);
+    map.insert("b", 2u8);
+    assert_eq!(hash::canonical_bytes(token::to_tokens(&map).unwrap()).unwrap(), vec![
+        0x23, 0, 0, 0, 0, 0, 0, 0, 2,
+        0x30, 0x09, 0, 0, 0, 0, 0, 0, 0, 1, b'a', 0x05, 0, 0, 0, 0, 0, 0, 0, 1,
+        0x30, 0x09, 0, 0, 0, 0, 0, 0, 0, 1, b'b', 0x05, 0, 0, 0, 0, 0, 0, 0, 2,
+        0x31,
+    ]);
+    let hashes: Vec<u64> = algorithms.iter().map(|&algorithm| {
+        hash::content_hash(token::to_tokens(&map).unwrap(), algorithm).unwrap()
+    }).collect();
+    assert_eq!(hashes, vec![0x57b30df7c2eff8e0, 0xc08986a8af109751, 0xa8f4b261]);
+}
+
+#[test]
+fn test_incremental() {
+    use std::cell::Cell;
+    use self::serde::token::Incremental;
+
+    /// Counts how often it is serialized.
+    struct Counted<'a> {
+        value: u32,
+        calls: &'a Cell<usize>,
+    }
+
+    impl<'a> Serialize for Counted<'a> {
+        fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+            where S: Serializer,
+        {
+            self.calls.set(self.calls.get() + 1);
+            self.value.serialize(serializer)
+        }
+    }
+
+    let calls = Cell::new(0);
+    let mut map = Incremental::new();
+    let mut plain = BTreeMap::new();
+    for i in 0..100 {
+        map.insert(format!("key{}", i), Counted { value: i, calls: &calls });
+        plain.insert(format!("key{}", i), i);
+    }
+    assert_eq!(map.to_tokens().unwrap(), token::to_tokens(&plain).unwrap());
+    assert_eq!(calls.get(), 100);
+
+    map.get_mut("key42").unwrap().value = 1000;
+    plain.insert("key42".to_owned(), 1000);
+    assert!(map.invalidate("key42"));
+    assert_eq!(map.to_tokens().unwrap(), token::to_tokens(&plain).unwrap());
+    assert_eq!(calls.get(), 101);
+
+    // Nothing changed, nothing is serialized.
+    map.to_tokens().unwrap();
+    assert_eq!(calls.get(), 101);
+
+    assert!(!map.invalidate("missing"));
+    map.remove("key0");
+    plain.remove("key0");
+    assert_eq!(map.to_tokens().unwrap(), token::to_tokens(&plain).unwrap());
+    assert_eq!(calls.get(), 101);
+
+    let mut point = Incremental::new().named("Point");
+    point.insert("x".to_owned(), 1);
+    point.insert("y".to_owned(), 2);
+    assert_eq!(point.to_tokens().unwrap(), token::to_tokens(&Point { x: 1, y: 2 }).unwrap());
+}
+
+#[test]
+fn test_context_serializer() {
+    use self::serde::token::context::Overrides;
+
+    // Stands in for a type of another crate, which serializes as a struct.
+    struct Duration {
+        secs: u64,
+        nanos: u32,
+    }
+    serializable_struct!(Duration { secs, nanos });
+
+    fn to_json(value: &BTreeMap<&str, Duration>, overrides: Overrides) -> String {
+        let mut ser = token::Serializer::with_overrides(json::Writer::new(Vec::new()), overrides);
+        value.serialize(&mut ser).unwrap();
+        String::from_utf8(ser.into_inner().into_inner().into_inner()).unwrap()
+    }
+
+    let millis = Overrides::new().with("Duration", |tokens| {
+        let field = |name| {
+            match token::find_field(tokens.clone(), name) {
+                Ok(Some(Token::U64(v))) => Ok(v),
+                Ok(Some(Token::U32(v))) => Ok(v as u64),
+                _ => Err(token::Error::InvalidValue(format!("Duration without {}", name))),
+            }
+        };
+        Ok(vec![Token::U64(try!(field("secs")) * 1000 + try!(field("nanos")) / 1_000_000)])
+    });
+
+    let timeouts = btreemap![
+        "connect" => Duration { secs: 1, nanos: 500_000_000 },
+        "read" => Duration { secs: 30, nanos: 0 }
+    ];
+    assert_eq!(to_json(&timeouts, millis.clone()), r#"{"connect":1500,"read":30000}"#);
+    assert_eq!(to_json(&timeouts, Overrides::new()),
+               r#"{"connect":{"secs":1,"nanos":500000000},"read":{"secs":30,"nanos":0}}"#);
+
+    // Clones share the rewrites, and extending one leaves the others alone.
+    let seconds = Overrides::new().with("Duration", |tokens| {
+        Ok(match token::find_field(tokens, "secs") {
+            Ok(Some(token)) => vec![token],
+            _ => vec![Token::Unit],
+        })
+    });
+    let combined = millis.clone().extend(&seconds);
+    assert_eq!(to_json(&timeouts, combined), r#"{"connect":1,"read":30}"#);
+    assert_eq!(to_json(&timeouts, millis), r#"{"connect":1500,"read":30000}"#);
+
+    // A failing rewrite fails the serialization.
+    let failing = Overrides::new().with("Duration", |_| {
+        Err(token::Error::InvalidValue("no durations here".to_owned()))
+    });
+    let mut ser = token::Serializer::with_overrides(Vec::new(), failing);
+    assert!(timeouts.serialize(&mut ser).is_err());
+}
+
+#[test]
+fn test_tee() {
+    use self::serde::token::TokenSink;
+
+    /// Accepts `limit` tokens, then fails.
+    struct Limited {
+        tokens: Vec<Token<'static>>,
+        limit: usize,
+    }
+
+    impl TokenSink for Limited {
+        fn push(&mut self, token: Token<'static>) -> Result<(), token::Error> {
+            if self.tokens.len() == self.limit {
+                return Err(token::Error::InvalidValue("sink is full".to_owned()));
+            }
+            self.tokens.push(token);
+            Ok(())
+        }
+    }
+
+    let tokens = token::to_tokens(&vec![Point { x: 1, y: 2 }, Point { x: 3, y: 4 }]).unwrap();
+
+    let mut first = Vec::new();
+    let mut second = Vec::new();
+    let results = {
+        let mut sinks: [&mut TokenSink; 2] = [&mut first, &mut second];
+        token::tee(tokens.clone(), &mut sinks)
+    };
+    assert_eq!(results, vec![Ok(()), Ok(())]);
+    assert_eq!(first, tokens);
+    assert_eq!(second, tokens);
+
+    // A failing sink doesn't stop the others.
+    let mut limited = Limited { tokens: Vec::new(), limit: 3 };
+    let mut writer = json::Writer::new(Vec::new());
+    let results = {
+        let mut sinks: [&mut TokenSink; 2] = [&mut limited, &mut writer];
+        token::tee(tokens.clone(), &mut sinks)
+    };
+    assert_eq!(results, vec![
+        Err(token::Error::InvalidValue("sink is full".to_owned())),
+        Ok(()),
+    ]);
+    assert_eq!(limited.tokens, &tokens[..3]);
+    assert_eq!(String::from_utf8(writer.into_inner()).unwrap(), token::to_json(tokens).unwrap());
+}
+
+#[test]
+fn test_counted() {
+    use std::cell::Cell;
+    use std::iter::Cloned;
+    use std::rc::Rc;
+    use std::slice;
+    use self::serde::token::testing::corpus;
+
+    /// Counts how often it is cloned.
+    struct Clones<'a, 'b: 'a> {
+        iter: Cloned<slice::Iter<'a, Token<'b>>>,
+        clones: Rc<Cell<usize>>,
+    }
+
+    impl<'a, 'b> Clone for Clones<'a, 'b> {
+        fn clone(&self) -> Self {
+            self.clones.set(self.clones.get() + 1);
+            Clones { iter: self.iter.clone(), clones: self.clones.clone() }
+        }
+    }
+
+    impl<'a, 'b> Iterator for Clones<'a, 'b> {
+        type Item = Token<'b>;
+
+        fn next(&mut self) -> Option<Token<'b>> {
+            self.iter.next()
+        }
+    }
+
+    for fixture in corpus() {
+        // Filtering loses the exact size of the stream.
+        let iter = fixture.tokens.iter().cloned().filter(|_| true);
+        let (len, iter) = token::counted(iter);
+        assert_eq!(len, fixture.tokens.len(), "{}", fixture.name);
+        assert_eq!(iter.len(), len);
+        assert_eq!(iter.collect::<Vec<_>>(), fixture.tokens);
+
+        let (len, iter) = token::counted_recorded(fixture.tokens.iter().cloned().filter(|_| true));
+        assert_eq!(len, fixture.tokens.len(), "{}", fixture.name);
+        assert_eq!(iter.collect::<Vec<_>>(), fixture.tokens);
+    }
+
+    // The tokens of a Vec<i32> are counted on a clone of an iterator borrowing them, without
+    // recording them again.
+    let tokens = token::to_tokens(&vec![1i32, 2, 3]).unwrap();
+    let clones = Rc::new(Cell::new(0));
+    let iter = Clones { iter: tokens.iter().cloned(), clones: clones.clone() };
+    let (len, mut iter) = token::counted(iter);
+    assert_eq!(clones.get(), 1);
+    assert_eq!(len, 8);
+
+    assert_eq!(iter.next(), Some(Token::SeqStart(Some(3))));
+    assert_eq!(iter.size_hint(), (7, Some(7)));
+    assert_eq!(iter.by_ref().count(), 7);
+    assert_eq!(iter.len(), 0);
+}
+
+#[test]
+fn test_count_tokens() {
+    let value = vec![
+        btreemap!["a".to_owned() => vec![Some(1u8), None]],
+        btreemap![],
+    ];
+    assert_eq!(token::count_tokens(&value).unwrap(), token::to_tokens(&value).unwrap().len());
+    assert_eq!(token::count_tokens(&vec![1i32, 2, 3]).unwrap(), 8);
+    assert_eq!(token::count_tokens(&()).unwrap(), 1);
+}
+
+#[test]
+fn test_bytes_encodings() {
+    use self::serde::token::encoding::{self, BytesEncoding};
+
+    let encodings = [
+        BytesEncoding::Base64,
+        BytesEncoding::Base64NoPad,
+        BytesEncoding::Base64Url,
+        BytesEncoding::Base64UrlNoPad,
+        BytesEncoding::Base32,
+        BytesEncoding::Hex,
+    ];
+    let every_byte: Vec<u8> = (0..256).map(|b| b as u8).collect();
+    let blobs: Vec<&[u8]> = vec![
+        b"", b"f", b"fo", b"foo", b"foob", b"fooba", b"foobar", &every_byte,
+    ];
+
+    for &enc in &encodings {
+        for blob in &blobs {
+            let text = encoding::encode(blob, enc);
+            assert_eq!(encoding::decode(&text, enc).unwrap(), *blob, "{:?} {:?}", enc, text);
+        }
+        assert_eq!(encoding::encode(b"", enc), "");
+    }
+
+    // One, two and three bytes need two, one and no padding characters.
+    let padding = vec![
+        (&b"\xfb"[..], "+w==", "-w", "-w=="),
+        (&b"\xfb\xff"[..], "+/8=", "-_8", "-_8="),
+        (&b"\xfb\xff\xbf"[..], "+/+/", "-_-_", "-_-_"),
+    ];
+    for (bytes, std, url_no_pad, url) in padding {
+        assert_eq!(encoding::encode(bytes, BytesEncoding::Base64), std);
+        assert_eq!(encoding::encode(bytes, BytesEncoding::Base64NoPad),
+                   std.trim_right_matches('='));
+        assert_eq!(encoding::encode(bytes, BytesEncoding::Base64Url), url);
+        assert_eq!(encoding::encode(bytes, BytesEncoding::Base64UrlNoPad), url_no_pad);
+        // Padding is optional when decoding.
+        assert_eq!(encoding::decode(url_no_pad, BytesEncoding::Base64Url).unwrap(), bytes);
+        assert_eq!(encoding::decode(std, BytesEncoding::Base64NoPad).unwrap(), bytes);
+    }
+
+    assert_eq!(encoding::encode(b"foobar", BytesEncoding::Base32), "MZXW6YTBOI======");
+    assert_eq!(encoding::decode("mzxw6ytboi", BytesEncoding::Base32).unwrap(), b"foobar");
+    assert_eq!(encoding::encode(b"\x00\xab", BytesEncoding::Hex), "00ab");
+    assert_eq!(encoding::decode("00AB", BytesEncoding::Hex).unwrap(), b"\x00\xab");
+
+    // Whitespace is skipped anywhere.
+    assert_eq!(encoding::decode(" Zm9v\r\n YmFy\t", BytesEncoding::Base64).unwrap(), b"foobar");
+
+    let errors = vec![
+        ("Zm9v!mFy", BytesEncoding::Base64, "invalid character '!' at offset 4"),
+        ("Zm9v-mFy", BytesEncoding::Base64, "invalid character '-' at offset 4"),
+        ("Zm9v+mFy", BytesEncoding::Base64Url, "invalid character '+' at offset 4"),
+        ("Zg=a", BytesEncoding::Base64, "character 'a' after padding at offset 3"),
+        ("Zg=", BytesEncoding::Base64, "invalid padding at offset 2"),
+        ("Zm9vY", BytesEncoding::Base64, "incomplete final character at offset 5"),
+        ("MZXW1", BytesEncoding::Base32, "invalid character '1' at offset 4"),
+        ("0g", BytesEncoding::Hex, "invalid character 'g' at offset 1"),
+        ("abc", BytesEncoding::Hex, "incomplete final character at offset 3"),
+        ("ab\u{e9}", BytesEncoding::Hex, "invalid character '\u{e9}' at offset 2"),
+    ];
+    for (text, enc, message) in errors {
+        let err = encoding::decode(text, enc).unwrap_err();
+        assert_eq!(err.to_string(), message, "{:?}", text);
+    }
+}
+
+#[test]
+fn test_check_strings() {
+    use self::serde::token::ControlCharPolicy;
+
+    let tokens = vec![
+        Token::MapStart(Some(1)),
+        Token::MapSep, Token::Str("key"), Token::Str("nul\u{0}here"),
+        Token::MapEnd,
+    ];
+
+    let err = token::check_strings(tokens.clone(), ControlCharPolicy::Reject).unwrap_err();
+    assert_eq!((err.index(), err.offset(), err.char()), (3, 3, '\u{0}'));
+    assert_eq!(err.to_string(), "token #3: control character U+0000 at byte 3 of the string");
+    assert!(token::check_strings(tokens.clone(), ControlCharPolicy::Escape).is_ok());
+    assert!(token::check_strings(tokens.clone(), ControlCharPolicy::Allow).is_ok());
+
+    let rejected: Vec<_> = token::escape_strings(tokens.clone(), ControlCharPolicy::Reject)
+        .collect();
+    assert_eq!(rejected.len(), 4);
+    assert_eq!(rejected[3], Err(err));
+
+    let escaped: Result<Vec<_>, _> = token::escape_strings(tokens.clone(),
+                                                           ControlCharPolicy::Escape)
+        .collect();
+    assert_eq!(escaped.unwrap(), vec![
+        Token::MapStart(Some(1)),
+        Token::MapSep, Token::Str("key"), Token::String("nul\\u0000here".to_owned()),
+        Token::MapEnd,
+    ]);
+
+    let allowed: Result<Vec<_>, _> = token::escape_strings(tokens.clone(),
+                                                           ControlCharPolicy::Allow)
+        .collect();
+    assert_eq!(allowed.unwrap(), tokens);
+
+    // Keys, owned strings and characters are checked too, other tokens aren't.
+    let others = vec![
+        Token::SeqStart(Some(3)),
+        Token::SeqSep, Token::String("\u{1b}[0m".to_owned()),
+        Token::SeqSep, Token::Char('\u{7f}'),
+        Token::SeqSep, Token::Bytes(b"\x00"),
+        Token::SeqEnd,
+    ];
+    let err = token::check_strings(others.clone(), ControlCharPolicy::Reject).unwrap_err();
+    assert_eq!(err.index(), 2);
+    let escaped: Vec<_> = token::escape_strings(others, ControlCharPolicy::Escape)
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(escaped[2], Token::String("\\u001b[0m".to_owned()));
+    assert_eq!(escaped[4], Token::String("\\u007f".to_owned()));
+    assert_eq!(escaped[6], Token::Bytes(b"\x00"));
+}
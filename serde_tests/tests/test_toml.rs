@@ -0,0 +1,330 @@
+use std::collections::BTreeMap;
+use std::f64;
+
+extern crate serde;
+use self::serde::token::{self, Token, Value};
+use self::serde::token::borrowed::{self, DeserializeBorrowed};
+use self::serde::token::{json, toml};
+
+use token::{field_value, missing_field, unknown_field};
+
+//////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, PartialEq)]
+struct Config<'a> {
+    title: &'a str,
+    server: Server<'a>,
+    backends: Vec<Backend<'a>>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Server<'a> {
+    host: &'a str,
+    port: u16,
+    tags: Vec<String>,
+    limits: BTreeMap<String, i64>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Backend<'a> {
+    name: &'a str,
+    weight: f64,
+    enabled: bool,
+}
+
+impl<'a> DeserializeBorrowed<'a> for Config<'a> {
+    fn deserialize_borrowed<I>(deserializer: &mut borrowed::Deserializer<I>)
+                               -> Result<Self, token::Error>
+        where I: Iterator<Item=Token<'a>>,
+    {
+        try!(deserializer.struct_start());
+
+        let (mut title, mut server, mut backends) = (None, None, None);
+        while let Some(field) = try!(deserializer.struct_field()) {
+            match &*field {
+                "title" => title = try!(field_value(deserializer)),
+                "server" => server = try!(field_value(deserializer)),
+                "backends" => backends = try!(field_value(deserializer)),
+                _ => return Err(unknown_field(&field)),
+            }
+        }
+
+        Ok(Config {
+            title: try!(title.ok_or_else(|| missing_field("title"))),
+            server: try!(server.ok_or_else(|| missing_field("server"))),
+            backends: try!(backends.ok_or_else(|| missing_field("backends"))),
+        })
+    }
+}
+
+impl<'a> DeserializeBorrowed<'a> for Server<'a> {
+    fn deserialize_borrowed<I>(deserializer: &mut borrowed::Deserializer<I>)
+                               -> Result<Self, token::Error>
+        where I: Iterator<Item=Token<'a>>,
+    {
+        try!(deserializer.struct_start());
+
+        let (mut host, mut port, mut tags, mut limits) = (None, None, None, None);
+        while let Some(field) = try!(deserializer.struct_field()) {
+            match &*field {
+                "host" => host = try!(field_value(deserializer)),
+                "port" => port = try!(field_value(deserializer)),
+                "tags" => tags = try!(field_value(deserializer)),
+                "limits" => limits = try!(field_value(deserializer)),
+                _ => return Err(unknown_field(&field)),
+            }
+        }
+
+        Ok(Server {
+            host: try!(host.ok_or_else(|| missing_field("host"))),
+            port: try!(port.ok_or_else(|| missing_field("port"))),
+            tags: try!(tags.ok_or_else(|| missing_field("tags"))),
+            limits: try!(limits.ok_or_else(|| missing_field("limits"))),
+        })
+    }
+}
+
+impl<'a> DeserializeBorrowed<'a> for Backend<'a> {
+    fn deserialize_borrowed<I>(deserializer: &mut borrowed::Deserializer<I>)
+                               -> Result<Self, token::Error>
+        where I: Iterator<Item=Token<'a>>,
+    {
+        try!(deserializer.struct_start());
+
+        let (mut name, mut weight, mut enabled) = (None, None, None);
+        while let Some(field) = try!(deserializer.struct_field()) {
+            match &*field {
+                "name" => name = try!(field_value(deserializer)),
+                "weight" => weight = try!(field_value(deserializer)),
+                "enabled" => enabled = try!(field_value(deserializer)),
+                _ => return Err(unknown_field(&field)),
+            }
+        }
+
+        Ok(Backend {
+            name: try!(name.ok_or_else(|| missing_field("name"))),
+            weight: try!(weight.ok_or_else(|| missing_field("weight"))),
+            enabled: try!(enabled.ok_or_else(|| missing_field("enabled"))),
+        })
+    }
+}
+
+const CONFIG: &'static str = r#"
+# A representative configuration.
+title = "proxy"
+
+[server]
+host = 'example.com'
+port = 8_080
+tags = [
+    "edge",     # trailing comments and newlines are fine in arrays
+    "eu\u002Dwest",
+]
+limits = { connections = 0x400, "idle.timeout" = 30 }
+
+[[backends]]
+name = "primary"
+weight = 0.75
+enabled = true
+
+[[backends]]
+name = "fallback"
+weight = 2.5e-1
+enabled = false
+"#;
+
+#[test]
+fn test_toml_config() {
+    let tokens = toml::from_str(CONFIG).unwrap();
+    let config: Config = token::from_tokens_borrowed(tokens).unwrap();
+
+    assert_eq!(config, Config {
+        title: "proxy",
+        server: Server {
+            host: "example.com",
+            port: 8080,
+            tags: vec!["edge".to_owned(), "eu-west".to_owned()],
+            limits: btreemap!["connections".to_owned() => 1024, "idle.timeout".to_owned() => 30],
+        },
+        backends: vec![
+            Backend { name: "primary", weight: 0.75, enabled: true },
+            Backend { name: "fallback", weight: 0.25, enabled: false },
+        ],
+    });
+
+    // Strings without escapes point into the document.
+    let offset = config.server.host.as_ptr() as usize - CONFIG.as_ptr() as usize;
+    assert_eq!(&CONFIG[offset..offset + 11], "example.com");
+}
+
+#[test]
+fn test_toml_tokens() {
+    let tokens = toml::from_str("a.b = 1\na.c = [true, \"x\"]\n").unwrap();
+    assert_eq!(tokens, vec![
+        Token::MapStart(Some(1)),
+            Token::MapSep,
+            Token::Str("a"),
+            Token::MapStart(Some(2)),
+                Token::MapSep,
+                Token::Str("b"),
+                Token::I64(1),
+
+                Token::MapSep,
+                Token::Str("c"),
+                Token::SeqStart(Some(2)),
+                    Token::SeqSep,
+                    Token::Bool(true),
+
+                    Token::SeqSep,
+                    Token::Str("x"),
+                Token::SeqEnd,
+            Token::MapEnd,
+        Token::MapEnd,
+    ]);
+}
+
+#[test]
+fn test_toml_scalars() {
+    let cases = vec![
+        ("v = +99", Token::I64(99)),
+        ("v = -17", Token::I64(-17)),
+        ("v = 1_000_000", Token::I64(1000000)),
+        ("v = 0xDEAD_beef", Token::I64(0xdeadbeef)),
+        ("v = 0o755", Token::I64(0o755)),
+        ("v = 0b1101", Token::I64(13)),
+        ("v = 6.626e-34", Token::F64(6.626e-34)),
+        ("v = -3.1415", Token::F64(-3.1415)),
+        ("v = 5e+22", Token::F64(5e22)),
+        ("v = -inf", Token::F64(f64::NEG_INFINITY)),
+        ("v = false", Token::Bool(false)),
+        ("v = 1979-05-27T07:32:00Z", Token::Timestamp(296638320, 0)),
+        ("v = 1979-05-27 00:32:00.5-07:00", Token::Timestamp(296638320, 500000000)),
+        ("v = \"tab\\there \\U0001F600\"", Token::String("tab\there \u{1F600}".to_owned())),
+        ("v = 'C:\\path'", Token::Str("C:\\path")),
+        ("v = \"\"\"\none\ntwo\"\"\"", Token::Str("one\ntwo")),
+        ("v = \"\"\"a \\\n    b\"\"\"", Token::String("a b".to_owned())),
+        ("v = '''it's \"raw\"'''", Token::Str("it's \"raw\"")),
+        ("v = \"\"\"\"quoted\"\"\"\"", Token::Str("\"quoted\"")),
+    ];
+
+    for (input, expected) in cases {
+        let tokens = toml::from_str(input).unwrap();
+        assert_eq!(tokens[3], expected, "{}", input);
+    }
+
+    match toml::from_str("v = nan").unwrap()[3] {
+        Token::F64(v) => assert!(v.is_nan()),
+        ref token => panic!("unexpected token {:?}", token),
+    }
+}
+
+#[test]
+fn test_toml_matches_json() {
+    // There is no TOML writer, so the document is compared with the same data written as JSON.
+    let toml = r#"
+        name = "widget"
+        sizes = [1, 2, 3]
+        nested = [[1, 2], ["a"]]
+        empty = {}
+
+        [owner]
+        name = "Tom"
+        dob = 1979-05-27T07:32:00Z
+
+        [owner.address]
+        city = "Lisbon"
+
+        [[parts]]
+        id = 1
+
+        [[parts]]
+        id = 2
+        [parts.extra]
+        fragile = true
+    "#;
+    let json = r#"{
+        "name": "widget",
+        "sizes": [1, 2, 3],
+        "nested": [[1, 2], ["a"]],
+        "empty": {},
+        "owner": {
+            "name": "Tom",
+            "dob": "1979-05-27T07:32:00Z",
+            "address": {"city": "Lisbon"}
+        },
+        "parts": [{"id": 1}, {"id": 2, "extra": {"fragile": true}}]
+    }"#;
+
+    assert_eq!(Value::from_tokens(toml::from_str(toml).unwrap()).unwrap(),
+               Value::from_tokens(json::from_str(json).unwrap()).unwrap());
+}
+
+#[test]
+fn test_toml_errors() {
+    fn error(input: &str) -> String {
+        toml::from_str(input).unwrap_err().to_string()
+    }
+
+    // Duplicate tables and keys.
+    assert_eq!(error("[a]\nx = 1\n\n[a]\ny = 2\n"), "duplicate table `a` at line 4 column 1");
+    assert_eq!(error("x = 1\nx = 2\n"), "duplicate key `x` at line 2 column 1");
+    assert_eq!(error("[a.b]\n[a]\n[a]\n"), "duplicate table `a` at line 3 column 1");
+
+    // A key defined as one type and then used as another.
+    assert_eq!(error("a = 1\n[a.b]\n"),
+               "key `a` is already defined as a value at line 2 column 1");
+    assert_eq!(error("a = {x = 1}\na.y = 2\n"),
+               "table `a` can't be extended here at line 2 column 1");
+    assert_eq!(error("[a]\n[[a]]\n"), "`a` is not an array of tables at line 2 column 1");
+
+    // Syntax errors.
+    assert_eq!(error("a = \"open\nb = 1\n"), "newline in string at line 1 column 10");
+    assert_eq!(error("a = 1 b = 2\n"), "expected a newline at line 1 column 7");
+    assert_eq!(error("a = 01\n"), "invalid number at line 1 column 5");
+    assert_eq!(error("a = 1979-05-27\n"),
+               "local dates and times are not supported at line 1 column 5");
+    assert_eq!(error("a = 1979-05-27T07:32:00\n"),
+               "local dates and times are not supported at line 1 column 5");
+    assert_eq!(error("a = 1900-02-29T00:00:00Z\n"), "invalid date-time at line 1 column 5");
+    assert_eq!(error("a = 1979-05-27 25:00:00-07:00\n"),
+               "invalid date-time at line 1 column 5");
+    assert_eq!(error("[a\n"), "expected `]` at line 1 column 3");
+}
+
+#[test]
+fn test_toml_max_depth() {
+    fn nested(open: &str, close: &str, depth: usize) -> String {
+        format!("a = {}{}\n", open.repeat(depth), close.repeat(depth))
+    }
+
+    // The document is the first level, so 127 arrays fit below it.
+    assert!(toml::from_str(&nested("[", "]", 127)).is_ok());
+    let err = toml::from_str(&nested("[", "]", 128)).unwrap_err();
+    assert_eq!(err.to_string(), "nesting exceeds the depth limit of 128 at line 1 column 132");
+    assert!(toml::from_str(&nested("[", "]", 100_000)).is_err());
+    assert!(toml::from_str(&nested("{b = ", "}", 100_000)).is_err());
+
+    // Dotted keys and table headers open a level per part.
+    let dotted = vec!["a"; 100_000].join(".");
+    let err = toml::from_str(&format!("x = 1\n{} = 1\n", dotted)).unwrap_err();
+    assert_eq!(err.to_string(), "nesting exceeds the depth limit of 128 at line 2 column 1");
+    let err = toml::from_str(&format!("[{}]\n", dotted)).unwrap_err();
+    assert_eq!(err.to_string(), "nesting exceeds the depth limit of 128 at line 1 column 1");
+
+    let input = "[a.b]\nc = {d = [1]}\n";
+    assert!(toml::Parser::new(input).max_depth(5).parse().is_ok());
+    let err = toml::Parser::new(input).max_depth(4).parse().unwrap_err();
+    assert_eq!(err.to_string(), "nesting exceeds the depth limit of 4 at line 2 column 10");
+    let err = toml::Parser::new(input).max_depth(3).parse().unwrap_err();
+    assert_eq!(err.to_string(), "nesting exceeds the depth limit of 3 at line 2 column 5");
+    let err = toml::Parser::new(input).max_depth(2).parse().unwrap_err();
+    assert_eq!(err.to_string(), "nesting exceeds the depth limit of 2 at line 1 column 1");
+}
+
+#[test]
+fn test_toml_error_snippet() {
+    let err = toml::from_str("[server]\nport = = 1\n").unwrap_err();
+    assert_eq!(err.snippet().line(), 2);
+    assert_eq!(format!("{:#}", err),
+               "invalid number at line 2 column 8\n  |\n2 | port = = 1\n  |        ^");
+}
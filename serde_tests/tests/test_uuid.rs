@@ -0,0 +1,88 @@
+extern crate serde;
+use self::serde::token::{self, Token};
+use self::serde::token::{json, msgpack};
+use self::serde::uuid::{Format, Uuid};
+
+use token::assert_de_tokens;
+
+//////////////////////////////////////////////////////////////////////////
+
+const BYTES: [u8; 16] = [
+    0x67, 0xe5, 0x50, 0x44, 0x10, 0xb1, 0x42, 0x6f,
+    0x92, 0x47, 0xbb, 0x68, 0x0e, 0x5f, 0xe0, 0xc8,
+];
+
+#[test]
+fn test_uuid_formats() {
+    let uuid = Uuid::from_bytes(BYTES);
+    let cases = vec![
+        (Format::Hyphenated, "67e55044-10b1-426f-9247-bb680e5fe0c8"),
+        (Format::Simple, "67e5504410b1426f9247bb680e5fe0c8"),
+        (Format::Urn, "urn:uuid:67e55044-10b1-426f-9247-bb680e5fe0c8"),
+    ];
+    for (format, text) in cases {
+        assert_eq!(token::to_tokens(&uuid.format(format)).unwrap(),
+                   vec![Token::String(text.to_owned())]);
+        assert_eq!(text.parse::<Uuid>().unwrap(), uuid);
+    }
+    assert_eq!(token::to_tokens(&uuid).unwrap(),
+               vec![Token::String("67e55044-10b1-426f-9247-bb680e5fe0c8".to_owned())]);
+    assert_eq!(token::to_tokens(&uuid.format(Format::Bytes)).unwrap(),
+               vec![Token::Bytes(&BYTES)]);
+
+    // Mixed case, braces and an upper case URN prefix.
+    for text in &["67E55044-10b1-426F-9247-BB680e5fe0c8",
+                  "67E5504410B1426F9247BB680E5FE0C8",
+                  "{67e55044-10b1-426f-9247-bb680e5fe0c8}",
+                  "URN:UUID:67e55044-10b1-426f-9247-bb680e5fe0c8"] {
+        assert_eq!(text.parse::<Uuid>().unwrap(), uuid, "{}", text);
+    }
+
+    assert_de_tokens(&uuid, vec![Token::Str("67E55044-10B1-426F-9247-BB680E5FE0C8")]);
+    assert_de_tokens(&uuid, vec![Token::Bytes(&BYTES)]);
+}
+
+#[test]
+fn test_uuid_round_trips() {
+    let uuid = Uuid::from_bytes(BYTES);
+
+    let text = json::serialize(&uuid).unwrap();
+    assert_eq!(text, r#""67e55044-10b1-426f-9247-bb680e5fe0c8""#);
+    assert_eq!(json::deserialize::<Uuid>(&text).unwrap(), uuid);
+
+    let encoded = msgpack::serialize(&uuid.format(Format::Bytes)).unwrap();
+    assert_eq!(&encoded[..2], &[0xc4, 16]);
+    let tokens = msgpack::from_msgpack(&encoded).unwrap();
+    assert_eq!(token::from_tokens_borrowed::<Uuid, _>(tokens).unwrap(), uuid);
+}
+
+#[test]
+fn test_uuid_errors() {
+    let err = "67e55044-10b1-426f-9247-bb680e5fe0cx".parse::<Uuid>().unwrap_err();
+    assert_eq!(err.text(), "67e55044-10b1-426f-9247-bb680e5fe0cx");
+    assert_eq!(err.to_string(), concat!(r#"invalid UUID "67e55044-10b1-426f-9247-bb680e5fe0cx": "#,
+                                        "expected a hexadecimal digit"));
+
+    let err = "67e55044_10b1_426f_9247_bb680e5fe0c8".parse::<Uuid>().unwrap_err();
+    assert_eq!(err.to_string(), concat!(r#"invalid UUID "67e55044_10b1_426f_9247_bb680e5fe0c8": "#,
+                                        "expected a hyphen between the groups of digits"));
+
+    let err = "67e55044".parse::<Uuid>().unwrap_err();
+    assert!(err.to_string().starts_with(r#"invalid UUID "67e55044": expected 32"#), "{}", err);
+
+    // A multi-byte character across the end of where a URN prefix would be.
+    let text = "aaaaaaaa\u{e9}aaaaaaaaaaaaaaaaaaaaaaaaa";
+    let err = text.parse::<Uuid>().unwrap_err();
+    assert_eq!(err.text(), text);
+    assert_eq!(err.to_string(),
+               format!("invalid UUID \"{}\": expected 32 hexadecimal digits, optionally in \
+                        groups of 8-4-4-4-12", text));
+    let err = json::deserialize::<Uuid>("\"urn:uuid:\u{e9}\"").unwrap_err();
+    assert!(err.to_string().contains("invalid UUID \"urn:uuid:\u{e9}\""), "{}", err);
+
+    let err = json::deserialize::<Uuid>(r#""not-a-uuid""#).unwrap_err();
+    assert!(err.to_string().contains(r#"invalid UUID "not-a-uuid""#), "{}", err);
+
+    let err = token::from_tokens_borrowed::<Uuid, _>(vec![Token::Bytes(&[1, 2, 3])]).unwrap_err();
+    assert_eq!(err.to_string(), "Invalid value: expected 16 bytes for a UUID, found 3");
+}
@@ -0,0 +1,234 @@
+extern crate serde;
+use self::serde::token::{self, Token, Value};
+use self::serde::token::borrowed::{self, DeserializeBorrowed};
+use self::serde::token::{json, xml};
+
+use token::{field_value, missing_field, unknown_field};
+
+//////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, PartialEq)]
+struct Order {
+    id: String,
+    customer: String,
+    items: Vec<Item>,
+    note: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Item {
+    sku: String,
+    name: String,
+}
+
+impl<'a> DeserializeBorrowed<'a> for Order {
+    fn deserialize_borrowed<I>(deserializer: &mut borrowed::Deserializer<I>)
+                               -> Result<Self, token::Error>
+        where I: Iterator<Item=Token<'a>>,
+    {
+        try!(deserializer.struct_start());
+
+        let (mut id, mut customer, mut items, mut note) = (None, None, None, None);
+        while let Some(field) = try!(deserializer.struct_field()) {
+            match &*field {
+                "@id" => id = try!(field_value(deserializer)),
+                "customer" => customer = try!(field_value(deserializer)),
+                "item" => items = try!(field_value(deserializer)),
+                "note" => note = try!(field_value(deserializer)),
+                _ => return Err(unknown_field(&field)),
+            }
+        }
+
+        Ok(Order {
+            id: try!(id.ok_or_else(|| missing_field("@id"))),
+            customer: try!(customer.ok_or_else(|| missing_field("customer"))),
+            items: try!(items.ok_or_else(|| missing_field("item"))),
+            note: note,
+        })
+    }
+}
+
+impl<'a> DeserializeBorrowed<'a> for Item {
+    fn deserialize_borrowed<I>(deserializer: &mut borrowed::Deserializer<I>)
+                               -> Result<Self, token::Error>
+        where I: Iterator<Item=Token<'a>>,
+    {
+        try!(deserializer.struct_start());
+
+        let (mut sku, mut name) = (None, None);
+        while let Some(field) = try!(deserializer.struct_field()) {
+            match &*field {
+                "@sku" => sku = try!(field_value(deserializer)),
+                "$text" => name = try!(field_value(deserializer)),
+                _ => return Err(unknown_field(&field)),
+            }
+        }
+
+        Ok(Item {
+            sku: try!(sku.ok_or_else(|| missing_field("@sku"))),
+            name: try!(name.ok_or_else(|| missing_field("$text"))),
+        })
+    }
+}
+
+#[test]
+fn test_xml_document() {
+    let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE order [ <!ELEMENT order ANY> ]>
+<!-- exported by the legacy shop -->
+<order id="A-17">
+    <customer>Smith &amp; Sons</customer>
+    <item sku="X1">Widget &#x2014; large</item>
+    <item sku='X2'><![CDATA[<Gadget>]]></item>
+    <note/>
+</order>
+"#;
+    let order: Order = token::from_tokens_borrowed(xml::from_str(input).unwrap()).unwrap();
+    assert_eq!(order, Order {
+        id: "A-17".to_owned(),
+        customer: "Smith & Sons".to_owned(),
+        items: vec![
+            Item { sku: "X1".to_owned(), name: "Widget \u{2014} large".to_owned() },
+            Item { sku: "X2".to_owned(), name: "<Gadget>".to_owned() },
+        ],
+        note: Some("".to_owned()),
+    });
+}
+
+#[test]
+fn test_xml_repeated_children() {
+    let input = "<list><a>1</a><b>x</b><a>2</a><a>3</a></list>";
+    assert_eq!(xml::from_str(input).unwrap(), vec![
+        Token::MapStart(Some(2)),
+            Token::MapSep,
+            Token::Str("a"),
+            Token::SeqStart(Some(3)),
+                Token::SeqSep,
+                Token::Str("1"),
+                Token::SeqSep,
+                Token::Str("2"),
+                Token::SeqSep,
+                Token::Str("3"),
+            Token::SeqEnd,
+
+            Token::MapSep,
+            Token::Str("b"),
+            Token::Str("x"),
+        Token::MapEnd,
+    ]);
+
+    // A single child is not wrapped in a sequence.
+    assert_eq!(token::to_json(xml::from_str("<list><a>1</a></list>").unwrap()).unwrap(),
+               r#"{"a":"1"}"#);
+}
+
+#[test]
+fn test_xml_options_and_mixed_content() {
+    let input = "<soap:Envelope xmlns:soap=\"urn:s\"><p class=\"x\">Hello <b>world</b>!</p>\
+                 </soap:Envelope>";
+    let tokens = xml::Parser::new(input.as_bytes())
+        .attribute_prefix("-")
+        .text_key("#text")
+        .parse()
+        .unwrap();
+    let expected = r##"{
+        "-xmlns:soap": "urn:s",
+        "p": {"-class": "x", "b": "world", "#text": "Hello !"}
+    }"##;
+    assert_eq!(Value::from_tokens(tokens).unwrap(),
+               Value::from_tokens(json::from_str(expected).unwrap()).unwrap());
+}
+
+#[test]
+fn test_xml_errors() {
+    fn error(input: &str) -> String {
+        xml::from_str(input).unwrap_err().to_string()
+    }
+
+    assert_eq!(error("<a><b></a>"), "expected `</b>`, found `</a>` at line 1 column 7");
+    assert_eq!(error("<a>\n  <b>"), "missing end tag `</b>` at line 2 column 6");
+    assert_eq!(error("<a x=\"1\" x=\"2\"/>"), "duplicate attribute `x` at line 1 column 10");
+    assert_eq!(error("<a>&nbsp;</a>"), "unknown entity `&nbsp;` at line 1 column 4");
+    assert_eq!(error("<a/><b/>"), "trailing characters after the root element at line 1 column 5");
+    assert_eq!(error("text"), "expected the root element at line 1 column 1");
+}
+
+#[test]
+fn test_xml_max_depth() {
+    fn nested(depth: usize) -> String {
+        format!("{}{}", "<a>".repeat(depth), "</a>".repeat(depth))
+    }
+
+    // The root element counts as the first level.
+    assert!(xml::from_str(&nested(128)).is_ok());
+    let err = xml::from_str(&nested(129)).unwrap_err();
+    assert_eq!(err.to_string(), "nesting exceeds the depth limit of 128 at line 1 column 385");
+    assert!(xml::from_str(&nested(100_000)).is_err());
+
+    let input = "<a>\n  <b x=\"1\"/>\n  <b><c>text</c></b>\n</a>";
+    assert!(xml::Parser::new(input.as_bytes()).max_depth(3).parse().is_ok());
+    let err = xml::Parser::new(input.as_bytes()).max_depth(2).parse().unwrap_err();
+    assert_eq!(err.to_string(), "nesting exceeds the depth limit of 2 at line 3 column 6");
+}
+
+#[test]
+fn test_to_xml_struct() {
+    let tokens = vec![
+        Token::StructStart("Item", Some(3)),
+            Token::StructSep,
+            Token::Str("sku"),
+            Token::Str("X1"),
+            Token::StructSep,
+            Token::Str("name"),
+            Token::Str("Fish & <Chips>"),
+            Token::StructSep,
+            Token::Str("note"),
+            Token::Option(false),
+        Token::StructEnd,
+    ];
+    let xml = token::to_xml(tokens, "item").unwrap();
+    assert_eq!(xml, "<item><sku>X1</sku><name>Fish &amp; &lt;Chips&gt;</name><note/></item>");
+
+    // The reader reads it back, with the empty element as an empty string.
+    assert_eq!(token::to_json(xml::from_str(&xml).unwrap()).unwrap(),
+               r#"{"sku":"X1","name":"Fish & <Chips>","note":""}"#);
+}
+
+#[test]
+fn test_to_xml_seq() {
+    let tokens = token::to_tokens(&vec![Some(1.5), None]).unwrap();
+    assert_eq!(token::to_xml(tokens, "values").unwrap(),
+               "<values><item>1.5</item><item/></values>");
+
+    // Single precision floats are written with their own shortest digits.
+    let tokens = vec![Token::SeqStart(Some(2)), Token::SeqSep, Token::F32(0.1),
+                      Token::SeqSep, Token::F32(::std::f32::NAN), Token::SeqEnd];
+    assert_eq!(token::to_xml(tokens, "v").unwrap(),
+               "<v><item>0.1</item><item>NaN</item></v>");
+
+    let tokens = json::from_str(r#"{"tags": ["a", "b"], "empty": []}"#).unwrap();
+    let xml = token::to_xml(tokens, "post").unwrap();
+    assert_eq!(xml, "<post><tags><item>a</item><item>b</item></tags><empty/></post>");
+    assert_eq!(token::to_json(xml::from_str(&xml).unwrap()).unwrap(),
+               r#"{"tags":{"item":["a","b"]},"empty":""}"#);
+}
+
+#[test]
+fn test_to_xml_errors() {
+    fn error(tokens: Vec<Token>, root: &str) -> String {
+        match token::to_xml(tokens, root).unwrap_err() {
+            token::Error::InvalidValue(msg) => msg,
+            err => panic!("unexpected error {:?}", err),
+        }
+    }
+
+    assert_eq!(error(vec![Token::Unit], "1st"), r#""1st" is not a valid XML element name"#);
+    assert_eq!(error(json::from_str(r#"{"a b": 1}"#).unwrap(), "root"),
+               r#""a b" is not a valid XML element name"#);
+    assert_eq!(error(vec![Token::MapStart(Some(1)), Token::MapSep, Token::I32(1), Token::I32(2),
+                          Token::MapEnd],
+                     "root"),
+               "map key I32(1) can't be an XML element name");
+    assert_eq!(error(vec![Token::Str("a\u{0}")], "root"),
+               "character U+0000 can't appear in an XML document");
+}
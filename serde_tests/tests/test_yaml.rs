@@ -0,0 +1,409 @@
+use std::collections::BTreeMap;
+
+extern crate serde;
+use self::serde::token::{self, TimestampFormat, Token, Value};
+use self::serde::token::yaml;
+
+//////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_yaml_flow_nested_map() {
+    let value = btreemap![
+        "a" => btreemap!["x" => vec![1, 2]],
+        "b" => btreemap![]
+    ];
+    assert_eq!(token::to_yaml_flow(token::to_tokens(&value).unwrap()).unwrap(),
+               "{a: {x: [1, 2]}, b: {}}");
+
+    let tokens = vec![
+        Token::StructStart("Point", Some(2)),
+            Token::StructSep,
+            Token::Str("x"),
+            Token::Option(true),
+            Token::I32(1),
+
+            Token::StructSep,
+            Token::Str("y"),
+            Token::EnumSeqStart("E", "V", Some(1)),
+                Token::EnumSeqSep,
+                Token::Unit,
+            Token::EnumSeqEnd,
+        Token::StructEnd,
+    ];
+    // `y` is a boolean in YAML 1.1.
+    assert_eq!(token::to_yaml_flow(tokens).unwrap(), "{x: 1, \"y\": {V: [null]}}");
+}
+
+#[test]
+fn test_yaml_flow_scalars() {
+    let cases: Vec<(Token, &str)> = vec![
+        (Token::Bool(true), "true"),
+        (Token::I8(-3), "-3"),
+        (Token::F64(1.5), "1.5"),
+        (Token::F64(::std::f64::NEG_INFINITY), "-.inf"),
+        (Token::F32(0.1), "0.1"),
+        (Token::F32(::std::f32::INFINITY), ".inf"),
+        (Token::Unit, "null"),
+        (Token::EnumUnit("E", "Unit"), "Unit"),
+        (Token::Bytes(b"\x01\x02"), "AQI="),
+        (Token::Str("hello world"), "hello world"),
+        (Token::Str("true"), "\"true\""),
+        (Token::Str("No"), "\"No\""),
+        (Token::Str("null"), "\"null\""),
+        (Token::Str("~"), "\"~\""),
+        (Token::Str("12"), "\"12\""),
+        (Token::Str("-1.5"), "\"-1.5\""),
+        (Token::Str(".5"), "\".5\""),
+        (Token::Str(""), "\"\""),
+        (Token::Str("a: b"), "\"a: b\""),
+        (Token::Str("a, b"), "\"a, b\""),
+        (Token::Str("- item"), "\"- item\""),
+        (Token::Str(" padded"), "\" padded\""),
+        (Token::Str("line\nbreak \"q\""), "\"line\\nbreak \\\"q\\\"\""),
+        (Token::Char('#'), "\"#\""),
+    ];
+
+    for (token, expected) in cases {
+        assert_eq!(token::to_yaml_flow(vec![token.clone()]).unwrap(), expected, "{:?}", token);
+    }
+}
+
+#[test]
+fn test_yaml_flow_timestamps() {
+    let tokens = vec![
+        Token::SeqStart(Some(2)),
+            Token::SeqSep,
+            Token::Timestamp(-1, 250_000_000),
+            Token::SeqSep,
+            Token::Timestamp(1_500_000_000, 0),
+        Token::SeqEnd,
+    ];
+    let write = |format| yaml::FlowWriter::new().timestamp_format(format).write(tokens.clone());
+
+    assert_eq!(token::to_yaml_flow(tokens.clone()).unwrap(),
+               r#"["1969-12-31T23:59:59.250Z", "2017-07-14T02:40:00Z"]"#);
+    assert_eq!(write(TimestampFormat::UnixSeconds).unwrap(), "[-1, 1500000000]");
+    assert_eq!(write(TimestampFormat::UnixMillis).unwrap(), "[-750, 1500000000000]");
+    assert_eq!(write(TimestampFormat::Custom("on %d.%m.%Y".to_owned())).unwrap(),
+               "[on 31.12.1969, on 14.07.2017]");
+}
+
+#[test]
+fn test_yaml_flow_quoted_keys() {
+    let mut map = BTreeMap::new();
+    map.insert("true", "yes");
+    map.insert("name", "1e3");
+    assert_eq!(token::to_yaml_flow(token::to_tokens(&map).unwrap()).unwrap(),
+               "{name: \"1e3\", \"true\": \"yes\"}");
+}
+
+#[test]
+fn test_yaml_flow_errors() {
+    let tokens = vec![
+        Token::MapStart(Some(1)),
+            Token::MapSep,
+            Token::SeqStart(Some(0)),
+            Token::SeqEnd,
+            Token::I32(1),
+        Token::MapEnd,
+    ];
+    assert_eq!(token::to_yaml_flow(tokens).unwrap_err(),
+               token::Error::InvalidValue("map key must be a scalar, found SeqStart(Some(0))"
+                                              .to_owned()));
+
+    assert!(token::to_yaml_flow(vec![Token::SeqStart(None), Token::SeqSep]).is_err());
+    assert!(token::to_yaml_flow(vec![Token::Unit, Token::Unit]).is_err());
+}
+
+#[test]
+fn test_yaml_block_round_trip() {
+    let input = "\
+# Service configuration
+name: proxy
+replicas: 3
+ratio: 0.5
+debug: false
+owner: ~
+servers:
+- host: a.example.com
+  ports: [none]
+- host: 'b.example.com'
+  ports:
+    - 80
+    - 0x1bb
+labels:
+  tier: \"edge\"   # quoted, so it stays a string
+  \"y\": \"1.0\"
+";
+    // Flow collections are rejected, so the ports of the first server are a plain string.
+    let input = input.replace("[none]", "none");
+    let tokens = yaml::from_str(&input).unwrap();
+
+    let flow = token::to_yaml_flow(tokens.clone()).unwrap();
+    assert_eq!(flow,
+               "{name: proxy, replicas: 3, ratio: 0.5, debug: false, owner: null, \
+                servers: [{host: a.example.com, ports: none}, {host: b.example.com, ports: [80, \
+                443]}], labels: {tier: edge, \"y\": \"1.0\"}}");
+
+    let expected = value!({
+        "name": "proxy",
+        "replicas": 3,
+        "ratio": 0.5,
+        "debug": false,
+        "owner": null,
+        "servers": [
+            {"host": "a.example.com", "ports": "none"},
+            {"host": "b.example.com", "ports": [80, 443]},
+        ],
+        "labels": {"tier": "edge", "y": "1.0"},
+    });
+    assert_eq!(Value::from_tokens(tokens).unwrap(), expected);
+}
+
+#[test]
+fn test_yaml_block_scalars() {
+    let cases: Vec<(&str, Token)> = vec![
+        ("null", Token::Unit),
+        ("", Token::Unit),
+        ("True", Token::Bool(true)),
+        ("-12", Token::I64(-12)),
+        ("0o17", Token::I64(15)),
+        ("18446744073709551615", Token::U64(18446744073709551615)),
+        ("1e3", Token::F64(1000.0)),
+        ("-.5", Token::F64(-0.5)),
+        (".Inf", Token::F64(::std::f64::INFINITY)),
+        ("1.2.3", Token::Str("1.2.3")),
+        ("yes", Token::Str("yes")),
+        ("a # comment", Token::Str("a")),
+        ("a#b", Token::Str("a#b")),
+        ("'it''s'", Token::String("it's".to_owned())),
+        ("\"tab\\t \\u00e9\"", Token::String("tab\t \u{e9}".to_owned())),
+        ("\"12\"", Token::Str("12")),
+        ("|\n  one\n   two\n\n", Token::String("one\n two\n".to_owned())),
+        ("|-\n  one\n", Token::String("one".to_owned())),
+        ("|+\n  one\n\n", Token::String("one\n\n".to_owned())),
+        (">\n  folded\n  text\n\n  para\n", Token::String("folded text\npara\n".to_owned())),
+    ];
+
+    for (value, expected) in cases {
+        let input = format!("v: {}", value);
+        let tokens = yaml::from_str(&input).unwrap();
+        assert_eq!(tokens[3], expected, "{:?}", input);
+    }
+
+    // A document that is just a scalar, or nothing at all.
+    assert_eq!(yaml::from_str("--- \n42\n...\n").unwrap(), vec![Token::I64(42)]);
+    assert_eq!(yaml::from_str("# empty\n").unwrap(), vec![Token::Unit]);
+}
+
+#[test]
+fn test_yaml_block_unsupported() {
+    fn error(input: &str) -> String {
+        yaml::from_str(input).unwrap_err().to_string()
+    }
+
+    assert_eq!(error("a: [1, 2]"), "flow collections are not supported at line 1 column 4");
+    assert_eq!(error("a: {b: 1}"), "flow collections are not supported at line 1 column 4");
+    assert_eq!(error("a: &x 1\nb: *x\n"),
+               "anchors and aliases are not supported at line 1 column 4");
+    assert_eq!(error("- !!str 1"), "tags are not supported at line 1 column 3");
+    assert_eq!(error("? a\n: b\n"), "complex keys are not supported at line 1 column 1");
+    assert_eq!(error("%YAML 1.2\n---\na: 1\n"), "directives are not supported at line 1 column 1");
+    assert_eq!(error("a: 1\n---\nb: 2\n"),
+               "multiple documents are not supported at line 2 column 1");
+    assert_eq!(error("a: \"open\n  close\"\n"),
+               "multi-line quoted scalars are not supported at line 1 column 4");
+}
+
+#[test]
+fn test_yaml_block_error_snippet() {
+    let err = yaml::from_str("a:\n\tb: [1]\n").unwrap_err();
+    assert_eq!(format!("{:#}", err),
+               concat!("tab characters are not allowed in indentation at line 2 column 1\n",
+                       "  |\n",
+                       "2 | \tb: [1]\n",
+                       "  | ^"));
+
+    let err = yaml::from_str("key: value\nlist: [1]\n").unwrap_err();
+    assert_eq!(format!("{:#}", err),
+               concat!("flow collections are not supported at line 2 column 7\n",
+                       "  |\n",
+                       "2 | list: [1]\n",
+                       "  |       ^"));
+}
+
+#[test]
+fn test_yaml_block_indentation_errors() {
+    fn error(input: &str) -> String {
+        yaml::from_str(input).unwrap_err().to_string()
+    }
+
+    assert_eq!(error("a:\n\tb: 1\n"),
+               "tab characters are not allowed in indentation at line 2 column 1");
+    assert_eq!(error("a:\n    b: 1\n  c: 2\n"), "inconsistent indentation at line 3 column 3");
+    assert_eq!(error("- a\n  - b\n"), "inconsistent indentation at line 2 column 3");
+    assert_eq!(error("a: 1\n  b: 2\n"), "multi-line scalars are not supported at line 2 column 3");
+    assert_eq!(error("a: 1\nb\n"), "expected a mapping key at line 2 column 1");
+    assert_eq!(error("a: b: c\n"), "mapping values are not allowed here at line 1 column 4");
+}
+
+#[test]
+fn test_yaml_block_max_depth() {
+    fn error(input: &str) -> String {
+        yaml::from_str(input).unwrap_err().to_string()
+    }
+
+    assert!(yaml::from_str(&("- ".repeat(128) + "x\n")).is_ok());
+    assert_eq!(error(&("- ".repeat(129) + "x\n")),
+               "nesting exceeds the depth limit of 128 at line 1 column 257");
+    assert_eq!(error(&("- ".repeat(200_000) + "x\n")),
+               "nesting exceeds the depth limit of 128 at line 1 column 257");
+
+    let mut maps = String::new();
+    for depth in 0..1000 {
+        maps.push_str(&format!("{}a:\n", "  ".repeat(depth)));
+    }
+    assert_eq!(error(&maps), "nesting exceeds the depth limit of 128 at line 129 column 257");
+
+    let input = "a:\n  b:\n  - - 1\n---\n- x\n";
+    let documents: Vec<_> = yaml::ReadDocuments::new(input.as_bytes())
+        .max_depth(2)
+        .skip_invalid()
+        .collect();
+    assert_eq!(documents[0].as_ref().unwrap_err().to_string(),
+               "document 0: nesting exceeds the depth limit of 2 at line 3 column 3");
+    assert!(documents[1].is_ok());
+}
+
+#[test]
+fn test_yaml_documents() {
+    let input = "# config\na: 1\n---\n- x\n- y\n...\n--- \nthree\n";
+    let documents: Vec<_> = yaml::Documents::new(input).map(Result::unwrap).collect();
+    assert_eq!(documents.len(), 3);
+    assert_eq!(token::to_json(documents[0].clone()).unwrap(), r#"{"a":1}"#);
+    assert_eq!(token::to_json(documents[1].clone()).unwrap(), r#"["x","y"]"#);
+    assert_eq!(documents[2], vec![Token::Str("three")]);
+
+    assert_eq!(yaml::Documents::new("").count(), 0);
+    assert_eq!(yaml::Documents::new("---\n").next(), Some(Ok(vec![Token::Unit])));
+
+    // The reader only supports block collections, so only scalars make the round trip.
+    let documents = vec![
+        token::to_tokens(&1).unwrap(),
+        token::to_tokens(&"two").unwrap(),
+        token::to_tokens(&true).unwrap(),
+    ];
+    let output = yaml::to_yaml_documents(documents).unwrap();
+    assert_eq!(output, "---\n1\n---\ntwo\n---\ntrue\n");
+    let read: Vec<_> = yaml::Documents::new(&output).map(Result::unwrap).collect();
+    assert_eq!(read, vec![vec![Token::I64(1)], vec![Token::Str("two")], vec![Token::Bool(true)]]);
+
+    // Strings that look like document markers are quoted.
+    let markers = vec!["...", "---", "... more", "---x"];
+    let documents: Vec<_> = markers.iter().map(|&s| vec![Token::Str(s)]).collect();
+    let output = yaml::to_yaml_documents(documents.clone()).unwrap();
+    assert_eq!(output, r#"---
+"..."
+---
+"---"
+---
+"... more"
+---
+"---x"
+"#);
+    let read: Vec<_> = yaml::Documents::new(&output).map(Result::unwrap).collect();
+    assert_eq!(read, documents);
+}
+
+#[test]
+fn test_yaml_documents_errors() {
+    let input = "a: 1\n---\nb: [2]\n---\nc: 3\n";
+
+    let results: Vec<_> = yaml::Documents::new(input).collect();
+    assert_eq!(results.len(), 2);
+    let err = results[1].clone().unwrap_err();
+    assert_eq!(err.document(), Some(1));
+    assert_eq!(err.to_string(),
+               "document 1: flow collections are not supported at line 3 column 4");
+
+    let results: Vec<_> = yaml::Documents::new(input).skip_invalid().collect();
+    assert_eq!(results.len(), 3);
+    assert!(results[1].is_err());
+    assert_eq!(token::to_json(results[2].clone().unwrap()).unwrap(), r#"{"c":3}"#);
+
+    assert_eq!(yaml::from_str("b: [2]").unwrap_err().document(), None);
+}
+
+#[test]
+fn test_yaml_read_documents() {
+    let input = "# config\na: 1\n---\n- x\n- y\n...\n--- \nthree\n";
+    let documents: Vec<_> = yaml::ReadDocuments::new(input.as_bytes())
+        .map(Result::unwrap)
+        .collect();
+    let expected: Vec<_> = yaml::Documents::new(input).map(Result::unwrap).collect();
+    assert_eq!(documents, expected);
+    assert_eq!(documents.len(), 3);
+
+    assert_eq!(yaml::ReadDocuments::new(&b""[..]).count(), 0);
+    assert_eq!(yaml::ReadDocuments::new(&b"\n# nothing\n"[..]).count(), 0);
+
+    let documents = vec![
+        token::to_tokens(&1).unwrap(),
+        token::to_tokens(&"two").unwrap(),
+        token::to_tokens(&true).unwrap(),
+    ];
+    let output = yaml::to_yaml_documents(documents).unwrap();
+    let read: Vec<_> = yaml::ReadDocuments::new(output.as_bytes()).map(Result::unwrap).collect();
+    assert_eq!(read, vec![vec![Token::I64(1)], vec![Token::Str("two")], vec![Token::Bool(true)]]);
+}
+
+#[test]
+fn test_yaml_read_documents_errors() {
+    let input = "a: 1\n---\nb: [2]\n---\nc: 3\n";
+
+    let results: Vec<_> = yaml::ReadDocuments::new(input.as_bytes()).collect();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[1].as_ref().unwrap_err().to_string(),
+               "document 1: flow collections are not supported at line 3 column 4");
+    match results[1] {
+        Err(yaml::ReadError::Parse(ref err)) => assert_eq!(err.snippet().line(), 3),
+        ref result => panic!("unexpected result {:?}", result),
+    }
+
+    let results: Vec<_> = yaml::ReadDocuments::new(input.as_bytes()).skip_invalid().collect();
+    assert_eq!(results.len(), 3);
+    assert!(results[1].is_err());
+    assert_eq!(token::to_json(results[2].as_ref().unwrap().clone()).unwrap(), r#"{"c":3}"#);
+
+    match yaml::ReadDocuments::new(&b"a: \xff\n"[..]).next() {
+        Some(Err(yaml::ReadError::Io(_))) => { }
+        result => panic!("unexpected result {:?}", result),
+    }
+}
+
+#[test]
+fn test_yaml_documents_deserialize() {
+    let input = "port: 80\n---\nport: https\n---\nport: 443\n";
+
+    let ports: Vec<_> = yaml::Documents::new(input)
+        .deserialize::<BTreeMap<String, u16>>()
+        .collect();
+    assert_eq!(ports.len(), 3);
+    assert_eq!(ports[0].as_ref().unwrap()["port"], 80);
+    let err = ports[1].clone().unwrap_err();
+    assert_eq!(err.document(), Some(1));
+    assert_eq!((err.line(), err.column()), (2, 1));
+    assert_eq!(ports[2].as_ref().unwrap()["port"], 443);
+
+    let ports: Vec<_> = yaml::ReadDocuments::new(input.as_bytes())
+        .deserialize::<BTreeMap<String, u16>>()
+        .collect();
+    assert_eq!(ports.len(), 3);
+    match ports[1] {
+        Err(yaml::ReadError::Parse(ref err)) => {
+            assert_eq!((err.document(), err.line(), err.column()), (Some(1), 2, 1));
+        }
+        ref result => panic!("unexpected result {:?}", result),
+    }
+    assert_eq!(ports[2].as_ref().unwrap()["port"], 443);
+}
@@ -7,66 +7,10 @@ use self::serde::ser::{self, Serialize};
 use self::serde::de;
 use self::serde::de::value::{self, ValueDeserializer};
 
-#[derive(Clone, PartialEq, Debug)]
-pub enum Token<'a> {
-    Bool(bool),
-    Isize(isize),
-    I8(i8),
-    I16(i16),
-    I32(i32),
-    I64(i64),
-    Usize(usize),
-    U8(u8),
-    U16(u16),
-    U32(u32),
-    U64(u64),
-    F32(f32),
-    F64(f64),
-    Char(char),
-    Str(&'a str),
-    String(String),
-    Bytes(&'a [u8]),
-
-    Option(bool),
-
-    Unit,
-    UnitStruct(&'a str),
-
-    StructNewType(&'a str),
-
-    EnumStart(&'a str),
-    EnumUnit(&'a str, &'a str),
-    EnumNewType(&'a str, &'a str),
-
-    SeqStart(Option<usize>),
-    SeqArrayStart(usize),
-    SeqSep,
-    SeqEnd,
-
-    TupleStart(usize),
-    TupleSep,
-    TupleEnd,
-
-    TupleStructStart(&'a str, Option<usize>),
-    TupleStructSep,
-    TupleStructEnd,
-
-    MapStart(Option<usize>),
-    MapSep,
-    MapEnd,
-
-    StructStart(&'a str, Option<usize>),
-    StructSep,
-    StructEnd,
-
-    EnumSeqStart(&'a str, &'a str, Option<usize>),
-    EnumSeqSep,
-    EnumSeqEnd,
-
-    EnumMapStart(&'a str, &'a str, Option<usize>),
-    EnumMapSep,
-    EnumMapEnd,
-}
+use self::serde::token::Error as TokenError;
+use self::serde::token::borrowed::{self, DeserializeBorrowed};
+
+pub use self::serde::token::Token;
 
 //////////////////////////////////////////////////////////////////////////////
 
@@ -414,6 +358,7 @@ pub enum Error {
     InvalidName(&'static str),
     InvalidValue(String),
     UnexpectedToken(Token<'static>),
+    InvalidLength(usize, usize),
     ValueError(value::Error),
 }
 
@@ -449,6 +394,10 @@ impl de::Error for Error {
     fn duplicate_field(field: &'static str) -> Error {
         Error::DuplicateFieldError(field)
     }
+
+    fn invalid_arity(expected: usize, len: usize) -> Error {
+        Error::InvalidLength(expected, len)
+    }
 }
 
 impl fmt::Display for Error {
@@ -585,6 +534,7 @@ impl<I> de::Deserializer for Deserializer<I>
             Some(Token::Str(v)) => visitor.visit_str(v),
             Some(Token::String(v)) => visitor.visit_string(v),
             Some(Token::Bytes(v)) => visitor.visit_bytes(v),
+            Some(Token::ByteBuf(v)) => visitor.visit_byte_buf(v),
             Some(Token::Option(false)) => visitor.visit_none(),
             Some(Token::Option(true)) => visitor.visit_some(self),
             Some(Token::Unit) => visitor.visit_unit(),
@@ -829,7 +779,7 @@ impl<'a, I> de::SeqVisitor for DeserializerSeqVisitor<'a, I>
         match self.de.tokens.peek() {
             Some(&Token::SeqSep) => {
                 self.de.tokens.next();
-                self.len = self.len.map(|len| len - 1);
+                self.len = self.len.map(|len| len.saturating_sub(1));
                 Ok(Some(try!(de::Deserialize::deserialize(self.de))))
             }
             Some(&Token::SeqEnd) => Ok(None),
@@ -874,7 +824,7 @@ impl<'a, I> de::SeqVisitor for DeserializerArrayVisitor<'a, I>
         match self.de.tokens.peek() {
             Some(&Token::SeqSep) => {
                 self.de.tokens.next();
-                self.len -= 1;
+                self.len = self.len.saturating_sub(1);
                 Ok(Some(try!(de::Deserialize::deserialize(self.de))))
             }
             Some(&Token::SeqEnd) => Ok(None),
@@ -918,7 +868,7 @@ impl<'a, I> de::SeqVisitor for DeserializerTupleVisitor<'a, I>
         match self.de.tokens.peek() {
             Some(&Token::TupleSep) => {
                 self.de.tokens.next();
-                self.len -= 1;
+                self.len = self.len.saturating_sub(1);
                 Ok(Some(try!(de::Deserialize::deserialize(self.de))))
             }
             Some(&Token::TupleEnd) => Ok(None),
@@ -1006,7 +956,7 @@ impl<'a, I> de::SeqVisitor for DeserializerVariantSeqVisitor<'a, I>
         match self.de.tokens.peek() {
             Some(&Token::EnumSeqSep) => {
                 self.de.tokens.next();
-                self.len = self.len.map(|len| len - 1);
+                self.len = self.len.map(|len| len.saturating_sub(1));
                 Ok(Some(try!(de::Deserialize::deserialize(self.de))))
             }
             Some(&Token::EnumSeqEnd) => Ok(None),
@@ -1051,7 +1001,7 @@ impl<'a, I> de::MapVisitor for DeserializerMapVisitor<'a, I>
         match self.de.tokens.peek() {
             Some(&Token::MapSep) => {
                 self.de.tokens.next();
-                self.len = self.len.map(|len| if len > 0 { len - 1} else { 0 });
+                self.len = self.len.map(|len| len.saturating_sub(1));
                 Ok(Some(try!(de::Deserialize::deserialize(self.de))))
             }
             Some(&Token::MapEnd) => Ok(None),
@@ -1102,7 +1052,7 @@ impl<'a, I> de::MapVisitor for DeserializerStructVisitor<'a, I>
         match self.de.tokens.peek() {
             Some(&Token::StructSep) => {
                 self.de.tokens.next();
-                self.len = self.len.map(|len| if len > 0 { len - 1} else { 0 });
+                self.len = self.len.map(|len| len.saturating_sub(1));
                 Ok(Some(try!(de::Deserialize::deserialize(self.de))))
             }
             Some(&Token::StructEnd) => Ok(None),
@@ -1274,7 +1224,7 @@ impl<'a, I> de::MapVisitor for DeserializerVariantMapVisitor<'a, I>
         match self.de.tokens.peek() {
             Some(&Token::EnumMapSep) => {
                 self.de.tokens.next();
-                self.len = self.len.map(|len| if len > 0 { len - 1} else { 0 });
+                self.len = self.len.map(|len| len.saturating_sub(1));
                 Ok(Some(try!(de::Deserialize::deserialize(self.de))))
             }
             Some(&Token::EnumMapEnd) => Ok(None),
@@ -1392,3 +1342,22 @@ pub fn assert_tokens<T>(value: &T, tokens: Vec<Token<'static>>)
     assert_ser_tokens(value, &tokens[..]);
     assert_de_tokens(value, tokens);
 }
+
+//////////////////////////////////////////////////////////////////////////////
+
+pub fn missing_field(field: &str) -> TokenError {
+    TokenError::InvalidValue(format!("missing field {}", field))
+}
+
+pub fn unknown_field(field: &str) -> TokenError {
+    TokenError::InvalidValue(format!("unknown field {}", field))
+}
+
+/// Reads the value of a struct field in a hand-written `DeserializeBorrowed` impl.
+pub fn field_value<'a, T, I>(deserializer: &mut borrowed::Deserializer<I>)
+                            -> Result<Option<T>, TokenError>
+    where T: DeserializeBorrowed<'a>,
+          I: Iterator<Item=Token<'a>>,
+{
+    DeserializeBorrowed::deserialize_borrowed(deserializer).map(Some)
+}